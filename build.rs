@@ -1,11 +1,60 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt::Write;
+use std::fs;
 use std::path::PathBuf;
+use std::process::Command;
+
+/// Default bindings file; inside src/ so that creating it reruns this script
+const BINDINGS: &str = "src/exports.toml";
+
+/// An export of the bindings file: the reflex_schema.toml format (schema.rs)
+/// plus a return type
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ExportBinding {
+    params: Vec<ParamBinding>,
+    ret: Option<String>,
+    /// Forward with a jump stub that resolves the export on first call
+    jump: bool,
+}
+
+#[derive(Deserialize)]
+struct ParamBinding {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
 
 fn main() {
     // Tell cargo to rerun this build script if any of these change
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-changed=src/");
 
+    // Off Windows only the unit tests of the portable modules are built
+    if env::var("CARGO_CFG_TARGET_OS").unwrap() != "windows" {
+        return;
+    }
+
+    // Copy of the original to embed (embedded.rs)
+    if env::var_os("CARGO_FEATURE_EMBEDDED_ORIGINAL").is_some() {
+        println!("cargo:rerun-if-env-changed=REFLEX_EMBEDDED_ORIGINAL");
+        let original = env::var("REFLEX_EMBEDDED_ORIGINAL")
+            .expect("the embedded-original feature needs REFLEX_EMBEDDED_ORIGINAL=<path to reflex_original.dll>");
+        let original = PathBuf::from(original)
+            .canonicalize()
+            .unwrap_or_else(|e| panic!("REFLEX_EMBEDDED_ORIGINAL: {}", e));
+        println!("cargo:rerun-if-changed={}", original.display());
+        println!("cargo:rustc-env=REFLEX_EMBEDDED_ORIGINAL={}", original.display());
+    }
+
+    // Typed forwarders for the exports described in the bindings file
+    generate_bindings(&env::var("OUT_DIR").unwrap());
+
+    // Identity of the sources, returned by ReflexProxyGetBuildId (status.rs)
+    println!("cargo:rustc-env=REFLEX_BUILD_ID={}", build_id());
+
     // Link against Windows libraries
     println!("cargo:rustc-link-lib=ntdll");
     println!("cargo:rustc-link-lib=kernel32");
@@ -13,19 +62,27 @@ fn main() {
     println!("cargo:rustc-link-lib=advapi32");
     println!("cargo:rustc-link-lib=shlwapi");
 
+    // DLL-only settings; the companion tools in src/bin are console programs
+
     // Set the subsystem to Windows (GUI) to avoid console window
-    println!("cargo:rustc-link-arg=/SUBSYSTEM:WINDOWS");
+    println!("cargo:rustc-link-arg-cdylib=/SUBSYSTEM:WINDOWS");
 
     // Export DllMain
-    println!("cargo:rustc-link-arg=/EXPORT:DllMain");
+    println!("cargo:rustc-link-arg-cdylib=/EXPORT:DllMain");
 
     // Set the DLL base address (same as original)
-    println!("cargo:rustc-link-arg=/BASE:0x180000000");
+    println!("cargo:rustc-link-arg-cdylib=/BASE:0x180000000");
 
-    // Generate PDB file for debugging
-    let out_dir = env::var("OUT_DIR").unwrap();
-    let pdb_path = PathBuf::from(&out_dir).join("reflex.pdb");
-    println!("cargo:rustc-link-arg=/PDB:{}", pdb_path.display());
+    // PDB file for debugging
+    pdb_args(&env::var("OUT_DIR").unwrap());
+
+    // Reproducible output: timestamps and the PDB GUID become hashes of
+    // the content, so two builds of the same sources are byte-identical
+    println!("cargo:rustc-link-arg-cdylib=/Brepro");
+
+    // Write a valid PE checksum (MSVC link.exe; for other linkers, and to
+    // strip the Rich header, run reflex_postlink on the DLL)
+    println!("cargo:rustc-link-arg-cdylib=/RELEASE");
 
     // Set DLL characteristics
     println!("cargo:rustc-link-arg=/DYNAMICBASE"); // ASLR
@@ -37,3 +94,216 @@ fn main() {
         println!("cargo:rustc-link-arg=/OPT:ICF");
     }
 }
+
+/// Where the PDB goes and how the DLL names it
+///
+/// `REFLEX_PDB`:
+/// - `out_dir` (default): in the build script's output directory
+/// - `dll`: next to reflex.dll, e.g. target/release/reflex.pdb
+/// - `none`: no PDB, for release distributions
+///
+/// `REFLEX_PDB_PATH`:
+/// - `name` (default): the DLL holds only "reflex.pdb", not the builder's
+///   directories; debuggers find it through the symbol path
+/// - `full`: the DLL holds the absolute path, found without a symbol path
+fn pdb_args(out_dir: &str) {
+    println!("cargo:rerun-if-env-changed=REFLEX_PDB");
+    println!("cargo:rerun-if-env-changed=REFLEX_PDB_PATH");
+
+    let pdb_path = match env::var("REFLEX_PDB").as_deref() {
+        Ok("none") => {
+            println!("cargo:rustc-link-arg-cdylib=/DEBUG:NONE");
+            return;
+        }
+        // OUT_DIR is target/<profile>/build/reflex-<hash>/out
+        Ok("dll") => PathBuf::from(out_dir)
+            .ancestors()
+            .nth(3)
+            .expect("OUT_DIR is inside the target directory")
+            .join("reflex.pdb"),
+        Ok("out_dir") | Err(_) => PathBuf::from(out_dir).join("reflex.pdb"),
+        Ok(other) => panic!("REFLEX_PDB must be out_dir, dll or none, not {:?}", other),
+    };
+    println!("cargo:rustc-link-arg-cdylib=/PDB:{}", pdb_path.display());
+
+    match env::var("REFLEX_PDB_PATH").as_deref() {
+        Ok("full") => {}
+        Ok("name") | Err(_) => println!("cargo:rustc-link-arg-cdylib=/PDBALTPATH:reflex.pdb"),
+        Ok(other) => panic!("REFLEX_PDB_PATH must be name or full, not {:?}", other),
+    }
+}
+
+/// `REFLEX_BUILD_ID` if set, else the git commit, "-dirty" if the tree has
+/// changes; never a time, so rebuilding does not change it
+fn build_id() -> String {
+    println!("cargo:rerun-if-env-changed=REFLEX_BUILD_ID");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    if let Ok(id) = env::var("REFLEX_BUILD_ID") {
+        return id;
+    }
+
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    match git(&["rev-parse", "HEAD"]) {
+        Some(commit) if git(&["status", "--porcelain"]).is_some_and(|changes| !changes.is_empty()) => {
+            format!("{}-dirty", commit)
+        }
+        Some(commit) => commit,
+        None => "unknown".to_string(),
+    }
+}
+
+/// Write `bindings.rs` to `out_dir`: per export of the bindings file a
+/// function pointer type, a hook slot and a forwarder (or with `jump`, a
+/// stub) exported under the export's name (included by
+/// src/proxy_impl/bindings.rs)
+fn generate_bindings(out_dir: &str) {
+    println!("cargo:rerun-if-env-changed=REFLEX_BINDINGS");
+    let path = match env::var("REFLEX_BINDINGS") {
+        Ok(path) => {
+            println!("cargo:rerun-if-changed={}", path);
+            path
+        }
+        Err(_) => BINDINGS.to_string(),
+    };
+
+    let exports: BTreeMap<String, ExportBinding> = match fs::read_to_string(&path) {
+        Ok(text) if path.ends_with(".json") => {
+            serde_json::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", path, e))
+        }
+        Ok(text) => toml::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", path, e)),
+        Err(_) => BTreeMap::new(),
+    };
+
+    let mut code = String::new();
+    let mut slots = Vec::new();
+    for (name, export) in &exports {
+        // The proxy's own entry point
+        if name == "DllMain" {
+            continue;
+        }
+        if !is_identifier(name) {
+            panic!("{}: export {} is not a valid Rust identifier", path, name);
+        }
+
+        let params: Vec<(String, &str)> = export
+            .params
+            .iter()
+            .map(|p| (parameter_name(&p.name), rust_type(&path, &p.kind)))
+            .collect();
+        let declared = params
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let passed = params.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", ");
+        let ret = match export.ret.as_deref() {
+            None | Some("void") => String::new(),
+            Some(kind) => format!(" -> {}", rust_type(&path, kind)),
+        };
+        let slot = name.to_uppercase();
+        slots.push(format!("&{}", slot));
+
+        let _ = write!(
+            code,
+            r#"
+/// `{name}` of reflex_original.dll
+pub type {name}Fn = unsafe extern "system" fn({declared}){ret};
+"#
+        );
+
+        if export.jump {
+            let _ = write!(
+                code,
+                r#"
+/// Call target of the `{name}` stub
+pub static {slot}: Slot = Slot::lazy("{name}", resolve_{name});
+
+#[no_mangle]
+#[unsafe(naked)]
+pub unsafe extern "system" fn {name}() {{
+    std::arch::naked_asm!("jmp qword ptr [rip + {{slot}}]", slot = sym {slot});
+}}
+
+#[unsafe(naked)]
+#[allow(non_snake_case)]
+unsafe extern "system" fn resolve_{name}() {{
+    std::arch::naked_asm!("lea r10, [rip + {{slot}}]", "jmp {{thunk}}", slot = sym {slot}, thunk = sym lazy_thunk);
+}}
+"#
+            );
+            continue;
+        }
+
+        let _ = write!(
+            code,
+            r#"
+/// Call target of the `{name}` forwarder
+pub static {slot}: Slot = Slot::new("{name}");
+
+#[no_mangle]
+pub unsafe extern "system" fn {name}({declared}){ret} {{
+    match {slot}.target() {{
+        Some(target) => std::mem::transmute::<usize, {name}Fn>(target)({passed}),
+        None => std::mem::zeroed(),
+    }}
+}}
+"#
+        );
+    }
+
+    let _ = write!(
+        code,
+        r#"
+/// Every slot above
+pub static SLOTS: &[&Slot] = &[{}];
+"#,
+        slots.join(", ")
+    );
+
+    fs::write(PathBuf::from(out_dir).join("bindings.rs"), code).unwrap();
+}
+
+/// Rust type of a schema.rs type name
+fn rust_type(path: &str, kind: &str) -> &'static str {
+    match kind {
+        "byte" => "u8",
+        "word" => "u16",
+        "dword" => "u32",
+        "int" | "bool" => "i32",
+        "qword" => "u64",
+        "handle" | "ptr" => "*mut std::ffi::c_void",
+        "str" => "*const i8",
+        "wstr" => "*const u16",
+        other => panic!("{}: unknown type {}", path, other),
+    }
+}
+
+/// A parameter name usable in Rust; keywords get a trailing underscore
+fn parameter_name(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+        "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+        "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+    ];
+    if !is_identifier(name) {
+        panic!("parameter {} is not a valid Rust identifier", name);
+    }
+    if KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,502 @@
+//! Fuzzing harness for the exports of reflex_original.dll
+//!
+//! Loads the DLL outside the game and calls each export with structured and
+//! random integer arguments, logging which inputs crash or hang:
+//!
+//! ```text
+//! reflex_fuzz.exe <reflex_original.dll> [--export NAME]... [--cases N] [--seed N] [--timeout-ms N] [--out FILE]
+//! ```
+//!
+//! Calls run in a worker process (this executable with `--worker`) so a
+//! crash or hang only costs the worker; the parent restarts it after the
+//! failing case. The worker's unhandled-exception filter reports the
+//! exception code, the faulting offset and, for access violations, the
+//! accessed address. An access near one of the arguments marks that slot as
+//! a likely pointer in the per-export summary.
+//!
+//! Exports run with real side effects. Use a throwaway VM. Windows only.
+
+// Most of the tool needs Windows; off Windows it only builds
+#![cfg_attr(not(windows), allow(dead_code))]
+
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdout, Command, ExitCode, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+#[cfg(windows)]
+use {
+    std::ffi::{CStr, CString},
+    std::os::windows::ffi::OsStrExt,
+    winapi::shared::minwindef::{DWORD, HMODULE},
+    winapi::um::errhandlingapi::SetUnhandledExceptionFilter,
+    winapi::um::libloaderapi::{GetProcAddress, LoadLibraryExW, DONT_RESOLVE_DLL_REFERENCES},
+    winapi::um::minwinbase::EXCEPTION_ACCESS_VIOLATION,
+    winapi::um::processthreadsapi::{ExitProcess, GetCurrentProcess, TerminateProcess},
+    winapi::um::winnt::{
+        EXCEPTION_POINTERS, IMAGE_DIRECTORY_ENTRY_EXPORT, IMAGE_DOS_HEADER, IMAGE_EXPORT_DIRECTORY, IMAGE_NT_HEADERS,
+        LONG,
+    },
+};
+
+/// Integer argument slots passed to every call
+const ARG_SLOTS: usize = 8;
+/// Size of the buffers behind pointer arguments
+const BUFFER_SIZE: usize = 4096;
+/// An address that is never mapped
+const BAD_POINTER: usize = 0x4141_4141_0000;
+
+#[cfg(windows)]
+type ExportFn = unsafe extern "system" fn(usize, usize, usize, usize, usize, usize, usize, usize) -> usize;
+
+#[cfg(windows)]
+static mut MODULE_BASE: usize = 0;
+
+struct Options {
+    dll: String,
+    exports: Vec<String>,
+    cases: u64,
+    seed: u64,
+    timeout: Duration,
+    out: String,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    #[cfg(windows)]
+    if args.first().map(String::as_str) == Some("--worker") {
+        return worker(&args[1..]);
+    }
+
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!(
+                "usage: reflex_fuzz <reflex_original.dll> [--export NAME]... [--cases N] [--seed N] [--timeout-ms N] [--out FILE]"
+            );
+            return ExitCode::from(2);
+        }
+    };
+
+    match fuzz(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        dll: String::new(),
+        exports: Vec::new(),
+        cases: 200,
+        seed: 0x5EED,
+        timeout: Duration::from_millis(2000),
+        out: "reflex_fuzz.jsonl".to_string(),
+    };
+
+    let number = |value: Option<String>, flag: &str| -> Result<u64, String> {
+        let value = value.ok_or_else(|| format!("{} needs a value", flag))?;
+        value.parse().map_err(|_| format!("invalid value '{}' for {}", value, flag))
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--export" => options.exports.push(args.next().ok_or("--export needs a name")?),
+            "--cases" => options.cases = number(args.next(), "--cases")?,
+            "--seed" => options.seed = number(args.next(), "--seed")?,
+            "--timeout-ms" => options.timeout = Duration::from_millis(number(args.next(), "--timeout-ms")?),
+            "--out" => options.out = args.next().ok_or("--out needs a file")?,
+            _ if options.dll.is_empty() => options.dll = arg,
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    if options.dll.is_empty() {
+        return Err("expected a DLL".to_string());
+    }
+    Ok(options)
+}
+
+// ============================================================================
+// Parent
+// ============================================================================
+
+/// What happened to one case
+enum Outcome {
+    Returned(usize),
+    Crashed { code: u32, offset: String, access: Option<usize> },
+    Hung,
+}
+
+/// A worker process and the lines it prints
+struct Worker {
+    child: Child,
+    lines: Receiver<String>,
+}
+
+impl Worker {
+    fn spawn(options: &Options, export: &str, first_case: u64) -> Result<Self, String> {
+        let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+        let mut child = Command::new(exe)
+            .args(["--worker", &options.dll, export])
+            .args([first_case.to_string(), options.cases.to_string(), options.seed.to_string()])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to start worker: {}", e))?;
+
+        let stdout: ChildStdout = child.stdout.take().ok_or("worker has no stdout")?;
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self { child, lines })
+    }
+
+    fn kill(mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn fuzz(options: &Options) -> Result<(), String> {
+    let mut exports = exports_of(&options.dll)?;
+    if !options.exports.is_empty() {
+        exports.retain(|name| options.exports.contains(name));
+    }
+    println!("Fuzzing {} export(s), {} case(s) each", exports.len(), options.cases);
+
+    let mut out = File::create(&options.out).map_err(|e| format!("Failed to create {}: {}", options.out, e))?;
+    let mut summary = Vec::new();
+
+    for export in &exports {
+        let (mut ok, mut crashes, mut hangs) = (0u64, 0u64, 0u64);
+        let mut pointer_slots: BTreeMap<usize, u64> = BTreeMap::new();
+        let mut case = 0;
+
+        while case < options.cases {
+            let worker = Worker::spawn(options, export, case)?;
+            let (next, finished) = run_worker(worker, options, |case, args, outcome| {
+                let entry = match &outcome {
+                    Outcome::Returned(ret) => {
+                        ok += 1;
+                        json!({ "export": export, "case": case, "args": hex_args(args), "outcome": "ok", "ret": format!("0x{:x}", ret) })
+                    }
+                    Outcome::Crashed { code, offset, access } => {
+                        crashes += 1;
+                        if let Some(access) = access {
+                            for (slot, &value) in args.iter().enumerate() {
+                                if *access >= value && *access < value.saturating_add(BUFFER_SIZE) {
+                                    *pointer_slots.entry(slot).or_default() += 1;
+                                }
+                            }
+                        }
+                        json!({
+                            "export": export, "case": case, "args": hex_args(args), "outcome": "crash",
+                            "code": format!("0x{:08x}", code), "offset": offset,
+                            "access": access.map(|a| format!("0x{:x}", a)),
+                        })
+                    }
+                    Outcome::Hung => {
+                        hangs += 1;
+                        json!({ "export": export, "case": case, "args": hex_args(args), "outcome": "hang" })
+                    }
+                };
+                let _ = writeln!(out, "{}", entry);
+            });
+            case = next;
+            if finished {
+                break;
+            }
+        }
+
+        println!("{}: {} ok, {} crash(es), {} hang(s)", export, ok, crashes, hangs);
+        summary.push(json!({
+            "export": export, "ok": ok, "crashes": crashes, "hangs": hangs,
+            "likely_pointer_slots": pointer_slots,
+        }));
+    }
+
+    let summary_path = format!("{}.summary.json", options.out.trim_end_matches(".jsonl"));
+    let text = serde_json::to_string_pretty(&summary).map_err(|e| e.to_string())?;
+    std::fs::write(&summary_path, text).map_err(|e| format!("Failed to write {}: {}", summary_path, e))?;
+    println!("Results in {} and {}", options.out, summary_path);
+    Ok(())
+}
+
+/// Follow a worker until it finishes, crashes or hangs
+///
+/// Returns the next case to run and whether all cases are done.
+fn run_worker(worker: Worker, options: &Options, mut report: impl FnMut(u64, &[usize], Outcome)) -> (u64, bool) {
+    let mut current: Option<(u64, Vec<usize>)> = None;
+
+    loop {
+        let line = match worker.lines.recv_timeout(options.timeout) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Timeout) => {
+                worker.kill();
+                return match current {
+                    Some((case, args)) => {
+                        report(case, &args, Outcome::Hung);
+                        (case + 1, case + 1 >= options.cases)
+                    }
+                    None => (options.cases, true),
+                };
+            }
+            // Exited without a report: nothing more to learn from this export
+            Err(RecvTimeoutError::Disconnected) => {
+                worker.kill();
+                return match current {
+                    Some((case, _)) => (case + 1, case + 1 >= options.cases),
+                    None => (options.cases, true),
+                };
+            }
+        };
+
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("CASE") => {
+                let case = fields.next().and_then(|c| c.parse().ok()).unwrap_or(0);
+                let args = fields.filter_map(|a| usize::from_str_radix(a, 16).ok()).collect();
+                current = Some((case, args));
+            }
+            Some("RET") => {
+                if let Some((case, args)) = current.take() {
+                    let ret = fields.next().and_then(|r| usize::from_str_radix(r, 16).ok()).unwrap_or(0);
+                    report(case, &args, Outcome::Returned(ret));
+                }
+            }
+            Some("CRASH") => {
+                let code = fields.next().and_then(|c| u32::from_str_radix(c, 16).ok()).unwrap_or(0);
+                let offset = fields.next().unwrap_or("?").to_string();
+                let access = fields.next().and_then(|a| usize::from_str_radix(a, 16).ok());
+                worker.kill();
+                return match current {
+                    Some((case, args)) => {
+                        report(case, &args, Outcome::Crashed { code, offset, access });
+                        (case + 1, case + 1 >= options.cases)
+                    }
+                    None => (options.cases, true),
+                };
+            }
+            Some("DONE") => {
+                worker.kill();
+                return (options.cases, true);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn hex_args(args: &[usize]) -> Vec<String> {
+    args.iter().map(|a| format!("0x{:x}", a)).collect()
+}
+
+// ============================================================================
+// Worker
+// ============================================================================
+
+#[cfg(windows)]
+fn worker(args: &[String]) -> ExitCode {
+    let [dll, export, first, count, seed] = match args {
+        [a, b, c, d, e] => [a, b, c, d, e],
+        _ => return ExitCode::from(2),
+    };
+    let parse = |s: &String| s.parse::<u64>().unwrap_or(0);
+    let (first, count, seed) = (parse(first), parse(count), parse(seed));
+
+    let module = match load(dll, 0) {
+        Ok(module) => module,
+        Err(_) => return ExitCode::FAILURE,
+    };
+    let name = CString::new(export.as_str()).unwrap();
+    let address = unsafe { GetProcAddress(module, name.as_ptr()) };
+    if address.is_null() {
+        return ExitCode::FAILURE;
+    }
+
+    unsafe {
+        MODULE_BASE = module as usize;
+        SetUnhandledExceptionFilter(Some(report_crash));
+    }
+    let function: ExportFn = unsafe { std::mem::transmute(address) };
+
+    let mut stdout = std::io::stdout();
+    for case in first..count {
+        let mut inputs = Inputs::new(seed ^ hash(export) ^ case.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        let args: [usize; ARG_SLOTS] = std::array::from_fn(|_| inputs.next());
+
+        let line: Vec<String> = args.iter().map(|a| format!("{:x}", a)).collect();
+        let _ = writeln!(stdout, "CASE {} {}", case, line.join(" "));
+        let _ = stdout.flush();
+
+        let ret = unsafe { function(args[0], args[1], args[2], args[3], args[4], args[5], args[6], args[7]) };
+        let _ = writeln!(stdout, "RET {:x}", ret);
+        let _ = stdout.flush();
+    }
+
+    let _ = writeln!(stdout, "DONE");
+    let _ = stdout.flush();
+    // Skip DLL_PROCESS_DETACH of a DLL in an unknown state
+    unsafe { TerminateProcess(GetCurrentProcess(), 0) };
+    ExitCode::SUCCESS
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn report_crash(info: *mut EXCEPTION_POINTERS) -> LONG {
+    let record = &*(*info).ExceptionRecord;
+    let address = record.ExceptionAddress as usize;
+    let offset = match address.checked_sub(MODULE_BASE) {
+        Some(rva) if rva < 0x1000_0000 => format!("+0x{:x}", rva),
+        _ => format!("0x{:x}", address),
+    };
+
+    let mut line = format!("CRASH {:x} {}", record.ExceptionCode, offset);
+    if record.ExceptionCode == EXCEPTION_ACCESS_VIOLATION && record.NumberParameters >= 2 {
+        line.push_str(&format!(" {:x}", record.ExceptionInformation[1]));
+    }
+    println!("{}", line);
+    let _ = std::io::stdout().flush();
+
+    ExitProcess(record.ExceptionCode);
+    0
+}
+
+/// Argument generator: interesting values, buffers and random garbage
+struct Inputs {
+    state: u64,
+}
+
+impl Inputs {
+    fn new(seed: u64) -> Self {
+        Self { state: seed | 1 }
+    }
+
+    /// xorshift64*
+    fn random(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn next(&mut self) -> usize {
+        match self.random() % 12 {
+            0 => 0,
+            1 => 1,
+            2 => usize::MAX,
+            3 => (self.random() % 256) as usize,
+            4 => [0x7F, 0xFF, 0x7FFF, 0xFFFF, 0x7FFF_FFFF, 0xFFFF_FFFF][self.random() as usize % 6],
+            5 => BAD_POINTER,
+            6 => self.buffer(vec![0; BUFFER_SIZE]),
+            7 => self.buffer(vec![0xFF; BUFFER_SIZE]),
+            8 => self.buffer(text(b"reflex_fuzz", false)),
+            9 => self.buffer(text(b"reflex_fuzz", true)),
+            10 => {
+                let bytes = (0..BUFFER_SIZE).map(|_| self.random() as u8).collect();
+                self.buffer(bytes)
+            }
+            _ => self.random() as usize,
+        }
+    }
+
+    /// Leak `bytes` (the export may keep the pointer) and return its address
+    fn buffer(&mut self, mut bytes: Vec<u8>) -> usize {
+        bytes.resize(BUFFER_SIZE, 0);
+        Box::leak(bytes.into_boxed_slice()).as_ptr() as usize
+    }
+}
+
+/// A NUL-terminated ANSI or UTF-16 string
+fn text(chars: &[u8], wide: bool) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for &c in chars.iter().chain(&[0]) {
+        bytes.push(c);
+        if wide {
+            bytes.push(0);
+        }
+    }
+    bytes
+}
+
+fn hash(text: &str) -> u64 {
+    text.bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ b as u64).wrapping_mul(0x100_0000_01b3))
+}
+
+// ============================================================================
+// Utility Functions
+// ============================================================================
+
+/// Named code exports of `dll`
+#[cfg(windows)]
+fn exports_of(dll: &str) -> Result<Vec<String>, String> {
+    // Only the export table is needed here, so DllMain is not run
+    let module = load(dll, DONT_RESOLVE_DLL_REFERENCES)?;
+    Ok(unsafe { export_names(module as *const u8) })
+}
+
+#[cfg(not(windows))]
+fn exports_of(_dll: &str) -> Result<Vec<String>, String> {
+    Err("fuzzing needs Windows".to_string())
+}
+
+#[cfg(windows)]
+fn load(dll: &str, flags: DWORD) -> Result<HMODULE, String> {
+    let path: Vec<u16> = std::path::Path::new(dll)
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    let module = unsafe { LoadLibraryExW(path.as_ptr(), std::ptr::null_mut(), flags) };
+    if module.is_null() {
+        return Err(format!("Failed to load {}", dll));
+    }
+    Ok(module)
+}
+
+/// Named exports of a loaded module that are code, not forwarders
+#[cfg(windows)]
+unsafe fn export_names(base: *const u8) -> Vec<String> {
+    let dos = &*(base as *const IMAGE_DOS_HEADER);
+    let nt = &*(base.offset(dos.e_lfanew as isize) as *const IMAGE_NT_HEADERS);
+    let dir = nt.OptionalHeader.DataDirectory[IMAGE_DIRECTORY_ENTRY_EXPORT as usize];
+    if dir.VirtualAddress == 0 {
+        return Vec::new();
+    }
+
+    let exports = &*(base.add(dir.VirtualAddress as usize) as *const IMAGE_EXPORT_DIRECTORY);
+    let functions = std::slice::from_raw_parts(
+        base.add(exports.AddressOfFunctions as usize) as *const u32,
+        exports.NumberOfFunctions as usize,
+    );
+    let names = std::slice::from_raw_parts(
+        base.add(exports.AddressOfNames as usize) as *const u32,
+        exports.NumberOfNames as usize,
+    );
+    let ordinals = std::slice::from_raw_parts(
+        base.add(exports.AddressOfNameOrdinals as usize) as *const u16,
+        exports.NumberOfNames as usize,
+    );
+
+    let forwarded = |rva: u32| rva >= dir.VirtualAddress && rva < dir.VirtualAddress + dir.Size;
+    names
+        .iter()
+        .zip(ordinals)
+        .filter(|(_, &index)| functions.get(index as usize).is_some_and(|&rva| rva != 0 && !forwarded(rva)))
+        .map(|(&name, _)| CStr::from_ptr(base.add(name as usize) as *const i8).to_string_lossy().into_owned())
+        .filter(|name| name != "DllMain")
+        .collect()
+}
@@ -0,0 +1,80 @@
+//! Post-link fixes for the built proxy
+//!
+//! Writes a valid PE checksum into reflex.dll, which MSVC's `/RELEASE` does
+//! at link time but other linkers do not, and can zero the Rich header so
+//! builds from different machines differ less:
+//!
+//! ```text
+//! reflex_postlink.exe <reflex.dll> [--strip-rich]
+//! ```
+//!
+//! The file is rewritten in place. Run it again after any other tool that
+//! edits the DLL (signing comes last).
+
+// Shared with the proxy, where only its unit tests use it
+#[path = "../proxy_impl/postlink.rs"]
+mod postlink;
+
+use std::fs;
+use std::process::ExitCode;
+
+struct Options {
+    path: String,
+    strip_rich: bool,
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("usage: reflex_postlink <reflex.dll> [--strip-rich]");
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut positional = Vec::new();
+    let mut strip_rich = false;
+
+    for arg in args {
+        match arg.as_str() {
+            "--strip-rich" => strip_rich = true,
+            _ => positional.push(arg),
+        }
+    }
+
+    match <[String; 1]>::try_from(positional) {
+        Ok([path]) => Ok(Options { path, strip_rich }),
+        Err(_) => Err("expected one DLL".to_string()),
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let mut data = fs::read(&options.path).map_err(|e| format!("{}: {}", options.path, e))?;
+
+    if options.strip_rich {
+        match postlink::strip_rich_header(&mut data) {
+            0 => println!("no Rich header"),
+            size => println!("zeroed the Rich header ({} bytes)", size),
+        }
+    }
+
+    let (old, new) = postlink::fix_checksum(&mut data).map_err(|e| format!("{}: {}", options.path, e))?;
+    if old == new && !options.strip_rich {
+        println!("checksum 0x{:08x} is already valid", new);
+        return Ok(());
+    }
+    fs::write(&options.path, &data).map_err(|e| format!("{}: {}", options.path, e))?;
+    println!("checksum 0x{:08x} -> 0x{:08x}", old, new);
+    Ok(())
+}
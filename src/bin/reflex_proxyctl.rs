@@ -0,0 +1,302 @@
+//! Command-line client for a running session's control channel
+//!
+//! Sends one control command (ipc.rs) to the proxy inside a running game,
+//! over its named pipe or the HTTP endpoint (`[http]`), and prints the
+//! response:
+//!
+//! ```text
+//! reflex-proxyctl.exe [--pid PID | --http PORT] <command> [argument]
+//!
+//! reflex-proxyctl.exe hooks
+//! reflex-proxyctl.exe suspend DeleteFileW
+//! reflex-proxyctl.exe capture start      # fresh sampling profile
+//! reflex-proxyctl.exe capture stop       # stop sampling, write the profile
+//! reflex-proxyctl.exe --http 9231 stats
+//! ```
+//!
+//! Without `--pid` or `--http` the pipe of the only proxy running is used.
+//! `capture` is `profile start|stop`; every other command is sent as given,
+//! see the Control Channel section of the README. JSON responses are
+//! pretty-printed, `hooks` as a table. Only `--http` works off Windows.
+//!
+//! `--validate` checks a config file instead, as the proxy would read it
+//! for the executable `--exe` (config_check.rs), and exits with 1 if
+//! anything is wrong:
+//!
+//! ```text
+//! reflex-proxyctl.exe --validate [reflex_proxy.toml] [--exe game.exe]
+//! ```
+
+// Off Windows only the HTTP transport is usable
+#![cfg_attr(not(windows), allow(dead_code))]
+
+// The proxy's config parsing, for --validate; its files start with
+// `///` module docs
+#[allow(dead_code, clippy::empty_line_after_doc_comments)]
+#[path = "../proxy_impl"]
+mod proxy_impl {
+    pub mod config;
+    pub mod config_check;
+    pub mod cow;
+    pub mod rules;
+}
+
+use proxy_impl::{config, config_check};
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, TcpStream};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// Prefix of the pipe every proxy serves, followed by the process id
+const PIPE_PREFIX: &str = "reflex-proxy-";
+/// A session that does not answer within this time is reported as hung
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+enum Target {
+    Pipe(Option<u32>),
+    Http(u16),
+}
+
+struct Options {
+    target: Target,
+    command: String,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "--validate") {
+        return validate(&args[1..]);
+    }
+
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("usage: reflex-proxyctl [--pid PID | --http PORT] <command> [argument]");
+            eprintln!("       reflex-proxyctl --validate [FILE] [--exe NAME]");
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut positional = Vec::new();
+    let mut pid = None;
+    let mut http = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--pid" => {
+                let value = args.next().ok_or("--pid needs a process id")?;
+                pid = Some(value.parse().map_err(|_| format!("invalid process id: {}", value))?);
+            }
+            "--http" => {
+                let value = args.next().ok_or("--http needs a port")?;
+                http = Some(value.parse().map_err(|_| format!("invalid port: {}", value))?);
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let target = match (pid, http) {
+        (Some(_), Some(_)) => return Err("--pid and --http cannot be combined".to_string()),
+        (_, Some(port)) => Target::Http(port),
+        (pid, None) => Target::Pipe(pid),
+    };
+
+    let command = match positional.split_first() {
+        None => return Err("expected a command".to_string()),
+        Some((command, [action])) if command == "capture" => match action.as_str() {
+            "start" | "stop" => format!("profile {}", action),
+            _ => return Err(format!("unknown capture action: {}", action)),
+        },
+        Some((command, _)) if command == "capture" => return Err("capture needs start or stop".to_string()),
+        Some(_) => positional.join(" "),
+    };
+
+    Ok(Options { target, command })
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let response = match options.target {
+        Target::Pipe(pid) => send_pipe(pid, &options.command)?,
+        Target::Http(port) => send_http(port, &options.command)?,
+    };
+
+    if let Some(message) = response.strip_prefix("error: ") {
+        return Err(message.to_string());
+    }
+
+    let is_hooks = options.command == "hooks";
+    match serde_json::from_str::<Value>(&response) {
+        Ok(Value::Array(hooks)) if is_hooks => print_hooks(&hooks),
+        Ok(value @ (Value::Object(_) | Value::Array(_))) => {
+            println!("{}", serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?)
+        }
+        _ => println!("{}", response),
+    }
+    Ok(())
+}
+
+/// Print what is wrong with a config file; exit code 1 if anything is
+fn validate(args: &[String]) -> ExitCode {
+    let mut file = config::CONFIG_FILE_NAME.to_string();
+    let mut exe = String::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.as_slice().first()) {
+            ("--exe", Some(name)) => {
+                exe = name.clone();
+                args.next();
+            }
+            ("--exe", None) => {
+                eprintln!("--exe needs an executable name");
+                return ExitCode::from(2);
+            }
+            _ => file = arg.clone(),
+        }
+    }
+
+    let text = match std::fs::read_to_string(&file) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("error: Failed to read {}: {}", file, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let diagnostics = config_check::check(&text, &exe);
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic.in_file(Path::new(&file)));
+    }
+    if diagnostics.is_empty() {
+        println!("{}: ok", file);
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn print_hooks(hooks: &[Value]) {
+    let text = |hook: &Value, key: &str| match &hook[key] {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+
+    println!("{:<32} {:<10} {:<8} TARGET", "NAME", "KIND", "ACTIVE");
+    for hook in hooks {
+        println!(
+            "{:<32} {:<10} {:<8} {}",
+            text(hook, "name"),
+            text(hook, "kind"),
+            text(hook, "active"),
+            text(hook, "target")
+        );
+    }
+}
+
+// ============================================================================
+// Transports
+// ============================================================================
+
+#[cfg(windows)]
+fn send_pipe(pid: Option<u32>, command: &str) -> Result<String, String> {
+    use std::fs::OpenOptions;
+    use std::io::{BufRead, BufReader};
+
+    let pid = match pid {
+        Some(pid) => pid,
+        None => find_session()?,
+    };
+    let name = format!(r"\\.\pipe\{}{}", PIPE_PREFIX, pid);
+
+    // The proxy serves one client at a time
+    let mut pipe = OpenOptions::new().read(true).write(true).open(&name).map_err(|e| match e.raw_os_error() {
+        Some(2) => format!("no proxy is running in process {}", pid),
+        Some(231) => format!("{} is busy with another client", name),
+        _ => format!("Failed to open {}: {}", name, e),
+    })?;
+    pipe.write_all(format!("{}\n", command).as_bytes())
+        .map_err(|e| format!("Failed to send command: {}", e))?;
+
+    let mut response = String::new();
+    BufReader::new(pipe)
+        .read_line(&mut response)
+        .map_err(|e| format!("Failed to read response: {}", e))?;
+    Ok(response.trim_end().to_string())
+}
+
+#[cfg(not(windows))]
+fn send_pipe(_pid: Option<u32>, _command: &str) -> Result<String, String> {
+    Err("named pipes are Windows only; use --http PORT".to_string())
+}
+
+/// Process id of the only proxy serving a pipe
+#[cfg(windows)]
+fn find_session() -> Result<u32, String> {
+    let entries = std::fs::read_dir(r"\\.\pipe\").map_err(|e| format!("Failed to list pipes: {}", e))?;
+    let mut pids: Vec<u32> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.strip_prefix(PIPE_PREFIX)?.parse().ok())
+        .collect();
+    pids.sort_unstable();
+    pids.dedup();
+
+    match pids.as_slice() {
+        [pid] => Ok(*pid),
+        [] => Err("no running proxy found".to_string()),
+        many => Err(format!(
+            "several proxies are running ({}), pick one with --pid",
+            many.iter().map(u32::to_string).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+/// Send `command` as the request the HTTP endpoint (http.rs) routes to it
+fn send_http(port: u16, command: &str) -> Result<String, String> {
+    let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+    let (method, path, body) = match (name, argument) {
+        ("status" | "stats" | "events" | "hooks", "") => ("GET", format!("/{}", name), ""),
+        ("suspend" | "resume", hook) if !hook.is_empty() => ("POST", format!("/hooks/{}/{}", hook, name), ""),
+        ("log", filter) => ("POST", "/log".to_string(), filter),
+        (name, "") => ("POST", format!("/{}", name), ""),
+        (name, argument) => ("POST", format!("/{}/{}", name, argument), ""),
+    };
+
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+        .map_err(|e| format!("Failed to connect to 127.0.0.1:{}: {}", port, e))?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        method,
+        path,
+        port,
+        body.len(),
+        body
+    )
+    .map_err(|e| format!("Failed to send request: {}", e))?;
+
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .map_err(|e| format!("Failed to read response: {}", e))?;
+    let (head, body) = response.split_once("\r\n\r\n").ok_or("malformed HTTP response")?;
+    let status = head.split_whitespace().nth(1).unwrap_or("");
+
+    match status {
+        "200" => Ok(body.to_string()),
+        "400" => Ok(format!("error: {}", body)),
+        _ => Err(format!("HTTP {}: {}", status, body)),
+    }
+}
@@ -0,0 +1,240 @@
+//! Offline replay of calls recorded by the proxy (`[recording]`)
+//!
+//! Loads reflex_original.dll standalone, outside the game, and calls its
+//! exports again with the recorded arguments, in recorded order:
+//!
+//! ```text
+//! reflex_replay.exe <reflex_original.dll> <reflex_calls.jsonl> [--export NAME]... [--thread ID] [--dry-run]
+//! ```
+//!
+//! Arguments that carried captured memory are passed as pointers to fresh
+//! buffers holding those bytes; all others are passed as recorded. Each
+//! call prints its return value next to the recorded one. Only `--dry-run`
+//! works off Windows.
+
+// Off Windows only the parts that need no DLL are usable
+#![cfg_attr(not(windows), allow(dead_code))]
+
+use serde::Deserialize;
+use std::fs;
+use std::process::ExitCode;
+
+/// Integer argument slots passed to every export (4 registers + 12 stack)
+const ARG_SLOTS: usize = 16;
+/// Minimum size of a re-created buffer, so reads past the captured bytes
+/// stay inside it
+const MIN_BUFFER_SIZE: usize = 4096;
+
+#[cfg(windows)]
+type ExportFn = unsafe extern "system" fn(
+    usize, usize, usize, usize, usize, usize, usize, usize,
+    usize, usize, usize, usize, usize, usize, usize, usize,
+) -> usize;
+
+#[derive(Deserialize)]
+struct Argument {
+    value: String,
+    data: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CallRecord {
+    seq: u64,
+    thread: u32,
+    export: String,
+    args: Vec<Argument>,
+    ret: String,
+}
+
+struct Options {
+    dll: String,
+    recording: String,
+    exports: Vec<String>,
+    thread: Option<u32>,
+    dry_run: bool,
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!(
+                "usage: reflex_replay <reflex_original.dll> <reflex_calls.jsonl> [--export NAME]... [--thread ID] [--dry-run]"
+            );
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut positional = Vec::new();
+    let mut exports = Vec::new();
+    let mut thread = None;
+    let mut dry_run = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--export" => exports.push(args.next().ok_or("--export needs a name")?),
+            "--thread" => {
+                let id = args.next().ok_or("--thread needs an id")?;
+                thread = Some(id.parse().map_err(|_| format!("invalid thread id '{}'", id))?);
+            }
+            "--dry-run" => dry_run = true,
+            _ => positional.push(arg),
+        }
+    }
+
+    match <[String; 2]>::try_from(positional) {
+        Ok([dll, recording]) => Ok(Options {
+            dll,
+            recording,
+            exports,
+            thread,
+            dry_run,
+        }),
+        Err(_) => Err("expected a DLL and a recording".to_string()),
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let text = fs::read_to_string(&options.recording)
+        .map_err(|e| format!("Failed to read {}: {}", options.recording, e))?;
+    let calls = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| serde_json::from_str::<CallRecord>(line).map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let selected: Vec<&CallRecord> = calls
+        .iter()
+        .filter(|c| options.exports.is_empty() || options.exports.contains(&c.export))
+        .filter(|c| options.thread.is_none_or(|t| t == c.thread))
+        .collect();
+    println!("{} of {} recorded call(s) selected", selected.len(), calls.len());
+
+    if options.dry_run {
+        for call in &selected {
+            println!("#{} {}({})", call.seq, call.export, describe_args(&call.args));
+        }
+        return Ok(());
+    }
+
+    replay(options, &selected)
+}
+
+/// Call the selected exports and compare their return values
+#[cfg(windows)]
+fn replay(options: &Options, selected: &[&CallRecord]) -> Result<(), String> {
+    use std::ffi::CString;
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::libloaderapi::{GetProcAddress, LoadLibraryW};
+
+    let path: Vec<u16> = std::path::Path::new(&options.dll)
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    let module = unsafe { LoadLibraryW(path.as_ptr()) };
+    if module.is_null() {
+        return Err(format!("Failed to load {}", options.dll));
+    }
+
+    let mut mismatches = 0;
+    for call in selected {
+        let name = CString::new(call.export.as_str()).map_err(|e| e.to_string())?;
+        let address = unsafe { GetProcAddress(module, name.as_ptr()) };
+        if address.is_null() {
+            println!("#{} {}: not exported, skipped", call.seq, call.export);
+            continue;
+        }
+
+        // Buffers must outlive the call
+        let (slots, _buffers) = build_args(&call.args)?;
+        let function: ExportFn = unsafe { std::mem::transmute(address) };
+        let ret = unsafe {
+            function(
+                slots[0], slots[1], slots[2], slots[3], slots[4], slots[5], slots[6], slots[7],
+                slots[8], slots[9], slots[10], slots[11], slots[12], slots[13], slots[14], slots[15],
+            )
+        };
+
+        let recorded = parse_value(&call.ret)?;
+        let marker = if ret == recorded {
+            ""
+        } else {
+            mismatches += 1;
+            "  <- differs"
+        };
+        println!(
+            "#{} {}({}) -> 0x{:x} (recorded 0x{:x}){}",
+            call.seq,
+            call.export,
+            describe_args(&call.args),
+            ret,
+            recorded,
+            marker
+        );
+    }
+
+    println!("{} call(s) replayed, {} return value(s) differ", selected.len(), mismatches);
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn replay(_options: &Options, _selected: &[&CallRecord]) -> Result<(), String> {
+    Err("replaying calls needs Windows; use --dry-run to list them".to_string())
+}
+
+/// Argument slots for a call, and the buffers the pointer slots refer to
+#[cfg(windows)]
+fn build_args(args: &[Argument]) -> Result<([usize; ARG_SLOTS], Vec<Vec<u8>>), String> {
+    let mut slots = [0usize; ARG_SLOTS];
+    let mut buffers = Vec::new();
+
+    for (slot, arg) in slots.iter_mut().zip(args) {
+        *slot = match &arg.data {
+            Some(data) => {
+                let mut buffer = parse_hex(data)?;
+                buffer.resize(buffer.len().max(MIN_BUFFER_SIZE), 0);
+                let pointer = buffer.as_mut_ptr() as usize;
+                buffers.push(buffer);
+                pointer
+            }
+            None => parse_value(&arg.value)?,
+        };
+    }
+
+    Ok((slots, buffers))
+}
+
+fn describe_args(args: &[Argument]) -> String {
+    args.iter()
+        .map(|a| match &a.data {
+            Some(_) => format!("&[{}]", a.value),
+            None => a.value.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn parse_value(text: &str) -> Result<usize, String> {
+    let digits = text.trim_start_matches("0x");
+    usize::from_str_radix(digits, 16).map_err(|_| format!("invalid value '{}'", text))
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    text.split_whitespace()
+        .map(|token| u8::from_str_radix(token, 16).map_err(|_| format!("invalid byte '{}'", token)))
+        .collect()
+}
@@ -0,0 +1,164 @@
+//! Offline converter for binary traces (`[trace] format = "binary"`)
+//!
+//! Turns the compact trace the proxy streams to disk into Chrome trace JSON
+//! (for Perfetto or chrome://tracing) or CSV:
+//!
+//! ```text
+//! reflex-trace-dump.exe <reflex_trace.bin> [--csv] [--output FILE]
+//! ```
+//!
+//! Without `--output` the result goes to stdout. A trace cut short by a
+//! crash is converted up to its last complete event.
+
+// Shared with the proxy, which only uses the writer half
+#[path = "../proxy_impl/binary_trace.rs"]
+#[allow(dead_code)]
+mod binary_trace;
+
+use binary_trace::{Event, FieldValue};
+use serde_json::{json, Map, Value};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::process::ExitCode;
+
+struct Options {
+    input: String,
+    output: Option<String>,
+    csv: bool,
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("usage: reflex-trace-dump <reflex_trace.bin> [--csv] [--output FILE]");
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut positional = Vec::new();
+    let mut output = None;
+    let mut csv = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => output = Some(args.next().ok_or("--output needs a file")?),
+            "--csv" => csv = true,
+            _ => positional.push(arg),
+        }
+    }
+
+    match <[String; 1]>::try_from(positional) {
+        Ok([input]) => Ok(Options { input, output, csv }),
+        Err(_) => Err("expected one binary trace".to_string()),
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let data = fs::read(&options.input).map_err(|e| format!("Failed to read {}: {}", options.input, e))?;
+    let (pid, events) = binary_trace::decode(&data[..])?;
+    eprintln!("{} event(s) from process {}", events.len(), pid);
+
+    let mut out: Box<dyn Write> = match &options.output {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?,
+        )),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+
+    let written = if options.csv {
+        write_csv(&mut out, &events)
+    } else {
+        write_chrome(&mut out, pid, &events)
+    };
+    written.and_then(|()| out.flush()).map_err(|e| e.to_string())
+}
+
+/// Same layout as the proxy's own Chrome trace output (trace.rs)
+fn write_chrome(out: &mut dyn Write, pid: u32, events: &[Event]) -> io::Result<()> {
+    let trace_events: Vec<Value> = events
+        .iter()
+        .map(|event| {
+            let args: Map<String, Value> = event
+                .fields
+                .iter()
+                .map(|(key, value)| (key.clone(), json_value(value)))
+                .collect();
+            json!({
+                "name": event.name,
+                "cat": event.cat,
+                "ph": "X",
+                "ts": event.start_ns as f64 / 1000.0,
+                "dur": event.dur_ns as f64 / 1000.0,
+                "pid": pid,
+                "tid": event.tid,
+                "args": args,
+            })
+        })
+        .collect();
+
+    let file = json!({ "traceEvents": trace_events, "displayTimeUnit": "ms" });
+    serde_json::to_writer(&mut *out, &file).map_err(io::Error::other)
+}
+
+fn write_csv(out: &mut dyn Write, events: &[Event]) -> io::Result<()> {
+    writeln!(out, "name,category,start_us,duration_us,thread,fields")?;
+    for event in events {
+        let fields = event
+            .fields
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, text(value)))
+            .collect::<Vec<_>>()
+            .join(";");
+        writeln!(
+            out,
+            "{},{},{:.3},{:.3},{},{}",
+            csv_field(&event.name),
+            csv_field(&event.cat),
+            event.start_ns as f64 / 1000.0,
+            event.dur_ns as f64 / 1000.0,
+            event.tid,
+            csv_field(&fields)
+        )?;
+    }
+    Ok(())
+}
+
+fn json_value(value: &FieldValue) -> Value {
+    match value {
+        FieldValue::U64(v) => json!(v),
+        FieldValue::I64(v) => json!(v),
+        FieldValue::F64(v) => json!(v),
+        FieldValue::Str(v) => json!(v),
+    }
+}
+
+fn text(value: &FieldValue) -> String {
+    match value {
+        FieldValue::U64(v) => v.to_string(),
+        FieldValue::I64(v) => v.to_string(),
+        FieldValue::F64(v) => v.to_string(),
+        FieldValue::Str(v) => v.clone(),
+    }
+}
+
+/// Quote a CSV field if needed
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
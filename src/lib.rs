@@ -1,14 +1,40 @@
+// Off Windows only the platform-independent modules are built, for their
+// unit tests; nothing calls into them there.
+#![cfg_attr(not(windows), allow(dead_code))]
+
+#[cfg(windows)]
 use winapi::shared::minwindef::{BOOL, DWORD, HINSTANCE, LPVOID, TRUE};
+#[cfg(windows)]
 use winapi::um::winnt::{DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH};
 
 mod proxy_impl;
 
-use proxy_impl::proxy;
-use proxy_impl::detours;
+#[cfg(windows)]
+use proxy_impl::{
+    analysis, children, config, correlation, deferred, detours, environment, exceptions, exports, hooks, hookscan, hotkeys, install, instance,
+    ipc, launches, load_retry, locate, logging, metrics, original_watch, os, patches, profiler, proxy, qpc_hook, qpc_jitter, recording, signature, sigscan, stacks, stealth, symbols,
+    syscalls, tls, watchdog, window, worker,
+    status::{self, ErrorKind, InitState},
+};
+#[cfg(all(windows, feature = "spoof"))]
+use proxy_impl::{antidebug, gpu_spoof};
+#[cfg(all(windows, feature = "nvapi"))]
+use proxy_impl::{frame_latency, nvapi, pacing, sleep_stats};
+#[cfg(all(windows, feature = "trace"))]
+use proxy_impl::trace;
+#[cfg(all(windows, feature = "network"))]
+use proxy_impl::http;
+#[cfg(all(windows, feature = "trace", feature = "network"))]
+use proxy_impl::stream;
 
+#[cfg(windows)]
 use once_cell::sync::Lazy;
+#[cfg(windows)]
 use std::sync::Mutex;
+#[cfg(windows)]
+use std::time::Duration;
 
+#[cfg(windows)]
 static INITIALIZED: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
 
 /// DllMain - Proxy entry point for reflex.dll
@@ -29,6 +55,7 @@ static INITIALIZED: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
 /// - Original functionality continues to work
 /// - Can selectively replace/intercept specific functions
 /// - Easy to maintain and debug
+#[cfg(windows)]
 #[no_mangle]
 #[allow(non_snake_case)]
 pub extern "system" fn DllMain(
@@ -44,66 +71,138 @@ pub extern "system" fn DllMain(
                 return TRUE;
             }
 
-            // Initialize logging first
-            if let Err(e) = init_logging() {
+            // Initialize logging first (buffered until the config is read)
+            if let Err(e) = logging::init() {
                 eprintln!("[reflex-proxy] Failed to initialize logging: {}", e);
                 return TRUE;
             }
 
-            log::info!("[reflex-proxy] Proxy DLL initializing...");
-            log::info!("[reflex-proxy] This is a proxy that forwards to reflex_original.dll");
+            status::set_init_state(InitState::Initializing);
+            tracing::info!("[reflex-proxy] Proxy DLL initializing...");
+            tracing::info!("[reflex-proxy] This is a proxy that forwards to reflex_original.dll");
 
-            // Configure proxy behavior
-            let config = proxy::ProxyConfig {
-                original_dll_path: "reflex_original.dll",
-                enable_logging: true,
-                enable_pre_hook: false,  // Set to true to add custom pre-processing
-                enable_post_hook: false, // Set to true to add custom post-processing
-            };
+            // Join the other copies of the proxy in this process, if any; a
+            // secondary reads the primary's config and logs to its sinks
+            let role = unsafe { instance::claim(hinst_dll as usize, status::peer_status, logging::peer_log) };
 
-            // Initialize the proxy (load original DLL)
-            unsafe {
-                if let Err(e) = proxy::initialize_proxy(&config) {
-                    log::error!("[reflex-proxy] Failed to initialize proxy: {}", e);
-                    log::error!("[reflex-proxy] Make sure reflex_original.dll exists!");
-                    return TRUE;
+            // Load reflex_proxy.toml early so config problems show up at the top of the log
+            logging::configure(&config::get().logging);
+            symbols::configure(&config::get().symbols);
+            #[cfg(feature = "trace")]
+            trace::configure(&config::get().trace);
+            #[cfg(not(feature = "trace"))]
+            if config::get().trace.enabled {
+                tracing::warn!("[reflex-proxy] [trace] is enabled but this build has no 'trace' feature");
+            }
+            if config::get().stream.enabled {
+                #[cfg(all(feature = "trace", feature = "network"))]
+                if let Err(e) = stream::start(&config::get().stream) {
+                    tracing::warn!("[reflex-proxy] Failed to start trace streaming: {}", e);
                 }
+                #[cfg(not(all(feature = "trace", feature = "network")))]
+                tracing::warn!("[reflex-proxy] [stream] is enabled but this build lacks the 'trace' or 'network' feature");
             }
 
-            log::info!("[reflex-proxy] Proxy initialized successfully");
+            // Hooks installed from here on start suspended; a second copy of
+            // the proxy in this process only forwards
+            if matches!(role, instance::Role::Secondary | instance::Role::Passive) {
+                tracing::warn!("[reflex-proxy] Another copy of the proxy is active in this process, hooks start suspended");
+                unsafe { hooks::set_passthrough(true) };
+            } else if config::get().passthrough {
+                unsafe { hooks::set_passthrough(true) };
+            }
 
-            // Optional: Initialize detours to intercept specific functions
-            // Uncomment the following lines to enable custom hooks
-            // unsafe {
-            //     if let Err(e) = detours::initialize_detours() {
-            //         log::warn!("[reflex-proxy] Failed to initialize detours: {}", e);
-            //     }
-            // }
+            // Optional: a session id (and frame numbers) to join with external ETW traces
+            if config::get().correlation.enabled {
+                correlation::start(&config::get().correlation);
+            }
 
-            log::info!("[reflex-proxy] Forwarding DllMain to original...");
+            // Search order for the original and its dependencies
+            let search_flags = os::search_flags(&config::get().loader.search).unwrap_or_else(|e| {
+                tracing::warn!("[reflex-proxy] [loader] search: {}, using the classic search order", e);
+                0
+            });
 
-            *init = true;
+            // Configure proxy behavior; the original (or the next proxy of a
+            // chain) is the one next to the proxy
+            let next_proxy = &config::get().loader.next_proxy;
+            let chained = !next_proxy.is_empty();
+            let file_name = if chained { next_proxy.as_str() } else { "reflex_original.dll" };
+            let proxy_path = unsafe { proxy::get_proxy_module_path() };
+            let mut original = proxy_path
+                .as_deref()
+                .and_then(|path| Some(path.parent()?.join(file_name)))
+                .unwrap_or_else(|| file_name.into());
+            if chained {
+                tracing::info!("[reflex-proxy] Chaining to next proxy {}", original.display());
+            }
 
-            // Forward the DLL_PROCESS_ATTACH to the original DLL
-            unsafe { proxy::forward_dllmain(hinst_dll, fdw_reason, lpv_reserved, &config) }
+            // Optional: no renamed copy, forward to the genuine reflex.dll where it is installed
+            let loader = &config::get().loader;
+            if loader.fallback && !chained && !original.exists() {
+                match unsafe { locate::find("reflex.dll", &loader.fallback_locations, proxy_path.as_deref()) } {
+                    Some(path) => {
+                        tracing::info!("[reflex-proxy] {} not found, forwarding to {}", original.display(), path.display());
+                        original = path;
+                    }
+                    None => tracing::warn!("[reflex-proxy] [loader] fallback: no installed reflex.dll found"),
+                }
+            }
+            let config = proxy::ProxyConfig::builder()
+                .original_dll(original)
+                .logging(true)
+                .manual_map(config::get().loader.manual_map)
+                .search_flags(search_flags)
+                .chained(chained)
+                .build();
+
+            // Optional: the original's TLS callbacks run while it is loaded, before its DllMain
+            let tls = &config::get().tls;
+            if (tls.log || tls.hook) && config.manual_map {
+                // The proxy runs them itself, without a load notification to hook in at
+                tracing::warn!("[reflex-proxy] [tls] has no effect with [loader] manual_map");
+            } else if tls.log || tls.hook {
+                unsafe { tls::watch(tls, &config.original_dll_name()) };
+            }
+
+            // Initialize the proxy (load original DLL)
+            if let Err(e) = unsafe { proxy::initialize_proxy(&config) } {
+                // Locked by an updater or anti-cheat: try again from a thread
+                let loader = &config::get().loader;
+                if loader.retries > 0 && load_retry::is_transient(&e) {
+                    tracing::warn!("[reflex-proxy] {}, retrying in the background", e);
+                    *init = true;
+                    retry_attach(hinst_dll as usize, config, loader.retries, Duration::from_millis(loader.retry_delay_ms));
+                    return TRUE;
+                }
+                unsafe { fail_attach(&config, &e) };
+                return TRUE;
+            }
+
+            *init = true;
+            attach_original(hinst_dll, lpv_reserved, &config)
         }
 
         DLL_PROCESS_DETACH => {
-            log::info!("[reflex-proxy] Proxy detaching, forwarding to original...");
+            tracing::info!("[reflex-proxy] Proxy detaching, shutting down...");
 
-            // Configure proxy for detach
-            let config = proxy::ProxyConfig {
-                original_dll_path: "reflex_original.dll",
-                enable_logging: true,
-                enable_pre_hook: false,
-                enable_post_hook: false,
-            };
+            // Configure proxy for detach; nothing is loaded, so no path or
+            // search flags are needed
+            let config = proxy::ProxyConfig::builder()
+                .logging(true)
+                .manual_map(config::get().loader.manual_map)
+                .build();
 
-            // Forward the DLL_PROCESS_DETACH to the original DLL
-            unsafe { proxy::forward_dllmain(hinst_dll, fdw_reason, lpv_reserved, &config) }
+            unsafe { shutdown(hinst_dll, fdw_reason, lpv_reserved, &config) }
         }
 
         _ => {
+            // Threads started while the original is still being loaded are
+            // not announced to it, like those before a LoadLibrary
+            if status::init_state() == InitState::Initializing {
+                return TRUE;
+            }
+
             // Forward other reasons to original DLL
             let config = proxy::ProxyConfig::default();
             unsafe { proxy::forward_dllmain(hinst_dll, fdw_reason, lpv_reserved, &config) }
@@ -111,17 +210,368 @@ pub extern "system" fn DllMain(
     }
 }
 
-fn init_logging() -> Result<(), Box<dyn std::error::Error>> {
-    use std::fs::OpenOptions;
+/// Everything after the original is loaded, ending with its
+/// DLL_PROCESS_ATTACH
+#[cfg(windows)]
+fn attach_original(hinst_dll: HINSTANCE, lpv_reserved: LPVOID, config: &proxy::ProxyConfig) -> BOOL {
+    tracing::info!("[reflex-proxy] Proxy initialized successfully");
+
+    // Optional: resolve every export now rather than on each first call
+    if config::get().loader.preload_exports {
+        let cached = unsafe { proxy::preload_exports() };
+        tracing::info!("[reflex-proxy] Preloaded {} export address(es)", cached);
+    }
+
+    // Warn when the original's exports no longer match the analysed build
+    unsafe { exports::check_drift(proxy::get_original_dll_base() as *const u8) };
+
+    // Byte patches from reflex_proxy.toml, before the original's DllMain runs
+    unsafe {
+        let patches = &config::get().patches;
+        let applied = patches::apply_all_configured(proxy::get_original_dll_base() as *const u8, patches);
+        let own = patches.iter().filter(|p| p.module.is_none()).count();
+        if own > 0 {
+            tracing::info!("[reflex-proxy] Applied {}/{} configured patch(es)", applied, own);
+            sigscan::save_cache();
+        }
+    }
+
+    // Optional: warn loudly when the original's signature is missing or broken
+    if config::get().signature.enabled {
+        if unsafe { proxy::is_chained() } {
+            tracing::info!("[reflex-proxy] [signature] is left to the next proxy of the chain");
+        } else {
+            unsafe { signature::start(&config::get().signature) };
+        }
+    }
+
+    // Warn when an update replaces the original on disk
+    if config::get().original_watch.enabled {
+        unsafe { original_watch::start(&config::get().original_watch) };
+    }
+
+    // Control channel for external tools (status, hook suspend/resume)
+    if let Err(e) = ipc::start_server() {
+        tracing::warn!("[reflex-proxy] Failed to start control channel: {}", e);
+    }
+    if config::get().http.enabled {
+        #[cfg(feature = "network")]
+        if let Err(e) = http::start(&config::get().http) {
+            tracing::warn!("[reflex-proxy] Failed to start HTTP endpoint: {}", e);
+        }
+        #[cfg(not(feature = "network"))]
+        tracing::warn!("[reflex-proxy] [http] is enabled but this build has no 'network' feature");
+    }
+    if config::get().metrics.enabled {
+        metrics::start(&config::get().metrics);
+    }
+    // Optional: report calls into the original that never return
+    if config::get().watchdog.enabled {
+        unsafe { watchdog::start(&config::get().watchdog) };
+    }
+    // Optional: log exceptions the original raises, including during its DllMain
+    if config::get().exceptions.enabled {
+        unsafe { exceptions::install(&config::get().exceptions) };
+    }
+    // UI features, optionally once the game's window exists
+    window::when_ready(&config::get().window, || hotkeys::start(&config::get().hotkeys));
+    if config::get().profiler.enabled {
+        profiler::start(&config::get().profiler);
+    }
+
+    // Optional: log who calls selected detours and syscall hooks
+    stacks::configure(&config::get().stacks);
+
+    // Optional: Initialize detours to intercept specific functions
+    // Enable with `detours = true` in reflex_proxy.toml
+    if config::get().detours {
+        unsafe {
+            if let Err(e) = detours::initialize_detours() {
+                status::record_error(ErrorKind::Detour);
+                tracing::warn!("[reflex-proxy] Failed to initialize detours: {}", e);
+            }
+        }
+    }
+
+    // Optional: follow the game's NVIDIA Reflex calls once nvapi64.dll loads
+    if config::get().nvapi.enabled {
+        #[cfg(feature = "nvapi")]
+        unsafe { nvapi::initialize(&config::get().nvapi) };
+        #[cfg(not(feature = "nvapi"))]
+        tracing::warn!("[reflex-proxy] [nvapi] is enabled but this build has no 'nvapi' feature");
+    }
+
+    // Optional: hide an attached debugger from the original DLL
+    if config::get().anti_debug.enabled {
+        #[cfg(feature = "spoof")]
+        unsafe { antidebug::initialize() };
+        #[cfg(not(feature = "spoof"))]
+        tracing::warn!("[reflex-proxy] [anti_debug] is enabled but this build has no 'spoof' feature");
+    }
+
+    // Optional: present another GPU and driver to the original DLL
+    if config::get().gpu_identity.enabled {
+        #[cfg(feature = "spoof")]
+        unsafe { gpu_spoof::initialize(&config::get().gpu_identity) };
+        #[cfg(not(feature = "spoof"))]
+        tracing::warn!("[reflex-proxy] [gpu_identity] is enabled but this build has no 'spoof' feature");
+    }
+
+    // Optional: perturb the original's QueryPerformanceCounter readings
+    if config::get().qpc_jitter.enabled {
+        if let Err(e) = unsafe { qpc_hook::initialize(&config::get().qpc_jitter) } {
+            status::record_error(ErrorKind::Detour);
+            tracing::warn!("[reflex-proxy] Failed to start QPC jitter: {}", e);
+        }
+    }
+
+    // Optional: ntdll stub hooks for calls that bypass the Win32 layer
+    if config::get().syscalls.enabled {
+        unsafe { syscalls::initialize(&config::get().syscalls) };
+    }
+
+    // Optional: record calls into the original's exports
+    if config::get().recording.enabled {
+        if let Err(e) = unsafe { recording::initialize(&config::get().recording) } {
+            status::record_error(ErrorKind::Detour);
+            tracing::warn!("[reflex-proxy] Failed to start call recording: {}", e);
+        }
+    }
+
+    // Optional: log what the original launches
+    if config::get().launches.enabled {
+        unsafe { launches::initialize() };
+    }
+
+    // Optional: carry the config and output location over to child processes
+    if config::get().children.enabled {
+        unsafe { children::initialize(&config::get().children) };
+    }
+
+    // Optional: unlink the proxy from the loader's module lists
+    if config::get().stealth.enabled {
+        unsafe { stealth::initialize(hinst_dll as usize, &config::get().stealth) };
+    }
+
+    tracing::info!("[reflex-proxy] Forwarding DllMain to original...");
+
+    status::set_init_state(InitState::Initialized);
+
+    // Forward the DLL_PROCESS_ATTACH to the original DLL
+    let result = unsafe { proxy::forward_dllmain(hinst_dll, DLL_PROCESS_ATTACH, lpv_reserved, config) };
+
+    // Record what the proxy was loaded into, for bug reports
+    if result != 0 && config::get().environment.enabled {
+        environment::write(&config::get().environment);
+    }
+
+    // Optional: find what the original hooked in system DLLs during its DllMain
+    if config::get().hook_scan.enabled {
+        if let Err(e) = unsafe { hookscan::run(&config::get().hook_scan) } {
+            tracing::warn!("[reflex-proxy] Hook scan failed: {}", e);
+        }
+    }
+
+    // Optional: list the original's C++ classes and their vtables
+    if config::get().rtti.enabled {
+        if let Err(e) = analysis::write_classes(&config::get().rtti) {
+            tracing::warn!("[reflex-proxy] RTTI scan failed: {}", e);
+        }
+    }
+
+    result
+}
+
+/// Load the original from a background thread after a transient failure
+/// at attach, then finish the attach there (load_retry.rs)
+#[cfg(windows)]
+fn retry_attach(hinst_dll: usize, config: proxy::ProxyConfig, retries: u32, first_delay: Duration) {
+    let fallback = config.clone();
+    let spawned = std::thread::Builder::new()
+        .name("reflex-load-retry".to_string())
+        .spawn(move || match load_retry::retry(retries, first_delay, || unsafe { proxy::initialize_proxy(&config) }) {
+            Ok(attempt) => {
+                tracing::info!("[reflex-proxy] Loaded the original on retry {}", attempt);
+                // Like a LoadLibrary after process start
+                attach_original(hinst_dll as HINSTANCE, std::ptr::null_mut(), &config);
+            }
+            Err(_) if load_retry::cancelled() => {}
+            Err(e) => unsafe { fail_attach(&config, &e) },
+        });
+
+    if let Err(e) = spawned {
+        unsafe { fail_attach(&fallback, &format!("Failed to spawn the load retry thread: {}", e)) };
+    }
+}
+
+/// The original could not be loaded: nothing is forwarded and every hook
+/// stays suspended
+#[cfg(windows)]
+unsafe fn fail_attach(config: &proxy::ProxyConfig, error: &str) {
+    status::record_error(ErrorKind::Load);
+    status::set_init_state(InitState::Failed);
+    hooks::set_passthrough(true);
+    tracing::error!("[reflex-proxy] Failed to initialize proxy: {}", error);
+    tracing::error!(
+        "[reflex-proxy] Make sure {} exists!",
+        config.original_dll_path.display()
+    );
+    if config::get().error_dialog {
+        logging::flush();
+        show_fatal_error(error);
+    }
+}
+
+/// Tell the user why the game is about to fail (`error_dialog = true`)
+///
+/// Runs from DllMain, so the game waits until the box is dismissed. user32
+/// is a static import of the proxy and already loaded.
+#[cfg(windows)]
+unsafe fn show_fatal_error(error: &str) {
+    // The load error ends with the Win32 error code
+    let code = error.rsplit("error ").next().and_then(|code| code.trim().parse::<u32>().ok());
+    let hint = match code {
+        // ERROR_BAD_EXE_FORMAT
+        Some(193) => {
+            "reflex_original.dll is built for a different architecture (32-bit vs 64-bit) than the game. \
+             Use the proxy build that matches the game."
+        }
+        // ERROR_FILE_NOT_FOUND, ERROR_PATH_NOT_FOUND, ERROR_MOD_NOT_FOUND
+        Some(2 | 3 | 126) => {
+            "reflex_original.dll, or a DLL it depends on, was not found. Rename the game's original DLL to \
+             reflex_original.dll and keep it next to the proxy."
+        }
+        _ => "Run \"rundll32 reflex.dll,ReflexProxySelfTest <game dir>\" for a diagnosis.",
+    };
+    let log = &config::get().logging;
+    let details = if log.file {
+        format!("\n\nDetails are in {}.", log.path)
+    } else {
+        String::new()
+    };
+
+    install::show_result(
+        std::ptr::null_mut(),
+        &format!("Reflex Proxy could not start:\n{}\n\n{}{}", error, hint, details),
+        true,
+    );
+}
+
+/// Orderly shutdown on DLL_PROCESS_DETACH
+///
+/// Hooks redirect into this DLL's code, so they are removed before anything
+/// else. The original DLL handle is released last because the forwarded
+/// detach still needs the original mapped. When the process is terminating
+/// (`lpv_reserved` non-null) the loader cleans up, so the handle is kept.
+#[cfg(windows)]
+unsafe fn shutdown(
+    hinst_dll: HINSTANCE,
+    fdw_reason: DWORD,
+    lpv_reserved: LPVOID,
+    config: &proxy::ProxyConfig,
+) -> BOOL {
+    if !lpv_reserved.is_null() {
+        worker::set_exiting();
+    }
+    load_retry::cancel();
+    ipc::stop_server();
+    #[cfg(feature = "network")]
+    http::stop();
+    #[cfg(all(feature = "trace", feature = "network"))]
+    stream::stop();
+    window::stop();
+    hotkeys::stop();
+    profiler::stop();
+    metrics::stop();
+    original_watch::stop();
+    signature::stop();
+    deferred::shutdown();
+
+    let removed = hooks::remove_all_hooks();
+    tracing::info!("[reflex-proxy] Removed {} hook(s)", removed);
+    let reverted = patches::revert_kept_sets();
+    tracing::info!("[reflex-proxy] Reverted {} patch(es)", reverted);
+    recording::flush();
+    if stealth::unhide() {
+        tracing::info!("[reflex-proxy] Relinked proxy module entry");
+    }
+    logging::flush();
+
+    // Forward the DLL_PROCESS_DETACH to the original DLL
+    let result = proxy::forward_dllmain(hinst_dll, fdw_reason, lpv_reserved, config);
+    for stats in proxy::dllmain_stats().iter().filter(|stats| stats.count > 0) {
+        tracing::info!(
+            "[reflex-proxy] Forwarded {} {} time(s), first {}, last {}",
+            stats.reason,
+            stats.count,
+            stats.first.as_deref().unwrap_or("-"),
+            stats.last.as_deref().unwrap_or("-")
+        );
+    }
+    watchdog::stop();
+    exceptions::remove();
+
+    if lpv_reserved.is_null() {
+        proxy::release_original_dll();
+    }
+    instance::release(!lpv_reserved.is_null());
+
+    #[cfg(feature = "trace")]
+    if config::get().trace.enabled {
+        match trace::write() {
+            Ok((path, count)) => tracing::info!("[reflex-proxy] Wrote {} trace event(s) to {}", count, path),
+            Err(e) => tracing::error!("[reflex-proxy] Failed to write trace: {}", e),
+        }
+    }
+
+    let recording = &config::get().recording;
+    if recording.enabled && !recording.coverage_path.is_empty() {
+        match recording::write_coverage() {
+            Ok((path, called, total)) => {
+                tracing::info!("[reflex-proxy] {}/{} export(s) were called, coverage written to {}", called, total, path)
+            }
+            Err(e) => tracing::error!("[reflex-proxy] Failed to write export coverage: {}", e),
+        }
+    }
+
+    if config::get().profiler.enabled {
+        match profiler::write() {
+            Ok((path, count)) => tracing::info!("[reflex-proxy] Wrote profile of {} sample(s) to {}", count, path),
+            Err(e) => tracing::error!("[reflex-proxy] Failed to write profile: {}", e),
+        }
+    }
+
+    if config::get().metrics.enabled {
+        match metrics::write() {
+            Ok(path) => tracing::info!("[reflex-proxy] Wrote metrics to {}", path),
+            Err(e) => tracing::error!("[reflex-proxy] Failed to write metrics: {}", e),
+        }
+    }
 
-    let log_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("reflex.log")?;
+    if config::get().qpc_jitter.enabled {
+        qpc_jitter::finish();
+    }
 
-    env_logger::Builder::from_default_env()
-        .target(env_logger::Target::Pipe(Box::new(log_file)))
-        .init();
+    #[cfg(feature = "nvapi")]
+    if config::get().nvapi.enabled {
+        match sleep_stats::write_summary(&config::get().nvapi.summary_path) {
+            Ok(Some(path)) => tracing::info!("[reflex-proxy] Wrote sleep summary to {}", path),
+            Ok(None) => {}
+            Err(e) => tracing::error!("[reflex-proxy] Failed to write sleep summary: {}", e),
+        }
+        if config::get().nvapi.pacing.enabled {
+            match pacing::write_summary(&config::get().nvapi.pacing.summary_path) {
+                Ok(Some(path)) => tracing::info!("[reflex-proxy] Wrote pacing summary to {}", path),
+                Ok(None) => {}
+                Err(e) => tracing::error!("[reflex-proxy] Failed to write pacing summary: {}", e),
+            }
+            if let Err(e) = frame_latency::finish() {
+                tracing::error!("[reflex-proxy] Failed to write frame latency export: {}", e);
+            }
+        }
+    }
 
-    Ok(())
+    tracing::info!("[reflex-proxy] Shutdown complete");
+    logging::flush();
+    result
 }
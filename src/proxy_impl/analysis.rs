@@ -0,0 +1,192 @@
+/// Static analysis of the original, from inside the process
+///
+/// Works on a copy of reflex_original.dll as it is mapped (unpacked and
+/// relocated), so results are live addresses ready for the hook functions:
+/// - `string_refs`: code referencing a string, the quickest way to find a
+///   function by one of its log or error messages (xrefs.rs)
+/// - `callers`: calls to a function, to map the call graph one function
+///   at a time (xrefs.rs)
+/// - `classes` / `vtable`: C++ classes with vtables (rtti.rs)
+///
+/// ```rust,ignore
+/// declare_hook! {
+///     fn Player_Update(this: *mut c_void, delta: f32) {
+///         call_original(this, delta)
+///     }
+/// }
+///
+/// let vtable = analysis::vtable("game::Player").ok_or("no RTTI for game::Player")?;
+/// Player_Update::install_vtable(vtable, 3)?;
+/// ```
+///
+/// The `strings <text>` and `callers <address>` pipe commands list the
+/// references to a string and the calls to a function.
+/// C++ classes come from MSVC RTTI and are scanned once. With
+/// `[rtti] enabled` they are logged and written to `path` after the
+/// original's DllMain returns; the `rtti` pipe command does the same on
+/// demand.
+///
+/// ```toml
+/// [rtti]
+/// enabled = true
+/// path = "reflex_rtti.json"
+/// ```
+
+use crate::proxy;
+use crate::proxy_impl::config::RttiConfig;
+use crate::proxy_impl::rtti::{self, Class};
+use crate::proxy_impl::{image_dump, pe, xrefs};
+use once_cell::sync::OnceCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+/// Classes of the original, scanned on first use
+static CLASSES: OnceCell<Vec<Class>> = OnceCell::new();
+
+/// An instruction referencing a string
+#[derive(Debug, Clone)]
+pub struct StringRef {
+    /// The whole string, of which the searched text is a part
+    pub string: String,
+    /// Address of the string
+    pub string_address: usize,
+    /// Address of the instruction
+    pub address: usize,
+    /// Start of the function containing the instruction, from the unwind
+    /// data
+    pub function: Option<usize>,
+}
+
+/// Code in the original referencing a string that contains `text`
+pub fn string_refs(text: &str) -> Result<Vec<StringRef>, String> {
+    let base = unsafe { proxy::get_original_dll_base() } as usize;
+    let (image, _) = unsafe { image_dump::read_image(base) }?;
+    let sections = unsafe { pe::sections(base as *const u8) };
+
+    let mut strings = HashMap::new();
+    for section in sections.iter().filter(|s| !s.is_executable()) {
+        for (start, string) in xrefs::find_strings(section_bytes(&image, section), text) {
+            strings.insert((base + section.rva as usize + start) as u64, string);
+        }
+    }
+    let targets: HashSet<u64> = strings.keys().copied().collect();
+
+    let mut refs = Vec::new();
+    for section in sections.iter().filter(|s| s.is_executable()) {
+        let ip = (base + section.rva as usize) as u64;
+        for (address, target) in xrefs::rip_relative_refs(section_bytes(&image, section), ip, &targets) {
+            let rva = (address as usize - base) as u32;
+            refs.push(StringRef {
+                string: strings[&target].clone(),
+                string_address: target as usize,
+                address: address as usize,
+                function: unsafe { pe::function_range(base as *const u8, rva) }.map(|(start, _)| base + start as usize),
+            });
+        }
+    }
+    tracing::info!(
+        "[analysis] {} string(s) containing {:?}, {} reference(s)",
+        strings.len(),
+        text,
+        refs.len()
+    );
+    Ok(refs)
+}
+
+/// A call instruction
+#[derive(Debug, Clone)]
+pub struct Caller {
+    /// Address of the call
+    pub address: usize,
+    /// Start of the function making the call, from the unwind data
+    pub function: Option<usize>,
+}
+
+/// Calls in the original's code to `function` (an address in any module),
+/// made directly or through a pointer stored in the original
+pub fn callers(function: usize) -> Result<Vec<Caller>, String> {
+    let base = unsafe { proxy::get_original_dll_base() } as usize;
+    let (image, _) = unsafe { image_dump::read_image(base) }?;
+    let sections = unsafe { pe::sections(base as *const u8) };
+
+    // Import slots, function pointer tables
+    let mut slots = HashSet::new();
+    for section in sections.iter().filter(|s| !s.is_executable()) {
+        let data = section_bytes(&image, section);
+        for (i, pointer) in data.chunks_exact(8).enumerate() {
+            if u64::from_le_bytes(pointer.try_into().unwrap()) == function as u64 {
+                slots.insert((base + section.rva as usize + i * 8) as u64);
+            }
+        }
+    }
+
+    let mut callers = Vec::new();
+    for section in sections.iter().filter(|s| s.is_executable()) {
+        let ip = (base + section.rva as usize) as u64;
+        for address in xrefs::calls_to(section_bytes(&image, section), ip, function as u64, &slots) {
+            let rva = (address as usize - base) as u32;
+            callers.push(Caller {
+                address: address as usize,
+                function: unsafe { pe::function_range(base as *const u8, rva) }.map(|(start, _)| base + start as usize),
+            });
+        }
+    }
+    tracing::info!(
+        "[analysis] {} call(s) to 0x{:x} ({} pointer slot(s))",
+        callers.len(),
+        function,
+        slots.len()
+    );
+    Ok(callers)
+}
+
+/// Bytes of a section in a copy of its image
+fn section_bytes<'a>(image: &'a [u8], section: &pe::Section) -> &'a [u8] {
+    let start = (section.rva as usize).min(image.len());
+    let end = (section.rva as usize + section.size as usize).min(image.len());
+    &image[start..end]
+}
+
+/// Every class with a vtable in the original, by name and subobject offset
+pub fn classes() -> Result<&'static [Class], String> {
+    CLASSES
+        .get_or_try_init(|| {
+            let base = unsafe { proxy::get_original_dll_base() } as usize;
+            let (image, _) = unsafe { image_dump::read_image(base) }?;
+            Ok(rtti::scan(&image, base as u64))
+        })
+        .map(Vec::as_slice)
+}
+
+/// Address of the primary vtable of a class, e.g. "game::Player"
+pub fn vtable(class: &str) -> Option<usize> {
+    let base = unsafe { proxy::get_original_dll_base() } as usize;
+    classes()
+        .ok()?
+        .iter()
+        .find(|c| c.name == class && c.offset == 0)
+        .map(|c| base + c.vtable as usize)
+}
+
+/// Log the classes and write them to `path`; returns the path and count
+pub fn write_classes(config: &RttiConfig) -> Result<(String, usize), String> {
+    if config.path.is_empty() {
+        return Err("RTTI file is disabled".to_string());
+    }
+    let base = unsafe { proxy::get_original_dll_base() } as usize;
+    let classes = classes()?;
+
+    for class in classes {
+        tracing::info!(
+            "[analysis] {} vtable 0x{:x} (reflex_original.dll+0x{:x}, offset {})",
+            class.name,
+            base + class.vtable as usize,
+            class.vtable,
+            class.offset
+        );
+    }
+    let json = serde_json::to_string_pretty(classes).map_err(|e| e.to_string())?;
+    fs::write(&config.path, json).map_err(|e| format!("Failed to write {}: {}", config.path, e))?;
+    tracing::info!("[analysis] {} class vtable(s), wrote {}", classes.len(), config.path);
+    Ok((config.path.clone(), classes.len()))
+}
@@ -0,0 +1,162 @@
+/// Anti-anti-debug hooks
+///
+/// Some code changes behavior when it notices a debugger. With
+/// `[anti_debug] enabled = true` the debugger checks imported by
+/// reflex_original.dll are answered with "no debugger":
+/// - IsDebuggerPresent returns FALSE
+/// - CheckRemoteDebuggerPresent reports FALSE
+/// - NtQueryInformationProcess hides ProcessDebugPort, ProcessDebugObjectHandle
+///   and ProcessDebugFlags for the current process
+///
+/// The hooks patch the import table of the original DLL only, so the game
+/// and every other module still see the real answer. Checks the original
+/// performs without an import (reading PEB.BeingDebugged, GetProcAddress)
+/// are not covered.
+
+use crate::proxy;
+use crate::proxy_impl::{hooks, pe};
+use crate::proxy_impl::status::{self, ErrorKind};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use winapi::shared::minwindef::{BOOL, FALSE, PBOOL, TRUE};
+use winapi::shared::ntdef::{HANDLE, NTSTATUS, PULONG, PVOID, ULONG};
+use winapi::um::processthreadsapi::{GetCurrentProcess, GetCurrentProcessId, GetProcessId};
+
+const PROCESS_DEBUG_PORT: ULONG = 7;
+const PROCESS_DEBUG_OBJECT_HANDLE: ULONG = 30;
+const PROCESS_DEBUG_FLAGS: ULONG = 31;
+const STATUS_PORT_NOT_SET: NTSTATUS = 0xC000_0353_u32 as NTSTATUS;
+const STATUS_UNSUCCESSFUL: NTSTATUS = 0xC000_0001_u32 as NTSTATUS;
+
+type CheckRemoteDebuggerPresentFn = unsafe extern "system" fn(HANDLE, PBOOL) -> BOOL;
+type NtQueryInformationProcessFn = unsafe extern "system" fn(HANDLE, ULONG, PVOID, ULONG, PULONG) -> NTSTATUS;
+
+static IS_DEBUGGER_PRESENT: AtomicUsize = AtomicUsize::new(0);
+static CHECK_REMOTE_DEBUGGER_PRESENT: AtomicUsize = AtomicUsize::new(0);
+static NT_QUERY_INFORMATION_PROCESS: AtomicUsize = AtomicUsize::new(0);
+
+/// Hook the debugger checks imported by the original DLL
+///
+/// Returns the number of hooks installed. Missing imports are normal.
+pub unsafe fn initialize() -> usize {
+    let base = proxy::get_original_dll_base() as *const u8;
+    if base.is_null() {
+        return 0;
+    }
+
+    let hooks: [(&str, usize, &AtomicUsize); 3] = [
+        (
+            "IsDebuggerPresent",
+            hooked_is_debugger_present as *const () as usize,
+            &IS_DEBUGGER_PRESENT,
+        ),
+        (
+            "CheckRemoteDebuggerPresent",
+            hooked_check_remote_debugger_present as *const () as usize,
+            &CHECK_REMOTE_DEBUGGER_PRESENT,
+        ),
+        (
+            "NtQueryInformationProcess",
+            hooked_nt_query_information_process as *const () as usize,
+            &NT_QUERY_INFORMATION_PROCESS,
+        ),
+    ];
+
+    let mut installed = 0;
+    for (function, detour, original) in hooks.iter() {
+        if pe::find_import_slot(base, None, function).is_none() {
+            tracing::debug!("[anti_debug] {} not imported, skipped", function);
+            continue;
+        }
+
+        let name = format!("anti_debug!{}", function);
+        match hooks::install_iat_hook(&name, base, function, *detour) {
+            Ok(address) => {
+                original.store(address, Ordering::SeqCst);
+                installed += 1;
+            }
+            Err(e) => {
+                status::record_error(ErrorKind::Detour);
+                tracing::warn!("[anti_debug] Failed to hook {}: {}", function, e);
+            }
+        }
+    }
+
+    tracing::info!("[anti_debug] Hiding the debugger from the original DLL ({} hook(s))", installed);
+    installed
+}
+
+// ============================================================================
+// Hook Implementations
+// ============================================================================
+
+unsafe extern "system" fn hooked_is_debugger_present() -> BOOL {
+    tracing::debug!("[anti_debug] IsDebuggerPresent -> FALSE");
+    FALSE
+}
+
+unsafe extern "system" fn hooked_check_remote_debugger_present(process: HANDLE, present: PBOOL) -> BOOL {
+    let result = match original::<CheckRemoteDebuggerPresentFn>(&CHECK_REMOTE_DEBUGGER_PRESENT) {
+        Some(original) => original(process, present),
+        None => TRUE,
+    };
+
+    if result != FALSE && !present.is_null() {
+        tracing::debug!("[anti_debug] CheckRemoteDebuggerPresent -> FALSE");
+        *present = FALSE;
+    }
+    result
+}
+
+unsafe extern "system" fn hooked_nt_query_information_process(
+    process: HANDLE,
+    class: ULONG,
+    information: PVOID,
+    length: ULONG,
+    return_length: PULONG,
+) -> NTSTATUS {
+    let original = match original::<NtQueryInformationProcessFn>(&NT_QUERY_INFORMATION_PROCESS) {
+        Some(original) => original,
+        None => return STATUS_UNSUCCESSFUL,
+    };
+    let status = original(process, class, information, length, return_length);
+
+    if status < 0 || information.is_null() || !is_current_process(process) {
+        return status;
+    }
+
+    match class {
+        PROCESS_DEBUG_PORT if length as usize >= std::mem::size_of::<usize>() => {
+            tracing::debug!("[anti_debug] Hiding ProcessDebugPort");
+            *(information as *mut usize) = 0;
+            status
+        }
+        PROCESS_DEBUG_OBJECT_HANDLE if length as usize >= std::mem::size_of::<usize>() => {
+            tracing::debug!("[anti_debug] Hiding ProcessDebugObjectHandle");
+            *(information as *mut usize) = 0;
+            STATUS_PORT_NOT_SET
+        }
+        PROCESS_DEBUG_FLAGS if length as usize >= std::mem::size_of::<ULONG>() => {
+            tracing::debug!("[anti_debug] Hiding ProcessDebugFlags");
+            // NoDebugInherit set means "not being debugged"
+            *(information as *mut ULONG) = 1;
+            status
+        }
+        _ => status,
+    }
+}
+
+// ============================================================================
+// Utility Functions
+// ============================================================================
+
+/// The original function stored in `slot`, once its hook is installed
+unsafe fn original<F>(slot: &AtomicUsize) -> Option<F> {
+    match slot.load(Ordering::SeqCst) {
+        0 => None,
+        address => Some(std::mem::transmute_copy(&address)),
+    }
+}
+
+unsafe fn is_current_process(process: HANDLE) -> bool {
+    process == GetCurrentProcess() || GetProcessId(process) == GetCurrentProcessId()
+}
@@ -0,0 +1,109 @@
+/// Operation audit log (JSON Lines)
+///
+/// Every file, registry and process operation of reflex_original.dll that
+/// passes through a detour is appended to a separate audit file, one JSON
+/// object per line, for later behavioral analysis:
+///
+/// ```json
+/// {"timestamp_ms":1714590000123,"thread":4242,"category":"file","api":"DeleteFileW",
+///  "args":{"path":"C:\\tmp\\x.cfg"},"decision":"allow","result":1}
+/// ```
+///
+/// Enabled with `[audit] enabled = true` in reflex_proxy.toml. Lines are
+/// written unbuffered so nothing is lost if the game crashes.
+
+use crate::proxy_impl::config;
+use crate::proxy_impl::rules::Decision;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use winapi::um::processthreadsapi::GetCurrentThreadId;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    File,
+    /// Only recorded by the `spoof` detours so far
+    #[cfg_attr(not(feature = "spoof"), allow(dead_code))]
+    Registry,
+    Process,
+    /// Device I/O control requests
+    Device,
+    /// System-wide information queries
+    System,
+}
+
+#[derive(Serialize)]
+struct AuditEvent<'a> {
+    timestamp_ms: u128,
+    thread: u32,
+    category: Category,
+    api: &'a str,
+    args: serde_json::Value,
+    decision: &'a str,
+    result: i64,
+}
+
+static AUDIT_FILE: Lazy<Mutex<Option<File>>> = Lazy::new(|| Mutex::new(open_audit_file()));
+
+fn open_audit_file() -> Option<File> {
+    let audit = &config::get().audit;
+    if !audit.enabled {
+        return None;
+    }
+
+    match OpenOptions::new().create(true).append(true).open(&audit.path) {
+        Ok(file) => {
+            tracing::info!("[audit] Writing audit log to {}", audit.path);
+            Some(file)
+        }
+        Err(e) => {
+            tracing::error!("[audit] Failed to open {}: {}", audit.path, e);
+            None
+        }
+    }
+}
+
+/// Append one intercepted operation to the audit log
+pub fn record(
+    category: Category,
+    api: &str,
+    args: serde_json::Value,
+    decision: &Decision,
+    result: i64,
+) {
+    if !config::get().audit.enabled {
+        return;
+    }
+
+    let event = AuditEvent {
+        timestamp_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0),
+        thread: unsafe { GetCurrentThreadId() },
+        category,
+        api,
+        args,
+        decision: decision.label(),
+        result,
+    };
+
+    let mut line = match serde_json::to_string(&event) {
+        Ok(line) => line,
+        Err(e) => {
+            tracing::error!("[audit] Failed to serialize event: {}", e);
+            return;
+        }
+    };
+    line.push('\n');
+
+    if let Some(file) = AUDIT_FILE.lock().unwrap().as_mut() {
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            tracing::error!("[audit] Failed to write event: {}", e);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+/// The `overhead [calls]` control command: hook overhead microbenchmark
+///
+/// Times a no-op function standing in for an export of the original,
+/// called directly and through hooks with more and more of the proxy's
+/// layers in their detours (overhead.rs). The hooks are never installed:
+/// their trampoline slot is pointed at the no-op, so the detours are called
+/// like a patched function would call them, without touching any code.
+
+use crate::proxy_impl::detours;
+use crate::proxy_impl::hooks::declare_hook;
+use crate::proxy_impl::overhead::{self, Report};
+use std::hint::black_box;
+use std::sync::atomic::Ordering;
+
+/// Calls per layer when the command gives no count
+pub const DEFAULT_CALLS: u64 = 100_000;
+
+/// Stands in for an export of the original
+#[inline(never)]
+unsafe extern "system" fn noop(value: usize) -> usize {
+    black_box(value)
+}
+
+declare_hook! {
+    /// The detour and dispatch every detours.rs hook goes through
+    fn ReflexBenchDispatch(value: usize) -> usize {
+        detours::dispatch("ReflexBenchDispatch", move || unsafe { call_original(value) as i64 }) as usize
+    }
+}
+
+declare_hook! {
+    /// Also opens a span with the argument
+    fn ReflexBenchSpan(value: usize) -> usize {
+        let span = tracing::info_span!("ReflexBenchSpan", value);
+        let _enter = span.enter();
+        detours::dispatch("ReflexBenchSpan", move || unsafe { call_original(value) as i64 }) as usize
+    }
+}
+
+declare_hook! {
+    /// Also logs an event
+    fn ReflexBenchLog(value: usize) -> usize {
+        let span = tracing::info_span!("ReflexBenchLog", value);
+        let _enter = span.enter();
+        tracing::debug!("[bench] Call {}", value);
+        detours::dispatch("ReflexBenchLog", move || unsafe { call_original(value) as i64 }) as usize
+    }
+}
+
+/// Time every layer over `calls` calls each
+pub fn run(calls: u64) -> Result<Report, String> {
+    if calls == 0 {
+        return Err("the number of calls must be positive".to_string());
+    }
+    for original in [&ReflexBenchDispatch::ORIGINAL, &ReflexBenchSpan::ORIGINAL, &ReflexBenchLog::ORIGINAL] {
+        original.store(noop as *const () as usize, Ordering::SeqCst);
+    }
+
+    let layers: [(&'static str, unsafe extern "system" fn(usize) -> usize); 4] = [
+        ("direct", noop),
+        ("dispatcher", ReflexBenchDispatch::detour),
+        ("tracer", ReflexBenchSpan::detour),
+        ("logging", ReflexBenchLog::detour),
+    ];
+    let timings: Vec<(&'static str, f64)> = layers
+        .iter()
+        .map(|&(layer, function)| {
+            // Through a pointer the compiler cannot see, like a caller of an export
+            let function = black_box(function);
+            (layer, overhead::per_call_ns(calls, |i| unsafe { black_box(function(i as usize)); }))
+        })
+        .collect();
+
+    let report = Report::new(calls, &timings);
+    tracing::info!("[bench] {}", report.line());
+    Ok(report)
+}
@@ -0,0 +1,310 @@
+/// Compact binary trace format
+///
+/// `[trace] format = "binary"` streams spans to disk in this format instead
+/// of keeping Chrome trace JSON in memory, for sessions with millions of
+/// calls. reflex-trace-dump.exe converts a file to JSON or CSV offline, so
+/// this module is also compiled into that binary and depends on std only.
+///
+/// Layout: the magic `RXTR`, a version byte and the process id (varint),
+/// then records, each starting with a tag byte:
+/// - `TAG_STRING`: id, length, UTF-8 bytes - defines an interned string
+///   (span names, categories, field names), written before its first use
+/// - `TAG_EVENT`: name id, category id, start and duration in nanoseconds
+///   since the proxy was loaded, thread id, field count, then per field its
+///   name id, a value type byte and the value
+///
+/// Integers are LEB128 varints, signed ones zigzag-encoded first. A file
+/// cut short by a crash decodes up to the last complete record.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+pub const MAGIC: &[u8; 4] = b"RXTR";
+pub const VERSION: u8 = 1;
+
+const TAG_STRING: u8 = 1;
+const TAG_EVENT: u8 = 2;
+
+const VALUE_U64: u8 = 0;
+const VALUE_I64: u8 = 1;
+const VALUE_F64: u8 = 2;
+const VALUE_STR: u8 = 3;
+
+/// Value of a span field
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    Str(String),
+}
+
+/// A decoded span
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub name: String,
+    pub cat: String,
+    pub start_ns: u64,
+    pub dur_ns: u64,
+    pub tid: u32,
+    pub fields: Vec<(String, FieldValue)>,
+}
+
+/// Streams events to `W`, interning repeated strings
+pub struct Writer<W: Write> {
+    out: W,
+    strings: HashMap<String, u64>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Writer<W> {
+    /// Write the file header
+    pub fn new(mut out: W, pid: u32) -> io::Result<Self> {
+        let mut header = MAGIC.to_vec();
+        header.push(VERSION);
+        put_varint(&mut header, pid as u64);
+        out.write_all(&header)?;
+        Ok(Self {
+            out,
+            strings: HashMap::new(),
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Append one span; `fields` are (name, value) pairs
+    pub fn event(
+        &mut self,
+        name: &str,
+        cat: &str,
+        start_ns: u64,
+        dur_ns: u64,
+        tid: u32,
+        fields: &[(&str, FieldValue)],
+    ) -> io::Result<()> {
+        self.buffer.clear();
+        let name = self.intern(name);
+        let cat = self.intern(cat);
+        let keys: Vec<u64> = fields.iter().map(|(key, _)| self.intern(key)).collect();
+
+        self.buffer.push(TAG_EVENT);
+        put_varint(&mut self.buffer, name);
+        put_varint(&mut self.buffer, cat);
+        put_varint(&mut self.buffer, start_ns);
+        put_varint(&mut self.buffer, dur_ns);
+        put_varint(&mut self.buffer, tid as u64);
+        put_varint(&mut self.buffer, fields.len() as u64);
+        for (key, (_, value)) in keys.into_iter().zip(fields) {
+            put_varint(&mut self.buffer, key);
+            match value {
+                FieldValue::U64(v) => {
+                    self.buffer.push(VALUE_U64);
+                    put_varint(&mut self.buffer, *v);
+                }
+                FieldValue::I64(v) => {
+                    self.buffer.push(VALUE_I64);
+                    put_varint(&mut self.buffer, ((v << 1) ^ (v >> 63)) as u64);
+                }
+                FieldValue::F64(v) => {
+                    self.buffer.push(VALUE_F64);
+                    self.buffer.extend_from_slice(&v.to_le_bytes());
+                }
+                FieldValue::Str(v) => {
+                    self.buffer.push(VALUE_STR);
+                    put_varint(&mut self.buffer, v.len() as u64);
+                    self.buffer.extend_from_slice(v.as_bytes());
+                }
+            }
+        }
+        self.out.write_all(&self.buffer)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Id of `s`, defining it in `buffer` on first use
+    fn intern(&mut self, s: &str) -> u64 {
+        if let Some(&id) = self.strings.get(s) {
+            return id;
+        }
+        let id = self.strings.len() as u64;
+        self.strings.insert(s.to_string(), id);
+        self.buffer.push(TAG_STRING);
+        put_varint(&mut self.buffer, id);
+        put_varint(&mut self.buffer, s.len() as u64);
+        self.buffer.extend_from_slice(s.as_bytes());
+        id
+    }
+}
+
+/// Decode a whole trace file; returns the process id and the events
+pub fn decode(mut input: impl Read) -> Result<(u32, Vec<Event>), String> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data).map_err(|e| e.to_string())?;
+    if data.len() < 5 || &data[..4] != MAGIC {
+        return Err("not a reflex binary trace".to_string());
+    }
+    if data[4] != VERSION {
+        return Err(format!("unsupported trace version {}", data[4]));
+    }
+
+    let mut cursor = Cursor { data: &data, pos: 5 };
+    let pid = cursor.varint().ok_or("truncated header")? as u32;
+    let mut strings: Vec<String> = Vec::new();
+    let mut events = Vec::new();
+
+    while cursor.pos < data.len() {
+        match cursor.record(&mut strings) {
+            Some(Some(event)) => events.push(event),
+            Some(None) => {}
+            // Cut short (crash while writing): keep what was complete
+            None => break,
+        }
+    }
+
+    Ok((pid, events))
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let b = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&[u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            value |= ((b & 0x7F) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.varint()? as usize;
+        Some(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    /// One record: `Some(None)` for a string definition, `None` when the
+    /// data is truncated or malformed
+    fn record(&mut self, strings: &mut Vec<String>) -> Option<Option<Event>> {
+        match self.byte()? {
+            TAG_STRING => {
+                let id = self.varint()? as usize;
+                let s = self.string()?;
+                if id != strings.len() {
+                    return None;
+                }
+                strings.push(s);
+                Some(None)
+            }
+            TAG_EVENT => {
+                let lookup = |id: u64, strings: &[String]| strings.get(id as usize).cloned();
+                let name = lookup(self.varint()?, strings)?;
+                let cat = lookup(self.varint()?, strings)?;
+                let start_ns = self.varint()?;
+                let dur_ns = self.varint()?;
+                let tid = self.varint()? as u32;
+                let count = self.varint()?;
+
+                let mut fields = Vec::new();
+                for _ in 0..count {
+                    let key = lookup(self.varint()?, strings)?;
+                    let value = match self.byte()? {
+                        VALUE_U64 => FieldValue::U64(self.varint()?),
+                        VALUE_I64 => {
+                            let v = self.varint()?;
+                            FieldValue::I64((v >> 1) as i64 ^ -((v & 1) as i64))
+                        }
+                        VALUE_F64 => FieldValue::F64(f64::from_le_bytes(self.bytes(8)?.try_into().ok()?)),
+                        VALUE_STR => FieldValue::Str(self.string()?),
+                        _ => return None,
+                    };
+                    fields.push((key, value));
+                }
+
+                Some(Some(Event {
+                    name,
+                    cat,
+                    start_ns,
+                    dur_ns,
+                    tid,
+                    fields,
+                }))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn put_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut writer = Writer::new(Vec::new(), 4242).unwrap();
+        writer
+            .event("DeleteFileW", "detours", 1_500, 300, 7, &[("path", FieldValue::Str("C:\\x.cfg".into()))])
+            .unwrap();
+        writer
+            .event(
+                "DeleteFileW",
+                "detours",
+                u64::MAX / 2,
+                0,
+                7,
+                &[("result", FieldValue::I64(-5)), ("ratio", FieldValue::F64(0.25)), ("n", FieldValue::U64(300))],
+            )
+            .unwrap();
+        writer.out
+    }
+
+    #[test]
+    fn events_round_trip() {
+        let (pid, events) = decode(&sample()[..]).unwrap();
+        assert_eq!(pid, 4242);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].name, "DeleteFileW");
+        assert_eq!(events[0].cat, "detours");
+        assert_eq!((events[0].start_ns, events[0].dur_ns, events[0].tid), (1_500, 300, 7));
+        assert_eq!(events[0].fields, vec![("path".to_string(), FieldValue::Str("C:\\x.cfg".into()))]);
+        assert_eq!(events[1].start_ns, u64::MAX / 2);
+        assert_eq!(events[1].fields[0].1, FieldValue::I64(-5));
+        assert_eq!(events[1].fields[1].1, FieldValue::F64(0.25));
+        assert_eq!(events[1].fields[2].1, FieldValue::U64(300));
+    }
+
+    #[test]
+    fn strings_are_interned_and_truncation_is_tolerated() {
+        let data = sample();
+        // The second event reuses the name and category of the first
+        assert_eq!(data.windows(11).filter(|w| w == b"DeleteFileW").count(), 1);
+
+        let (_, events) = decode(&data[..data.len() - 3]).unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(decode(&b"JSON"[..]).is_err());
+    }
+}
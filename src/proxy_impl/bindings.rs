@@ -0,0 +1,187 @@
+/// Typed forwarders generated from an export description
+///
+/// build.rs reads `src/exports.toml` (or the file in `REFLEX_BINDINGS`,
+/// TOML or JSON) in the reflex_schema.toml format (schema.rs), plus an
+/// optional return type, and generates for every export:
+/// - `InitFn`, the function pointer type
+/// - `INIT`, a `Slot` the forwarder calls through
+/// - `Init`, the forwarder itself, exported by the proxy under that name
+///
+/// ```toml
+/// [Init]
+/// ret = "dword"                      # default: no return value ("void")
+/// params = [{ name = "flags", type = "dword" }, { name = "path", type = "wstr" }]
+/// ```
+///
+/// Supporting a newly reverse-engineered export is then an entry in the
+/// file. Hooking it needs no patching, the slot is redirected:
+///
+/// ```rust,ignore
+/// unsafe extern "system" fn hooked_init(flags: u32, path: *const u16) -> u32 {
+///     let original = bindings::INIT.original::<bindings::InitFn>().unwrap();
+///     original(flags | 1, path)
+/// }
+///
+/// bindings::INIT.set_hook(hooked_init as *const () as usize);
+/// ```
+///
+/// A forwarder whose export cannot be resolved returns zero.
+///
+/// With `jump = true` the forwarder is a stub instead, a single
+/// `jmp qword ptr [rip+...]` through the slot. Until the first call it
+/// jumps to `lazy_thunk`, which resolves the export (outside the loader
+/// lock), points the stub at it and continues there; from then on a call
+/// costs that one jump. Any signature is forwarded, so `params` and `ret`
+/// only shape `InitFn`. Setting a hook repoints the stub.
+
+use crate::proxy;
+use crate::proxy_impl::status::{self, ErrorKind};
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+/// Where a generated forwarder calls: a hook if one is set, else the
+/// original export (resolved on first call)
+#[repr(C)]
+pub struct Slot {
+    /// Where the stub of a `jump` export goes; read by the stub at offset 0
+    jump: AtomicPtr<()>,
+    /// Entry of the stub that resolves the export; `None` for a typed
+    /// forwarder
+    resolver: Option<unsafe extern "system" fn()>,
+    name: &'static str,
+    original: AtomicUsize,
+    hook: AtomicUsize,
+}
+
+impl Slot {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            jump: AtomicPtr::new(std::ptr::null_mut()),
+            resolver: None,
+            name,
+            original: AtomicUsize::new(0),
+            hook: AtomicUsize::new(0),
+        }
+    }
+
+    /// Slot of a `jump` stub, which enters `resolver` until the first call
+    pub const fn lazy(name: &'static str, resolver: unsafe extern "system" fn()) -> Self {
+        Self {
+            jump: AtomicPtr::new(resolver as *mut ()),
+            resolver: Some(resolver),
+            ..Self::new(name)
+        }
+    }
+
+    /// The export of reflex_original.dll, as `F` (its `...Fn` type)
+    pub unsafe fn original<F>(&self) -> Option<F> {
+        let mut address = self.original.load(Ordering::Acquire);
+        if address == 0 {
+            address = proxy::get_original_export::<usize>(self.name)?;
+            self.original.store(address, Ordering::Release);
+        }
+        Some(std::mem::transmute_copy(&address))
+    }
+
+    /// Send the forwarder's calls to `detour`
+    pub fn set_hook(&self, detour: usize) {
+        self.hook.store(detour, Ordering::Release);
+        if self.resolver.is_some() {
+            self.jump.store(detour as *mut (), Ordering::Release);
+        }
+        tracing::info!("[bindings] {} now calls 0x{:x}", self.name, detour);
+    }
+
+    /// Send the forwarder's calls to the original again
+    pub fn clear_hook(&self) {
+        self.hook.store(0, Ordering::Release);
+        // The next call resolves again, from the memoized original
+        if let Some(resolver) = self.resolver {
+            self.jump.store(resolver as *mut (), Ordering::Release);
+        }
+    }
+
+    /// Resolve the export again on the next call, e.g. because another
+    /// build of the original was loaded; a hook stays set
+    pub fn forget_original(&self) {
+        self.original.store(0, Ordering::Release);
+        if let (Some(resolver), 0) = (self.resolver, self.hook.load(Ordering::Acquire)) {
+            self.jump.store(resolver as *mut (), Ordering::Release);
+        }
+    }
+
+    /// Address the forwarder calls
+    pub unsafe fn target(&self) -> Option<usize> {
+        match self.hook.load(Ordering::Acquire) {
+            0 => {
+                let original = self.original::<usize>();
+                if original.is_none() {
+                    status::record_error(ErrorKind::Forward);
+                    tracing::error!("[bindings] {} is not exported by the original", self.name);
+                }
+                original
+            }
+            hook => Some(hook),
+        }
+    }
+}
+
+/// First call of a `jump` stub: point the stub at its target and return
+/// that, or 0 if the export cannot be resolved
+unsafe extern "system" fn resolve_jump(slot: &Slot) -> usize {
+    let target = match slot.target() {
+        Some(target) => target,
+        None => return 0,
+    };
+    // A hook set meanwhile has already repointed the stub
+    let resolver = slot.resolver.map_or(std::ptr::null_mut(), |resolver| resolver as *mut ());
+    let _ = slot
+        .jump
+        .compare_exchange(resolver, target as *mut (), Ordering::AcqRel, Ordering::Acquire);
+    target
+}
+
+// Entered with the Slot in r10 and the caller's arguments untouched.
+// Frame (0x88 bytes, keeps rsp 16-byte aligned for the call):
+//   0x00  shadow space
+//   0x20  rcx, rdx, r8, r9
+//   0x40  xmm0-xmm3
+#[unsafe(naked)]
+unsafe extern "system" fn lazy_thunk() {
+    std::arch::naked_asm!(
+        "sub rsp, 0x88",
+        "mov [rsp+0x20], rcx",
+        "mov [rsp+0x28], rdx",
+        "mov [rsp+0x30], r8",
+        "mov [rsp+0x38], r9",
+        "movdqu [rsp+0x40], xmm0",
+        "movdqu [rsp+0x50], xmm1",
+        "movdqu [rsp+0x60], xmm2",
+        "movdqu [rsp+0x70], xmm3",
+        "mov rcx, r10",
+        "call {resolve}",
+        "mov rcx, [rsp+0x20]",
+        "mov rdx, [rsp+0x28]",
+        "mov r8, [rsp+0x30]",
+        "mov r9, [rsp+0x38]",
+        "movdqu xmm0, [rsp+0x40]",
+        "movdqu xmm1, [rsp+0x50]",
+        "movdqu xmm2, [rsp+0x60]",
+        "movdqu xmm3, [rsp+0x70]",
+        "add rsp, 0x88",
+        // Not resolved: return 0 to the caller, like a typed forwarder
+        "test rax, rax",
+        "jz 2f",
+        "jmp rax",
+        "2:",
+        "ret",
+        resolve = sym resolve_jump,
+    );
+}
+
+/// `forget_original` of every slot; returns how many there are
+pub fn forget_originals() -> usize {
+    SLOTS.iter().for_each(|slot| slot.forget_original());
+    SLOTS.len()
+}
+
+include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
@@ -0,0 +1,341 @@
+/// Propagation of the proxy to child processes
+///
+/// Some games relaunch themselves or start a helper process that loads
+/// reflex.dll again, from another working directory where neither the
+/// config nor the output location apply. With `[children] enabled = true`
+/// CreateProcessW is hooked process-wide and every child is started with
+/// - `REFLEX_CONFIG`: the absolute path of the config this process read
+/// - `REFLEX_OUTPUT`: a subdirectory of this process's output location
+///   named after the child, e.g. `logs\launcher`, or `none` if this process
+///   writes no files
+///
+/// added to its environment, whether inherited or passed by the caller.
+/// With `install = true` the proxy is also installed into the child's
+/// directory, as the ReflexProxyInstall export would, when that directory
+/// has a reflex.dll which is not the proxy:
+///
+/// ```toml
+/// [children]
+/// enabled = true
+/// install = true   # default false
+/// ```
+///
+/// A child loading reflex.dll from anywhere but its own directory (e.g.
+/// System32) is not covered by `install`. Processes started through
+/// CreateProcessA or ShellExecuteExW reach CreateProcessInternalW without
+/// passing CreateProcessW and are not covered either.
+
+use std::path::{Path, PathBuf};
+
+/// The executable a CreateProcessW call starts: `application` if given,
+/// else the first token of the command line, quoted or not, with ".exe"
+/// added when it has no extension
+pub fn executable(application: Option<&str>, command_line: &str) -> PathBuf {
+    if let Some(application) = application.filter(|a| !a.is_empty()) {
+        return PathBuf::from(application);
+    }
+    let command_line = command_line.trim_start();
+    let first = match command_line.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next().unwrap_or_default(),
+        None => command_line.split([' ', '\t']).next().unwrap_or_default(),
+    };
+    let path = PathBuf::from(first);
+    if path.extension().is_none() && !first.is_empty() {
+        path.with_extension("exe")
+    } else {
+        path
+    }
+}
+
+/// Output location of a child: `parent`'s subdirectory named after the
+/// child's executable
+pub fn child_output(parent: &Path, executable: &Path) -> PathBuf {
+    let stem = executable.file_stem().map_or("child".into(), |stem| stem.to_string_lossy());
+    parent.join(&*stem)
+}
+
+/// A copy of the environment block `block` ("NAME=value" entries, each
+/// NUL-terminated, the last followed by another NUL) with `variables` set,
+/// replacing entries of the same name regardless of case
+pub fn with_variables(block: &[u16], variables: &[(&str, String)]) -> Vec<u16> {
+    let mut merged = Vec::with_capacity(block.len());
+    for entry in block.split(|&c| c == 0).take_while(|entry| !entry.is_empty()) {
+        let text = String::from_utf16_lossy(entry);
+        // Entries such as "=C:=C:\Games" start with '='
+        let end = text.char_indices().skip(1).find(|&(_, c)| c == '=').map_or(text.len(), |(i, _)| i);
+        let name = &text[..end];
+        if variables.iter().any(|(variable, _)| variable.eq_ignore_ascii_case(name)) {
+            continue;
+        }
+        merged.extend_from_slice(entry);
+        merged.push(0);
+    }
+    for (name, value) in variables {
+        merged.extend(format!("{}={}", name, value).encode_utf16());
+        merged.push(0);
+    }
+    if merged.is_empty() {
+        merged.push(0);
+    }
+    merged.push(0);
+    merged
+}
+
+#[cfg(windows)]
+pub use windows::initialize;
+
+#[cfg(windows)]
+mod windows {
+    use super::{child_output, executable, with_variables};
+    use crate::proxy;
+    use crate::proxy_impl::config::{self, ChildrenConfig, Output, CONFIG_ENV, OUTPUT_ENV};
+    use crate::proxy_impl::hooks;
+    use crate::proxy_impl::install::{self, PROXY_FILE_NAME};
+    use crate::proxy_impl::status::{self, ErrorKind};
+    use crate::proxy_impl::wide::from_wide_ptr;
+    use std::ffi::CString;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use winapi::shared::minwindef::{BOOL, DWORD, LPVOID};
+    use winapi::um::libloaderapi::{GetModuleHandleA, GetProcAddress};
+    use winapi::um::minwinbase::LPSECURITY_ATTRIBUTES;
+    use winapi::um::processenv::{FreeEnvironmentStringsW, GetEnvironmentStringsW};
+    use winapi::um::processthreadsapi::{LPPROCESS_INFORMATION, LPSTARTUPINFOW};
+    use winapi::um::stringapiset::MultiByteToWideChar;
+    use winapi::um::winbase::CREATE_UNICODE_ENVIRONMENT;
+    use winapi::um::winnls::CP_ACP;
+    use winapi::um::winnt::{LPCWSTR, LPWSTR};
+
+    type CreateProcessWFn = unsafe extern "system" fn(
+        LPCWSTR,
+        LPWSTR,
+        LPSECURITY_ATTRIBUTES,
+        LPSECURITY_ATTRIBUTES,
+        BOOL,
+        DWORD,
+        LPVOID,
+        LPCWSTR,
+        LPSTARTUPINFOW,
+        LPPROCESS_INFORMATION,
+    ) -> BOOL;
+
+    static ORIGINAL: AtomicUsize = AtomicUsize::new(0);
+    static INSTALL: AtomicBool = AtomicBool::new(false);
+
+    /// Hook CreateProcessW (kernelbase, else kernel32)
+    pub unsafe fn initialize(config: &ChildrenConfig) {
+        INSTALL.store(config.install, Ordering::SeqCst);
+        let name = CString::new("CreateProcessW").unwrap();
+        let target = [&b"kernelbase.dll\0"[..], &b"kernel32.dll\0"[..]].iter().find_map(|module| {
+            let handle = GetModuleHandleA(module.as_ptr() as *const i8);
+            if handle.is_null() {
+                return None;
+            }
+            match GetProcAddress(handle, name.as_ptr()) as usize {
+                0 => None,
+                address => Some(address),
+            }
+        });
+        let Some(target) = target else {
+            tracing::warn!("[children] CreateProcessW not found");
+            return;
+        };
+        let detour = create_process_w as *const () as usize;
+        match hooks::install_inline_hook_into("children!CreateProcessW", target, detour, &ORIGINAL) {
+            Ok(()) => tracing::info!("[children] Passing the proxy's config to child processes"),
+            Err(e) => {
+                status::record_error(ErrorKind::Detour);
+                tracing::warn!("[children] Failed to hook CreateProcessW: {}", e);
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    unsafe extern "system" fn create_process_w(
+        application: LPCWSTR,
+        command_line: LPWSTR,
+        process_attributes: LPSECURITY_ATTRIBUTES,
+        thread_attributes: LPSECURITY_ATTRIBUTES,
+        inherit_handles: BOOL,
+        flags: DWORD,
+        environment: LPVOID,
+        current_directory: LPCWSTR,
+        startup_info: LPSTARTUPINFOW,
+        process_information: LPPROCESS_INFORMATION,
+    ) -> BOOL {
+        let original: CreateProcessWFn = std::mem::transmute(ORIGINAL.load(Ordering::SeqCst));
+        let child = executable(from_wide_ptr(application).as_deref(), &from_wide_ptr(command_line).unwrap_or_default());
+        if INSTALL.load(Ordering::SeqCst) {
+            install_for(&child);
+        }
+
+        let variables = variables(&child);
+        let block = match environment_block(environment, flags) {
+            Some(block) => with_variables(&block, &variables),
+            None => {
+                tracing::warn!("[children] Cannot read the environment for {}", child.display());
+                return original(
+                    application,
+                    command_line,
+                    process_attributes,
+                    thread_attributes,
+                    inherit_handles,
+                    flags,
+                    environment,
+                    current_directory,
+                    startup_info,
+                    process_information,
+                );
+            }
+        };
+
+        let result = original(
+            application,
+            command_line,
+            process_attributes,
+            thread_attributes,
+            inherit_handles,
+            flags | CREATE_UNICODE_ENVIRONMENT,
+            block.as_ptr() as LPVOID,
+            current_directory,
+            startup_info,
+            process_information,
+        );
+        if result != 0 && !process_information.is_null() {
+            tracing::info!(
+                "[children] Started {} (pid {}) with {}",
+                child.display(),
+                (*process_information).dwProcessId,
+                variables
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+        }
+        result
+    }
+
+    /// REFLEX_CONFIG and REFLEX_OUTPUT for `child`
+    fn variables(child: &Path) -> Vec<(&'static str, String)> {
+        let current = std::env::current_dir().unwrap_or_default();
+        let mut variables = Vec::new();
+        let config_path = current.join(config::path());
+        if config_path.is_file() {
+            variables.push((CONFIG_ENV, config_path.display().to_string()));
+        }
+        let output = match config::output() {
+            Some(Output::Disabled) => "none".to_string(),
+            Some(Output::Directory(dir)) => child_output(&current.join(dir), child).display().to_string(),
+            None => child_output(&current, child).display().to_string(),
+        };
+        variables.push((OUTPUT_ENV, output));
+        variables
+    }
+
+    /// Install the proxy next to `child` unless it is already there
+    fn install_for(child: &Path) {
+        let directory = match child.parent() {
+            Some(parent) if parent.as_os_str().is_empty() => std::env::current_dir().unwrap_or_default(),
+            Some(parent) => std::env::current_dir().unwrap_or_default().join(parent),
+            None => return,
+        };
+        let own = unsafe { proxy::get_proxy_module_path() }.and_then(|path| path.parent().map(PathBuf::from));
+        if own.is_some_and(|own| same_directory(&own, &directory)) || !directory.join(PROXY_FILE_NAME).is_file() {
+            return;
+        }
+        match install::install_proxy(&directory) {
+            Ok(()) => tracing::info!("[children] Proxy installed into {}", directory.display()),
+            Err(e) => tracing::warn!("[children] Not installing into {}: {}", directory.display(), e),
+        }
+    }
+
+    fn same_directory(a: &Path, b: &Path) -> bool {
+        match (a.canonicalize(), b.canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// The block the child would get, as UTF-16 with the final NULs: the
+    /// caller's, or this process's when it passed none
+    unsafe fn environment_block(environment: LPVOID, flags: DWORD) -> Option<Vec<u16>> {
+        if environment.is_null() {
+            let strings = GetEnvironmentStringsW();
+            if strings.is_null() {
+                return None;
+            }
+            let block = wide_block(strings);
+            FreeEnvironmentStringsW(strings);
+            return Some(block);
+        }
+        if flags & CREATE_UNICODE_ENVIRONMENT != 0 {
+            return Some(wide_block(environment as *const u16));
+        }
+
+        // An ANSI block ends with two NULs too
+        let ansi = environment as *const u8;
+        let mut len = 0;
+        while *ansi.add(len) != 0 || *ansi.add(len + 1) != 0 {
+            len += 1;
+        }
+        len += 2;
+        let needed = MultiByteToWideChar(CP_ACP, 0, ansi as *const i8, len as i32, std::ptr::null_mut(), 0);
+        if needed <= 0 {
+            return None;
+        }
+        let mut block = vec![0u16; needed as usize];
+        MultiByteToWideChar(CP_ACP, 0, ansi as *const i8, len as i32, block.as_mut_ptr(), needed);
+        Some(block)
+    }
+
+    unsafe fn wide_block(block: *const u16) -> Vec<u16> {
+        let mut len = 0;
+        while *block.add(len) != 0 || *block.add(len + 1) != 0 {
+            len += 1;
+        }
+        std::slice::from_raw_parts(block, len + 2).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(entries: &[&str]) -> Vec<u16> {
+        let mut block: Vec<u16> = entries.iter().flat_map(|e| e.encode_utf16().chain(Some(0))).collect();
+        block.push(0);
+        block
+    }
+
+    #[test]
+    fn executables_come_from_the_command_line() {
+        assert_eq!(executable(Some(r"C:\Games\game.exe"), "game.exe -x"), PathBuf::from(r"C:\Games\game.exe"));
+        assert_eq!(
+            executable(None, r#""C:\Program Files\Game\launcher.exe" --relaunch"#),
+            PathBuf::from(r"C:\Program Files\Game\launcher.exe")
+        );
+        assert_eq!(executable(Some(""), "  helper -child 2"), PathBuf::from("helper.exe"));
+        assert_eq!(executable(None, "crash_reporter.bin"), PathBuf::from("crash_reporter.bin"));
+        assert_eq!(
+            child_output(Path::new("logs"), Path::new("bin/launcher.exe")),
+            Path::new("logs").join("launcher")
+        );
+    }
+
+    #[test]
+    fn variables_are_added_or_replaced() {
+        let variables = [("REFLEX_OUTPUT", "logs".to_string()), ("REFLEX_CONFIG", "C:/reflex_proxy.toml".to_string())];
+        assert_eq!(
+            with_variables(&block(&["=C:=C:\\Games", "Path=C:\\Windows", "reflex_output=old"]), &variables),
+            block(&[
+                "=C:=C:\\Games",
+                "Path=C:\\Windows",
+                "REFLEX_OUTPUT=logs",
+                "REFLEX_CONFIG=C:/reflex_proxy.toml"
+            ])
+        );
+        assert_eq!(with_variables(&block(&[]), &[]), [0, 0]);
+        assert_eq!(with_variables(&[0, 0], &variables[..1]), block(&["REFLEX_OUTPUT=logs"]));
+    }
+}
@@ -0,0 +1,1477 @@
+/// Runtime configuration loaded from reflex_proxy.toml
+///
+/// The file is read once from the working directory (next to reflex.log);
+/// a secondary copy of the proxy reads the primary's file (instance.rs).
+/// A missing file means defaults; a malformed file is logged and ignored so
+/// a typo never prevents the game from starting.
+///
+/// One file can serve several titles: a `[profile."<exe>"]` section whose
+/// name matches the host executable (case-insensitive) is merged over the
+/// rest of the file. Tables merge key by key; other values, including
+/// arrays such as `rules` and `patches`, replace the base value:
+///
+/// ```toml
+/// detours = true
+///
+/// [profile."game.exe".logging]
+/// json = true
+///
+/// [[profile."launcher.exe".rules]]
+/// api = "DeleteFileW"
+/// action = "allow"
+/// ```
+
+use crate::proxy_impl::config_check;
+use crate::proxy_impl::rules::{self, Rule};
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub const CONFIG_FILE_NAME: &str = "reflex_proxy.toml";
+/// Environment variable selecting where output files go (`Output`)
+pub const OUTPUT_ENV: &str = "REFLEX_OUTPUT";
+/// Environment variable naming the config file to read instead of the one
+/// in the working directory; set for child processes (children.rs)
+pub const CONFIG_ENV: &str = "REFLEX_CONFIG";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Install the API detours on the original DLL
+    pub detours: bool,
+    /// Show a MessageBox when the proxy cannot start (off for unattended use)
+    pub error_dialog: bool,
+    /// Start with every hook suspended: a pure forwarder until passthrough
+    /// is switched off (hooks.rs)
+    pub passthrough: bool,
+    /// Intercept rules evaluated by the detours
+    pub rules: Vec<Rule>,
+    /// Operation audit log
+    pub audit: AuditConfig,
+    /// Log sinks
+    pub logging: LoggingConfig,
+    /// Hotkey bindings
+    pub hotkeys: HotkeyConfig,
+    /// Chrome trace-event output
+    pub trace: TraceConfig,
+    /// Session and frame ids for joining with external ETW traces
+    /// (correlation.rs)
+    pub correlation: CorrelationConfig,
+    /// Byte patches applied to the original DLL after loading
+    pub patches: Vec<PatchConfig>,
+    /// ntdll syscall-stub hooks
+    pub syscalls: SyscallConfig,
+    /// Hide an attached debugger from the original DLL
+    pub anti_debug: AntiDebugConfig,
+    /// Present another GPU and driver to the original DLL (gpu_identity.rs)
+    pub gpu_identity: GpuIdentityConfig,
+    /// Perturb the original's QueryPerformanceCounter readings
+    pub qpc_jitter: QpcJitterConfig,
+    /// Compare system DLL code against disk after the original initializes
+    pub hook_scan: HookScanConfig,
+    /// Hide the proxy from module enumeration
+    pub stealth: StealthConfig,
+    /// Sampling profiler for the original DLL
+    pub profiler: ProfilerConfig,
+    /// Record calls into the original's exports for offline replay
+    pub recording: RecordingConfig,
+    /// Delay UI features until the game's window exists
+    pub window: WindowConfig,
+    /// Report calls into the original that never return
+    pub watchdog: WatchdogConfig,
+    /// Warn when reflex_original.dll is replaced on disk
+    pub original_watch: OriginalWatchConfig,
+    /// Report of the machine and process, written once at attach
+    pub environment: EnvironmentConfig,
+    /// Carry the proxy's config and output over to child processes
+    pub children: ChildrenConfig,
+    /// Log the processes the original launches
+    pub launches: LaunchesConfig,
+    /// Verify the original's Authenticode signature
+    pub signature: SignatureConfig,
+    /// Detours (by API name) that give up after a timeout
+    pub hook_timeouts: HashMap<String, HookTimeout>,
+    /// Hooks only handling calls from threads whose name matches one of
+    /// the globs (thread_scope.rs)
+    pub hook_threads: HashMap<String, Vec<String>>,
+    /// Inspect the original's TLS callbacks
+    pub tls: TlsConfig,
+    /// How reflex_original.dll is loaded
+    pub loader: LoaderConfig,
+    /// Serve spans live over WebSocket
+    pub stream: StreamConfig,
+    /// Control commands over local HTTP
+    pub http: HttpConfig,
+    /// Prometheus metrics file for the textfile collector
+    pub metrics: MetricsConfig,
+    /// Log first-chance exceptions raised inside the original
+    pub exceptions: ExceptionsConfig,
+    /// Log the callers of selected hooks
+    pub stacks: StackConfig,
+    /// Name stack frames with dbghelp
+    pub symbols: SymbolConfig,
+    /// Diff of the original's writable sections between two snapshots
+    pub memory_diff: MemoryDiffConfig,
+    /// Dump of the original's in-memory image
+    pub image_dump: ImageDumpConfig,
+    /// Signature scan cache
+    pub sigscan: SigScanConfig,
+    /// C++ classes found through RTTI
+    pub rtti: RttiConfig,
+    /// NVIDIA Reflex calls through NvAPI (nvapi.rs)
+    pub nvapi: NvapiConfig,
+    /// Where output files go
+    pub output: OutputConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub enabled: bool,
+    /// JSON Lines output file
+    pub path: String,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "reflex_audit.jsonl".to_string(),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            detours: false,
+            error_dialog: false,
+            passthrough: false,
+            rules: rules::default_rules(),
+            audit: AuditConfig::default(),
+            logging: LoggingConfig::default(),
+            hotkeys: HotkeyConfig::default(),
+            trace: TraceConfig::default(),
+            correlation: CorrelationConfig::default(),
+            patches: Vec::new(),
+            syscalls: SyscallConfig::default(),
+            anti_debug: AntiDebugConfig::default(),
+            gpu_identity: GpuIdentityConfig::default(),
+            qpc_jitter: QpcJitterConfig::default(),
+            hook_scan: HookScanConfig::default(),
+            stealth: StealthConfig::default(),
+            profiler: ProfilerConfig::default(),
+            recording: RecordingConfig::default(),
+            window: WindowConfig::default(),
+            watchdog: WatchdogConfig::default(),
+            original_watch: OriginalWatchConfig::default(),
+            environment: EnvironmentConfig::default(),
+            children: ChildrenConfig::default(),
+            launches: LaunchesConfig::default(),
+            signature: SignatureConfig::default(),
+            hook_timeouts: HashMap::new(),
+            hook_threads: HashMap::new(),
+            tls: TlsConfig::default(),
+            loader: LoaderConfig::default(),
+            stream: StreamConfig::default(),
+            http: HttpConfig::default(),
+            metrics: MetricsConfig::default(),
+            exceptions: ExceptionsConfig::default(),
+            stacks: StackConfig::default(),
+            symbols: SymbolConfig::default(),
+            memory_diff: MemoryDiffConfig::default(),
+            image_dump: ImageDumpConfig::default(),
+            sigscan: SigScanConfig::default(),
+            rtti: RttiConfig::default(),
+            nvapi: NvapiConfig::default(),
+            output: OutputConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Write events to `path` as they happen
+    pub file: bool,
+    pub path: String,
+    /// JSON Lines output with span fields
+    pub json: bool,
+    pub json_path: String,
+    /// Mirror events to OutputDebugStringW (DebugView, WinDbg)
+    pub debug_output: bool,
+    /// Emit events from the `ReflexProxy` ETW provider
+    pub etw: bool,
+    /// Report errors and crashes to the Windows Application event log
+    pub event_log: bool,
+    /// Events kept in memory for dump-on-demand (0 disables the ring buffer)
+    pub ring_buffer: usize,
+    /// File the ring buffer is dumped to
+    pub ring_dump_path: String,
+    /// Ship events over UDP to "host:port" ("" disables)
+    pub remote: String,
+    /// "syslog" (RFC 5424) or "json"
+    pub remote_format: String,
+    /// Interval between batches sent to `remote`
+    pub remote_batch_ms: u64,
+    /// Events queued for `remote` before new ones are dropped
+    pub remote_queue: usize,
+    /// Level of every category not in `categories`; RUST_LOG, when set,
+    /// replaces both
+    pub level: String,
+    /// Level per category: a module of proxy_impl (e.g. "detours") or a
+    /// full target (e.g. "reflex::proxy_impl::detours")
+    pub categories: HashMap<String, String>,
+    /// Personal data hidden from every sink and from traces (redact.rs)
+    pub redact: RedactConfig,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            file: true,
+            path: "reflex.log".to_string(),
+            json: false,
+            json_path: "reflex_log.jsonl".to_string(),
+            debug_output: false,
+            etw: false,
+            event_log: false,
+            ring_buffer: 0,
+            ring_dump_path: "reflex_ring.log".to_string(),
+            remote: String::new(),
+            remote_format: "syslog".to_string(),
+            remote_batch_ms: 200,
+            remote_queue: 4096,
+            level: "error".to_string(),
+            categories: HashMap::new(),
+            redact: RedactConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RedactConfig {
+    pub enabled: bool,
+    /// "mask" (`<user>`) or "hash" (`<user:3f2a91c0>`)
+    pub mode: String,
+    /// The profile directory and the account name
+    pub user: bool,
+    /// The machine name
+    pub computer: bool,
+    /// Anything shaped like a GUID
+    pub guids: bool,
+    /// More strings to hide, matched ignoring case
+    pub patterns: Vec<String>,
+}
+
+impl Default for RedactConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: "mask".to_string(),
+            user: true,
+            computer: true,
+            guids: true,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+/// Levels accepted for `level` and `categories`
+pub const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+/// RUST_LOG-style filter for a default level and per-category levels,
+/// e.g. `warn,reflex::proxy_impl::detours=debug`
+pub fn log_directives(level: &str, categories: &HashMap<String, String>) -> Result<String, String> {
+    let check = |level: &str| {
+        if LOG_LEVELS.contains(&level) {
+            Ok(level.to_string())
+        } else {
+            Err(format!("unknown log level '{}' (expected {})", level, LOG_LEVELS.join(", ")))
+        }
+    };
+
+    let mut directives = vec![check(level)?];
+    let mut categories: Vec<_> = categories.iter().collect();
+    categories.sort();
+    for (category, level) in categories {
+        let target = if category.contains("::") {
+            category.clone()
+        } else {
+            format!("reflex::proxy_impl::{}", category)
+        };
+        directives.push(format!("{}={}", target, check(level)?));
+    }
+    Ok(directives.join(","))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HotkeyConfig {
+    /// Dump the ring buffer, e.g. "Ctrl+F10"
+    pub dump_ring: Option<String>,
+    /// Snapshot the original's writable sections, or diff against the last
+    /// snapshot (snapshot.rs)
+    pub memory_snapshot: Option<String>,
+    /// Switch passthrough (every hook suspended) on and off
+    pub passthrough: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TraceConfig {
+    pub enabled: bool,
+    /// Output file
+    pub path: String,
+    /// "chrome" (JSON, written at the end) or "binary" (binary_trace.rs,
+    /// streamed while the game runs)
+    pub format: String,
+    /// Spans kept in memory for the Chrome format; later ones are dropped
+    pub max_events: usize,
+    /// Environment variables recorded in the trace metadata (globs)
+    pub environment: Vec<String>,
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "reflex_trace.json".to_string(),
+            format: "chrome".to_string(),
+            max_events: 1_000_000,
+            // Set by the Steam client and Proton, and compatibility
+            // settings; not Steam*, which includes SteamUser
+            environment: [
+                "SteamAppId",
+                "SteamGameId",
+                "SteamClientLaunch",
+                "SteamOverlayGameId",
+                "SteamEnv",
+                "STEAM_COMPAT_*",
+                "PROTON_*",
+                "DXVK_*",
+                "VKD3D_*",
+                "__COMPAT_LAYER",
+            ]
+            .iter()
+            .map(|v| v.to_string())
+            .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SyscallConfig {
+    pub enabled: bool,
+    /// Only report calls with reflex_original.dll on the call stack
+    pub only_original: bool,
+    /// Stubs to hook; empty means every supported stub
+    pub functions: Vec<String>,
+}
+
+impl Default for SyscallConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            only_original: true,
+            functions: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CorrelationConfig {
+    pub enabled: bool,
+    /// Number every Present ('nvapi' feature)
+    pub frames: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AntiDebugConfig {
+    pub enabled: bool,
+}
+
+/// Identity presented to the original DLL; unset keys keep the real value
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GpuIdentityConfig {
+    pub enabled: bool,
+    /// Adapter name (DXGI description, NvAPI full name, DriverDesc)
+    pub name: Option<String>,
+    pub vendor_id: Option<u32>,
+    pub device_id: Option<u32>,
+    pub subsys_id: Option<u32>,
+    pub revision: Option<u32>,
+    /// Dedicated video memory in MiB
+    pub video_memory_mb: Option<u64>,
+    /// NVIDIA driver version, e.g. "551.86"
+    pub driver_version: Option<String>,
+    /// `NV_GPU_ARCHITECTURE_ID`, e.g. 0x190 (Ada)
+    pub architecture: Option<u32>,
+}
+
+/// Perturbation profile of QueryPerformanceCounter readings (qpc_jitter.rs)
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct QpcJitterConfig {
+    pub enabled: bool,
+    /// Random offset of each reading, up to this either way
+    pub jitter_us: f64,
+    /// How fast the perturbed clock gains (or, negative, loses) time
+    pub drift_ppm: f64,
+    /// Chance of a reading stepping back behind the previous one
+    pub backstep_chance: f64,
+    /// How far behind the previous reading a backward step lands
+    pub backstep_us: f64,
+    /// Random seed; 0 picks one and logs it
+    pub seed: u64,
+    /// JSON Lines file of every injected delta; empty writes none
+    pub log_path: String,
+}
+
+impl Default for QpcJitterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            jitter_us: 0.0,
+            drift_ppm: 0.0,
+            backstep_chance: 0.0,
+            backstep_us: 0.0,
+            seed: 0,
+            log_path: "reflex_qpc.jsonl".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HookScanConfig {
+    pub enabled: bool,
+    /// Loaded modules to compare against their files on disk
+    pub modules: Vec<String>,
+    /// JSON report output file
+    pub path: String,
+}
+
+impl Default for HookScanConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            modules: ["ntdll.dll", "kernel32.dll", "kernelbase.dll", "user32.dll"]
+                .iter()
+                .map(|m| m.to_string())
+                .collect(),
+            path: "reflex_hookscan.json".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct StealthConfig {
+    pub enabled: bool,
+    /// Log module-enumeration API calls made while hidden
+    pub log_enumeration: bool,
+}
+
+impl Default for StealthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            log_enumeration: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ProfilerConfig {
+    pub enabled: bool,
+    /// Time between samples of all threads
+    pub interval_ms: u64,
+    /// JSON profile output file
+    pub path: String,
+    /// Collapsed stacks for flamegraph.pl / inferno; empty to disable
+    pub collapsed_path: String,
+    /// speedscope JSON; empty to disable
+    pub speedscope_path: String,
+}
+
+impl Default for ProfilerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_ms: 10,
+            path: "reflex_profile.json".to_string(),
+            collapsed_path: "reflex_profile.folded".to_string(),
+            speedscope_path: "reflex_profile.speedscope.json".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RecordingConfig {
+    pub enabled: bool,
+    /// JSON Lines output file, one line per completed call; empty only
+    /// counts calls (for the coverage report)
+    pub path: String,
+    /// Export coverage report written on detach; empty = none
+    pub coverage_path: String,
+    /// Exports to record; empty means every code export
+    pub exports: Vec<String>,
+    /// Stack argument slots recorded after rcx, rdx, r8, r9
+    pub stack_args: usize,
+    /// Bytes captured behind arguments that point to readable memory
+    pub capture_bytes: usize,
+    /// Parameter schema file (schema.rs) for decoding arguments; empty = none
+    pub schema: String,
+    /// Exports not forwarded to the original: calls are recorded and get
+    /// the value instead (dry run)
+    pub dry_run: HashMap<String, i64>,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "reflex_calls.jsonl".to_string(),
+            coverage_path: "reflex_coverage.json".to_string(),
+            exports: Vec::new(),
+            stack_args: 4,
+            capture_bytes: 64,
+            schema: String::new(),
+            dry_run: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    /// Start hotkeys (and other UI features) only once the window exists
+    pub wait: bool,
+    /// Window class to wait for; empty means any visible top-level window
+    pub class: String,
+    /// Start anyway after this many milliseconds (0 = wait forever)
+    pub timeout_ms: u64,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            wait: false,
+            class: String::new(),
+            timeout_ms: 60000,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct WatchdogConfig {
+    pub enabled: bool,
+    /// A call running longer than this is reported as hung
+    pub timeout_ms: u64,
+    /// Also write a minidump of the process when a hang is detected
+    pub dump: bool,
+    pub dump_path: String,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout_ms: 5000,
+            dump: false,
+            dump_path: "reflex_hang.dmp".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct OriginalWatchConfig {
+    pub enabled: bool,
+    /// How often the file is checked
+    pub interval_ms: u64,
+    /// Suspend inline hooks placed by offset rather than at an export until
+    /// the `reverify` control command (original_watch.rs)
+    pub suspend_offset_hooks: bool,
+}
+
+impl Default for OriginalWatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_ms: 2000,
+            suspend_offset_hooks: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentConfig {
+    pub enabled: bool,
+    /// JSON output file (environment.rs)
+    pub path: String,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: "reflex_environment.json".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ChildrenConfig {
+    pub enabled: bool,
+    /// Also install the proxy into the directory of a child whose reflex.dll
+    /// is not the proxy (install.rs)
+    pub install: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LaunchesConfig {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SignatureConfig {
+    pub enabled: bool,
+    /// Expected signer name, compared case-insensitively (signature.rs)
+    pub signer: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// List the TLS callbacks when the original is mapped
+    pub log: bool,
+    /// Log every invocation of the callbacks
+    pub hook: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LoaderConfig {
+    /// Map the original manually instead of with LoadLibrary
+    pub manual_map: bool,
+    /// Where the original's dependencies are searched: "dll_load_dir",
+    /// "application_dir", "user_dirs", "system32", "default_dirs"; empty
+    /// means the classic LoadLibrary search order
+    pub search: Vec<String>,
+    /// Another proxy of the same DLL to load instead of the original, e.g.
+    /// a mod's reflex.dll renamed; relative to the proxy's directory. It
+    /// loads the original in turn.
+    pub next_proxy: String,
+    /// Resolve every export of the original right after loading it rather
+    /// than on first use (export_cache.rs)
+    pub preload_exports: bool,
+    /// Attempts to load the original again, from a background thread, when
+    /// it is in use at attach (load_retry.rs); 0 fails at once
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each next one
+    pub retry_delay_ms: u64,
+    /// Without a renamed original next to the proxy, forward to the
+    /// genuine reflex.dll where it is installed (locate.rs)
+    pub fallback: bool,
+    /// Where to look for it, in order: "system32" or a registry value
+    /// naming a file or directory, `HKLM\<key>\<value>`
+    pub fallback_locations: Vec<String>,
+}
+
+impl Default for LoaderConfig {
+    fn default() -> Self {
+        Self {
+            manual_map: false,
+            search: vec!["dll_load_dir".to_string(), "system32".to_string()],
+            next_proxy: String::new(),
+            preload_exports: false,
+            retries: 5,
+            retry_delay_ms: 250,
+            fallback: false,
+            fallback_locations: vec![
+                // Display adapters; the user-mode driver lies in the driver store
+                r"HKLM\SYSTEM\CurrentControlSet\Control\Class\{4d36e968-e325-11ce-bfc1-08002be10318}\*\UserModeDriverName"
+                    .to_string(),
+                r"HKLM\SOFTWARE\NVIDIA Corporation\Global\GFExperience\FullPath".to_string(),
+                "system32".to_string(),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct StreamConfig {
+    pub enabled: bool,
+    /// Loopback port of the WebSocket endpoint
+    pub port: u16,
+    /// Spans waiting to be sent; more are dropped
+    pub queue: usize,
+    /// Web page origins allowed to connect, e.g. "http://localhost:8000"
+    /// (stream.rs); pages from anywhere else are refused
+    pub origins: Vec<String>,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 9230,
+            queue: 4096,
+            origins: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub enabled: bool,
+    /// Loopback port of the HTTP endpoint
+    pub port: u16,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 9231,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Write the metrics to `path` periodically and on detach
+    pub enabled: bool,
+    pub path: String,
+    pub interval_ms: u64,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "reflex_metrics.prom".to_string(),
+            interval_ms: 15_000,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ExceptionsConfig {
+    pub enabled: bool,
+    /// Occurrences logged per exception code and address; later ones are
+    /// only counted
+    pub max_per_site: u32,
+}
+
+impl Default for ExceptionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_per_site: 5,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct StackConfig {
+    /// Detours and syscall hooks (by API name) whose call stack is logged;
+    /// "*" selects every hook
+    pub hooks: Vec<String>,
+    /// Frames logged per call
+    pub depth: u32,
+}
+
+impl Default for StackConfig {
+    fn default() -> Self {
+        Self {
+            hooks: Vec::new(),
+            depth: 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SymbolConfig {
+    pub enabled: bool,
+    /// dbghelp search path for PDBs, e.g. "srv*C:\\symbols*<server>"; empty
+    /// means _NT_SYMBOL_PATH and the modules' directories
+    pub search_path: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MemoryDiffConfig {
+    /// Report of the changed ranges
+    pub path: String,
+    /// Bytes dumped per changed range
+    pub max_dump: usize,
+}
+
+impl Default for MemoryDiffConfig {
+    fn default() -> Self {
+        Self {
+            path: "reflex_memdiff.txt".to_string(),
+            max_dump: 256,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ImageDumpConfig {
+    /// Written by the `imagedump` command
+    pub path: String,
+}
+
+impl Default for ImageDumpConfig {
+    fn default() -> Self {
+        Self {
+            path: "reflex_original.dump.dll".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SigScanConfig {
+    /// Matches of the `[[patches]]` signatures in the original, reused while
+    /// its hash is unchanged; "" to scan at every launch
+    pub cache_path: String,
+}
+
+impl Default for SigScanConfig {
+    fn default() -> Self {
+        Self {
+            cache_path: "reflex_sigcache.json".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RttiConfig {
+    /// Scan and log the classes after the original's DllMain
+    pub enabled: bool,
+    /// JSON list of classes and vtables
+    pub path: String,
+}
+
+impl Default for RttiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "reflex_rtti.json".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct NvapiConfig {
+    /// Hook nvapi_QueryInterface and wrap the Reflex entry points
+    pub enabled: bool,
+    /// Sleep telemetry summary written on detach (sleep_stats.rs); empty
+    /// only logs it
+    pub summary_path: String,
+    /// Derive latency markers from sleeps, presents and input reads for a
+    /// game that sends none (markers.rs)
+    pub synthesize_markers: bool,
+    /// Answer Reflex calls as if the GPU supported Reflex, without the
+    /// driver; sleeps go to the proxy's frame limiter (limiter.rs)
+    pub spoof_support: bool,
+    /// Replaces parameters of the game's NvAPI_D3D_SetSleepMode calls
+    pub sleep_mode: SleepModeConfig,
+    /// Frame pacing analysis of presents, sleeps and markers (pacing.rs)
+    pub pacing: PacingConfig,
+}
+
+impl Default for NvapiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            summary_path: "reflex_sleep.json".to_string(),
+            synthesize_markers: false,
+            spoof_support: false,
+            sleep_mode: SleepModeConfig::default(),
+            pacing: PacingConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct PacingConfig {
+    pub enabled: bool,
+    /// Frames further than this from the target frame time miss pacing
+    /// and are logged
+    pub threshold_ms: f64,
+    /// Summary written on detach; empty only logs it
+    pub summary_path: String,
+    /// JSON Lines export of every frame's latency breakdown
+    /// (frame_latency.rs); empty writes none
+    pub frames_path: String,
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_ms: 2.0,
+            summary_path: "reflex_pacing.json".to_string(),
+            frames_path: String::new(),
+        }
+    }
+}
+
+/// Sleep mode parameters forced on the game; unset ones keep its value
+///
+/// ```toml
+/// [nvapi.sleep_mode]
+/// low_latency = true
+/// boost = false
+/// clamp_interval_us = [4167, 16667]   # 60 to 240 fps
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SleepModeConfig {
+    pub low_latency: Option<bool>,
+    pub boost: Option<bool>,
+    pub use_markers: Option<bool>,
+    /// Minimum time between frames (0 = no limit), replacing the game's
+    pub minimum_interval_us: Option<u32>,
+    /// [low, high] the minimum interval is clamped to, after
+    /// `minimum_interval_us`; a game without a limit (0) gets `low`
+    pub clamp_interval_us: Option<[u32; 2]>,
+}
+
+/// Directory for every relative output path (logs, traces, dumps, ...)
+///
+/// ```toml
+/// [output]
+/// directory = "logs"   # default: the working directory
+/// session = true       # logs/2024-05-01_2031/reflex.log
+/// ```
+///
+/// `REFLEX_OUTPUT` replaces `directory`; `session` applies to both.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    pub directory: String,
+    /// A subdirectory per session, named after its start time (UTC)
+    pub session: bool,
+}
+
+impl OutputConfig {
+    /// The directory for a session started at `started`, if the files do
+    /// not go to the working directory
+    pub fn resolve(&self, started: SystemTime) -> Option<PathBuf> {
+        if self.directory.is_empty() && !self.session {
+            return None;
+        }
+        let directory = PathBuf::from(&self.directory);
+        Some(match self.session {
+            true => directory.join(session_name(started)),
+            false => directory,
+        })
+    }
+}
+
+/// "2024-05-01_2031" for a session started at 2024-05-01T20:31:xxZ
+pub fn session_name(started: SystemTime) -> String {
+    let time = humantime::format_rfc3339_seconds(started).to_string();
+    format!("{}_{}{}", &time[..10], &time[11..13], &time[14..16])
+}
+
+/// Zero-write mode for read-only game directories, from `REFLEX_OUTPUT`
+///
+/// - `appdata`: %LOCALAPPDATA%\reflex-proxy\<game>
+/// - `none`: no files are written at all
+/// - any other value: that directory
+///
+/// Relative output paths of the config are moved into the directory;
+/// absolute ones are kept.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    Directory(PathBuf),
+    Disabled,
+}
+
+impl Output {
+    /// Mode for the value of `REFLEX_OUTPUT`; `exe` names the game's folder
+    pub fn parse(value: &str, local_app_data: Option<&str>, exe: &str) -> Result<Self, String> {
+        match value.trim() {
+            "" => Err(format!("{} is empty", OUTPUT_ENV)),
+            "none" => Ok(Output::Disabled),
+            "appdata" => {
+                let base = local_app_data.ok_or("LOCALAPPDATA is not set")?;
+                let game = Path::new(exe).file_stem().map_or("unknown".into(), |stem| stem.to_string_lossy());
+                Ok(Output::Directory(Path::new(base).join("reflex-proxy").join(&*game)))
+            }
+            dir => Ok(Output::Directory(PathBuf::from(dir))),
+        }
+    }
+}
+
+impl Config {
+    /// Apply zero-write mode
+    pub fn redirect_output(&mut self, output: &Output) {
+        match output {
+            Output::Directory(dir) => {
+                for path in self.output_paths() {
+                    if !path.is_empty() && Path::new(path.as_str()).is_relative() {
+                        *path = dir.join(path.as_str()).to_string_lossy().into_owned();
+                    }
+                }
+            }
+            Output::Disabled => {
+                self.logging.file = false;
+                self.logging.json = false;
+                self.audit.enabled = false;
+                self.trace.enabled = false;
+                self.profiler.enabled = false;
+                self.watchdog.dump = false;
+                self.metrics.enabled = false;
+                // The rest (ring buffer, hook scan, call counting, control
+                // commands) still runs and skips or refuses its file
+                for path in self.output_paths() {
+                    path.clear();
+                }
+            }
+        }
+    }
+
+    /// Every file the proxy writes during a session
+    fn output_paths(&mut self) -> [&mut String; 22] {
+        [
+            &mut self.logging.path,
+            &mut self.logging.json_path,
+            &mut self.logging.ring_dump_path,
+            &mut self.audit.path,
+            &mut self.trace.path,
+            &mut self.hook_scan.path,
+            &mut self.profiler.path,
+            &mut self.profiler.collapsed_path,
+            &mut self.profiler.speedscope_path,
+            &mut self.recording.path,
+            &mut self.recording.coverage_path,
+            &mut self.watchdog.dump_path,
+            &mut self.metrics.path,
+            &mut self.memory_diff.path,
+            &mut self.image_dump.path,
+            &mut self.sigscan.cache_path,
+            &mut self.rtti.path,
+            &mut self.qpc_jitter.log_path,
+            &mut self.nvapi.summary_path,
+            &mut self.nvapi.pacing.summary_path,
+            &mut self.nvapi.pacing.frames_path,
+            &mut self.environment.path,
+        ]
+    }
+}
+
+/// Timeout of one detour (hook_timeout.rs)
+#[derive(Debug, Clone, Deserialize)]
+pub struct HookTimeout {
+    pub timeout_ms: u64,
+    /// Returned to the caller when the detour does not finish in time
+    #[serde(default)]
+    pub fallback: i64,
+}
+
+/// A byte patch for reflex_original.dll
+///
+/// ```toml
+/// [[patches]]
+/// name = "skip-intro"
+/// signature = "74 05 E8 ?? ?? ?? ??"   # or: offset = 0x1234 (RVA)
+/// original = "74 05"                   # optional, verified before patching
+/// replacement = "EB 05"
+/// module = "d3d11.dll"                 # optional, patch another module once it loads
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct PatchConfig {
+    pub name: String,
+    /// Module to patch instead of reflex_original.dll; applied when it loads
+    pub module: Option<String>,
+    /// RVA in the original DLL
+    pub offset: Option<usize>,
+    /// Byte signature with ?? wildcards; must match exactly once
+    pub signature: Option<String>,
+    /// Added to the signature match address
+    #[serde(default)]
+    pub signature_offset: isize,
+    /// Expected bytes at the patch location
+    pub original: Option<String>,
+    pub replacement: String,
+}
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+/// Config file read instead of the one in the working directory
+static PATH: OnceCell<PathBuf> = OnceCell::new();
+/// Where output files went, if redirected
+static OUTPUT: OnceCell<Output> = OnceCell::new();
+
+/// Get the configuration, loading it on first use
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(load)
+}
+
+/// Read the config from `path`; only has an effect before the first `get`
+pub fn set_path(path: PathBuf) {
+    let _ = PATH.set(path);
+}
+
+/// The config file read by `get`
+pub fn path() -> &'static Path {
+    PATH.get().map_or(Path::new(CONFIG_FILE_NAME), PathBuf::as_path)
+}
+
+/// The redirection `get` applied to the output paths; None when they are
+/// relative to the working directory
+pub fn output() -> Option<&'static Output> {
+    OUTPUT.get()
+}
+
+fn load() -> Config {
+    if let Some(path) = std::env::var_os(CONFIG_ENV) {
+        set_path(PathBuf::from(path));
+    }
+    // GetModuleFileNameW(NULL): the game, not this DLL
+    let exe = std::env::current_exe()
+        .ok()
+        .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+
+    let mut config = read(&exe);
+    let started = SystemTime::now();
+    let output = match std::env::var(OUTPUT_ENV) {
+        Ok(value) => {
+            let local_app_data = std::env::var("LOCALAPPDATA").ok();
+            match Output::parse(&value, local_app_data.as_deref(), &exe) {
+                Ok(Output::Directory(dir)) if config.output.session => {
+                    Some(Output::Directory(dir.join(session_name(started))))
+                }
+                Ok(output) => Some(output),
+                Err(e) => {
+                    tracing::error!("[config] Ignoring {}: {}", OUTPUT_ENV, e);
+                    config.output.resolve(started).map(Output::Directory)
+                }
+            }
+        }
+        Err(_) => config.output.resolve(started).map(Output::Directory),
+    };
+    let output = match output {
+        Some(Output::Directory(dir)) => match fs::create_dir_all(&dir) {
+            Ok(()) => {
+                tracing::info!("[config] Writing output files to {}", dir.display());
+                Output::Directory(dir)
+            }
+            Err(e) => {
+                tracing::error!("[config] Failed to create {}, writing no files: {}", dir.display(), e);
+                Output::Disabled
+            }
+        },
+        Some(Output::Disabled) => {
+            tracing::info!("[config] {}=none, writing no files", OUTPUT_ENV);
+            Output::Disabled
+        }
+        None => return config,
+    };
+    config.redirect_output(&output);
+    let _ = OUTPUT.set(output);
+    config
+}
+
+fn read(exe: &str) -> Config {
+    let text = match fs::read_to_string(path()) {
+        Ok(text) => text,
+        Err(_) => {
+            tracing::info!("[config] No {} found, using defaults", path().display());
+            return Config::default();
+        }
+    };
+
+    // Typos, unknown keys and invalid values, with their lines
+    for diagnostic in config_check::check(&text, exe) {
+        tracing::warn!("[config] {}", diagnostic.in_file(path()));
+    }
+
+    match parse_for(&text, exe) {
+        Ok((config, profile)) => {
+            tracing::info!(
+                "[config] Loaded {} ({} rule(s))",
+                path().display(),
+                config.rules.len()
+            );
+            if let Some(profile) = profile {
+                tracing::info!("[config] Applied profile \"{}\"", profile);
+            }
+            config
+        }
+        Err(e) => {
+            tracing::error!("[config] Failed to parse {}, using defaults: {}", path().display(), e);
+            Config::default()
+        }
+    }
+}
+
+/// Parse the contents of a config file for the host executable `exe`
+/// (file name only)
+///
+/// Returns the config and the name of the profile merged into it, if any.
+/// Keys no field takes are ignored; `config_check::check` reports them.
+pub fn parse_for(text: &str, exe: &str) -> Result<(Config, Option<String>), String> {
+    let (table, name) = merged_table(text, exe)?;
+    let (config, _) = config_check::deserialize::<Config>(table).map_err(|(path, message)| match path.is_empty() {
+        true => message,
+        false => format!("`{}`: {}", config_check::dotted(&path), message),
+    })?;
+    Ok((config, name))
+}
+
+/// The file's table with the profile for `exe` merged in, and the name of
+/// that profile
+pub fn merged_table(text: &str, exe: &str) -> Result<(toml::Table, Option<String>), String> {
+    let mut table: toml::Table = toml::from_str(text).map_err(|e| e.to_string())?;
+
+    let profile = match table.remove("profile") {
+        Some(toml::Value::Table(profiles)) => profiles.into_iter().find(|(name, _)| name.eq_ignore_ascii_case(exe)),
+        Some(_) => return Err("profile must be a table of [profile.\"<exe>\"] sections".to_string()),
+        None => None,
+    };
+    let name = match profile {
+        Some((name, toml::Value::Table(overlay))) => {
+            merge(&mut table, overlay);
+            Some(name)
+        }
+        Some((name, _)) => return Err(format!("profile.\"{}\" must be a table", name)),
+        None => None,
+    };
+    Ok((table, name))
+}
+
+/// Merge `overlay` into `base`: tables recursively, other values replace
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy_impl::rules::Action;
+
+    /// Parse without a matching profile
+    fn parse(text: &str) -> Result<Config, String> {
+        parse_for(text, "").map(|(config, _)| config)
+    }
+
+    #[test]
+    fn empty_file_gives_defaults() {
+        let config = parse("").unwrap();
+        assert!(!config.detours);
+        assert_eq!(config.rules.len(), rules::default_rules().len());
+        assert_eq!(config.logging.path, "reflex.log");
+        assert!(config.patches.is_empty());
+    }
+
+    #[test]
+    fn sections_keep_defaults_for_missing_keys() {
+        let config = parse(
+            r#"
+            [profiler]
+            enabled = true
+
+            [recording]
+            exports = ["Init"]
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.profiler.enabled);
+        assert_eq!(config.profiler.interval_ms, 10);
+        assert_eq!(config.recording.exports, ["Init"]);
+        assert_eq!(config.recording.stack_args, 4);
+    }
+
+    #[test]
+    fn rules_replace_the_defaults() {
+        let config = parse(
+            r#"
+            [[rules]]
+            api = "DeleteFileW"
+            path = "*.sav"
+            action = "block"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.rules.len(), 1);
+        assert_eq!(config.rules[0].api, "DeleteFileW");
+        assert_eq!(config.rules[0].path.as_deref(), Some("*.sav"));
+        assert_eq!(config.rules[0].action, Action::Block);
+    }
+
+    #[test]
+    fn patches_parse() {
+        let config = parse(
+            r#"
+            [[patches]]
+            name = "skip-intro"
+            signature = "74 05 E8 ?? ?? ?? ??"
+            replacement = "EB 05"
+            "#,
+        )
+        .unwrap();
+
+        let patch = &config.patches[0];
+        assert_eq!(patch.name, "skip-intro");
+        assert_eq!(patch.offset, None);
+        assert_eq!(patch.module, None);
+        assert_eq!(patch.signature_offset, 0);
+        assert_eq!(patch.replacement, "EB 05");
+    }
+
+    #[test]
+    fn hook_timeouts_parse() {
+        let config = parse(
+            r#"
+            [hook_timeouts]
+            DeleteFileW = { timeout_ms = 2000 }
+            RegQueryValueExW = { timeout_ms = 500, fallback = 2 }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.hook_timeouts["DeleteFileW"].timeout_ms, 2000);
+        assert_eq!(config.hook_timeouts["DeleteFileW"].fallback, 0);
+        assert_eq!(config.hook_timeouts["RegQueryValueExW"].fallback, 2);
+    }
+
+    #[test]
+    fn matching_profile_is_merged() {
+        let text = r#"
+            detours = true
+
+            [profiler]
+            enabled = true
+            interval_ms = 5
+
+            [profile."Game.exe".profiler]
+            interval_ms = 20
+
+            [[profile."Game.exe".rules]]
+            api = "DeleteFileW"
+            action = "block"
+
+            [profile."launcher.exe"]
+            detours = false
+        "#;
+
+        let (config, profile) = parse_for(text, "game.exe").unwrap();
+        assert_eq!(profile.as_deref(), Some("Game.exe"));
+        assert!(config.detours);
+        assert!(config.profiler.enabled);
+        assert_eq!(config.profiler.interval_ms, 20);
+        assert_eq!(config.rules.len(), 1);
+
+        let (config, profile) = parse_for(text, "other.exe").unwrap();
+        assert_eq!(profile, None);
+        assert_eq!(config.profiler.interval_ms, 5);
+        assert_eq!(config.rules.len(), rules::default_rules().len());
+
+        assert!(!parse_for(text, "LAUNCHER.EXE").unwrap().0.detours);
+        assert!(parse_for("profile = 1", "game.exe").is_err());
+    }
+
+    #[test]
+    fn output_is_redirected_or_disabled() {
+        assert_eq!(
+            Output::parse("appdata", Some("/users/me/AppData/Local"), "Game.exe").unwrap(),
+            Output::Directory(PathBuf::from("/users/me/AppData/Local/reflex-proxy/Game"))
+        );
+        assert!(Output::parse("appdata", None, "game.exe").is_err());
+
+        let mut config = parse("[trace]\npath = \"/traces/reflex_trace.json\"").unwrap();
+        config.redirect_output(&Output::parse("/tmp/out", None, "game.exe").unwrap());
+        assert_eq!(Path::new(&config.logging.path), Path::new("/tmp/out/reflex.log"));
+        assert_eq!(Path::new(&config.watchdog.dump_path), Path::new("/tmp/out/reflex_hang.dmp"));
+        assert_eq!(config.trace.path, "/traces/reflex_trace.json");
+
+        let mut config = parse("[logging]\njson = true\n[recording]\nenabled = true").unwrap();
+        config.redirect_output(&Output::Disabled);
+        assert!(!config.logging.file && !config.logging.json);
+        assert!(config.recording.enabled);
+        assert!(config.recording.path.is_empty() && config.recording.coverage_path.is_empty());
+    }
+
+    #[test]
+    fn sessions_get_their_own_directory() {
+        let started = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_714_595_460);
+        assert_eq!(session_name(started), "2024-05-01_2031");
+
+        assert_eq!(parse("").unwrap().output.resolve(started), None);
+        let config = parse("[output]\ndirectory = \"logs\"").unwrap();
+        assert_eq!(config.output.resolve(started), Some(PathBuf::from("logs")));
+        let config = parse("[output]\ndirectory = \"logs\"\nsession = true").unwrap();
+        assert_eq!(config.output.resolve(started), Some(Path::new("logs").join("2024-05-01_2031")));
+        let config = parse("[output]\nsession = true").unwrap();
+        assert_eq!(config.output.resolve(started), Some(PathBuf::from("2024-05-01_2031")));
+    }
+
+    #[test]
+    fn log_categories_become_directives() {
+        let config = parse(
+            r#"
+            [logging]
+            level = "warn"
+            categories = { proxy = "info", detours = "debug", "reflex::proxy_impl::http" = "off" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            log_directives(&config.logging.level, &config.logging.categories).unwrap(),
+            "warn,reflex::proxy_impl::detours=debug,reflex::proxy_impl::proxy=info,reflex::proxy_impl::http=off"
+        );
+        assert_eq!(log_directives("error", &HashMap::new()).unwrap(), "error");
+        assert!(log_directives("loud", &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn invalid_values_are_errors() {
+        assert!(parse("detours = \"yes\"").is_err());
+        assert!(parse("[[rules]]\napi = \"DeleteFileW\"\naction = \"explode\"").is_err());
+        assert!(parse("[[patches]]\nname = \"no-replacement\"").is_err());
+    }
+}
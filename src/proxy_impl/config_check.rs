@@ -0,0 +1,562 @@
+/// Strict checking of reflex_proxy.toml
+///
+/// serde ignores keys no field takes, so a typo (`[loggin]`, `jsn = true`)
+/// silently leaves the default in place. The config is deserialized
+/// through a wrapper that records every key a struct does not declare and
+/// where a type error occurred; each finding is reported with its line:
+///
+/// ```text
+/// reflex_proxy.toml:12: unknown key `logging.jsn` (did you mean `json`?)
+/// reflex_proxy.toml:20: `loader.retries`: invalid type: string "5", expected u32
+/// reflex_proxy.toml:31: `patches[0]`: exactly one of `offset` and `signature` is required
+/// ```
+///
+/// The proxy logs the findings at load (config.rs); `reflex-proxyctl
+/// --validate` prints them without starting a game. Unknown keys and
+/// invalid values are only reported, the rest of the file applies; a type
+/// error still means defaults.
+///
+/// Independent of the host platform.
+
+use crate::proxy_impl::config::{self, Config, LOG_LEVELS};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use std::cell::RefCell;
+use std::fmt;
+use std::path::Path;
+
+/// A problem found in a config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// From 1; None if the key could not be found in the text
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// `reflex_proxy.toml:12: unknown key ...`
+    pub fn in_file(&self, file: &Path) -> String {
+        match self.line {
+            Some(line) => format!("{}:{}: {}", file.display(), line, self.message),
+            None => format!("{}: {}", file.display(), self.message),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}: {}", line, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Check the contents of a config file as it applies to the host
+/// executable `exe` (file name only)
+pub fn check(text: &str, exe: &str) -> Vec<Diagnostic> {
+    if let Err(e) = toml::from_str::<toml::Table>(text) {
+        let line = e.span().map(|span| line_at(text, span.start));
+        return vec![Diagnostic {
+            line,
+            message: e.message().trim_end().to_string(),
+        }];
+    }
+    let (table, profile) = match config::merged_table(text, exe) {
+        Ok(merged) => merged,
+        Err(message) => return vec![Diagnostic { line: None, message }],
+    };
+    // A key the profile sets is reported in the profile
+    let locate = |path: &[String]| {
+        let in_profile = profile.as_ref().and_then(|profile| {
+            let mut full = vec!["profile".to_string(), profile.clone()];
+            full.extend_from_slice(path);
+            line_of(text, &full, true)
+        });
+        in_profile.or_else(|| line_of(text, path, false))
+    };
+
+    let mut diagnostics = Vec::new();
+    match deserialize::<Config>(table) {
+        Ok((config, unknown)) => {
+            for key in unknown {
+                diagnostics.push(Diagnostic {
+                    line: locate(&key.path),
+                    message: key.to_string(),
+                });
+            }
+            for (path, message) in invalid_values(&config) {
+                diagnostics.push(Diagnostic {
+                    line: locate(&path),
+                    message: format!("`{}`: {}", dotted(&path), message),
+                });
+            }
+        }
+        Err((path, message)) => diagnostics.push(Diagnostic {
+            line: locate(&path),
+            message: if path.is_empty() { message } else { format!("`{}`: {}", dotted(&path), message) },
+        }),
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line.unwrap_or(usize::MAX));
+    diagnostics
+}
+
+/// A key no field takes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKey {
+    pub path: Vec<String>,
+    /// The declared field closest to it, if any is close
+    pub suggestion: Option<&'static str>,
+}
+
+impl fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown key `{}`", dotted(&self.path))?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, " (did you mean `{}`?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Deserialize `table` as `T`, also returning the keys no field took; an
+/// error comes with the path of the value it is about
+pub fn deserialize<T: DeserializeOwned>(table: toml::Table) -> Result<(T, Vec<UnknownKey>), (Vec<String>, String)> {
+    let state = State::default();
+    let checked = Checked {
+        value: toml::Value::Table(table),
+        path: Vec::new(),
+        state: &state,
+    };
+    match T::deserialize(checked) {
+        Ok(value) => Ok((value, state.unknown.into_inner())),
+        Err(e) => Err((state.failed_at.into_inner().unwrap_or_default(), e.message().to_string())),
+    }
+}
+
+/// Values of the right type that the proxy would still reject
+fn invalid_values(config: &Config) -> Vec<(Vec<String>, String)> {
+    let path = |keys: &[&str]| keys.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+    let level = |level: &str| {
+        (!LOG_LEVELS.contains(&level)).then(|| format!("unknown log level '{}' (expected {})", level, LOG_LEVELS.join(", ")))
+    };
+    let one_of = |value: &str, allowed: &[&str]| {
+        (!allowed.contains(&value)).then(|| format!("'{}' is not one of \"{}\"", value, allowed.join("\", \"")))
+    };
+
+    let mut invalid = Vec::new();
+    let logging = &config.logging;
+    if let Some(message) = level(&logging.level) {
+        invalid.push((path(&["logging", "level"]), message));
+    }
+    let mut categories: Vec<_> = logging.categories.iter().collect();
+    categories.sort();
+    for (category, value) in categories {
+        if let Some(message) = level(value) {
+            invalid.push((path(&["logging", "categories", category]), message));
+        }
+    }
+    if let Some(message) = one_of(&logging.remote_format, &["syslog", "json"]) {
+        invalid.push((path(&["logging", "remote_format"]), message));
+    }
+    if let Some(message) = one_of(&logging.redact.mode, &["mask", "hash"]) {
+        invalid.push((path(&["logging", "redact", "mode"]), message));
+    }
+
+    for (index, patch) in config.patches.iter().enumerate() {
+        let at = path(&["patches", &index.to_string()]);
+        if patch.offset.is_some() == patch.signature.is_some() {
+            invalid.push((at.clone(), "exactly one of `offset` and `signature` is required".to_string()));
+        }
+        let bytes = [("replacement", Some(&patch.replacement)), ("original", patch.original.as_ref())];
+        for (key, value) in bytes {
+            if let Some(value) = value.filter(|value| !is_hex_bytes(value)) {
+                let mut at = at.clone();
+                at.push(key.to_string());
+                invalid.push((at, format!("'{}' is not hex bytes like \"90 90\"", value)));
+            }
+        }
+    }
+    invalid
+}
+
+fn is_hex_bytes(text: &str) -> bool {
+    let mut tokens = text.split_whitespace().peekable();
+    tokens.peek().is_some() && tokens.all(|token| token.len() == 2 && u8::from_str_radix(token, 16).is_ok())
+}
+
+/// `logging.categories.detours`, `patches[0].offset`
+pub fn dotted(path: &[String]) -> String {
+    let mut text = String::new();
+    for key in path {
+        if key.parse::<usize>().is_ok() {
+            text += &format!("[{}]", key);
+        } else {
+            if !text.is_empty() {
+                text.push('.');
+            }
+            text += key;
+        }
+    }
+    text
+}
+
+// ============================================================================
+// Lines
+// ============================================================================
+
+/// Line (from 1) of the byte at `offset`
+fn line_at(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+/// Line that sets `path`, or else (unless `exact`) the line of its closest
+/// ancestor or first descendant, e.g. `[[a.b]]` for `a`; array elements
+/// are numbered by their `[[header]]`
+fn line_of(text: &str, path: &[String], exact: bool) -> Option<usize> {
+    let mut table: Vec<String> = Vec::new();
+    let mut elements: Vec<(Vec<String>, usize)> = Vec::new();
+    let mut closest: Option<(usize, usize)> = None;
+    let mut descendant = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let keys = if let Some(header) = line.strip_prefix("[[").and_then(|line| line.split("]]").next()) {
+            let name = split_keys(header);
+            let count = match elements.iter_mut().find(|(seen, _)| *seen == name) {
+                Some((_, count)) => {
+                    *count += 1;
+                    *count
+                }
+                None => {
+                    elements.push((name.clone(), 0));
+                    0
+                }
+            };
+            table = name;
+            table.push(count.to_string());
+            table.clone()
+        } else if let Some(header) = line.strip_prefix('[').and_then(|line| line.split(']').next()) {
+            table = split_keys(header);
+            table.clone()
+        } else if let Some((key, _)) = line.split_once('=').filter(|_| !line.starts_with('#')) {
+            let mut keys = table.clone();
+            keys.extend(split_keys(key));
+            keys
+        } else {
+            continue;
+        };
+
+        if keys == path {
+            return Some(index + 1);
+        }
+        if path.starts_with(&keys) && closest.is_none_or(|(len, _)| keys.len() > len) {
+            closest = Some((keys.len(), index + 1));
+        }
+        if keys.starts_with(path) {
+            descendant.get_or_insert(index + 1);
+        }
+    }
+    closest.map(|(_, line)| line).or(descendant).filter(|_| !exact)
+}
+
+/// `a."b.c".d` as its keys
+fn split_keys(text: &str) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut key = String::new();
+    let mut quote = None;
+    for c in text.chars() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            (None, '.') => keys.push(std::mem::take(&mut key).trim().to_string()),
+            _ => key.push(c),
+        }
+    }
+    keys.push(key.trim().to_string());
+    keys
+}
+
+/// The declared field `key` most likely is a typo of
+fn closest(key: &str, fields: &'static [&'static str]) -> Option<&'static str> {
+    fields
+        .iter()
+        .map(|field| (distance(key, field), *field))
+        .filter(|(distance, field)| *distance <= 2.max(field.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, field)| field)
+}
+
+/// Levenshtein distance
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substituted = previous + usize::from(a != *b);
+            previous = row[j + 1];
+            row[j + 1] = substituted.min(previous + 1).min(row[j] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+// ============================================================================
+// Deserializer
+// ============================================================================
+
+#[derive(Default)]
+struct State {
+    unknown: RefCell<Vec<UnknownKey>>,
+    /// Path of the innermost value that failed
+    failed_at: RefCell<Option<Vec<String>>>,
+}
+
+/// A value being deserialized, at `path`
+struct Checked<'a> {
+    value: toml::Value,
+    path: Vec<String>,
+    state: &'a State,
+}
+
+impl Checked<'_> {
+    /// Remember where the first error occurred: errors return from the
+    /// innermost value first
+    fn note<T>(state: &State, path: Vec<String>, result: Result<T, toml::de::Error>) -> Result<T, toml::de::Error> {
+        if result.is_err() {
+            state.failed_at.borrow_mut().get_or_insert(path);
+        }
+        result
+    }
+
+    fn visit_table_or_array<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, toml::de::Error> {
+        let (path, state) = (self.path.clone(), self.state);
+        let result = match self.value {
+            toml::Value::Table(table) => visitor.visit_map(CheckedMap {
+                entries: table.into_iter(),
+                pending: None,
+                path: self.path,
+                state,
+            }),
+            toml::Value::Array(array) => visitor.visit_seq(CheckedSeq {
+                elements: array.into_iter().enumerate(),
+                path: self.path,
+                state,
+            }),
+            value => de::Deserializer::deserialize_any(value, visitor),
+        };
+        Self::note(state, path, result)
+    }
+}
+
+/// Deserializer methods taken over from `toml::Value`
+macro_rules! forward {
+    ($($method:ident($($arg:ident: $type:ty),*)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $type,)* visitor: V) -> Result<V::Value, Self::Error> {
+                let (path, state) = (self.path, self.state);
+                Self::note(state, path, self.value.$method($($arg,)* visitor))
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Checked<'_> {
+    type Error = toml::de::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_table_or_array(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let toml::Value::Table(table) = &self.value else {
+            let (path, state) = (self.path, self.state);
+            return Self::note(state, path, self.value.deserialize_struct(name, fields, visitor));
+        };
+        for key in table.keys().filter(|key| !fields.contains(&key.as_str())) {
+            let mut path = self.path.clone();
+            path.push(key.clone());
+            self.state.unknown.borrow_mut().push(UnknownKey {
+                path,
+                suggestion: closest(key, fields),
+            });
+        }
+        self.visit_table_or_array(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_table_or_array(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_table_or_array(visitor)
+    }
+
+    // A present key is never none
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward! {
+        deserialize_bool(), deserialize_i8(), deserialize_i16(), deserialize_i32(), deserialize_i64(),
+        deserialize_u8(), deserialize_u16(), deserialize_u32(), deserialize_u64(), deserialize_f32(),
+        deserialize_f64(), deserialize_char(), deserialize_str(), deserialize_string(), deserialize_bytes(),
+        deserialize_byte_buf(), deserialize_unit(), deserialize_identifier(), deserialize_ignored_any(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+    }
+}
+
+struct CheckedMap<'a> {
+    entries: toml::map::IntoIter,
+    pending: Option<(String, toml::Value)>,
+    path: Vec<String>,
+    state: &'a State,
+}
+
+impl<'de> MapAccess<'de> for CheckedMap<'_> {
+    type Error = toml::de::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        let Some((key, value)) = self.entries.next() else {
+            return Ok(None);
+        };
+        let result = seed.deserialize(key.clone().into_deserializer()).map(Some);
+        self.pending = Some((key, value));
+        result
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
+        let (key, value) = self.pending.take().ok_or_else(|| de::Error::custom("value without a key"))?;
+        let mut path = self.path.clone();
+        path.push(key);
+        seed.deserialize(Checked {
+            value,
+            path,
+            state: self.state,
+        })
+    }
+}
+
+struct CheckedSeq<'a> {
+    elements: std::iter::Enumerate<std::vec::IntoIter<toml::Value>>,
+    path: Vec<String>,
+    state: &'a State,
+}
+
+impl<'de> SeqAccess<'de> for CheckedSeq<'_> {
+    type Error = toml::de::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error> {
+        let Some((index, value)) = self.elements.next() else {
+            return Ok(None);
+        };
+        let mut path = self.path.clone();
+        path.push(index.to_string());
+        seed.deserialize(Checked {
+            value,
+            path,
+            state: self.state,
+        })
+        .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(text: &str) -> Vec<String> {
+        check(text, "game.exe").iter().map(Diagnostic::to_string).collect()
+    }
+
+    #[test]
+    fn valid_files_have_no_findings() {
+        assert!(messages("").is_empty());
+        let text = "detours = true\n\n[logging]\njson = true\n\n[logging.categories]\ndetours = \"debug\"\n";
+        assert!(messages(text).is_empty());
+    }
+
+    #[test]
+    fn unknown_keys_are_reported_where_they_are() {
+        let text = "detuors = true\n\n[logging]\njsn = true\n\n[loggin]\nfile = false\n";
+        assert_eq!(
+            messages(text),
+            [
+                "1: unknown key `detuors` (did you mean `detours`?)",
+                "4: unknown key `logging.jsn` (did you mean `json`?)",
+                "6: unknown key `loggin` (did you mean `logging`?)",
+            ]
+        );
+    }
+
+    #[test]
+    fn type_errors_name_the_value() {
+        let text = "[loader]\nmanual_map = false\nretries = \"5\"\n";
+        let found = messages(text);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].starts_with("3: `loader.retries`: invalid type: string \"5\""), "{}", found[0]);
+
+        let found = messages("[logging\n");
+        assert!(found[0].starts_with("1: "), "{}", found[0]);
+    }
+
+    #[test]
+    fn array_elements_and_profiles_are_located() {
+        let text = r#"
+[[patches]]
+name = "a"
+offset = 16
+replacement = "90"
+
+[[patches]]
+name = "b"
+replacement = "zz"
+ofset = 32
+
+[profile."game.exe".logging]
+levle = "info"
+"#;
+        assert_eq!(
+            messages(text),
+            [
+                "7: `patches[1]`: exactly one of `offset` and `signature` is required",
+                "9: `patches[1].replacement`: 'zz' is not hex bytes like \"90 90\"",
+                "10: unknown key `patches[1].ofset` (did you mean `offset`?)",
+                "13: unknown key `logging.levle` (did you mean `level`?)",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_levels_are_values_of_the_right_type() {
+        let text = "[logging]\nlevel = \"verbose\"\n\n[logging.categories]\nhooks = \"loud\"\n";
+        let found = messages(text);
+        assert_eq!(found.len(), 2);
+        assert!(found[0].starts_with("2: `logging.level`: unknown log level 'verbose'"), "{}", found[0]);
+        assert!(found[1].starts_with("5: `logging.categories.hooks`"), "{}", found[1]);
+    }
+
+    #[test]
+    fn typos_suggest_close_fields_only() {
+        assert_eq!(closest("jsn", &["json", "path"]), Some("json"));
+        assert_eq!(closest("frobnicate", &["json", "path"]), None);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+}
@@ -0,0 +1,133 @@
+/// Correlation ids joining the proxy's output with external ETW traces
+///
+/// With `[correlation] enabled = true` each session gets a random GUID,
+/// logged at startup and emitted
+/// - into the trace output (trace.rs): a `Session` mark with the GUID, the
+///   QueryPerformanceCounter value and the wall-clock time it was taken at,
+///   and `otherData.session_id` in Chrome JSON
+/// - into the `ReflexProxy` ETW provider (logging.rs): the same values as
+///   a string event, and the GUID as the activity id of every event the
+///   provider writes from then on
+///
+/// With `frames = true`, every Present (frame_events.rs, 'nvapi' feature)
+/// gets a sequence number, emitted as a `Frame` mark and an ETW event with
+/// its QPC value, so frames line up with DXGI and driver events captured
+/// by WPR or xperf in the same session:
+///
+/// ```toml
+/// [correlation]
+/// enabled = true
+/// frames = true
+/// ```
+
+use crate::proxy_impl::config::CorrelationConfig;
+use crate::proxy_impl::logging;
+#[cfg(feature = "trace")]
+use crate::proxy_impl::trace;
+#[cfg(feature = "nvapi")]
+use crate::proxy_impl::frame_events::{self, FrameEvent};
+use once_cell::sync::OnceCell;
+use serde_json::{json, Map, Value};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::AtomicU64;
+#[cfg(feature = "nvapi")]
+use std::sync::atomic::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
+use winapi::shared::guiddef::GUID;
+use winapi::um::processthreadsapi::GetCurrentProcessId;
+use winapi::um::profileapi::QueryPerformanceCounter;
+use winapi::um::winnt::LARGE_INTEGER;
+
+static SESSION: OnceCell<GUID> = OnceCell::new();
+/// Presents numbered so far
+#[cfg_attr(not(feature = "nvapi"), allow(dead_code))]
+static FRAMES: AtomicU64 = AtomicU64::new(0);
+
+/// The session GUID, once `start` has run
+pub fn session() -> Option<GUID> {
+    SESSION.get().copied()
+}
+
+/// Lowercase with dashes: "0f8e3a52-91c4-4b7d-a3e6-5d20c19b7f48"
+pub fn format_guid(guid: &GUID) -> String {
+    let d = guid.Data4;
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        guid.Data1, guid.Data2, guid.Data3, d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7]
+    )
+}
+
+/// A random (version 4) GUID; the hasher keys come from the OS
+fn random_guid() -> GUID {
+    let random = |salt: u64| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(salt);
+        hasher.finish()
+    };
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    let (high, low) = (random(nanos), random(nanos ^ unsafe { GetCurrentProcessId() } as u64));
+    let mut data4 = low.to_be_bytes();
+    data4[0] = (data4[0] & 0x3f) | 0x80;
+    GUID {
+        Data1: (high >> 32) as u32,
+        Data2: (high >> 16) as u16,
+        Data3: (high as u16 & 0x0fff) | 0x4000,
+        Data4: data4,
+    }
+}
+
+fn qpc() -> i64 {
+    unsafe {
+        let mut count: LARGE_INTEGER = std::mem::zeroed();
+        QueryPerformanceCounter(&mut count);
+        *count.QuadPart()
+    }
+}
+
+/// Emit a mark into the trace output and an event from the ETW provider
+fn emit(name: &'static str, args: Map<String, Value>) {
+    let text = args
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value.as_str().map_or_else(|| value.to_string(), str::to_string)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    logging::write_etw(&format!("{} {}", name, text));
+    #[cfg(feature = "trace")]
+    trace::mark(name, args);
+}
+
+/// Create the session id and emit it; number frames from now on if
+/// configured
+pub fn start(config: &CorrelationConfig) {
+    let session = format_guid(SESSION.get_or_init(random_guid));
+    tracing::info!("[correlation] Session {}", session);
+
+    let unix_ms = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64);
+    let args = json!({ "session_id": session, "qpc": qpc(), "unix_ms": unix_ms });
+    if let Value::Object(args) = args {
+        emit("Session", args);
+    }
+
+    if config.frames {
+        #[cfg(feature = "nvapi")]
+        match frame_events::subscribe(on_frame_event) {
+            Ok(()) => unsafe { frame_events::install() },
+            Err(e) => tracing::warn!("[correlation] Cannot number frames: {}", e),
+        }
+        #[cfg(not(feature = "nvapi"))]
+        tracing::warn!("[correlation] Numbering frames needs the Present hook of the 'nvapi' feature");
+    }
+}
+
+#[cfg(feature = "nvapi")]
+fn on_frame_event(event: FrameEvent) {
+    if event != FrameEvent::PresentStart {
+        return;
+    }
+    let frame = FRAMES.fetch_add(1, Ordering::Relaxed) + 1;
+    let mut args = Map::new();
+    args.insert("frame".to_string(), frame.into());
+    args.insert("qpc".to_string(), qpc().into());
+    emit("Frame", args);
+}
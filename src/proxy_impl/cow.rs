@@ -0,0 +1,92 @@
+/// Copy-on-write cell for tables read on every hooked call
+///
+/// Readers get the current version with one atomic load, without a lock,
+/// so a detour on the render thread never waits for a registration.
+/// Writers clone the current version, change the copy and publish it;
+/// they are serialized by a mutex only writers take.
+///
+/// Replaced versions are never freed, as a reader may still be using one:
+/// a cell is for tables changed while the proxy sets up (handler chains,
+/// registries), not for data changing per call.
+///
+/// Independent of the host platform.
+
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Mutex;
+
+pub struct CowCell<T> {
+    current: AtomicPtr<T>,
+    writer: Mutex<()>,
+    /// Shared and sent like a `T`
+    _value: PhantomData<T>,
+}
+
+impl<T: Clone> CowCell<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            current: AtomicPtr::new(Box::into_raw(Box::new(value))),
+            writer: Mutex::new(()),
+            _value: PhantomData,
+        }
+    }
+
+    /// The current version
+    pub fn load(&self) -> &T {
+        // SAFETY: versions are only freed with the cell
+        unsafe { &*self.current.load(Ordering::Acquire) }
+    }
+
+    /// Publish a copy of the current version changed by `change`
+    pub fn update(&self, change: impl FnOnce(&mut T)) {
+        let _writer = self.writer.lock().unwrap();
+        let mut next = self.load().clone();
+        change(&mut next);
+        // The replaced version is leaked, readers may still hold it
+        self.current.store(Box::into_raw(Box::new(next)), Ordering::Release);
+    }
+}
+
+impl<T: Clone + Default> Default for CowCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> Drop for CowCell<T> {
+    fn drop(&mut self) {
+        // SAFETY: no reader can outlive the cell
+        drop(unsafe { Box::from_raw(*self.current.get_mut()) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readers_keep_the_version_they_loaded() {
+        let cell = CowCell::new(vec![1]);
+        let before = cell.load();
+        cell.update(|values| values.push(2));
+        assert_eq!(before, &[1]);
+        assert_eq!(cell.load(), &[1, 2]);
+    }
+
+    #[test]
+    fn concurrent_updates_are_not_lost() {
+        let cell = CowCell::<Vec<usize>>::default();
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let cell = &cell;
+                scope.spawn(move || {
+                    for i in 0..25 {
+                        cell.update(|values| values.push(thread * 100 + i));
+                        assert!(!cell.load().is_empty());
+                    }
+                });
+            }
+        });
+        assert_eq!(cell.load().len(), 100);
+    }
+}
@@ -0,0 +1,214 @@
+/// Deferred hook installation for modules loaded after the proxy
+///
+/// Targets such as nvapi64.dll or d3d11.dll are usually loaded well after
+/// reflex.dll, so GetModuleHandle returns null at attach. `when_loaded`
+/// queues an installer that runs as soon as the module is mapped:
+///
+/// ```ignore
+/// deferred::when_loaded("nvapi64.dll", "nvapi_QueryInterface", |base| unsafe {
+///     let target = os::system().get_proc_address(base, "nvapi_QueryInterface").ok_or("not exported")?;
+///     hooks::install_inline_hook_into("nvapi_QueryInterface", target, detour as usize, &ORIGINAL)
+/// });
+/// ```
+///
+/// If the module is already loaded the installer runs immediately.
+/// Otherwise a DLL load notification (LdrRegisterDllNotification) is
+/// registered on first use and removed during shutdown. Installers run on
+/// the loading thread with the loader lock held, before the module's
+/// DllMain: they may patch memory but must not load libraries or wait on
+/// other threads.
+
+use crate::proxy_impl::status::{self, ErrorKind};
+use crate::proxy_impl::wide::to_wide;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::ffi::CString;
+use std::sync::Mutex;
+use winapi::shared::ntdef::{NTSTATUS, PVOID, ULONG, UNICODE_STRING};
+use winapi::um::libloaderapi::{GetModuleHandleA, GetModuleHandleW, GetProcAddress};
+
+/// `LDR_DLL_NOTIFICATION_REASON_LOADED`
+const REASON_LOADED: ULONG = 1;
+
+type Installer = Box<dyn FnOnce(usize) -> Result<(), String> + Send>;
+
+/// `LDR_DLL_LOADED_NOTIFICATION_DATA` (same layout for unloads)
+#[repr(C)]
+struct NotificationData {
+    flags: ULONG,
+    full_dll_name: *const UNICODE_STRING,
+    base_dll_name: *const UNICODE_STRING,
+    dll_base: PVOID,
+    size_of_image: ULONG,
+}
+
+type NotificationFn = unsafe extern "system" fn(ULONG, *const NotificationData, PVOID);
+type LdrRegisterDllNotificationFn = unsafe extern "system" fn(ULONG, NotificationFn, PVOID, *mut PVOID) -> NTSTATUS;
+type LdrUnregisterDllNotificationFn = unsafe extern "system" fn(PVOID) -> NTSTATUS;
+
+struct Pending {
+    /// Lowercase base name, e.g. "nvapi64.dll"
+    module: String,
+    name: String,
+    install: Installer,
+}
+
+/// A queued installer, for the status report
+#[derive(Debug, Clone, Serialize)]
+pub struct DeferredInfo {
+    pub module: String,
+    pub name: String,
+}
+
+static PENDING: Lazy<Mutex<Vec<Pending>>> = Lazy::new(|| Mutex::new(Vec::new()));
+/// Registration cookie, as usize (null = not registered)
+static COOKIE: Mutex<usize> = Mutex::new(0);
+
+/// Run `install` with the base address of `module` once it is loaded
+///
+/// `name` identifies the installer in the log and the status report.
+pub unsafe fn when_loaded<F>(module: &str, name: &str, install: F)
+where
+    F: FnOnce(usize) -> Result<(), String> + Send + 'static,
+{
+    if let Some(base) = module_base(module) {
+        run(module, name, Box::new(install), base);
+        return;
+    }
+
+    if let Err(e) = register() {
+        status::record_error(ErrorKind::Detour);
+        tracing::error!("[deferred] Cannot wait for {} ({}): {}", module, name, e);
+        return;
+    }
+
+    tracing::info!("[deferred] {} waits for {} to load", name, module);
+    let module = module.to_lowercase();
+    PENDING.lock().unwrap().push(Pending {
+        module: module.clone(),
+        name: name.to_string(),
+        install: Box::new(install),
+    });
+
+    // The module may have been loaded before the notification was registered
+    if let Some(base) = module_base(&module) {
+        run_ready(&module, base);
+    }
+}
+
+/// Installers still waiting for their module
+pub fn pending() -> Vec<DeferredInfo> {
+    PENDING
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|p| DeferredInfo {
+            module: p.module.clone(),
+            name: p.name.clone(),
+        })
+        .collect()
+}
+
+/// Remove the load notification and drop installers that never ran
+pub unsafe fn shutdown() {
+    let mut cookie = COOKIE.lock().unwrap();
+    if *cookie != 0 {
+        if let Some(unregister) = resolve::<LdrUnregisterDllNotificationFn>("LdrUnregisterDllNotification") {
+            unregister(*cookie as PVOID);
+        }
+        *cookie = 0;
+    }
+    drop(cookie);
+
+    let dropped = std::mem::take(&mut *PENDING.lock().unwrap());
+    for pending in &dropped {
+        tracing::info!("[deferred] {} never ran, {} was not loaded", pending.name, pending.module);
+    }
+}
+
+// ============================================================================
+// Utility Functions
+// ============================================================================
+
+unsafe fn register() -> Result<(), String> {
+    let mut cookie = COOKIE.lock().unwrap();
+    if *cookie != 0 {
+        return Ok(());
+    }
+
+    let register = resolve::<LdrRegisterDllNotificationFn>("LdrRegisterDllNotification")
+        .ok_or("LdrRegisterDllNotification not found")?;
+    let mut handle: PVOID = std::ptr::null_mut();
+    let result = register(0, on_notification, std::ptr::null_mut(), &mut handle);
+    if result < 0 {
+        return Err(format!("LdrRegisterDllNotification failed with 0x{:08x}", result));
+    }
+
+    *cookie = handle as usize;
+    tracing::info!("[deferred] Registered DLL load notification");
+    Ok(())
+}
+
+unsafe extern "system" fn on_notification(reason: ULONG, data: *const NotificationData, _context: PVOID) {
+    if reason != REASON_LOADED || data.is_null() {
+        return;
+    }
+
+    let data = &*data;
+    let loaded = unicode_to_string(data.base_dll_name).to_lowercase();
+    run_ready(&loaded, data.dll_base as usize);
+}
+
+/// Run every installer waiting for `module`
+unsafe fn run_ready(module: &str, base: usize) {
+    // Run outside the lock: an installer may queue further installers
+    let ready: Vec<Pending> = {
+        let mut pending = PENDING.lock().unwrap();
+        let (ready, waiting) = std::mem::take(&mut *pending).into_iter().partition(|p| p.module == module);
+        *pending = waiting;
+        ready
+    };
+
+    for pending in ready {
+        run(&pending.module, &pending.name, pending.install, base);
+    }
+}
+
+unsafe fn run(module: &str, name: &str, install: Installer, base: usize) {
+    match install(base) {
+        Ok(()) => tracing::info!("[deferred] Installed {} on {} at 0x{:x}", name, module, base),
+        Err(e) => {
+            status::record_error(ErrorKind::Detour);
+            tracing::error!("[deferred] Failed to install {} on {}: {}", name, module, e);
+        }
+    }
+}
+
+unsafe fn module_base(module: &str) -> Option<usize> {
+    let wide = to_wide(module);
+    match GetModuleHandleW(wide.as_ptr()) as usize {
+        0 => None,
+        base => Some(base),
+    }
+}
+
+unsafe fn resolve<F>(function: &str) -> Option<F> {
+    let ntdll = GetModuleHandleA(c"ntdll.dll".as_ptr());
+    if ntdll.is_null() {
+        return None;
+    }
+    let name = CString::new(function).ok()?;
+    match GetProcAddress(ntdll, name.as_ptr()) as usize {
+        0 => None,
+        address => Some(std::mem::transmute_copy(&address)),
+    }
+}
+
+unsafe fn unicode_to_string(string: *const UNICODE_STRING) -> String {
+    if string.is_null() || (*string).Buffer.is_null() {
+        return String::new();
+    }
+    let string = &*string;
+    let chars = std::slice::from_raw_parts(string.Buffer, string.Length as usize / 2);
+    String::from_utf16_lossy(chars)
+}
@@ -4,11 +4,26 @@
 /// 1. Hook specific functions by offset (for internal functions)
 /// 2. Hook exported functions by name
 /// 3. Replace functionality while optionally calling the original
-/// 4. Implement custom behavior
+/// 4. Implement custom behavior, driven by the intercept rules (rules.rs)
+/// 5. Declare a hook without boilerplate (`declare_hook!`, hooks.rs)
+/// 6. Run code around the original's DllMain (`proxy::add_pre_dllmain_hook`)
 
 use crate::proxy;
+use crate::proxy_impl::audit::{self, Category};
+use crate::proxy_impl::rules::{self, CallContext, Decision, Filter};
+use crate::proxy_impl::hooks::declare_hook;
+use crate::proxy_impl::{config, hook_timeout, offsets, stacks};
+#[cfg(feature = "spoof")]
+use crate::proxy_impl::thread_scope;
+use serde_json::json;
+use crate::proxy_impl::status::{self, ErrorKind};
+use crate::proxy_impl::wide::{from_wide_ptr, to_wide};
 use winapi::shared::minwindef::{BOOL, DWORD, LPVOID};
-use winapi::um::winnt::{HANDLE, LPCSTR, LPCWSTR, LPWSTR};
+use winapi::um::winnt::{DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH, LPCSTR, LPCWSTR};
+#[cfg(feature = "spoof")]
+use crate::proxy_impl::hooks;
+#[cfg(feature = "spoof")]
+use winapi::um::winnt::{HANDLE, LPWSTR};
 
 /// Example: Hook an internal function by offset
 ///
@@ -19,15 +34,24 @@ use winapi::um::winnt::{HANDLE, LPCSTR, LPCWSTR, LPWSTR};
 ///
 /// # Safety
 /// This is extremely unsafe and depends on exact binary layout.
-/// Offsets will change if the DLL is recompiled or updated.
+/// Offsets will change if the DLL is recompiled or updated; give the entry
+/// an anchor (offsets.rs) to find it from an export instead.
 pub unsafe fn hook_internal_function_example() {
-    // Example: Hook a function at offset 0x1234 from DLL base
-    const FUNCTION_OFFSET: usize = 0x1234;
+    // Example: Hook the function EXAMPLE_FN's anchor leads to (0x1234 from
+    // the DLL base in the analysed build)
+    let base = proxy::get_original_dll_base() as *const u8;
+    let function_offset = match offsets::resolve(base, &offsets::EXAMPLE_FN) {
+        Ok(offset) => offset,
+        Err(e) => {
+            tracing::error!("[detours] Failed to resolve internal function: {}", e);
+            return;
+        }
+    };
 
     type InternalFunctionType = unsafe extern "system" fn(DWORD, LPVOID) -> BOOL;
 
-    if let Some(original_fn) = proxy::resolve_internal_function::<InternalFunctionType>(FUNCTION_OFFSET) {
-        log::info!("[detours] Successfully resolved internal function at offset 0x{:x}", FUNCTION_OFFSET);
+    if let Some(original_fn) = proxy::resolve_internal_function::<InternalFunctionType>(function_offset) {
+        tracing::info!("[detours] Successfully resolved internal function at offset 0x{:x}", function_offset);
 
         // You can now call the original function
         // let result = original_fn(param1, param2);
@@ -35,7 +59,7 @@ pub unsafe fn hook_internal_function_example() {
         // Or store it for later use in your hook
         // ORIGINAL_INTERNAL_FN = Some(original_fn);
     } else {
-        log::error!("[detours] Failed to resolve internal function at offset 0x{:x}", FUNCTION_OFFSET);
+        tracing::error!("[detours] Failed to resolve internal function at offset 0x{:x}", function_offset);
     }
 }
 
@@ -46,10 +70,10 @@ pub unsafe fn hook_exported_function_example() {
     type DllMainType = unsafe extern "system" fn(LPVOID, DWORD, LPVOID) -> BOOL;
 
     if let Some(original_dllmain) = proxy::get_original_export::<DllMainType>("DllMain") {
-        log::info!("[detours] Successfully resolved exported DllMain");
+        tracing::info!("[detours] Successfully resolved exported DllMain");
         // Store for later use
     } else {
-        log::warn!("[detours] DllMain not found in exports (this is normal)");
+        tracing::warn!("[detours] DllMain not found in exports (this is normal)");
     }
 }
 
@@ -57,55 +81,131 @@ pub unsafe fn hook_exported_function_example() {
 // Example Hook Implementations
 // ============================================================================
 
-/// Example: Hook for DeleteFileW
-///
-/// This demonstrates how to intercept a Windows API call that the original
-/// DLL might be hooking, and add your own custom behavior.
-pub unsafe extern "system" fn hooked_delete_file_w(file_name: LPCWSTR) -> BOOL {
+declare_hook! {
+    /// Example: Hook for DeleteFileW
+    ///
+    /// This demonstrates how to intercept a Windows API call that the original
+    /// DLL might be hooking. What happens is decided by the intercept rules:
+    /// `block` fails the call, `modify` deletes the file named by the rule's
+    /// value instead.
+    fn DeleteFileW(file_name: LPCWSTR) -> BOOL {
+        let mut file_name = copy_wstr(file_name);
+        dispatch("DeleteFileW", move || unsafe { delete_file_w(buffer_ptr(&mut file_name)) as i64 }) as BOOL
+    }
+}
+
+unsafe fn delete_file_w(file_name: LPCWSTR) -> BOOL {
     // Convert wide string to Rust string for logging
-    let path = wstr_to_string(file_name);
+    let path = from_wide_ptr(file_name).unwrap_or_default();
+    let span = tracing::info_span!("DeleteFileW", path = %path);
+    let _enter = span.enter();
+
+    tracing::info!("[detours] DeleteFileW intercepted: {}", path);
 
-    log::info!("[detours] DeleteFileW intercepted: {}", path);
+    let call = CallContext {
+        api: "DeleteFileW",
+        path: Some(&path),
+        ..Default::default()
+    };
 
-    // Add custom logic here
-    if path.contains("important_file") {
-        log::warn!("[detours] Blocking deletion of important file: {}", path);
-        return 0; // FALSE - block deletion
+    if !DeleteFileW::is_installed() {
+        return 0; // FALSE - nothing to forward to
     }
 
-    // Call the original function from reflex_original.dll
-    // You would need to resolve this first and store it
-    // For now, just return success
-    1 // TRUE
+    let decision = rules::decide(&call);
+    let result = match &decision {
+        Decision::Allow => DeleteFileW::call_original(file_name),
+        Decision::Block => {
+            tracing::warn!("[detours] Blocking deletion of: {}", path);
+            0 // FALSE - block deletion
+        }
+        Decision::Modify(redirect) => {
+            tracing::warn!("[detours] Redirecting deletion of {} to {}", path, redirect);
+            let redirect_wide = to_wide(redirect);
+            DeleteFileW::call_original(redirect_wide.as_ptr())
+        }
+    };
+
+    audit::record(
+        Category::File,
+        "DeleteFileW",
+        json!({ "path": path }),
+        &decision,
+        result as i64,
+    );
+    result
 }
 
 /// Example: Hook for GetUserNameW
 ///
-/// This shows how to spoof return values
+/// This shows how to spoof return values (`modify` rule)
+#[cfg(feature = "spoof")]
 pub unsafe extern "system" fn hooked_get_user_name_w(buffer: LPWSTR, size: *mut DWORD) -> BOOL {
-    log::info!("[detours] GetUserNameW intercepted");
-
-    // Return a custom username
-    let custom_username = "CustomUser";
-    let username_wide: Vec<u16> = custom_username
-        .encode_utf16()
-        .chain(std::iter::once(0))
-        .collect();
-
-    if (*size as usize) < username_wide.len() {
-        *size = username_wide.len() as DWORD;
-        return 0; // FALSE - buffer too small
+    if !thread_scope::in_scope("GetUserNameW") {
+        return match ORIGINAL_FUNCTIONS.get_user_name_w {
+            Some(original) => original(buffer, size),
+            None => 0, // FALSE
+        };
     }
+    // The body fills copies, written back only if it finishes in time
+    let capacity = if size.is_null() { 0 } else { *size as usize };
+    let (mut local_buffer, mut local_size) = (copy_in(buffer, capacity), size.as_ref().copied());
+    let outputs = dispatch_with("GetUserNameW", move || unsafe {
+        let result = get_user_name_w(buffer_ptr(&mut local_buffer), value_ptr(&mut local_size));
+        (result, local_buffer, local_size)
+    });
+    match outputs {
+        Ok((result, local_buffer, local_size)) => {
+            copy_out(&local_buffer, buffer);
+            copy_out_value(local_size, size);
+            result
+        }
+        Err(fallback) => fallback as BOOL,
+    }
+}
 
-    std::ptr::copy_nonoverlapping(username_wide.as_ptr(), buffer, username_wide.len());
-    *size = username_wide.len() as DWORD;
+#[cfg(feature = "spoof")]
+unsafe fn get_user_name_w(buffer: LPWSTR, size: *mut DWORD) -> BOOL {
+    let span = tracing::info_span!("GetUserNameW");
+    let _enter = span.enter();
+    tracing::info!("[detours] GetUserNameW intercepted");
+
+    let call = CallContext {
+        api: "GetUserNameW",
+        ..Default::default()
+    };
+
+    let decision = rules::decide(&call);
+    let result = match &decision {
+        Decision::Allow => match ORIGINAL_FUNCTIONS.get_user_name_w {
+            Some(original) => original(buffer, size),
+            None => 0, // FALSE
+        },
+        Decision::Block => 0, // FALSE
+        Decision::Modify(custom_username) => {
+            // Return a custom username
+            let username_wide = to_wide(custom_username);
+
+            if (*size as usize) < username_wide.len() {
+                *size = username_wide.len() as DWORD;
+                0 // FALSE - buffer too small
+            } else {
+                std::ptr::copy_nonoverlapping(username_wide.as_ptr(), buffer, username_wide.len());
+                *size = username_wide.len() as DWORD;
+                1 // TRUE
+            }
+        }
+    };
 
-    1 // TRUE
+    audit::record(Category::Process, "GetUserNameW", json!({}), &decision, result as i64);
+    result
 }
 
 /// Example: Hook for registry operations
 ///
-/// This demonstrates intercepting registry queries
+/// This demonstrates intercepting registry queries; `modify` rules return
+/// their value as a REG_SZ
+#[cfg(feature = "spoof")]
 pub unsafe extern "system" fn hooked_reg_query_value_ex_w(
     key: HANDLE,
     value_name: LPCWSTR,
@@ -114,47 +214,202 @@ pub unsafe extern "system" fn hooked_reg_query_value_ex_w(
     data: *mut u8,
     data_size: *mut DWORD,
 ) -> i32 {
-    let name = wstr_to_string(value_name);
-    log::info!("[detours] RegQueryValueExW intercepted: {}", name);
-
-    // Spoof specific registry values
-    if name == "HwProfileGuid" {
-        log::info!("[detours] Spoofing HwProfileGuid");
-        // Return custom GUID
-        let custom_guid = "{AAAAAAAA-AAAA-AAAA-AAAA-AAAAAAAAAAAA}";
-        let guid_wide: Vec<u16> = custom_guid
-            .encode_utf16()
-            .chain(std::iter::once(0))
-            .collect();
-
-        if !data.is_null() && (*data_size as usize) >= guid_wide.len() * 2 {
-            std::ptr::copy_nonoverlapping(
-                guid_wide.as_ptr() as *const u8,
-                data,
-                guid_wide.len() * 2,
-            );
+    if !thread_scope::in_scope("RegQueryValueExW") {
+        return match ORIGINAL_FUNCTIONS.reg_query_value_ex_w {
+            Some(original) => original(key, value_name, reserved, type_, data, data_size),
+            None => 2, // ERROR_FILE_NOT_FOUND
+        };
+    }
+    // The body fills copies, written back only if it finishes in time; the
+    // key is passed on as a value
+    let key = key as usize;
+    let mut local_name = copy_wstr(value_name);
+    let mut local_reserved = reserved.as_ref().copied();
+    let mut local_type = type_.as_ref().copied();
+    let capacity = if data_size.is_null() { 0 } else { *data_size as usize };
+    let mut local_data = copy_in(data, capacity);
+    let mut local_size = data_size.as_ref().copied();
+    let outputs = dispatch_with("RegQueryValueExW", move || unsafe {
+        let result = reg_query_value_ex_w(
+            key as HANDLE,
+            buffer_ptr(&mut local_name),
+            value_ptr(&mut local_reserved),
+            value_ptr(&mut local_type),
+            buffer_ptr(&mut local_data),
+            value_ptr(&mut local_size),
+        );
+        (result, local_type, local_data, local_size)
+    });
+    match outputs {
+        Ok((result, local_type, local_data, local_size)) => {
+            copy_out_value(local_type, type_);
+            copy_out(&local_data, data);
+            copy_out_value(local_size, data_size);
+            result
+        }
+        Err(fallback) => fallback as i32,
+    }
+}
+
+#[cfg(feature = "spoof")]
+unsafe fn reg_query_value_ex_w(
+    key: HANDLE,
+    value_name: LPCWSTR,
+    reserved: *mut DWORD,
+    type_: *mut DWORD,
+    data: *mut u8,
+    data_size: *mut DWORD,
+) -> i32 {
+    const ERROR_FILE_NOT_FOUND: i32 = 2;
+
+    let name = from_wide_ptr(value_name).unwrap_or_default();
+    let span = tracing::info_span!("RegQueryValueExW", value_name = %name);
+    let _enter = span.enter();
+    tracing::info!("[detours] RegQueryValueExW intercepted: {}", name);
+
+    let call = CallContext {
+        api: "RegQueryValueExW",
+        value_name: Some(&name),
+        ..Default::default()
+    };
+
+    let decision = rules::decide(&call);
+    let result = match &decision {
+        Decision::Allow => match ORIGINAL_FUNCTIONS.reg_query_value_ex_w {
+            Some(original) => original(key, value_name, reserved, type_, data, data_size),
+            None => ERROR_FILE_NOT_FOUND,
+        },
+        Decision::Block => ERROR_FILE_NOT_FOUND,
+        Decision::Modify(value) => {
+            tracing::info!("[detours] Spoofing {}", name);
+            write_reg_sz(value, type_, data, data_size)
         }
+    };
+
+    audit::record(
+        Category::Registry,
+        "RegQueryValueExW",
+        json!({ "key": format!("{:p}", key), "value_name": name }),
+        &decision,
+        result as i64,
+    );
+    result
+}
+
+/// Run a detour body, on a worker thread with a timeout if `[hook_timeouts]`
+/// lists the API (hook_timeout.rs); the caller's stack is logged first if
+/// `[stacks]` selects it (stacks.rs)
+///
+/// The body may outlive the call, so it gets copies of what pointer
+/// arguments point to rather than the pointers (`copy_in`, `copy_wstr`).
+pub fn dispatch<F>(api: &str, body: F) -> i64
+where
+    F: FnOnce() -> i64 + Send + 'static,
+{
+    dispatch_with(api, body).unwrap_or_else(|fallback| fallback)
+}
+
+/// `dispatch` for detours with out-parameters: the body returns its copies
+/// along with the result, or `Err(fallback)` if it did not finish in time
+/// and nothing may be written back
+pub fn dispatch_with<T, F>(api: &str, body: F) -> Result<T, i64>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    // On the calling thread, before the body may move to a worker
+    unsafe { stacks::capture(api) };
+    hook_timeout::run(api, config::get().hook_timeouts.get(api), body)
+}
+
+/// A copy of the `len` elements at `ptr`, None for a null pointer
+unsafe fn copy_in<T: Copy>(ptr: *const T, len: usize) -> Option<Vec<T>> {
+    (!ptr.is_null()).then(|| std::slice::from_raw_parts(ptr, len).to_vec())
+}
+
+/// A copy of a NUL-terminated wide string, including the NUL
+unsafe fn copy_wstr(ptr: LPCWSTR) -> Option<Vec<u16>> {
+    if ptr.is_null() {
+        return None;
+    }
+    let len = (0..).take_while(|&i| *ptr.add(i) != 0).count();
+    copy_in(ptr, len + 1)
+}
+
+/// Pointer to a copy, null where the caller passed null
+fn buffer_ptr<T>(copy: &mut Option<Vec<T>>) -> *mut T {
+    copy.as_mut().map_or(std::ptr::null_mut(), |copy| copy.as_mut_ptr())
+}
+
+#[cfg(feature = "spoof")]
+fn value_ptr<T>(copy: &mut Option<T>) -> *mut T {
+    copy.as_mut().map_or(std::ptr::null_mut(), |copy| copy as *mut T)
+}
 
-        return 0; // ERROR_SUCCESS
+/// Write a copy back over the caller's buffer
+#[cfg(feature = "spoof")]
+unsafe fn copy_out<T: Copy>(copy: &Option<Vec<T>>, ptr: *mut T) {
+    if let Some(copy) = copy {
+        std::ptr::copy_nonoverlapping(copy.as_ptr(), ptr, copy.len());
     }
+}
 
-    // For other values, call original or return error
-    0 // ERROR_SUCCESS
+#[cfg(feature = "spoof")]
+unsafe fn copy_out_value<T>(copy: Option<T>, ptr: *mut T) {
+    if let Some(value) = copy {
+        *ptr = value;
+    }
+}
+
+/// Return a string value the way RegQueryValueExW would for a REG_SZ
+#[cfg(feature = "spoof")]
+unsafe fn write_reg_sz(value: &str, type_: *mut DWORD, data: *mut u8, data_size: *mut DWORD) -> i32 {
+    const ERROR_SUCCESS: i32 = 0;
+    const ERROR_MORE_DATA: i32 = 234;
+    const REG_SZ: DWORD = 1;
+
+    let value_wide = to_wide(value);
+    let byte_len = value_wide.len() * 2;
+
+    if !type_.is_null() {
+        *type_ = REG_SZ;
+    }
+    if data_size.is_null() {
+        return ERROR_SUCCESS;
+    }
+
+    let available = *data_size as usize;
+    *data_size = byte_len as DWORD;
+
+    if data.is_null() {
+        return ERROR_SUCCESS; // size query
+    }
+    if available < byte_len {
+        return ERROR_MORE_DATA;
+    }
+
+    std::ptr::copy_nonoverlapping(value_wide.as_ptr() as *const u8, data, byte_len);
+    ERROR_SUCCESS
 }
 
 // ============================================================================
 // Function Pointer Storage
 // ============================================================================
 
+#[cfg(feature = "spoof")]
+type RegQueryValueExWFn =
+    unsafe extern "system" fn(HANDLE, LPCWSTR, *mut DWORD, *mut DWORD, *mut u8, *mut DWORD) -> i32;
+
 /// Storage for original function pointers
 ///
 /// These would be initialized during DLL_PROCESS_ATTACH by resolving
 /// functions from the original DLL.
 pub struct OriginalFunctions {
     // Windows API hooks (if the original DLL hooks them)
-    pub delete_file_w: Option<unsafe extern "system" fn(LPCWSTR) -> BOOL>,
+    #[cfg(feature = "spoof")]
     pub get_user_name_w: Option<unsafe extern "system" fn(LPWSTR, *mut DWORD) -> BOOL>,
-    pub reg_query_value_ex_w: Option<unsafe extern "system" fn(HANDLE, LPCWSTR, *mut DWORD, *mut DWORD, *mut u8, *mut DWORD) -> i32>,
+    #[cfg(feature = "spoof")]
+    pub reg_query_value_ex_w: Option<RegQueryValueExWFn>,
 
     // Internal reflex.dll functions (by offset)
     pub internal_init_fn: Option<unsafe extern "system" fn() -> BOOL>,
@@ -164,8 +419,9 @@ pub struct OriginalFunctions {
 impl OriginalFunctions {
     pub const fn new() -> Self {
         Self {
-            delete_file_w: None,
+            #[cfg(feature = "spoof")]
             get_user_name_w: None,
+            #[cfg(feature = "spoof")]
             reg_query_value_ex_w: None,
             internal_init_fn: None,
             internal_cleanup_fn: None,
@@ -179,31 +435,121 @@ static mut ORIGINAL_FUNCTIONS: OriginalFunctions = OriginalFunctions::new();
 ///
 /// Call this during DLL_PROCESS_ATTACH after the proxy is initialized
 pub unsafe fn initialize_detours() -> Result<(), String> {
-    log::info!("[detours] Initializing detours...");
+    tracing::info!("[detours] Initializing detours...");
+
+    let base = proxy::get_original_dll_base() as *const u8;
+    if base.is_null() {
+        return Err("Original DLL is not loaded".to_string());
+    }
+
+    // Example: Run code around the original's DllMain. Registered before
+    // the proxy forwards DLL_PROCESS_ATTACH, so they see it too
+    proxy::add_pre_dllmain_hook(|_, reason, _| {
+        match reason {
+            DLL_PROCESS_ATTACH => tracing::info!("[detours] Pre-hook: DLL_PROCESS_ATTACH"),
+            DLL_PROCESS_DETACH => tracing::info!("[detours] Pre-hook: DLL_PROCESS_DETACH"),
+            _ => {}
+        }
+        None // Continue to original; Some(result) would skip it
+    });
+    proxy::add_post_dllmain_hook(|_, reason, _, result| {
+        if reason == DLL_PROCESS_ATTACH || reason == DLL_PROCESS_DETACH {
+            tracing::info!(
+                "[detours] Post-hook: original's DllMain({}) completed with result={}",
+                reason,
+                result
+            );
+        }
+    });
+
+    // The config file's rules decide on the intercepted calls; other
+    // handlers can run before or after them (rules.rs)
+    rules::add_handler("rules", rules::RULES_PRIORITY, Filter::default(), |call| {
+        rules::first_match(&config::get().rules, call)
+    });
+
+    // Example: Handlers only called for the calls their filter selects;
+    // they log and leave the decision to the rules
+    rules::add_handler("log-config-deletions", -10, Filter::api("DeleteFileW").path("*.cfg"), |call| {
+        tracing::info!("[detours] Config file deleted: {}", call.path.unwrap_or_default());
+        None
+    });
+    let hardware_id = Filter::api("RegQueryValueExW").value_name("HwProfileGuid");
+    rules::add_handler("log-hardware-id", -10, hardware_id, |_| {
+        tracing::info!("[detours] Hardware profile GUID queried");
+        None
+    });
+
+    // Hook the Windows APIs as imported by reflex_original.dll, so only
+    // calls made by the original DLL are intercepted
+    if let Err(e) = DeleteFileW::install_iat(base) {
+        hook_failed("DeleteFileW", &e);
+    }
+
+    #[cfg(feature = "spoof")]
+    {
+        ORIGINAL_FUNCTIONS.get_user_name_w = install_api_hook(
+            base,
+            "GetUserNameW",
+            hooked_get_user_name_w as *const () as usize,
+        )
+        .map(|f| std::mem::transmute::<usize, unsafe extern "system" fn(LPWSTR, *mut DWORD) -> BOOL>(f));
+
+        ORIGINAL_FUNCTIONS.reg_query_value_ex_w = install_api_hook(
+            base,
+            "RegQueryValueExW",
+            hooked_reg_query_value_ex_w as *const () as usize,
+        )
+        .map(|f| std::mem::transmute::<usize, RegQueryValueExWFn>(f));
+    }
 
     // Example: Resolve internal functions by offset
-    // These offsets would come from reverse engineering with radare2
+    // These offsets come from the offset database (see offsets.rs), through
+    // their anchors where they have one
 
-    // Example offset for an initialization function
-    const INIT_FN_OFFSET: usize = 0x1000; // Replace with actual offset
-    ORIGINAL_FUNCTIONS.internal_init_fn =
-        proxy::resolve_internal_function(INIT_FN_OFFSET);
+    ORIGINAL_FUNCTIONS.internal_init_fn = resolve_known(base, &offsets::INIT_FN);
 
-    // Example offset for a cleanup function
-    const CLEANUP_FN_OFFSET: usize = 0x2000; // Replace with actual offset
-    ORIGINAL_FUNCTIONS.internal_cleanup_fn =
-        proxy::resolve_internal_function(CLEANUP_FN_OFFSET);
+    ORIGINAL_FUNCTIONS.internal_cleanup_fn = resolve_known(base, &offsets::CLEANUP_FN);
 
-    log::info!("[detours] Detours initialized successfully");
+    tracing::info!("[detours] Detours initialized successfully");
     Ok(())
 }
 
+/// Resolve an entry of the offset database, logging failures
+unsafe fn resolve_known<F>(base: *const u8, entry: &offsets::KnownOffset) -> Option<F> {
+    match offsets::resolve(base, entry) {
+        Ok(offset) => proxy::resolve_internal_function(offset),
+        Err(e) => {
+            tracing::warn!("[detours] Failed to resolve {}: {}", entry.name, e);
+            None
+        }
+    }
+}
+
+/// Install an IAT hook on the original DLL, logging failures
+#[cfg(feature = "spoof")]
+unsafe fn install_api_hook(base: *const u8, function: &str, detour: usize) -> Option<usize> {
+    match hooks::install_iat_hook(function, base, function, detour) {
+        Ok(original) => Some(original),
+        Err(e) => {
+            hook_failed(function, &e);
+            None
+        }
+    }
+}
+
+fn hook_failed(function: &str, error: &str) {
+    status::record_error(ErrorKind::Detour);
+    tracing::warn!("[detours] Failed to hook {}: {}", function, error);
+}
+
 /// Call an original internal function if it was resolved
 pub unsafe fn call_original_init() -> Result<(), String> {
     if let Some(init_fn) = ORIGINAL_FUNCTIONS.internal_init_fn {
-        log::debug!("[detours] Calling original init function");
+        tracing::debug!("[detours] Calling original init function");
         let result = init_fn();
         if result == 0 {
+            status::record_error(ErrorKind::Detour);
             return Err("Original init function failed".to_string());
         }
         Ok(())
@@ -216,21 +562,6 @@ pub unsafe fn call_original_init() -> Result<(), String> {
 // Utility Functions
 // ============================================================================
 
-/// Convert a wide string pointer to a Rust String
-unsafe fn wstr_to_string(ptr: LPCWSTR) -> String {
-    if ptr.is_null() {
-        return String::new();
-    }
-
-    let mut len = 0;
-    while *ptr.offset(len) != 0 {
-        len += 1;
-    }
-
-    let slice = std::slice::from_raw_parts(ptr, len as usize);
-    String::from_utf16_lossy(slice)
-}
-
 /// Convert an ANSI string pointer to a Rust String
 unsafe fn str_to_string(ptr: LPCSTR) -> String {
     if ptr.is_null() {
@@ -0,0 +1,51 @@
+/// Copy of reflex_original.dll embedded in the proxy
+///
+/// Built with the `embedded-original` feature, the proxy carries the file
+/// named by `REFLEX_EMBEDDED_ORIGINAL` at build time:
+///
+/// ```bash
+/// REFLEX_EMBEDDED_ORIGINAL=C:\\rig\\reflex_original.dll cargo build --release --features embedded-original
+/// ```
+///
+/// It is only used when no reflex_original.dll exists next to the game.
+/// With `[loader] manual_map = true` it is mapped straight from memory;
+/// otherwise it is extracted once to the temp directory, under a name
+/// derived from its contents, and loaded from there.
+
+use crate::proxy_impl::{manual_map, os};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+static ORIGINAL: &[u8] = include_bytes!(env!("REFLEX_EMBEDDED_ORIGINAL"));
+
+/// Load the embedded original and return its base address
+///
+/// `flags` are the `LOAD_LIBRARY_SEARCH_*` flags for it and its imports;
+/// the DLL load directory is the temp directory.
+pub unsafe fn load(manual_map: bool, flags: u32) -> Result<usize, String> {
+    if manual_map {
+        return manual_map::load_image(ORIGINAL, "embedded reflex_original.dll", flags);
+    }
+
+    let path = extract()?;
+    tracing::info!("[embedded] Extracted reflex_original.dll to {}", path.display());
+    os::system().load_library(&path.to_string_lossy(), flags)
+}
+
+/// Write the embedded copy to the temp directory unless already there
+///
+/// A file of that name with other contents, e.g. truncated by a crash
+/// mid-write, is rewritten.
+fn extract() -> Result<PathBuf, String> {
+    let mut hasher = DefaultHasher::new();
+    ORIGINAL.hash(&mut hasher);
+    let path = std::env::temp_dir().join(format!("reflex_original_{:016x}.dll", hasher.finish()));
+
+    let current = fs::read(&path).is_ok_and(|data| data == ORIGINAL);
+    if !current {
+        fs::write(&path, ORIGINAL).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(path)
+}
@@ -0,0 +1,510 @@
+/// One-shot report of the environment the proxy was loaded into
+///
+/// Bug reports need to say which game build, Windows build, driver and
+/// original DLL they happened with. Once the original's DllMain succeeded,
+/// a background thread writes `path` as JSON with
+/// - the proxy's version, build id and path
+/// - the process's path, command line and id
+/// - the Windows product name, release and build
+/// - each display adapter and its driver version
+/// - the original DLL's path, base address, SHA-256 and, with
+///   `[signature]` enabled, its signature (signature.rs)
+/// - every loaded module with its base, size and file version
+///
+/// ```toml
+/// [environment]
+/// enabled = true                       # default
+/// path = "reflex_environment.json"
+/// ```
+///
+/// Launcher credentials on the command line (`SECRET_ARGUMENTS`, e.g.
+/// Epic's -AUTH_PASSWORD exchange code) are replaced by `<removed>`, and
+/// with `[logging.redact]` enabled every string of the report is redacted
+/// like the log (redact.rs).
+///
+/// `launch` describes how the game was started, for the trace metadata
+/// (trace.rs, so only with the `trace` feature): the working directory,
+/// the storefront that launched it and the variables named in `[trace]
+/// environment`.
+
+use crate::proxy_impl::redact;
+#[cfg(feature = "trace")]
+use crate::proxy_impl::rules::glob_match;
+use crate::proxy_impl::signature::Signature;
+use serde::Serialize;
+#[cfg(feature = "trace")]
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub proxy: ProxyInfo,
+    pub process: ProcessInfo,
+    pub os: OsInfo,
+    pub gpus: Vec<GpuInfo>,
+    pub original: Option<OriginalInfo>,
+    pub modules: Vec<ModuleInfo>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ProxyInfo {
+    pub version: &'static str,
+    pub build_id: &'static str,
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ProcessInfo {
+    pub path: Option<String>,
+    pub command_line: String,
+    pub pid: u32,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct OsInfo {
+    /// e.g. "Windows 10 Pro"; Windows 11 still calls itself 10 here
+    pub product: Option<String>,
+    /// e.g. "23H2"
+    pub release: Option<String>,
+    /// e.g. "22631.3447"
+    pub build: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct GpuInfo {
+    pub name: String,
+    pub provider: Option<String>,
+    pub driver_version: Option<String>,
+    /// The driver version as NVIDIA numbers it, e.g. "551.86"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nvidia_version: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct OriginalInfo {
+    pub path: Option<String>,
+    pub base: String,
+    pub sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ModuleInfo {
+    pub name: String,
+    pub path: String,
+    pub base: String,
+    pub size: u32,
+    pub version: Option<String>,
+}
+
+/// "a.b.c.d" from a VS_FIXEDFILEINFO, which winapi does not declare
+pub fn file_version(info: &[u8]) -> Option<String> {
+    let dword = |offset: usize| Some(u32::from_le_bytes(info.get(offset..offset + 4)?.try_into().ok()?));
+    if dword(0)? != 0xfeef04bd {
+        return None;
+    }
+    let (ms, ls) = (dword(8)?, dword(12)?);
+    Some(format!("{}.{}.{}.{}", ms >> 16, ms & 0xffff, ls >> 16, ls & 0xffff))
+}
+
+/// "22631.3447" from CurrentBuildNumber and UBR (update build revision)
+pub fn os_build(build: Option<String>, revision: Option<u32>) -> Option<String> {
+    match (build, revision) {
+        (Some(build), Some(revision)) => Some(format!("{}.{}", build, revision)),
+        (build, _) => build,
+    }
+}
+
+/// Arguments the Epic Games Launcher adds to the command line, without
+/// -AUTH_PASSWORD and -epicusername
+#[cfg(feature = "trace")]
+const EPIC_ARGUMENTS: &[&str] = &[
+    "-EpicPortal",
+    "-epicapp",
+    "-epicenv",
+    "-epiclocale",
+    "-epicsandboxid",
+    "-epicdeploymentid",
+];
+
+/// Command line arguments carrying launcher credentials or account names
+const SECRET_ARGUMENTS: &[&str] = &["-AUTH_PASSWORD", "-AUTH_LOGIN", "-epicusername", "-epicuserid"];
+
+/// `command_line` with the values of `SECRET_ARGUMENTS` replaced, given as
+/// `-name=value` or `-name value`
+pub fn strip_secrets(command_line: &str) -> String {
+    let is_secret = |name: &str| SECRET_ARGUMENTS.iter().any(|secret| secret.eq_ignore_ascii_case(name));
+    let mut value_follows = false;
+    arguments(command_line)
+        .into_iter()
+        .map(|token| {
+            if std::mem::take(&mut value_follows) && !token.is_empty() {
+                return "<removed>".to_string();
+            }
+            let argument = token.trim_matches('"');
+            match argument.split_once('=') {
+                Some((name, _)) if is_secret(name) => format!("{}=<removed>", name),
+                None if is_secret(argument) => {
+                    value_follows = true;
+                    token.to_string()
+                }
+                _ => token.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The command line split at spaces outside quotes, keeping the quotes so
+/// the pieces join back into the original
+fn arguments(command_line: &str) -> Vec<&str> {
+    let mut arguments = Vec::new();
+    let (mut start, mut quoted) = (0, false);
+    for (i, c) in command_line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ' ' if !quoted => {
+                arguments.push(&command_line[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    arguments.push(&command_line[start..]);
+    arguments
+}
+
+/// The report as JSON, redacted like the log
+pub fn to_json(report: &Report) -> Result<String, String> {
+    let mut value = serde_json::to_value(report).map_err(|e| e.to_string())?;
+    redact::value(&mut value);
+    serde_json::to_string_pretty(&value).map_err(|e| e.to_string())
+}
+
+/// How the process was started
+#[cfg(feature = "trace")]
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Launch {
+    pub working_directory: Option<String>,
+    /// "steam" or "epic", from the variables and arguments they add
+    pub launcher: Option<&'static str>,
+    /// The launcher's own arguments, e.g. "-epicapp=Sugar"
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub launcher_arguments: Vec<String>,
+    /// Variables matching the configured patterns
+    pub variables: BTreeMap<String, String>,
+}
+
+/// Pick the variables matching `patterns` (globs, ignoring case) and
+/// recognize the launcher
+#[cfg(feature = "trace")]
+pub fn launch(
+    variables: impl IntoIterator<Item = (String, String)>,
+    patterns: &[String],
+    command_line: &str,
+    working_directory: Option<String>,
+) -> Launch {
+    let mut steam = false;
+    let mut selected = BTreeMap::new();
+    for (name, value) in variables {
+        steam |= ["SteamAppId", "SteamGameId", "SteamClientLaunch"]
+            .iter()
+            .any(|marker| marker.eq_ignore_ascii_case(&name));
+        if patterns.iter().any(|pattern| glob_match(pattern, &name)) {
+            selected.insert(name, value);
+        }
+    }
+
+    let launcher_arguments: Vec<String> = command_line
+        .split_whitespace()
+        .map(|argument| argument.trim_matches('"'))
+        .filter(|argument| {
+            let name = argument.split('=').next().unwrap_or_default();
+            EPIC_ARGUMENTS.iter().any(|epic| epic.eq_ignore_ascii_case(name))
+        })
+        .map(str::to_string)
+        .collect();
+
+    let launcher = if !launcher_arguments.is_empty() {
+        Some("epic")
+    } else if steam {
+        Some("steam")
+    } else {
+        None
+    };
+    Launch {
+        working_directory,
+        launcher,
+        launcher_arguments,
+        variables: selected,
+    }
+}
+
+#[cfg(all(windows, feature = "trace"))]
+pub use windows::current_launch;
+#[cfg(windows)]
+pub use windows::write;
+
+#[cfg(windows)]
+mod windows {
+    use super::{
+        file_version, os_build, strip_secrets, to_json, GpuInfo, ModuleInfo, OriginalInfo, OsInfo, ProcessInfo,
+        ProxyInfo, Report,
+    };
+    #[cfg(feature = "trace")]
+    use super::{launch, Launch};
+    use crate::proxy;
+    use crate::proxy_impl::config::EnvironmentConfig;
+    use crate::proxy_impl::{gpu_identity, hash, locate, signature};
+    use crate::proxy_impl::wide::{from_wide_ptr, to_wide};
+    use std::fs;
+    use winapi::ctypes::c_void;
+    use winapi::shared::minwindef::{DWORD, FALSE, HKEY};
+    use winapi::shared::winerror::ERROR_SUCCESS;
+    use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+    use winapi::um::processenv::GetCommandLineW;
+    use winapi::um::processthreadsapi::GetCurrentProcessId;
+    use winapi::um::tlhelp32::{CreateToolhelp32Snapshot, Module32FirstW, Module32NextW, MODULEENTRY32W, TH32CS_SNAPMODULE};
+    use winapi::um::winreg::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD};
+    use winapi::um::winver::{GetFileVersionInfoSizeW, GetFileVersionInfoW, VerQueryValueW};
+
+    const CURRENT_VERSION: &str = r"SOFTWARE\Microsoft\Windows NT\CurrentVersion";
+    /// Device setup class of display adapters
+    const DISPLAY_CLASS: &str = r"SYSTEM\CurrentControlSet\Control\Class\{4d36e968-e325-11ce-bfc1-08002be10318}";
+
+    /// `launch` for this process
+    #[cfg(feature = "trace")]
+    pub fn current_launch(patterns: &[String]) -> Launch {
+        let working_directory = std::env::current_dir().ok().map(|p| p.display().to_string());
+        let variables = std::env::vars_os()
+            .map(|(name, value)| (name.to_string_lossy().into_owned(), value.to_string_lossy().into_owned()));
+        launch(variables, patterns, &unsafe { command_line() }, working_directory)
+    }
+
+    /// Write the report from a background thread; the module list and file
+    /// versions are not read under the loader lock
+    pub fn write(config: &EnvironmentConfig) {
+        let path = config.path.clone();
+        let spawned = std::thread::Builder::new().name("reflex-environment".to_string()).spawn(move || {
+            let report = unsafe { collect() };
+            let written = to_json(&report).and_then(|json| fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path, e)));
+            match written {
+                Ok(()) => tracing::info!(
+                    "[environment] Wrote {} ({} module(s), {} adapter(s))",
+                    path,
+                    report.modules.len(),
+                    report.gpus.len()
+                ),
+                Err(e) => tracing::warn!("[environment] {}", e),
+            }
+        });
+        if let Err(e) = spawned {
+            tracing::warn!("[environment] Failed to start the report thread: {}", e);
+        }
+    }
+
+    unsafe fn collect() -> Report {
+        Report {
+            proxy: ProxyInfo {
+                version: env!("CARGO_PKG_VERSION"),
+                build_id: env!("REFLEX_BUILD_ID"),
+                path: proxy::get_proxy_module_path().map(|p| p.display().to_string()),
+            },
+            process: ProcessInfo {
+                path: std::env::current_exe().ok().map(|p| p.display().to_string()),
+                command_line: strip_secrets(&command_line()),
+                pid: GetCurrentProcessId(),
+            },
+            os: OsInfo {
+                product: locate::read_string(HKEY_LOCAL_MACHINE, CURRENT_VERSION, "ProductName"),
+                release: locate::read_string(HKEY_LOCAL_MACHINE, CURRENT_VERSION, "DisplayVersion"),
+                build: os_build(
+                    locate::read_string(HKEY_LOCAL_MACHINE, CURRENT_VERSION, "CurrentBuildNumber"),
+                    read_dword(HKEY_LOCAL_MACHINE, CURRENT_VERSION, "UBR"),
+                ),
+            },
+            gpus: gpus(),
+            original: original(),
+            modules: modules(),
+        }
+    }
+
+    unsafe fn command_line() -> String {
+        from_wide_ptr(GetCommandLineW()).unwrap_or_default()
+    }
+
+    unsafe fn read_dword(root: HKEY, key: &str, value: &str) -> Option<u32> {
+        let (key, value) = (to_wide(key), to_wide(value));
+        let mut data: DWORD = 0;
+        let mut size = std::mem::size_of::<DWORD>() as DWORD;
+        let result = RegGetValueW(
+            root,
+            key.as_ptr(),
+            value.as_ptr(),
+            RRF_RT_REG_DWORD,
+            std::ptr::null_mut(),
+            &mut data as *mut DWORD as *mut c_void,
+            &mut size,
+        );
+        (result == ERROR_SUCCESS as i32).then_some(data)
+    }
+
+    /// Display adapters installed on the machine, attached or not
+    unsafe fn gpus() -> Vec<GpuInfo> {
+        locate::subkeys(HKEY_LOCAL_MACHINE, DISPLAY_CLASS)
+            .into_iter()
+            .filter_map(|subkey| {
+                let key = format!("{}\\{}", DISPLAY_CLASS, subkey);
+                let name = locate::read_string(HKEY_LOCAL_MACHINE, &key, "DriverDesc")?;
+                let driver_version = locate::read_string(HKEY_LOCAL_MACHINE, &key, "DriverVersion");
+                Some(GpuInfo {
+                    name,
+                    provider: locate::read_string(HKEY_LOCAL_MACHINE, &key, "ProviderName"),
+                    nvidia_version: driver_version.as_deref().and_then(gpu_identity::nvidia_driver_version),
+                    driver_version,
+                })
+            })
+            .collect()
+    }
+
+    unsafe fn original() -> Option<OriginalInfo> {
+        let base = proxy::get_original_dll_base();
+        if base.is_null() {
+            return None;
+        }
+        let path = proxy::get_module_path(base);
+        let sha256 = path.as_deref().and_then(|path| match hash::sha256_file(path) {
+            Ok(digest) => Some(digest),
+            Err(e) => {
+                tracing::warn!("[environment] {}", e);
+                None
+            }
+        });
+        Some(OriginalInfo {
+            path: path.map(|p| p.display().to_string()),
+            base: format!("{:p}", base),
+            sha256,
+            signature: signature::result().cloned(),
+        })
+    }
+
+    /// Modules in the loader's list, in load order
+    unsafe fn modules() -> Vec<ModuleInfo> {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPMODULE, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Vec::new();
+        }
+
+        let mut modules = Vec::new();
+        let mut entry: MODULEENTRY32W = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<MODULEENTRY32W>() as u32;
+
+        let mut more = Module32FirstW(snapshot, &mut entry);
+        while more != FALSE {
+            let path = from_wide_ptr(entry.szExePath.as_ptr()).unwrap_or_default();
+            modules.push(ModuleInfo {
+                name: from_wide_ptr(entry.szModule.as_ptr()).unwrap_or_default(),
+                version: version_of(&path),
+                path,
+                base: format!("{:p}", entry.modBaseAddr),
+                size: entry.modBaseSize,
+            });
+            more = Module32NextW(snapshot, &mut entry);
+        }
+        CloseHandle(snapshot);
+        modules
+    }
+
+    /// The file version of a DLL or executable, None without a version
+    /// resource
+    unsafe fn version_of(path: &str) -> Option<String> {
+        let path = to_wide(path);
+        let mut handle = 0;
+        let size = GetFileVersionInfoSizeW(path.as_ptr(), &mut handle);
+        if size == 0 {
+            return None;
+        }
+        let mut data = vec![0u8; size as usize];
+        if GetFileVersionInfoW(path.as_ptr(), 0, size, data.as_mut_ptr() as *mut c_void) == FALSE {
+            return None;
+        }
+        let mut info: *mut c_void = std::ptr::null_mut();
+        let mut len = 0;
+        if VerQueryValueW(data.as_ptr() as *const c_void, to_wide("\\").as_ptr(), &mut info, &mut len) == FALSE
+            || info.is_null()
+        {
+            return None;
+        }
+        file_version(std::slice::from_raw_parts(info as *const u8, len as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "trace")]
+    use crate::proxy_impl::config::TraceConfig;
+
+    #[test]
+    fn fixed_file_info_is_read() {
+        let mut info = vec![0u8; 52];
+        info[0..4].copy_from_slice(&0xfeef04bdu32.to_le_bytes());
+        info[8..12].copy_from_slice(&(10u32 << 16).to_le_bytes());
+        info[12..16].copy_from_slice(&((22621 << 16) | 3447u32).to_le_bytes());
+        assert_eq!(file_version(&info).as_deref(), Some("10.0.22621.3447"));
+
+        assert_eq!(file_version(&info[..12]), None);
+        info[0] = 0;
+        assert_eq!(file_version(&info), None);
+    }
+
+    #[test]
+    fn launcher_credentials_are_removed() {
+        assert_eq!(
+            strip_secrets(r#""C:\Games\game.exe" -AUTH_LOGIN=unused -AUTH_PASSWORD=0123abcd -AUTH_TYPE=exchangecode -epicapp=Sugar"#),
+            r#""C:\Games\game.exe" -AUTH_LOGIN=<removed> -AUTH_PASSWORD=<removed> -AUTH_TYPE=exchangecode -epicapp=Sugar"#
+        );
+        assert_eq!(
+            strip_secrets(r#"game.exe "-epicusername=Some One" -auth_password 0123abcd -windowed"#),
+            r#"game.exe -epicusername=<removed> -auth_password <removed> -windowed"#
+        );
+        assert_eq!(strip_secrets("game.exe -windowed"), "game.exe -windowed");
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn launches_are_recognized() {
+        let patterns = TraceConfig::default().environment;
+        let variables = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect()
+        };
+
+        let steam = launch(
+            variables(&[("SteamAppId", "1091500"), ("SteamUser", "someone"), ("DXVK_HUD", "fps"), ("PATH", "C:\\")]),
+            &patterns,
+            r#""C:\Games\game.exe" -windowed"#,
+            Some(r"C:\Games".to_string()),
+        );
+        assert_eq!(steam.launcher, Some("steam"));
+        assert_eq!(steam.variables.keys().collect::<Vec<_>>(), ["DXVK_HUD", "SteamAppId"]);
+        assert_eq!(steam.working_directory.as_deref(), Some(r"C:\Games"));
+
+        let epic = launch(
+            Vec::new(),
+            &patterns,
+            "game.exe -AUTH_LOGIN=unused -AUTH_PASSWORD=secret -AUTH_TYPE=exchangecode -epicapp=Sugar -epicenv=Prod -EpicPortal -epicusername=someone",
+            None,
+        );
+        assert_eq!(epic.launcher, Some("epic"));
+        assert_eq!(epic.launcher_arguments, ["-epicapp=Sugar", "-epicenv=Prod", "-EpicPortal"]);
+
+        assert_eq!(launch(variables(&[("PATH", "C:\\")]), &patterns, "game.exe", None), Launch::default());
+    }
+
+    #[test]
+    fn builds_include_the_revision() {
+        assert_eq!(os_build(Some("22631".to_string()), Some(3447)).as_deref(), Some("22631.3447"));
+        assert_eq!(os_build(Some("19045".to_string()), None).as_deref(), Some("19045"));
+        assert_eq!(os_build(None, Some(3447)), None);
+    }
+}
@@ -0,0 +1,164 @@
+/// First-chance exceptions raised inside the original DLL
+///
+/// A vectored exception handler sees every exception before the frame-based
+/// handlers of the code that raised it, so errors the original catches and
+/// swallows (access violations probed in `__try` blocks, C++ throws) show up
+/// in the log:
+///
+/// ```text
+/// [exceptions] 0xc0000005 at reflex_original.dll+0x1a2b3c on thread 4312 (read of 0x0)
+/// [exceptions] 0xe06d7363 at 0x7ffb1c2e4f69 raised by reflex_original.dll+0x8f10 on thread 4312
+/// ```
+///
+/// Exceptions raised with RaiseException (C++ `throw`) have their address in
+/// KernelBase; they are attributed to the nearest frame of the original on
+/// the stack. The handler always continues the search, so how the original
+/// handles its exceptions is unchanged.
+///
+/// ```toml
+/// [exceptions]
+/// enabled = true
+/// max_per_site = 5
+/// ```
+///
+/// Each exception code and address is logged `max_per_site` times, later
+/// ones are counted; the totals are logged on detach. The events are
+/// warnings, so `[logging] level = "warn"` or a `exceptions = "warn"`
+/// category is needed to see them.
+
+use crate::proxy;
+use crate::proxy_impl::config::ExceptionsConfig;
+use once_cell::sync::Lazy;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use winapi::shared::ntdef::LONG;
+use winapi::um::errhandlingapi::{AddVectoredExceptionHandler, RemoveVectoredExceptionHandler};
+use winapi::um::minwinbase::EXCEPTION_ACCESS_VIOLATION;
+use winapi::um::processthreadsapi::GetCurrentThreadId;
+use winapi::um::winnt::{RtlCaptureStackBackTrace, EXCEPTION_POINTERS, EXCEPTION_RECORD};
+use winapi::vc::excpt::EXCEPTION_CONTINUE_SEARCH;
+
+/// Frames searched for a caller inside the original; the first few belong
+/// to the handler and the exception dispatcher
+const CALLER_FRAMES: usize = 32;
+
+/// Handle returned by AddVectoredExceptionHandler, 0 when not installed
+static HANDLER: AtomicUsize = AtomicUsize::new(0);
+static MAX_PER_SITE: AtomicU32 = AtomicU32::new(0);
+/// Occurrences by exception code and RVA in the original
+static SITES: Lazy<Mutex<HashMap<(u32, usize), u64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+thread_local! {
+    /// Set while the handler runs, so an exception inside it is not observed
+    static IN_HANDLER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Install the handler; the original must be loaded
+pub unsafe fn install(config: &ExceptionsConfig) {
+    if HANDLER.load(Ordering::SeqCst) != 0 {
+        return;
+    }
+    MAX_PER_SITE.store(config.max_per_site, Ordering::SeqCst);
+
+    // First in the chain, so the original's own vectored handlers cannot
+    // hide an exception from it
+    let handle = AddVectoredExceptionHandler(1, Some(handler));
+    if handle.is_null() {
+        tracing::error!("[exceptions] Failed to install the vectored exception handler");
+        return;
+    }
+    HANDLER.store(handle as usize, Ordering::SeqCst);
+    tracing::info!("[exceptions] Logging first-chance exceptions inside reflex_original.dll");
+}
+
+/// Remove the handler and log how often each site raised
+pub unsafe fn remove() {
+    let handle = HANDLER.swap(0, Ordering::SeqCst);
+    if handle == 0 {
+        return;
+    }
+    RemoveVectoredExceptionHandler(handle as *mut _);
+
+    let mut sites: Vec<_> = SITES.lock().unwrap().iter().map(|(&site, &count)| (site, count)).collect();
+    sites.sort_unstable();
+    for ((code, rva), count) in sites {
+        tracing::info!("[exceptions] 0x{:08x} at reflex_original.dll+0x{:x}: {} time(s)", code, rva, count);
+    }
+}
+
+unsafe extern "system" fn handler(info: *mut EXCEPTION_POINTERS) -> LONG {
+    if info.is_null() || (*info).ExceptionRecord.is_null() {
+        return EXCEPTION_CONTINUE_SEARCH;
+    }
+
+    if !IN_HANDLER.with(|flag| flag.replace(true)) {
+        observe(&*(*info).ExceptionRecord);
+        IN_HANDLER.with(|flag| flag.set(false));
+    }
+    EXCEPTION_CONTINUE_SEARCH
+}
+
+unsafe fn observe(record: &EXCEPTION_RECORD) {
+    let address = record.ExceptionAddress as usize;
+    let (site, raised_at) = if proxy::is_in_original(address) {
+        (address, None)
+    } else {
+        match caller_in_original() {
+            Some(frame) => (frame, Some(address)),
+            None => return,
+        }
+    };
+
+    let code = record.ExceptionCode;
+    let rva = site - proxy::get_original_dll_base() as usize;
+    let count = {
+        let mut sites = SITES.lock().unwrap();
+        let count = sites.entry((code, rva)).or_insert(0);
+        *count += 1;
+        *count
+    };
+    let max = MAX_PER_SITE.load(Ordering::Relaxed) as u64;
+    if count > max {
+        return;
+    }
+
+    let location = match raised_at {
+        None => format!("reflex_original.dll+0x{:x}", rva),
+        Some(address) => format!("0x{:x} raised by reflex_original.dll+0x{:x}", address, rva),
+    };
+    let last = if count == max { ", further ones are only counted" } else { "" };
+    tracing::warn!(
+        "[exceptions] 0x{:08x} at {} on thread {}{}{}",
+        code,
+        location,
+        GetCurrentThreadId(),
+        access_detail(record),
+        last
+    );
+}
+
+/// Return address of the innermost frame inside the original
+unsafe fn caller_in_original() -> Option<usize> {
+    let mut frames = [std::ptr::null_mut(); CALLER_FRAMES];
+    let depth = RtlCaptureStackBackTrace(0, CALLER_FRAMES as u32, frames.as_mut_ptr(), std::ptr::null_mut());
+    frames[..depth as usize]
+        .iter()
+        .map(|&frame| frame as usize)
+        .find(|&frame| proxy::is_in_original(frame))
+}
+
+/// ` (read of 0x...)` for access violations
+fn access_detail(record: &EXCEPTION_RECORD) -> String {
+    if record.ExceptionCode != EXCEPTION_ACCESS_VIOLATION || record.NumberParameters < 2 {
+        return String::new();
+    }
+    let kind = match record.ExceptionInformation[0] {
+        0 => "read",
+        1 => "write",
+        8 => "execution",
+        _ => "access",
+    };
+    format!(" ({} of 0x{:x})", kind, record.ExceptionInformation[1])
+}
@@ -0,0 +1,115 @@
+/// Export addresses of the original DLL, resolved once
+///
+/// `proxy::get_original_export` asks here before GetProcAddress (or the
+/// export table walk of a manually mapped original). Names are resolved
+/// on first use, or all at once after loading with
+/// `[loader] preload_exports = true`, which moves the cost out of the
+/// first calls into attach. A name the original does not export is
+/// remembered too.
+///
+/// The cache is emptied when the original is released. Its statistics are
+/// part of the status export (`export_cache`).
+///
+/// Independent of the host platform.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ExportCacheStats {
+    /// Names cached, exported or not
+    pub entries: usize,
+    /// Of them, filled from the export table at load
+    pub preloaded: usize,
+    /// Lookups answered from the cache
+    pub hits: u64,
+    /// Lookups that had to resolve the name
+    pub misses: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ExportCache {
+    /// Address by name; `None` for a name that is not exported
+    entries: RwLock<HashMap<String, Option<usize>>>,
+    preloaded: AtomicUsize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ExportCache {
+    /// The address of `name`, from the cache or else from `resolve`
+    pub fn resolve(&self, name: &str, resolve: impl FnOnce(&str) -> Option<usize>) -> Option<usize> {
+        if let Some(&address) = self.entries.read().unwrap().get(name) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return address;
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let address = resolve(name);
+        self.entries.write().unwrap().insert(name.to_string(), address);
+        address
+    }
+
+    /// Fill the cache from an export table; returns the names added
+    pub fn preload(&self, exports: impl IntoIterator<Item = (String, usize)>) -> usize {
+        let mut entries = self.entries.write().unwrap();
+        let before = entries.len();
+        for (name, address) in exports {
+            entries.entry(name).or_insert(Some(address));
+        }
+        let added = entries.len() - before;
+        self.preloaded.fetch_add(added, Ordering::Relaxed);
+        added
+    }
+
+    /// Forget every name, for another original
+    pub fn clear(&self) {
+        self.entries.write().unwrap().clear();
+        self.preloaded.store(0, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> ExportCacheStats {
+        ExportCacheStats {
+            entries: self.entries.read().unwrap().len(),
+            preloaded: self.preloaded.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_resolved_once() {
+        let cache = ExportCache::default();
+        let mut resolved = Vec::new();
+        let mut lookup = |name: &str| {
+            resolved.push(name.to_string());
+            (name == "Init").then_some(0x1000)
+        };
+        assert_eq!(cache.resolve("Init", &mut lookup), Some(0x1000));
+        assert_eq!(cache.resolve("Init", &mut lookup), Some(0x1000));
+        assert_eq!(cache.resolve("Missing", &mut lookup), None);
+        assert_eq!(cache.resolve("Missing", &mut lookup), None);
+        assert_eq!(resolved, ["Init", "Missing"]);
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (2, 2, 2));
+    }
+
+    #[test]
+    fn preloaded_names_need_no_lookup() {
+        let cache = ExportCache::default();
+        assert_eq!(cache.preload([("Init".to_string(), 0x1000), ("Run".to_string(), 0x2000)]), 2);
+        assert_eq!(cache.resolve("Run", |_| panic!("Run was preloaded")), Some(0x2000));
+        assert_eq!(cache.stats().preloaded, 2);
+
+        cache.clear();
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(cache.resolve("Run", |_| Some(0x3000)), Some(0x3000));
+    }
+}
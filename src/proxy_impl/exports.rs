@@ -0,0 +1,122 @@
+/// Export table of reflex_original.dll the proxy was built against
+///
+/// The proxy resolves the original's exports at runtime (DllMain, recording,
+/// profiler), so a game update that renames, drops or renumbers exports
+/// goes unnoticed until something fails. `KNOWN_EXPORTS` records the export
+/// table of the analysed build; at attach `check_drift` compares it with the
+/// loaded original and logs what is missing, new or moved to another
+/// ordinal. Exports without a name are listed as `#ordinal`.
+
+#[cfg(windows)]
+use crate::proxy_impl::pe;
+
+/// An export of the analysed build
+#[derive(Debug, Clone, Copy)]
+pub struct KnownExport {
+    pub name: &'static str,
+    pub ordinal: u16,
+}
+
+/// Export table of the analysed build; reflex_selftest.txt lists the table
+/// of the installed original
+pub const KNOWN_EXPORTS: &[KnownExport] = &[
+    KnownExport { name: "DllMain", ordinal: 1 }, // Replace with the actual table
+];
+
+/// Differences between the known and the loaded export table
+#[derive(Debug, Default, PartialEq)]
+pub struct Drift {
+    /// Known exports the loaded original lacks
+    pub missing: Vec<String>,
+    /// Exports of the loaded original that are not known
+    pub added: Vec<String>,
+    /// Known exports at another ordinal: (name, known, loaded)
+    pub renumbered: Vec<(String, u16, u16)>,
+}
+
+impl Drift {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.added.is_empty() && self.renumbered.is_empty()
+    }
+}
+
+/// Compare `known` with the `(name, ordinal)` pairs of a loaded export table
+pub fn diff(known: &[KnownExport], loaded: &[(Option<&str>, u16)]) -> Drift {
+    let key = |name: Option<&str>, ordinal: u16| name.map_or_else(|| format!("#{}", ordinal), str::to_string);
+    let mut drift = Drift::default();
+
+    for export in known {
+        match loaded.iter().find(|(name, ordinal)| key(*name, *ordinal) == export.name) {
+            None => drift.missing.push(export.name.to_string()),
+            Some(&(Some(_), ordinal)) if ordinal != export.ordinal => {
+                drift.renumbered.push((export.name.to_string(), export.ordinal, ordinal))
+            }
+            Some(_) => {}
+        }
+    }
+    for &(name, ordinal) in loaded {
+        let name = key(name, ordinal);
+        if !known.iter().any(|e| e.name == name) {
+            drift.added.push(name);
+        }
+    }
+
+    drift
+}
+
+/// Log how the export table of the original at `base` differs from
+/// `KNOWN_EXPORTS`; returns the differences
+#[cfg(windows)]
+pub unsafe fn check_drift(base: *const u8) -> Drift {
+    let exports = pe::exports(base);
+    let loaded: Vec<(Option<&str>, u16)> = exports.iter().map(|e| (e.name.as_deref(), e.ordinal)).collect();
+    let drift = diff(KNOWN_EXPORTS, &loaded);
+
+    if drift.is_empty() {
+        tracing::info!("[exports] Export table matches the analysed build ({} exports)", loaded.len());
+        return drift;
+    }
+
+    tracing::warn!("[exports] The original's export table differs from the analysed build - it was probably updated");
+    if !drift.missing.is_empty() {
+        tracing::warn!("[exports] Missing: {}", drift.missing.join(", "));
+    }
+    if !drift.added.is_empty() {
+        tracing::warn!("[exports] New: {}", drift.added.join(", "));
+    }
+    for (name, known, loaded) in &drift.renumbered {
+        tracing::warn!("[exports] {} moved from ordinal {} to {}", name, known, loaded);
+    }
+    drift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KNOWN: &[KnownExport] = &[
+        KnownExport { name: "DllMain", ordinal: 1 },
+        KnownExport { name: "Init", ordinal: 2 },
+        KnownExport { name: "#3", ordinal: 3 },
+    ];
+
+    #[test]
+    fn names_are_unique() {
+        for (i, a) in KNOWN_EXPORTS.iter().enumerate() {
+            assert!(KNOWN_EXPORTS[i + 1..].iter().all(|b| b.name != a.name), "{}", a.name);
+        }
+    }
+
+    #[test]
+    fn unchanged_table_has_no_drift() {
+        assert!(diff(KNOWN, &[(Some("DllMain"), 1), (Some("Init"), 2), (None, 3)]).is_empty());
+    }
+
+    #[test]
+    fn drift_lists_missing_new_and_renumbered() {
+        let drift = diff(KNOWN, &[(Some("DllMain"), 1), (Some("Init"), 4), (Some("Shutdown"), 5), (None, 6)]);
+        assert_eq!(drift.missing, vec!["#3"]);
+        assert_eq!(drift.added, vec!["Shutdown", "#6"]);
+        assert_eq!(drift.renumbered, vec![("Init".to_string(), 2, 4)]);
+    }
+}
@@ -0,0 +1,169 @@
+/// Prometheus text exposition format
+///
+/// Building blocks for the metrics served at `/metrics` and written to the
+/// metrics file (metrics.rs): a lock-free latency histogram that hooked
+/// calls can update, and a writer for the text format (version 0.0.4).
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds of the latency buckets, in seconds (10 µs to 1 s)
+pub const LATENCY_BUCKETS: [f64; 11] = [
+    0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0,
+];
+
+/// Call latency distribution; `observe` never blocks
+pub struct Histogram {
+    /// Observations per bucket (not cumulative); the last one is +Inf
+    buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    count: AtomicU64,
+    sum_ns: AtomicU64,
+}
+
+/// Consistent-enough copy of a `Histogram` for rendering
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    /// Cumulative counts per bound of `LATENCY_BUCKETS`, then +Inf
+    pub cumulative: Vec<u64>,
+    pub count: u64,
+    pub sum_seconds: f64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum_ns: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let index = LATENCY_BUCKETS.partition_point(|&bound| bound < seconds);
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_ns.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let mut total = 0;
+        let cumulative = self
+            .buckets
+            .iter()
+            .map(|bucket| {
+                total += bucket.load(Ordering::Relaxed);
+                total
+            })
+            .collect();
+        HistogramSnapshot {
+            cumulative,
+            // The buckets, so +Inf and _count agree even during updates
+            count: total,
+            sum_seconds: self.sum_ns.load(Ordering::Relaxed) as f64 / 1e9,
+        }
+    }
+}
+
+/// Metric type of a family
+#[derive(Debug, Clone, Copy)]
+pub enum Type {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+/// Accumulates metric families as exposition text
+#[derive(Default)]
+pub struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    /// Start a family; its samples follow
+    pub fn family(&mut self, name: &str, kind: Type, help: &str) {
+        let kind = match kind {
+            Type::Counter => "counter",
+            Type::Gauge => "gauge",
+            Type::Histogram => "histogram",
+        };
+        let _ = writeln!(self.text, "# HELP {} {}", name, help.replace('\\', "\\\\").replace('\n', "\\n"));
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let _ = writeln!(self.text, "{}{} {}", name, format_labels(labels), value);
+    }
+
+    /// The `_bucket`, `_sum` and `_count` samples of a histogram
+    pub fn histogram(&mut self, name: &str, labels: &[(&str, &str)], histogram: &HistogramSnapshot) {
+        let bounds = LATENCY_BUCKETS.iter().map(|bound| bound.to_string()).chain(["+Inf".to_string()]);
+        for (bound, count) in bounds.zip(&histogram.cumulative) {
+            let mut bucket_labels = labels.to_vec();
+            bucket_labels.push(("le", &bound));
+            self.sample(&format!("{}_bucket", name), &bucket_labels, *count as f64);
+        }
+        self.sample(&format!("{}_sum", name), labels, histogram.sum_seconds);
+        self.sample(&format!("{}_count", name), labels, histogram.count as f64);
+    }
+
+    pub fn finish(self) -> String {
+        self.text
+    }
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(3));
+        histogram.observe(Duration::from_micros(10));
+        histogram.observe(Duration::from_millis(2));
+        histogram.observe(Duration::from_secs(3));
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.cumulative, vec![2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4]);
+        assert_eq!(snapshot.count, 4);
+        assert!((snapshot.sum_seconds - 3.002013).abs() < 1e-9);
+    }
+
+    #[test]
+    fn families_render_as_text_format() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(20));
+
+        let mut exposition = Exposition::default();
+        exposition.family("reflex_errors_total", Type::Counter, "Errors by kind");
+        exposition.sample("reflex_errors_total", &[("kind", "load")], 2.0);
+        exposition.family("reflex_export_call_duration_seconds", Type::Histogram, "Call latency");
+        exposition.histogram("reflex_export_call_duration_seconds", &[("export", "Init\"x")], &histogram.snapshot());
+        let text = exposition.finish();
+
+        assert!(text.starts_with(
+            "# HELP reflex_errors_total Errors by kind\n# TYPE reflex_errors_total counter\nreflex_errors_total{kind=\"load\"} 2\n"
+        ));
+        assert!(text.contains("reflex_export_call_duration_seconds_bucket{export=\"Init\\\"x\",le=\"0.00001\"} 0\n"));
+        assert!(text.contains("reflex_export_call_duration_seconds_bucket{export=\"Init\\\"x\",le=\"+Inf\"} 1\n"));
+        assert!(text.ends_with("reflex_export_call_duration_seconds_count{export=\"Init\\\"x\"} 1\n"));
+    }
+}
@@ -0,0 +1,67 @@
+/// Flamegraph exports for sampled profiles
+///
+/// Stacks are given root first, with the number of samples that hit them.
+/// - collapsed ("folded") stacks: `frame;frame;frame count` per line, the
+///   input format of flamegraph.pl, inferno and speedscope
+/// - speedscope JSON: a sampled profile for https://www.speedscope.app
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// One line per stack, `root;...;leaf count`, heaviest stacks first
+pub fn collapsed(stacks: &HashMap<Vec<String>, u64>) -> String {
+    let mut lines: Vec<_> = stacks.iter().collect();
+    lines.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+    let mut out = String::new();
+    for (frames, count) in lines {
+        // ';' separates frames and ' ' the count, so neither may appear in a name
+        let names: Vec<String> = frames.iter().map(|f| f.replace([';', ' '], "_")).collect();
+        out.push_str(&names.join(";"));
+        out.push_str(&format!(" {}\n", count));
+    }
+    out
+}
+
+/// Speedscope file with one sampled profile, each sample weighted by
+/// `interval_ms`
+pub fn speedscope(stacks: &HashMap<Vec<String>, u64>, interval_ms: u64, name: &str) -> Value {
+    let mut frame_index: HashMap<&str, usize> = HashMap::new();
+    let mut frames = Vec::new();
+    let mut samples = Vec::new();
+    let mut weights = Vec::new();
+
+    let mut sorted: Vec<_> = stacks.iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (stack, count) in sorted {
+        let indices: Vec<usize> = stack
+            .iter()
+            .map(|frame| {
+                *frame_index.entry(frame.as_str()).or_insert_with(|| {
+                    frames.push(json!({ "name": frame }));
+                    frames.len() - 1
+                })
+            })
+            .collect();
+        samples.push(indices);
+        weights.push(count * interval_ms);
+    }
+
+    let total: u64 = weights.iter().sum();
+    json!({
+        "$schema": "https://www.speedscope.app/file-format-schema.json",
+        "exporter": "reflex-proxy",
+        "name": name,
+        "shared": { "frames": frames },
+        "profiles": [{
+            "type": "sampled",
+            "name": name,
+            "unit": "milliseconds",
+            "startValue": 0,
+            "endValue": total,
+            "samples": samples,
+            "weights": weights,
+        }],
+    })
+}
@@ -0,0 +1,291 @@
+/// Present and input hooks: when the game reads input and shows a frame
+///
+/// Installed on demand (`install`), e.g. for synthetic latency markers
+/// (markers.rs); listeners registered with `subscribe` get each event on
+/// the thread that caused it:
+/// - Present: CreateDXGIFactory/1/2 are hooked when dxgi.dll loads; the
+///   first factory's CreateSwapChain and CreateSwapChainForHwnd are hooked
+///   in its vtable, and the first swap chain's Present and Present1 in
+///   its. `PresentStart` and `PresentEnd` surround the call; test
+///   presents (DXGI_PRESENT_TEST) are not reported.
+/// - Input: GetRawInputData, GetRawInputBuffer and GetCursorPos (user32)
+///   and XInputGetState (xinput1_4.dll) report `InputSample` before they
+///   read.
+///
+/// Vtable hooks affect every object of the class. Swap chains created
+/// before dxgi.dll was hooked, for a CoreWindow or for composition, or of
+/// a second swap chain class (D3D11 and D3D12 in one process) are not
+/// seen.
+
+use crate::proxy_impl::hooks::declare_hook;
+use crate::proxy_impl::{deferred, os};
+use std::cell::Cell;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use winapi::shared::guiddef::GUID;
+use winapi::shared::minwindef::{BOOL, DWORD, UINT};
+use winapi::shared::windef::{HWND, LPPOINT};
+use winapi::um::winnt::HRESULT;
+
+/// Listeners `subscribe` accepts
+const MAX_LISTENERS: usize = 4;
+/// `DXGI_PRESENT_TEST`
+const PRESENT_TEST: UINT = 0x1;
+
+// Vtable slots
+const FACTORY_CREATE_SWAP_CHAIN: usize = 10;
+const FACTORY2_CREATE_SWAP_CHAIN_FOR_HWND: usize = 15;
+const SWAP_CHAIN_PRESENT: usize = 8;
+const SWAP_CHAIN1_PRESENT1: usize = 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameEvent {
+    InputSample,
+    PresentStart,
+    PresentEnd,
+}
+
+/// `fn(FrameEvent)` per listener, 0 for a free slot
+static LISTENERS: [AtomicUsize; MAX_LISTENERS] = [const { AtomicUsize::new(0) }; MAX_LISTENERS];
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static FACTORY_VTABLE: AtomicUsize = AtomicUsize::new(0);
+static SWAP_CHAIN_VTABLE: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Inside a hooked Present, which may call the other one
+    static PRESENTING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Call `listener` for every event from now on
+pub fn subscribe(listener: fn(FrameEvent)) -> Result<(), String> {
+    LISTENERS
+        .iter()
+        .find(|slot| {
+            slot.compare_exchange(0, listener as usize, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        })
+        .map(|_| ())
+        .ok_or_else(|| format!("more than {} frame event listeners", MAX_LISTENERS))
+}
+
+fn emit(event: FrameEvent) {
+    for slot in &LISTENERS {
+        match slot.load(Ordering::Acquire) {
+            0 => {}
+            // SAFETY: `subscribe` stored a `fn(FrameEvent)`
+            listener => {
+                let listener = unsafe { std::mem::transmute::<usize, fn(FrameEvent)>(listener) };
+                listener(event)
+            }
+        }
+    }
+}
+
+/// Hook the factories (once dxgi.dll loads) and the input functions
+pub unsafe fn install() {
+    if INSTALLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    deferred::when_loaded("dxgi.dll", "CreateDXGIFactory", |base| unsafe {
+        let export = |name: &str| {
+            os::system()
+                .get_proc_address(base, name)
+                .ok_or_else(|| format!("{} is not exported", name))
+        };
+        CreateDXGIFactory::install_inline(export("CreateDXGIFactory")?)?;
+        CreateDXGIFactory1::install_inline(export("CreateDXGIFactory1")?)?;
+        // Windows 8.1 and later
+        if let Ok(target) = export("CreateDXGIFactory2") {
+            CreateDXGIFactory2::install_inline(target)?;
+        }
+        Ok(())
+    });
+
+    deferred::when_loaded("user32.dll", "input hooks", |base| unsafe {
+        let export = |name: &str| {
+            os::system()
+                .get_proc_address(base, name)
+                .ok_or_else(|| format!("{} is not exported", name))
+        };
+        GetRawInputData::install_inline(export("GetRawInputData")?)?;
+        GetRawInputBuffer::install_inline(export("GetRawInputBuffer")?)?;
+        GetCursorPos::install_inline(export("GetCursorPos")?)
+    });
+
+    deferred::when_loaded("xinput1_4.dll", "XInputGetState", |base| unsafe {
+        let target = os::system()
+            .get_proc_address(base, "XInputGetState")
+            .ok_or("XInputGetState is not exported")?;
+        XInputGetState::install_inline(target)
+    });
+}
+
+// ============================================================================
+// DXGI
+// ============================================================================
+
+declare_hook! {
+    fn CreateDXGIFactory(riid: *const GUID, factory: *mut *mut c_void) -> HRESULT {
+        let result = call_original(riid, factory);
+        if result >= 0 {
+            hook_factory(*factory);
+        }
+        result
+    }
+}
+
+declare_hook! {
+    fn CreateDXGIFactory1(riid: *const GUID, factory: *mut *mut c_void) -> HRESULT {
+        let result = call_original(riid, factory);
+        if result >= 0 {
+            hook_factory(*factory);
+        }
+        result
+    }
+}
+
+declare_hook! {
+    fn CreateDXGIFactory2(flags: UINT, riid: *const GUID, factory: *mut *mut c_void) -> HRESULT {
+        let result = call_original(flags, riid, factory);
+        if result >= 0 {
+            hook_factory(*factory);
+        }
+        result
+    }
+}
+
+declare_hook! {
+    fn IDXGIFactory_CreateSwapChain(
+        this: *mut c_void,
+        device: *mut c_void,
+        desc: *mut c_void,
+        swap_chain: *mut *mut c_void,
+    ) -> HRESULT {
+        let result = call_original(this, device, desc, swap_chain);
+        if result >= 0 {
+            hook_swap_chain(*swap_chain);
+        }
+        result
+    }
+}
+
+declare_hook! {
+    fn IDXGIFactory2_CreateSwapChainForHwnd(
+        this: *mut c_void,
+        device: *mut c_void,
+        window: HWND,
+        desc: *const c_void,
+        fullscreen_desc: *const c_void,
+        restrict_to_output: *mut c_void,
+        swap_chain: *mut *mut c_void,
+    ) -> HRESULT {
+        let result = call_original(this, device, window, desc, fullscreen_desc, restrict_to_output, swap_chain);
+        if result >= 0 {
+            hook_swap_chain(*swap_chain);
+        }
+        result
+    }
+}
+
+declare_hook! {
+    fn IDXGISwapChain_Present(this: *mut c_void, sync_interval: UINT, flags: UINT) -> HRESULT {
+        present(flags, || call_original(this, sync_interval, flags))
+    }
+}
+
+declare_hook! {
+    fn IDXGISwapChain1_Present1(
+        this: *mut c_void,
+        sync_interval: UINT,
+        flags: UINT,
+        parameters: *const c_void,
+    ) -> HRESULT {
+        present(flags, || call_original(this, sync_interval, flags, parameters))
+    }
+}
+
+/// Hook the swap chain creation of the first factory's class
+unsafe fn hook_factory(factory: *mut c_void) {
+    if factory.is_null() {
+        return;
+    }
+    let vtable = *(factory as *const usize);
+    if FACTORY_VTABLE.compare_exchange(0, vtable, Ordering::AcqRel, Ordering::Acquire).is_err() {
+        return;
+    }
+    let hooked = IDXGIFactory_CreateSwapChain::install_vtable(vtable, FACTORY_CREATE_SWAP_CHAIN).and_then(|_| {
+        IDXGIFactory2_CreateSwapChainForHwnd::install_vtable(vtable, FACTORY2_CREATE_SWAP_CHAIN_FOR_HWND)
+    });
+    if let Err(e) = hooked {
+        tracing::warn!("[frame_events] Failed to hook the DXGI factory: {}", e);
+    }
+}
+
+/// Hook Present of the first swap chain's class
+unsafe fn hook_swap_chain(swap_chain: *mut c_void) {
+    if swap_chain.is_null() {
+        return;
+    }
+    let vtable = *(swap_chain as *const usize);
+    match SWAP_CHAIN_VTABLE.compare_exchange(0, vtable, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => {}
+        Err(hooked) if hooked == vtable => return,
+        Err(_) => {
+            tracing::warn!("[frame_events] Swap chain of another class, its presents are not seen");
+            return;
+        }
+    }
+    let hooked = IDXGISwapChain_Present::install_vtable(vtable, SWAP_CHAIN_PRESENT)
+        .and_then(|_| IDXGISwapChain1_Present1::install_vtable(vtable, SWAP_CHAIN1_PRESENT1));
+    match hooked {
+        Ok(()) => tracing::info!("[frame_events] Hooked Present of swap chain {:p}", swap_chain),
+        Err(e) => tracing::warn!("[frame_events] Failed to hook Present: {}", e),
+    }
+}
+
+fn present(flags: UINT, call: impl FnOnce() -> HRESULT) -> HRESULT {
+    let nested = PRESENTING.with(|presenting| presenting.replace(true));
+    if nested || flags & PRESENT_TEST != 0 {
+        let result = call();
+        PRESENTING.with(|presenting| presenting.set(nested));
+        return result;
+    }
+    emit(FrameEvent::PresentStart);
+    let result = call();
+    emit(FrameEvent::PresentEnd);
+    PRESENTING.with(|presenting| presenting.set(false));
+    result
+}
+
+// ============================================================================
+// Input
+// ============================================================================
+
+declare_hook! {
+    fn GetRawInputData(input: *mut c_void, command: UINT, data: *mut c_void, size: *mut UINT, header_size: UINT) -> UINT {
+        emit(FrameEvent::InputSample);
+        call_original(input, command, data, size, header_size)
+    }
+}
+
+declare_hook! {
+    fn GetRawInputBuffer(data: *mut c_void, size: *mut UINT, header_size: UINT) -> UINT {
+        emit(FrameEvent::InputSample);
+        call_original(data, size, header_size)
+    }
+}
+
+declare_hook! {
+    fn GetCursorPos(point: LPPOINT) -> BOOL {
+        emit(FrameEvent::InputSample);
+        call_original(point)
+    }
+}
+
+declare_hook! {
+    fn XInputGetState(user_index: DWORD, state: *mut c_void) -> DWORD {
+        emit(FrameEvent::InputSample);
+        call_original(user_index, state)
+    }
+}
@@ -0,0 +1,198 @@
+/// Per-frame latency export
+///
+/// With `[nvapi.pacing] frames_path` set, every frame record of the pacing
+/// analysis (pacing.rs) is written as one JSON object per line, for
+/// notebooks and dashboards:
+///
+/// ```json
+/// {"schema_version":1,"frame":120,"marker_frame":4711,"simulation_start_ms":2001.2,"simulation_end_ms":2004.9,"render_submit_start_ms":2005.0,"render_submit_end_ms":2008.1,"present_start_ms":2008.3,"present_end_ms":2008.9,"input_sample_ms":null,"sleep_end_ms":2001.0,"sleep_ms":3.2,"frame_time_ms":16.7,"simulation_ms":3.7,"render_submit_ms":3.1,"present_ms":0.6,"sleep_to_present_ms":7.3,"simulation_to_present_ms":7.1,"input_to_present_ms":null,"end_to_end_ms":7.7,"missed":false}
+/// ```
+///
+/// Times are milliseconds since the session start (sleep_stats.rs); a
+/// field is `null` when its marker or sleep did not arrive. The fields
+/// keep their meaning within a `schema_version`; a change of meaning or a
+/// removal bumps it, new fields do not:
+/// - `frame`: number of the Present, from 1
+/// - `marker_frame`: frame id of the latency markers joined to it
+/// - `simulation_start_ms` .. `render_submit_end_ms`, `input_sample_ms`:
+///   when the markers of that name arrived
+/// - `present_start_ms`, `present_end_ms`: when the hooked Present was
+///   called and returned
+/// - `sleep_end_ms`, `sleep_ms`: when the NvAPI_D3D_Sleep before the
+///   Present returned and how long it blocked
+/// - `frame_time_ms`: since the previous Present started
+/// - `simulation_ms`, `render_submit_ms`, `present_ms`: length of each
+///   stage
+/// - `sleep_to_present_ms`, `simulation_to_present_ms`,
+///   `input_to_present_ms`: from the sleep, SIMULATION_START or
+///   INPUT_SAMPLE to the start of Present
+/// - `end_to_end_ms`: estimated latency, from the input sample (or else
+///   SIMULATION_START, or else the end of the sleep) to the return of
+///   Present; scan-out and the display are not included
+/// - `missed`: further than `threshold_ms` from the target frame time
+///
+/// Independent of the host platform.
+
+use crate::proxy_impl::markers::Marker;
+use crate::proxy_impl::pacing::FrameRecord;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Version of the line format
+pub const SCHEMA_VERSION: u32 = 1;
+
+static EXPORT: Mutex<Option<BufWriter<File>>> = Mutex::new(None);
+
+/// One line of the export
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FrameLatency {
+    pub schema_version: u32,
+    pub frame: u64,
+    pub marker_frame: Option<u64>,
+    pub simulation_start_ms: Option<f64>,
+    pub simulation_end_ms: Option<f64>,
+    pub render_submit_start_ms: Option<f64>,
+    pub render_submit_end_ms: Option<f64>,
+    pub present_start_ms: f64,
+    pub present_end_ms: f64,
+    pub input_sample_ms: Option<f64>,
+    pub sleep_end_ms: Option<f64>,
+    pub sleep_ms: Option<f64>,
+    pub frame_time_ms: Option<f64>,
+    pub simulation_ms: Option<f64>,
+    pub render_submit_ms: Option<f64>,
+    pub present_ms: f64,
+    pub sleep_to_present_ms: Option<f64>,
+    pub simulation_to_present_ms: Option<f64>,
+    pub input_to_present_ms: Option<f64>,
+    pub end_to_end_ms: Option<f64>,
+    pub missed: bool,
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// `to - from` in ms, if both are known and in that order
+fn span(from: Option<Duration>, to: Option<Duration>) -> Option<f64> {
+    to?.checked_sub(from?).map(ms)
+}
+
+impl FrameLatency {
+    pub fn new(record: &FrameRecord) -> Self {
+        let marker = |marker| record.markers.get(marker);
+        let present_start = Some(record.present_start);
+        let sleep_end = record.sleep.map(|(end, _)| end);
+        let input = marker(Marker::InputSample);
+        let origin = input.or(marker(Marker::SimulationStart)).or(sleep_end);
+        Self {
+            schema_version: SCHEMA_VERSION,
+            frame: record.frame,
+            marker_frame: record.marker_frame,
+            simulation_start_ms: marker(Marker::SimulationStart).map(ms),
+            simulation_end_ms: marker(Marker::SimulationEnd).map(ms),
+            render_submit_start_ms: marker(Marker::RenderSubmitStart).map(ms),
+            render_submit_end_ms: marker(Marker::RenderSubmitEnd).map(ms),
+            present_start_ms: ms(record.present_start),
+            present_end_ms: ms(record.present_end),
+            input_sample_ms: input.map(ms),
+            sleep_end_ms: sleep_end.map(ms),
+            sleep_ms: record.sleep.map(|(_, took)| ms(took)),
+            frame_time_ms: record.frame_time.map(ms),
+            simulation_ms: span(marker(Marker::SimulationStart), marker(Marker::SimulationEnd)),
+            render_submit_ms: span(marker(Marker::RenderSubmitStart), marker(Marker::RenderSubmitEnd)),
+            present_ms: ms(record.present_end.saturating_sub(record.present_start)),
+            sleep_to_present_ms: record.sleep_to_present().map(ms),
+            simulation_to_present_ms: span(marker(Marker::SimulationStart), present_start),
+            input_to_present_ms: span(input, present_start),
+            end_to_end_ms: span(origin, Some(record.present_end)),
+            missed: record.missed,
+        }
+    }
+}
+
+/// Start writing frames to `path`, which is created
+pub fn start(path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    *EXPORT.lock().unwrap() = Some(BufWriter::new(file));
+    Ok(())
+}
+
+/// Append the frame, if the export is running
+pub fn write(record: &FrameRecord) {
+    let mut export = EXPORT.lock().unwrap();
+    if let Some(writer) = export.as_mut() {
+        let written =
+            serde_json::to_writer(&mut *writer, &FrameLatency::new(record)).is_ok() && writer.write_all(b"\n").is_ok();
+        if !written {
+            tracing::warn!("[frame_latency] Failed to write the frame export, no longer writing it");
+            *export = None;
+        }
+    }
+}
+
+/// Stop the export and flush it
+pub fn finish() -> Result<(), String> {
+    match EXPORT.lock().unwrap().take() {
+        Some(mut writer) => writer.flush().map_err(|e| e.to_string()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy_impl::pacing::Analyzer;
+
+    fn at(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn breakdown_of_a_marked_frame() {
+        let mut analyzer = Analyzer::new(at(2));
+        analyzer.sleep(at(100), at(4));
+        analyzer.marker(9, Marker::SimulationStart, at(101));
+        analyzer.marker(9, Marker::InputSample, at(102));
+        analyzer.marker(9, Marker::SimulationEnd, at(104));
+        analyzer.marker(9, Marker::RenderSubmitStart, at(104));
+        analyzer.marker(9, Marker::RenderSubmitEnd, at(107));
+        analyzer.marker(9, Marker::PresentStart, at(108));
+        analyzer.present_start(at(108));
+        let frame = FrameLatency::new(&analyzer.present_end(at(110)).unwrap());
+
+        assert_eq!(frame.marker_frame, Some(9));
+        assert_eq!(frame.sleep_ms, Some(4.0));
+        assert_eq!(frame.simulation_ms, Some(3.0));
+        assert_eq!(frame.render_submit_ms, Some(3.0));
+        assert_eq!(frame.present_ms, 2.0);
+        assert_eq!(frame.sleep_to_present_ms, Some(8.0));
+        assert_eq!(frame.simulation_to_present_ms, Some(7.0));
+        assert_eq!(frame.input_to_present_ms, Some(6.0));
+        assert_eq!(frame.end_to_end_ms, Some(8.0));
+        assert_eq!(frame.frame_time_ms, None);
+
+        let line = serde_json::to_value(&frame).unwrap();
+        assert_eq!(line["schema_version"], SCHEMA_VERSION);
+        assert_eq!(line["simulation_start_ms"], 101.0);
+    }
+
+    #[test]
+    fn unmarked_frames_estimate_from_the_sleep() {
+        let mut analyzer = Analyzer::new(at(2));
+        analyzer.sleep(at(50), at(1));
+        analyzer.present_start(at(55));
+        let frame = FrameLatency::new(&analyzer.present_end(at(56)).unwrap());
+        assert_eq!(frame.marker_frame, None);
+        assert_eq!(frame.simulation_start_ms, None);
+        assert_eq!(frame.end_to_end_ms, Some(6.0));
+
+        analyzer.present_start(at(70));
+        let frame = FrameLatency::new(&analyzer.present_end(at(71)).unwrap());
+        assert_eq!(frame.end_to_end_ms, None);
+        assert_eq!(serde_json::to_value(&frame).unwrap()["sleep_ms"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,211 @@
+/// The GPU and driver identity presented to the original DLL
+///
+/// `[gpu_identity]` describes another adapter; every key that is set
+/// replaces what the hooks in gpu_spoof.rs return, in each of the forms the
+/// original may ask for it:
+/// - DXGI adapter descriptions (`AdapterDesc`): name, PCI ids, video memory
+/// - the display class registry values (`registry_value`): DriverDesc,
+///   DriverVersion as Windows shows it, MatchingDeviceId
+/// - NvAPI: full name, driver version, PCI ids, architecture
+///
+/// ```toml
+/// [gpu_identity]
+/// enabled = true
+/// name = "NVIDIA GeForce RTX 4090"
+/// vendor_id = 0x10de
+/// device_id = 0x2684
+/// driver_version = "551.86"
+/// architecture = 0x190                 # NV_GPU_ARCHITECTURE_AD100
+/// ```
+///
+/// Independent of the host platform.
+
+use crate::proxy_impl::config::GpuIdentityConfig;
+
+/// Prefix shared by `DXGI_ADAPTER_DESC`, `DXGI_ADAPTER_DESC1` and
+/// `DXGI_ADAPTER_DESC2`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AdapterDesc {
+    pub description: [u16; 128],
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub sub_sys_id: u32,
+    pub revision: u32,
+    pub dedicated_video_memory: usize,
+    pub dedicated_system_memory: usize,
+    pub shared_system_memory: usize,
+}
+
+/// NVIDIA driver version as NvAPI reports it: "551.86" is 55186
+pub fn nvapi_driver_version(version: &str) -> Result<u32, String> {
+    let invalid = || format!("driver version '{}' is not of the form 551.86", version);
+    let (major, minor) = version.split_once('.').ok_or_else(invalid)?;
+    if minor.len() != 2 {
+        return Err(invalid());
+    }
+    let major: u32 = major.parse().map_err(|_| invalid())?;
+    let minor: u32 = minor.parse().map_err(|_| invalid())?;
+    match major {
+        100..=999 => Ok(major * 100 + minor),
+        _ => Err(invalid()),
+    }
+}
+
+/// The same version as Windows shows it for the display driver: 55186 is
+/// "31.0.15.5186"
+pub fn windows_driver_version(nvapi_version: u32) -> String {
+    format!("31.0.{}.{:04}", 10 + nvapi_version / 10000, nvapi_version % 10000)
+}
+
+/// The inverse of windows_driver_version: "31.0.15.5186" is "551.86";
+/// None for versions not numbered the NVIDIA way
+pub fn nvidia_driver_version(windows_version: &str) -> Option<String> {
+    let parts: Vec<&str> = windows_version.split('.').collect();
+    let [_, _, high, low] = parts[..] else {
+        return None;
+    };
+    let high: u32 = high.parse().ok()?;
+    let low: u32 = low.parse().ok()?;
+    if !(10..=99).contains(&high) || low > 9999 {
+        return None;
+    }
+    let version = (high - 10) * 10000 + low;
+    Some(format!("{}.{:02}", version / 100, version % 100))
+}
+
+/// Check the configured identity before it is used
+pub fn validate(config: &GpuIdentityConfig) -> Result<(), String> {
+    if let Some(version) = &config.driver_version {
+        nvapi_driver_version(version)?;
+    }
+    if config.name.as_ref().is_some_and(|name| name.chars().count() >= 64) {
+        return Err("name is longer than NvAPI's 63 characters".to_string());
+    }
+    Ok(())
+}
+
+/// The spoofed string value of a display class registry value, if
+/// `[gpu_identity]` replaces it
+pub fn registry_value(config: &GpuIdentityConfig, value_name: &str) -> Option<String> {
+    match value_name.to_ascii_lowercase().as_str() {
+        "driverdesc" => config.name.clone(),
+        "driverversion" => config
+            .driver_version
+            .as_deref()
+            .and_then(|version| nvapi_driver_version(version).ok())
+            .map(windows_driver_version),
+        "matchingdeviceid" => Some(format!(
+            "pci\\ven_{:04x}&dev_{:04x}",
+            config.vendor_id?, config.device_id?
+        )),
+        _ => None,
+    }
+}
+
+/// Replace the configured fields of an adapter description; returns
+/// whether anything changed
+pub fn patch_adapter_desc(config: &GpuIdentityConfig, desc: &mut AdapterDesc) -> bool {
+    let before = *desc;
+    if let Some(name) = &config.name {
+        desc.description = [0; 128];
+        for (slot, unit) in desc.description.iter_mut().take(127).zip(name.encode_utf16()) {
+            *slot = unit;
+        }
+    }
+    desc.vendor_id = config.vendor_id.unwrap_or(desc.vendor_id);
+    desc.device_id = config.device_id.unwrap_or(desc.device_id);
+    desc.sub_sys_id = config.subsys_id.unwrap_or(desc.sub_sys_id);
+    desc.revision = config.revision.unwrap_or(desc.revision);
+    if let Some(mb) = config.video_memory_mb {
+        desc.dedicated_video_memory = (mb as usize).saturating_mul(1 << 20);
+    }
+    before.description != desc.description
+        || (before.vendor_id, before.device_id, before.sub_sys_id, before.revision)
+            != (desc.vendor_id, desc.device_id, desc.sub_sys_id, desc.revision)
+        || before.dedicated_video_memory != desc.dedicated_video_memory
+}
+
+/// Replace the configured fields of NvAPI_GPU_GetPCIIdentifiers' answer:
+/// device id (device << 16 | vendor), subsystem id and revision
+pub fn patch_pci_identifiers(config: &GpuIdentityConfig, device: &mut u32, subsys: &mut u32, revision: &mut u32) {
+    let vendor_id = config.vendor_id.unwrap_or(*device & 0xffff);
+    let device_id = config.device_id.unwrap_or(*device >> 16);
+    *device = (device_id << 16) | (vendor_id & 0xffff);
+    *subsys = config.subsys_id.unwrap_or(*subsys);
+    *revision = config.revision.unwrap_or(*revision);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rtx_4090() -> GpuIdentityConfig {
+        GpuIdentityConfig {
+            enabled: true,
+            name: Some("NVIDIA GeForce RTX 4090".to_string()),
+            vendor_id: Some(0x10de),
+            device_id: Some(0x2684),
+            driver_version: Some("551.86".to_string()),
+            video_memory_mb: Some(24564),
+            ..GpuIdentityConfig::default()
+        }
+    }
+
+    #[test]
+    fn driver_versions_convert() {
+        assert_eq!(nvapi_driver_version("551.86"), Ok(55186));
+        assert_eq!(nvapi_driver_version("460.09"), Ok(46009));
+        assert!(nvapi_driver_version("551.8").is_err());
+        assert!(nvapi_driver_version("31.0.15.5186").is_err());
+        assert_eq!(windows_driver_version(55186), "31.0.15.5186");
+        assert_eq!(windows_driver_version(46009), "31.0.14.6009");
+        assert_eq!(windows_driver_version(50006), "31.0.15.0006");
+        assert_eq!(nvidia_driver_version("31.0.15.5186").as_deref(), Some("551.86"));
+        assert_eq!(nvidia_driver_version("31.0.14.6009").as_deref(), Some("460.09"));
+        assert_eq!(nvidia_driver_version("31.0.101.5333"), None);
+        assert_eq!(nvidia_driver_version("551.86"), None);
+
+        assert!(validate(&rtx_4090()).is_ok());
+        let long = GpuIdentityConfig {
+            name: Some("x".repeat(64)),
+            ..GpuIdentityConfig::default()
+        };
+        assert!(validate(&long).is_err());
+    }
+
+    #[test]
+    fn registry_values_are_replaced() {
+        let config = rtx_4090();
+        assert_eq!(registry_value(&config, "DriverDesc").as_deref(), Some("NVIDIA GeForce RTX 4090"));
+        assert_eq!(registry_value(&config, "DriverVersion").as_deref(), Some("31.0.15.5186"));
+        assert_eq!(registry_value(&config, "MatchingDeviceId").as_deref(), Some("pci\\ven_10de&dev_2684"));
+        assert_eq!(registry_value(&config, "ProviderName"), None);
+        assert_eq!(registry_value(&GpuIdentityConfig::default(), "DriverDesc"), None);
+    }
+
+    #[test]
+    fn adapter_descriptions_keep_unset_fields() {
+        let mut desc = AdapterDesc {
+            description: [0; 128],
+            vendor_id: 0x1002,
+            device_id: 0x744c,
+            sub_sys_id: 0x1234,
+            revision: 0xc8,
+            dedicated_video_memory: 1 << 30,
+            dedicated_system_memory: 0,
+            shared_system_memory: 0,
+        };
+        assert!(patch_adapter_desc(&rtx_4090(), &mut desc));
+        assert_eq!(String::from_utf16_lossy(&desc.description[..23]), "NVIDIA GeForce RTX 4090");
+        assert_eq!(desc.description[23], 0);
+        assert_eq!((desc.vendor_id, desc.device_id), (0x10de, 0x2684));
+        assert_eq!((desc.sub_sys_id, desc.revision), (0x1234, 0xc8));
+        assert_eq!(desc.dedicated_video_memory, 24564 << 20);
+        assert!(!patch_adapter_desc(&rtx_4090(), &mut desc));
+
+        let (mut device, mut subsys, mut revision) = (0x744c_1002, 0x1234, 0xc8);
+        patch_pci_identifiers(&rtx_4090(), &mut device, &mut subsys, &mut revision);
+        assert_eq!((device, subsys, revision), (0x2684_10de, 0x1234, 0xc8));
+    }
+}
@@ -0,0 +1,339 @@
+/// GPU and driver identity hooks
+///
+/// With `[gpu_identity] enabled = true` the ways the original DLL learns
+/// which GPU and driver it runs on are answered with the configured
+/// identity (gpu_identity.rs):
+/// - DXGI: CreateDXGIFactory/1/2 as imported by reflex_original.dll; the
+///   factory's EnumAdapters and EnumAdapters1 are hooked in its vtable, and
+///   the first adapter's GetDesc, GetDesc1 and GetDesc2 in its
+/// - registry: DriverDesc, DriverVersion and MatchingDeviceId through the
+///   RegQueryValueExW detour (detours.rs), as a rules handler that runs
+///   before the config's rules
+/// - NvAPI: NvAPI_GPU_GetFullName, NvAPI_SYS_GetDriverAndBranchVersion,
+///   NvAPI_GPU_GetArchInfo and NvAPI_GPU_GetPCIIdentifiers, handed out by
+///   the nvapi_QueryInterface hook (nvapi.rs)
+///
+/// The registry needs `detours = true` and NvAPI `[nvapi] enabled = true`.
+/// Vtable and NvAPI hooks apply to the whole process, not only to the
+/// original; adapters enumerated through EnumAdapterByLuid or
+/// EnumAdapterByGpuPreference before EnumAdapters are not seen. Nothing
+/// is replaced during passthrough.
+
+use crate::proxy;
+use crate::proxy_impl::config::GpuIdentityConfig;
+use crate::proxy_impl::gpu_identity::{self, AdapterDesc};
+use crate::proxy_impl::hooks::declare_hook;
+use crate::proxy_impl::rules::{self, Decision, Filter};
+use crate::proxy_impl::status::{self, ErrorKind};
+use crate::proxy_impl::{config, hooks, pe};
+use std::ffi::{c_char, c_void};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use winapi::shared::guiddef::GUID;
+use winapi::shared::minwindef::UINT;
+use winapi::um::winnt::HRESULT;
+
+/// Runs before the config's rules (`RULES_PRIORITY`)
+const REGISTRY_PRIORITY: i32 = -20;
+
+// Vtable slots
+const FACTORY_ENUM_ADAPTERS: usize = 7;
+const FACTORY1_ENUM_ADAPTERS1: usize = 12;
+const ADAPTER_GET_DESC: usize = 8;
+const ADAPTER1_GET_DESC1: usize = 10;
+const ADAPTER2_GET_DESC2: usize = 11;
+
+// NvAPI function ids of nvapi_interface.h
+const ID_GPU_GET_FULL_NAME: u32 = 0xceee_8e9f;
+const ID_SYS_GET_DRIVER_AND_BRANCH_VERSION: u32 = 0x2926_aaad;
+const ID_GPU_GET_ARCH_INFO: u32 = 0xd826_5d24;
+const ID_GPU_GET_PCI_IDENTIFIERS: u32 = 0x2ddf_b66e;
+
+/// `NvAPI_Status`
+type Status = i32;
+type GetFullNameFn = unsafe extern "C" fn(*mut c_void, *mut c_char) -> Status;
+type GetDriverAndBranchVersionFn = unsafe extern "C" fn(*mut u32, *mut c_char) -> Status;
+type GetArchInfoFn = unsafe extern "C" fn(*mut c_void, *mut ArchInfo) -> Status;
+type GetPciIdentifiersFn = unsafe extern "C" fn(*mut c_void, *mut u32, *mut u32, *mut u32, *mut u32) -> Status;
+
+/// `NV_GPU_ARCH_INFO`
+#[repr(C)]
+struct ArchInfo {
+    version: u32,
+    architecture: u32,
+    implementation: u32,
+    revision: u32,
+}
+
+static FACTORY_VTABLE: AtomicUsize = AtomicUsize::new(0);
+static ADAPTER_VTABLE: AtomicUsize = AtomicUsize::new(0);
+
+static GET_FULL_NAME: AtomicUsize = AtomicUsize::new(0);
+static GET_DRIVER_AND_BRANCH_VERSION: AtomicUsize = AtomicUsize::new(0);
+static GET_ARCH_INFO: AtomicUsize = AtomicUsize::new(0);
+static GET_PCI_IDENTIFIERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Hook the DXGI factory functions imported by the original DLL and
+/// answer the registry values
+///
+/// Returns the number of factory hooks installed.
+pub unsafe fn initialize(config: &GpuIdentityConfig) -> usize {
+    if let Err(e) = gpu_identity::validate(config) {
+        tracing::warn!("[gpu_identity] Not spoofing the GPU: {}", e);
+        return 0;
+    }
+    let base = proxy::get_original_dll_base() as *const u8;
+    if base.is_null() {
+        return 0;
+    }
+
+    rules::add_handler("gpu-identity", REGISTRY_PRIORITY, Filter::api("RegQueryValueExW"), |call| {
+        if hooks::passthrough() {
+            return None;
+        }
+        let value = gpu_identity::registry_value(&config::get().gpu_identity, call.value_name?)?;
+        tracing::info!("[gpu_identity] Registry value {} -> {}", call.value_name?, value);
+        Some(Decision::Modify(value))
+    });
+    if !config::get().detours {
+        tracing::info!("[gpu_identity] Registry values are only spoofed with detours = true");
+    }
+
+    // Named apart from frame_events.rs's inline hooks on the same functions
+    let factories: [(&str, usize, &AtomicUsize); 3] = [
+        (
+            "CreateDXGIFactory",
+            CreateDXGIFactory::detour as *const () as usize,
+            &CreateDXGIFactory::ORIGINAL,
+        ),
+        (
+            "CreateDXGIFactory1",
+            CreateDXGIFactory1::detour as *const () as usize,
+            &CreateDXGIFactory1::ORIGINAL,
+        ),
+        (
+            "CreateDXGIFactory2",
+            CreateDXGIFactory2::detour as *const () as usize,
+            &CreateDXGIFactory2::ORIGINAL,
+        ),
+    ];
+    let mut installed = 0;
+    for (function, detour, original) in factories {
+        if pe::find_import_slot(base, None, function).is_none() {
+            tracing::debug!("[gpu_identity] {} not imported, skipped", function);
+            continue;
+        }
+        let name = format!("gpu_identity!{}", function);
+        match hooks::install_iat_hook_into(&name, base, function, detour, original) {
+            Ok(()) => installed += 1,
+            Err(e) => {
+                status::record_error(ErrorKind::Detour);
+                tracing::warn!("[gpu_identity] Failed to hook {}: {}", function, e);
+            }
+        }
+    }
+
+    tracing::info!("[gpu_identity] Spoofing the GPU identity ({} factory hook(s))", installed);
+    installed
+}
+
+// ============================================================================
+// DXGI
+// ============================================================================
+
+declare_hook! {
+    fn CreateDXGIFactory(riid: *const GUID, factory: *mut *mut c_void) -> HRESULT {
+        let result = call_original(riid, factory);
+        if result >= 0 {
+            hook_factory(*factory);
+        }
+        result
+    }
+}
+
+declare_hook! {
+    fn CreateDXGIFactory1(riid: *const GUID, factory: *mut *mut c_void) -> HRESULT {
+        let result = call_original(riid, factory);
+        if result >= 0 {
+            hook_factory(*factory);
+        }
+        result
+    }
+}
+
+declare_hook! {
+    fn CreateDXGIFactory2(flags: UINT, riid: *const GUID, factory: *mut *mut c_void) -> HRESULT {
+        let result = call_original(flags, riid, factory);
+        if result >= 0 {
+            hook_factory(*factory);
+        }
+        result
+    }
+}
+
+declare_hook! {
+    fn IDXGIFactory_EnumAdapters(this: *mut c_void, index: UINT, adapter: *mut *mut c_void) -> HRESULT {
+        let result = call_original(this, index, adapter);
+        if result >= 0 {
+            hook_adapter(*adapter);
+        }
+        result
+    }
+}
+
+declare_hook! {
+    fn IDXGIFactory1_EnumAdapters1(this: *mut c_void, index: UINT, adapter: *mut *mut c_void) -> HRESULT {
+        let result = call_original(this, index, adapter);
+        if result >= 0 {
+            hook_adapter(*adapter);
+        }
+        result
+    }
+}
+
+declare_hook! {
+    fn IDXGIAdapter_GetDesc(this: *mut c_void, desc: *mut AdapterDesc) -> HRESULT {
+        let result = call_original(this, desc);
+        patch_desc(result, desc);
+        result
+    }
+}
+
+declare_hook! {
+    fn IDXGIAdapter1_GetDesc1(this: *mut c_void, desc: *mut AdapterDesc) -> HRESULT {
+        let result = call_original(this, desc);
+        patch_desc(result, desc);
+        result
+    }
+}
+
+declare_hook! {
+    fn IDXGIAdapter2_GetDesc2(this: *mut c_void, desc: *mut AdapterDesc) -> HRESULT {
+        let result = call_original(this, desc);
+        patch_desc(result, desc);
+        result
+    }
+}
+
+/// Hook adapter enumeration of the first factory's class
+unsafe fn hook_factory(factory: *mut c_void) {
+    if factory.is_null() {
+        return;
+    }
+    let vtable = *(factory as *const usize);
+    if FACTORY_VTABLE.compare_exchange(0, vtable, Ordering::AcqRel, Ordering::Acquire).is_err() {
+        return;
+    }
+    let hooked = IDXGIFactory_EnumAdapters::install_vtable(vtable, FACTORY_ENUM_ADAPTERS)
+        .and_then(|_| IDXGIFactory1_EnumAdapters1::install_vtable(vtable, FACTORY1_ENUM_ADAPTERS1));
+    if let Err(e) = hooked {
+        tracing::warn!("[gpu_identity] Failed to hook adapter enumeration: {}", e);
+    }
+}
+
+/// Hook the descriptions of the first adapter's class
+unsafe fn hook_adapter(adapter: *mut c_void) {
+    if adapter.is_null() {
+        return;
+    }
+    let vtable = *(adapter as *const usize);
+    if ADAPTER_VTABLE.compare_exchange(0, vtable, Ordering::AcqRel, Ordering::Acquire).is_err() {
+        return;
+    }
+    let hooked = IDXGIAdapter_GetDesc::install_vtable(vtable, ADAPTER_GET_DESC)
+        .and_then(|_| IDXGIAdapter1_GetDesc1::install_vtable(vtable, ADAPTER1_GET_DESC1))
+        .and_then(|_| IDXGIAdapter2_GetDesc2::install_vtable(vtable, ADAPTER2_GET_DESC2));
+    if let Err(e) = hooked {
+        tracing::warn!("[gpu_identity] Failed to hook the adapter descriptions: {}", e);
+    }
+}
+
+unsafe fn patch_desc(result: HRESULT, desc: *mut AdapterDesc) {
+    if let (true, Some(desc)) = (result >= 0, desc.as_mut()) {
+        if gpu_identity::patch_adapter_desc(&config::get().gpu_identity, desc) {
+            tracing::debug!("[gpu_identity] Spoofed an adapter description");
+        }
+    }
+}
+
+// ============================================================================
+// NvAPI
+// ============================================================================
+
+/// The wrapper to return for the entry point `entry` of NvAPI function
+/// `id`, if it reports the GPU's identity
+pub fn wrap_nvapi(id: u32, entry: usize) -> Option<usize> {
+    if !config::get().gpu_identity.enabled || entry == 0 {
+        return None;
+    }
+    let (original, wrapper) = match id {
+        ID_GPU_GET_FULL_NAME => (&GET_FULL_NAME, get_full_name as GetFullNameFn as usize),
+        ID_SYS_GET_DRIVER_AND_BRANCH_VERSION => (
+            &GET_DRIVER_AND_BRANCH_VERSION,
+            get_driver_and_branch_version as GetDriverAndBranchVersionFn as usize,
+        ),
+        ID_GPU_GET_ARCH_INFO => (&GET_ARCH_INFO, get_arch_info as GetArchInfoFn as usize),
+        ID_GPU_GET_PCI_IDENTIFIERS => (&GET_PCI_IDENTIFIERS, get_pci_identifiers as GetPciIdentifiersFn as usize),
+        _ => return None,
+    };
+    original.store(entry, Ordering::Release);
+    Some(wrapper)
+}
+
+/// The driver's entry point in `slot`, as `F`
+unsafe fn original<F>(slot: &AtomicUsize) -> F {
+    std::mem::transmute_copy(&slot.load(Ordering::Acquire))
+}
+
+/// Whether to replace the driver's answer `status`
+fn spoofing(status: Status) -> bool {
+    status == 0 && !hooks::passthrough()
+}
+
+unsafe extern "C" fn get_full_name(gpu: *mut c_void, name: *mut c_char) -> Status {
+    let status = original::<GetFullNameFn>(&GET_FULL_NAME)(gpu, name);
+    if let (true, Some(spoofed)) = (spoofing(status), &config::get().gpu_identity.name) {
+        // NvAPI_ShortString; `validate` keeps the name below 64
+        std::ptr::write_bytes(name, 0, 64);
+        std::ptr::copy_nonoverlapping(spoofed.as_ptr() as *const c_char, name, spoofed.len().min(63));
+    }
+    status
+}
+
+unsafe extern "C" fn get_driver_and_branch_version(version: *mut u32, branch: *mut c_char) -> Status {
+    let status = original::<GetDriverAndBranchVersionFn>(&GET_DRIVER_AND_BRANCH_VERSION)(version, branch);
+    let spoofed = config::get()
+        .gpu_identity
+        .driver_version
+        .as_deref()
+        .and_then(|version| gpu_identity::nvapi_driver_version(version).ok());
+    if let (true, Some(spoofed), false) = (spoofing(status), spoofed, version.is_null()) {
+        *version = spoofed;
+    }
+    status
+}
+
+unsafe extern "C" fn get_arch_info(gpu: *mut c_void, info: *mut ArchInfo) -> Status {
+    let status = original::<GetArchInfoFn>(&GET_ARCH_INFO)(gpu, info);
+    if let (true, Some(architecture), Some(info)) =
+        (spoofing(status), config::get().gpu_identity.architecture, info.as_mut())
+    {
+        info.architecture = architecture;
+    }
+    status
+}
+
+unsafe extern "C" fn get_pci_identifiers(
+    gpu: *mut c_void,
+    device: *mut u32,
+    subsys: *mut u32,
+    revision: *mut u32,
+    ext_device: *mut u32,
+) -> Status {
+    let status = original::<GetPciIdentifiersFn>(&GET_PCI_IDENTIFIERS)(gpu, device, subsys, revision, ext_device);
+    if let (true, Some(device), Some(subsys), Some(revision)) =
+        (spoofing(status), device.as_mut(), subsys.as_mut(), revision.as_mut())
+    {
+        gpu_identity::patch_pci_identifiers(&config::get().gpu_identity, device, subsys, revision);
+    }
+    status
+}
@@ -0,0 +1,76 @@
+/// SHA-256 hashing through the Windows CryptoAPI
+///
+/// Used to identify files before the installer touches them and to
+/// fingerprint the original DLL in reports. SHA-1 is only there for the
+/// WebSocket handshake (stream.rs).
+
+use std::fs;
+use std::path::Path;
+use winapi::shared::minwindef::DWORD;
+use winapi::um::wincrypt::{
+    CryptAcquireContextW, CryptCreateHash, CryptDestroyHash, CryptGetHashParam, CryptHashData,
+    CryptReleaseContext, ALG_ID, CALG_SHA_256, CRYPT_VERIFYCONTEXT, HCRYPTHASH, HCRYPTPROV, HP_HASHVAL,
+    PROV_RSA_AES,
+};
+
+/// Compute the SHA-256 of a byte slice as a lowercase hex string
+pub fn sha256_hex(data: &[u8]) -> Result<String, String> {
+    let digest = digest(CALG_SHA_256, data).ok_or("Failed to compute SHA-256")?;
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Compute the SHA-1 of a byte slice
+#[cfg(feature = "trace")]
+pub fn sha1(data: &[u8]) -> Result<Vec<u8>, String> {
+    digest(winapi::um::wincrypt::CALG_SHA1, data).ok_or_else(|| "Failed to compute SHA-1".to_string())
+}
+
+/// Compute the SHA-256 of a file on disk
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    sha256_hex(&data)
+}
+
+fn digest(algorithm: ALG_ID, data: &[u8]) -> Option<Vec<u8>> {
+    unsafe {
+        let mut provider: HCRYPTPROV = 0;
+        if CryptAcquireContextW(
+            &mut provider,
+            std::ptr::null(),
+            std::ptr::null(),
+            PROV_RSA_AES,
+            CRYPT_VERIFYCONTEXT,
+        ) == 0
+        {
+            return None;
+        }
+
+        let mut hash: HCRYPTHASH = 0;
+        if CryptCreateHash(provider, algorithm, 0, 0, &mut hash) == 0 {
+            CryptReleaseContext(provider, 0);
+            return None;
+        }
+
+        let mut ok = true;
+        for chunk in data.chunks(DWORD::MAX as usize) {
+            if CryptHashData(hash, chunk.as_ptr(), chunk.len() as DWORD, 0) == 0 {
+                ok = false;
+                break;
+            }
+        }
+
+        let mut digest = [0u8; 32];
+        let mut digest_len = digest.len() as DWORD;
+        if ok && CryptGetHashParam(hash, HP_HASHVAL, digest.as_mut_ptr(), &mut digest_len, 0) == 0 {
+            ok = false;
+        }
+
+        CryptDestroyHash(hash);
+        CryptReleaseContext(provider, 0);
+
+        if !ok {
+            return None;
+        }
+        Some(digest[..digest_len as usize].to_vec())
+    }
+}
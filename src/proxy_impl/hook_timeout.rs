@@ -0,0 +1,173 @@
+/// Execution timeout for experimental hooks
+///
+/// A detour listed in `[hook_timeouts]` runs its body on a worker thread.
+/// If the body has not finished after `timeout_ms`, the incident is logged
+/// and the caller gets `fallback` (cast to the hook's return type) instead
+/// of waiting forever:
+///
+/// ```toml
+/// [hook_timeouts]
+/// DeleteFileW = { timeout_ms = 2000, fallback = 0 }
+/// RegQueryValueExW = { timeout_ms = 500, fallback = 2 }   # ERROR_FILE_NOT_FOUND
+/// ```
+///
+/// A thread cannot be cancelled, so the stuck worker keeps running; until
+/// it finishes, further calls of that hook return the fallback right away.
+/// The body runs on another thread than the caller and may outlive the
+/// call, so it must not rely on thread-local state and must not touch the
+/// caller's memory: detours hand it copies of their pointer arguments and
+/// write the outputs back only when `run` returns them in time
+/// (`detours::dispatch_with`). Hooks without an entry run inline.
+
+use crate::proxy_impl::config::HookTimeout;
+#[cfg(windows)]
+use crate::proxy_impl::status::{self, ErrorKind};
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Hooks with a worker that timed out and has not finished yet
+static STUCK: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Outcome of one worker, shared with the caller
+enum State<T> {
+    Running,
+    Done(T),
+    /// The caller timed out and returned the fallback
+    Abandoned,
+}
+
+/// Run `body` for hook `name`, giving up after `limit` if one is configured.
+/// Returns what the body produced, e.g. the result plus copies of
+/// out-parameters, or `Err(fallback)` if it did not finish in time, when
+/// whatever it produces later is dropped
+pub fn run<T, F>(name: &str, limit: Option<&HookTimeout>, body: F) -> Result<T, i64>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return Ok(body()),
+    };
+
+    if STUCK.lock().unwrap().contains(name) {
+        tracing::debug!("[hook_timeout] {} is still stuck, returning {}", name, limit.fallback);
+        return Err(limit.fallback);
+    }
+
+    let shared = Arc::new((Mutex::new(State::Running), Condvar::new()));
+    let worker_shared = Arc::clone(&shared);
+    let worker_name = name.to_string();
+    let started = Instant::now();
+    let spawned = std::thread::Builder::new()
+        .name(format!("reflex-hook-{}", name))
+        .spawn(move || {
+            let result = body();
+            let (state, finished) = &*worker_shared;
+            let mut state = state.lock().unwrap();
+            if let State::Abandoned = *state {
+                STUCK.lock().unwrap().remove(&worker_name);
+                tracing::warn!(
+                    "[hook_timeout] {} finished after {} ms, its result discarded",
+                    worker_name,
+                    started.elapsed().as_millis()
+                );
+            } else {
+                *state = State::Done(result);
+                finished.notify_one();
+            }
+        });
+
+    if let Err(e) = spawned {
+        tracing::error!("[hook_timeout] Failed to spawn worker for {}: {}", name, e);
+        return Err(limit.fallback);
+    }
+
+    let (state, finished) = &*shared;
+    let (mut state, _) = finished
+        .wait_timeout_while(state.lock().unwrap(), Duration::from_millis(limit.timeout_ms), |s| {
+            matches!(s, State::Running)
+        })
+        .unwrap();
+    if let State::Done(result) = std::mem::replace(&mut *state, State::Abandoned) {
+        return Ok(result);
+    }
+
+    // Marked stuck under the state lock, so the worker cannot finish in between
+    STUCK.lock().unwrap().insert(name.to_string());
+    drop(state);
+
+    #[cfg(windows)]
+    status::record_error(ErrorKind::Hang);
+    tracing::error!(
+        "[hook_timeout] {} did not finish within {} ms, returning {}",
+        name,
+        limit.timeout_ms,
+        limit.fallback
+    );
+    Err(limit.fallback)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn limit(timeout_ms: u64, fallback: i64) -> HookTimeout {
+        HookTimeout { timeout_ms, fallback }
+    }
+
+    #[test]
+    fn unlisted_hooks_run_inline() {
+        let caller = std::thread::current().id();
+        let result = run("Inline", None, move || (std::thread::current().id() == caller) as i64);
+        assert_eq!(result, Ok(1));
+    }
+
+    #[test]
+    fn finished_body_returns_its_result() {
+        assert_eq!(run("Quick", Some(&limit(5000, -1)), || 42), Ok(42));
+    }
+
+    #[test]
+    fn stuck_body_returns_fallback_until_it_finishes() {
+        let (release, blocked) = mpsc::channel::<()>();
+        let (finished, done) = mpsc::channel();
+        let result = run("Stuck", Some(&limit(20, -1)), move || {
+            let _ = blocked.recv();
+            let _ = finished.send(());
+            7
+        });
+        assert_eq!(result, Err(-1));
+
+        // Still stuck: no new worker, immediate fallback
+        let started = Instant::now();
+        assert_eq!(run("Stuck", Some(&limit(5000, -2)), || 8), Err(-2));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        release.send(()).unwrap();
+        done.recv().unwrap();
+        while STUCK.lock().unwrap().contains("Stuck") {
+            std::thread::yield_now();
+        }
+        assert_eq!(run("Stuck", Some(&limit(5000, -2)), || 8), Ok(8));
+    }
+
+    #[test]
+    fn late_outputs_never_reach_the_caller() {
+        let (release, blocked) = mpsc::channel::<()>();
+        let (finished, done) = mpsc::channel();
+        let outputs = run("Late", Some(&limit(20, 2)), move || {
+            let _ = blocked.recv();
+            let _ = finished.send(());
+            (0, vec![1u8; 4])
+        });
+        assert_eq!(outputs, Err(2));
+
+        release.send(()).unwrap();
+        done.recv().unwrap();
+        assert_eq!(run("Inline", None, || (0, vec![1u8])), Ok((0, vec![1u8])));
+    }
+}
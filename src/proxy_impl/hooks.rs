@@ -0,0 +1,716 @@
+/// Hook registry and installation engine
+///
+/// Every patch the proxy applies is recorded here so it can be listed
+/// (status export) and removed again (teardown on DLL_PROCESS_DETACH):
+/// - Inline hooks overwrite the target prologue with an absolute jump to
+///   the detour; a trampoline runs the displaced instructions (relocated,
+///   see trampoline.rs) and jumps back
+/// - IAT hooks swap an import address table slot of a module
+/// - Vtable hooks swap a virtual function table slot (vtables can be found
+///   by class name, see analysis.rs)
+///
+/// Hooks can be suspended temporarily (original bytes restored) when code
+/// needs to see itself unmodified, e.g. while the original DLL self-verifies.
+/// Suspensions nest: a hook is re-applied once every suspend is resumed.
+///
+/// Passthrough suspends every hook at once, and the hooks installed while
+/// it lasts start suspended, which leaves the proxy a pure forwarder until
+/// it is switched off (`set_passthrough`; `passthrough` in the config, the
+/// `passthrough` hotkey and control command). Byte patches (patches.rs) are
+/// not hooks and stay applied.
+///
+/// The registry is locked only to install, suspend, resume and list
+/// hooks. What hooked calls check, `passthrough`, is an atomic flag read
+/// without the lock, so render-path detours never wait for the control
+/// channel or an installation on another thread.
+///
+/// Inline hooks assume x64 code.
+
+use crate::proxy_impl::patches::Patch;
+#[cfg(windows)]
+use crate::proxy_impl::{pe, trampoline};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+#[cfg(windows)]
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// `int3`, fills the tail of a partially overwritten instruction
+#[cfg(windows)]
+const INT3: u8 = 0xCC;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookKind {
+    Inline,
+    Iat,
+    Vtable,
+}
+
+/// An installed hook
+struct HookEntry {
+    name: String,
+    kind: HookKind,
+    /// Patch at the function start (inline), IAT slot (iat) or vtable slot
+    /// (vtable)
+    patch: Patch,
+    /// Number of outstanding suspensions (0 = active)
+    suspend_count: u32,
+    /// What an IAT hook hooks, to hook it in another module
+    import: Option<Import>,
+}
+
+/// Imported function and detour of an IAT hook
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub function: String,
+    pub detour: usize,
+}
+
+/// Public view of an installed hook
+#[derive(Debug, Clone, Serialize)]
+pub struct HookInfo {
+    pub name: String,
+    pub kind: HookKind,
+    pub target: String,
+    pub active: bool,
+}
+
+/// Installed hooks, in installation order
+#[derive(Default)]
+struct Registry {
+    entries: Vec<HookEntry>,
+    /// Every hook holds one extra suspension
+    passthrough: bool,
+}
+
+static HOOKS: Lazy<Mutex<Registry>> = Lazy::new(|| Mutex::new(Registry::default()));
+/// `Registry::passthrough`, for the hot path
+static PASSTHROUGH: AtomicBool = AtomicBool::new(false);
+
+/// Install an inline hook at `target`, redirecting it to `detour`
+///
+/// Stores the trampoline address, callable as the original function, in
+/// `original` before the target is patched: other threads may be calling
+/// the function while the hook is installed, and the detour can rely on
+/// `original` being set.
+#[cfg(windows)]
+pub unsafe fn install_inline_hook_into(
+    name: &str,
+    target: usize,
+    detour: usize,
+    original: &AtomicUsize,
+) -> Result<(), String> {
+    let mut hooks = HOOKS.lock().unwrap();
+    hooks.check_unique(name)?;
+
+    let trampoline = trampoline::build(target).map_err(|e| format!("{}: {}", name, e))?;
+
+    original.store(trampoline.address, Ordering::SeqCst);
+
+    let mut jump = trampoline::encode_abs_jump(detour).to_vec();
+    jump.resize(trampoline.stolen_len, INT3);
+    let patch = Patch::apply(target, &jump)?;
+
+    tracing::info!(
+        "[hooks] Installed inline hook {} at 0x{:x} (trampoline 0x{:x}, {} bytes relocated)",
+        name,
+        target,
+        trampoline.address,
+        trampoline.stolen_len
+    );
+
+    hooks.add(name, HookKind::Inline, patch);
+
+    Ok(())
+}
+
+/// Install an IAT hook for `function` imported by the module at `module_base`
+///
+/// Returns the previous import address, callable as the original function.
+#[cfg(windows)]
+pub unsafe fn install_iat_hook(
+    name: &str,
+    module_base: *const u8,
+    function: &str,
+    detour: usize,
+) -> Result<usize, String> {
+    let original = AtomicUsize::new(0);
+    install_iat_hook_into(name, module_base, function, detour, &original)?;
+    Ok(original.load(Ordering::SeqCst))
+}
+
+/// Like `install_iat_hook`, but stores the previous import address in
+/// `original` before the slot is patched
+#[cfg(windows)]
+pub unsafe fn install_iat_hook_into(
+    name: &str,
+    module_base: *const u8,
+    function: &str,
+    detour: usize,
+    original: &AtomicUsize,
+) -> Result<(), String> {
+    let mut hooks = HOOKS.lock().unwrap();
+    hooks.check_unique(name)?;
+
+    let slot = pe::find_import_slot(module_base, None, function)
+        .ok_or_else(|| format!("{} is not imported by the module", function))?;
+    let previous = *slot;
+    original.store(previous, Ordering::SeqCst);
+
+    let patch = Patch::apply(slot as usize, &detour.to_ne_bytes())?;
+
+    tracing::info!(
+        "[hooks] Installed IAT hook {} at slot {:p} (original 0x{:x})",
+        name,
+        slot,
+        previous
+    );
+
+    hooks.add(name, HookKind::Iat, patch);
+    hooks.set_import(
+        name,
+        Import {
+            function: function.to_string(),
+            detour,
+        },
+    );
+
+    Ok(())
+}
+
+/// Install a hook on the `index`-th (from 0) virtual function of `vtable`
+///
+/// Affects every object of the class. Stores the previous slot value,
+/// callable as the original method, in `original` before the slot is
+/// patched.
+#[cfg(windows)]
+pub unsafe fn install_vtable_hook_into(
+    name: &str,
+    vtable: usize,
+    index: usize,
+    detour: usize,
+    original: &AtomicUsize,
+) -> Result<(), String> {
+    let mut hooks = HOOKS.lock().unwrap();
+    hooks.check_unique(name)?;
+
+    let slot = vtable + index * std::mem::size_of::<usize>();
+    let previous = *(slot as *const usize);
+    original.store(previous, Ordering::SeqCst);
+
+    let patch = Patch::apply(slot, &detour.to_ne_bytes())?;
+
+    tracing::info!(
+        "[hooks] Installed vtable hook {} at slot 0x{:x} (original 0x{:x})",
+        name,
+        slot,
+        previous
+    );
+
+    hooks.add(name, HookKind::Vtable, patch);
+
+    Ok(())
+}
+
+/// Declare a hook from its signature and body
+///
+/// ```ignore
+/// declare_hook! {
+///     /// Log the files the original deletes
+///     fn DeleteFileW(file_name: LPCWSTR) -> BOOL {
+///         tracing::info!("[detours] Deleting {}", from_wide_ptr(file_name).unwrap_or_default());
+///         call_original(file_name)
+///     }
+/// }
+///
+/// DeleteFileW::install_iat(original_base)?;
+/// ```
+///
+/// expands to a module named after the target holding
+/// - `Signature`, the function pointer type
+/// - `ORIGINAL`, the original function, set before the target is patched
+/// - `detour`, the `extern "system"` thunk running the body, or calling
+///   the original for threads out of the hook's scope (thread_scope.rs)
+/// - `call_original`, which calls `ORIGINAL` with the same parameters
+/// - `install_inline(target)`, `install_iat(module_base)` and
+///   `install_vtable(vtable, index)`, which install and register the hook
+///   under the target's name
+///
+/// The body sees the items of the enclosing module.
+#[cfg(windows)]
+macro_rules! declare_hook {
+    ($(#[$meta:meta])* fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)? $body:block) => {
+        $(#[$meta])*
+        #[allow(non_snake_case, dead_code)]
+        pub mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+            use std::sync::atomic::{AtomicUsize, Ordering};
+
+            pub type Signature = unsafe extern "system" fn($($ty),*) $(-> $ret)?;
+
+            /// Trampoline or previous import address; 0 until installed
+            pub static ORIGINAL: AtomicUsize = AtomicUsize::new(0);
+
+            pub unsafe extern "system" fn detour($($arg: $ty),*) $(-> $ret)? {
+                if !$crate::proxy_impl::thread_scope::in_scope(stringify!($name)) {
+                    return call_original($($arg),*);
+                }
+                $body
+            }
+
+            pub unsafe fn call_original($($arg: $ty),*) $(-> $ret)? {
+                std::mem::transmute::<usize, Signature>(ORIGINAL.load(Ordering::SeqCst))($($arg),*)
+            }
+
+            pub fn is_installed() -> bool {
+                ORIGINAL.load(Ordering::SeqCst) != 0
+            }
+
+            pub unsafe fn install_inline(target: usize) -> Result<(), String> {
+                $crate::proxy_impl::hooks::install_inline_hook_into(
+                    stringify!($name),
+                    target,
+                    detour as *const () as usize,
+                    &ORIGINAL,
+                )
+            }
+
+            pub unsafe fn install_iat(module_base: *const u8) -> Result<(), String> {
+                $crate::proxy_impl::hooks::install_iat_hook_into(
+                    stringify!($name),
+                    module_base,
+                    stringify!($name),
+                    detour as *const () as usize,
+                    &ORIGINAL,
+                )
+            }
+
+            pub unsafe fn install_vtable(vtable: usize, index: usize) -> Result<(), String> {
+                $crate::proxy_impl::hooks::install_vtable_hook_into(
+                    stringify!($name),
+                    vtable,
+                    index,
+                    detour as *const () as usize,
+                    &ORIGINAL,
+                )
+            }
+        }
+    };
+}
+#[cfg(windows)]
+pub(crate) use declare_hook;
+
+/// Temporarily restore the original code of a hook
+pub unsafe fn suspend_hook(name: &str) -> Result<(), String> {
+    HOOKS.lock().unwrap().suspend(name)
+}
+
+/// Re-apply a hook previously suspended with `suspend_hook`
+pub unsafe fn resume_hook(name: &str) -> Result<(), String> {
+    HOOKS.lock().unwrap().resume(name)
+}
+
+/// Keeps a hook suspended until dropped
+pub struct SuspendGuard {
+    name: String,
+}
+
+impl Drop for SuspendGuard {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { resume_hook(&self.name) } {
+            tracing::error!("[hooks] Failed to resume {}: {}", self.name, e);
+        }
+    }
+}
+
+/// Suspend a hook for the lifetime of the returned guard
+///
+/// ```ignore
+/// let _passthrough = hooks::suspend_scoped("DeleteFileW")?;
+/// // original code is intact here
+/// ```
+pub unsafe fn suspend_scoped(name: &str) -> Result<SuspendGuard, String> {
+    suspend_hook(name)?;
+    Ok(SuspendGuard {
+        name: name.to_string(),
+    })
+}
+
+/// Remove every installed hook, most recent first
+///
+/// Trampolines are intentionally leaked: another thread may still be
+/// executing inside one. Returns the number of hooks removed.
+pub unsafe fn remove_all_hooks() -> usize {
+    HOOKS.lock().unwrap().remove_all()
+}
+
+/// Remove the hooks patching `start..end`, e.g. the image of a module
+/// about to be unloaded
+///
+/// Returns their names in installation order, with what IAT hooks hooked.
+pub unsafe fn remove_hooks_in(start: usize, end: usize) -> Vec<(String, Option<Import>)> {
+    HOOKS.lock().unwrap().remove_in(start, end)
+}
+
+/// Suspend every hook (and the ones installed later), or resume them
+///
+/// Hooks suspended on their own stay suspended when passthrough ends.
+/// Returns the number of hooks whose code changed.
+pub unsafe fn set_passthrough(enabled: bool) -> usize {
+    HOOKS.lock().unwrap().set_passthrough(enabled)
+}
+
+/// Whether passthrough is on; lock-free
+pub fn passthrough() -> bool {
+    PASSTHROUGH.load(Ordering::Acquire)
+}
+
+/// List installed hooks
+pub fn list_hooks() -> Vec<HookInfo> {
+    HOOKS.lock().unwrap().list()
+}
+
+/// Address and length of every byte range currently patched by a hook
+pub fn patched_ranges() -> Vec<(usize, usize)> {
+    HOOKS.lock().unwrap().patched_ranges()
+}
+
+/// Name and target of every inline hook
+pub fn inline_targets() -> Vec<(String, usize)> {
+    HOOKS
+        .lock()
+        .unwrap()
+        .entries
+        .iter()
+        .filter(|h| h.kind == HookKind::Inline)
+        .map(|h| (h.name.clone(), h.patch.address()))
+        .collect()
+}
+
+// ============================================================================
+// Registry
+// ============================================================================
+
+impl Registry {
+    fn check_unique(&self, name: &str) -> Result<(), String> {
+        if self.entries.iter().any(|h| h.name == name) {
+            return Err(format!("Hook {} is already installed", name));
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, name: &str, kind: HookKind, patch: Patch) {
+        self.entries.push(HookEntry {
+            name: name.to_string(),
+            kind,
+            patch,
+            suspend_count: 0,
+            import: None,
+        });
+    }
+
+    fn set_import(&mut self, name: &str, import: Import) {
+        if let Ok(hook) = self.find_mut(name) {
+            hook.import = Some(import);
+        }
+    }
+
+    /// Insert a hook just applied, suspending it during passthrough
+    unsafe fn add(&mut self, name: &str, kind: HookKind, patch: Patch) {
+        self.insert(name, kind, patch);
+        if self.passthrough {
+            if let Err(e) = self.suspend(name) {
+                tracing::error!("[hooks] Failed to suspend {} for passthrough: {}", name, e);
+            }
+        }
+    }
+
+    unsafe fn suspend(&mut self, name: &str) -> Result<(), String> {
+        let hook = self.find_mut(name)?;
+
+        if hook.suspend_count == 0 {
+            hook.patch.revert()?;
+            tracing::info!("[hooks] Suspended {}", hook.name);
+        }
+        hook.suspend_count += 1;
+        Ok(())
+    }
+
+    unsafe fn resume(&mut self, name: &str) -> Result<(), String> {
+        let hook = self.find_mut(name)?;
+
+        match hook.suspend_count {
+            0 => return Err(format!("Hook {} is not suspended", name)),
+            1 => {
+                hook.patch.reapply()?;
+                tracing::info!("[hooks] Resumed {}", hook.name);
+            }
+            _ => {}
+        }
+        hook.suspend_count -= 1;
+        Ok(())
+    }
+
+    unsafe fn set_passthrough(&mut self, enabled: bool) -> usize {
+        if self.passthrough == enabled {
+            return 0;
+        }
+        self.passthrough = enabled;
+        PASSTHROUGH.store(enabled, Ordering::Release);
+
+        // Reverted most recent first and re-applied in installation order,
+        // like overlapping patches must be
+        let mut changed = 0;
+        if enabled {
+            for hook in self.entries.iter_mut().rev() {
+                if hook.suspend_count == 0 {
+                    match hook.patch.revert() {
+                        Ok(()) => changed += 1,
+                        Err(e) => tracing::error!("[hooks] Failed to suspend {}: {}", hook.name, e),
+                    }
+                }
+                hook.suspend_count += 1;
+            }
+        } else {
+            for hook in self.entries.iter_mut() {
+                if hook.suspend_count == 1 {
+                    match hook.patch.reapply() {
+                        Ok(()) => changed += 1,
+                        Err(e) => tracing::error!("[hooks] Failed to resume {}: {}", hook.name, e),
+                    }
+                }
+                hook.suspend_count -= 1;
+            }
+        }
+        tracing::warn!(
+            "[hooks] Passthrough {}, {} hook(s) {}",
+            if enabled { "on" } else { "off" },
+            changed,
+            if enabled { "suspended" } else { "resumed" }
+        );
+        changed
+    }
+
+    unsafe fn remove_all(&mut self) -> usize {
+        let mut removed = 0;
+
+        while let Some(mut hook) = self.entries.pop() {
+            match restore(&mut hook) {
+                Ok(()) => removed += 1,
+                Err(e) => tracing::error!("[hooks] Failed to remove {}: {}", hook.name, e),
+            }
+        }
+
+        removed
+    }
+
+    /// Remove the hooks patching `start..end`, most recent first
+    unsafe fn remove_in(&mut self, start: usize, end: usize) -> Vec<(String, Option<Import>)> {
+        let mut removed = Vec::new();
+        for index in (0..self.entries.len()).rev() {
+            if !(start..end).contains(&self.entries[index].patch.address()) {
+                continue;
+            }
+            let mut hook = self.entries.remove(index);
+            if let Err(e) = restore(&mut hook) {
+                tracing::error!("[hooks] Failed to remove {}: {}", hook.name, e);
+            }
+            removed.push((hook.name, hook.import));
+        }
+        removed.reverse();
+        removed
+    }
+
+    fn list(&self) -> Vec<HookInfo> {
+        self.entries
+            .iter()
+            .map(|h| HookInfo {
+                name: h.name.clone(),
+                kind: h.kind,
+                target: format!("0x{:x}", h.patch.address()),
+                active: h.suspend_count == 0,
+            })
+            .collect()
+    }
+
+    fn patched_ranges(&self) -> Vec<(usize, usize)> {
+        self.entries
+            .iter()
+            .filter(|h| h.patch.is_applied())
+            .map(|h| (h.patch.address(), h.patch.patched_bytes().len()))
+            .collect()
+    }
+
+    fn find_mut(&mut self, name: &str) -> Result<&mut HookEntry, String> {
+        self.entries
+            .iter_mut()
+            .find(|h| h.name == name)
+            .ok_or_else(|| format!("Hook {} is not installed", name))
+    }
+}
+
+// ============================================================================
+// Utility Functions
+// ============================================================================
+
+unsafe fn restore(hook: &mut HookEntry) -> Result<(), String> {
+    hook.patch.revert()?;
+    tracing::info!("[hooks] Removed {:?} hook {}", hook.kind, hook.name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy_impl::os::fake::FakeOs;
+
+    /// A registry with one fake inline hook over `code`
+    fn registry_with_hook(code: &mut [u8]) -> Registry {
+        let mut registry = Registry::default();
+        let patch = unsafe { Patch::apply(code.as_mut_ptr() as usize, &[0xE9, 0x00, 0x00, 0x00, 0x00]) }.unwrap();
+        registry.insert("Target", HookKind::Inline, patch);
+        registry
+    }
+
+    #[test]
+    fn names_are_unique() {
+        FakeOs::default().install();
+        let mut code = vec![0x90u8; 8];
+        let registry = registry_with_hook(&mut code);
+
+        assert!(registry.check_unique("Target").is_err());
+        assert!(registry.check_unique("Other").is_ok());
+    }
+
+    #[test]
+    fn suspensions_nest() {
+        FakeOs::default().install();
+        let mut code = vec![0x90u8; 8];
+        let mut registry = registry_with_hook(&mut code);
+
+        unsafe {
+            registry.suspend("Target").unwrap();
+            registry.suspend("Target").unwrap();
+            assert_eq!(code[0], 0x90);
+            assert!(!registry.list()[0].active);
+            assert!(registry.patched_ranges().is_empty());
+
+            registry.resume("Target").unwrap();
+            assert_eq!(code[0], 0x90, "re-applied before the last resume");
+
+            registry.resume("Target").unwrap();
+            assert_eq!(code[0], 0xE9);
+            assert!(registry.list()[0].active);
+
+            assert!(registry.resume("Target").is_err());
+            assert!(registry.suspend("Missing").is_err());
+        }
+    }
+
+    #[test]
+    fn passthrough_suspends_every_hook_including_later_ones() {
+        FakeOs::default().install();
+        let mut code = vec![0x90u8; 8];
+        let address = code.as_mut_ptr() as usize;
+        let mut registry = registry_with_hook(&mut code);
+        let mut other = vec![0x90u8; 4];
+
+        unsafe {
+            registry.suspend("Target").unwrap();
+            assert_eq!(registry.set_passthrough(true), 0);
+            assert_eq!(registry.set_passthrough(true), 0);
+
+            let patch = Patch::apply(other.as_mut_ptr() as usize, &[0xCC]).unwrap();
+            registry.add("Later", HookKind::Inline, patch);
+            assert_eq!(other[0], 0x90, "installed suspended");
+
+            assert_eq!(registry.set_passthrough(false), 1);
+            assert_eq!(other[0], 0xCC);
+            assert_eq!(*(address as *const u8), 0x90, "still suspended on its own");
+
+            registry.resume("Target").unwrap();
+            assert_eq!(registry.set_passthrough(true), 2);
+            assert!(registry.list().iter().all(|hook| !hook.active));
+        }
+    }
+
+    #[test]
+    fn scoped_suspension_lasts_as_long_as_the_guard() {
+        FakeOs::default().install();
+        let mut code = vec![0x90u8; 8];
+        let address = code.as_mut_ptr() as usize;
+        let patch = unsafe { Patch::apply(address, &[0xE9, 0x00, 0x00, 0x00, 0x00]) }.unwrap();
+        HOOKS.lock().unwrap().insert("Scoped", HookKind::Inline, patch);
+
+        unsafe {
+            let guard = suspend_scoped("Scoped").unwrap();
+            assert_eq!(*(address as *const u8), 0x90);
+            drop(guard);
+            assert_eq!(*(address as *const u8), 0xE9);
+
+            assert!(suspend_scoped("Missing").is_err());
+            remove_hooks_in(address, address + code.len());
+        }
+        assert_eq!(code, [0x90; 8]);
+    }
+
+    #[test]
+    fn list_and_ranges() {
+        FakeOs::default().install();
+        let mut code = vec![0x90u8; 8];
+        let registry = registry_with_hook(&mut code);
+        let address = code.as_ptr() as usize;
+
+        let hooks = registry.list();
+        assert_eq!(hooks.len(), 1);
+        assert_eq!(hooks[0].name, "Target");
+        assert_eq!(hooks[0].kind, HookKind::Inline);
+        assert_eq!(hooks[0].target, format!("0x{:x}", address));
+        assert_eq!(registry.patched_ranges(), [(address, 5)]);
+    }
+
+    #[test]
+    fn remove_all_restores_in_reverse_order() {
+        FakeOs::default().install();
+        let mut code = vec![0x90u8; 8];
+        let address = code.as_mut_ptr() as usize;
+        let mut registry = registry_with_hook(&mut code);
+
+        // A second hook over the first one's bytes only comes out right
+        // if it is removed first
+        let patch = unsafe { Patch::apply(address + 2, &[0xCC, 0xCC]) }.unwrap();
+        registry.insert("Overlapping", HookKind::Inline, patch);
+
+        assert_eq!(unsafe { registry.remove_all() }, 2);
+        assert_eq!(code, [0x90; 8]);
+        assert!(registry.list().is_empty());
+    }
+
+    #[test]
+    fn removal_by_range_keeps_the_hooks_outside() {
+        FakeOs::default().install();
+        let mut code = vec![0x90u8; 8];
+        let mut other = vec![0x90u8; 4];
+        let address = code.as_mut_ptr() as usize;
+        let mut registry = registry_with_hook(&mut code);
+
+        let slot = unsafe { Patch::apply(address + 6, &[0xCC, 0xCC]) }.unwrap();
+        registry.insert("Slot", HookKind::Iat, slot);
+        let import = Import {
+            function: "DeleteFileW".to_string(),
+            detour: 0x1234,
+        };
+        registry.set_import("Slot", import.clone());
+        let outside = unsafe { Patch::apply(other.as_mut_ptr() as usize, &[0xCC]) }.unwrap();
+        registry.insert("Outside", HookKind::Inline, outside);
+
+        let removed = unsafe { registry.remove_in(address, address + code.len()) };
+        assert_eq!(removed, [("Target".to_string(), None), ("Slot".to_string(), Some(import))]);
+        assert_eq!(code, [0x90; 8]);
+        assert_eq!(other[0], 0xCC);
+        assert_eq!(registry.list().len(), 1);
+    }
+}
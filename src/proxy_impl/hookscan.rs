@@ -0,0 +1,235 @@
+/// Hook detection in system DLLs
+///
+/// Compares the code sections of loaded system DLLs (ntdll, kernel32, ...)
+/// against their files on disk, to find out what reflex_original.dll itself
+/// hooks. Each file is laid out and relocated like the loader would
+/// (pe::map_file, pe::relocate), so only real modifications remain.
+///
+/// Runs once after the original DLL's DllMain returns, and on demand via
+/// the `hookscan` pipe command. The IAT and the proxy's own hooks are
+/// skipped. Findings are logged and written to `path` as JSON.
+///
+/// ```toml
+/// [hook_scan]
+/// enabled = true
+/// modules = ["ntdll.dll", "kernel32.dll"]   # default: ntdll, kernel32, kernelbase, user32
+/// ```
+
+use crate::proxy;
+use crate::proxy_impl::config::HookScanConfig;
+use crate::proxy_impl::{hooks, patches, pe};
+use iced_x86::{Code, Decoder, DecoderOptions, FlowControl, OpKind};
+use serde::Serialize;
+use std::ffi::CString;
+use std::fs;
+use winapi::shared::minwindef::HMODULE;
+use winapi::um::libloaderapi::GetModuleHandleA;
+use winapi::um::winnt::IMAGE_DIRECTORY_ENTRY_IAT;
+
+/// Differences separated by at most this many equal bytes form one region
+const MERGE_GAP: usize = 4;
+/// Bytes decoded at the start of a region to find a jump destination
+const DECODE_WINDOW: usize = 32;
+
+/// A modified byte range in a loaded module
+#[derive(Debug, Clone, Serialize)]
+pub struct Modification {
+    pub module: String,
+    pub rva: String,
+    /// Nearest preceding export, e.g. "NtCreateFile+0x0"
+    pub symbol: Option<String>,
+    pub length: usize,
+    /// Bytes in the file on disk
+    pub disk: String,
+    /// Bytes in memory
+    pub memory: String,
+    /// Where the patched code jumps to, if it is a recognizable jump
+    pub destination: Option<String>,
+    /// Module owning `destination`; None for private memory
+    pub destination_module: Option<String>,
+}
+
+/// Result of one scan
+#[derive(Debug, Default, Serialize)]
+pub struct ScanReport {
+    pub scanned: Vec<String>,
+    pub skipped: Vec<String>,
+    pub modifications: Vec<Modification>,
+}
+
+/// Scan the configured modules, log the findings and write the report
+/// (unless `path` is empty)
+pub unsafe fn run(config: &HookScanConfig) -> Result<ScanReport, String> {
+    let report = scan(&config.modules);
+
+    for m in &report.modifications {
+        tracing::warn!(
+            "[hookscan] {}+{} ({}) modified: {} -> {}{}",
+            m.module,
+            m.rva,
+            m.symbol.as_deref().unwrap_or("?"),
+            m.disk,
+            m.memory,
+            match (&m.destination, &m.destination_module) {
+                (Some(dest), Some(module)) => format!(" => {} ({})", dest, module),
+                (Some(dest), None) => format!(" => {} (private memory)", dest),
+                _ => String::new(),
+            }
+        );
+    }
+    tracing::info!(
+        "[hookscan] {} modification(s) in {} module(s)",
+        report.modifications.len(),
+        report.scanned.len()
+    );
+
+    if !config.path.is_empty() {
+        let json = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+        fs::write(&config.path, json).map_err(|e| format!("Failed to write {}: {}", config.path, e))?;
+    }
+    Ok(report)
+}
+
+/// Compare each loaded module in `modules` against its file on disk
+///
+/// Modules that are not loaded or cannot be read are listed as skipped.
+pub unsafe fn scan(modules: &[String]) -> ScanReport {
+    let own = hooks::patched_ranges();
+    let mut report = ScanReport::default();
+
+    for name in modules {
+        let module = match CString::new(name.as_str()) {
+            Ok(c) => GetModuleHandleA(c.as_ptr()),
+            Err(_) => std::ptr::null_mut(),
+        };
+        if module.is_null() {
+            tracing::debug!("[hookscan] {} is not loaded, skipped", name);
+            report.skipped.push(name.clone());
+            continue;
+        }
+
+        match scan_module(name, module, &own) {
+            Ok(found) => {
+                report.scanned.push(name.clone());
+                report.modifications.extend(found);
+            }
+            Err(e) => {
+                tracing::warn!("[hookscan] Skipped {}: {}", name, e);
+                report.skipped.push(name.clone());
+            }
+        }
+    }
+
+    report
+}
+
+unsafe fn scan_module(name: &str, module: HMODULE, own: &[(usize, usize)]) -> Result<Vec<Modification>, String> {
+    let base = module as *const u8;
+    let path = proxy::get_module_path(module).ok_or("module path unavailable")?;
+    let data = fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut image = pe::map_file(&data).ok_or("invalid PE file on disk")?;
+    pe::relocate(&mut image, base as usize)?;
+
+    if pe::image_size(base) != Some(image.len() as u32) {
+        return Err("image on disk does not match the loaded module".to_string());
+    }
+
+    let iat = pe::data_directory(base, IMAGE_DIRECTORY_ENTRY_IAT)
+        .map(|(rva, size)| (rva as usize, (rva + size) as usize));
+    let skip = |rva: usize| {
+        iat.is_some_and(|(start, end)| rva >= start && rva < end)
+            || own
+                .iter()
+                .any(|&(address, len)| base as usize + rva >= address && base as usize + rva < address + len)
+    };
+
+    let mut exports: Vec<pe::Export> = pe::exports(base)
+        .into_iter()
+        .filter(|e| e.forwarder.is_none())
+        .collect();
+    exports.sort_by_key(|e| e.rva);
+
+    let mut found = Vec::new();
+    for section in pe::sections(base).iter().filter(|s| s.is_executable()) {
+        let start = section.rva as usize;
+        let end = (start + section.size as usize).min(image.len());
+        let memory = std::slice::from_raw_parts(base.add(start), end - start);
+
+        for (first, last) in diff_regions(&image[start..end], memory, |i| skip(start + i)) {
+            let rva = start + first;
+            let disk = &image[rva..start + last];
+            let current = &memory[first..last];
+            let destination = jump_destination(base as usize + rva, (end - rva).min(DECODE_WINDOW));
+
+            found.push(Modification {
+                module: name.to_string(),
+                rva: format!("0x{:x}", rva),
+                symbol: nearest_export(&exports, rva as u32),
+                length: current.len(),
+                disk: patches::hex(disk),
+                memory: patches::hex(current),
+                destination: destination.map(|d| format!("0x{:x}", d)),
+                destination_module: destination.and_then(|d| proxy::module_at(d)).and_then(|m| proxy::get_module_name(m)),
+            });
+        }
+    }
+
+    Ok(found)
+}
+
+/// Ranges `[first, last)` where `disk` and `memory` differ, merging ranges
+/// separated by at most `MERGE_GAP` equal bytes
+fn diff_regions(disk: &[u8], memory: &[u8], skip: impl Fn(usize) -> bool) -> Vec<(usize, usize)> {
+    let mut regions: Vec<(usize, usize)> = Vec::new();
+    for i in 0..disk.len().min(memory.len()) {
+        if disk[i] == memory[i] || skip(i) {
+            continue;
+        }
+        match regions.last_mut() {
+            Some((_, last)) if i - *last <= MERGE_GAP => *last = i + 1,
+            _ => regions.push((i, i + 1)),
+        }
+    }
+    regions
+}
+
+/// Destination of a jump written at `address`, for the common hook shapes:
+/// `jmp rel32`, `jmp qword ptr [rip+x]` and `mov rax, imm64; jmp rax`
+unsafe fn jump_destination(address: usize, len: usize) -> Option<usize> {
+    let bytes = std::slice::from_raw_parts(address as *const u8, len);
+    let mut decoder = Decoder::with_ip(64, bytes, address as u64, DecoderOptions::NONE);
+
+    let first = decoder.decode();
+    if first.is_invalid() {
+        return None;
+    }
+
+    if first.flow_control() == FlowControl::UnconditionalBranch && first.op0_kind() == OpKind::NearBranch64 {
+        return Some(first.near_branch_target() as usize);
+    }
+    if first.flow_control() == FlowControl::IndirectBranch && first.is_ip_rel_memory_operand() {
+        return Some(*(first.ip_rel_memory_address() as *const usize));
+    }
+    if first.code() == Code::Mov_r64_imm64 {
+        let second = decoder.decode();
+        if second.flow_control() == FlowControl::IndirectBranch
+            && second.op0_kind() == OpKind::Register
+            && second.op0_register() == first.op0_register()
+        {
+            return Some(first.immediate64() as usize);
+        }
+    }
+
+    None
+}
+
+/// "Name+0xN" of the closest export at or before `rva`
+fn nearest_export(exports: &[pe::Export], rva: u32) -> Option<String> {
+    let index = exports.partition_point(|e| e.rva <= rva).checked_sub(1)?;
+    let export = &exports[index];
+    let name = export
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("#{}", export.ordinal));
+    Some(format!("{}+0x{:x}", name, rva - export.rva))
+}
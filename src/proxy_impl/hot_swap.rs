@@ -0,0 +1,160 @@
+/// Replacing the original with another build without restarting the game
+///
+/// For iterating on a patched copy of the original: the `swap <path>`
+/// control command unloads reflex_original.dll and loads `path` in its
+/// place. A loaded DLL cannot be overwritten, so the copy is written next
+/// to it under another name, e.g. `swap C:\Games\Title\reflex_patched.dll`.
+///
+/// The swap
+/// 1. turns passthrough on, so no detour runs meanwhile
+/// 2. removes the hooks and reverts the patches inside the old image, and
+///    forwards DLL_PROCESS_DETACH to it
+/// 3. unloads it, loads `path` the way the original was loaded (`[loader]
+///    manual_map` and `search`) and forwards DLL_PROCESS_ATTACH
+/// 4. resolves the exports again, applies the configured `[[patches]]` and
+///    installs the removed IAT hooks on the new image
+/// 5. restores passthrough as it was
+///
+/// Inline and vtable hooks inside the old image are not reinstalled: they
+/// were placed by offset or found by scanning that build. The response
+/// names them. If `path` fails to load, the old file is loaded again the
+/// same way.
+///
+/// Game threads must not be inside the original while it is swapped, e.g.
+/// swap in a menu or with the game paused; calls made meanwhile return
+/// zero. A chained proxy (`[loader] next_proxy`) cannot be swapped. The
+/// command is only accepted on the control pipe (ipc.rs), not over HTTP.
+
+use crate::proxy;
+use crate::proxy_impl::{bindings, config, hooks, original_watch, os, patches, pe, sigscan};
+use crate::proxy_impl::config::PatchConfig;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use winapi::shared::minwindef::{FALSE, HINSTANCE};
+use winapi::um::winnt::{DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH};
+
+/// Held for the duration of a swap
+static SWAPPING: Mutex<()> = Mutex::new(());
+
+/// Replace the loaded original with the DLL at `path`
+pub fn swap(path: &str) -> Result<String, String> {
+    let _swapping = SWAPPING.try_lock().map_err(|_| "a swap is already in progress".to_string())?;
+    let path = PathBuf::from(path.trim_matches('"'));
+    if path.as_os_str().is_empty() {
+        return Err("usage: swap <path>".to_string());
+    }
+    if !path.is_file() {
+        return Err(format!("{} does not exist", path.display()));
+    }
+    if let Some(explanation) = proxy::architecture_mismatch(&path) {
+        return Err(explanation);
+    }
+
+    unsafe {
+        let old_base = proxy::get_original_dll_base();
+        if old_base.is_null() {
+            return Err("original DLL is not loaded".to_string());
+        }
+        if proxy::is_chained() {
+            return Err("the proxy is chained, swap the next proxy's original instead".to_string());
+        }
+        let hinst = proxy::get_proxy_module().ok_or("cannot find the proxy's module")? as HINSTANCE;
+        let size = pe::image_size(old_base as *const u8).unwrap_or(0) as usize;
+        let old_path = proxy::get_module_path(old_base);
+
+        let was_passthrough = hooks::passthrough();
+        hooks::set_passthrough(true);
+        let removed = hooks::remove_hooks_in(old_base as usize, old_base as usize + size);
+        let reverted = patches::revert_kept_in(old_base as usize, old_base as usize + size);
+        tracing::warn!(
+            "[hot_swap] Unloading the original at {:p}: removed {} hook(s), reverted {} patch(es)",
+            old_base,
+            removed.len(),
+            reverted
+        );
+
+        // The watched file and the scan cache belong to the old build
+        original_watch::stop();
+        sigscan::stop_caching();
+
+        let proxy_config = proxy::ProxyConfig::default();
+        proxy::forward_dllmain(hinst, DLL_PROCESS_DETACH, std::ptr::null_mut(), &proxy_config);
+        proxy::release_original_dll();
+        bindings::forget_originals();
+
+        // On failure the old build is set up again, as if swapped back to
+        let (loaded, failure) = match load(hinst, &path) {
+            Ok(()) => (path, None),
+            Err(e) => match old_path.map(|old| load(hinst, &old).map(|()| old)) {
+                Some(Ok(old)) => (old, Some(e)),
+                Some(Err(reload)) => {
+                    tracing::error!("[hot_swap] {}; reloading the old build failed too: {}", e, reload);
+                    return Err(format!("{}; reloading the old build failed too: {}", e, reload));
+                }
+                None => {
+                    tracing::error!("[hot_swap] {}; the old build has no file to load again", e);
+                    return Err(format!("{}; the old build has no file to load again", e));
+                }
+            },
+        };
+
+        let base = proxy::get_original_dll_base() as *const u8;
+        let own: Vec<PatchConfig> = config::get().patches.iter().filter(|p| p.module.is_none()).cloned().collect();
+        let applied = patches::apply_configured(base, &own);
+
+        let mut reinstalled = 0;
+        let mut dropped = Vec::new();
+        for (name, import) in removed {
+            let Some(import) = import else {
+                dropped.push(name);
+                continue;
+            };
+            match hooks::install_iat_hook(&name, base, &import.function, import.detour) {
+                Ok(_) => reinstalled += 1,
+                Err(e) => {
+                    tracing::error!("[hot_swap] Failed to reinstall {}: {}", name, e);
+                    dropped.push(name);
+                }
+            }
+        }
+        if !was_passthrough {
+            hooks::set_passthrough(false);
+        }
+
+        let mut summary = format!(
+            "loaded {} at {:p}, applied {}/{} patch(es), reinstalled {} hook(s)",
+            loaded.display(),
+            base,
+            applied,
+            own.len(),
+            reinstalled
+        );
+        if !dropped.is_empty() {
+            summary += &format!(", not reinstalled: {}", dropped.join(", "));
+        }
+        if let Some(e) = failure {
+            tracing::error!("[hot_swap] {}; {}", e, summary);
+            return Err(format!("{}; {}", e, summary));
+        }
+        tracing::warn!("[hot_swap] Swapped the original: {}", summary);
+        Ok(summary)
+    }
+}
+
+/// Load `path` as the original and forward DLL_PROCESS_ATTACH to it
+unsafe fn load(hinst: HINSTANCE, path: &Path) -> Result<(), String> {
+    let loader = &config::get().loader;
+    let search_flags = os::search_flags(&loader.search).unwrap_or(0);
+    let proxy_config = proxy::ProxyConfig::builder()
+        .original_dll(path)
+        .logging(true)
+        .manual_map(loader.manual_map)
+        .search_flags(search_flags)
+        .build();
+    proxy::initialize_proxy(&proxy_config)?;
+
+    if proxy::forward_dllmain(hinst, DLL_PROCESS_ATTACH, std::ptr::null_mut(), &proxy_config) == FALSE {
+        tracing::warn!("[hot_swap] DllMain of {} returned FALSE", path.display());
+    }
+    Ok(())
+}
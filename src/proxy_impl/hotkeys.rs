@@ -0,0 +1,152 @@
+/// Global hotkeys, polled on a background thread
+///
+/// Bindings come from the `[hotkeys]` section of reflex_proxy.toml:
+///
+/// ```toml
+/// [hotkeys]
+/// dump_ring = "Ctrl+F10"
+/// memory_snapshot = "Ctrl+F11"
+/// passthrough = "Ctrl+F12"
+/// ```
+///
+/// Keys are F1-F24, A-Z or 0-9, optionally combined with Ctrl, Shift, Alt.
+/// Polling with GetAsyncKeyState needs no window, so hotkeys work before
+/// (and regardless of whether) the game creates one, unless `[window] wait`
+/// holds them back until it does (window.rs).
+
+use crate::proxy_impl::config::{self, HotkeyConfig};
+use crate::proxy_impl::{hooks, logging, snapshot};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use winapi::um::winuser::{GetAsyncKeyState, VK_CONTROL, VK_F1, VK_MENU, VK_SHIFT};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Actions that can be bound to a hotkey
+#[derive(Debug, Clone, Copy)]
+pub enum HotkeyAction {
+    DumpRing,
+    MemorySnapshot,
+    Passthrough,
+}
+
+/// A key plus required modifiers, as virtual-key codes
+#[derive(Debug, Clone)]
+pub struct Hotkey {
+    pub modifiers: Vec<i32>,
+    pub key: i32,
+}
+
+impl Hotkey {
+    /// Parse "Ctrl+Shift+F10" style key combinations
+    pub fn parse(text: &str) -> Option<Hotkey> {
+        let mut modifiers = Vec::new();
+        let mut key = None;
+
+        for part in text.split('+').map(str::trim) {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.push(VK_CONTROL),
+                "shift" => modifiers.push(VK_SHIFT),
+                "alt" => modifiers.push(VK_MENU),
+                other => key = Some(parse_key(other)?),
+            }
+        }
+
+        Some(Hotkey {
+            modifiers,
+            key: key?,
+        })
+    }
+
+    fn is_pressed(&self) -> bool {
+        let down = |vk: i32| unsafe { GetAsyncKeyState(vk) } as u16 & 0x8000 != 0;
+        down(self.key) && self.modifiers.iter().all(|&m| down(m))
+    }
+}
+
+fn parse_key(name: &str) -> Option<i32> {
+    if let Some(number) = name.strip_prefix('f') {
+        if let Ok(n) = number.parse::<i32>() {
+            return (1..=24).contains(&n).then(|| VK_F1 + n - 1);
+        }
+    }
+
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Some(c.to_ascii_uppercase() as i32),
+        _ => None,
+    }
+}
+
+/// Start polling the configured hotkeys
+pub fn start(config: &HotkeyConfig) {
+    let mut bindings = Vec::new();
+
+    for (name, text, action) in [
+        ("dump_ring", &config.dump_ring, HotkeyAction::DumpRing),
+        ("memory_snapshot", &config.memory_snapshot, HotkeyAction::MemorySnapshot),
+        ("passthrough", &config.passthrough, HotkeyAction::Passthrough),
+    ] {
+        if let Some(text) = text {
+            match Hotkey::parse(text) {
+                Some(hotkey) => bindings.push((hotkey, action)),
+                None => tracing::warn!("[hotkeys] Invalid hotkey for {}: {}", name, text),
+            }
+        }
+    }
+
+    if bindings.is_empty() || RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    tracing::info!("[hotkeys] Polling {} hotkey(s)", bindings.len());
+
+    let spawned = std::thread::Builder::new()
+        .name("reflex-hotkeys".to_string())
+        .spawn(move || poll(bindings));
+
+    if let Err(e) = spawned {
+        RUNNING.store(false, Ordering::SeqCst);
+        tracing::error!("[hotkeys] Failed to spawn hotkey thread: {}", e);
+    }
+}
+
+/// Ask the polling thread to exit
+pub fn stop() {
+    RUNNING.store(false, Ordering::SeqCst);
+}
+
+fn poll(bindings: Vec<(Hotkey, HotkeyAction)>) {
+    let mut was_pressed = vec![false; bindings.len()];
+
+    while RUNNING.load(Ordering::SeqCst) {
+        for (i, (hotkey, action)) in bindings.iter().enumerate() {
+            let pressed = hotkey.is_pressed();
+            if pressed && !was_pressed[i] {
+                run(*action);
+            }
+            was_pressed[i] = pressed;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn run(action: HotkeyAction) {
+    tracing::info!("[hotkeys] {:?}", action);
+
+    match action {
+        HotkeyAction::DumpRing => match logging::dump_ring("hotkey") {
+            Ok((path, count)) => tracing::info!("[hotkeys] Dumped {} event(s) to {}", count, path),
+            Err(e) => tracing::warn!("[hotkeys] Ring dump failed: {}", e),
+        },
+        HotkeyAction::MemorySnapshot => match snapshot::toggle(&config::get().memory_diff) {
+            Ok(message) => tracing::info!("[hotkeys] {}", message),
+            Err(e) => tracing::warn!("[hotkeys] Memory snapshot failed: {}", e),
+        },
+        HotkeyAction::Passthrough => {
+            unsafe { hooks::set_passthrough(!hooks::passthrough()) };
+        }
+    }
+}
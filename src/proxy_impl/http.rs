@@ -0,0 +1,195 @@
+/// Local HTTP control endpoint
+///
+/// The control channel commands (ipc.rs) over HTTP on
+/// `http://127.0.0.1:<port>/`, for scripting with curl instead of a named
+/// pipe client:
+///
+/// | Request                      | Command                         |
+/// |------------------------------|---------------------------------|
+/// | `GET /status`                | `status`                        |
+/// | `GET /stats`                 | `stats`                         |
+/// | `GET /events`                | `events`                        |
+/// | `GET /hooks`                 | `hooks`                         |
+/// | `GET /metrics`               | Prometheus metrics (metrics.rs) |
+/// | `POST /hooks/<name>/suspend` | `suspend <name>`                |
+/// | `POST /hooks/<name>/resume`  | `resume <name>`                 |
+/// | `POST /log` (filter as body) | `log <filter>`                  |
+/// | `POST /<command>`            | `<command>`                     |
+/// | `POST /<command>/<argument>` | `<command> <argument>`          |
+///
+/// ```toml
+/// [http]
+/// enabled = true
+/// port = 9231
+/// ```
+///
+/// ```bash
+/// curl http://127.0.0.1:9231/status
+/// curl -X POST http://127.0.0.1:9231/hooks/DeleteFileW/suspend
+/// curl -X POST -d "info,reflex::proxy_impl::detours=trace" http://127.0.0.1:9231/log
+/// ```
+///
+/// Only loopback connections are accepted, one request per connection.
+/// Requests from web pages are refused with 403: those carrying an `Origin`
+/// header (cross-site POSTs) and those whose `Host` is not
+/// `127.0.0.1:<port>` or `localhost:<port>` (DNS rebinding). `swap` is
+/// refused with 403 too, as it loads a DLL. Command errors are answered
+/// with 400 and the message.
+
+use crate::proxy_impl::config::HttpConfig;
+use crate::proxy_impl::{ipc, metrics};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::time::Duration;
+
+/// Largest request body read
+const MAX_BODY: usize = 64 * 1024;
+/// Route of `GET /metrics`, answered here rather than by a control command
+/// since the response spans many lines
+const METRICS: &str = "<metrics>";
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static PORT: AtomicU16 = AtomicU16::new(0);
+
+/// Start the server on a background thread
+pub fn start(config: &HttpConfig) -> Result<(), String> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, config.port)).map_err(|e| {
+        RUNNING.store(false, Ordering::SeqCst);
+        format!("Failed to listen on 127.0.0.1:{}: {}", config.port, e)
+    })?;
+    PORT.store(config.port, Ordering::SeqCst);
+
+    std::thread::Builder::new()
+        .name("reflex-http".to_string())
+        .spawn(move || serve(listener))
+        .map_err(|e| {
+            RUNNING.store(false, Ordering::SeqCst);
+            format!("Failed to spawn HTTP thread: {}", e)
+        })?;
+
+    tracing::info!("[http] Control endpoint listening on http://127.0.0.1:{}/", config.port);
+    Ok(())
+}
+
+/// Ask the server thread to exit
+pub fn stop() {
+    if !RUNNING.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    // Wake the thread blocked in accept()
+    let _ = TcpStream::connect_timeout(
+        &SocketAddr::from((Ipv4Addr::LOCALHOST, PORT.load(Ordering::SeqCst))),
+        Duration::from_millis(100),
+    );
+}
+
+fn serve(listener: TcpListener) {
+    for connection in listener.incoming() {
+        if !RUNNING.load(Ordering::SeqCst) {
+            break;
+        }
+        match connection {
+            Ok(stream) => {
+                if let Err(e) = handle(stream) {
+                    tracing::debug!("[http] Request failed: {}", e);
+                }
+            }
+            Err(e) => tracing::debug!("[http] accept failed: {}", e),
+        }
+    }
+    tracing::info!("[http] Control endpoint stopped");
+}
+
+fn handle(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+    let mut reader = BufReader::new(&stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    let (mut host, mut origin) = (None, None);
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("host") {
+                host = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("origin") {
+                origin = Some(value.to_string());
+            }
+        }
+    }
+    let mut body = vec![0u8; content_length.min(MAX_BODY)];
+    reader.read_exact(&mut body)?;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+    let routed = if origin.is_some() || !is_local_host(host.as_deref(), PORT.load(Ordering::SeqCst)) {
+        tracing::warn!("[http] Refused {} {} from a web page (Host {:?}, Origin {:?})", method, path, host, origin);
+        Err(("403 Forbidden", "requests from web pages are not accepted".to_string()))
+    } else {
+        route(method, path, &String::from_utf8_lossy(&body))
+    };
+    let (status, content_type, body) = match routed {
+        Ok(command) if command == METRICS => ("200 OK", "text/plain; version=0.0.4", metrics::render()),
+        Ok(command) => {
+            let response = ipc::handle_command(&command);
+            match response.strip_prefix("error: ") {
+                Some(message) => ("400 Bad Request", "text/plain", message.to_string()),
+                None if response.starts_with(['{', '[']) => ("200 OK", "application/json", response),
+                None => ("200 OK", "text/plain", response),
+            }
+        }
+        Err((status, message)) => (status, "text/plain", message),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// Whether a `Host` header names the loopback endpoint on `port`; a page
+/// using DNS rebinding sends its own host name
+pub fn is_local_host(host: Option<&str>, port: u16) -> bool {
+    let Some((name, host_port)) = host.and_then(|host| host.rsplit_once(':')) else {
+        return false;
+    };
+    (name == "127.0.0.1" || name.eq_ignore_ascii_case("localhost")) && host_port.parse() == Ok(port)
+}
+
+/// The control command for a request
+fn route(method: &str, path: &str, body: &str) -> Result<String, (&'static str, String)> {
+    let path = path.split('?').next().unwrap_or("").trim_matches('/');
+    let segments: Vec<&str> = path.split('/').collect();
+
+    match (method, segments.as_slice()) {
+        ("GET", ["status" | "stats" | "events" | "hooks"]) => Ok(path.to_string()),
+        ("GET", ["metrics"]) => Ok(METRICS.to_string()),
+        ("POST", ["hooks", name, action @ ("suspend" | "resume")]) => Ok(format!("{} {}", action, name)),
+        ("POST", ["log"]) => Ok(format!("log {}", body.trim())),
+        // Loads any DLL into the game; the pipe only takes the user's own
+        // connections, the port any local user's
+        ("POST", ["swap", ..]) => Err(("403 Forbidden", "swap is only accepted on the control pipe".to_string())),
+        ("POST", [command]) if !command.is_empty() => Ok(command.to_string()),
+        ("POST", [command, argument]) => Ok(format!("{} {}", command, argument)),
+        ("GET" | "POST", _) => Err(("404 Not Found", format!("no route for {} /{}", method, path))),
+        _ => Err(("405 Method Not Allowed", format!("{} is not supported", method))),
+    }
+}
@@ -0,0 +1,70 @@
+/// Dump of the original's in-memory image
+///
+/// The `imagedump` pipe command writes reflex_original.dll as it is mapped
+/// right now (unpacked, relocated, patched by itself or by `[[patches]]`)
+/// to `path`. The headers are rewritten so disassemblers load the file
+/// with every section at its RVA and the image based where it was loaded
+/// (pe.rs `unmap_headers`), so addresses match the live process and the
+/// on-disk file can be compared against it.
+///
+/// ```toml
+/// [image_dump]
+/// path = "reflex_original.dump.dll"
+/// ```
+///
+/// Pages the original made unreadable are written as zeros and counted in
+/// the response. Imports are left as resolved at load time.
+
+use crate::proxy;
+use crate::proxy_impl::config::ImageDumpConfig;
+use crate::proxy_impl::pe;
+use std::fs;
+use winapi::um::memoryapi::ReadProcessMemory;
+use winapi::um::processthreadsapi::GetCurrentProcess;
+
+const PAGE_SIZE: usize = 0x1000;
+
+/// Write the image; returns the path, its size and the unreadable pages
+pub fn write(config: &ImageDumpConfig) -> Result<(String, usize, usize), String> {
+    if config.path.is_empty() {
+        return Err("image dump file is disabled".to_string());
+    }
+    let base = unsafe { proxy::get_original_dll_base() } as usize;
+    let (mut image, unreadable) = unsafe { read_image(base) }?;
+    let size = image.len();
+
+    pe::unmap_headers(&mut image, base)?;
+    fs::write(&config.path, &image).map_err(|e| format!("Failed to write {}: {}", config.path, e))?;
+    tracing::info!(
+        "[image_dump] Wrote {} byte(s) to {} ({} unreadable page(s))",
+        size,
+        config.path,
+        unreadable
+    );
+    Ok((config.path.clone(), size, unreadable))
+}
+
+/// Copy of the image mapped at `base` and the number of pages that could not
+/// be read (zeroed in the copy)
+pub unsafe fn read_image(base: usize) -> Result<(Vec<u8>, usize), String> {
+    let size = pe::image_size(base as *const u8).ok_or("the original DLL is not loaded")? as usize;
+
+    let mut image = vec![0u8; size];
+    let mut unreadable = 0;
+    for offset in (0..size).step_by(PAGE_SIZE) {
+        let len = PAGE_SIZE.min(size - offset);
+        let mut read = 0;
+        let ok = ReadProcessMemory(
+            GetCurrentProcess(),
+            (base + offset) as *const _,
+            image[offset..].as_mut_ptr() as *mut _,
+            len,
+            &mut read,
+        );
+        if ok == 0 || read != len {
+            image[offset..offset + len].fill(0);
+            unreadable += 1;
+        }
+    }
+    Ok((image, unreadable))
+}
@@ -0,0 +1,385 @@
+/// Installer exports callable through rundll32
+///
+/// Replaces the manual deployment steps from the README:
+/// 1. Rename the real reflex.dll to reflex_original.dll
+/// 2. Copy this proxy into place as reflex.dll
+/// 3. Verify both files ended up where they belong
+///
+/// Usage:
+/// `rundll32.exe path\to\reflex.dll,ReflexProxyInstall C:\Games\MyGame`
+/// `rundll32.exe C:\Games\MyGame\reflex.dll,ReflexProxyUninstall C:\Games\MyGame /purge`
+
+use crate::proxy;
+use crate::proxy_impl::hash;
+use crate::proxy_impl::wide::to_wide;
+use std::ffi::CStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use winapi::shared::minwindef::HINSTANCE;
+use winapi::shared::windef::HWND;
+use winapi::um::winnt::LPSTR;
+use winapi::um::winuser::{MessageBoxW, MB_ICONERROR, MB_ICONINFORMATION, MB_OK};
+
+/// File name the game loads
+pub const PROXY_FILE_NAME: &str = "reflex.dll";
+/// File name the real DLL is renamed to
+pub const ORIGINAL_FILE_NAME: &str = "reflex_original.dll";
+/// Temporary name for the proxy while it is being removed
+const REMOVED_FILE_NAME: &str = "reflex.dll.uninstalled";
+/// Files the proxy generates in the game directory (removed with /purge)
+const GENERATED_FILES: &[&str] = &[
+    "reflex.log",
+    "reflex_proxy.toml",
+    "reflex_selftest.txt",
+    "reflex_audit.jsonl",
+    "reflex_ring.log",
+    "reflex_trace.json",
+    "reflex_trace.bin",
+    "reflex_log.jsonl",
+    "reflex_hookscan.json",
+    "reflex_profile.json",
+    "reflex_profile.folded",
+    "reflex_profile.speedscope.json",
+    "reflex_calls.jsonl",
+    "reflex_coverage.json",
+    "reflex_metrics.prom",
+    "reflex_memdiff.txt",
+    "reflex_original.dump.dll",
+    "reflex_sigcache.json",
+    "reflex_rtti.json",
+    "reflex_hang.dmp",
+    "reflex_sleep.json",
+    "reflex_qpc.jsonl",
+    "reflex_pacing.json",
+    "reflex_environment.json",
+];
+
+/// rundll32 entry point: install the proxy into the given game directory
+///
+/// The command line is the game directory (optionally quoted).
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn ReflexProxyInstall(
+    hwnd: HWND,
+    _hinst: HINSTANCE,
+    cmd_line: LPSTR,
+    _n_cmd_show: i32,
+) {
+    let args = parse_arguments(cmd_line);
+    let game_dir = directory_argument(&args);
+
+    match install_proxy(&game_dir) {
+        Ok(()) => {
+            tracing::info!("[install] Proxy installed into {}", game_dir.display());
+            show_result(
+                hwnd,
+                &format!("Reflex proxy installed into:\n{}", game_dir.display()),
+                false,
+            );
+        }
+        Err(e) => {
+            tracing::error!("[install] Installation failed: {}", e);
+            show_result(hwnd, &format!("Reflex proxy installation failed:\n{}", e), true);
+        }
+    }
+}
+
+/// rundll32 entry point: remove the proxy and restore the original DLL
+///
+/// The command line is the game directory (optionally quoted), followed by
+/// `/purge` to also delete generated logs and config.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn ReflexProxyUninstall(
+    hwnd: HWND,
+    _hinst: HINSTANCE,
+    cmd_line: LPSTR,
+    _n_cmd_show: i32,
+) {
+    let args = parse_arguments(cmd_line);
+    let game_dir = directory_argument(&args);
+    let purge = args.iter().any(|a| a.eq_ignore_ascii_case("/purge"));
+
+    match uninstall_proxy(&game_dir, purge) {
+        Ok(()) => {
+            tracing::info!("[install] Proxy removed from {}", game_dir.display());
+            show_result(
+                hwnd,
+                &format!("Reflex proxy removed from:\n{}", game_dir.display()),
+                false,
+            );
+        }
+        Err(e) => {
+            tracing::error!("[install] Uninstall failed: {}", e);
+            show_result(hwnd, &format!("Reflex proxy uninstall failed:\n{}", e), true);
+        }
+    }
+}
+
+/// Install the proxy into `game_dir`
+///
+/// If anything fails after the original has been renamed, the rename is
+/// rolled back so the game directory is never left without a reflex.dll.
+pub fn install_proxy(game_dir: &Path) -> Result<(), String> {
+    if !game_dir.is_dir() {
+        return Err(format!("Not a directory: {}", game_dir.display()));
+    }
+
+    let proxy_source = unsafe { proxy::get_proxy_module_path() }
+        .ok_or_else(|| "Failed to determine the proxy DLL path".to_string())?;
+    let proxy_bytes = fs::read(&proxy_source)
+        .map_err(|e| format!("Failed to read proxy {}: {}", proxy_source.display(), e))?;
+
+    let target = game_dir.join(PROXY_FILE_NAME);
+    let original = game_dir.join(ORIGINAL_FILE_NAME);
+
+    tracing::info!("[install] Proxy source: {}", proxy_source.display());
+    tracing::info!("[install] Target directory: {}", game_dir.display());
+
+    if !target.exists() {
+        return Err(format!("{} not found in {}", PROXY_FILE_NAME, game_dir.display()));
+    }
+
+    // Re-running the installer on an installed directory is not an error
+    if original.exists() && file_matches(&target, &proxy_bytes) {
+        tracing::info!("[install] Proxy is already installed");
+        return verify_installation(&target, &original, &proxy_bytes, None);
+    }
+
+    // Don't guess which of the two files is the real one
+    if original.exists() {
+        return Err(format!(
+            "Both {} and {} exist, but {} is not this proxy. Resolve manually.",
+            PROXY_FILE_NAME, ORIGINAL_FILE_NAME, PROXY_FILE_NAME
+        ));
+    }
+
+    let original_len = fs::metadata(&target)
+        .map_err(|e| format!("Failed to stat {}: {}", target.display(), e))?
+        .len();
+
+    fs::rename(&target, &original).map_err(|e| {
+        format!(
+            "Failed to rename {} to {}: {}",
+            target.display(),
+            original.display(),
+            e
+        )
+    })?;
+    tracing::info!("[install] Renamed {} -> {}", PROXY_FILE_NAME, ORIGINAL_FILE_NAME);
+
+    let result = fs::write(&target, &proxy_bytes)
+        .map_err(|e| format!("Failed to copy proxy to {}: {}", target.display(), e))
+        .and_then(|_| verify_installation(&target, &original, &proxy_bytes, Some(original_len)));
+
+    if let Err(e) = result {
+        rollback(&target, &original);
+        return Err(e);
+    }
+
+    tracing::info!("[install] Copied proxy to {}", target.display());
+    Ok(())
+}
+
+/// Remove the proxy from `game_dir` and restore the original filename
+///
+/// Every file is hashed before it is touched: reflex.dll must be this proxy
+/// and reflex_original.dll must not be, otherwise nothing is modified.
+pub fn uninstall_proxy(game_dir: &Path, purge: bool) -> Result<(), String> {
+    let target = game_dir.join(PROXY_FILE_NAME);
+    let original = game_dir.join(ORIGINAL_FILE_NAME);
+    let removed = game_dir.join(REMOVED_FILE_NAME);
+
+    if !original.exists() {
+        return Err(format!(
+            "{} not found in {} - proxy does not appear to be installed",
+            ORIGINAL_FILE_NAME,
+            game_dir.display()
+        ));
+    }
+
+    let proxy_source = unsafe { proxy::get_proxy_module_path() }
+        .ok_or_else(|| "Failed to determine the proxy DLL path".to_string())?;
+    let proxy_hash = hash::sha256_file(&proxy_source)?;
+    let original_hash = hash::sha256_file(&original)?;
+
+    tracing::info!("[install] Proxy hash:    {}", proxy_hash);
+    tracing::info!("[install] Original hash: {}", original_hash);
+
+    if original_hash == proxy_hash {
+        return Err(format!(
+            "{} is a copy of the proxy, refusing to restore it",
+            ORIGINAL_FILE_NAME
+        ));
+    }
+
+    if target.exists() {
+        let target_hash = hash::sha256_file(&target)?;
+        tracing::info!("[install] Target hash:   {}", target_hash);
+
+        if target_hash != proxy_hash {
+            return Err(format!(
+                "{} does not match this proxy (hash {}), refusing to remove it. \
+                 Run the uninstaller from the installed {}.",
+                PROXY_FILE_NAME, target_hash, PROXY_FILE_NAME
+            ));
+        }
+
+        // Renaming works even while the DLL is loaded (e.g. by rundll32)
+        if removed.exists() {
+            fs::remove_file(&removed)
+                .map_err(|e| format!("Failed to remove stale {}: {}", removed.display(), e))?;
+        }
+        fs::rename(&target, &removed)
+            .map_err(|e| format!("Failed to move proxy out of the way: {}", e))?;
+    }
+
+    if let Err(e) = fs::rename(&original, &target) {
+        if removed.exists() {
+            let _ = fs::rename(&removed, &target);
+        }
+        return Err(format!(
+            "Failed to rename {} back to {}: {}",
+            ORIGINAL_FILE_NAME, PROXY_FILE_NAME, e
+        ));
+    }
+    tracing::info!("[install] Renamed {} -> {}", ORIGINAL_FILE_NAME, PROXY_FILE_NAME);
+
+    let restored_hash = hash::sha256_file(&target)?;
+    if restored_hash != original_hash {
+        return Err(format!(
+            "Verification failed: restored {} hash {} does not match {}",
+            PROXY_FILE_NAME, restored_hash, original_hash
+        ));
+    }
+
+    if removed.exists() {
+        if let Err(e) = fs::remove_file(&removed) {
+            tracing::warn!(
+                "[install] Could not delete {} ({}), delete it once the game is closed",
+                removed.display(),
+                e
+            );
+        }
+    }
+
+    if purge {
+        for name in GENERATED_FILES {
+            let path = game_dir.join(name);
+            if !path.exists() {
+                continue;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => tracing::info!("[install] Deleted {}", path.display()),
+                Err(e) => tracing::warn!("[install] Failed to delete {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the proxy and the renamed original are both in place
+fn verify_installation(
+    target: &Path,
+    original: &Path,
+    proxy_bytes: &[u8],
+    original_len: Option<u64>,
+) -> Result<(), String> {
+    if !file_matches(target, proxy_bytes) {
+        return Err(format!("Verification failed: {} does not match the proxy", target.display()));
+    }
+
+    let len = fs::metadata(original)
+        .map_err(|e| format!("Verification failed: {} missing: {}", original.display(), e))?
+        .len();
+
+    if let Some(expected) = original_len {
+        if len != expected {
+            return Err(format!(
+                "Verification failed: {} is {} bytes, expected {}",
+                original.display(),
+                len,
+                expected
+            ));
+        }
+    }
+
+    tracing::info!("[install] Verified installation ({} bytes original)", len);
+    Ok(())
+}
+
+/// Undo a partially completed installation
+fn rollback(target: &Path, original: &Path) {
+    tracing::warn!("[install] Rolling back installation");
+
+    if target.exists() {
+        if let Err(e) = fs::remove_file(target) {
+            tracing::error!("[install] Failed to remove {}: {}", target.display(), e);
+            return;
+        }
+    }
+
+    if let Err(e) = fs::rename(original, target) {
+        tracing::error!(
+            "[install] Failed to restore {}: {} - rename it back manually!",
+            original.display(),
+            e
+        );
+    }
+}
+
+// ============================================================================
+// Utility Functions
+// ============================================================================
+
+/// Compare a file on disk against expected contents
+pub(crate) fn file_matches(path: &Path, expected: &[u8]) -> bool {
+    fs::read(path).map(|bytes| bytes == expected).unwrap_or(false)
+}
+
+/// Split a rundll32 command line into arguments, honouring double quotes
+pub(crate) unsafe fn parse_arguments(cmd_line: LPSTR) -> Vec<String> {
+    if cmd_line.is_null() {
+        return Vec::new();
+    }
+
+    let line = CStr::from_ptr(cmd_line).to_string_lossy();
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    args.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        args.push(current);
+    }
+
+    args
+}
+
+/// The first non-switch argument as a directory (defaults to the current one)
+pub(crate) fn directory_argument(args: &[String]) -> PathBuf {
+    args.iter()
+        .find(|a| !a.starts_with('/'))
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Show the result to the user who invoked rundll32
+pub(crate) unsafe fn show_result(hwnd: HWND, message: &str, is_error: bool) {
+    let text = to_wide(message);
+    let caption = to_wide("Reflex Proxy");
+    let icon = if is_error { MB_ICONERROR } else { MB_ICONINFORMATION };
+
+    MessageBoxW(hwnd, text.as_ptr(), caption.as_ptr(), MB_OK | icon);
+}
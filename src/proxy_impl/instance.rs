@@ -0,0 +1,292 @@
+/// Coordination between copies of the proxy in one process
+///
+/// The same proxy can end up loaded twice: as version.dll and as reflex.dll,
+/// or as two copies under different names. Instead of each copy keeping
+/// its own log, reading the config on its own and installing the same
+/// hooks, they share a section of memory, `Local\ReflexProxy-<pid>`:
+/// - the first copy to attach creates it and is the primary; it publishes
+///   the config file it reads, an entry point for log events and one for
+///   its status
+/// - a later copy (a secondary) checks the section's layout version, reads
+///   the primary's config file, sends its log events to the primary's
+///   sinks and registers its own status entry point
+/// - every copy's status lists the other copies' status (`peers`), so one
+///   `status` query shows every hook in the process
+///
+/// Secondaries start in passthrough (hooks.rs), so hooks are installed
+/// once. A copy finding a section of another layout version shares
+/// nothing and runs passive the same way.
+/// Entry points are only called while their module is pinned: a secondary
+/// keeps the primary loaded for as long as it is registered, and a status
+/// query holds the peer it calls. A primary detaching (at process exit)
+/// withdraws its entry points, and its secondaries log on their own from
+/// then on.
+
+use crate::proxy_impl::config;
+use crate::proxy_impl::wide::to_wide;
+use serde::Serialize;
+use std::ffi::OsString;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use tracing::Level;
+use winapi::shared::minwindef::{DWORD, HMODULE, MAX_PATH};
+use winapi::shared::winerror::ERROR_ALREADY_EXISTS;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::libloaderapi::{FreeLibrary, GetModuleHandleExW, GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS};
+use winapi::um::memoryapi::{CreateFileMappingW, MapViewOfFile, UnmapViewOfFile, FILE_MAP_ALL_ACCESS};
+use winapi::um::processthreadsapi::GetCurrentProcessId;
+use winapi::um::winnt::{HANDLE, PAGE_READWRITE};
+
+/// "RFXS"
+const MAGIC: u32 = 0x5358_4652;
+/// Layout of `Shared`; bumped on any change to it
+const VERSION: u32 = 1;
+/// Copies registered in the section, the primary included
+const MAX_PEERS: usize = 8;
+
+/// Receives a log event from a secondary: level (1 = error .. 5 = trace),
+/// target and line, both UTF-8
+pub type PeerLogFn = unsafe extern "system" fn(DWORD, *const u8, usize, *const u8, usize);
+/// `ReflexProxyGetStatus` without the peers
+pub type PeerStatusFn = unsafe extern "system" fn(*mut u8, DWORD) -> DWORD;
+
+/// The shared section; zeroed when created
+#[repr(C)]
+struct Shared {
+    /// Written last by the primary, once the rest is filled in
+    magic: AtomicU32,
+    version: u32,
+    /// `PeerLogFn` of the primary, 0 once it detached
+    log: AtomicUsize,
+    /// Config file the primary reads, NUL-terminated UTF-16
+    config_path: [u16; MAX_PATH],
+    peers: [Peer; MAX_PEERS],
+}
+
+#[repr(C)]
+struct Peer {
+    /// Module of the copy, 0 for a free slot
+    module: AtomicUsize,
+    status: AtomicUsize,
+}
+
+/// What this copy is to the others
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// No section could be created; the copy is on its own
+    Standalone,
+    Primary,
+    Secondary,
+    /// A copy of another layout version holds the section
+    Passive,
+}
+
+static mut MAPPING: HANDLE = std::ptr::null_mut();
+static mut SHARED: *const Shared = std::ptr::null();
+static mut ROLE: Role = Role::Standalone;
+static mut MODULE: usize = 0;
+/// The primary's module, pinned while this secondary is registered
+static mut PRIMARY: HMODULE = std::ptr::null_mut();
+
+/// Join the process's section, creating it if this is the first copy
+///
+/// Called before the config is read: a secondary reads the primary's file.
+pub unsafe fn claim(module: usize, status: PeerStatusFn, log: PeerLogFn) -> Role {
+    let name = format!("Local\\ReflexProxy-{}", GetCurrentProcessId());
+    let wide = to_wide(&name);
+    let size = std::mem::size_of::<Shared>() as DWORD;
+    let mapping = CreateFileMappingW(INVALID_HANDLE_VALUE, std::ptr::null_mut(), PAGE_READWRITE, 0, size, wide.as_ptr());
+    if mapping.is_null() {
+        tracing::warn!("[instance] Failed to create {}: error {}", name, GetLastError());
+        return Role::Standalone;
+    }
+    let existed = GetLastError() == ERROR_ALREADY_EXISTS;
+    let shared = MapViewOfFile(mapping, FILE_MAP_ALL_ACCESS, 0, 0, 0) as *const Shared;
+    if shared.is_null() {
+        tracing::warn!("[instance] Failed to map {}: error {}", name, GetLastError());
+        CloseHandle(mapping);
+        return Role::Standalone;
+    }
+    let shared_ref = &*shared;
+
+    let role = if !existed {
+        let writable = shared as *mut Shared;
+        (*writable).version = VERSION;
+        (*writable).config_path = config_path();
+        shared_ref.log.store(log as usize, Ordering::Release);
+        shared_ref.magic.store(MAGIC, Ordering::Release);
+        Role::Primary
+    } else if shared_ref.magic.load(Ordering::Acquire) != MAGIC || shared_ref.version != VERSION {
+        tracing::warn!("[instance] {} belongs to an incompatible copy of the proxy", name);
+        UnmapViewOfFile(shared as _);
+        CloseHandle(mapping);
+        ROLE = Role::Passive;
+        return Role::Passive;
+    } else {
+        let path = &shared_ref.config_path;
+        let len = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+        if len > 0 {
+            config::set_path(PathBuf::from(OsString::from_wide(&path[..len])));
+        }
+        // Every log event calls into the primary
+        let log = shared_ref.log.load(Ordering::Acquire);
+        if log != 0 {
+            PRIMARY = pin(log).unwrap_or(std::ptr::null_mut());
+        }
+        Role::Secondary
+    };
+
+    // Slots are claimed with a compare-exchange; copies attach under the
+    // loader lock, but the section outlives any one of them
+    let slot = shared_ref.peers.iter().find(|peer| {
+        peer.module
+            .compare_exchange(0, module, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    });
+    match slot {
+        Some(peer) => peer.status.store(status as usize, Ordering::Release),
+        None => tracing::warn!("[instance] {} has no free slot, status is not shared", name),
+    }
+
+    MAPPING = mapping;
+    SHARED = shared;
+    ROLE = role;
+    MODULE = module;
+    role
+}
+
+/// Take a reference on the module containing `address`, so it stays
+/// mapped until `FreeLibrary`; None once no module contains it
+unsafe fn pin(address: usize) -> Option<HMODULE> {
+    let mut module = std::ptr::null_mut();
+    let pinned = GetModuleHandleExW(GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS, address as *const u16, &mut module);
+    (pinned != 0).then_some(module)
+}
+
+/// The config file the primary reads, absolute so a secondary finds it
+/// from any working directory
+fn config_path() -> [u16; MAX_PATH] {
+    let mut buffer = [0u16; MAX_PATH];
+    let path = config::path();
+    let path = std::env::current_dir().map_or_else(|_| path.to_path_buf(), |dir| dir.join(path));
+    let wide: Vec<u16> = path.as_os_str().encode_wide().collect();
+    if wide.len() < MAX_PATH {
+        buffer[..wide.len()].copy_from_slice(&wide);
+    }
+    buffer
+}
+
+pub fn role() -> Role {
+    unsafe { ROLE }
+}
+
+fn shared() -> Option<&'static Shared> {
+    unsafe { SHARED.as_ref() }
+}
+
+/// Whether log events go to the primary's sinks instead of this copy's
+pub fn logs_to_primary() -> bool {
+    role() == Role::Secondary
+        && unsafe { !PRIMARY.is_null() }
+        && shared().is_some_and(|shared| shared.log.load(Ordering::Acquire) != 0)
+}
+
+/// Hand a log event to the primary; false if it is gone
+pub fn send_log(level: Level, target: &str, line: &str) -> bool {
+    if !logs_to_primary() {
+        return false;
+    }
+    let log = match shared().map(|shared| shared.log.load(Ordering::Acquire)) {
+        Some(log) if log != 0 => log,
+        _ => return false,
+    };
+    let level = match level {
+        Level::ERROR => 1,
+        Level::WARN => 2,
+        Level::INFO => 3,
+        Level::DEBUG => 4,
+        _ => 5,
+    };
+    // SAFETY: the primary stored a `PeerLogFn`, and `PRIMARY` keeps it mapped
+    let log = unsafe { std::mem::transmute::<usize, PeerLogFn>(log) };
+    unsafe { log(level, target.as_ptr(), target.len(), line.as_ptr(), line.len()) };
+    true
+}
+
+/// Level of a `PeerLogFn` call
+pub fn peer_level(level: DWORD) -> Level {
+    match level {
+        1 => Level::ERROR,
+        2 => Level::WARN,
+        4 => Level::DEBUG,
+        5 => Level::TRACE,
+        _ => Level::INFO,
+    }
+}
+
+/// Status of every other copy in the process, as JSON
+pub fn peer_statuses() -> Vec<serde_json::Value> {
+    let shared = match shared() {
+        Some(shared) => shared,
+        None => return Vec::new(),
+    };
+    let own = unsafe { MODULE };
+    shared
+        .peers
+        .iter()
+        .filter(|peer| {
+            let module = peer.module.load(Ordering::Acquire);
+            module != 0 && module != own
+        })
+        .filter_map(|peer| unsafe {
+            let address = peer.status.load(Ordering::Acquire);
+            if address == 0 {
+                return None;
+            }
+            // Pinned, then checked again: a peer that withdrew meanwhile
+            // may be unloaded
+            let module = pin(address)?;
+            let status = (peer.status.load(Ordering::Acquire) == address).then(|| {
+                // SAFETY: the peer stored a `PeerStatusFn`
+                let status = std::mem::transmute::<usize, PeerStatusFn>(address);
+                let mut buffer = vec![0u8; status(std::ptr::null_mut(), 0) as usize];
+                let written = status(buffer.as_mut_ptr(), buffer.len() as DWORD) as usize;
+                buffer.truncate(written.saturating_sub(1));
+                serde_json::from_slice(&buffer).ok()
+            });
+            FreeLibrary(module);
+            status.flatten()
+        })
+        .collect()
+}
+
+/// Leave the section, on DLL_PROCESS_DETACH
+///
+/// The primary is unpinned unless the process is `terminating`, when the
+/// loader unloads everything anyway.
+pub unsafe fn release(terminating: bool) {
+    if let Some(shared) = shared() {
+        if let Some(peer) = shared.peers.iter().find(|peer| peer.module.load(Ordering::Acquire) == MODULE) {
+            peer.status.store(0, Ordering::Release);
+            peer.module.store(0, Ordering::Release);
+        }
+        if ROLE == Role::Primary {
+            shared.log.store(0, Ordering::Release);
+        }
+        UnmapViewOfFile(SHARED as _);
+        SHARED = std::ptr::null();
+    }
+    if !MAPPING.is_null() {
+        CloseHandle(MAPPING);
+        MAPPING = std::ptr::null_mut();
+    }
+    if !PRIMARY.is_null() {
+        if !terminating {
+            FreeLibrary(PRIMARY);
+        }
+        PRIMARY = std::ptr::null_mut();
+    }
+}
@@ -0,0 +1,362 @@
+/// Named-pipe control channel
+///
+/// A background thread serves `\\.\pipe\reflex-proxy-<pid>`, so a running
+/// session can be inspected and controlled without restarting the game.
+///
+/// Protocol: one text command per line, one response line per command.
+///
+/// | Command                    | Response                                   |
+/// |----------------------------|--------------------------------------------|
+/// | `status`                   | JSON status (see status.rs)                |
+/// | `hooks`                    | JSON list of installed hooks               |
+/// | `suspend <hook>`           | `ok` or `error: <message>`                 |
+/// | `resume <hook>`            | `ok` or `error: <message>`                 |
+/// | `passthrough [on|off]`     | whether every hook is suspended            |
+/// | `dump`                     | ring buffer dump file and size             |
+/// | `trace`                    | Chrome trace file and size                 |
+/// | `hookscan`                 | modified system DLL ranges                 |
+/// | `snapshot`                 | snapshot, or diff against the last one     |
+/// | `imagedump`                | in-memory image of the original written    |
+/// | `rtti`                     | class count and JSON file (analysis.rs)    |
+/// | `rtti <class>`             | address of the class's vtable              |
+/// | `strings <text>`           | JSON list of code referencing the text     |
+/// | `callers <address>`        | JSON list of calls to the function         |
+/// | `profile`                  | profile file and sample count              |
+/// | `profile start`            | `ok`; starts a fresh profile               |
+/// | `profile stop`             | stops sampling, writes profile             |
+/// | `coverage`                 | export coverage file and counts            |
+/// | `dryrun <export> <value>`  | `ok`; stops forwarding, returns the value  |
+/// | `dryrun <export> off`      | `ok`; forwards the export again            |
+/// | `metrics`                  | Prometheus metrics file path               |
+/// | `stats`                    | JSON error and export call counts          |
+/// | `events`                   | JSON array of ring buffer lines            |
+/// | `log <filter>`             | `ok`; sets the RUST_LOG filter             |
+/// | `level <level>`            | sets the default level; returns the filter |
+/// | `level <category> <level>` | sets one category's level, likewise        |
+/// | `overhead [calls]`         | JSON per-call overhead of each hook layer  |
+/// | `reverify`                 | checks offsets, resumes suspended hooks    |
+/// | `swap <path>`              | replaces the original (hot_swap.rs)        |
+
+use crate::proxy_impl::{
+    analysis, bench, config, hooks, hookscan, hot_swap, image_dump, logging, metrics, original_watch, profiler, recording,
+    snapshot, status,
+};
+#[cfg(feature = "trace")]
+use crate::proxy_impl::trace;
+use crate::proxy_impl::wide::to_wide;
+use std::sync::atomic::{AtomicBool, Ordering};
+use winapi::shared::minwindef::{DWORD, LPVOID};
+use winapi::shared::winerror::ERROR_PIPE_CONNECTED;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::fileapi::{CreateFileW, FlushFileBuffers, ReadFile, WriteFile, OPEN_EXISTING};
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe};
+use winapi::um::processthreadsapi::GetCurrentProcessId;
+use winapi::um::winbase::{PIPE_ACCESS_DUPLEX, PIPE_READMODE_BYTE, PIPE_TYPE_BYTE, PIPE_WAIT};
+use winapi::um::winnt::{GENERIC_READ, GENERIC_WRITE, HANDLE};
+
+const BUFFER_SIZE: DWORD = 4096;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Pipe name for a given process
+pub fn pipe_name(pid: u32) -> String {
+    format!(r"\\.\pipe\reflex-proxy-{}", pid)
+}
+
+/// Start the control channel on a background thread
+pub fn start_server() -> Result<(), String> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    let name = pipe_name(unsafe { GetCurrentProcessId() });
+    tracing::info!("[ipc] Control channel listening on {}", name);
+
+    std::thread::Builder::new()
+        .name("reflex-ipc".to_string())
+        .spawn(move || unsafe { serve(&name) })
+        .map(|_| ())
+        .map_err(|e| {
+            RUNNING.store(false, Ordering::SeqCst);
+            format!("Failed to spawn IPC thread: {}", e)
+        })
+}
+
+/// Ask the server thread to exit
+///
+/// Called from DLL_PROCESS_DETACH, so the thread cannot be joined (that
+/// would deadlock on the loader lock); it is woken and exits on its own.
+pub fn stop_server() {
+    if !RUNNING.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    // Wake a thread blocked in ConnectNamedPipe
+    unsafe {
+        let name = to_wide(pipe_name(GetCurrentProcessId()));
+        let handle = CreateFileW(
+            name.as_ptr(),
+            GENERIC_READ | GENERIC_WRITE,
+            0,
+            std::ptr::null_mut(),
+            OPEN_EXISTING,
+            0,
+            std::ptr::null_mut(),
+        );
+        if handle != INVALID_HANDLE_VALUE {
+            CloseHandle(handle);
+        }
+    }
+}
+
+/// Execute one command line and produce the response line
+pub fn handle_command(line: &str) -> String {
+    let mut parts = line.trim().splitn(2, char::is_whitespace);
+    let command = parts.next().unwrap_or("");
+    let argument = parts.next().map(str::trim).unwrap_or("");
+
+    let result = match command {
+        "status" => serde_json::to_string(&unsafe { status::snapshot() }).map_err(|e| e.to_string()),
+        "hooks" => serde_json::to_string(&hooks::list_hooks()).map_err(|e| e.to_string()),
+        "suspend" => unsafe { hooks::suspend_hook(argument) }.map(|_| "ok".to_string()),
+        "resume" => unsafe { hooks::resume_hook(argument) }.map(|_| "ok".to_string()),
+        "passthrough" => passthrough(argument),
+        "dump" => logging::dump_ring("ipc")
+            .map(|(path, count)| format!("dumped {} event(s) to {}", count, path)),
+        #[cfg(feature = "trace")]
+        "trace" => trace::write().map(|(path, count)| format!("wrote {} event(s) to {}", count, path)),
+        "profile" => profile(argument),
+        "coverage" => recording::write_coverage()
+            .map(|(path, called, total)| format!("{}/{} export(s) called, wrote {}", called, total, path)),
+        "dryrun" => dry_run(argument),
+        "metrics" => metrics::write().map(|path| format!("wrote {}", path)),
+        "stats" => serde_json::to_string(&stats()).map_err(|e| e.to_string()),
+        "events" => logging::recent_events().and_then(|events| serde_json::to_string(&events).map_err(|e| e.to_string())),
+        "log" => logging::set_filter(argument).map(|_| "ok".to_string()),
+        "level" => match argument.split_whitespace().collect::<Vec<_>>()[..] {
+            [level] => logging::set_level(None, level),
+            [category, level] => logging::set_level(Some(category), level),
+            _ => Err("usage: level [<category>] <level>".to_string()),
+        },
+        "snapshot" => snapshot::toggle(&config::get().memory_diff),
+        "imagedump" => image_dump::write(&config::get().image_dump).map(|(path, size, unreadable)| {
+            format!("wrote {} byte(s) to {} ({} unreadable page(s))", size, path, unreadable)
+        }),
+        "rtti" if !argument.is_empty() => analysis::vtable(argument)
+            .map(|vtable| format!("0x{:x}", vtable))
+            .ok_or_else(|| format!("no vtable for {}", argument)),
+        "rtti" => analysis::write_classes(&config::get().rtti)
+            .map(|(path, count)| format!("{} class vtable(s), wrote {}", count, path)),
+        "strings" => string_refs(argument),
+        "callers" => callers(argument),
+        "hookscan" => unsafe { hookscan::run(&config::get().hook_scan) }
+            .and_then(|report| serde_json::to_string(&report).map_err(|e| e.to_string())),
+        "overhead" => overhead(argument),
+        "reverify" => original_watch::reverify(),
+        "swap" => hot_swap::swap(argument),
+        "" => Err("empty command".to_string()),
+        other => Err(format!("unknown command: {}", other)),
+    };
+
+    match result {
+        Ok(response) => response,
+        Err(e) => format!("error: {}", e),
+    }
+}
+
+/// `passthrough [on|off]`: suspend every hook, or resume them; answers the
+/// resulting state
+fn passthrough(argument: &str) -> Result<String, String> {
+    let enabled = match argument {
+        "" => None,
+        "on" => Some(true),
+        "off" => Some(false),
+        other => return Err(format!("unknown passthrough action: {}", other)),
+    };
+    if let Some(enabled) = enabled {
+        unsafe { hooks::set_passthrough(enabled) };
+    }
+    Ok(if hooks::passthrough() { "on" } else { "off" }.to_string())
+}
+
+/// `profile [start|stop]`: capture around a benchmark run, or write the
+/// samples collected so far
+fn profile(argument: &str) -> Result<String, String> {
+    let written = |(path, count): (String, usize)| format!("wrote {} sample(s) to {}", count, path);
+    match argument {
+        "" => profiler::write().map(written),
+        "start" if profiler::is_running() => Err("profiler is already running".to_string()),
+        "start" => {
+            profiler::start(&config::get().profiler);
+            Ok("ok".to_string())
+        }
+        "stop" => {
+            profiler::stop();
+            profiler::write().map(written)
+        }
+        other => Err(format!("unknown profile action: {}", other)),
+    }
+}
+
+/// `dryrun <export> <value>|off`: answer calls of an export with a value
+/// instead of forwarding them (decimal or 0x hex)
+fn dry_run(argument: &str) -> Result<String, String> {
+    let usage = || "usage: dryrun <export> <value>|off".to_string();
+    let (export, value) = match argument.split_whitespace().collect::<Vec<_>>()[..] {
+        [export, "off"] => (export, None),
+        [export, value] => {
+            let value = match value.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16).map(|v| v as i64).map_err(|_| usage())?,
+                None => value.parse::<i64>().map_err(|_| usage())?,
+            };
+            (export, Some(value))
+        }
+        _ => return Err(usage()),
+    };
+    recording::set_dry_run(export, value).map(|_| "ok".to_string())
+}
+
+/// `overhead [calls]`: time the hook layers over `calls` calls each
+fn overhead(argument: &str) -> Result<String, String> {
+    let calls = match argument {
+        "" => bench::DEFAULT_CALLS,
+        calls => calls.parse().map_err(|_| "usage: overhead [calls]".to_string())?,
+    };
+    bench::run(calls).and_then(|report| serde_json::to_string(&report).map_err(|e| e.to_string()))
+}
+
+/// `strings <text>`: code referencing a string of the original
+fn string_refs(text: &str) -> Result<String, String> {
+    if text.is_empty() {
+        return Err("usage: strings <text>".to_string());
+    }
+    let refs: Vec<serde_json::Value> = analysis::string_refs(text)?
+        .iter()
+        .map(|r| {
+            serde_json::json!({
+                "string": r.string,
+                "string_address": format!("0x{:x}", r.string_address),
+                "address": format!("0x{:x}", r.address),
+                "function": r.function.map(|f| format!("0x{:x}", f)),
+            })
+        })
+        .collect();
+    serde_json::to_string(&refs).map_err(|e| e.to_string())
+}
+
+/// `callers <address>`: calls in the original to a function, address in hex
+fn callers(argument: &str) -> Result<String, String> {
+    let function = usize::from_str_radix(argument.trim_start_matches("0x"), 16)
+        .map_err(|_| "usage: callers <address in hex>".to_string())?;
+    let callers: Vec<serde_json::Value> = analysis::callers(function)?
+        .iter()
+        .map(|c| {
+            serde_json::json!({
+                "address": format!("0x{:x}", c.address),
+                "function": c.function.map(|f| format!("0x{:x}", f)),
+            })
+        })
+        .collect();
+    serde_json::to_string(&callers).map_err(|e| e.to_string())
+}
+
+/// Counters for long sessions: errors and calls per recorded export
+fn stats() -> serde_json::Value {
+    let exports: serde_json::Map<String, serde_json::Value> = recording::call_counts()
+        .into_iter()
+        .map(|(name, calls)| (name, calls.into()))
+        .collect();
+    serde_json::json!({
+        "errors": unsafe { status::snapshot() }.errors,
+        "exports": exports,
+    })
+}
+
+// ============================================================================
+// Pipe Server
+// ============================================================================
+
+unsafe fn serve(name: &str) {
+    let wide_name = to_wide(name);
+
+    while RUNNING.load(Ordering::SeqCst) {
+        let pipe = CreateNamedPipeW(
+            wide_name.as_ptr(),
+            PIPE_ACCESS_DUPLEX,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
+            1,
+            BUFFER_SIZE,
+            BUFFER_SIZE,
+            0,
+            std::ptr::null_mut(),
+        );
+        if pipe == INVALID_HANDLE_VALUE {
+            tracing::error!("[ipc] CreateNamedPipeW failed (error {})", GetLastError());
+            break;
+        }
+
+        let connected =
+            ConnectNamedPipe(pipe, std::ptr::null_mut()) != 0 || GetLastError() == ERROR_PIPE_CONNECTED;
+
+        if connected && RUNNING.load(Ordering::SeqCst) {
+            handle_client(pipe);
+        }
+
+        DisconnectNamedPipe(pipe);
+        CloseHandle(pipe);
+    }
+
+    tracing::info!("[ipc] Control channel stopped");
+}
+
+unsafe fn handle_client(pipe: HANDLE) {
+    let mut pending = Vec::new();
+    let mut buffer = [0u8; BUFFER_SIZE as usize];
+
+    loop {
+        let mut read: DWORD = 0;
+        let ok = ReadFile(
+            pipe,
+            buffer.as_mut_ptr() as LPVOID,
+            BUFFER_SIZE,
+            &mut read,
+            std::ptr::null_mut(),
+        );
+        if ok == 0 || read == 0 {
+            return;
+        }
+        pending.extend_from_slice(&buffer[..read as usize]);
+
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            tracing::debug!("[ipc] Command: {}", line.trim());
+
+            let mut response = handle_command(&line);
+            response.push('\n');
+            if !write_all(pipe, response.as_bytes()) {
+                return;
+            }
+        }
+    }
+}
+
+unsafe fn write_all(pipe: HANDLE, mut data: &[u8]) -> bool {
+    while !data.is_empty() {
+        let mut written: DWORD = 0;
+        if WriteFile(
+            pipe,
+            data.as_ptr() as LPVOID,
+            data.len() as DWORD,
+            &mut written,
+            std::ptr::null_mut(),
+        ) == 0
+        {
+            return false;
+        }
+        data = &data[written as usize..];
+    }
+    FlushFileBuffers(pipe);
+    true
+}
@@ -0,0 +1,153 @@
+/// Logging of the processes reflex_original.dll launches
+///
+/// With `[launches] enabled = true` the original's imports of
+/// CreateProcessW, ShellExecuteW and ShellExecuteExW are hooked, so only
+/// its own calls are seen. Each launch is logged and audited (category
+/// `process`) with the command line, working directory and the new
+/// process's id:
+///
+/// ```json
+/// {"category":"process","api":"CreateProcessW","args":{"application":null,
+///  "command_line":"\"C:\\Tools\\helper.exe\" --probe","directory":null,"flags":"0x0",
+///  "pid":5120},...}
+/// ```
+///
+/// Independent of `[children]`, which changes what every child of the
+/// game gets. Launches through functions the original resolves with
+/// GetProcAddress are not seen.
+
+use crate::proxy;
+use crate::proxy_impl::audit::{self, Category};
+use crate::proxy_impl::hooks::declare_hook;
+use crate::proxy_impl::rules::Decision;
+use crate::proxy_impl::stacks;
+use crate::proxy_impl::status::{self, ErrorKind};
+use crate::proxy_impl::wide::from_wide_ptr;
+use serde_json::json;
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::{BOOL, DWORD, HINSTANCE, LPVOID};
+use winapi::shared::windef::HWND;
+use winapi::um::minwinbase::LPSECURITY_ATTRIBUTES;
+use winapi::um::processthreadsapi::{GetProcessId, LPPROCESS_INFORMATION, LPSTARTUPINFOW};
+use winapi::um::shellapi::SHELLEXECUTEINFOW;
+use winapi::um::winnt::{LPCWSTR, LPWSTR};
+
+declare_hook! {
+    #[allow(clippy::too_many_arguments)]
+    fn CreateProcessW(
+        application: LPCWSTR,
+        command_line: LPWSTR,
+        process_attributes: LPSECURITY_ATTRIBUTES,
+        thread_attributes: LPSECURITY_ATTRIBUTES,
+        inherit_handles: BOOL,
+        flags: DWORD,
+        environment: LPVOID,
+        current_directory: LPCWSTR,
+        startup_info: LPSTARTUPINFOW,
+        process_information: LPPROCESS_INFORMATION,
+    ) -> BOOL {
+        // Read before the call, which may modify the command line in place
+        let args = json!({
+            "application": from_wide_ptr(application),
+            "command_line": from_wide_ptr(command_line),
+            "directory": from_wide_ptr(current_directory),
+            "flags": format!("0x{:x}", flags),
+        });
+        let result = call_original(
+            application,
+            command_line,
+            process_attributes,
+            thread_attributes,
+            inherit_handles,
+            flags,
+            environment,
+            current_directory,
+            startup_info,
+            process_information,
+        );
+        let pid = (result != 0 && !process_information.is_null()).then(|| (*process_information).dwProcessId);
+        report("CreateProcessW", args, pid, result as i64);
+        result
+    }
+}
+
+declare_hook! {
+    fn ShellExecuteW(
+        window: HWND,
+        verb: LPCWSTR,
+        file: LPCWSTR,
+        parameters: LPCWSTR,
+        directory: LPCWSTR,
+        show: c_int,
+    ) -> HINSTANCE {
+        let result = call_original(window, verb, file, parameters, directory, show);
+        let args = json!({
+            "verb": from_wide_ptr(verb),
+            "file": from_wide_ptr(file),
+            "parameters": from_wide_ptr(parameters),
+            "directory": from_wide_ptr(directory),
+        });
+        // Values above 32 mean success; there is no process handle
+        report("ShellExecuteW", args, None, result as i64);
+        result
+    }
+}
+
+declare_hook! {
+    fn ShellExecuteExW(info: *mut SHELLEXECUTEINFOW) -> BOOL {
+        let result = call_original(info);
+        if info.is_null() {
+            return result;
+        }
+        let info = &*info;
+        let args = json!({
+            "verb": from_wide_ptr(info.lpVerb),
+            "file": from_wide_ptr(info.lpFile),
+            "parameters": from_wide_ptr(info.lpParameters),
+            "directory": from_wide_ptr(info.lpDirectory),
+        });
+        // hProcess is only filled in with SEE_MASK_NOCLOSEPROCESS
+        let pid = (result != 0 && !info.hProcess.is_null()).then(|| GetProcessId(info.hProcess));
+        report("ShellExecuteExW", args, pid, result as i64);
+        result
+    }
+}
+
+/// Hook the original's imports of the launch APIs it uses
+pub unsafe fn initialize() {
+    let base = proxy::get_original_dll_base() as *const u8;
+    if base.is_null() {
+        tracing::warn!("[launches] Original DLL is not loaded");
+        return;
+    }
+
+    let hooked = [
+        ("CreateProcessW", CreateProcessW::install_iat(base)),
+        ("ShellExecuteW", ShellExecuteW::install_iat(base)),
+        ("ShellExecuteExW", ShellExecuteExW::install_iat(base)),
+    ]
+    .into_iter()
+    .filter(|(api, result)| match result {
+        Ok(()) => true,
+        // Most builds import none or only some of them
+        Err(e) if e.contains("is not imported") => {
+            tracing::debug!("[launches] {} is not imported", api);
+            false
+        }
+        Err(e) => {
+            status::record_error(ErrorKind::Detour);
+            tracing::warn!("[launches] Failed to hook {}: {}", api, e);
+            false
+        }
+    })
+    .count();
+    tracing::info!("[launches] Logging launches through {} API(s) the original imports", hooked);
+}
+
+/// Log and audit a launch
+unsafe fn report(api: &str, mut args: serde_json::Value, pid: Option<DWORD>, result: i64) {
+    args["pid"] = json!(pid);
+    tracing::info!("[launches] The original called {} {} -> {}", api, args, result);
+    stacks::capture(api);
+    audit::record(Category::Process, api, args, &Decision::Allow, result);
+}
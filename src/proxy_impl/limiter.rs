@@ -0,0 +1,90 @@
+/// Frame limiter standing in for the driver's Reflex sleep
+///
+/// With `[nvapi] spoof_support`, NvAPI_D3D_Sleep never reaches the driver
+/// (nvapi.rs). It waits here instead, so that frames start no closer
+/// together than the sleep mode's minimum interval. Frames are paced on a
+/// fixed grid; a frame that runs late starts a new grid rather than letting
+/// the following ones catch up.
+///
+/// Independent of the host platform.
+
+use std::time::{Duration, Instant};
+
+/// Left of a wait that is spun rather than slept, for timer resolution
+const SPIN: Duration = Duration::from_millis(1);
+
+/// Paces frames to a minimum interval
+#[derive(Debug, Default)]
+pub struct FrameLimiter {
+    /// When the next frame may start
+    next: Option<Duration>,
+}
+
+impl FrameLimiter {
+    pub const fn new() -> Self {
+        Self { next: None }
+    }
+
+    /// A frame wants to start at `now`; returns how long to wait before it
+    /// may, with frames `interval` apart (zero = no limit)
+    pub fn wait(&mut self, now: Duration, interval: Duration) -> Duration {
+        if interval.is_zero() {
+            self.next = None;
+            return Duration::ZERO;
+        }
+        match self.next {
+            Some(next) if next > now => {
+                self.next = Some(next + interval);
+                next - now
+            }
+            _ => {
+                self.next = Some(now + interval);
+                Duration::ZERO
+            }
+        }
+    }
+}
+
+/// Block the thread for `wait`: sleep most of it, spin the rest
+pub fn pause(wait: Duration) {
+    let deadline = Instant::now() + wait;
+    if let Some(coarse) = wait.checked_sub(SPIN) {
+        std::thread::sleep(coarse);
+    }
+    while Instant::now() < deadline {
+        std::thread::yield_now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn frames_are_paced_on_a_grid() {
+        let mut limiter = FrameLimiter::new();
+        let interval = ms(10);
+
+        assert_eq!(limiter.wait(ms(100), interval), ms(0));
+        // Early frames wait for their slot, which stays on the grid
+        assert_eq!(limiter.wait(ms(104), interval), ms(6));
+        assert_eq!(limiter.wait(ms(110), interval), ms(10));
+        // A late frame starts at once and moves the grid
+        assert_eq!(limiter.wait(ms(135), interval), ms(0));
+        assert_eq!(limiter.wait(ms(140), interval), ms(5));
+    }
+
+    #[test]
+    fn zero_interval_does_not_limit() {
+        let mut limiter = FrameLimiter::new();
+        assert_eq!(limiter.wait(ms(0), ms(10)), ms(0));
+        assert_eq!(limiter.wait(ms(1), ms(0)), ms(0));
+        // The limit restarts from the next frame
+        assert_eq!(limiter.wait(ms(2), ms(10)), ms(0));
+        assert_eq!(limiter.wait(ms(3), ms(10)), ms(9));
+    }
+}
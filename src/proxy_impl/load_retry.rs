@@ -0,0 +1,128 @@
+/// Retries of a load of the original that failed at attach
+///
+/// An updater or anti-cheat scanning reflex_original.dll may hold it open
+/// for a moment while the game starts; LoadLibrary then fails with a
+/// sharing or lock violation, or access denied. Rather than failing for
+/// good, attach returns and a background thread tries again up to
+/// `retries` times, waiting `retry_delay_ms` before the first retry and
+/// twice as long before each next one (at most `MAX_DELAY`). Once the
+/// original is loaded the rest of the attach runs on that thread, outside
+/// the loader lock, and the original's DllMain gets DLL_PROCESS_ATTACH.
+///
+/// ```toml
+/// [loader]
+/// retries = 5          # 0 = fail at attach
+/// retry_delay_ms = 250
+/// ```
+///
+/// Until then nothing is forwarded: exports return zero and threads that
+/// attach meanwhile are not announced to the original. Other errors, or
+/// the last retry failing, leave the proxy failed and in passthrough.
+///
+/// Independent of the host platform.
+
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// Longest wait between two attempts
+pub const MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// Set on detach; a pending retry gives up
+static CANCELLED: Mutex<bool> = Mutex::new(false);
+static WAKE: Condvar = Condvar::new();
+
+/// Whether a load error may go away by itself: the file is in use
+/// (ERROR_ACCESS_DENIED, ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION)
+pub fn is_transient(error: &str) -> bool {
+    // The load error ends with the Win32 error code
+    let code = error.rsplit("error ").next().and_then(|code| code.trim().parse::<u32>().ok());
+    matches!(code, Some(5 | 32 | 33))
+}
+
+/// Wait before retry `attempt` (from 1)
+pub fn delay(first: Duration, attempt: u32) -> Duration {
+    first.saturating_mul(1 << attempt.saturating_sub(1).min(16)).min(MAX_DELAY)
+}
+
+/// Call `load` up to `retries` times with backoff while it fails with a
+/// transient error; returns the attempt that succeeded or the last error
+pub fn retry<F>(retries: u32, first_delay: Duration, mut load: F) -> Result<u32, String>
+where
+    F: FnMut() -> Result<(), String>,
+{
+    let mut last = "no retries".to_string();
+    for attempt in 1..=retries {
+        if wait(delay(first_delay, attempt)) {
+            return Err("cancelled".to_string());
+        }
+        match load() {
+            Ok(()) => return Ok(attempt),
+            Err(e) if is_transient(&e) => {
+                tracing::warn!("[load_retry] Retry {}/{} failed: {}", attempt, retries, e);
+                last = e;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Err(last)
+}
+
+/// Stop waiting for the next retry, e.g. because the proxy unloads
+pub fn cancel() {
+    *CANCELLED.lock().unwrap() = true;
+    WAKE.notify_all();
+}
+
+/// Whether `cancel` was called
+pub fn cancelled() -> bool {
+    *CANCELLED.lock().unwrap()
+}
+
+/// Sleep for `timeout` unless cancelled; returns whether it was
+fn wait(timeout: Duration) -> bool {
+    let cancelled = CANCELLED.lock().unwrap();
+    let (cancelled, _) = WAKE.wait_timeout_while(cancelled, timeout, |cancelled| !*cancelled).unwrap();
+    *cancelled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_files_in_use_are_transient() {
+        assert!(is_transient("LoadLibraryExW(reflex_original.dll, 0x900) failed with error 32"));
+        assert!(is_transient("Failed to load original DLL: LoadLibraryExW(x, 0x0) failed with error 5"));
+        assert!(!is_transient("LoadLibraryExW(reflex_original.dll, 0x900) failed with error 2"));
+        assert!(!is_transient("Original DLL has no entry point"));
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_maximum() {
+        let first = Duration::from_millis(250);
+        assert_eq!(delay(first, 1), first);
+        assert_eq!(delay(first, 3), Duration::from_secs(1));
+        assert_eq!(delay(first, 40), MAX_DELAY);
+    }
+
+    #[test]
+    fn retries_stop_at_success_or_a_permanent_error() {
+        let locked = || Err("failed with error 32".to_string());
+
+        let mut calls = 0;
+        let result = retry(5, Duration::ZERO, || {
+            calls += 1;
+            if calls < 3 { locked() } else { Ok(()) }
+        });
+        assert_eq!(result, Ok(3));
+
+        let mut calls = 0;
+        let result = retry(5, Duration::ZERO, || {
+            calls += 1;
+            Err("failed with error 2".to_string())
+        });
+        assert_eq!((result.is_err(), calls), (true, 1));
+
+        assert_eq!(retry(2, Duration::ZERO, locked), Err("failed with error 32".to_string()));
+    }
+}
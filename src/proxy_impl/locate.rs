@@ -0,0 +1,254 @@
+/// Finding the genuine reflex.dll where it is installed
+///
+/// For a quick analysis setup the original need not be copied and renamed:
+/// with `fallback = true` and no reflex_original.dll next to the proxy, the
+/// proxy forwards to the first reflex.dll found in `fallback_locations`:
+///
+/// ```toml
+/// [loader]
+/// fallback = true
+/// fallback_locations = [
+///     'HKLM\SYSTEM\CurrentControlSet\Control\Class\{4d36e968-e325-11ce-bfc1-08002be10318}\*\UserModeDriverName',
+///     'HKLM\SOFTWARE\NVIDIA Corporation\Global\GFExperience\FullPath',
+///     "system32",
+/// ]
+/// ```
+///
+/// An entry is `system32` or a registry value, `HKLM\<key>\<value>` (or
+/// `HKCU`), whose data names a file or a directory; for a file its
+/// directory is searched. A `*` component of the key stands for every
+/// subkey, e.g. every display adapter, whose `UserModeDriverName` lies in
+/// the driver store. REG_MULTI_SZ data contributes each of its entries.
+///
+/// The proxy itself is never picked. With `dll_load_dir` in
+/// `[loader] search` the original's dependencies are found next to it.
+
+use std::path::PathBuf;
+#[cfg(windows)]
+use std::path::Path;
+
+/// Registry hive of a location
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Root {
+    LocalMachine,
+    CurrentUser,
+}
+
+/// An entry of `fallback_locations`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    System32,
+    /// `key` may contain one `*` component
+    Registry { root: Root, key: String, value: String },
+}
+
+/// Parse a `fallback_locations` entry
+pub fn parse(text: &str) -> Result<Location, String> {
+    if text.eq_ignore_ascii_case("system32") {
+        return Ok(Location::System32);
+    }
+
+    let (root, path) = text.split_once('\\').ok_or_else(|| format!("'{}' is not system32 or a registry value", text))?;
+    let root = match root.to_ascii_uppercase().as_str() {
+        "HKLM" | "HKEY_LOCAL_MACHINE" => Root::LocalMachine,
+        "HKCU" | "HKEY_CURRENT_USER" => Root::CurrentUser,
+        other => return Err(format!("unknown registry root '{}' in '{}'", other, text)),
+    };
+    let (key, value) = path.rsplit_once('\\').ok_or_else(|| format!("'{}' names no value", text))?;
+    if key.split('\\').filter(|component| component.contains('*')).count() > 1
+        || key.split('\\').any(|component| component.contains('*') && component != "*")
+    {
+        return Err(format!("'{}' may have one '*' key component", text));
+    }
+    Ok(Location::Registry {
+        root,
+        key: key.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// Directories named by the data of a registry value: each entry of it,
+/// unquoted, or its directory if it names a file
+pub fn directories(data: &str) -> Vec<PathBuf> {
+    let mut directories: Vec<PathBuf> = Vec::new();
+    for entry in data.split('\0').map(|entry| entry.trim().trim_matches('"')).filter(|entry| !entry.is_empty()) {
+        // Windows paths, also when the unit tests run elsewhere
+        let directory = match entry.rsplit_once(['\\', '/']) {
+            Some((directory, file)) if file.contains('.') => directory,
+            _ => entry,
+        };
+        let directory = PathBuf::from(directory);
+        if !directories.contains(&directory) {
+            directories.push(directory);
+        }
+    }
+    directories
+}
+
+/// The first `file_name` in `locations` that is not `exclude` (the proxy)
+#[cfg(windows)]
+pub unsafe fn find(file_name: &str, locations: &[String], exclude: Option<&Path>) -> Option<PathBuf> {
+    for text in locations {
+        let location = match parse(text) {
+            Ok(location) => location,
+            Err(e) => {
+                tracing::warn!("[locate] Skipping fallback location: {}", e);
+                continue;
+            }
+        };
+        for directory in windows::candidates(&location) {
+            let path = directory.join(file_name);
+            let is_proxy = exclude.is_some_and(|exclude| same_file(&path, exclude));
+            if path.is_file() && !is_proxy {
+                tracing::info!("[locate] Found {} via {}", path.display(), text);
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(windows)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(windows)]
+pub use windows::{read_string, subkeys};
+
+#[cfg(windows)]
+mod windows {
+    use super::{directories, Location, Root};
+    use crate::proxy_impl::wide::to_wide;
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+    use std::path::PathBuf;
+    use winapi::shared::minwindef::HKEY;
+    use winapi::shared::winerror::ERROR_SUCCESS;
+    use winapi::um::sysinfoapi::GetSystemDirectoryW;
+    use winapi::um::winnt::KEY_ENUMERATE_SUB_KEYS;
+    use winapi::um::winreg::{
+        RegCloseKey, RegEnumKeyExW, RegGetValueW, RegOpenKeyExW, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE,
+        RRF_RT_REG_EXPAND_SZ, RRF_RT_REG_MULTI_SZ, RRF_RT_REG_SZ,
+    };
+
+    /// Directories a location stands for, in order
+    pub unsafe fn candidates(location: &Location) -> Vec<PathBuf> {
+        match location {
+            Location::System32 => system_directory().into_iter().collect(),
+            Location::Registry { root, key, value } => {
+                let root = match root {
+                    Root::LocalMachine => HKEY_LOCAL_MACHINE,
+                    Root::CurrentUser => HKEY_CURRENT_USER,
+                };
+                let keys = match key.split_once("\\*") {
+                    Some((parent, rest)) => subkeys(root, parent)
+                        .into_iter()
+                        .map(|subkey| format!("{}\\{}{}", parent, subkey, rest))
+                        .collect(),
+                    None => vec![key.clone()],
+                };
+                keys.iter()
+                    .filter_map(|key| read_string(root, key, value))
+                    .flat_map(|data| directories(&data))
+                    .collect()
+            }
+        }
+    }
+
+    unsafe fn system_directory() -> Option<PathBuf> {
+        let mut buffer = [0u16; 260];
+        let len = GetSystemDirectoryW(buffer.as_mut_ptr(), buffer.len() as u32) as usize;
+        (len > 0 && len < buffer.len()).then(|| PathBuf::from(OsString::from_wide(&buffer[..len])))
+    }
+
+    /// Names of the subkeys of `key`
+    pub unsafe fn subkeys(root: HKEY, key: &str) -> Vec<String> {
+        let mut handle: HKEY = std::ptr::null_mut();
+        if RegOpenKeyExW(root, to_wide(key).as_ptr(), 0, KEY_ENUMERATE_SUB_KEYS, &mut handle) != ERROR_SUCCESS as i32 {
+            return Vec::new();
+        }
+
+        let mut names = Vec::new();
+        let mut name = [0u16; 256];
+        for index in 0.. {
+            let mut len = name.len() as u32;
+            let result = RegEnumKeyExW(
+                handle,
+                index,
+                name.as_mut_ptr(),
+                &mut len,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            if result != ERROR_SUCCESS as i32 {
+                break;
+            }
+            names.push(String::from_utf16_lossy(&name[..len as usize]));
+        }
+        RegCloseKey(handle);
+        names
+    }
+
+    /// A string value, environment variables expanded; REG_MULTI_SZ entries
+    /// are separated by NULs
+    pub unsafe fn read_string(root: HKEY, key: &str, value: &str) -> Option<String> {
+        let flags = RRF_RT_REG_SZ | RRF_RT_REG_EXPAND_SZ | RRF_RT_REG_MULTI_SZ;
+        let (key, value) = (to_wide(key), to_wide(value));
+        let mut size = 0u32;
+        let read = |buffer: *mut u16, size: &mut u32| {
+            RegGetValueW(root, key.as_ptr(), value.as_ptr(), flags, std::ptr::null_mut(), buffer as _, size)
+        };
+        if read(std::ptr::null_mut(), &mut size) != ERROR_SUCCESS as i32 {
+            return None;
+        }
+        let mut buffer = vec![0u16; size as usize / 2 + 1];
+        let mut size = (buffer.len() * 2) as u32;
+        if read(buffer.as_mut_ptr(), &mut size) != ERROR_SUCCESS as i32 {
+            return None;
+        }
+        buffer.truncate(size as usize / 2);
+        Some(String::from_utf16_lossy(&buffer).trim_end_matches('\0').to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations_parse() {
+        assert_eq!(parse("System32"), Ok(Location::System32));
+        assert_eq!(
+            parse(r"HKLM\SOFTWARE\NVIDIA Corporation\Global\GFExperience\FullPath"),
+            Ok(Location::Registry {
+                root: Root::LocalMachine,
+                key: r"SOFTWARE\NVIDIA Corporation\Global\GFExperience".to_string(),
+                value: "FullPath".to_string(),
+            })
+        );
+        assert!(parse(r"HKLM\SYSTEM\Class\*\UserModeDriverName").is_ok());
+        assert!(parse(r"HKLM\SYSTEM\Class\00*\UserModeDriverName").is_err());
+        assert!(parse(r"HKCR\Value").is_err());
+        assert!(parse(r"HKLM\OnlyValue").is_err());
+        assert!(parse("driver_store").is_err());
+    }
+
+    #[test]
+    fn value_data_names_directories() {
+        let store = r"C:\Windows\System32\DriverStore\FileRepository\nv_dispi.inf_amd64_1234";
+        let data = format!("{0}\\nvldumdx.dll\0{0}\\nvldumdx.dll\0\0", store);
+        assert_eq!(directories(&data), [PathBuf::from(store)]);
+        assert_eq!(
+            directories(r#""C:\Program Files\NVIDIA Corporation\NVIDIA GeForce Experience\NVIDIA GeForce Experience.exe""#),
+            [PathBuf::from(r"C:\Program Files\NVIDIA Corporation\NVIDIA GeForce Experience")]
+        );
+        assert_eq!(directories(r"D:\Tools\Reflex"), [PathBuf::from(r"D:\Tools\Reflex")]);
+        assert!(directories("").is_empty());
+    }
+}
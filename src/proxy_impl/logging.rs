@@ -0,0 +1,883 @@
+/// Logging pipeline
+///
+/// Diagnostics are emitted with the `tracing` macros. Forwarded export calls
+/// and hooks open spans carrying their arguments as fields, so every event
+/// logged inside a hook shows which call (and which DllMain) it belongs to:
+///
+/// ```text
+/// [2024-05-01T12:00:00Z WARN  reflex::proxy_impl::detours] DllMain{reason=1}:DeleteFileW{path=C:\tmp\x.cfg}: Blocking deletion
+/// ```
+///
+/// A subscriber layer fans events out to the sinks chosen in the `[logging]`
+/// section of reflex_proxy.toml:
+/// - file: reflex.log, written as events happen (default)
+/// - json: one JSON object per event, with span fields, for tooling
+/// - debug output: OutputDebugStringW, for DebugView or an attached debugger
+/// - ETW: the `ReflexProxy` provider, for xperf/WPR/PerfView sessions; with
+///   `[correlation]` its events carry the session GUID as activity id
+/// - event log: errors and crashes only, to the Windows Application log, for
+///   installs where the game directory is read-only
+/// - ring buffer: the last N events kept in memory and written to disk only
+///   on demand (IPC `dump`, hotkey) or when a crash is detected
+/// - remote: syslog or JSON over UDP to another machine (remote_log.rs),
+///   sent in batches from a background thread; events are dropped rather
+///   than queued without bound when the network cannot keep up
+///
+/// `[logging.redact]` hides the user's and machine's names, GUIDs and
+/// configured strings from every sink (redact.rs).
+///
+/// A secondary copy of the proxy (instance.rs) hands its events to the
+/// primary copy's sinks instead, so the process has a single log; its own
+/// sinks only take over once the primary is gone.
+///
+/// Levels come from `level` and per-category `categories` in `[logging]`
+/// (`error` by default) and can be changed at runtime with the `level`
+/// control command; RUST_LOG, when set, replaces them, as it did with
+/// env_logger.
+/// Events logged before the config file has been read are held back and
+/// replayed into the sinks.
+
+use crate::proxy_impl::config::{self, LoggingConfig};
+use crate::proxy_impl::correlation;
+use crate::proxy_impl::instance;
+use crate::proxy_impl::redact;
+#[cfg(feature = "network")]
+use crate::proxy_impl::remote_log::{self, Format};
+use crate::proxy_impl::ring_buffer::RingBuffer;
+use crate::proxy_impl::stacks;
+#[cfg(feature = "trace")]
+use crate::proxy_impl::trace;
+use crate::proxy_impl::wide::to_wide;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+#[cfg(feature = "network")]
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+#[cfg(feature = "network")]
+use std::{sync::Arc, time::Duration};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
+use winapi::shared::evntprov::{
+    EventActivityIdControl, EventRegister, EventWriteString, EVENT_ACTIVITY_CTRL_GET_SET_ID,
+    EVENT_ACTIVITY_CTRL_SET_ID, REGHANDLE,
+};
+use winapi::shared::guiddef::GUID;
+use winapi::um::debugapi::OutputDebugStringW;
+use winapi::um::errhandlingapi::SetUnhandledExceptionFilter;
+use winapi::um::processthreadsapi::GetCurrentThreadId;
+#[cfg(feature = "network")]
+use winapi::um::processthreadsapi::GetCurrentProcessId;
+use winapi::um::winbase::{RegisterEventSourceW, ReportEventW};
+use winapi::um::winnt::{EVENTLOG_ERROR_TYPE, EXCEPTION_POINTERS, LONG};
+use winapi::vc::excpt::EXCEPTION_CONTINUE_SEARCH;
+
+/// Events kept while waiting for `configure`
+const MAX_PENDING: usize = 1000;
+
+/// Source name shown in Event Viewer
+const EVENT_SOURCE: &str = "ReflexProxy";
+
+/// ETW provider `ReflexProxy` {6A3B5C2E-7D41-4F8A-9E0B-3C5D7F1A2B4E}
+const ETW_PROVIDER: GUID = GUID {
+    Data1: 0x6a3b_5c2e,
+    Data2: 0x7d41,
+    Data3: 0x4f8a,
+    Data4: [0x9e, 0x0b, 0x3c, 0x5d, 0x7f, 0x1a, 0x2b, 0x4e],
+};
+
+/// A span enclosing an event
+#[derive(Debug, Clone, Serialize)]
+pub struct SpanContext {
+    pub name: &'static str,
+    #[serde(flatten)]
+    pub fields: Map<String, Value>,
+}
+
+/// One log event as handed to the sinks
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEvent {
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub timestamp: SystemTime,
+    #[serde(serialize_with = "serialize_level")]
+    pub level: Level,
+    pub target: String,
+    pub thread: u32,
+    pub message: String,
+    pub fields: Map<String, Value>,
+    /// Enclosing spans, outermost first
+    pub spans: Vec<SpanContext>,
+}
+
+impl LogEvent {
+    /// Human-readable single line, as written to reflex.log
+    pub fn line(&self) -> String {
+        format!(
+            "[{} {:<5} {}] {}",
+            humantime::format_rfc3339_seconds(self.timestamp),
+            self.level,
+            self.target,
+            self.body()
+        )
+    }
+
+    /// The line without timestamp, level and target: spans, message, fields
+    pub fn body(&self) -> String {
+        let mut line = String::new();
+        for span in &self.spans {
+            line.push_str(span.name);
+            if !span.fields.is_empty() {
+                line.push('{');
+                line.push_str(&format_fields(&span.fields, ","));
+                line.push('}');
+            }
+            line.push(':');
+        }
+        if !self.spans.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&self.message);
+        if !self.fields.is_empty() {
+            line.push(' ');
+            line.push_str(&format_fields(&self.fields, " "));
+        }
+        line
+    }
+}
+
+/// A destination for log events
+pub trait Sink: Send + Sync {
+    fn write(&self, event: &LogEvent);
+    fn flush(&self) {}
+}
+
+/// Subscriber layer that turns events into `LogEvent`s for the sinks
+struct SinkLayer;
+
+/// Fields of a span, stored in its extensions
+struct SpanFields(Map<String, Value>);
+
+static INSTALLED: OnceCell<()> = OnceCell::new();
+static SINKS: OnceCell<Vec<Box<dyn Sink>>> = OnceCell::new();
+static PENDING: Mutex<Vec<LogEvent>> = Mutex::new(Vec::new());
+static RING: OnceCell<RingBuffer<String>> = OnceCell::new();
+static RING_DUMP_PATH: OnceCell<String> = OnceCell::new();
+static PREVIOUS_FILTER: AtomicUsize = AtomicUsize::new(0);
+/// Replaces the RUST_LOG filter at runtime
+static FILTER: OnceCell<reload::Handle<EnvFilter, Registry>> = OnceCell::new();
+/// Default level and per-category levels the filter was built from
+static LEVELS: Mutex<Option<(String, HashMap<String, String>)>> = Mutex::new(None);
+
+impl<S> Layer<S> for SinkLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(visitor.fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            if let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() {
+                let mut visitor = FieldVisitor {
+                    message: None,
+                    fields: std::mem::take(fields),
+                };
+                values.record(&mut visitor);
+                *fields = visitor.fields;
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| {
+                scope
+                    .from_root()
+                    .map(|span| SpanContext {
+                        name: span.name(),
+                        fields: span
+                            .extensions()
+                            .get::<SpanFields>()
+                            .map(|f| f.0.clone())
+                            .unwrap_or_default(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let metadata = event.metadata();
+        dispatch(LogEvent {
+            timestamp: SystemTime::now(),
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            thread: unsafe { GetCurrentThreadId() },
+            message: visitor.message.unwrap_or_default(),
+            fields: visitor.fields,
+            spans,
+        });
+    }
+}
+
+fn dispatch(event: LogEvent) {
+    match SINKS.get() {
+        Some(sinks) => {
+            let event = redacted(event);
+            for sink in sinks {
+                sink.write(&event);
+            }
+        }
+        None => {
+            let mut pending = PENDING.lock().unwrap();
+            if pending.len() < MAX_PENDING {
+                pending.push(event);
+            }
+        }
+    }
+}
+
+/// The event with its message and fields redacted, if redaction is on
+fn redacted(mut event: LogEvent) -> LogEvent {
+    if redact::enabled() {
+        event.message = redact::text(&event.message);
+        redact::fields(&mut event.fields);
+        for span in &mut event.spans {
+            redact::fields(&mut span.fields);
+        }
+    }
+    event
+}
+
+/// Install the subscriber; events are buffered until `configure` is called
+pub fn init() -> Result<(), String> {
+    if INSTALLED.set(()).is_err() {
+        return Err("logging already initialized".to_string());
+    }
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("error"));
+    let (filter, handle) = reload::Layer::new(filter);
+    let _ = FILTER.set(handle);
+    let subscriber = Registry::default().with(SinkLayer.with_filter(filter));
+    #[cfg(feature = "trace")]
+    let subscriber = subscriber.with(trace::layer());
+
+    tracing::subscriber::set_global_default(subscriber).map_err(|e| e.to_string())
+}
+
+/// Create the configured sinks and replay buffered events into them
+pub fn configure(config: &LoggingConfig) {
+    if INSTALLED.get().is_none() {
+        return;
+    }
+
+    if let Err(e) = redact::configure(&config.redact) {
+        eprintln!("[reflex-proxy] Invalid [logging.redact]: {}", e);
+    }
+
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+
+    if config.file {
+        match open_append(&config.path) {
+            Ok(file) => sinks.push(Box::new(FileSink(Mutex::new(file)))),
+            Err(e) => eprintln!("[reflex-proxy] Failed to open {}: {}", config.path, e),
+        }
+    }
+
+    if config.json {
+        match open_append(&config.json_path) {
+            Ok(file) => sinks.push(Box::new(JsonSink(Mutex::new(file)))),
+            Err(e) => eprintln!("[reflex-proxy] Failed to open {}: {}", config.json_path, e),
+        }
+    }
+
+    if config.debug_output {
+        sinks.push(Box::new(DebugOutputSink));
+    }
+
+    if config.etw {
+        match EtwSink::register() {
+            Some(sink) => sinks.push(Box::new(sink)),
+            None => eprintln!("[reflex-proxy] Failed to register ETW provider"),
+        }
+    }
+
+    if config.event_log {
+        match EventLogSink::register() {
+            Some(sink) => sinks.push(Box::new(sink)),
+            None => eprintln!("[reflex-proxy] Failed to register event source {}", EVENT_SOURCE),
+        }
+    }
+
+    if config.ring_buffer > 0 {
+        RING.get_or_init(|| RingBuffer::new(config.ring_buffer));
+        RING_DUMP_PATH.get_or_init(|| config.ring_dump_path.clone());
+        sinks.push(Box::new(RingSink));
+    }
+
+    if !config.remote.is_empty() {
+        #[cfg(feature = "network")]
+        match RemoteSink::connect(config) {
+            Ok(sink) => sinks.push(Box::new(sink)),
+            Err(e) => eprintln!("[reflex-proxy] Failed to set up remote logging to {}: {}", config.remote, e),
+        }
+        #[cfg(not(feature = "network"))]
+        eprintln!("[reflex-proxy] [logging] remote is set but this build has no 'network' feature");
+    }
+
+    if config.ring_buffer > 0 || config.event_log {
+        unsafe { install_crash_filter() };
+    }
+
+    if instance::logs_to_primary() {
+        sinks = vec![Box::new(PeerSink(sinks))];
+    }
+
+    if SINKS.set(sinks).is_err() {
+        return;
+    }
+
+    if std::env::var_os("RUST_LOG").is_none() {
+        if let Err(e) = set_levels(config.level.clone(), config.categories.clone()) {
+            eprintln!("[reflex-proxy] Invalid log levels in [logging]: {}", e);
+        }
+    }
+
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+    if let Some(sinks) = SINKS.get() {
+        for event in pending.into_iter().map(redacted) {
+            for sink in sinks {
+                sink.write(&event);
+            }
+        }
+    }
+
+    if let Some(ring) = RING.get() {
+        tracing::info!(
+            "[logging] Ring buffer enabled ({} events, dumps to {})",
+            ring.capacity(),
+            config.ring_dump_path
+        );
+    }
+}
+
+/// Log an event from a secondary copy of the proxy (`instance::PeerLogFn`)
+pub unsafe extern "system" fn peer_log(level: u32, target: *const u8, target_len: usize, line: *const u8, line_len: usize) {
+    let text = |ptr: *const u8, len: usize| String::from_utf8_lossy(std::slice::from_raw_parts(ptr, len)).into_owned();
+    dispatch(LogEvent {
+        timestamp: SystemTime::now(),
+        level: instance::peer_level(level),
+        target: format!("peer:{}", text(target, target_len)),
+        thread: GetCurrentThreadId(),
+        message: text(line, line_len),
+        fields: Map::new(),
+        spans: Vec::new(),
+    });
+}
+
+/// Flush every sink
+pub fn flush() {
+    if let Some(sinks) = SINKS.get() {
+        for sink in sinks {
+            sink.flush();
+        }
+    }
+}
+
+/// Replace the level filter, with RUST_LOG syntax (e.g. `info` or
+/// `warn,reflex::proxy_impl::detours=trace`)
+pub fn set_filter(directives: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+    let handle = FILTER.get().ok_or("logging is not initialized")?;
+    handle.reload(filter).map_err(|e| e.to_string())?;
+    tracing::info!("[logging] Filter set to '{}'", directives);
+    Ok(())
+}
+
+/// Set the level of one category (a module of proxy_impl or a full
+/// target), or of everything else with `category = None`
+///
+/// Replaces a filter set with `set_filter` or RUST_LOG.
+pub fn set_level(category: Option<&str>, level: &str) -> Result<String, String> {
+    let (mut default, mut categories) = LEVELS
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| ("error".to_string(), HashMap::new()));
+    match category {
+        Some(category) => {
+            categories.insert(category.to_string(), level.to_string());
+        }
+        None => default = level.to_string(),
+    }
+    set_levels(default, categories)
+}
+
+/// Build the filter from levels; returns its directives
+fn set_levels(default: String, categories: HashMap<String, String>) -> Result<String, String> {
+    let directives = config::log_directives(&default, &categories)?;
+    let filter = EnvFilter::try_new(&directives).map_err(|e| e.to_string())?;
+    FILTER
+        .get()
+        .ok_or("logging is not initialized")?
+        .reload(filter)
+        .map_err(|e| e.to_string())?;
+    *LEVELS.lock().unwrap() = Some((default, categories));
+    Ok(directives)
+}
+
+/// Events in the ring buffer, oldest first, without clearing it
+pub fn recent_events() -> Result<Vec<String>, String> {
+    RING.get()
+        .map(|ring| ring.snapshot())
+        .ok_or_else(|| "ring buffer is not enabled".to_string())
+}
+
+/// Write the ring buffer to disk and clear it
+///
+/// Returns the dump file path and the number of events written.
+pub fn dump_ring(reason: &str) -> Result<(String, usize), String> {
+    let ring = RING
+        .get()
+        .ok_or_else(|| "ring buffer is not enabled".to_string())?;
+    let path = RING_DUMP_PATH.get().cloned().unwrap_or_default();
+    if path.is_empty() {
+        return Err("ring buffer dumps are disabled (no ring_dump_path)".to_string());
+    }
+
+    let events = ring.drain();
+    let mut text = format!(
+        "==== ring buffer dump: {} ({} events) at {} ====\n",
+        reason,
+        events.len(),
+        humantime::format_rfc3339_seconds(SystemTime::now())
+    );
+    for line in &events {
+        text.push_str(line);
+        text.push('\n');
+    }
+
+    open_append(&path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|e| format!("failed to write {}: {}", path, e))?;
+
+    Ok((path, events.len()))
+}
+
+fn open_append(path: &str) -> std::io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+// ============================================================================
+// Field Recording
+// ============================================================================
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: Option<String>,
+    fields: Map<String, Value>,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        if field.name() == "message" {
+            self.message = Some(match value {
+                Value::String(s) => s,
+                other => other.to_string(),
+            });
+        } else {
+            self.fields.insert(field.name().to_string(), value);
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{:?}", value).into());
+    }
+}
+
+fn format_fields(fields: &Map<String, Value>, separator: &str) -> String {
+    fields
+        .iter()
+        .map(|(name, value)| match value {
+            Value::String(s) => format!("{}={}", name, s),
+            other => format!("{}={}", name, other),
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn serialize_timestamp<S: serde::Serializer>(time: &SystemTime, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_str(&humantime::format_rfc3339_millis(*time))
+}
+
+fn serialize_level<S: serde::Serializer>(level: &Level, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(level.as_str())
+}
+
+// ============================================================================
+// Sinks
+// ============================================================================
+
+struct FileSink(Mutex<File>);
+
+impl Sink for FileSink {
+    fn write(&self, event: &LogEvent) {
+        let mut file = self.0.lock().unwrap();
+        let _ = writeln!(file, "{}", event.line());
+    }
+
+    fn flush(&self) {
+        let _ = self.0.lock().unwrap().flush();
+    }
+}
+
+/// One JSON object per line, including the enclosing spans and their fields
+struct JsonSink(Mutex<File>);
+
+impl Sink for JsonSink {
+    fn write(&self, event: &LogEvent) {
+        if let Ok(json) = serde_json::to_string(event) {
+            let mut file = self.0.lock().unwrap();
+            let _ = writeln!(file, "{}", json);
+        }
+    }
+
+    fn flush(&self) {
+        let _ = self.0.lock().unwrap().flush();
+    }
+}
+
+/// Sends events to the primary copy of the proxy, or to this copy's sinks
+/// once the primary has detached
+struct PeerSink(Vec<Box<dyn Sink>>);
+
+impl Sink for PeerSink {
+    fn write(&self, event: &LogEvent) {
+        if !instance::send_log(event.level, &event.target, &event.body()) {
+            for sink in &self.0 {
+                sink.write(event);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        for sink in &self.0 {
+            sink.flush();
+        }
+    }
+}
+
+struct DebugOutputSink;
+
+impl Sink for DebugOutputSink {
+    fn write(&self, event: &LogEvent) {
+        let text = to_wide(format!("{}\n", event.line()));
+        unsafe { OutputDebugStringW(text.as_ptr()) };
+    }
+}
+
+/// The `ReflexProxy` ETW provider, registered on first use
+fn etw_provider() -> Option<REGHANDLE> {
+    static PROVIDER: OnceCell<Option<REGHANDLE>> = OnceCell::new();
+    *PROVIDER.get_or_init(|| {
+        let mut handle: REGHANDLE = 0;
+        let status =
+            unsafe { EventRegister(&ETW_PROVIDER, None, std::ptr::null_mut(), &mut handle) };
+        if status == 0 {
+            Some(handle)
+        } else {
+            None
+        }
+    })
+}
+
+/// Write a string event from the provider; with `[correlation]` the
+/// session GUID is its activity id
+fn write_etw_string(handle: REGHANDLE, level: u8, text: &str) {
+    let text = to_wide(text);
+    unsafe {
+        match correlation::session() {
+            Some(mut activity) => {
+                // Swap the session in as the thread's activity id, then restore it
+                EventActivityIdControl(EVENT_ACTIVITY_CTRL_GET_SET_ID, &mut activity);
+                EventWriteString(handle, level, 0, text.as_ptr());
+                EventActivityIdControl(EVENT_ACTIVITY_CTRL_SET_ID, &mut activity);
+            }
+            None => {
+                EventWriteString(handle, level, 0, text.as_ptr());
+            }
+        }
+    }
+}
+
+/// Write an informational event from the ETW provider, whether or not the
+/// ETW sink is configured (correlation.rs)
+pub fn write_etw(text: &str) {
+    if let Some(handle) = etw_provider() {
+        write_etw_string(handle, 4, text);
+    }
+}
+
+/// Writes events as strings from the `ReflexProxy` ETW provider
+struct EtwSink {
+    handle: REGHANDLE,
+}
+
+impl EtwSink {
+    fn register() -> Option<Self> {
+        etw_provider().map(|handle| Self { handle })
+    }
+}
+
+impl Sink for EtwSink {
+    fn write(&self, event: &LogEvent) {
+        // TRACE_LEVEL_* values
+        let level = match event.level {
+            Level::ERROR => 2,
+            Level::WARN => 3,
+            Level::INFO => 4,
+            _ => 5,
+        };
+        write_etw_string(self.handle, level, &event.line());
+    }
+}
+
+/// Reports error-level events to the Application event log
+struct EventLogSink {
+    source: usize,
+}
+
+impl EventLogSink {
+    fn register() -> Option<Self> {
+        let name = to_wide(EVENT_SOURCE);
+        let source = unsafe { RegisterEventSourceW(std::ptr::null(), name.as_ptr()) };
+        if source.is_null() {
+            None
+        } else {
+            Some(Self {
+                source: source as usize,
+            })
+        }
+    }
+}
+
+impl Sink for EventLogSink {
+    fn write(&self, event: &LogEvent) {
+        if event.level != Level::ERROR {
+            return;
+        }
+
+        let text = to_wide(event.line());
+        let mut strings = [text.as_ptr()];
+        unsafe {
+            ReportEventW(
+                self.source as _,
+                EVENTLOG_ERROR_TYPE,
+                0,
+                1,
+                std::ptr::null_mut(),
+                1,
+                0,
+                strings.as_mut_ptr(),
+                std::ptr::null_mut(),
+            );
+        }
+    }
+}
+
+struct RingSink;
+
+impl Sink for RingSink {
+    fn write(&self, event: &LogEvent) {
+        if let Some(ring) = RING.get() {
+            ring.push(event.line());
+        }
+    }
+}
+
+/// Queues events for a background thread that ships them over UDP
+#[cfg(feature = "network")]
+struct RemoteSink(Arc<RemoteQueue>);
+
+#[cfg(feature = "network")]
+struct RemoteQueue {
+    socket: UdpSocket,
+    format: Format,
+    host: String,
+    pid: u32,
+    capacity: usize,
+    pending: Mutex<Vec<String>>,
+    dropped: AtomicUsize,
+}
+
+#[cfg(feature = "network")]
+impl RemoteSink {
+    fn connect(config: &LoggingConfig) -> Result<Self, String> {
+        let format = Format::parse(&config.remote_format)?;
+        let socket = UdpSocket::bind(("0.0.0.0", 0)).map_err(|e| e.to_string())?;
+        socket.connect(&config.remote).map_err(|e| e.to_string())?;
+
+        let queue = Arc::new(RemoteQueue {
+            socket,
+            format,
+            host: std::env::var("COMPUTERNAME").unwrap_or_default(),
+            pid: unsafe { GetCurrentProcessId() },
+            capacity: config.remote_queue.max(1),
+            pending: Mutex::new(Vec::new()),
+            dropped: AtomicUsize::new(0),
+        });
+
+        let interval = Duration::from_millis(config.remote_batch_ms.max(1));
+        let sender = Arc::clone(&queue);
+        std::thread::Builder::new()
+            .name("reflex-log-remote".to_string())
+            .spawn(move || loop {
+                std::thread::sleep(interval);
+                sender.send_pending();
+            })
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self(queue))
+    }
+}
+
+#[cfg(feature = "network")]
+impl RemoteQueue {
+    fn syslog(&self, level: Level, timestamp: SystemTime, text: &str) -> String {
+        remote_log::syslog_message(
+            remote_log::severity(level.as_str()),
+            &humantime::format_rfc3339_millis(timestamp).to_string(),
+            &self.host,
+            self.pid,
+            text,
+        )
+    }
+
+    /// A JSON object tagged with the sending machine and process
+    fn json(&self, mut object: Map<String, Value>) -> String {
+        object.insert("host".to_string(), self.host.clone().into());
+        object.insert("pid".to_string(), self.pid.into());
+        Value::Object(object).to_string()
+    }
+
+    /// Send everything queued; send errors are ignored, like a full queue
+    fn send_pending(&self) {
+        let mut batch = std::mem::take(&mut *self.pending.lock().unwrap());
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            let notice = format!("[logging] {} event(s) dropped, remote logging could not keep up", dropped);
+            batch.push(match self.format {
+                Format::Syslog => self.syslog(Level::WARN, SystemTime::now(), &notice),
+                Format::Json => {
+                    let mut object = Map::new();
+                    object.insert("level".to_string(), "WARN".into());
+                    object.insert("message".to_string(), notice.into());
+                    self.json(object)
+                }
+            });
+        }
+
+        for datagram in remote_log::datagrams(&batch, self.format) {
+            let _ = self.socket.send(&datagram);
+        }
+    }
+}
+
+#[cfg(feature = "network")]
+impl Sink for RemoteSink {
+    fn write(&self, event: &LogEvent) {
+        let message = match self.0.format {
+            Format::Syslog => {
+                let text = format!("{}: {}", event.target, event.body());
+                self.0.syslog(event.level, event.timestamp, &text)
+            }
+            Format::Json => match serde_json::to_value(event) {
+                Ok(Value::Object(object)) => self.0.json(object),
+                _ => return,
+            },
+        };
+
+        let mut pending = self.0.pending.lock().unwrap();
+        if pending.len() < self.0.capacity {
+            pending.push(message);
+        } else {
+            self.0.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn flush(&self) {
+        self.0.send_pending();
+    }
+}
+
+// ============================================================================
+// Crash Detection
+// ============================================================================
+
+/// Record the crash (event log) and dump the ring buffer when the process is
+/// about to die
+unsafe fn install_crash_filter() {
+    let previous = SetUnhandledExceptionFilter(Some(crash_filter));
+    PREVIOUS_FILTER.store(previous.map_or(0, |f| f as usize), Ordering::SeqCst);
+}
+
+unsafe extern "system" fn crash_filter(info: *mut EXCEPTION_POINTERS) -> LONG {
+    let reason = if info.is_null() || (*info).ExceptionRecord.is_null() {
+        "unhandled exception".to_string()
+    } else {
+        let record = &*(*info).ExceptionRecord;
+        format!(
+            "unhandled exception 0x{:08x} at {}",
+            record.ExceptionCode,
+            stacks::describe(record.ExceptionAddress as usize)
+        )
+    };
+
+    tracing::error!("[reflex-proxy] Crash: {}", reason);
+    let _ = dump_ring(&reason);
+
+    match PREVIOUS_FILTER.load(Ordering::SeqCst) {
+        0 => EXCEPTION_CONTINUE_SEARCH,
+        previous => {
+            let previous: unsafe extern "system" fn(*mut EXCEPTION_POINTERS) -> LONG =
+                std::mem::transmute(previous);
+            previous(info)
+        }
+    }
+}
@@ -0,0 +1,209 @@
+/// Manual mapping of the original DLL
+///
+/// With `manual_map = true` reflex_original.dll is not loaded with
+/// LoadLibrary but mapped by the proxy: sections are copied to their RVAs,
+/// base relocations applied, imports resolved, section protections set and
+/// the exception table registered (RtlAddFunctionTable), so the image never
+/// appears in the loader's module lists. Its entry point is then called by
+/// the forwarded DllMain like any other original, under the proxy's control.
+///
+/// ```toml
+/// [loader]
+/// manual_map = true
+/// ```
+///
+/// Limitations: TLS callbacks are called once with DLL_PROCESS_ATTACH while
+/// mapping but never for threads, implicit TLS (`__declspec(thread)`) data
+/// is not allocated, and GetModuleHandle / GetProcAddress do not know the
+/// image (`proxy::get_original_export` reads its export table instead).
+/// Dependencies are loaded with LoadLibrary as usual. x64 only.
+
+use crate::proxy_impl::{os, pe};
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::path::Path;
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::ntdef::PVOID;
+use winapi::um::libloaderapi::GetProcAddress;
+use winapi::um::memoryapi::{VirtualAlloc, VirtualFree};
+use winapi::um::winnt::{
+    RtlAddFunctionTable, RtlDeleteFunctionTable, DLL_PROCESS_ATTACH, IMAGE_DIRECTORY_ENTRY_EXCEPTION,
+    IMAGE_DIRECTORY_ENTRY_IMPORT, IMAGE_FILE_MACHINE_AMD64, IMAGE_IMPORT_DESCRIPTOR, IMAGE_ORDINAL_FLAG64,
+    IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_READ, IMAGE_SCN_MEM_WRITE, MEM_COMMIT, MEM_RELEASE, MEM_RESERVE,
+    PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_NOACCESS, PAGE_READONLY, PAGE_READWRITE,
+    RUNTIME_FUNCTION,
+};
+
+type TlsCallbackFn = unsafe extern "system" fn(PVOID, DWORD, PVOID);
+
+/// Map the DLL at `path` into this process and return its base address
+///
+/// Its imports are loaded with the `LOAD_LIBRARY_SEARCH_*` `flags`, with
+/// the directory of `path` standing in for the DLL load directory.
+pub unsafe fn load(path: &str, flags: u32) -> Result<usize, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    load_image(&data, path, flags)
+}
+
+/// Map a DLL file held in memory; `path` names it in messages
+pub unsafe fn load_image(data: &[u8], path: &str, flags: u32) -> Result<usize, String> {
+    let mut image = pe::map_file(data).ok_or_else(|| format!("{} is not a valid PE image", path))?;
+
+    let nt = pe::nt_headers(image.as_ptr()).ok_or("invalid PE image")?;
+    if (*nt).FileHeader.Machine != IMAGE_FILE_MACHINE_AMD64 {
+        return Err(format!("{} is an {} image, not x64", path, pe::machine_name((*nt).FileHeader.Machine)));
+    }
+    let preferred = (*nt).OptionalHeader.ImageBase as usize;
+
+    // Preferred base first, so nothing needs relocating
+    let mut memory = VirtualAlloc(preferred as _, image.len(), MEM_RESERVE | MEM_COMMIT, PAGE_READWRITE) as usize;
+    if memory == 0 {
+        memory = VirtualAlloc(std::ptr::null_mut(), image.len(), MEM_RESERVE | MEM_COMMIT, PAGE_READWRITE) as usize;
+    }
+    if memory == 0 {
+        return Err(format!("VirtualAlloc of {} bytes failed", image.len()));
+    }
+
+    let mapped = pe::relocate(&mut image, memory).and_then(|()| {
+        std::ptr::copy_nonoverlapping(image.as_ptr(), memory as *mut u8, image.len());
+        resolve_imports(memory as *const u8, Path::new(path).parent(), flags)?;
+        protect_sections(memory, image.len())
+    });
+    if let Err(e) = mapped {
+        VirtualFree(memory as _, 0, MEM_RELEASE);
+        return Err(e);
+    }
+
+    register_exception_table(memory);
+    os::system().flush_instruction_cache(memory, image.len());
+    tracing::info!("[manual_map] Mapped {} at 0x{:x} ({} bytes)", path, memory, image.len());
+
+    run_tls_callbacks(memory);
+    Ok(memory)
+}
+
+/// Entry point (DllMainCRTStartup or DllMain) of a mapped image
+pub unsafe fn entry_point(base: *const u8) -> Option<usize> {
+    let nt = pe::nt_headers(base)?;
+    match (*nt).OptionalHeader.AddressOfEntryPoint {
+        0 => None,
+        rva => Some(base as usize + rva as usize),
+    }
+}
+
+/// Release a mapped image; its entry point must already have been called
+/// with DLL_PROCESS_DETACH
+pub unsafe fn unload(base: usize) {
+    if let Some((rva, _)) = pe::data_directory(base as *const u8, IMAGE_DIRECTORY_ENTRY_EXCEPTION) {
+        RtlDeleteFunctionTable((base + rva as usize) as *mut RUNTIME_FUNCTION);
+    }
+    VirtualFree(base as _, 0, MEM_RELEASE);
+}
+
+// ============================================================================
+// Mapping Steps
+// ============================================================================
+
+unsafe fn resolve_imports(base: *const u8, directory: Option<&Path>, flags: u32) -> Result<(), String> {
+    let (rva, _) = match pe::data_directory(base, IMAGE_DIRECTORY_ENTRY_IMPORT) {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+
+    let mut descriptor = base.add(rva as usize) as *const IMAGE_IMPORT_DESCRIPTOR;
+    while (*descriptor).Name != 0 {
+        let dll = read_cstr(base.add((*descriptor).Name as usize));
+        let module = load_dependency(&dll, directory, flags)?;
+
+        let lookup_rva = match *(*descriptor).u.OriginalFirstThunk() {
+            0 => (*descriptor).FirstThunk,
+            rva => rva,
+        };
+        let mut lookup = base.add(lookup_rva as usize) as *const u64;
+        let mut slot = base.add((*descriptor).FirstThunk as usize) as *mut usize;
+
+        while *lookup != 0 {
+            let address = if *lookup & IMAGE_ORDINAL_FLAG64 != 0 {
+                let ordinal = (*lookup & 0xFFFF) as usize;
+                GetProcAddress(module as _, ordinal as *const i8) as usize
+            } else {
+                // IMAGE_IMPORT_BY_NAME: u16 hint followed by the name
+                let name = CString::new(read_cstr(base.add((*lookup as u32) as usize + 2))).unwrap_or_default();
+                GetProcAddress(module as _, name.as_ptr()) as usize
+            };
+            if address == 0 {
+                return Err(format!("{} does not export an import of the original", dll));
+            }
+
+            *slot = address;
+            lookup = lookup.add(1);
+            slot = slot.add(1);
+        }
+
+        descriptor = descriptor.add(1);
+    }
+
+    Ok(())
+}
+
+/// LoadLibraryExW only honors the DLL load directory for absolute paths
+unsafe fn load_dependency(dll: &str, directory: Option<&Path>, flags: u32) -> Result<usize, String> {
+    if flags & os::LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR != 0 {
+        if let Some(local) = directory.map(|d| d.join(dll)).filter(|p| p.is_absolute() && p.is_file()) {
+            return os::system().load_library(&local.to_string_lossy(), flags);
+        }
+    }
+    os::system().load_library(dll, flags & !os::LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR)
+}
+
+/// Headers read-only, sections as their characteristics say
+unsafe fn protect_sections(base: usize, len: usize) -> Result<(), String> {
+    let os = os::system();
+    os.virtual_protect(base, len, PAGE_READONLY)?;
+
+    for section in pe::sections(base as *const u8) {
+        if section.size == 0 {
+            continue;
+        }
+        let execute = section.characteristics & IMAGE_SCN_MEM_EXECUTE != 0;
+        let read = section.characteristics & IMAGE_SCN_MEM_READ != 0;
+        let write = section.characteristics & IMAGE_SCN_MEM_WRITE != 0;
+        let protect = match (execute, read, write) {
+            (true, _, true) => PAGE_EXECUTE_READWRITE,
+            (true, true, false) => PAGE_EXECUTE_READ,
+            (true, false, false) => PAGE_EXECUTE,
+            (false, _, true) => PAGE_READWRITE,
+            (false, true, false) => PAGE_READONLY,
+            (false, false, false) => PAGE_NOACCESS,
+        };
+        os.virtual_protect(base + section.rva as usize, section.size as usize, protect)?;
+    }
+
+    Ok(())
+}
+
+/// Make the image's unwind data known, so exceptions can pass through it
+unsafe fn register_exception_table(base: usize) {
+    let (rva, size) = match pe::data_directory(base as *const u8, IMAGE_DIRECTORY_ENTRY_EXCEPTION) {
+        Some(dir) => dir,
+        None => return,
+    };
+
+    let count = size as usize / std::mem::size_of::<RUNTIME_FUNCTION>();
+    let table = (base + rva as usize) as *mut RUNTIME_FUNCTION;
+    if RtlAddFunctionTable(table, count as DWORD, base as u64) == 0 {
+        tracing::warn!("[manual_map] RtlAddFunctionTable failed; exceptions through the original will crash");
+    }
+}
+
+unsafe fn run_tls_callbacks(base: usize) {
+    for slot in pe::tls_callback_slots(base as *const u8) {
+        let callback: TlsCallbackFn = std::mem::transmute(*slot);
+        tracing::debug!("[manual_map] Calling TLS callback at 0x{:x}", *slot);
+        callback(base as PVOID, DLL_PROCESS_ATTACH, std::ptr::null_mut());
+    }
+}
+
+unsafe fn read_cstr(ptr: *const u8) -> String {
+    CStr::from_ptr(ptr as *const i8).to_string_lossy().into_owned()
+}
@@ -0,0 +1,153 @@
+/// Synthetic Reflex latency markers
+///
+/// Reflex only measures the frames a game describes with latency markers
+/// (NvAPI_D3D_SetLatencyMarker). For games that sleep but never send
+/// markers, `[nvapi] synthesize_markers` derives them from what the proxy
+/// sees of each frame (frame_events.rs, nvapi.rs):
+///
+/// - NvAPI_D3D_Sleep returns: SIMULATION_START of a new frame
+/// - the frame's first input read: INPUT_SAMPLE
+/// - Present is called: SIMULATION_END, RENDERSUBMIT_START,
+///   RENDERSUBMIT_END and PRESENT_START
+/// - Present returns: PRESENT_END
+///
+/// A game that never sleeps starts its next frame when Present returns.
+/// The model assumes a frame's simulation lasts until it is presented; a
+/// game rendering one frame while simulating the next gets markers that
+/// describe neither exactly, but frame ids and timing stay consistent.
+///
+/// Independent of the host platform; sending the markers is nvapi.rs's.
+
+/// `NV_LATENCY_MARKER_TYPE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Marker {
+    SimulationStart = 0,
+    SimulationEnd = 1,
+    RenderSubmitStart = 2,
+    RenderSubmitEnd = 3,
+    PresentStart = 4,
+    PresentEnd = 5,
+    InputSample = 6,
+}
+
+impl Marker {
+    /// The marker of an `NV_LATENCY_MARKER_TYPE` value
+    pub fn from_u32(value: u32) -> Option<Self> {
+        use Marker::*;
+        [SimulationStart, SimulationEnd, RenderSubmitStart, RenderSubmitEnd, PresentStart, PresentEnd, InputSample]
+            .get(value as usize)
+            .copied()
+    }
+}
+
+/// Markers to send, each with its frame id
+pub type Markers = Vec<(u64, Marker)>;
+
+/// Tracks the synthesized frame
+#[derive(Debug, Default)]
+pub struct Synthesizer {
+    /// Id of the current frame; 0 before the first
+    frame: u64,
+    /// The current frame's simulation started and it is not presented yet
+    open: bool,
+    input_sampled: bool,
+    /// Frames start at the end of NvAPI_D3D_Sleep, not of Present
+    sleeps: bool,
+}
+
+impl Synthesizer {
+    fn start_frame(&mut self, markers: &mut Markers) {
+        self.frame += 1;
+        self.open = true;
+        self.input_sampled = false;
+        markers.push((self.frame, Marker::SimulationStart));
+    }
+
+    /// The game's NvAPI_D3D_Sleep returned
+    pub fn sleep_end(&mut self) -> Markers {
+        self.sleeps = true;
+        let mut markers = Vec::new();
+        if !self.open {
+            self.start_frame(&mut markers);
+        }
+        markers
+    }
+
+    /// The game read input
+    pub fn input(&mut self) -> Markers {
+        if !self.open || self.input_sampled {
+            return Vec::new();
+        }
+        self.input_sampled = true;
+        vec![(self.frame, Marker::InputSample)]
+    }
+
+    /// The game called Present
+    pub fn present_start(&mut self) -> Markers {
+        let mut markers = Vec::new();
+        if !self.open {
+            self.start_frame(&mut markers);
+        }
+        self.open = false;
+        markers.extend(
+            [
+                Marker::SimulationEnd,
+                Marker::RenderSubmitStart,
+                Marker::RenderSubmitEnd,
+                Marker::PresentStart,
+            ]
+            .map(|marker| (self.frame, marker)),
+        );
+        markers
+    }
+
+    /// Present returned
+    pub fn present_end(&mut self) -> Markers {
+        if self.frame == 0 {
+            return Vec::new();
+        }
+        let mut markers = vec![(self.frame, Marker::PresentEnd)];
+        if !self.sleeps && !self.open {
+            self.start_frame(&mut markers);
+        }
+        markers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Marker::*;
+
+    #[test]
+    fn frames_start_when_the_sleep_ends() {
+        let mut synthesizer = Synthesizer::default();
+
+        assert_eq!(synthesizer.input(), []);
+        assert_eq!(synthesizer.sleep_end(), [(1, SimulationStart)]);
+        assert_eq!(synthesizer.input(), [(1, InputSample)]);
+        assert_eq!(synthesizer.input(), []);
+        assert_eq!(
+            synthesizer.present_start(),
+            [(1, SimulationEnd), (1, RenderSubmitStart), (1, RenderSubmitEnd), (1, PresentStart)]
+        );
+        assert_eq!(synthesizer.input(), []);
+        assert_eq!(synthesizer.present_end(), [(1, PresentEnd)]);
+        assert_eq!(synthesizer.sleep_end(), [(2, SimulationStart)]);
+        assert_eq!(synthesizer.sleep_end(), []);
+    }
+
+    #[test]
+    fn frames_start_at_present_without_sleeps() {
+        let mut synthesizer = Synthesizer::default();
+
+        assert_eq!(synthesizer.present_end(), []);
+        let markers = synthesizer.present_start();
+        assert_eq!(markers[0], (1, SimulationStart));
+        assert_eq!(markers.last(), Some(&(1, PresentStart)));
+        assert_eq!(synthesizer.present_end(), [(1, PresentEnd), (2, SimulationStart)]);
+        assert_eq!(synthesizer.input(), [(2, InputSample)]);
+        assert_eq!(synthesizer.present_start()[0], (2, SimulationEnd));
+    }
+}
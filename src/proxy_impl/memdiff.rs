@@ -0,0 +1,75 @@
+/// Changed ranges between two memory snapshots
+///
+/// The comparison behind the `snapshot` command (snapshot.rs): the bytes
+/// of a section before and after are compared, changes close to each other
+/// are merged into one range, and each range is reported with hex dumps of
+/// both versions.
+
+use std::fmt::Write;
+
+/// Changes at most this many unchanged bytes apart form one range
+pub const MERGE_GAP: usize = 8;
+/// Bytes per hex dump line
+const LINE: usize = 16;
+
+/// Half-open byte ranges `[start, end)` that differ between two buffers of
+/// the same length
+pub fn changed_ranges(before: &[u8], after: &[u8]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (offset, _) in before.iter().zip(after).enumerate().filter(|(_, (a, b))| a != b) {
+        match ranges.last_mut() {
+            Some((_, end)) if offset - *end <= MERGE_GAP => *end = offset + 1,
+            _ => ranges.push((offset, offset + 1)),
+        }
+    }
+    ranges
+}
+
+/// Hex dump with addresses starting at `address` and an ASCII column
+///
+/// ```text
+/// 0000000180045010  01 00 00 00 2a 00 00 00 00 00 80 3f 00 00 00 00  ....*......?....
+/// ```
+pub fn hex_dump(address: usize, bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, line) in bytes.chunks(LINE).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = line
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        let _ = writeln!(out, "{:016x}  {:<width$}  {}", address + i * LINE, hex.join(" "), ascii, width = LINE * 3 - 1);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearby_changes_merge_into_one_range() {
+        let before = vec![0u8; 64];
+        let mut after = before.clone();
+        after[3] = 1;
+        after[10] = 1; // 6 unchanged bytes after the first change
+        after[40] = 1;
+        after[63] = 1;
+
+        assert_eq!(changed_ranges(&before, &after), vec![(3, 11), (40, 41), (63, 64)]);
+        assert!(changed_ranges(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn hex_dump_lines_show_address_and_ascii() {
+        let dump = hex_dump(0x1000, b"reflex\x00\x01 proxy data!");
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "0000000000001000  72 65 66 6c 65 78 00 01 20 70 72 6f 78 79 20 64  reflex.. proxy d"
+        );
+        assert!(lines[1].starts_with("0000000000001010  61 74 61 21 "));
+        assert!(lines[1].ends_with("  ata!"));
+    }
+}
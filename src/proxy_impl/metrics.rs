@@ -0,0 +1,129 @@
+/// Prometheus metrics of a running session
+///
+/// Counters in the Prometheus text format, so long benchmark sessions can
+/// be scraped and graphed in Grafana:
+/// - `reflex_initialized`: 1 once the original DLL is loaded
+/// - `reflex_hooks_installed`, `reflex_hook_active{hook,kind}`
+/// - `reflex_errors_total{kind}`: the status error counters
+/// - `reflex_export_calls_total{export}` and the latency histogram
+///   `reflex_export_call_duration_seconds{export}` of the exports hooked by
+///   `[recording]` (histograms only for exports called at least once)
+///
+/// Served at `GET /metrics` by the HTTP endpoint (http.rs). With
+/// `[metrics]` enabled they are also written to `path` every `interval_ms`
+/// and on detach, for node_exporter's textfile collector:
+///
+/// ```toml
+/// [metrics]
+/// enabled = true
+/// path = "reflex_metrics.prom"
+/// interval_ms = 15000
+/// ```
+
+use crate::proxy_impl::config::MetricsConfig;
+use crate::proxy_impl::exposition::{Exposition, Type};
+use crate::proxy_impl::recording;
+use crate::proxy_impl::status::{self, InitState};
+use crate::proxy_impl::worker::Worker;
+use once_cell::sync::OnceCell;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static PATH: OnceCell<String> = OnceCell::new();
+static WORKER: Worker = Worker::new();
+
+/// Start writing the metrics file on a background thread
+pub fn start(config: &MetricsConfig) {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let _ = PATH.set(config.path.clone());
+
+    let interval = Duration::from_millis(config.interval_ms.max(1));
+    let spawned = WORKER.spawn("reflex-metrics", move || {
+        while RUNNING.load(Ordering::SeqCst) && WORKER.sleep(interval) {
+            if let Err(e) = write() {
+                tracing::debug!("[metrics] {}", e);
+            }
+        }
+    });
+
+    match spawned {
+        Ok(_) => tracing::info!("[metrics] Writing {} every {:?}", config.path, interval),
+        Err(e) => {
+            RUNNING.store(false, Ordering::SeqCst);
+            tracing::error!("[metrics] Failed to spawn metrics thread: {}", e);
+        }
+    }
+}
+
+/// Stop the writer thread and wait for it
+pub fn stop() {
+    RUNNING.store(false, Ordering::SeqCst);
+    WORKER.stop();
+}
+
+/// Write the metrics file; returns its path
+///
+/// The text goes to a temporary file that is renamed over `path`, so a
+/// collector never reads a half-written file.
+pub fn write() -> Result<String, String> {
+    let path = PATH.get().ok_or("metrics file is not enabled")?;
+    let temporary = format!("{}.tmp", path);
+    fs::write(&temporary, render()).map_err(|e| format!("Failed to write {}: {}", temporary, e))?;
+    fs::rename(&temporary, path).map_err(|e| format!("Failed to replace {}: {}", path, e))?;
+    Ok(path.clone())
+}
+
+/// Current metrics in the text exposition format
+pub fn render() -> String {
+    let status = unsafe { status::snapshot() };
+    let mut out = Exposition::default();
+
+    out.family("reflex_initialized", Type::Gauge, "Whether the original DLL is loaded");
+    out.sample("reflex_initialized", &[], (status.state == InitState::Initialized) as u8 as f64);
+
+    out.family("reflex_hooks_installed", Type::Gauge, "Installed hooks");
+    out.sample("reflex_hooks_installed", &[], status.hooks.len() as f64);
+    out.family("reflex_hook_active", Type::Gauge, "Whether a hook is active (not suspended)");
+    for hook in &status.hooks {
+        let kind = serde_json::to_value(hook.kind).ok().and_then(|v| v.as_str().map(str::to_string));
+        out.sample(
+            "reflex_hook_active",
+            &[("hook", &hook.name), ("kind", kind.as_deref().unwrap_or(""))],
+            hook.active as u8 as f64,
+        );
+    }
+
+    out.family("reflex_errors_total", Type::Counter, "Errors by kind");
+    let errors = &status.errors;
+    for (kind, count) in [
+        ("load", errors.load),
+        ("forward", errors.forward),
+        ("detour", errors.detour),
+        ("patch", errors.patch),
+        ("hang", errors.hang),
+    ] {
+        out.sample("reflex_errors_total", &[("kind", kind)], count as f64);
+    }
+
+    let exports = recording::call_stats();
+    if !exports.is_empty() {
+        out.family("reflex_export_calls_total", Type::Counter, "Calls into exports of the original DLL");
+        for (name, calls, _) in &exports {
+            out.sample("reflex_export_calls_total", &[("export", name)], *calls as f64);
+        }
+        out.family(
+            "reflex_export_call_duration_seconds",
+            Type::Histogram,
+            "Duration of calls into exports of the original DLL",
+        );
+        for (name, _, latency) in exports.iter().filter(|(_, _, latency)| latency.count > 0) {
+            out.histogram("reflex_export_call_duration_seconds", &[("export", name)], latency);
+        }
+    }
+
+    out.finish()
+}
@@ -1,2 +1,157 @@
+// Platform-independent logic; also builds off Windows for the unit tests
+// The decoder is only used by reflex-trace-dump, which includes this file
+#[allow(dead_code)]
+pub mod binary_trace;
+pub mod children;
+pub mod config;
+pub mod config_check;
+pub mod cow;
+pub mod environment;
+pub mod export_cache;
+pub mod exports;
+pub mod exposition;
+pub mod flamegraph;
+// Fed by the NvAPI hooks; built without them for its unit tests
+#[allow(dead_code)]
+pub mod frame_latency;
+// Used by the identity hooks; built without them for its unit tests
+#[allow(dead_code)]
+pub mod gpu_identity;
+pub mod hook_timeout;
+pub mod hooks;
+// Paces the spoofed NvAPI sleep; built without it for its unit tests
+#[allow(dead_code)]
+pub mod limiter;
+pub mod load_retry;
+pub mod locate;
+// Fed by the NvAPI hooks; built without them for its unit tests
+#[allow(dead_code)]
+pub mod markers;
+pub mod memdiff;
+pub mod offsets;
+pub mod original_watch;
+pub mod os;
+// Timed by the `overhead` control command; built without it for its unit
+// tests
+#[allow(dead_code)]
+pub mod overhead;
+// Fed by the NvAPI hooks; built without them for its unit tests
+#[allow(dead_code)]
+pub mod pacing;
+pub mod patches;
+// Used by reflex_postlink, which includes this file; built here for its
+// unit tests
+#[allow(dead_code)]
+pub mod postlink;
+pub mod qpc_jitter;
+pub mod redact;
+#[cfg(feature = "network")]
+pub mod remote_log;
+pub mod ring_buffer;
+pub mod rtti;
+pub mod rules;
+pub mod schema;
+pub mod sigcache;
+pub mod signature;
+// Fed by the NvAPI hooks; built without them for its unit tests
+#[allow(dead_code)]
+pub mod sleep_stats;
+pub mod thread_scope;
+pub mod worker;
+pub mod xrefs;
+
+// Windows only
+#[cfg(windows)]
 pub mod proxy;
+#[cfg(windows)]
+pub mod analysis;
+#[cfg(all(windows, feature = "spoof"))]
+pub mod antidebug;
+#[cfg(windows)]
+pub mod audit;
+#[cfg(windows)]
+pub mod bench;
+// Used by the forwarders build.rs generates, if exports are described
+#[cfg(windows)]
+#[allow(dead_code)]
+pub mod bindings;
+#[cfg(windows)]
+pub mod correlation;
+#[cfg(windows)]
+pub mod deferred;
+#[cfg(windows)]
 pub mod detours;
+#[cfg(all(windows, feature = "embedded-original"))]
+pub mod embedded;
+#[cfg(windows)]
+pub mod exceptions;
+#[cfg(all(windows, feature = "nvapi"))]
+pub mod frame_events;
+#[cfg(all(windows, feature = "spoof"))]
+pub mod gpu_spoof;
+#[cfg(windows)]
+pub mod hash;
+#[cfg(windows)]
+pub mod hookscan;
+#[cfg(windows)]
+pub mod hot_swap;
+#[cfg(windows)]
+pub mod hotkeys;
+#[cfg(all(windows, feature = "network"))]
+pub mod http;
+#[cfg(windows)]
+pub mod image_dump;
+#[cfg(windows)]
+pub mod install;
+#[cfg(windows)]
+pub mod instance;
+#[cfg(windows)]
+pub mod ipc;
+#[cfg(windows)]
+pub mod launches;
+#[cfg(windows)]
+pub mod logging;
+#[cfg(windows)]
+pub mod manual_map;
+#[cfg(windows)]
+pub mod metrics;
+#[cfg(all(windows, feature = "nvapi"))]
+pub mod nvapi;
+#[cfg(windows)]
+pub mod pe;
+#[cfg(windows)]
+pub mod profiler;
+#[cfg(windows)]
+pub mod qpc_hook;
+#[cfg(windows)]
+pub mod recording;
+#[cfg(windows)]
+pub mod selftest;
+#[cfg(windows)]
+pub mod sigscan;
+#[cfg(windows)]
+pub mod snapshot;
+#[cfg(windows)]
+pub mod stacks;
+#[cfg(windows)]
+pub mod status;
+#[cfg(windows)]
+pub mod stealth;
+#[cfg(all(windows, feature = "trace", feature = "network"))]
+pub mod stream;
+#[cfg(windows)]
+pub mod symbols;
+#[cfg(windows)]
+pub mod syscalls;
+#[cfg(windows)]
+pub mod tls;
+#[cfg(all(windows, feature = "trace"))]
+pub mod trace;
+#[cfg(windows)]
+pub mod trampoline;
+#[cfg(windows)]
+pub mod watchdog;
+#[cfg(windows)]
+pub mod wide;
+#[cfg(windows)]
+pub mod window;
@@ -0,0 +1,455 @@
+/// NVIDIA Reflex calls made through NvAPI
+///
+/// nvapi64.dll has one export, `nvapi_QueryInterface`, which returns the
+/// entry point of a function by id. With `[nvapi]` enabled it is hooked as
+/// soon as nvapi64.dll loads (deferred.rs), and the Reflex entry points it
+/// hands out are replaced with wrappers that report to sleep_stats.rs
+/// before calling the driver:
+/// - NvAPI_D3D_SetSleepMode: the sleep mode the game sets, with the
+///   parameters of `[nvapi.sleep_mode]` forced on it
+/// - NvAPI_D3D_Sleep: how long each frame's sleep blocked
+/// - NvAPI_D3D_GetSleepStatus, NvAPI_D3D_SetLatencyMarker and
+///   NvAPI_D3D_GetLatency: counted
+///
+/// With `synthesize_markers`, a game that sleeps but sends no latency
+/// markers gets them from its sleeps, presents and input reads
+/// (markers.rs, frame_events.rs), sent for the device of its last
+/// NvAPI_D3D_Sleep. The first marker the game sends itself stops them.
+///
+/// With `[nvapi.pacing]`, presents, sleeps and the markers sent (the
+/// game's or synthesized) are joined into frame records for the pacing
+/// analysis (pacing.rs), and with `frames_path` exported frame by frame
+/// (frame_latency.rs).
+///
+/// With `spoof_support`, the wrappers answer without the driver, as one
+/// supporting Reflex would: SetSleepMode keeps the mode, Sleep waits in the
+/// proxy's frame limiter (limiter.rs) for the mode's minimum interval,
+/// GetSleepStatus reports it, SetLatencyMarker accepts every marker and
+/// GetLatency returns no frames. Entry points the driver lacks are stubbed
+/// the same way whenever this is set, even during passthrough.
+///
+/// ```toml
+/// [nvapi]
+/// enabled = true
+/// summary_path = "reflex_sleep.json"   # written on detach; "" = log only
+///
+/// synthesize_markers = true
+/// spoof_support = false                # answer as if Reflex were supported
+///
+/// [nvapi.sleep_mode]
+/// low_latency = true                   # unset keys keep the game's value
+/// minimum_interval_us = 8333
+///
+/// [nvapi.pacing]
+/// enabled = true
+/// threshold_ms = 2.0
+/// frames_path = "reflex_frames.jsonl"  # "" = no per-frame export
+/// ```
+///
+/// The driver gets a copy of the game's parameters; its own structure is
+/// left as it was. Nothing is forced during passthrough (hooks.rs).
+///
+/// Entry points the game queried before the hook (nvapi64.dll loaded
+/// before reflex.dll) are not seen. Wrappers stay in the game's hands
+/// after the hook is removed, so they forward to the driver until the
+/// process exits. Vulkan Reflex (NvLowLatencyVk.dll) is not covered.
+
+use crate::proxy_impl::config::NvapiConfig;
+use crate::proxy_impl::frame_events::{self, FrameEvent};
+use crate::proxy_impl::hooks::declare_hook;
+use crate::proxy_impl::limiter::{self, FrameLimiter};
+use crate::proxy_impl::markers::{Marker, Markers, Synthesizer};
+use crate::proxy_impl::sleep_stats::{self, SleepMode};
+use crate::proxy_impl::{config, deferred, frame_latency, hooks, os, pacing};
+use once_cell::sync::Lazy;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// `NvAPI_Status`
+type Status = i32;
+
+const NVAPI_OK: Status = 0;
+const NVAPI_INVALID_ARGUMENT: Status = -5;
+const NVAPI_INCOMPATIBLE_STRUCT_VERSION: Status = -9;
+
+type SetSleepModeFn = unsafe extern "C" fn(*mut c_void, *mut SleepModeParams) -> Status;
+type SleepFn = unsafe extern "C" fn(*mut c_void) -> Status;
+type SetLatencyMarkerFn = unsafe extern "C" fn(*mut c_void, *mut LatencyMarkerParams) -> Status;
+/// GetSleepStatus, SetLatencyMarker, GetLatency: a device and a versioned
+/// parameter structure
+type ParamsFn = unsafe extern "C" fn(*mut c_void, *mut c_void) -> Status;
+
+// Function ids of nvapi_interface.h
+const ID_SET_SLEEP_MODE: u32 = 0xac1c_a9e0;
+const ID_SLEEP: u32 = 0x852c_d1d2;
+const ID_GET_SLEEP_STATUS: u32 = 0xaef9_6ca1;
+const ID_SET_LATENCY_MARKER: u32 = 0xd998_4c05;
+const ID_GET_LATENCY: u32 = 0x1a58_7f9c;
+
+/// `NV_SET_SLEEP_MODE_PARAMS_V1`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SleepModeParams {
+    version: u32,
+    low_latency_mode: u8,
+    low_latency_boost: u8,
+    minimum_interval_us: u32,
+    use_markers_to_optimize: u8,
+    reserved: [u8; 31],
+}
+
+/// `NV_LATENCY_MARKER_PARAMS_V1`
+#[repr(C)]
+struct LatencyMarkerParams {
+    version: u32,
+    frame_id: u64,
+    marker_type: u32,
+    reserved0: u64,
+    reserved: [u8; 56],
+}
+
+/// A wrapped entry point
+struct Function {
+    name: &'static str,
+    /// The driver's entry point; set before the wrapper is handed out
+    original: AtomicUsize,
+}
+
+impl Function {
+    const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            original: AtomicUsize::new(0),
+        }
+    }
+
+    /// The driver's entry point, as `F` (its `...Fn` type)
+    unsafe fn original<F>(&self) -> F {
+        std::mem::transmute_copy(&self.original.load(Ordering::Acquire))
+    }
+}
+
+static SET_SLEEP_MODE: Function = Function::new("NvAPI_D3D_SetSleepMode");
+static SLEEP: Function = Function::new("NvAPI_D3D_Sleep");
+static GET_SLEEP_STATUS: Function = Function::new("NvAPI_D3D_GetSleepStatus");
+static SET_LATENCY_MARKER: Function = Function::new("NvAPI_D3D_SetLatencyMarker");
+static GET_LATENCY: Function = Function::new("NvAPI_D3D_GetLatency");
+
+/// Synthesize latency markers (`synthesize_markers`, until the game sends
+/// its own)
+static SYNTHESIZE: AtomicBool = AtomicBool::new(false);
+static SYNTHESIZER: Lazy<Mutex<Synthesizer>> = Lazy::new(|| Mutex::new(Synthesizer::default()));
+/// Device of the game's last sleep, which synthetic markers are sent for
+static DEVICE: AtomicUsize = AtomicUsize::new(0);
+
+/// Feed the pacing analysis (`[nvapi.pacing]`)
+static PACING: AtomicBool = AtomicBool::new(false);
+
+/// Answer without the driver (`spoof_support`)
+static SPOOF: AtomicBool = AtomicBool::new(false);
+/// The mode last set while spoofing, as passed on
+static SPOOFED_MODE: Mutex<Option<SleepMode>> = Mutex::new(None);
+static LIMITER: Mutex<FrameLimiter> = Mutex::new(FrameLimiter::new());
+
+declare_hook! {
+    /// Hands out the wrappers in place of the Reflex entry points
+    fn nvapi_QueryInterface(id: u32) -> usize {
+        wrap(id, call_original(id))
+    }
+}
+
+/// Hook nvapi_QueryInterface once nvapi64.dll is loaded
+pub unsafe fn initialize(config: &NvapiConfig) {
+    // The session, and its summary, starts here
+    sleep_stats::now();
+
+    if config.spoof_support {
+        tracing::info!("[nvapi] Spoofing Reflex support, sleeps go to the proxy's frame limiter");
+        SPOOF.store(true, Ordering::SeqCst);
+    }
+
+    if config.pacing.enabled {
+        pacing::start(Duration::from_secs_f64(config.pacing.threshold_ms.max(0.0) / 1000.0));
+        if !config.pacing.frames_path.is_empty() {
+            match frame_latency::start(&config.pacing.frames_path) {
+                Ok(()) => tracing::info!("[nvapi] Exporting frame latencies to {}", config.pacing.frames_path),
+                Err(e) => tracing::error!("[nvapi] {}", e),
+            }
+        }
+    }
+    if config.synthesize_markers || config.pacing.enabled {
+        match frame_events::subscribe(on_frame_event) {
+            Ok(()) => {
+                SYNTHESIZE.store(config.synthesize_markers, Ordering::SeqCst);
+                PACING.store(config.pacing.enabled, Ordering::SeqCst);
+                frame_events::install();
+            }
+            Err(e) => tracing::warn!("[nvapi] Cannot follow presents and input: {}", e),
+        }
+    }
+
+    deferred::when_loaded("nvapi64.dll", "nvapi_QueryInterface", |base| unsafe {
+        let target = os::system()
+            .get_proc_address(base, "nvapi_QueryInterface")
+            .ok_or("nvapi_QueryInterface is not exported")?;
+        nvapi_QueryInterface::install_inline(target)
+    });
+}
+
+/// The wrapper to return for the entry point `entry` of function `id`, or
+/// `entry` itself if the function is not wrapped
+fn wrap(id: u32, entry: usize) -> usize {
+    // The GPU's identity (`[gpu_identity]`, gpu_spoof.rs)
+    #[cfg(feature = "spoof")]
+    if let Some(wrapper) = crate::proxy_impl::gpu_spoof::wrap_nvapi(id, entry) {
+        return wrapper;
+    }
+    let (function, wrapper) = match id {
+        ID_SET_SLEEP_MODE => (&SET_SLEEP_MODE, set_sleep_mode as SetSleepModeFn as usize),
+        ID_SLEEP => (&SLEEP, sleep as SleepFn as usize),
+        ID_GET_SLEEP_STATUS => (&GET_SLEEP_STATUS, get_sleep_status as ParamsFn as usize),
+        ID_SET_LATENCY_MARKER => (&SET_LATENCY_MARKER, set_latency_marker as ParamsFn as usize),
+        ID_GET_LATENCY => (&GET_LATENCY, get_latency as ParamsFn as usize),
+        _ => return entry,
+    };
+    // Not supported by this driver
+    if entry == 0 {
+        if !SPOOF.load(Ordering::Relaxed) {
+            return 0;
+        }
+        tracing::info!("[nvapi] The driver has no {}, stubbing it", function.name);
+        return wrapper;
+    }
+    if function.original.swap(entry, Ordering::AcqRel) != entry {
+        tracing::info!("[nvapi] Wrapping {} (0x{:x})", function.name, entry);
+    }
+    wrapper
+}
+
+// ============================================================================
+// Wrappers
+// ============================================================================
+
+/// Answer a call of `function` without the driver: support is spoofed
+/// (not during passthrough), or the driver doesn't have it
+fn stubbed(function: &Function) -> bool {
+    function.original.load(Ordering::Acquire) == 0 || (SPOOF.load(Ordering::Relaxed) && !hooks::passthrough())
+}
+
+/// Zero the versioned structure `params` after its version, as a stubbed
+/// call returning nothing; its size is the version's low word
+unsafe fn clear_params(params: *mut c_void) -> Status {
+    if params.is_null() {
+        return NVAPI_INVALID_ARGUMENT;
+    }
+    let size = (*(params as *const u32) & 0xffff) as usize;
+    if size < 8 {
+        return NVAPI_INCOMPATIBLE_STRUCT_VERSION;
+    }
+    std::ptr::write_bytes((params as *mut u8).add(4), 0, size - 4);
+    NVAPI_OK
+}
+
+unsafe extern "C" fn set_sleep_mode(device: *mut c_void, params: *mut SleepModeParams) -> Status {
+    sleep_stats::record_call(SET_SLEEP_MODE.name);
+    let game = match params.as_ref() {
+        Some(params) => *params,
+        None if stubbed(&SET_SLEEP_MODE) => return NVAPI_INVALID_ARGUMENT,
+        None => return SET_SLEEP_MODE.original::<SetSleepModeFn>()(device, params),
+    };
+
+    let mode = SleepMode {
+        low_latency: game.low_latency_mode != 0,
+        boost: game.low_latency_boost != 0,
+        minimum_interval_us: game.minimum_interval_us,
+        use_markers: game.use_markers_to_optimize != 0,
+    };
+    let forced = match hooks::passthrough() {
+        true => mode,
+        false => mode.overridden(&config::get().nvapi.sleep_mode),
+    };
+    if PACING.load(Ordering::Relaxed) {
+        let target = Duration::from_micros(forced.minimum_interval_us.into());
+        pacing::record_target(Some(target).filter(|target| forced.low_latency && !target.is_zero()));
+    }
+    if sleep_stats::record_mode(mode) {
+        tracing::info!("[nvapi] Sleep mode: {}", describe(mode));
+        if forced != mode {
+            tracing::info!("[nvapi] Forcing sleep mode: {}", describe(forced));
+        }
+    }
+    if stubbed(&SET_SLEEP_MODE) {
+        *SPOOFED_MODE.lock().unwrap() = Some(forced);
+        return NVAPI_OK;
+    }
+    if forced == mode {
+        return SET_SLEEP_MODE.original::<SetSleepModeFn>()(device, params);
+    }
+
+    let mut copy = SleepModeParams {
+        low_latency_mode: forced.low_latency as u8,
+        low_latency_boost: forced.boost as u8,
+        minimum_interval_us: forced.minimum_interval_us,
+        use_markers_to_optimize: forced.use_markers as u8,
+        ..game
+    };
+    SET_SLEEP_MODE.original::<SetSleepModeFn>()(device, &mut copy)
+}
+
+unsafe extern "C" fn sleep(device: *mut c_void) -> Status {
+    sleep_stats::record_call(SLEEP.name);
+    let at = sleep_stats::now();
+    let status = match stubbed(&SLEEP) {
+        true => {
+            fallback_sleep();
+            NVAPI_OK
+        }
+        false => SLEEP.original::<SleepFn>()(device),
+    };
+    let end = sleep_stats::now();
+    sleep_stats::record_sleep(at, end.saturating_sub(at));
+    if PACING.load(Ordering::Relaxed) {
+        pacing::record_sleep(end, end.saturating_sub(at));
+    }
+
+    if SYNTHESIZE.load(Ordering::Relaxed) && !hooks::passthrough() {
+        DEVICE.store(device as usize, Ordering::Release);
+        let markers = SYNTHESIZER.lock().unwrap().sleep_end();
+        send_markers(markers);
+    }
+    status
+}
+
+unsafe extern "C" fn get_sleep_status(device: *mut c_void, params: *mut c_void) -> Status {
+    sleep_stats::record_call(GET_SLEEP_STATUS.name);
+    if stubbed(&GET_SLEEP_STATUS) {
+        let status = clear_params(params);
+        if status == NVAPI_OK {
+            // NV_GET_SLEEP_STATUS_PARAMS::bLowLatencyMode
+            let low_latency = SPOOFED_MODE.lock().unwrap().is_some_and(|mode| mode.low_latency);
+            *(params as *mut u8).add(4) = low_latency as u8;
+        }
+        return status;
+    }
+    GET_SLEEP_STATUS.original::<ParamsFn>()(device, params)
+}
+
+unsafe extern "C" fn set_latency_marker(device: *mut c_void, params: *mut c_void) -> Status {
+    sleep_stats::record_call(SET_LATENCY_MARKER.name);
+    if SYNTHESIZE.swap(false, Ordering::Relaxed) {
+        tracing::info!("[nvapi] The game sends its own latency markers, no longer synthesizing them");
+    }
+    if let (true, Some(marker)) = (PACING.load(Ordering::Relaxed), (params as *const LatencyMarkerParams).as_ref()) {
+        if let Some(kind) = Marker::from_u32(marker.marker_type) {
+            pacing::record_marker(marker.frame_id, kind, sleep_stats::now());
+        }
+    }
+    if stubbed(&SET_LATENCY_MARKER) {
+        return match params.is_null() {
+            true => NVAPI_INVALID_ARGUMENT,
+            false => NVAPI_OK,
+        };
+    }
+    SET_LATENCY_MARKER.original::<ParamsFn>()(device, params)
+}
+
+unsafe extern "C" fn get_latency(device: *mut c_void, params: *mut c_void) -> Status {
+    sleep_stats::record_call(GET_LATENCY.name);
+    if stubbed(&GET_LATENCY) {
+        return clear_params(params);
+    }
+    GET_LATENCY.original::<ParamsFn>()(device, params)
+}
+
+/// Wait for the spoofed mode's minimum interval, in place of the driver
+fn fallback_sleep() {
+    let interval = match *SPOOFED_MODE.lock().unwrap() {
+        Some(mode) if mode.low_latency => mode.minimum_interval_us,
+        _ => 0,
+    };
+    let wait = LIMITER
+        .lock()
+        .unwrap()
+        .wait(sleep_stats::now(), Duration::from_micros(interval.into()));
+    limiter::pause(wait);
+}
+
+fn describe(mode: SleepMode) -> String {
+    format!(
+        "low latency {}, boost {}, minimum interval {} us, markers {}",
+        mode.low_latency, mode.boost, mode.minimum_interval_us, mode.use_markers
+    )
+}
+
+// ============================================================================
+// Synthetic Markers
+// ============================================================================
+
+fn on_frame_event(event: FrameEvent) {
+    if PACING.load(Ordering::Relaxed) {
+        match event {
+            FrameEvent::PresentStart => pacing::record_present_start(sleep_stats::now()),
+            FrameEvent::PresentEnd => {
+                if let Some(record) = pacing::record_present_end(sleep_stats::now()) {
+                    frame_latency::write(&record);
+                }
+            }
+            FrameEvent::InputSample => {}
+        }
+    }
+    if !SYNTHESIZE.load(Ordering::Relaxed) {
+        return;
+    }
+    let markers = {
+        let mut synthesizer = SYNTHESIZER.lock().unwrap();
+        match event {
+            FrameEvent::InputSample => synthesizer.input(),
+            FrameEvent::PresentStart => synthesizer.present_start(),
+            FrameEvent::PresentEnd => synthesizer.present_end(),
+        }
+    };
+    unsafe { send_markers(markers) };
+}
+
+/// Send markers to the driver, for the device of the last sleep
+unsafe fn send_markers(markers: Markers) {
+    if PACING.load(Ordering::Relaxed) {
+        let at = sleep_stats::now();
+        for &(frame_id, marker) in &markers {
+            pacing::record_marker(frame_id, marker, at);
+        }
+    }
+    let device = DEVICE.load(Ordering::Acquire);
+    // A spoofed driver takes no markers
+    if markers.is_empty() || device == 0 || (SPOOF.load(Ordering::Relaxed) && !hooks::passthrough()) {
+        return;
+    }
+    // The game may never have asked for the entry point
+    if SET_LATENCY_MARKER.original.load(Ordering::Acquire) == 0 {
+        match nvapi_QueryInterface::call_original(ID_SET_LATENCY_MARKER) {
+            0 => {
+                tracing::warn!("[nvapi] The driver has no NvAPI_D3D_SetLatencyMarker, not synthesizing markers");
+                SYNTHESIZE.store(false, Ordering::Relaxed);
+                return;
+            }
+            entry => SET_LATENCY_MARKER.original.store(entry, Ordering::Release),
+        }
+    }
+
+    let set_latency_marker = SET_LATENCY_MARKER.original::<SetLatencyMarkerFn>();
+    for (frame_id, marker) in markers {
+        let mut params = LatencyMarkerParams {
+            version: std::mem::size_of::<LatencyMarkerParams>() as u32 | (1 << 16),
+            frame_id,
+            marker_type: marker as u32,
+            reserved0: 0,
+            reserved: [0; 56],
+        };
+        let status = set_latency_marker(device as *mut c_void, &mut params);
+        if status != 0 {
+            tracing::debug!("[nvapi] Marker {:?} of frame {} failed with status {}", marker, frame_id, status);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+/// Offset database for internal functions of reflex_original.dll
+///
+/// Offsets are relative to the DLL base (0x180000000 in the analysed build)
+/// and come from reverse engineering with radare2. Every offset used by the
+/// detours lives here so the self-test can validate them all in one place.
+///
+/// Raw offsets break with every rebuild of the DLL. An entry with an
+/// `anchor` is found at runtime from an export instead, which usually
+/// survives updates:
+///
+/// ```rust,ignore
+/// pub const INIT_FN: KnownOffset = KnownOffset {
+///     name: "internal_init",
+///     offset: 0x1000, // where the analysed build has it
+///     // Third call inside the exported Initialize
+///     anchor: Some(Anchor { export: "Initialize", call: Some(2), delta: 0 }),
+///     description: "Initialization routine called during attach",
+/// };
+/// ```
+
+use iced_x86::{Decoder, DecoderOptions, FlowControl, OpKind};
+#[cfg(windows)]
+use crate::proxy_impl::pe;
+
+/// Largest function decoded for `Anchor::call` when the image has no
+/// unwind data for it
+#[cfg(windows)]
+const MAX_FUNCTION_BYTES: u32 = 0x2000;
+
+/// A known internal location in the original DLL
+#[derive(Debug, Clone, Copy)]
+pub struct KnownOffset {
+    pub name: &'static str,
+    /// RVA in the analysed build
+    pub offset: usize,
+    /// How to find the location in other builds; `offset` is used without one
+    pub anchor: Option<Anchor>,
+    pub description: &'static str,
+}
+
+/// A location relative to an export of the original: `delta` bytes from
+/// the exported function, or from the target of its `call`-th (from 0)
+/// direct call
+#[derive(Debug, Clone, Copy)]
+pub struct Anchor {
+    pub export: &'static str,
+    pub call: Option<usize>,
+    pub delta: isize,
+}
+
+/// Internal initialization function
+pub const INIT_FN: KnownOffset = KnownOffset {
+    name: "internal_init",
+    offset: 0x1000, // Replace with actual offset
+    anchor: None,
+    description: "Initialization routine called during attach",
+};
+
+/// Internal cleanup function
+pub const CLEANUP_FN: KnownOffset = KnownOffset {
+    name: "internal_cleanup",
+    offset: 0x2000, // Replace with actual offset
+    anchor: None,
+    description: "Cleanup routine called during detach",
+};
+
+/// Example target used by `detours::hook_internal_function_example`
+pub const EXAMPLE_FN: KnownOffset = KnownOffset {
+    name: "internal_example",
+    offset: 0x1234,
+    anchor: Some(Anchor { export: "DllMain", call: Some(0), delta: 0 }),
+    description: "Example internal function (DWORD, LPVOID) -> BOOL",
+};
+
+/// All offsets known to the proxy
+pub const KNOWN_OFFSETS: &[KnownOffset] = &[INIT_FN, CLEANUP_FN, EXAMPLE_FN];
+
+/// RVA of an entry in the mapped image, through its anchor if it has one
+#[cfg(windows)]
+pub unsafe fn resolve(base: *const u8, entry: &KnownOffset) -> Result<usize, String> {
+    let anchor = match entry.anchor {
+        Some(anchor) => anchor,
+        None => return Ok(entry.offset),
+    };
+    let export = pe::exports(base)
+        .into_iter()
+        .find(|e| e.forwarder.is_none() && e.name.as_deref() == Some(anchor.export))
+        .ok_or_else(|| format!("anchor export {} not found", anchor.export))?;
+
+    let start = match anchor.call {
+        None => export.rva as usize,
+        Some(index) => {
+            let end = match pe::function_range(base, export.rva) {
+                Some((_, end)) => end,
+                None => pe::section_for_rva(base, export.rva)
+                    .map_or(export.rva, |s| s.rva + s.size)
+                    .min(export.rva + MAX_FUNCTION_BYTES),
+            };
+            let code = std::slice::from_raw_parts(base.add(export.rva as usize), (end - export.rva) as usize);
+            let target = nth_call_target(code, base as u64 + export.rva as u64, index).ok_or_else(|| {
+                format!("{} has no call #{} (0x{:x}-0x{:x})", anchor.export, index, export.rva, end)
+            })?;
+            (target as usize).wrapping_sub(base as usize)
+        }
+    };
+    Ok(start.wrapping_add_signed(anchor.delta))
+}
+
+/// Check that an offset points into executable code of the mapped image
+#[cfg(windows)]
+pub unsafe fn validate_offset(base: *const u8, entry: &KnownOffset) -> Result<(), String> {
+    let size = pe::image_size(base).ok_or_else(|| "Invalid PE image".to_string())?;
+    let offset = resolve(base, entry)?;
+    let section = pe::section_for_rva(base, offset as u32);
+    let resolved = KnownOffset { offset, ..*entry };
+    check_offset(&resolved, size, section.as_ref().map(|s| (s.name.as_str(), s.is_executable())))
+}
+
+/// Target of the `index`-th direct call in `code`, decoded as x64 starting
+/// at address `ip`
+pub fn nth_call_target(code: &[u8], ip: u64, index: usize) -> Option<u64> {
+    Decoder::with_ip(64, code, ip, DecoderOptions::NONE)
+        .into_iter()
+        .take_while(|instruction| !instruction.is_invalid())
+        .filter(|instruction| {
+            instruction.flow_control() == FlowControl::Call && instruction.op0_kind() == OpKind::NearBranch64
+        })
+        .nth(index)
+        .map(|instruction| instruction.near_branch_target())
+}
+
+/// The checks of `validate_offset` for an image of `image_size` bytes
+///
+/// `section` is the name and executability of the section containing the
+/// offset, if any.
+pub fn check_offset(entry: &KnownOffset, image_size: u32, section: Option<(&str, bool)>) -> Result<(), String> {
+    if entry.offset >= image_size as usize {
+        return Err(format!(
+            "offset 0x{:x} is outside the image (size 0x{:x})",
+            entry.offset, image_size
+        ));
+    }
+
+    match section {
+        Some((_, true)) => Ok(()),
+        Some((name, false)) => Err(format!(
+            "offset 0x{:x} is in non-executable section {}",
+            entry.offset, name
+        )),
+        None => Err(format!("offset 0x{:x} is not inside any section", entry.offset)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_offsets_are_unique() {
+        for (i, a) in KNOWN_OFFSETS.iter().enumerate() {
+            for b in &KNOWN_OFFSETS[i + 1..] {
+                assert_ne!(a.name, b.name);
+                assert_ne!(a.offset, b.offset, "{} and {}", a.name, b.name);
+            }
+        }
+    }
+
+    #[test]
+    fn offset_checks() {
+        assert!(check_offset(&INIT_FN, 0x10000, Some((".text", true))).is_ok());
+
+        let outside = check_offset(&INIT_FN, 0x800, Some((".text", true))).unwrap_err();
+        assert!(outside.contains("outside the image"), "{}", outside);
+
+        let data = check_offset(&INIT_FN, 0x10000, Some((".data", false))).unwrap_err();
+        assert!(data.contains("non-executable section .data"), "{}", data);
+
+        let headers = check_offset(&INIT_FN, 0x10000, None).unwrap_err();
+        assert!(headers.contains("not inside any section"), "{}", headers);
+    }
+
+    #[test]
+    fn nth_call_targets_follow_direct_calls_only() {
+        let code = [
+            0x48, 0x83, 0xEC, 0x28, // sub rsp, 28h
+            0xE8, 0x10, 0x00, 0x00, 0x00, // call +0x10
+            0xFF, 0x15, 0x00, 0x10, 0x00, 0x00, // call [rip+0x1000] (indirect)
+            0xE8, 0xF0, 0xFF, 0xFF, 0xFF, // call -0x10
+            0x48, 0x83, 0xC4, 0x28, // add rsp, 28h
+            0xC3, // ret
+        ];
+        let ip = 0x1_8000_1000;
+
+        assert_eq!(nth_call_target(&code, ip, 0), Some(ip + 9 + 0x10));
+        assert_eq!(nth_call_target(&code, ip, 1), Some(ip + 20 - 0x10));
+        assert_eq!(nth_call_target(&code, ip, 2), None);
+    }
+}
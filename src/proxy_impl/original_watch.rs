@@ -0,0 +1,269 @@
+/// Detection of reflex_original.dll being replaced on disk
+///
+/// Driver updates replace the DLL mid-install while the game keeps running
+/// the build it loaded. The next launch loads the new build, for which
+/// offsets (offsets.rs), cached signature matches (sigscan.rs) and
+/// whatever was worked out from the old one may be wrong. A background
+/// thread checks the file's size and modification time every `interval_ms`
+/// and, when they change, its SHA-256 against the build that was loaded:
+///
+/// ```toml
+/// [original_watch]
+/// enabled = true                # default
+/// interval_ms = 2000
+/// suspend_offset_hooks = false
+/// ```
+///
+/// On a replacement it logs a warning, deletes the signature scan cache
+/// and, with `suspend_offset_hooks`, suspends the inline hooks inside the
+/// original that are not at one of its exports. They stay suspended until
+/// the `reverify` control command finds every known offset valid again,
+/// or the file is put back to the loaded build. A file rewritten with the
+/// same content is ignored.
+///
+/// A manually mapped or embedded original has no file to watch.
+
+use std::path::Path;
+use std::time::SystemTime;
+
+/// What identifies a version of the file without reading it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl Stamp {
+    /// None while the file does not exist, e.g. between an installer's
+    /// delete and copy
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Names of the inline hooks inside `image` (start, end) that are not at
+/// one of `exports`, i.e. placed by offset
+pub fn offset_based(inline: &[(String, usize)], image: (usize, usize), exports: &[usize]) -> Vec<String> {
+    inline
+        .iter()
+        .filter(|(_, target)| (image.0..image.1).contains(target) && !exports.contains(target))
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+#[cfg(windows)]
+pub use windows::{reverify, start, stop};
+
+#[cfg(windows)]
+mod windows {
+    use super::{offset_based, Stamp};
+    use crate::proxy;
+    use crate::proxy_impl::config::OriginalWatchConfig;
+    use crate::proxy_impl::worker::Worker;
+    use crate::proxy_impl::{hash, hooks, offsets, pe, sigscan};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use winapi::shared::minwindef::HMODULE;
+
+    static RUNNING: AtomicBool = AtomicBool::new(false);
+    static WORKER: Worker = Worker::new();
+    /// Hooks suspended because the file was replaced
+    static SUSPENDED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    /// Start watching the loaded original's file
+    pub unsafe fn start(config: &OriginalWatchConfig) {
+        let base = proxy::get_original_dll_base();
+        let path = match proxy::get_module_path(base as HMODULE) {
+            Some(path) => path,
+            None => {
+                tracing::debug!("[original_watch] The original has no file to watch");
+                return;
+            }
+        };
+        if RUNNING.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let interval = Duration::from_millis(config.interval_ms.max(100));
+        let suspend = config.suspend_offset_hooks;
+        let spawned = WORKER.spawn("reflex-original-watch", move || watch(path, interval, suspend));
+
+        if let Err(e) = spawned {
+            RUNNING.store(false, Ordering::SeqCst);
+            tracing::error!("[original_watch] Failed to spawn watch thread: {}", e);
+        }
+    }
+
+    /// Stop watching and wait for the watch thread
+    pub fn stop() {
+        RUNNING.store(false, Ordering::SeqCst);
+        WORKER.stop();
+    }
+
+    /// Check every known offset in the loaded original, its inline hooks
+    /// suspended meanwhile, and resume the hooks suspended because its file
+    /// was replaced
+    pub fn reverify() -> Result<String, String> {
+        let base = unsafe { proxy::get_original_dll_base() } as *const u8;
+        if base.is_null() {
+            return Err("original DLL is not loaded".to_string());
+        }
+        // Anchored offsets are found by decoding an export's code, which
+        // must be the original's and not the jump of a hook on it
+        let image = base as usize..base as usize + unsafe { pe::image_size(base) }.unwrap_or(0) as usize;
+        let intact: Vec<hooks::SuspendGuard> = hooks::inline_targets()
+            .into_iter()
+            .filter(|(_, target)| image.contains(target))
+            .filter_map(|(name, _)| unsafe { hooks::suspend_scoped(&name) }.ok())
+            .collect();
+        let failed: Vec<String> = offsets::KNOWN_OFFSETS
+            .iter()
+            .filter_map(|entry| unsafe { offsets::validate_offset(base, entry) }.err().map(|e| format!("{}: {}", entry.name, e)))
+            .collect();
+        drop(intact);
+        if !failed.is_empty() {
+            return Err(format!("offsets still invalid, hooks stay suspended: {}", failed.join("; ")));
+        }
+        Ok(format!("{} known offset(s) valid, resumed {} hook(s)", offsets::KNOWN_OFFSETS.len(), resume()))
+    }
+
+    fn watch(path: PathBuf, interval: Duration, suspend: bool) {
+        let loaded = match hash::sha256_file(&path) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                RUNNING.store(false, Ordering::SeqCst);
+                tracing::warn!("[original_watch] Not watching {}: {}", path.display(), e);
+                return;
+            }
+        };
+        tracing::info!("[original_watch] Watching {} for replacement", path.display());
+
+        let mut last = Stamp::of(&path);
+        let mut replaced = false;
+        while RUNNING.load(Ordering::SeqCst) && WORKER.sleep(interval) {
+            let stamp = Stamp::of(&path);
+            if stamp == last {
+                continue;
+            }
+            last = stamp;
+            // Mid-install; compare once the new file is there
+            if stamp.is_none() {
+                continue;
+            }
+            let current = match hash::sha256_file(&path) {
+                Ok(current) => current,
+                Err(e) => {
+                    tracing::debug!("[original_watch] Cannot hash {} yet: {}", path.display(), e);
+                    last = None;
+                    continue;
+                }
+            };
+
+            match (current == loaded, replaced) {
+                (true, false) => tracing::debug!("[original_watch] {} was rewritten unchanged", path.display()),
+                (true, true) => {
+                    replaced = false;
+                    tracing::info!(
+                        "[original_watch] {} is the loaded build again, resumed {} hook(s)",
+                        path.display(),
+                        resume()
+                    );
+                }
+                (false, _) => {
+                    replaced = true;
+                    on_replaced(&path, &current, suspend);
+                }
+            }
+        }
+    }
+
+    fn on_replaced(path: &std::path::Path, sha256: &str, suspend: bool) {
+        tracing::warn!("[original_watch] ==============================================================");
+        tracing::warn!("[original_watch] {} was REPLACED on disk (now sha256 {})", path.display(), sha256);
+        tracing::warn!("[original_watch] The running build is unaffected, but the next launch loads the new one:");
+        tracing::warn!("[original_watch] offsets and cached signature matches may be stale");
+        tracing::warn!("[original_watch] ==============================================================");
+        sigscan::invalidate_cache();
+        if suspend {
+            unsafe { suspend_offset_hooks() };
+        }
+    }
+
+    unsafe fn suspend_offset_hooks() {
+        let base = proxy::get_original_dll_base() as usize;
+        let size = pe::image_size(base as *const u8).unwrap_or(0) as usize;
+        let exports: Vec<usize> = pe::exports(base as *const u8)
+            .iter()
+            .filter(|export| export.forwarder.is_none())
+            .map(|export| base + export.rva as usize)
+            .collect();
+
+        let mut suspended = SUSPENDED.lock().unwrap();
+        for name in offset_based(&hooks::inline_targets(), (base, base + size), &exports) {
+            if suspended.contains(&name) {
+                continue;
+            }
+            match hooks::suspend_hook(&name) {
+                Ok(()) => suspended.push(name),
+                Err(e) => tracing::error!("[original_watch] Failed to suspend {}: {}", name, e),
+            }
+        }
+        if !suspended.is_empty() {
+            tracing::warn!(
+                "[original_watch] Suspended {} offset-based hook(s) until `reverify`: {}",
+                suspended.len(),
+                suspended.join(", ")
+            );
+        }
+    }
+
+    /// Resume the hooks `suspend_offset_hooks` suspended; returns how many
+    fn resume() -> usize {
+        let suspended = std::mem::take(&mut *SUSPENDED.lock().unwrap());
+        suspended
+            .iter()
+            .filter(|name| match unsafe { hooks::resume_hook(name) } {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::error!("[original_watch] Failed to resume {}: {}", name, e);
+                    false
+                }
+            })
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hooks_by_offset_are_those_inside_the_image_off_its_exports() {
+        let inline = [
+            ("export_hook".to_string(), 0x1_8000_1000),
+            ("offset_hook".to_string(), 0x1_8000_1234),
+            ("system_hook".to_string(), 0x7ff8_0000_1000),
+        ];
+        let image = (0x1_8000_0000, 0x1_8010_0000);
+        assert_eq!(offset_based(&inline, image, &[0x1_8000_1000]), ["offset_hook"]);
+    }
+
+    #[test]
+    fn stamp_follows_the_file() {
+        let path = std::env::temp_dir().join(format!("reflex_original_watch_{}.dll", std::process::id()));
+        std::fs::write(&path, b"MZ").unwrap();
+        let before = Stamp::of(&path).unwrap();
+        std::fs::write(&path, b"MZ\x90\x00").unwrap();
+        let after = Stamp::of(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_ne!(before, after);
+        assert_eq!(Stamp::of(&path), None);
+    }
+}
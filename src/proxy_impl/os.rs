@@ -0,0 +1,255 @@
+/// Operating-system primitives behind a trait
+///
+/// Loading the original DLL, resolving its exports and lifting page
+/// protection for patches go through `Os` instead of calling Win32
+/// directly. The proxy always runs on `Win32`; unit tests install a
+/// `fake::FakeOs` for the current thread, so the patch and hook-registry
+/// logic can be exercised on any platform without injecting into a process.
+
+/// `PAGE_EXECUTE_READWRITE`
+pub const PAGE_EXECUTE_READWRITE: u32 = 0x40;
+
+/// `LOAD_LIBRARY_SEARCH_*` flags for `load_library`; 0 is the classic
+/// LoadLibrary search order
+pub const LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR: u32 = 0x100;
+pub const LOAD_LIBRARY_SEARCH_APPLICATION_DIR: u32 = 0x200;
+pub const LOAD_LIBRARY_SEARCH_USER_DIRS: u32 = 0x400;
+pub const LOAD_LIBRARY_SEARCH_SYSTEM32: u32 = 0x800;
+pub const LOAD_LIBRARY_SEARCH_DEFAULT_DIRS: u32 = 0x1000;
+
+pub trait Os: Sync {
+    /// Load a module with LoadLibraryExW `flags` and return its base address
+    ///
+    /// With `LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR`, `path` must be absolute.
+    unsafe fn load_library(&self, path: &str, flags: u32) -> Result<usize, String>;
+
+    /// Address of the export `name` of the module at `module`
+    unsafe fn get_proc_address(&self, module: usize, name: &str) -> Option<usize>;
+
+    /// Change the protection of a range, returning the previous protection
+    unsafe fn virtual_protect(&self, address: usize, len: usize, protect: u32) -> Result<u32, String>;
+
+    /// Make code written to a range visible to instruction fetch
+    unsafe fn flush_instruction_cache(&self, address: usize, len: usize);
+}
+
+/// Combine `[loader] search` names into `LOAD_LIBRARY_SEARCH_*` flags
+pub fn search_flags(names: &[String]) -> Result<u32, String> {
+    names.iter().try_fold(0, |flags, name| {
+        let flag = match name.as_str() {
+            "dll_load_dir" => LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR,
+            "application_dir" => LOAD_LIBRARY_SEARCH_APPLICATION_DIR,
+            "user_dirs" => LOAD_LIBRARY_SEARCH_USER_DIRS,
+            "system32" => LOAD_LIBRARY_SEARCH_SYSTEM32,
+            "default_dirs" => LOAD_LIBRARY_SEARCH_DEFAULT_DIRS,
+            other => return Err(format!("unknown search location '{}'", other)),
+        };
+        Ok(flags | flag)
+    })
+}
+
+/// The implementation used by the current thread
+pub fn system() -> &'static dyn Os {
+    #[cfg(test)]
+    if let Some(os) = fake::installed() {
+        return os;
+    }
+
+    #[cfg(windows)]
+    {
+        &Win32
+    }
+    #[cfg(not(windows))]
+    {
+        &Unsupported
+    }
+}
+
+// ============================================================================
+// Win32
+// ============================================================================
+
+#[cfg(windows)]
+pub struct Win32;
+
+#[cfg(windows)]
+impl Os for Win32 {
+    unsafe fn load_library(&self, path: &str, flags: u32) -> Result<usize, String> {
+        use crate::proxy_impl::wide::to_wide;
+        use winapi::um::libloaderapi::LoadLibraryExW;
+
+        let wide = to_wide(path);
+        let module = LoadLibraryExW(wide.as_ptr(), std::ptr::null_mut(), flags);
+        if module.is_null() {
+            return Err(format!(
+                "LoadLibraryExW({}, 0x{:x}) failed with error {}",
+                path,
+                flags,
+                winapi::um::errhandlingapi::GetLastError()
+            ));
+        }
+        Ok(module as usize)
+    }
+
+    unsafe fn get_proc_address(&self, module: usize, name: &str) -> Option<usize> {
+        use winapi::um::libloaderapi::GetProcAddress;
+
+        let name = std::ffi::CString::new(name).ok()?;
+        match GetProcAddress(module as _, name.as_ptr()) as usize {
+            0 => None,
+            address => Some(address),
+        }
+    }
+
+    unsafe fn virtual_protect(&self, address: usize, len: usize, protect: u32) -> Result<u32, String> {
+        use winapi::um::memoryapi::VirtualProtect;
+
+        let mut old_protect = 0;
+        if VirtualProtect(address as _, len, protect, &mut old_protect) == 0 {
+            return Err(format!("VirtualProtect failed at 0x{:x}", address));
+        }
+        Ok(old_protect)
+    }
+
+    unsafe fn flush_instruction_cache(&self, address: usize, len: usize) {
+        use winapi::um::processthreadsapi::{FlushInstructionCache, GetCurrentProcess};
+
+        FlushInstructionCache(GetCurrentProcess(), address as _, len);
+    }
+}
+
+/// Placeholder for non-Windows builds, which only exist for unit tests
+#[cfg(not(windows))]
+pub struct Unsupported;
+
+#[cfg(not(windows))]
+impl Os for Unsupported {
+    unsafe fn load_library(&self, path: &str, _flags: u32) -> Result<usize, String> {
+        Err(format!("cannot load {}: not running on Windows", path))
+    }
+
+    unsafe fn get_proc_address(&self, _module: usize, _name: &str) -> Option<usize> {
+        None
+    }
+
+    unsafe fn virtual_protect(&self, address: usize, _len: usize, _protect: u32) -> Result<u32, String> {
+        Err(format!("cannot change protection at 0x{:x}: not running on Windows", address))
+    }
+
+    unsafe fn flush_instruction_cache(&self, _address: usize, _len: usize) {}
+}
+
+// ============================================================================
+// Test fake
+// ============================================================================
+
+#[cfg(test)]
+pub mod fake {
+    use super::Os;
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    thread_local! {
+        static INSTALLED: Cell<Option<&'static FakeOs>> = const { Cell::new(None) };
+    }
+
+    /// In-memory modules and a log of protection changes
+    ///
+    /// Memory itself is real: patches write to whatever address they are
+    /// given, so tests patch their own buffers.
+    #[derive(Default)]
+    pub struct FakeOs {
+        /// Module path (case-insensitive) -> base address and exports
+        modules: HashMap<String, (usize, HashMap<String, usize>)>,
+        /// `virtual_protect` calls as (address, len, protect)
+        pub protect_calls: Mutex<Vec<(usize, usize, u32)>>,
+        /// `flush_instruction_cache` calls as (address, len)
+        pub flushes: Mutex<Vec<(usize, usize)>>,
+        /// Make `virtual_protect` fail
+        pub deny_protect: AtomicBool,
+    }
+
+    impl FakeOs {
+        pub fn with_module(mut self, path: &str, base: usize, exports: &[(&str, usize)]) -> Self {
+            let exports = exports.iter().map(|&(name, address)| (name.to_string(), address)).collect();
+            self.modules.insert(path.to_lowercase(), (base, exports));
+            self
+        }
+
+        /// Make this fake the `system()` of the current thread
+        ///
+        /// The fake is leaked; tests are short-lived.
+        pub fn install(self) -> &'static FakeOs {
+            let os: &'static FakeOs = Box::leak(Box::new(self));
+            INSTALLED.with(|installed| installed.set(Some(os)));
+            os
+        }
+    }
+
+    pub(super) fn installed() -> Option<&'static dyn Os> {
+        INSTALLED.with(|installed| installed.get()).map(|os| os as &'static dyn Os)
+    }
+
+    impl Os for FakeOs {
+        unsafe fn load_library(&self, path: &str, _flags: u32) -> Result<usize, String> {
+            self.modules
+                .get(&path.to_lowercase())
+                .map(|(base, _)| *base)
+                .ok_or_else(|| format!("{} not found", path))
+        }
+
+        unsafe fn get_proc_address(&self, module: usize, name: &str) -> Option<usize> {
+            self.modules
+                .values()
+                .find(|(base, _)| *base == module)
+                .and_then(|(_, exports)| exports.get(name).copied())
+        }
+
+        unsafe fn virtual_protect(&self, address: usize, len: usize, protect: u32) -> Result<u32, String> {
+            if self.deny_protect.load(Ordering::SeqCst) {
+                return Err(format!("VirtualProtect failed at 0x{:x}", address));
+            }
+            self.protect_calls.lock().unwrap().push((address, len, protect));
+            // PAGE_EXECUTE_READ
+            Ok(0x20)
+        }
+
+        unsafe fn flush_instruction_cache(&self, address: usize, len: usize) {
+            self.flushes.lock().unwrap().push((address, len));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fake::FakeOs;
+    use super::{search_flags, system};
+
+    #[test]
+    fn fake_is_per_thread() {
+        FakeOs::default()
+            .with_module("Reflex_Original.dll", 0x1800_0000, &[("DllMain", 0x1800_1000)])
+            .install();
+
+        unsafe {
+            let base = system().load_library("reflex_original.dll", 0).unwrap();
+            assert_eq!(base, 0x1800_0000);
+            assert_eq!(system().get_proc_address(base, "DllMain"), Some(0x1800_1000));
+            assert_eq!(system().get_proc_address(base, "Missing"), None);
+            assert!(system().load_library("other.dll", 0).is_err());
+        }
+
+        let other = std::thread::spawn(|| unsafe { system().load_library("reflex_original.dll", 0) });
+        assert_ne!(other.join().unwrap(), Ok(0x1800_0000));
+    }
+
+    #[test]
+    fn search_flags_combine() {
+        let names = ["dll_load_dir".to_string(), "system32".to_string()];
+        assert_eq!(search_flags(&names), Ok(0x100 | 0x800));
+        assert_eq!(search_flags(&[]), Ok(0));
+        assert!(search_flags(&["cwd".to_string()]).is_err());
+    }
+}
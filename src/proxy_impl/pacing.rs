@@ -0,0 +1,353 @@
+/// Frame pacing analysis
+///
+/// Joins what the NvAPI hooks see of each frame into one record per
+/// Present (nvapi.rs, frame_events.rs): when Present was called and
+/// returned, the Reflex sleep before it, and the latency markers of the
+/// frame it presented, whether the game's or synthesized (markers.rs).
+/// From the records:
+/// - frame time: Present to Present, with mean, standard deviation, min
+///   and max
+/// - sleep to present: from the end of the frame's NvAPI_D3D_Sleep to its
+///   Present
+/// - simulation to present: SIMULATION_START to PRESENT_START markers
+/// - missed pacing: frames whose frame time is further than `threshold_ms`
+///   from the target, the sleep mode's minimum interval if one is set and
+///   the mean frame time otherwise; each is logged as it happens
+///
+/// The summary goes to the log and to `[nvapi.pacing] summary_path` on
+/// detach.
+///
+/// Works on durations since the session start (sleep_stats.rs) and is
+/// independent of the host platform.
+
+use crate::proxy_impl::markers::Marker;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Frames whose markers are kept until they are presented
+const MAX_PENDING_FRAMES: usize = 64;
+
+static ANALYZER: Lazy<Mutex<Analyzer>> = Lazy::new(|| Mutex::new(Analyzer::default()));
+
+/// When each latency marker of a frame arrived, by `Marker`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MarkerTimes([Option<Duration>; 7]);
+
+impl MarkerTimes {
+    pub fn get(&self, marker: Marker) -> Option<Duration> {
+        self.0[marker as usize]
+    }
+
+    fn set(&mut self, marker: Marker, at: Duration) {
+        self.0[marker as usize] = Some(at);
+    }
+}
+
+/// One presented frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameRecord {
+    /// Number of the Present, from 1
+    pub frame: u64,
+    pub present_start: Duration,
+    pub present_end: Duration,
+    /// Since the previous Present started; none for the first
+    pub frame_time: Option<Duration>,
+    /// The NvAPI_D3D_Sleep since the previous Present: when it ended and
+    /// how long it blocked
+    pub sleep: Option<(Duration, Duration)>,
+    /// Frame id of the latency markers, if any arrived
+    pub marker_frame: Option<u64>,
+    pub markers: MarkerTimes,
+    /// Further than the threshold from the target frame time
+    pub missed: bool,
+}
+
+impl FrameRecord {
+    /// From the end of the sleep to the start of Present
+    pub fn sleep_to_present(&self) -> Option<Duration> {
+        self.sleep.and_then(|(end, _)| self.present_start.checked_sub(end))
+    }
+
+    /// From SIMULATION_START to PRESENT_START
+    pub fn simulation_to_present(&self) -> Option<Duration> {
+        let start = self.markers.get(Marker::SimulationStart)?;
+        self.markers.get(Marker::PresentStart)?.checked_sub(start)
+    }
+}
+
+/// Count, mean and variance (Welford), min and max of values in ms
+#[derive(Debug, Default)]
+struct Stats {
+    count: u64,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Stats {
+    fn push(&mut self, value: Duration) {
+        let ms = value.as_secs_f64() * 1000.0;
+        self.min = if self.count == 0 { ms } else { self.min.min(ms) };
+        self.max = self.max.max(ms);
+        self.count += 1;
+        let delta = ms - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (ms - self.mean);
+    }
+
+    fn stddev(&self) -> f64 {
+        match self.count {
+            0 | 1 => 0.0,
+            count => (self.m2 / (count - 1) as f64).sqrt(),
+        }
+    }
+
+    fn summary(&self) -> Value {
+        json!({
+            "count": self.count,
+            "mean_ms": self.mean,
+            "stddev_ms": self.stddev(),
+            "min_ms": self.min,
+            "max_ms": self.max,
+        })
+    }
+}
+
+/// Builds the frame records and their metrics
+#[derive(Debug, Default)]
+pub struct Analyzer {
+    threshold: Duration,
+    /// Minimum interval of the sleep mode
+    target: Option<Duration>,
+    frames: u64,
+    present_start: Option<Duration>,
+    last_present: Option<Duration>,
+    last_sleep: Option<(Duration, Duration)>,
+    pending: BTreeMap<u64, MarkerTimes>,
+    frame_times: Stats,
+    sleep_to_present: Stats,
+    simulation_to_present: Stats,
+    missed: u64,
+}
+
+impl Analyzer {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            ..Self::default()
+        }
+    }
+
+    /// The frame time the game aims for; none to compare with the mean
+    pub fn target(&mut self, target: Option<Duration>) {
+        self.target = target;
+    }
+
+    /// A sleep that ended at `end` after blocking for `took`
+    pub fn sleep(&mut self, end: Duration, took: Duration) {
+        self.last_sleep = Some((end, took));
+    }
+
+    /// A latency marker of frame `frame_id`, sent at `at`
+    pub fn marker(&mut self, frame_id: u64, marker: Marker, at: Duration) {
+        self.pending.entry(frame_id).or_default().set(marker, at);
+        while self.pending.len() > MAX_PENDING_FRAMES {
+            self.pending.pop_first();
+        }
+    }
+
+    pub fn present_start(&mut self, at: Duration) {
+        self.present_start = Some(at);
+    }
+
+    /// Present returned; the record of the frame it showed
+    pub fn present_end(&mut self, at: Duration) -> Option<FrameRecord> {
+        let present_start = self.present_start.take()?;
+        self.frames += 1;
+        let frame_time = self.last_present.and_then(|last| present_start.checked_sub(last));
+        self.last_present = Some(present_start);
+
+        // The latest frame whose Present was marked, and any before it
+        let marker_frame = self
+            .pending
+            .iter()
+            .rev()
+            .find(|(_, markers)| markers.get(Marker::PresentStart).is_some())
+            .map(|(&id, _)| id);
+        let markers = match marker_frame {
+            Some(id) => {
+                let later = self.pending.split_off(&(id + 1));
+                let markers = self.pending.remove(&id).unwrap_or_default();
+                self.pending = later;
+                markers
+            }
+            None => MarkerTimes::default(),
+        };
+
+        let missed = frame_time.is_some_and(|frame_time| {
+            let target = match (self.target, self.frame_times.count) {
+                (Some(target), _) => target,
+                (None, 0) => return false,
+                (None, _) => Duration::from_secs_f64(self.frame_times.mean / 1000.0),
+            };
+            frame_time.abs_diff(target) > self.threshold
+        });
+        let record = FrameRecord {
+            frame: self.frames,
+            present_start,
+            present_end: at,
+            frame_time,
+            sleep: self.last_sleep.take(),
+            marker_frame,
+            markers,
+            missed,
+        };
+
+        if let Some(frame_time) = frame_time {
+            self.frame_times.push(frame_time);
+        }
+        if let Some(gap) = record.sleep_to_present() {
+            self.sleep_to_present.push(gap);
+        }
+        if let Some(latency) = record.simulation_to_present() {
+            self.simulation_to_present.push(latency);
+        }
+        self.missed += missed as u64;
+        Some(record)
+    }
+
+    /// The summary of the frames so far
+    pub fn summary(&self) -> Value {
+        json!({
+            "frames": self.frames,
+            "target_ms": self.target.map(|target| target.as_secs_f64() * 1000.0),
+            "threshold_ms": self.threshold.as_secs_f64() * 1000.0,
+            "frame_time": self.frame_times.summary(),
+            "sleep_to_present": self.sleep_to_present.summary(),
+            "simulation_to_present": self.simulation_to_present.summary(),
+            "missed": self.missed,
+        })
+    }
+
+    /// One line for the log
+    pub fn line(&self) -> String {
+        format!(
+            "{} frame(s), {:.2} ms mean frame time (stddev {:.2} ms), {} missed pacing",
+            self.frames,
+            self.frame_times.mean,
+            self.frame_times.stddev(),
+            self.missed
+        )
+    }
+}
+
+/// Start analyzing, logging frames further than `threshold` off the target
+pub fn start(threshold: Duration) {
+    *ANALYZER.lock().unwrap() = Analyzer::new(threshold);
+}
+
+pub fn record_target(target: Option<Duration>) {
+    ANALYZER.lock().unwrap().target(target);
+}
+
+pub fn record_sleep(end: Duration, took: Duration) {
+    ANALYZER.lock().unwrap().sleep(end, took);
+}
+
+pub fn record_marker(frame_id: u64, marker: Marker, at: Duration) {
+    ANALYZER.lock().unwrap().marker(frame_id, marker, at);
+}
+
+pub fn record_present_start(at: Duration) {
+    ANALYZER.lock().unwrap().present_start(at);
+}
+
+/// Present returned at `at`; logs the frame if it missed pacing
+pub fn record_present_end(at: Duration) -> Option<FrameRecord> {
+    let record = ANALYZER.lock().unwrap().present_end(at)?;
+    if let (true, Some(frame_time)) = (record.missed, record.frame_time) {
+        tracing::info!(
+            "[pacing] Frame {} missed pacing: {:.2} ms frame time",
+            record.frame,
+            frame_time.as_secs_f64() * 1000.0
+        );
+    }
+    Some(record)
+}
+
+/// Log the summary and write it to `path` ("" = log only); returns the
+/// path written
+pub fn write_summary(path: &str) -> Result<Option<String>, String> {
+    let analyzer = ANALYZER.lock().unwrap();
+    tracing::info!("[pacing] {}", analyzer.line());
+    if path.is_empty() {
+        return Ok(None);
+    }
+    let json = serde_json::to_string_pretty(&analyzer.summary()).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    Ok(Some(path.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn present(analyzer: &mut Analyzer, start: u64) -> FrameRecord {
+        analyzer.present_start(ms(start));
+        analyzer.present_end(ms(start + 1)).unwrap()
+    }
+
+    #[test]
+    fn records_join_sleeps_and_markers() {
+        let mut analyzer = Analyzer::new(ms(2));
+        assert_eq!(analyzer.present_end(ms(0)), None);
+
+        analyzer.sleep(ms(10), ms(3));
+        analyzer.marker(7, Marker::SimulationStart, ms(10));
+        analyzer.marker(7, Marker::PresentStart, ms(15));
+        // The next frame's simulation has already started
+        analyzer.marker(8, Marker::SimulationStart, ms(15));
+        let first = present(&mut analyzer, 15);
+        assert_eq!(first.frame, 1);
+        assert_eq!(first.frame_time, None);
+        assert_eq!(first.marker_frame, Some(7));
+        assert_eq!(first.sleep_to_present(), Some(ms(5)));
+        assert_eq!(first.simulation_to_present(), Some(ms(5)));
+
+        analyzer.marker(8, Marker::PresentStart, ms(31));
+        let second = present(&mut analyzer, 31);
+        assert_eq!(second.frame_time, Some(ms(16)));
+        assert_eq!(second.sleep, None);
+        assert_eq!(second.marker_frame, Some(8));
+        assert_eq!(second.simulation_to_present(), Some(ms(16)));
+
+        let summary = analyzer.summary();
+        assert_eq!(summary["frames"], 2);
+        assert_eq!(summary["frame_time"]["count"], 1);
+        assert_eq!(summary["simulation_to_present"]["max_ms"], 16.0);
+    }
+
+    #[test]
+    fn frames_off_the_target_miss_pacing() {
+        let mut analyzer = Analyzer::new(ms(2));
+        let starts = [0, 10, 20, 30, 45, 55];
+        let missed: Vec<bool> = starts.iter().map(|&start| present(&mut analyzer, start).missed).collect();
+        // Against the mean: only the 15 ms frame
+        assert_eq!(missed, [false, false, false, false, true, false]);
+
+        analyzer.target(Some(ms(16)));
+        assert!(present(&mut analyzer, 65).missed);
+        assert!(!present(&mut analyzer, 80).missed);
+        assert_eq!(analyzer.summary()["missed"], 2);
+        assert!(analyzer.line().ends_with("2 missed pacing"));
+    }
+}
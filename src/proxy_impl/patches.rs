@@ -0,0 +1,415 @@
+/// Memory patching utilities
+///
+/// Wrappers for writing bytes into code or data of a loaded module:
+/// - VirtualProtect is lifted around each write and restored afterwards
+/// - the original bytes are backed up so every patch can be reverted
+/// - the instruction cache is flushed after each write
+///
+/// A `PatchSet` groups related patches and reverts them (most recent first)
+/// when dropped. Sets that should live for the whole session are handed to
+/// `keep_until_detach` and reverted during DLL_PROCESS_DETACH.
+///
+/// Patches declared in the `[[patches]]` config section are applied to
+/// reflex_original.dll right after it is loaded, or to their `module` once
+/// that is loaded (`apply_all_configured`).
+
+#[cfg(windows)]
+use crate::proxy;
+#[cfg(windows)]
+use crate::proxy_impl::config::PatchConfig;
+#[cfg(windows)]
+use crate::proxy_impl::deferred;
+use crate::proxy_impl::os::{self, PAGE_EXECUTE_READWRITE};
+#[cfg(windows)]
+use crate::proxy_impl::pe;
+#[cfg(windows)]
+use crate::proxy_impl::sigscan::{self, Pattern};
+#[cfg(windows)]
+use crate::proxy_impl::status::{self, ErrorKind};
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+/// A single byte patch with its backup
+pub struct Patch {
+    address: usize,
+    original: Vec<u8>,
+    patched: Vec<u8>,
+    applied: bool,
+}
+
+impl Patch {
+    /// Back up the bytes at `address` and overwrite them with `bytes`
+    pub unsafe fn apply(address: usize, bytes: &[u8]) -> Result<Self, String> {
+        let original = read_memory(address, bytes.len());
+        write_memory(address, bytes)?;
+        Ok(Self {
+            address,
+            original,
+            patched: bytes.to_vec(),
+            applied: true,
+        })
+    }
+
+    /// Like `apply`, but only if the current bytes equal `expected`
+    pub unsafe fn apply_verified(address: usize, expected: &[u8], bytes: &[u8]) -> Result<Self, String> {
+        if expected.len() != bytes.len() {
+            return Err(format!(
+                "Patch at 0x{:x}: expected {} byte(s) but replacing {}",
+                address,
+                expected.len(),
+                bytes.len()
+            ));
+        }
+
+        let current = read_memory(address, expected.len());
+        if current != expected {
+            return Err(format!(
+                "Patch at 0x{:x}: found {} instead of {}",
+                address,
+                hex(&current),
+                hex(expected)
+            ));
+        }
+
+        Self::apply(address, bytes)
+    }
+
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    pub fn patched_bytes(&self) -> &[u8] {
+        &self.patched
+    }
+
+    pub fn is_applied(&self) -> bool {
+        self.applied
+    }
+
+    /// Restore the original bytes
+    pub unsafe fn revert(&mut self) -> Result<(), String> {
+        if self.applied {
+            write_memory(self.address, &self.original)?;
+            self.applied = false;
+        }
+        Ok(())
+    }
+
+    /// Write the patched bytes again after `revert`
+    pub unsafe fn reapply(&mut self) -> Result<(), String> {
+        if !self.applied {
+            write_memory(self.address, &self.patched)?;
+            self.applied = true;
+        }
+        Ok(())
+    }
+}
+
+/// A named group of patches, reverted together
+pub struct PatchSet {
+    name: String,
+    patches: Vec<Patch>,
+}
+
+static KEPT_SETS: Lazy<Mutex<Vec<PatchSet>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+impl PatchSet {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            patches: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.patches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patches.is_empty()
+    }
+
+    /// Apply a patch and add it to the set
+    pub unsafe fn apply(&mut self, address: usize, bytes: &[u8]) -> Result<(), String> {
+        self.patches.push(Patch::apply(address, bytes)?);
+        Ok(())
+    }
+
+    /// Apply a patch after verifying the current bytes, and add it to the set
+    pub unsafe fn apply_verified(&mut self, address: usize, expected: &[u8], bytes: &[u8]) -> Result<(), String> {
+        self.patches.push(Patch::apply_verified(address, expected, bytes)?);
+        Ok(())
+    }
+
+    /// Revert every patch, most recent first
+    ///
+    /// Returns the number of patches reverted; failures are logged.
+    pub unsafe fn revert_all(&mut self) -> usize {
+        let mut reverted = 0;
+        while let Some(mut patch) = self.patches.pop() {
+            match patch.revert() {
+                Ok(()) => reverted += 1,
+                Err(e) => tracing::error!("[patches] {}: {}", self.name, e),
+            }
+        }
+        reverted
+    }
+
+    /// Revert the patches at `start..end`, most recent first, and drop
+    /// them from the set
+    pub unsafe fn revert_in(&mut self, start: usize, end: usize) -> usize {
+        let mut reverted = 0;
+        for index in (0..self.patches.len()).rev() {
+            if !(start..end).contains(&self.patches[index].address()) {
+                continue;
+            }
+            match self.patches.remove(index).revert() {
+                Ok(()) => reverted += 1,
+                Err(e) => tracing::error!("[patches] {}: {}", self.name, e),
+            }
+        }
+        reverted
+    }
+
+    /// Keep the set applied until DLL_PROCESS_DETACH
+    pub fn keep_until_detach(self) {
+        tracing::info!("[patches] Keeping {} ({} patch(es)) until detach", self.name, self.len());
+        KEPT_SETS.lock().unwrap().push(self);
+    }
+}
+
+impl Drop for PatchSet {
+    fn drop(&mut self) {
+        if !self.patches.is_empty() {
+            let reverted = unsafe { self.revert_all() };
+            tracing::info!("[patches] Reverted {} patch(es) of {}", reverted, self.name);
+        }
+    }
+}
+
+/// Revert every set passed to `keep_until_detach`, most recent first
+///
+/// Returns the number of patches reverted.
+pub unsafe fn revert_kept_sets() -> usize {
+    let mut sets = KEPT_SETS.lock().unwrap();
+    let mut reverted = 0;
+    while let Some(mut set) = sets.pop() {
+        reverted += set.revert_all();
+    }
+    reverted
+}
+
+/// Revert the kept patches at `start..end`, e.g. the image of a module
+/// about to be unloaded, so detach does not write to it
+///
+/// Returns the number of patches reverted.
+pub unsafe fn revert_kept_in(start: usize, end: usize) -> usize {
+    let mut sets = KEPT_SETS.lock().unwrap();
+    let reverted = sets.iter_mut().rev().map(|set| set.revert_in(start, end)).sum();
+    sets.retain(|set| !set.is_empty());
+    reverted
+}
+
+/// Apply the configured patches for reflex_original.dll (at `original_base`)
+/// and defer those for other modules until each module is loaded
+///
+/// Returns the number of patches applied to reflex_original.dll.
+#[cfg(windows)]
+pub unsafe fn apply_all_configured(original_base: *const u8, specs: &[PatchConfig]) -> usize {
+    let mut by_module: Vec<(String, Vec<PatchConfig>)> = Vec::new();
+    for spec in specs {
+        let Some(module) = &spec.module else { continue };
+        let module = module.to_lowercase();
+        match by_module.iter_mut().find(|(m, _)| *m == module) {
+            Some((_, specs)) => specs.push(spec.clone()),
+            None => by_module.push((module, vec![spec.clone()])),
+        }
+    }
+
+    for (module, specs) in by_module {
+        deferred::when_loaded(&module, &format!("patches for {}", module), move |base| {
+            match apply_configured(base as *const u8, &specs) {
+                applied if applied == specs.len() => Ok(()),
+                applied => Err(format!("{}/{} patch(es) applied", applied, specs.len())),
+            }
+        });
+    }
+
+    let own: Vec<PatchConfig> = specs.iter().filter(|s| s.module.is_none()).cloned().collect();
+    apply_configured(original_base, &own)
+}
+
+/// Apply the configured patches to the module at `base`
+///
+/// Each patch is applied independently; a failing one is logged and counted
+/// but does not stop the others. Returns the number of patches applied.
+#[cfg(windows)]
+pub unsafe fn apply_configured(base: *const u8, specs: &[PatchConfig]) -> usize {
+    if specs.is_empty() {
+        return 0;
+    }
+
+    let mut set = PatchSet::new("config");
+    for spec in specs {
+        match apply_spec(&mut set, base, spec) {
+            Ok(address) => tracing::info!("[patches] Applied {} at 0x{:x}", spec.name, address),
+            Err(e) => {
+                status::record_error(ErrorKind::Patch);
+                tracing::error!("[patches] Failed to apply {}: {}", spec.name, e);
+            }
+        }
+    }
+
+    let applied = set.len();
+    set.keep_until_detach();
+    applied
+}
+
+#[cfg(windows)]
+unsafe fn apply_spec(set: &mut PatchSet, base: *const u8, spec: &PatchConfig) -> Result<usize, String> {
+    let address = match (spec.offset, &spec.signature) {
+        (Some(offset), None) => base as usize + offset,
+        (None, Some(signature)) => {
+            let found = if base == proxy::get_original_dll_base() as *const u8 {
+                sigscan::find_unique_in_original(base, signature)?
+            } else {
+                sigscan::find_unique(base, &Pattern::parse(signature)?)?
+            };
+            found.wrapping_add_signed(spec.signature_offset)
+        }
+        _ => return Err("exactly one of 'offset' and 'signature' is required".to_string()),
+    };
+
+    let replacement = sigscan::parse_bytes(&spec.replacement)?;
+    if replacement.is_empty() {
+        return Err("empty replacement".to_string());
+    }
+
+    let image_end = base as usize + pe::image_size(base).ok_or("invalid PE image")? as usize;
+    if address < base as usize || address + replacement.len() > image_end {
+        return Err(format!("0x{:x} is outside the image", address));
+    }
+
+    match &spec.original {
+        Some(original) => set.apply_verified(address, &sigscan::parse_bytes(original)?, &replacement)?,
+        None => set.apply(address, &replacement)?,
+    }
+    Ok(address)
+}
+
+/// Copy `len` bytes from `address`
+pub unsafe fn read_memory(address: usize, len: usize) -> Vec<u8> {
+    std::slice::from_raw_parts(address as *const u8, len).to_vec()
+}
+
+/// Write bytes to possibly protected memory and flush the instruction cache
+pub unsafe fn write_memory(address: usize, bytes: &[u8]) -> Result<(), String> {
+    let os = os::system();
+    let old_protect = os.virtual_protect(address, bytes.len(), PAGE_EXECUTE_READWRITE)?;
+
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), address as *mut u8, bytes.len());
+
+    let _ = os.virtual_protect(address, bytes.len(), old_protect);
+    os.flush_instruction_cache(address, bytes.len());
+    Ok(())
+}
+
+/// Bytes as space-separated hex, e.g. "48 8B C4"
+pub fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy_impl::os::fake::FakeOs;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn patch_and_revert() {
+        let os = FakeOs::default().install();
+        let mut code = vec![0x90u8; 8];
+        let address = code.as_mut_ptr() as usize;
+
+        let mut patch = unsafe { Patch::apply(address, &[0xEB, 0x05]) }.unwrap();
+        assert_eq!(code[..3], [0xEB, 0x05, 0x90]);
+        assert_eq!(patch.original, [0x90, 0x90]);
+
+        // Protection is lifted, then restored, and the cache flushed
+        assert_eq!(
+            *os.protect_calls.lock().unwrap(),
+            [(address, 2, PAGE_EXECUTE_READWRITE), (address, 2, 0x20)]
+        );
+        assert_eq!(*os.flushes.lock().unwrap(), [(address, 2)]);
+
+        unsafe { patch.revert() }.unwrap();
+        assert_eq!(code[..2], [0x90, 0x90]);
+        assert!(!patch.is_applied());
+
+        unsafe { patch.reapply() }.unwrap();
+        assert_eq!(code[..2], [0xEB, 0x05]);
+    }
+
+    #[test]
+    fn verified_patch_checks_bytes() {
+        FakeOs::default().install();
+        let mut code = vec![0x74u8, 0x05];
+        let address = code.as_mut_ptr() as usize;
+
+        let error = unsafe { Patch::apply_verified(address, &[0x75, 0x05], &[0xEB, 0x05]) }.err().unwrap();
+        assert!(error.contains("found 74 05 instead of 75 05"), "{}", error);
+        assert_eq!(code, [0x74, 0x05]);
+
+        unsafe { Patch::apply_verified(address, &[0x74, 0x05], &[0xEB, 0x05]) }.unwrap();
+        assert_eq!(code, [0xEB, 0x05]);
+    }
+
+    #[test]
+    fn protection_failure_leaves_memory_alone() {
+        let os = FakeOs::default().install();
+        os.deny_protect.store(true, Ordering::SeqCst);
+        let mut code = vec![0x90u8; 4];
+
+        assert!(unsafe { Patch::apply(code.as_mut_ptr() as usize, &[0xCC]) }.is_err());
+        assert_eq!(code, [0x90; 4]);
+    }
+
+    #[test]
+    fn sets_revert_most_recent_first() {
+        FakeOs::default().install();
+        let mut code = vec![0x00u8; 4];
+        let address = code.as_mut_ptr() as usize;
+
+        let mut set = PatchSet::new("test");
+        unsafe {
+            set.apply(address, &[0x11, 0x11]).unwrap();
+            set.apply(address + 1, &[0x22]).unwrap();
+        }
+        assert_eq!(code[..2], [0x11, 0x22]);
+
+        // The second patch backed up 0x11, so reverting in order restores 0x00
+        assert_eq!(unsafe { set.revert_all() }, 2);
+        assert_eq!(code[..2], [0x00, 0x00]);
+    }
+
+    #[test]
+    fn sets_revert_a_range() {
+        FakeOs::default().install();
+        let mut code = vec![0x00u8; 8];
+        let address = code.as_mut_ptr() as usize;
+
+        let mut set = PatchSet::new("test");
+        unsafe {
+            set.apply(address, &[0x11]).unwrap();
+            set.apply(address + 4, &[0x22]).unwrap();
+            assert_eq!(set.revert_in(address + 2, address + 8), 1);
+        }
+        assert_eq!(code[..5], [0x11, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(set.len(), 1);
+        unsafe { set.revert_all() };
+    }
+}
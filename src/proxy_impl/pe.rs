@@ -0,0 +1,406 @@
+/// Minimal PE parsing for modules mapped into memory
+///
+/// Only reads what the proxy needs (headers, sections, exports, imports) and works
+/// on any loaded module, not just reflex_original.dll. `map_file` and `relocate`
+/// reproduce the loader's layout of an on-disk image for comparison;
+/// `unmap_headers` turns a copy of a mapped image back into a loadable file.
+
+use std::ffi::CStr;
+use std::mem::size_of;
+use winapi::um::winnt::{
+    IMAGE_DIRECTORY_ENTRY_BASERELOC, IMAGE_DIRECTORY_ENTRY_EXCEPTION, IMAGE_DIRECTORY_ENTRY_EXPORT, IMAGE_DIRECTORY_ENTRY_IMPORT,
+    IMAGE_DIRECTORY_ENTRY_TLS, IMAGE_DOS_HEADER, IMAGE_DOS_SIGNATURE, IMAGE_EXPORT_DIRECTORY,
+    IMAGE_FILE_HEADER, IMAGE_FILE_MACHINE_AMD64, IMAGE_FILE_MACHINE_ARM64, IMAGE_FILE_MACHINE_ARMNT,
+    IMAGE_FILE_MACHINE_I386, IMAGE_IMPORT_DESCRIPTOR, IMAGE_NT_HEADERS, IMAGE_NT_SIGNATURE, IMAGE_SCN_MEM_EXECUTE,
+    IMAGE_SCN_MEM_WRITE, IMAGE_SECTION_HEADER, IMAGE_TLS_DIRECTORY,
+};
+
+/// A section of a mapped image
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    pub rva: u32,
+    pub size: u32,
+    pub characteristics: u32,
+}
+
+impl Section {
+    pub fn contains(&self, rva: u32) -> bool {
+        rva >= self.rva && rva < self.rva + self.size
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.characteristics & IMAGE_SCN_MEM_EXECUTE != 0
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.characteristics & IMAGE_SCN_MEM_WRITE != 0
+    }
+}
+
+/// An entry of the export table
+#[derive(Debug, Clone)]
+pub struct Export {
+    pub name: Option<String>,
+    pub ordinal: u16,
+    pub rva: u32,
+    /// "OTHERDLL.Function" if this export is forwarded
+    pub forwarder: Option<String>,
+}
+
+/// Get the NT headers of a mapped image, validating the signatures
+pub unsafe fn nt_headers(base: *const u8) -> Option<*const IMAGE_NT_HEADERS> {
+    if base.is_null() {
+        return None;
+    }
+
+    let dos = &*(base as *const IMAGE_DOS_HEADER);
+    if dos.e_magic != IMAGE_DOS_SIGNATURE {
+        return None;
+    }
+
+    let nt = base.offset(dos.e_lfanew as isize) as *const IMAGE_NT_HEADERS;
+    if (*nt).Signature != IMAGE_NT_SIGNATURE {
+        return None;
+    }
+
+    Some(nt)
+}
+
+/// Size of the mapped image in bytes
+pub unsafe fn image_size(base: *const u8) -> Option<u32> {
+    nt_headers(base).map(|nt| (*nt).OptionalHeader.SizeOfImage)
+}
+
+/// Location and size of a data directory entry, if present
+pub unsafe fn data_directory(base: *const u8, index: u16) -> Option<(u32, u32)> {
+    let nt = nt_headers(base)?;
+    let dir = (*nt).OptionalHeader.DataDirectory.get(index as usize)?;
+    if dir.VirtualAddress == 0 || dir.Size == 0 {
+        None
+    } else {
+        Some((dir.VirtualAddress, dir.Size))
+    }
+}
+
+unsafe fn section_headers<'a>(base: *const u8) -> &'a [IMAGE_SECTION_HEADER] {
+    let nt = match nt_headers(base) {
+        Some(nt) => nt,
+        None => return &[],
+    };
+
+    let file_header = &(*nt).FileHeader;
+    let first = (nt as *const u8)
+        .add(size_of::<u32>() + size_of::<IMAGE_FILE_HEADER>())
+        .add(file_header.SizeOfOptionalHeader as usize) as *const IMAGE_SECTION_HEADER;
+
+    std::slice::from_raw_parts(first, file_header.NumberOfSections as usize)
+}
+
+/// List the sections of a mapped image
+pub unsafe fn sections(base: *const u8) -> Vec<Section> {
+    section_headers(base)
+        .iter()
+        .map(|header| {
+            let name_len = header.Name.iter().position(|&b| b == 0).unwrap_or(header.Name.len());
+            Section {
+                name: String::from_utf8_lossy(&header.Name[..name_len]).into_owned(),
+                rva: header.VirtualAddress,
+                size: *header.Misc.VirtualSize(),
+                characteristics: header.Characteristics,
+            }
+        })
+        .collect()
+}
+
+/// Start and end RVA of the function containing `rva`, from the exception
+/// directory (x64 unwind data)
+pub unsafe fn function_range(base: *const u8, rva: u32) -> Option<(u32, u32)> {
+    let (start, size) = data_directory(base, IMAGE_DIRECTORY_ENTRY_EXCEPTION)?;
+    // RUNTIME_FUNCTION: BeginAddress, EndAddress, UnwindInfoAddress
+    let entries = std::slice::from_raw_parts(base.add(start as usize) as *const [u32; 3], size as usize / 12);
+    let index = entries.partition_point(|entry| entry[1] <= rva);
+    entries
+        .get(index)
+        .filter(|entry| entry[0] <= rva)
+        .map(|entry| (entry[0], entry[1]))
+}
+
+/// Find the section containing an RVA
+pub unsafe fn section_for_rva(base: *const u8, rva: u32) -> Option<Section> {
+    sections(base).into_iter().find(|s| s.contains(rva))
+}
+
+/// Enumerate the export table of a mapped image
+pub unsafe fn exports(base: *const u8) -> Vec<Export> {
+    let nt = match nt_headers(base) {
+        Some(nt) => nt,
+        None => return Vec::new(),
+    };
+
+    let dir = (*nt).OptionalHeader.DataDirectory[IMAGE_DIRECTORY_ENTRY_EXPORT as usize];
+    if dir.VirtualAddress == 0 || dir.Size == 0 {
+        return Vec::new();
+    }
+
+    let export_dir = &*(base.add(dir.VirtualAddress as usize) as *const IMAGE_EXPORT_DIRECTORY);
+    let functions = std::slice::from_raw_parts(
+        base.add(export_dir.AddressOfFunctions as usize) as *const u32,
+        export_dir.NumberOfFunctions as usize,
+    );
+    let names = std::slice::from_raw_parts(
+        base.add(export_dir.AddressOfNames as usize) as *const u32,
+        export_dir.NumberOfNames as usize,
+    );
+    let name_ordinals = std::slice::from_raw_parts(
+        base.add(export_dir.AddressOfNameOrdinals as usize) as *const u16,
+        export_dir.NumberOfNames as usize,
+    );
+
+    // Map function index -> name
+    let mut function_names: Vec<Option<String>> = vec![None; functions.len()];
+    for (name_rva, &index) in names.iter().zip(name_ordinals) {
+        if let Some(slot) = function_names.get_mut(index as usize) {
+            *slot = Some(read_cstr(base.add(*name_rva as usize)));
+        }
+    }
+
+    let dir_start = dir.VirtualAddress;
+    let dir_end = dir.VirtualAddress + dir.Size;
+
+    functions
+        .iter()
+        .enumerate()
+        .filter(|(_, &rva)| rva != 0)
+        .map(|(i, &rva)| Export {
+            name: function_names[i].take(),
+            ordinal: (export_dir.Base + i as u32) as u16,
+            rva,
+            forwarder: if rva >= dir_start && rva < dir_end {
+                Some(read_cstr(base.add(rva as usize)))
+            } else {
+                None
+            },
+        })
+        .collect()
+}
+
+/// Find the IAT slot through which a module imports `function`
+///
+/// `dll` restricts the search to one imported DLL; pass `None` to match
+/// any DLL, which also covers imports through api-ms-win-* API sets.
+pub unsafe fn find_import_slot(
+    base: *const u8,
+    dll: Option<&str>,
+    function: &str,
+) -> Option<*mut usize> {
+    const ORDINAL_FLAG: usize = 1 << (usize::BITS - 1);
+
+    let nt = nt_headers(base)?;
+    let dir = (*nt).OptionalHeader.DataDirectory[IMAGE_DIRECTORY_ENTRY_IMPORT as usize];
+    if dir.VirtualAddress == 0 {
+        return None;
+    }
+
+    let mut descriptor = base.add(dir.VirtualAddress as usize) as *const IMAGE_IMPORT_DESCRIPTOR;
+    while (*descriptor).Name != 0 {
+        let dll_name = read_cstr(base.add((*descriptor).Name as usize));
+
+        if dll.is_none_or(|d| d.eq_ignore_ascii_case(&dll_name)) {
+            // Without an import lookup table the IAT still holds the name RVAs
+            let lookup_rva = match *(*descriptor).u.OriginalFirstThunk() {
+                0 => (*descriptor).FirstThunk,
+                rva => rva,
+            };
+            let mut lookup = base.add(lookup_rva as usize) as *const usize;
+            let mut slot = base.add((*descriptor).FirstThunk as usize) as *mut usize;
+
+            while *lookup != 0 {
+                if *lookup & ORDINAL_FLAG == 0 {
+                    // IMAGE_IMPORT_BY_NAME: u16 hint followed by the name
+                    let name = read_cstr(base.add((*lookup as u32) as usize + 2));
+                    if name == function {
+                        return Some(slot);
+                    }
+                }
+                lookup = lookup.add(1);
+                slot = slot.add(1);
+            }
+        }
+
+        descriptor = descriptor.add(1);
+    }
+
+    None
+}
+
+/// Slots of the TLS callback array of a mapped image, in call order
+///
+/// Each slot holds the (relocated) address of one callback.
+pub unsafe fn tls_callback_slots(base: *const u8) -> Vec<*mut usize> {
+    let (rva, _) = match data_directory(base, IMAGE_DIRECTORY_ENTRY_TLS) {
+        Some(dir) => dir,
+        None => return Vec::new(),
+    };
+
+    let directory = &*(base.add(rva as usize) as *const IMAGE_TLS_DIRECTORY);
+    let mut slot = directory.AddressOfCallBacks as *mut usize;
+    let mut slots = Vec::new();
+    while !slot.is_null() && *slot != 0 {
+        slots.push(slot);
+        slot = slot.add(1);
+    }
+    slots
+}
+
+/// `FileHeader.Machine` of a PE file; only the headers need to be read
+pub fn file_machine(data: &[u8]) -> Option<u16> {
+    if data.get(..2)? != b"MZ" {
+        return None;
+    }
+    let e_lfanew = u32::from_le_bytes(data.get(0x3c..0x40)?.try_into().ok()?) as usize;
+    if data.get(e_lfanew..e_lfanew + 4)? != b"PE\0\0" {
+        return None;
+    }
+    Some(u16::from_le_bytes(data.get(e_lfanew + 4..e_lfanew + 6)?.try_into().ok()?))
+}
+
+/// The architecture a `Machine` value stands for, e.g. "x64 (64-bit)"
+pub fn machine_name(machine: u16) -> String {
+    match machine {
+        IMAGE_FILE_MACHINE_I386 => "x86 (32-bit)".to_string(),
+        IMAGE_FILE_MACHINE_AMD64 => "x64 (64-bit)".to_string(),
+        IMAGE_FILE_MACHINE_ARMNT => "ARM (32-bit)".to_string(),
+        IMAGE_FILE_MACHINE_ARM64 => "ARM64 (64-bit)".to_string(),
+        other => format!("unknown machine 0x{:04x}", other),
+    }
+}
+
+/// Lay out a PE file the way the loader maps it: headers at offset 0 and
+/// each section's raw data at its RVA. Imports are not resolved and
+/// relocations are not applied (see `relocate`).
+pub fn map_file(data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < size_of::<IMAGE_DOS_HEADER>() {
+        return None;
+    }
+    let e_lfanew = u32::from_le_bytes([data[0x3c], data[0x3d], data[0x3e], data[0x3f]]) as usize;
+    if e_lfanew + size_of::<IMAGE_NT_HEADERS>() > data.len() {
+        return None;
+    }
+
+    unsafe {
+        let nt = nt_headers(data.as_ptr())?;
+        let optional = &(*nt).OptionalHeader;
+        // Section headers are part of SizeOfHeaders
+        if optional.SizeOfHeaders as usize > data.len() {
+            return None;
+        }
+
+        let mut image = vec![0u8; optional.SizeOfImage as usize];
+        let headers = (optional.SizeOfHeaders as usize).min(data.len()).min(image.len());
+        image[..headers].copy_from_slice(&data[..headers]);
+
+        for header in section_headers(data.as_ptr()) {
+            let raw_start = header.PointerToRawData as usize;
+            let raw_len = (header.SizeOfRawData as usize).min(*header.Misc.VirtualSize() as usize);
+            let rva = header.VirtualAddress as usize;
+            if raw_len == 0 {
+                continue;
+            }
+            if raw_start + raw_len > data.len() || rva + raw_len > image.len() {
+                return None;
+            }
+            image[rva..rva + raw_len].copy_from_slice(&data[raw_start..raw_start + raw_len]);
+        }
+
+        Some(image)
+    }
+}
+
+/// Rewrite the headers of an image copied from memory so tools read it as a
+/// file: each section's raw data is where the loader mapped it (its RVA,
+/// over its whole virtual size) and ImageBase is the address it was copied
+/// from, so its relocated pointers are already correct
+pub fn unmap_headers(image: &mut [u8], loaded_at: usize) -> Result<(), String> {
+    unsafe {
+        let nt = nt_headers(image.as_ptr()).ok_or("invalid PE image")? as *mut IMAGE_NT_HEADERS;
+        let optional = &mut (*nt).OptionalHeader;
+        optional.ImageBase = loaded_at as _;
+        optional.FileAlignment = optional.SectionAlignment;
+
+        let headers = section_headers(image.as_ptr());
+        let headers = std::slice::from_raw_parts_mut(headers.as_ptr() as *mut IMAGE_SECTION_HEADER, headers.len());
+        for header in headers {
+            header.PointerToRawData = header.VirtualAddress;
+            header.SizeOfRawData = *header.Misc.VirtualSize();
+        }
+    }
+    Ok(())
+}
+
+/// Apply the base relocations of an image laid out by `map_file` as if it
+/// were loaded at `new_base`
+pub fn relocate(image: &mut [u8], new_base: usize) -> Result<(), String> {
+    const IMAGE_REL_BASED_ABSOLUTE: u16 = 0;
+    const IMAGE_REL_BASED_HIGHLOW: u16 = 3;
+    const IMAGE_REL_BASED_DIR64: u16 = 10;
+
+    let (preferred, relocations) = unsafe {
+        let nt = nt_headers(image.as_ptr()).ok_or("invalid PE image")?;
+        (
+            (*nt).OptionalHeader.ImageBase as usize,
+            data_directory(image.as_ptr(), IMAGE_DIRECTORY_ENTRY_BASERELOC),
+        )
+    };
+    let delta = new_base.wrapping_sub(preferred);
+    let (start, size) = match relocations {
+        Some(dir) if delta != 0 => dir,
+        _ => return Ok(()),
+    };
+
+    let read_u32 = |image: &[u8], at: usize| -> Option<u32> {
+        image.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    let mut block = start as usize;
+    let end = (start + size) as usize;
+    while block + 8 <= end {
+        let page = read_u32(image, block).ok_or("relocation block out of range")? as usize;
+        let block_size = read_u32(image, block + 4).ok_or("relocation block out of range")? as usize;
+        if block_size < 8 {
+            break;
+        }
+
+        for entry_at in (block + 8..block + block_size).step_by(2) {
+            let entry = match image.get(entry_at..entry_at + 2) {
+                Some(b) => u16::from_le_bytes([b[0], b[1]]),
+                None => return Err("relocation entry out of range".to_string()),
+            };
+            let at = page + (entry & 0x0FFF) as usize;
+            match entry >> 12 {
+                IMAGE_REL_BASED_ABSOLUTE => {}
+                IMAGE_REL_BASED_HIGHLOW => {
+                    let value = read_u32(image, at).ok_or("relocation target out of range")?;
+                    let value = value.wrapping_add(delta as u32);
+                    image[at..at + 4].copy_from_slice(&value.to_le_bytes());
+                }
+                IMAGE_REL_BASED_DIR64 => {
+                    let bytes = image.get(at..at + 8).ok_or("relocation target out of range")?;
+                    let mut value = [0u8; 8];
+                    value.copy_from_slice(bytes);
+                    let value = u64::from_le_bytes(value).wrapping_add(delta as u64);
+                    image[at..at + 8].copy_from_slice(&value.to_le_bytes());
+                }
+                other => return Err(format!("unsupported relocation type {}", other)),
+            }
+        }
+
+        block += block_size;
+    }
+
+    Ok(())
+}
+
+unsafe fn read_cstr(ptr: *const u8) -> String {
+    CStr::from_ptr(ptr as *const i8).to_string_lossy().into_owned()
+}
@@ -0,0 +1,137 @@
+/// Fixes applied to a linked PE file (reflex_postlink)
+///
+/// - `checksum`: the value `OptionalHeader.CheckSum` must hold, computed
+///   like CheckSumMappedFile. Drivers, and some loaders and anti-cheats,
+///   reject an image whose checksum does not match; MSVC only writes it
+///   with `/RELEASE`, other linkers leave it 0.
+/// - `rich_header`: the linker's "Rich" block between the DOS stub and the
+///   PE header, listing the tools that built each object. It differs from
+///   one build machine to the next, and `strip_rich_header` zeroes it.
+///
+/// Works on the file bytes and is independent of the host platform.
+
+use std::ops::Range;
+
+/// "DanS" as a little-endian dword, XORed with the key at the start of the
+/// Rich header
+const DANS: u32 = 0x536e_6144;
+
+/// Offset of the NT headers, after checking both signatures
+fn nt_offset(data: &[u8]) -> Option<usize> {
+    if data.get(..2)? != b"MZ" {
+        return None;
+    }
+    let e_lfanew = read_u32(data, 0x3c)? as usize;
+    if data.get(e_lfanew..e_lfanew + 4)? != b"PE\0\0" {
+        return None;
+    }
+    Some(e_lfanew)
+}
+
+/// Offset of `OptionalHeader.CheckSum`, the same in PE32 and PE32+
+pub fn checksum_offset(data: &[u8]) -> Option<usize> {
+    // Signature, IMAGE_FILE_HEADER, then 64 bytes into the optional header
+    let offset = nt_offset(data)? + 4 + 20 + 64;
+    (offset + 4 <= data.len()).then_some(offset)
+}
+
+/// The checksum of the file, ignoring the value currently stored
+pub fn checksum(data: &[u8]) -> Option<u32> {
+    let skip = checksum_offset(data)?;
+    let mut sum: u64 = 0;
+    for (i, word) in data.chunks(2).enumerate() {
+        if (skip..skip + 4).contains(&(i * 2)) {
+            continue;
+        }
+        sum += u64::from(u16::from_le_bytes([word[0], word.get(1).copied().unwrap_or(0)]));
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum = (sum & 0xffff) + (sum >> 16);
+    Some(sum as u32 + data.len() as u32)
+}
+
+/// Write the correct checksum; returns (old, new)
+pub fn fix_checksum(data: &mut [u8]) -> Result<(u32, u32), String> {
+    let offset = checksum_offset(data).ok_or("not a PE file")?;
+    let old = read_u32(data, offset).unwrap_or(0);
+    let new = checksum(data).ok_or("not a PE file")?;
+    data[offset..offset + 4].copy_from_slice(&new.to_le_bytes());
+    Ok((old, new))
+}
+
+/// Bytes of the Rich header, from its "DanS" start to the key after
+/// "Rich"
+pub fn rich_header(data: &[u8]) -> Option<Range<usize>> {
+    let end = nt_offset(data)?;
+    let rich = (0x40..end.saturating_sub(7)).step_by(4).find(|&at| data.get(at..at + 4) == Some(b"Rich"))?;
+    let key = read_u32(data, rich + 4)?;
+    let start = (0x40..rich).step_by(4).rev().find(|&at| read_u32(data, at) == Some(DANS ^ key))?;
+    Some(start..rich + 8)
+}
+
+/// Zero the Rich header, if there is one; returns its size
+///
+/// The checksum covers it, so fix the checksum afterwards.
+pub fn strip_rich_header(data: &mut [u8]) -> usize {
+    match rich_header(data) {
+        Some(range) => {
+            data[range.clone()].fill(0);
+            range.len()
+        }
+        None => 0,
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Headers only: DOS header, a Rich header at 0x80, NT headers at 0xc0
+    fn image() -> Vec<u8> {
+        let mut data = vec![0u8; 0x200];
+        data[..2].copy_from_slice(b"MZ");
+        data[0x3c..0x40].copy_from_slice(&0xc0u32.to_le_bytes());
+        data[0xc0..0xc4].copy_from_slice(b"PE\0\0");
+
+        let key = 0x1234_5678u32;
+        let rich = [DANS ^ key, key, key, key, 0x0101_0000 ^ key, 3 ^ key];
+        for (i, dword) in rich.iter().enumerate() {
+            data[0x80 + i * 4..0x84 + i * 4].copy_from_slice(&dword.to_le_bytes());
+        }
+        data[0x98..0x9c].copy_from_slice(b"Rich");
+        data[0x9c..0xa0].copy_from_slice(&key.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn checksum_ignores_its_own_field() {
+        let mut data = vec![0u8; 0x100];
+        data[..2].copy_from_slice(b"MZ");
+        data[0x3c] = 0x40;
+        data[0x40..0x44].copy_from_slice(b"PE\0\0");
+        assert_eq!(checksum_offset(&data), Some(0x98));
+
+        // "MZ" + e_lfanew + "PE", plus the file length
+        assert_eq!(checksum(&data), Some(0x5a4d + 0x40 + 0x4550 + 0x100));
+        data[0x98..0x9c].copy_from_slice(&0xdead_beefu32.to_le_bytes());
+        assert_eq!(fix_checksum(&mut data), Ok((0xdead_beef, 0xa0dd)));
+        assert_eq!(checksum(&data), Some(0xa0dd));
+        assert_eq!(checksum(b"not a PE file"), None);
+    }
+
+    #[test]
+    fn rich_header_is_found_and_zeroed() {
+        let mut data = image();
+        assert_eq!(rich_header(&data), Some(0x80..0xa0));
+
+        assert_eq!(strip_rich_header(&mut data), 0x20);
+        assert!(data[0x80..0xa0].iter().all(|&b| b == 0));
+        assert_eq!(rich_header(&data), None);
+        assert_eq!(strip_rich_header(&mut data), 0);
+        assert_eq!(&data[0xc0..0xc4], b"PE\0\0");
+    }
+}
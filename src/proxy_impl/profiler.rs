@@ -0,0 +1,367 @@
+/// Sampling profiler for time spent in the original DLL
+///
+/// A background thread wakes every `interval_ms`, suspends each other
+/// thread of the process in turn, captures its stack with the x64 unwind
+/// data (RtlLookupFunctionEntry / RtlVirtualUnwind) and resumes it. Frames
+/// inside reflex_original.dll are attributed to the nearest preceding
+/// export or known offset (offsets.rs):
+/// - `self`: the innermost original frame of a sample is in that function
+/// - `inclusive`: the function is anywhere on the sampled stack
+///
+/// Nothing is allocated or locked while a thread is suspended, since it may
+/// hold the heap lock. The profile is written on shutdown and with the
+/// `profile` pipe command; `profile start` / `profile stop` capture a fresh
+/// profile around a benchmark run. Full stacks of the samples that hit the original
+/// are also written as collapsed stacks and speedscope JSON (flamegraph.rs);
+/// frames outside the original are named after their module.
+///
+/// ```toml
+/// [profiler]
+/// enabled = true
+/// interval_ms = 10
+/// path = "reflex_profile.json"
+/// collapsed_path = "reflex_profile.folded"            # "" to disable
+/// speedscope_path = "reflex_profile.speedscope.json"  # "" to disable
+/// ```
+///
+/// x64 only.
+
+use crate::proxy;
+use crate::proxy_impl::config::ProfilerConfig;
+use crate::proxy_impl::worker::Worker;
+use crate::proxy_impl::{flamegraph, offsets, pe};
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use winapi::shared::minwindef::FALSE;
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::processthreadsapi::{
+    GetCurrentProcessId, GetCurrentThreadId, GetThreadContext, OpenThread, ResumeThread, SuspendThread,
+};
+use winapi::um::tlhelp32::{CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32};
+use winapi::um::winnt::{
+    RtlLookupFunctionEntry, RtlVirtualUnwind, CONTEXT, CONTEXT_CONTROL, CONTEXT_INTEGER, HANDLE,
+    THREAD_GET_CONTEXT, THREAD_QUERY_INFORMATION, THREAD_SUSPEND_RESUME, UNW_FLAG_NHANDLER,
+};
+
+/// Frames captured per sample
+pub const MAX_FRAMES: usize = 64;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+/// Bumped on every start, so a sampling thread still sleeping after a quick
+/// stop/start exits instead of sampling twice
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static WORKER: Worker = Worker::new();
+static PROFILE: Lazy<Mutex<Profile>> = Lazy::new(|| Mutex::new(Profile::default()));
+
+#[derive(Default)]
+struct Profile {
+    path: String,
+    collapsed_path: String,
+    speedscope_path: String,
+    interval_ms: u64,
+    samples: u64,
+    samples_in_original: u64,
+    /// symbol -> (self, inclusive)
+    functions: HashMap<String, (u64, u64)>,
+    /// Root-first stacks that reached the original -> sample count
+    stacks: HashMap<Vec<String>, u64>,
+}
+
+/// Symbols of the original DLL, sorted by RVA
+struct Symbols {
+    base: usize,
+    entries: Vec<(u32, String)>,
+}
+
+impl Symbols {
+    unsafe fn load() -> Self {
+        let base = proxy::get_original_dll_base() as *const u8;
+        let mut entries: Vec<(u32, String)> = pe::exports(base)
+            .into_iter()
+            .filter(|e| e.forwarder.is_none())
+            .map(|e| (e.rva, e.name.unwrap_or_else(|| format!("#{}", e.ordinal))))
+            .collect();
+        entries.extend(offsets::KNOWN_OFFSETS.iter().map(|o| (o.offset as u32, o.name.to_string())));
+        entries.sort_by_key(|(rva, _)| *rva);
+
+        Self {
+            base: base as usize,
+            entries,
+        }
+    }
+
+    /// Nearest symbol at or before `address`, or the raw RVA
+    fn lookup(&self, address: usize) -> String {
+        let rva = (address - self.base) as u32;
+        match self.entries.partition_point(|(start, _)| *start <= rva).checked_sub(1) {
+            Some(index) => self.entries[index].1.clone(),
+            None => format!("rva_{:x}", rva),
+        }
+    }
+}
+
+/// Start sampling on a background thread
+///
+/// Samples of an earlier, stopped run are discarded.
+pub fn start(config: &ProfilerConfig) {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    *PROFILE.lock().unwrap() = Profile {
+        path: config.path.clone(),
+        collapsed_path: config.collapsed_path.clone(),
+        speedscope_path: config.speedscope_path.clone(),
+        interval_ms: config.interval_ms.max(1),
+        ..Profile::default()
+    };
+
+    let interval = Duration::from_millis(config.interval_ms.max(1));
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    tracing::info!("[profiler] Sampling every {:?}", interval);
+
+    let spawned = WORKER.spawn("reflex-profiler", move || unsafe { sample_loop(interval, generation) });
+
+    if let Err(e) = spawned {
+        RUNNING.store(false, Ordering::SeqCst);
+        tracing::error!("[profiler] Failed to spawn profiler thread: {}", e);
+    }
+}
+
+/// Whether a sampling thread is running
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::SeqCst)
+}
+
+/// Stop the sampling thread and wait for it
+pub fn stop() {
+    RUNNING.store(false, Ordering::SeqCst);
+    WORKER.stop();
+}
+
+/// Write the profile collected so far; returns the path and sample count
+pub fn write() -> Result<(String, usize), String> {
+    let profile = PROFILE.lock().unwrap();
+    if profile.path.is_empty() {
+        return Err("profiler is not enabled".to_string());
+    }
+
+    let mut functions: Vec<_> = profile.functions.iter().collect();
+    functions.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(b.1 .1.cmp(&a.1 .1)));
+
+    let percent = |count: u64| match profile.samples_in_original {
+        0 => 0.0,
+        total => count as f64 * 100.0 / total as f64,
+    };
+    let report = json!({
+        "interval_ms": profile.interval_ms,
+        "samples": profile.samples,
+        "samples_in_original": profile.samples_in_original,
+        "functions": functions
+            .iter()
+            .map(|(symbol, (self_count, inclusive))| json!({
+                "symbol": symbol,
+                "self": self_count,
+                "inclusive": inclusive,
+                "self_percent": percent(*self_count),
+                "inclusive_percent": percent(*inclusive),
+            }))
+            .collect::<Vec<_>>(),
+    });
+
+    let json = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+    fs::write(&profile.path, json).map_err(|e| format!("Failed to write {}: {}", profile.path, e))?;
+    let mut written = vec![profile.path.clone()];
+
+    if !profile.collapsed_path.is_empty() {
+        fs::write(&profile.collapsed_path, flamegraph::collapsed(&profile.stacks))
+            .map_err(|e| format!("Failed to write {}: {}", profile.collapsed_path, e))?;
+        written.push(profile.collapsed_path.clone());
+    }
+
+    if !profile.speedscope_path.is_empty() {
+        let speedscope = flamegraph::speedscope(&profile.stacks, profile.interval_ms, "reflex_original.dll");
+        fs::write(&profile.speedscope_path, speedscope.to_string())
+            .map_err(|e| format!("Failed to write {}: {}", profile.speedscope_path, e))?;
+        written.push(profile.speedscope_path.clone());
+    }
+
+    Ok((written.join(", "), profile.samples as usize))
+}
+
+// ============================================================================
+// Sampling
+// ============================================================================
+
+unsafe fn sample_loop(interval: Duration, generation: u64) {
+    let symbols = Symbols::load();
+    let own_thread = GetCurrentThreadId();
+    let mut frames = [0usize; MAX_FRAMES];
+    let mut modules = HashMap::new();
+
+    while RUNNING.load(Ordering::SeqCst) && GENERATION.load(Ordering::SeqCst) == generation {
+        for thread_id in process_threads().into_iter().filter(|&id| id != own_thread) {
+            let depth = match capture_stack(thread_id, &mut frames) {
+                Some(depth) => depth,
+                None => continue,
+            };
+            record(&symbols, &mut modules, &frames[..depth]);
+        }
+        WORKER.sleep(interval);
+    }
+}
+
+/// IDs of every thread in this process
+unsafe fn process_threads() -> Vec<u32> {
+    let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+    if snapshot == INVALID_HANDLE_VALUE {
+        return Vec::new();
+    }
+
+    let pid = GetCurrentProcessId();
+    let mut threads = Vec::new();
+    let mut entry: THREADENTRY32 = std::mem::zeroed();
+    entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+
+    let mut more = Thread32First(snapshot, &mut entry);
+    while more != FALSE {
+        if entry.th32OwnerProcessID == pid {
+            threads.push(entry.th32ThreadID);
+        }
+        more = Thread32Next(snapshot, &mut entry);
+    }
+
+    CloseHandle(snapshot);
+    threads
+}
+
+/// Suspend a thread, unwind its stack into `frames` and resume it
+///
+/// Returns the number of frames captured.
+pub unsafe fn capture_stack(thread_id: u32, frames: &mut [usize; MAX_FRAMES]) -> Option<usize> {
+    let thread: HANDLE = OpenThread(
+        THREAD_SUSPEND_RESUME | THREAD_GET_CONTEXT | THREAD_QUERY_INFORMATION,
+        FALSE,
+        thread_id,
+    );
+    if thread.is_null() {
+        return None;
+    }
+
+    if SuspendThread(thread) == u32::MAX {
+        CloseHandle(thread);
+        return None;
+    }
+
+    // No allocation from here until ResumeThread
+    let mut context: CONTEXT = std::mem::zeroed();
+    context.ContextFlags = CONTEXT_CONTROL | CONTEXT_INTEGER;
+    let depth = if GetThreadContext(thread, &mut context) != FALSE {
+        unwind(&mut context, frames)
+    } else {
+        0
+    };
+
+    ResumeThread(thread);
+    CloseHandle(thread);
+    (depth > 0).then_some(depth)
+}
+
+fn unwind(context: &mut CONTEXT, frames: &mut [usize; MAX_FRAMES]) -> usize {
+    let mut depth = 0;
+
+    while depth < MAX_FRAMES && context.Rip != 0 {
+        frames[depth] = context.Rip as usize;
+        depth += 1;
+
+        unsafe {
+            let mut image_base = 0u64;
+            let function = RtlLookupFunctionEntry(context.Rip, &mut image_base, std::ptr::null_mut());
+            if function.is_null() {
+                // Leaf function without unwind data: only trustworthy at the top
+                if depth > 1 {
+                    break;
+                }
+                context.Rip = *(context.Rsp as *const u64);
+                context.Rsp += 8;
+                continue;
+            }
+
+            let mut handler_data = std::ptr::null_mut();
+            let mut establisher_frame = 0u64;
+            RtlVirtualUnwind(
+                UNW_FLAG_NHANDLER,
+                image_base,
+                context.Rip,
+                function,
+                context,
+                &mut handler_data,
+                &mut establisher_frame,
+                std::ptr::null_mut(),
+            );
+        }
+    }
+
+    depth
+}
+
+/// Attribute one sample (innermost frame first) to the functions of the
+/// original DLL on its stack
+unsafe fn record(symbols: &Symbols, modules: &mut HashMap<usize, String>, frames: &[usize]) {
+    let mut in_original = frames.iter().filter(|&&f| proxy::is_in_original(f)).map(|&f| symbols.lookup(f));
+
+    let innermost = match in_original.next() {
+        Some(symbol) => symbol,
+        None => {
+            PROFILE.lock().unwrap().samples += 1;
+            return;
+        }
+    };
+
+    // Root first; consecutive frames of the same outside module are merged
+    let mut stack: Vec<String> = Vec::with_capacity(frames.len());
+    for &frame in frames.iter().rev() {
+        if proxy::is_in_original(frame) {
+            stack.push(symbols.lookup(frame));
+        } else {
+            let name = module_name(modules, frame);
+            if stack.last() != Some(&name) {
+                stack.push(name);
+            }
+        }
+    }
+
+    let mut profile = PROFILE.lock().unwrap();
+    profile.samples += 1;
+    profile.samples_in_original += 1;
+    *profile.stacks.entry(stack).or_default() += 1;
+
+    let mut seen = vec![innermost.clone()];
+    profile.functions.entry(innermost).or_default().0 += 1;
+    for symbol in in_original {
+        if !seen.contains(&symbol) {
+            seen.push(symbol);
+        }
+    }
+    for symbol in seen {
+        profile.functions.entry(symbol).or_default().1 += 1;
+    }
+}
+
+/// File name of the module containing `address`, cached per module
+unsafe fn module_name(cache: &mut HashMap<usize, String>, address: usize) -> String {
+    let module = match proxy::module_at(address) {
+        Some(module) => module,
+        None => return "[unknown]".to_string(),
+    };
+
+    cache
+        .entry(module as usize)
+        .or_insert_with(|| proxy::get_module_name(module).unwrap_or_else(|| format!("module_{:x}", module as usize)))
+        .clone()
+}
@@ -4,84 +4,315 @@
 /// 1. This DLL is named reflex.dll
 /// 2. Original reflex.dll is renamed to reflex_original.dll
 /// 3. All calls are forwarded to the original DLL
-/// 4. Optional hooks can intercept/modify behavior
+/// 4. Optional hooks can intercept/modify behavior; code around the
+///    original's DllMain is registered with `add_pre_dllmain_hook` and
+///    `add_post_dllmain_hook`
+///
+/// In a proxy chain (`ProxyConfig::chained`) the "original" is the next
+/// proxy, another mod's reflex.dll, which loads the real original itself.
+/// Exports are forwarded to it unchanged. It gets its DllMain calls from
+/// the loader like any loaded DLL, so they are not forwarded a second time.
 
-use std::ffi::CString;
-use std::sync::Once;
-use winapi::shared::minwindef::{BOOL, DWORD, HINSTANCE, HMODULE, LPVOID, TRUE, FALSE};
-use winapi::um::libloaderapi::{GetProcAddress, LoadLibraryA};
-use winapi::um::winnt::{DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH};
+#[cfg(feature = "embedded-original")]
+use crate::proxy_impl::embedded;
+use crate::proxy_impl::export_cache::{ExportCache, ExportCacheStats};
+use crate::proxy_impl::{manual_map, os, pe, watchdog};
+use crate::proxy_impl::status::{self, ErrorKind};
+use crate::proxy_impl::wide::to_wide;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Read;
+use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Mutex, Once, RwLock};
+use std::time::SystemTime;
+use winapi::shared::minwindef::{BOOL, DWORD, HINSTANCE, HINSTANCE__, HMODULE, LPVOID, FALSE, TRUE};
+use winapi::um::libloaderapi::{
+    FreeLibrary, GetModuleFileNameW, GetModuleHandleExW, GetModuleHandleW,
+    GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS, GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+};
+use winapi::um::winnt::{
+    RtlCaptureStackBackTrace, DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH, DLL_THREAD_ATTACH, DLL_THREAD_DETACH,
+};
 
 static INIT: Once = Once::new();
-static mut ORIGINAL_DLL: HMODULE = std::ptr::null_mut();
-static mut ORIGINAL_DLLMAIN: Option<DllMainFn> = None;
+/// Base of the original; set from the retry (load_retry.rs) and swap
+/// (hot_swap.rs) threads too, hence atomics rather than `static mut`
+static ORIGINAL_DLL: AtomicPtr<HINSTANCE__> = AtomicPtr::new(std::ptr::null_mut());
+/// Address of the original's DllMain; 0 = none
+static ORIGINAL_DLLMAIN: AtomicUsize = AtomicUsize::new(0);
+/// The original was mapped by manual_map.rs rather than LoadLibrary
+static MANUALLY_MAPPED: AtomicBool = AtomicBool::new(false);
+/// The original is the next proxy of a chain and notified by the loader
+static CHAINED: AtomicBool = AtomicBool::new(false);
 
 type DllMainFn = unsafe extern "system" fn(HINSTANCE, DWORD, LPVOID) -> BOOL;
 
+/// Runs before the original's DllMain; `Some(result)` skips the original
+/// and returns `result` to the loader
+pub type PreDllMainHook = Box<dyn Fn(HINSTANCE, DWORD, LPVOID) -> Option<BOOL> + Send + Sync>;
+/// Runs after the original's DllMain, with its result
+pub type PostDllMainHook = Box<dyn Fn(HINSTANCE, DWORD, LPVOID, BOOL) + Send + Sync>;
+
+static PRE_DLLMAIN_HOOKS: Lazy<RwLock<Vec<PreDllMainHook>>> = Lazy::new(|| RwLock::new(Vec::new()));
+static POST_DLLMAIN_HOOKS: Lazy<RwLock<Vec<PostDllMainHook>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Export addresses resolved so far (export_cache.rs)
+static EXPORTS: Lazy<ExportCache> = Lazy::new(ExportCache::default);
+
+/// DllMain calls forwarded to the original, indexed by reason
+static FORWARDED: Mutex<[ReasonCounter; 4]> = Mutex::new([ReasonCounter::ZERO; 4]);
+
+#[derive(Clone, Copy)]
+struct ReasonCounter {
+    count: u64,
+    first: Option<SystemTime>,
+    last: Option<SystemTime>,
+}
+
+impl ReasonCounter {
+    const ZERO: Self = Self {
+        count: 0,
+        first: None,
+        last: None,
+    };
+}
+
+/// How often the original's DllMain was called with one reason
+#[derive(Debug, Clone, Serialize)]
+pub struct ReasonStats {
+    pub reason: &'static str,
+    pub count: u64,
+    /// First and latest call, RFC 3339
+    pub first: Option<String>,
+    pub last: Option<String>,
+}
+
 /// Configuration for proxy behavior
+///
+/// Built at runtime, so paths can be computed (next to the proxy, from the
+/// config file):
+///
+/// ```rust,ignore
+/// let config = proxy::ProxyConfig::builder()
+///     .original_dll(directory.join("reflex_original.dll"))
+///     .logging(true)
+///     .manual_map(config::get().loader.manual_map)
+///     .build();
+/// proxy::initialize_proxy(&config)?;
+/// ```
+#[derive(Debug, Clone)]
 pub struct ProxyConfig {
     /// Path to the original DLL (default: "reflex_original.dll")
-    pub original_dll_path: &'static str,
+    pub original_dll_path: PathBuf,
     /// Enable logging of proxy operations
     pub enable_logging: bool,
-    /// Enable pre-hook (called before forwarding to original)
-    pub enable_pre_hook: bool,
-    /// Enable post-hook (called after forwarding to original)
-    pub enable_post_hook: bool,
+    /// Map the original manually instead of with LoadLibrary (manual_map.rs)
+    pub manual_map: bool,
+    /// `LOAD_LIBRARY_SEARCH_*` flags for the original and its dependencies
+    /// (0 = classic search order)
+    pub search_flags: u32,
+    /// The DLL at `original_dll_path` is the next proxy of a chain rather
+    /// than the original
+    pub chained: bool,
 }
 
 impl Default for ProxyConfig {
     fn default() -> Self {
         Self {
-            original_dll_path: "reflex_original.dll",
+            original_dll_path: PathBuf::from("reflex_original.dll"),
             enable_logging: true,
-            enable_pre_hook: false,
-            enable_post_hook: false,
+            manual_map: false,
+            search_flags: 0,
+            chained: false,
         }
     }
 }
 
+impl ProxyConfig {
+    /// A builder starting from the defaults
+    pub fn builder() -> ProxyConfigBuilder {
+        ProxyConfigBuilder::default()
+    }
+
+    /// File name of the original, the module name the loader knows it by
+    pub fn original_dll_name(&self) -> String {
+        self.original_dll_path
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+    }
+}
+
+/// Builds a `ProxyConfig`; unset values keep their defaults
+#[derive(Debug, Default)]
+pub struct ProxyConfigBuilder {
+    config: ProxyConfig,
+}
+
+impl ProxyConfigBuilder {
+    /// Path of the original DLL; a relative path is resolved against the
+    /// proxy's directory when the DLL load directory is searched
+    pub fn original_dll(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.original_dll_path = path.into();
+        self
+    }
+
+    pub fn logging(mut self, enabled: bool) -> Self {
+        self.config.enable_logging = enabled;
+        self
+    }
+
+    pub fn manual_map(mut self, enabled: bool) -> Self {
+        self.config.manual_map = enabled;
+        self
+    }
+
+    /// `LOAD_LIBRARY_SEARCH_*` flags (0 = classic search order)
+    pub fn search_flags(mut self, flags: u32) -> Self {
+        self.config.search_flags = flags;
+        self
+    }
+
+    /// Load `original_dll` as the next proxy of a chain
+    pub fn chained(mut self, enabled: bool) -> Self {
+        self.config.chained = enabled;
+        self
+    }
+
+    pub fn build(self) -> ProxyConfig {
+        self.config
+    }
+}
+
 /// Initialize the proxy by loading the original DLL
 pub unsafe fn initialize_proxy(config: &ProxyConfig) -> Result<(), String> {
-    let dll_path = CString::new(config.original_dll_path)
-        .map_err(|e| format!("Invalid DLL path: {}", e))?;
+    let os = os::system();
 
-    // Load the original DLL
-    let handle = LoadLibraryA(dll_path.as_ptr());
-    if handle.is_null() {
+    // A proxy already in the process is this one or one before it in the
+    // chain; forwarding to it would loop
+    if config.chained && is_loaded(&original_path(config)) {
         return Err(format!(
-            "Failed to load original DLL: {}",
-            config.original_dll_path
+            "Next proxy {} is already loaded, the chain would loop",
+            config.original_dll_path.display()
         ));
     }
 
-    ORIGINAL_DLL = handle;
+    // Load the original DLL
+    let handle = load_original(config).map_err(|e| format!("Failed to load original DLL: {}", e))? as HMODULE;
+    let chained = config.chained && !config.manual_map;
+    MANUALLY_MAPPED.store(config.manual_map, Ordering::SeqCst);
+    CHAINED.store(chained, Ordering::SeqCst);
+    ORIGINAL_DLL.store(handle, Ordering::SeqCst);
 
     if config.enable_logging {
-        log::info!(
+        tracing::info!(
             "[reflex-proxy] Loaded original DLL from: {}",
-            config.original_dll_path
+            config.original_dll_path.display()
         );
-        log::info!("[reflex-proxy] Original DLL base address: {:p}", handle);
+        tracing::info!("[reflex-proxy] Original DLL base address: {:p}", handle);
     }
 
-    // Get the address of DllMain from the original DLL
-    let dllmain_name = CString::new("DllMain").unwrap();
-    let dllmain_addr = GetProcAddress(handle, dllmain_name.as_ptr());
-
-    if dllmain_addr.is_null() {
-        return Err("Failed to find DllMain in original DLL".to_string());
+    // The loader runs the next proxy's DllMain itself
+    if chained {
+        if config.enable_logging {
+            tracing::info!("[reflex-proxy] Chained to next proxy, its DllMain is called by the loader");
+        }
+        return Ok(());
     }
 
-    ORIGINAL_DLLMAIN = Some(std::mem::transmute(dllmain_addr));
+    // Get the address of DllMain from the original DLL; a manually mapped
+    // image has not been initialized yet, so its entry point is called instead
+    let dllmain_addr = if config.manual_map {
+        manual_map::entry_point(handle as *const u8).ok_or("Original DLL has no entry point")?
+    } else {
+        os.get_proc_address(handle as usize, "DllMain")
+            .ok_or("Failed to find DllMain in original DLL")?
+    };
+
+    ORIGINAL_DLLMAIN.store(dllmain_addr, Ordering::SeqCst);
 
     if config.enable_logging {
-        log::info!("[reflex-proxy] Original DllMain at: {:p}", dllmain_addr);
+        tracing::info!("[reflex-proxy] Original DllMain at: 0x{:x}", dllmain_addr);
     }
 
     Ok(())
 }
 
+/// Load (or map) the original from disk, or the embedded copy if there is
+/// none on disk
+unsafe fn load_original(config: &ProxyConfig) -> Result<usize, String> {
+    let path = original_path(config);
+
+    #[cfg(feature = "embedded-original")]
+    if !path.exists() {
+        tracing::warn!("[reflex-proxy] {} not found, using the embedded copy", path.display());
+        return embedded::load(config.manual_map, config.search_flags);
+    }
+
+    let result = if config.manual_map {
+        manual_map::load(&path.to_string_lossy(), config.search_flags)
+    } else {
+        os::system().load_library(&path.to_string_lossy(), config.search_flags)
+    };
+    // ERROR_BAD_EXE_FORMAT says nothing about which side is wrong
+    if let Err(e) = &result {
+        if e.ends_with("error 193") {
+            if let Some(explanation) = architecture_mismatch(&path) {
+                tracing::error!("[reflex-proxy] {}", explanation);
+            }
+        }
+    }
+    result
+}
+
+/// `Machine` the proxy was built for, which is the game's too: the loader
+/// only maps DLLs of the process's own architecture
+const PROXY_MACHINE: u16 = if cfg!(target_arch = "x86") {
+    winapi::um::winnt::IMAGE_FILE_MACHINE_I386
+} else if cfg!(target_arch = "aarch64") {
+    winapi::um::winnt::IMAGE_FILE_MACHINE_ARM64
+} else {
+    winapi::um::winnt::IMAGE_FILE_MACHINE_AMD64
+};
+
+/// Why `path` cannot be loaded into this process, if it was built for
+/// another architecture
+pub fn architecture_mismatch(path: &Path) -> Option<String> {
+    let mut headers = [0u8; 4096];
+    let len = File::open(path).and_then(|mut file| file.read(&mut headers)).ok()?;
+    let machine = pe::file_machine(&headers[..len])?;
+    if machine == PROXY_MACHINE {
+        return None;
+    }
+    let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+    Some(format!(
+        "{} is built for {}, but the game and this proxy are {}. Use the game's own {} build of it, or a proxy built for {}.",
+        name,
+        pe::machine_name(machine),
+        pe::machine_name(PROXY_MACHINE),
+        pe::machine_name(PROXY_MACHINE),
+        pe::machine_name(machine)
+    ))
+}
+
+/// The original's path; relative paths are resolved against the proxy's
+/// directory when the DLL load directory is searched, which needs an
+/// absolute path
+unsafe fn original_path(config: &ProxyConfig) -> PathBuf {
+    let path = config.original_dll_path.clone();
+    if config.search_flags & os::LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR == 0 || path.is_absolute() {
+        return path;
+    }
+
+    match get_proxy_module_path().as_deref().and_then(Path::parent) {
+        Some(directory) => directory.join(path),
+        None => path,
+    }
+}
+
 /// Forward DllMain call to the original DLL
 pub unsafe fn forward_dllmain(
     hinst_dll: HINSTANCE,
@@ -89,89 +320,229 @@ pub unsafe fn forward_dllmain(
     lpv_reserved: LPVOID,
     config: &ProxyConfig,
 ) -> BOOL {
-    // Pre-hook: called before forwarding to original
-    if config.enable_pre_hook {
-        if let Some(result) = pre_dllmain_hook(hinst_dll, fdw_reason, lpv_reserved) {
-            return result;
-        }
+    let span = tracing::info_span!("DllMain", reason = fdw_reason, reserved = ?lpv_reserved);
+    let _enter = span.enter();
+
+    // Pre-hooks: the first to return a result replaces the original's
+    let overridden = PRE_DLLMAIN_HOOKS
+        .read()
+        .unwrap()
+        .iter()
+        .find_map(|hook| hook(hinst_dll, fdw_reason, lpv_reserved));
+    if let Some(result) = overridden {
+        return result;
     }
 
     // Forward to original DllMain
-    let result = if let Some(original_dllmain) = ORIGINAL_DLLMAIN {
+    let dllmain = ORIGINAL_DLLMAIN.load(Ordering::SeqCst);
+    let result = if dllmain != 0 {
+        let original_dllmain = std::mem::transmute::<usize, DllMainFn>(dllmain);
         if config.enable_logging {
-            log::debug!(
+            tracing::debug!(
                 "[reflex-proxy] Forwarding DllMain(reason={}) to original",
                 fdw_reason
             );
         }
-        original_dllmain(hinst_dll, fdw_reason, lpv_reserved)
+        count_forward(fdw_reason);
+        let watch = watchdog::begin(&format!("DllMain(reason={})", fdw_reason));
+        let result = original_dllmain(hinst_dll, fdw_reason, lpv_reserved);
+        watchdog::end(watch);
+        result
+    } else if CHAINED.load(Ordering::SeqCst) {
+        TRUE
+    } else if matches!(fdw_reason, DLL_THREAD_ATTACH | DLL_THREAD_DETACH) {
+        // No original between an unload and the next load (a swap, a load
+        // retry): nothing to tell about threads, and the loader ignores
+        // the result
+        TRUE
     } else {
+        status::record_error(ErrorKind::Forward);
         if config.enable_logging {
-            log::error!("[reflex-proxy] Original DllMain not initialized!");
+            tracing::error!("[reflex-proxy] Original DllMain not initialized!");
         }
         FALSE
     };
 
-    // Post-hook: called after forwarding to original
-    if config.enable_post_hook {
-        post_dllmain_hook(hinst_dll, fdw_reason, lpv_reserved, result);
+    // Post-hooks: called after forwarding to original
+    for hook in POST_DLLMAIN_HOOKS.read().unwrap().iter() {
+        hook(hinst_dll, fdw_reason, lpv_reserved, result);
     }
 
     result
 }
 
-/// Pre-hook: called before forwarding to original DllMain
-/// Return Some(BOOL) to override the call, None to continue forwarding
-fn pre_dllmain_hook(
-    _hinst_dll: HINSTANCE,
-    fdw_reason: DWORD,
-    _lpv_reserved: LPVOID,
-) -> Option<BOOL> {
-    match fdw_reason {
-        DLL_PROCESS_ATTACH => {
-            log::info!("[reflex-proxy] Pre-hook: DLL_PROCESS_ATTACH");
-            // Add custom initialization here
-            // Return Some(TRUE) to skip original DllMain
-            // Return None to continue to original
-        }
-        DLL_PROCESS_DETACH => {
-            log::info!("[reflex-proxy] Pre-hook: DLL_PROCESS_DETACH");
-            // Add custom cleanup here
-        }
-        _ => {}
+/// Whether a module with this path is loaded
+unsafe fn is_loaded(path: &Path) -> bool {
+    let wide = to_wide(path);
+    !GetModuleHandleW(wide.as_ptr()).is_null()
+}
+
+fn count_forward(reason: DWORD) {
+    if let Some(counter) = FORWARDED.lock().unwrap().get_mut(reason as usize) {
+        let now = SystemTime::now();
+        counter.count += 1;
+        counter.first.get_or_insert(now);
+        counter.last = Some(now);
     }
-    None // Continue to original
 }
 
-/// Post-hook: called after forwarding to original DllMain
-fn post_dllmain_hook(
-    _hinst_dll: HINSTANCE,
-    fdw_reason: DWORD,
-    _lpv_reserved: LPVOID,
-    result: BOOL,
-) {
-    match fdw_reason {
-        DLL_PROCESS_ATTACH => {
-            log::info!(
-                "[reflex-proxy] Post-hook: DLL_PROCESS_ATTACH completed with result={}",
-                result
-            );
-            // Add custom post-initialization here
-        }
-        DLL_PROCESS_DETACH => {
-            log::info!(
-                "[reflex-proxy] Post-hook: DLL_PROCESS_DETACH completed with result={}",
-                result
-            );
-            // Add custom post-cleanup here
-        }
-        _ => {}
+/// DllMain calls forwarded so far, per reason in lifecycle order
+///
+/// THREAD_ATTACH minus THREAD_DETACH is the number of threads started since
+/// the original was loaded that are still running.
+pub fn dllmain_stats() -> Vec<ReasonStats> {
+    let forwarded = *FORWARDED.lock().unwrap();
+    [DLL_PROCESS_ATTACH, DLL_THREAD_ATTACH, DLL_THREAD_DETACH, DLL_PROCESS_DETACH]
+        .into_iter()
+        .map(|reason| {
+            let counter = forwarded[reason as usize];
+            let format = |time: SystemTime| humantime::format_rfc3339_millis(time).to_string();
+            ReasonStats {
+                reason: reason_name(reason),
+                count: counter.count,
+                first: counter.first.map(format),
+                last: counter.last.map(format),
+            }
+        })
+        .collect()
+}
+
+/// Name of a DllMain reason, e.g. "DLL_THREAD_ATTACH"
+pub fn reason_name(reason: DWORD) -> &'static str {
+    match reason {
+        DLL_PROCESS_ATTACH => "DLL_PROCESS_ATTACH",
+        DLL_PROCESS_DETACH => "DLL_PROCESS_DETACH",
+        DLL_THREAD_ATTACH => "DLL_THREAD_ATTACH",
+        DLL_THREAD_DETACH => "DLL_THREAD_DETACH",
+        _ => "unknown reason",
+    }
+}
+
+/// Run `hook` before every DllMain call forwarded to the original, after
+/// the hooks registered earlier
+///
+/// Registered during attach before the original is forwarded to (e.g. from
+/// `detours::initialize_detours`), it also sees DLL_PROCESS_ATTACH. Hooks
+/// must not register hooks themselves.
+pub fn add_pre_dllmain_hook<F>(hook: F)
+where
+    F: Fn(HINSTANCE, DWORD, LPVOID) -> Option<BOOL> + Send + Sync + 'static,
+{
+    PRE_DLLMAIN_HOOKS.write().unwrap().push(Box::new(hook));
+}
+
+/// Run `hook` after every DllMain call forwarded to the original
+pub fn add_post_dllmain_hook<F>(hook: F)
+where
+    F: Fn(HINSTANCE, DWORD, LPVOID, BOOL) + Send + Sync + 'static,
+{
+    POST_DLLMAIN_HOOKS.write().unwrap().push(Box::new(hook));
+}
+
+/// Release the original DLL handle
+///
+/// Must only be called after the last call has been forwarded to the original.
+pub unsafe fn release_original_dll() {
+    // Cleared before the image goes away, so readers see no original
+    // rather than an unmapped one
+    let original = ORIGINAL_DLL.swap(std::ptr::null_mut(), Ordering::SeqCst);
+    if original.is_null() {
+        return;
+    }
+
+    ORIGINAL_DLLMAIN.store(0, Ordering::SeqCst);
+    CHAINED.store(false, Ordering::SeqCst);
+    EXPORTS.clear();
+    if MANUALLY_MAPPED.swap(false, Ordering::SeqCst) {
+        manual_map::unload(original as usize);
+        tracing::info!("[reflex-proxy] Unmapped original DLL");
+    } else if FreeLibrary(original) == 0 {
+        tracing::warn!("[reflex-proxy] FreeLibrary failed for original DLL");
+    } else {
+        tracing::info!("[reflex-proxy] Released original DLL");
     }
 }
 
+/// Whether the loaded DLL is the next proxy of a chain
+pub unsafe fn is_chained() -> bool {
+    CHAINED.load(Ordering::SeqCst)
+}
+
 /// Get the base address of the original loaded DLL
 pub unsafe fn get_original_dll_base() -> HMODULE {
-    ORIGINAL_DLL
+    ORIGINAL_DLL.load(Ordering::SeqCst)
+}
+
+/// Is `address` inside the mapped image of the original DLL?
+pub unsafe fn is_in_original(address: usize) -> bool {
+    let base = get_original_dll_base() as usize;
+    if base == 0 {
+        return false;
+    }
+
+    match pe::image_size(base as *const u8) {
+        Some(size) => address >= base && address < base + size as usize,
+        None => false,
+    }
+}
+
+/// Was the current call made, directly or through system DLLs, by code of
+/// the original DLL?
+///
+/// Walks up to `MAX_CALLER_FRAMES` return addresses of the current stack.
+pub unsafe fn called_from_original() -> bool {
+    const MAX_CALLER_FRAMES: usize = 32;
+
+    let mut frames = [std::ptr::null_mut(); MAX_CALLER_FRAMES];
+    let captured = RtlCaptureStackBackTrace(
+        1,
+        MAX_CALLER_FRAMES as DWORD,
+        frames.as_mut_ptr(),
+        std::ptr::null_mut(),
+    ) as usize;
+
+    frames[..captured].iter().any(|&frame| is_in_original(frame as usize))
+}
+
+/// Get the full on-disk path of a loaded module
+pub unsafe fn get_module_path(module: HMODULE) -> Option<PathBuf> {
+    let mut buffer = vec![0u16; 32768];
+    let len = GetModuleFileNameW(module, buffer.as_mut_ptr(), buffer.len() as DWORD) as usize;
+    if len == 0 || len >= buffer.len() {
+        return None;
+    }
+
+    Some(PathBuf::from(OsString::from_wide(&buffer[..len])))
+}
+
+/// Get the file name of a loaded module
+pub unsafe fn get_module_name(module: HMODULE) -> Option<String> {
+    get_module_path(module)
+        .as_deref()
+        .and_then(Path::file_name)
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Get the module containing `address`, without taking a reference on it
+pub unsafe fn module_at(address: usize) -> Option<HMODULE> {
+    let mut module: HMODULE = std::ptr::null_mut();
+    let flags = GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT;
+    if GetModuleHandleExW(flags, address as *const u16, &mut module) == 0 {
+        return None;
+    }
+
+    Some(module)
+}
+
+/// Get the module handle of this proxy DLL
+pub unsafe fn get_proxy_module() -> Option<HMODULE> {
+    // Any address inside this DLL identifies our own module
+    module_at(get_proxy_module as *const () as usize)
+}
+
+/// Get the full on-disk path of this proxy DLL
+pub unsafe fn get_proxy_module_path() -> Option<PathBuf> {
+    get_module_path(get_proxy_module()?)
 }
 
 /// Resolve an internal function address by offset from the original DLL base
@@ -180,28 +551,58 @@ pub unsafe fn get_original_dll_base() -> HMODULE {
 /// This is highly unsafe and depends on the exact binary layout.
 /// Use only if you know the exact offset from reverse engineering.
 pub unsafe fn resolve_internal_function<F>(offset: usize) -> Option<F> {
-    if ORIGINAL_DLL.is_null() {
+    let base = get_original_dll_base() as usize;
+    if base == 0 {
         return None;
     }
 
-    let base = ORIGINAL_DLL as usize;
     let func_addr = base + offset;
 
     Some(std::mem::transmute_copy(&func_addr))
 }
 
 /// Get an exported function from the original DLL by name
+///
+/// Each name is resolved once (export_cache.rs).
 pub unsafe fn get_original_export<F>(name: &str) -> Option<F> {
-    if ORIGINAL_DLL.is_null() {
+    if get_original_dll_base().is_null() {
         return None;
     }
 
-    let name_cstr = CString::new(name).ok()?;
-    let func_addr = GetProcAddress(ORIGINAL_DLL, name_cstr.as_ptr());
+    let func_addr = EXPORTS.resolve(name, |name| unsafe { resolve_export(name) })?;
+    Some(std::mem::transmute_copy(&func_addr))
+}
 
-    if func_addr.is_null() {
-        return None;
+unsafe fn resolve_export(name: &str) -> Option<usize> {
+    let base = get_original_dll_base() as usize;
+    if MANUALLY_MAPPED.load(Ordering::SeqCst) {
+        // Not known to the loader; forwarded exports are not followed
+        let export = pe::exports(base as *const u8)
+            .into_iter()
+            .find(|e| e.forwarder.is_none() && e.name.as_deref() == Some(name))?;
+        Some(base + export.rva as usize)
+    } else {
+        os::system().get_proc_address(base, name)
     }
+}
 
-    Some(std::mem::transmute_copy(&func_addr))
+/// Resolve every named export of the original now instead of on first
+/// use; forwarded exports are still resolved on first use. Returns the
+/// number of names cached.
+pub unsafe fn preload_exports() -> usize {
+    let base = get_original_dll_base() as usize;
+    if base == 0 {
+        return 0;
+    }
+    EXPORTS.preload(
+        pe::exports(base as *const u8)
+            .into_iter()
+            .filter(|export| export.forwarder.is_none())
+            .filter_map(|export| Some((export.name?, base + export.rva as usize))),
+    )
+}
+
+/// Hits and misses of the export cache
+pub fn export_cache_stats() -> ExportCacheStats {
+    EXPORTS.stats()
 }
@@ -0,0 +1,58 @@
+/// QueryPerformanceCounter hook for `[qpc_jitter]`
+///
+/// Hooks the QueryPerformanceCounter import of reflex_original.dll, so only
+/// the original's readings are perturbed (qpc_jitter.rs); the game and the
+/// proxy keep the real clock. Readings the original takes without the
+/// import (RtlQueryPerformanceCounter, rdtsc, GetProcAddress) are not
+/// perturbed. During passthrough the hook is suspended like every other.
+
+use crate::proxy;
+use crate::proxy_impl::config::QpcJitterConfig;
+use crate::proxy_impl::hooks::declare_hook;
+use crate::proxy_impl::qpc_jitter;
+use std::time::{SystemTime, UNIX_EPOCH};
+use winapi::shared::minwindef::BOOL;
+use winapi::um::profileapi::QueryPerformanceFrequency;
+use winapi::um::winnt::LARGE_INTEGER;
+
+declare_hook! {
+    fn QueryPerformanceCounter(count: *mut LARGE_INTEGER) -> BOOL {
+        let result = call_original(count);
+        if result != 0 && !count.is_null() {
+            let perturbed = qpc_jitter::perturb(*(*count).QuadPart());
+            *(*count).QuadPart_mut() = perturbed;
+        }
+        result
+    }
+}
+
+/// Start the session and hook the original's QueryPerformanceCounter import
+pub unsafe fn initialize(config: &QpcJitterConfig) -> Result<(), String> {
+    let base = proxy::get_original_dll_base() as *const u8;
+    if base.is_null() {
+        return Err("Original DLL is not loaded".to_string());
+    }
+
+    let mut frequency: LARGE_INTEGER = std::mem::zeroed();
+    QueryPerformanceFrequency(&mut frequency);
+    let frequency = *frequency.QuadPart();
+    let seed = match config.seed {
+        0 => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(1),
+        seed => seed,
+    };
+
+    qpc_jitter::start(config, frequency, seed)?;
+    if let Err(e) = QueryPerformanceCounter::install_iat(base) {
+        qpc_jitter::finish();
+        return Err(e);
+    }
+    tracing::info!(
+        "[qpc_jitter] Perturbing the original's QueryPerformanceCounter ({} Hz, seed {})",
+        frequency,
+        seed
+    );
+    Ok(())
+}
@@ -0,0 +1,277 @@
+/// Perturbed QueryPerformanceCounter readings
+///
+/// A robustness test of the original DLL's timing code: with
+/// `[qpc_jitter] enabled = true`, every QueryPerformanceCounter reading it
+/// takes (qpc_hook.rs) is offset by
+/// - jitter: uniform in ±`jitter_us`
+/// - drift: `drift_ppm` of the time since the first reading, so the
+///   original's clock slowly runs fast or slow
+/// - backward steps: with `backstep_chance`, the reading lands
+///   `backstep_us` behind the previous one it returned
+///
+/// ```toml
+/// [qpc_jitter]
+/// enabled = true
+/// jitter_us = 50.0
+/// drift_ppm = 200.0
+/// backstep_chance = 0.001
+/// backstep_us = 500.0
+/// seed = 0                  # 0 = new per session, logged to repeat a run
+/// log_path = "reflex_qpc.jsonl"
+/// ```
+///
+/// Every injected delta is written to `log_path`, one JSON object per
+/// reading, and a summary is logged on detach. The same seed gives the
+/// same deltas for the same sequence of readings.
+///
+/// Independent of the host platform.
+
+use crate::proxy_impl::config::QpcJitterConfig;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Mutex;
+
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+
+/// One perturbed reading; components in counter ticks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Delta {
+    /// Number of the reading, from 1
+    pub call: u64,
+    /// The real counter value
+    pub qpc: i64,
+    pub jitter: i64,
+    pub drift: i64,
+    /// Set on a backward step, replacing the reading with one behind the
+    /// previous result
+    pub backstep: i64,
+    /// The value returned
+    pub result: i64,
+}
+
+/// Check a profile before it is used
+pub fn validate(config: &QpcJitterConfig) -> Result<(), String> {
+    if !(0.0..=1.0).contains(&config.backstep_chance) {
+        return Err(format!("backstep_chance {} is not between 0 and 1", config.backstep_chance));
+    }
+    if config.jitter_us < 0.0 || config.backstep_us < 0.0 {
+        return Err("jitter_us and backstep_us cannot be negative".to_string());
+    }
+    Ok(())
+}
+
+/// Applies a profile to successive readings
+#[derive(Debug)]
+pub struct Perturbation {
+    /// Ticks per microsecond
+    ticks_per_us: f64,
+    jitter_us: f64,
+    drift_ppm: f64,
+    backstep_chance: f64,
+    backstep_us: f64,
+    /// xorshift64* state, never 0
+    rng: u64,
+    start: Option<i64>,
+    last: Option<i64>,
+    calls: u64,
+    backsteps: u64,
+    smallest: i64,
+    largest: i64,
+}
+
+impl Perturbation {
+    /// A profile for a counter running at `frequency` ticks per second
+    pub fn new(config: &QpcJitterConfig, frequency: i64, seed: u64) -> Self {
+        Self {
+            ticks_per_us: frequency as f64 / 1e6,
+            jitter_us: config.jitter_us,
+            drift_ppm: config.drift_ppm,
+            backstep_chance: config.backstep_chance,
+            backstep_us: config.backstep_us,
+            rng: seed.max(1),
+            start: None,
+            last: None,
+            calls: 0,
+            backsteps: 0,
+            smallest: 0,
+            largest: 0,
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in [0, 1)
+    fn next_unit(&mut self) -> f64 {
+        (self.next_random() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Perturb the real reading `qpc`
+    pub fn perturb(&mut self, qpc: i64) -> Delta {
+        self.calls += 1;
+        let start = *self.start.get_or_insert(qpc);
+
+        let jitter = match self.jitter_us > 0.0 {
+            true => ((self.next_unit() * 2.0 - 1.0) * self.jitter_us * self.ticks_per_us) as i64,
+            false => 0,
+        };
+        let drift = ((qpc - start) as f64 * self.drift_ppm / 1e6) as i64;
+        let mut result = qpc + jitter + drift;
+
+        let mut backstep = 0;
+        if let Some(last) = self.last {
+            if self.backstep_chance > 0.0 && self.next_unit() < self.backstep_chance {
+                let behind = last - (self.backstep_us * self.ticks_per_us) as i64;
+                backstep = behind - result;
+                result = behind;
+                self.backsteps += 1;
+            }
+        }
+        self.last = Some(result);
+
+        let total = result - qpc;
+        self.smallest = self.smallest.min(total);
+        self.largest = self.largest.max(total);
+        Delta {
+            call: self.calls,
+            qpc,
+            jitter,
+            drift,
+            backstep,
+            result,
+        }
+    }
+
+    /// One line for the log
+    pub fn line(&self) -> String {
+        format!(
+            "{} reading(s) perturbed by {} to {} tick(s), {} backward step(s)",
+            self.calls, self.smallest, self.largest, self.backsteps
+        )
+    }
+}
+
+struct Session {
+    perturbation: Perturbation,
+    log: Option<BufWriter<File>>,
+}
+
+/// Start perturbing readings of a counter at `frequency`; `log_path` is
+/// created, or nothing is written if it is empty
+pub fn start(config: &QpcJitterConfig, frequency: i64, seed: u64) -> Result<(), String> {
+    validate(config)?;
+    let log = match config.log_path.as_str() {
+        "" => None,
+        path => Some(BufWriter::new(
+            File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?,
+        )),
+    };
+    *SESSION.lock().unwrap() = Some(Session {
+        perturbation: Perturbation::new(config, frequency, seed),
+        log,
+    });
+    Ok(())
+}
+
+/// The value to return for the real reading `qpc`
+pub fn perturb(qpc: i64) -> i64 {
+    let mut session = SESSION.lock().unwrap();
+    let session = match session.as_mut() {
+        Some(session) => session,
+        None => return qpc,
+    };
+    let delta = session.perturbation.perturb(qpc);
+    if let Some(log) = &mut session.log {
+        let written = serde_json::to_writer(&mut *log, &delta).is_ok() && log.write_all(b"\n").is_ok();
+        if !written {
+            tracing::warn!("[qpc_jitter] Failed to write the delta log, no longer writing it");
+            session.log = None;
+        }
+    }
+    delta.result
+}
+
+/// Stop perturbing: log the summary and flush the delta log
+pub fn finish() {
+    let mut session = match SESSION.lock().unwrap().take() {
+        Some(session) => session,
+        None => return,
+    };
+    tracing::info!("[qpc_jitter] {}", session.perturbation.line());
+    if let Some(Err(e)) = session.log.as_mut().map(|log| log.flush()) {
+        tracing::error!("[qpc_jitter] Failed to write the delta log: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 10 MHz, the usual QPC frequency: 10 ticks per microsecond
+    const FREQUENCY: i64 = 10_000_000;
+
+    fn profile() -> QpcJitterConfig {
+        QpcJitterConfig {
+            enabled: true,
+            jitter_us: 50.0,
+            ..QpcJitterConfig::default()
+        }
+    }
+
+    #[test]
+    fn jitter_is_bounded_and_repeatable() {
+        let mut first = Perturbation::new(&profile(), FREQUENCY, 42);
+        let mut second = Perturbation::new(&profile(), FREQUENCY, 42);
+        for qpc in (0..1000).map(|i| 1_000_000 + i * 1000) {
+            let delta = first.perturb(qpc);
+            assert!(delta.jitter.abs() <= 500);
+            assert_eq!(delta.result, qpc + delta.jitter);
+            assert_eq!(second.perturb(qpc), delta);
+        }
+        let other = Perturbation::new(&profile(), FREQUENCY, 7).perturb(1_000_000);
+        assert_ne!(other, Perturbation::new(&profile(), FREQUENCY, 42).perturb(1_000_000));
+    }
+
+    #[test]
+    fn drift_grows_with_time() {
+        let config = QpcJitterConfig {
+            drift_ppm: -1000.0,
+            ..QpcJitterConfig::default()
+        };
+        let mut perturbation = Perturbation::new(&config, FREQUENCY, 1);
+        assert_eq!(perturbation.perturb(5_000).drift, 0);
+        // One second later the clock is 1 ms slow
+        let delta = perturbation.perturb(5_000 + FREQUENCY);
+        assert_eq!(delta.drift, -10_000);
+        assert_eq!(delta.result, 5_000 + FREQUENCY - 10_000);
+    }
+
+    #[test]
+    fn backward_steps_land_behind_the_previous_result() {
+        let config = QpcJitterConfig {
+            backstep_chance: 1.0,
+            backstep_us: 100.0,
+            ..QpcJitterConfig::default()
+        };
+        let mut perturbation = Perturbation::new(&config, FREQUENCY, 1);
+        // Nothing to step behind yet
+        assert_eq!(perturbation.perturb(10_000).backstep, 0);
+        let delta = perturbation.perturb(20_000);
+        assert_eq!(delta.result, 9_000);
+        assert_eq!(delta.backstep, -11_000);
+        assert_eq!(perturbation.perturb(30_000).result, 8_000);
+        assert!(perturbation.line().ends_with("2 backward step(s)"));
+
+        assert!(validate(&config).is_ok());
+        let invalid = QpcJitterConfig {
+            backstep_chance: 2.0,
+            ..QpcJitterConfig::default()
+        };
+        assert!(validate(&invalid).is_err());
+    }
+}
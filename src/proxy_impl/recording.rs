@@ -0,0 +1,543 @@
+/// Call recording for the exports of the original DLL
+///
+/// Every code export of reflex_original.dll (or the configured subset) gets
+/// an inline hook that routes it through one generic thunk. The thunk saves
+/// the argument registers, records the call, calls the original through
+/// its trampoline and records the return value. Each completed call is one
+/// JSON line in `path`:
+///
+/// ```json
+/// {"seq":1,"thread":4242,"export":"Init","args":[{"value":"0x1"},{"value":"0x7ff6...","data":"01 00 ..."}],"ret":"0x0","duration_us":12}
+/// ```
+///
+/// Arguments are raw integer registers (rcx, rdx, r8, r9) plus `stack_args`
+/// stack slots; the signature of an export is not known, so unused slots
+/// hold whatever the caller left there. Arguments that point to readable
+/// memory also carry up to `capture_bytes` bytes of it, which lets
+/// reflex_replay.exe re-create the buffers when replaying the recording
+/// against a standalone copy of the DLL.
+///
+/// ```toml
+/// [recording]
+/// enabled = true
+/// exports = []          # empty = every code export
+/// stack_args = 4
+/// capture_bytes = 64
+/// coverage_path = "reflex_coverage.json"
+/// schema = "reflex_schema.toml"
+/// ```
+///
+/// With `schema` set, arguments of the exports it describes (schema.rs)
+/// also carry their parameter name and decoded value:
+///
+/// ```json
+/// {"value":"0x7ff6...","name":"path","decoded":"C:\\game\\reflex.cfg","data":"43 00 ..."}
+/// ```
+///
+/// The calls of every hooked export are also counted and timed (the
+/// `/metrics` latency histograms, metrics.rs). On detach (or with
+/// the `coverage` control command) `coverage_path` gets a summary of which
+/// exports were called how often and which never were, to prioritize
+/// reverse engineering. With `path = ""` nothing but the counts is kept.
+///
+/// Exports in `dry_run` are not forwarded at all: the call is recorded and
+/// logged with its arguments, and the caller gets the configured value (in
+/// rax) instead. Dropping exports one by one shows which the game can do
+/// without. The `dryrun` control command switches exports at runtime:
+///
+/// ```toml
+/// [recording]
+/// enabled = true
+/// dry_run = { SendTelemetry = 0, CheckLicense = 1 }
+/// ```
+///
+/// Limitations: floating-point arguments and return values are passed
+/// through but not recorded, at most `MAX_STACK_ARGS` stack arguments are
+/// forwarded, and the thunk has no unwind data, so an exception thrown
+/// through a recorded export terminates the process. x64 only.
+
+use crate::proxy;
+use crate::proxy_impl::config::RecordingConfig;
+use crate::proxy_impl::exposition::{Histogram, HistogramSnapshot};
+use crate::proxy_impl::schema::{self, ExportSchema};
+use crate::proxy_impl::{hooks, patches, pe, trampoline, watchdog};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use winapi::shared::minwindef::LPVOID;
+use winapi::um::memoryapi::{VirtualAlloc, VirtualQuery};
+use winapi::um::processthreadsapi::GetCurrentThreadId;
+use winapi::um::winnt::{
+    MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_RESERVE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE,
+    PAGE_EXECUTE_WRITECOPY, PAGE_GUARD, PAGE_READONLY, PAGE_READWRITE, PAGE_WRITECOPY,
+};
+
+/// Stack arguments copied to the original; the thunk forwards this many
+/// regardless of the real signature
+pub const MAX_STACK_ARGS: usize = 12;
+/// `mov r10, imm64; jmp qword ptr [rip+0]; dq thunk`
+const STUB_SIZE: usize = 24;
+
+/// Per-export data handed to the thunk in r10
+#[repr(C)]
+struct ExportSlot {
+    /// Index into `Recorder::exports`
+    index: usize,
+    /// Trampoline to the original export; read by the thunk at offset 8
+    original: AtomicUsize,
+    /// Return `dry_run_value` instead of calling the original; read by the
+    /// thunk at offset 16
+    dry_run: AtomicBool,
+    /// Read by the thunk at offset 24
+    dry_run_value: AtomicU64,
+}
+
+/// A recorded argument
+#[derive(Serialize)]
+struct Argument {
+    value: String,
+    /// Parameter name from the schema
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// Value decoded with the schema
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decoded: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CallRecord<'a> {
+    seq: u64,
+    thread: u32,
+    export: &'a str,
+    args: Vec<Argument>,
+    ret: String,
+    duration_us: u128,
+    /// Not forwarded to the original
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    dry_run: bool,
+}
+
+/// A call in progress on the current thread
+struct PendingCall {
+    seq: u64,
+    export: usize,
+    args: Vec<Argument>,
+    started: Instant,
+    /// Watchdog registration of the call
+    watch: u64,
+    dry_run: bool,
+}
+
+struct Recorder {
+    exports: Vec<String>,
+    /// Thunk data per entry of `exports`
+    slots: Vec<&'static ExportSlot>,
+    /// Calls per entry of `exports`
+    calls: Vec<AtomicU64>,
+    /// Duration of the completed calls per entry of `exports`
+    latency: Vec<Histogram>,
+    /// Parameters per entry of `exports`, if the schema describes it
+    schemas: Vec<Option<ExportSchema>>,
+    /// None when only calls are counted
+    output: Option<Mutex<BufWriter<File>>>,
+    coverage_path: String,
+    stack_args: usize,
+    capture_bytes: usize,
+}
+
+static RECORDER: OnceCell<Recorder> = OnceCell::new();
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static PENDING: RefCell<Vec<PendingCall>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Hook the exports of the original DLL and start recording
+///
+/// Returns the number of exports hooked.
+pub unsafe fn initialize(config: &RecordingConfig) -> Result<usize, String> {
+    let base = proxy::get_original_dll_base() as *const u8;
+    if base.is_null() {
+        return Err("original DLL is not loaded".to_string());
+    }
+
+    let output = match config.path.as_str() {
+        "" => None,
+        path => {
+            let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+            Some(Mutex::new(BufWriter::new(file)))
+        }
+    };
+
+    let targets: Vec<(String, usize)> = pe::exports(base)
+        .into_iter()
+        .filter(|e| e.forwarder.is_none())
+        .filter(|e| pe::section_for_rva(base, e.rva).is_some_and(|s| s.is_executable()))
+        .filter_map(|e| Some((e.name?, base as usize + e.rva as usize)))
+        .filter(|(name, _)| name != "DllMain")
+        .filter(|(name, _)| {
+            config.exports.is_empty() || config.exports.contains(name) || config.dry_run.contains_key(name)
+        })
+        .collect();
+
+    let mut schema = if config.schema.is_empty() {
+        schema::Schema::new()
+    } else {
+        load_schema(&config.schema).unwrap_or_else(|e| {
+            tracing::warn!("[recording] Arguments are not decoded: {}", e);
+            schema::Schema::new()
+        })
+    };
+
+    let slots: Vec<&'static ExportSlot> = targets
+        .iter()
+        .enumerate()
+        .map(|(index, (name, _))| {
+            let dry_run = config.dry_run.get(name);
+            &*Box::leak(Box::new(ExportSlot {
+                index,
+                original: AtomicUsize::new(0),
+                dry_run: AtomicBool::new(dry_run.is_some()),
+                dry_run_value: AtomicU64::new(dry_run.copied().unwrap_or(0) as u64),
+            }))
+        })
+        .collect();
+
+    let recorder = Recorder {
+        exports: targets.iter().map(|(name, _)| name.clone()).collect(),
+        slots: slots.clone(),
+        calls: targets.iter().map(|_| AtomicU64::new(0)).collect(),
+        latency: targets.iter().map(|_| Histogram::default()).collect(),
+        schemas: targets.iter().map(|(name, _)| schema.remove(name)).collect(),
+        output,
+        coverage_path: config.coverage_path.clone(),
+        stack_args: config.stack_args.min(MAX_STACK_ARGS),
+        capture_bytes: config.capture_bytes,
+    };
+    if RECORDER.set(recorder).is_err() {
+        return Err("recording is already initialized".to_string());
+    }
+
+    let mut hooked = 0;
+    for ((name, address), slot) in targets.iter().zip(slots) {
+        let stub = build_stub(slot)?;
+
+        match hooks::install_inline_hook_into(&format!("record!{}", name), *address, stub, &slot.original) {
+            Ok(()) => hooked += 1,
+            Err(e) => tracing::warn!("[recording] Not recording {}: {}", name, e),
+        }
+    }
+
+    for (name, value) in &config.dry_run {
+        tracing::warn!("[recording] Dry run: {} is not forwarded and returns 0x{:x}", name, value);
+    }
+    if config.path.is_empty() {
+        tracing::info!("[recording] Counting calls of {} export(s)", hooked);
+    } else {
+        tracing::info!("[recording] Recording {} export(s) to {}", hooked, config.path);
+    }
+    Ok(hooked)
+}
+
+/// Stop forwarding calls of a recorded export, answering them with
+/// `value` instead, or forward them again (`None`)
+pub fn set_dry_run(export: &str, value: Option<i64>) -> Result<(), String> {
+    let recorder = RECORDER.get().ok_or("recording is not enabled")?;
+    let index = recorder
+        .exports
+        .iter()
+        .position(|name| name == export)
+        .ok_or_else(|| format!("{} is not recorded", export))?;
+
+    let slot = recorder.slots[index];
+    slot.dry_run_value.store(value.unwrap_or(0) as u64, Ordering::SeqCst);
+    slot.dry_run.store(value.is_some(), Ordering::SeqCst);
+    match value {
+        Some(value) => tracing::warn!("[recording] Dry run: {} is not forwarded and returns 0x{:x}", export, value),
+        None => tracing::info!("[recording] {} is forwarded again", export),
+    }
+    Ok(())
+}
+
+/// Flush recorded calls to disk
+pub fn flush() {
+    if let Some(output) = RECORDER.get().and_then(|r| r.output.as_ref()) {
+        let _ = output.lock().unwrap().flush();
+    }
+}
+
+/// Calls of each hooked export so far
+pub fn call_counts() -> Vec<(String, u64)> {
+    RECORDER.get().map_or_else(Vec::new, |recorder| {
+        recorder
+            .exports
+            .iter()
+            .zip(&recorder.calls)
+            .map(|(name, calls)| (name.clone(), calls.load(Ordering::Relaxed)))
+            .collect()
+    })
+}
+
+/// Calls and call latency of each hooked export so far
+pub fn call_stats() -> Vec<(String, u64, HistogramSnapshot)> {
+    RECORDER.get().map_or_else(Vec::new, |recorder| {
+        recorder
+            .exports
+            .iter()
+            .zip(&recorder.calls)
+            .zip(&recorder.latency)
+            .map(|((name, calls), latency)| (name.clone(), calls.load(Ordering::Relaxed), latency.snapshot()))
+            .collect()
+    })
+}
+
+/// Write the export coverage report; returns the path and the number of
+/// exports called and hooked
+pub fn write_coverage() -> Result<(String, usize, usize), String> {
+    let recorder = RECORDER.get().ok_or("recording is not enabled")?;
+    if recorder.coverage_path.is_empty() {
+        return Err("no coverage_path configured".to_string());
+    }
+
+    let mut counts = call_counts();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let (called, never_called): (Vec<_>, Vec<_>) = counts.into_iter().partition(|&(_, count)| count > 0);
+
+    let report = json!({
+        "exports": recorder.exports.len(),
+        "called": called.len(),
+        "calls": called.iter().map(|(name, count)| json!({ "export": name, "count": count })).collect::<Vec<_>>(),
+        "never_called": never_called.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+    });
+    let json = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+    fs::write(&recorder.coverage_path, json)
+        .map_err(|e| format!("Failed to write {}: {}", recorder.coverage_path, e))?;
+
+    for (name, count) in called.iter().take(10) {
+        tracing::info!("[recording] {:>8} call(s) {}", count, name);
+    }
+    Ok((recorder.coverage_path.clone(), called.len(), recorder.exports.len()))
+}
+
+fn load_schema(path: &str) -> Result<schema::Schema, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let schema = schema::parse(&text).map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+    tracing::info!("[recording] Loaded parameter schema of {} export(s) from {}", schema.len(), path);
+    Ok(schema)
+}
+
+/// `mov r10, slot; jmp [rip+0]; dq record_thunk` in executable memory
+unsafe fn build_stub(slot: &'static ExportSlot) -> Result<usize, String> {
+    let memory = VirtualAlloc(
+        std::ptr::null_mut(),
+        STUB_SIZE,
+        MEM_COMMIT | MEM_RESERVE,
+        PAGE_EXECUTE_READWRITE,
+    ) as *mut u8;
+    if memory.is_null() {
+        return Err("VirtualAlloc failed for a recording stub".to_string());
+    }
+
+    let mut code = [0u8; STUB_SIZE];
+    code[0] = 0x49;
+    code[1] = 0xBA;
+    code[2..10].copy_from_slice(&(slot as *const ExportSlot as u64).to_le_bytes());
+    code[10..].copy_from_slice(&trampoline::encode_abs_jump(record_thunk as *const () as usize));
+    std::ptr::copy_nonoverlapping(code.as_ptr(), memory, STUB_SIZE);
+    Ok(memory as usize)
+}
+
+// ============================================================================
+// Thunk
+// ============================================================================
+
+// Frame (0xF8 bytes, keeps rsp 16-byte aligned for the calls):
+//   0x00  shadow space
+//   0x20  stack arguments for the original (MAX_STACK_ARGS qwords)
+//   0x80  rcx, rdx, r8, r9    (argument registers, later rax)
+//   0xA0  r10                 (ExportSlot)
+//   0xA8  call id from record_enter
+//   0xB0  xmm0-xmm3
+// The caller's stack arguments start at rsp + 0xF8 + 8 + 0x20 = rsp + 0x120.
+#[unsafe(naked)]
+unsafe extern "system" fn record_thunk() {
+    std::arch::naked_asm!(
+        "sub rsp, 0xF8",
+        "mov [rsp+0x80], rcx",
+        "mov [rsp+0x88], rdx",
+        "mov [rsp+0x90], r8",
+        "mov [rsp+0x98], r9",
+        "mov [rsp+0xA0], r10",
+        "movdqu [rsp+0xB0], xmm0",
+        "movdqu [rsp+0xC0], xmm1",
+        "movdqu [rsp+0xD0], xmm2",
+        "movdqu [rsp+0xE0], xmm3",
+        "mov rcx, r10",
+        "lea rdx, [rsp+0x80]",
+        "lea r8, [rsp+0x120]",
+        "call {enter}",
+        "mov [rsp+0xA8], rax",
+        "xor eax, eax",
+        "2:",
+        "mov r10, [rsp+0x120+rax*8]",
+        "mov [rsp+0x20+rax*8], r10",
+        "inc eax",
+        "cmp eax, {stack_args}",
+        "jb 2b",
+        "mov rcx, [rsp+0x80]",
+        "mov rdx, [rsp+0x88]",
+        "mov r8, [rsp+0x90]",
+        "mov r9, [rsp+0x98]",
+        "movdqu xmm0, [rsp+0xB0]",
+        "movdqu xmm1, [rsp+0xC0]",
+        "movdqu xmm2, [rsp+0xD0]",
+        "movdqu xmm3, [rsp+0xE0]",
+        "mov r10, [rsp+0xA0]",
+        "cmp byte ptr [r10+16], 0",
+        "jne 3f",
+        "call qword ptr [r10+8]",
+        "jmp 4f",
+        "3:",
+        "mov rax, [r10+24]",
+        "4:",
+        "mov [rsp+0x80], rax",
+        "movdqu [rsp+0xB0], xmm0",
+        "mov rcx, [rsp+0xA8]",
+        "mov rdx, rax",
+        "call {exit}",
+        "mov rax, [rsp+0x80]",
+        "movdqu xmm0, [rsp+0xB0]",
+        "add rsp, 0xF8",
+        "ret",
+        enter = sym record_enter,
+        exit = sym record_exit,
+        stack_args = const MAX_STACK_ARGS,
+    );
+}
+
+/// Called by the thunk before the original; returns the call id
+unsafe extern "system" fn record_enter(slot: *const ExportSlot, registers: *const u64, stack: *const u64) -> u64 {
+    let recorder = match RECORDER.get() {
+        Some(recorder) => recorder,
+        None => return 0,
+    };
+
+    recorder.calls[(*slot).index].fetch_add(1, Ordering::Relaxed);
+    let dry_run = (*slot).dry_run.load(Ordering::SeqCst);
+
+    let args = if recorder.output.is_some() || dry_run {
+        let registers = std::slice::from_raw_parts(registers, 4);
+        let stack = std::slice::from_raw_parts(stack, recorder.stack_args);
+        let params = recorder.schemas[(*slot).index].as_ref().map_or(&[][..], |s| &s.params[..]);
+        registers
+            .iter()
+            .chain(stack)
+            .enumerate()
+            .map(|(i, &value)| Argument {
+                value: format!("0x{:x}", value),
+                name: params.get(i).map(|p| p.name.clone()),
+                decoded: params.get(i).map(|p| schema::decode(p, value, &|address, len| unsafe { readable(address, len) })),
+                data: readable(value as usize, recorder.capture_bytes).map(|bytes| patches::hex(&bytes)),
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if dry_run {
+        tracing::warn!(
+            "[recording] Dry run: {}{} not forwarded, returning 0x{:x}",
+            recorder.exports[(*slot).index],
+            serde_json::to_string(&args).unwrap_or_default(),
+            (*slot).dry_run_value.load(Ordering::SeqCst)
+        );
+    }
+
+    let seq = SEQUENCE.fetch_add(1, Ordering::Relaxed) + 1;
+    let _ = PENDING.try_with(|pending| {
+        pending.borrow_mut().push(PendingCall {
+            seq,
+            export: (*slot).index,
+            args,
+            started: Instant::now(),
+            watch: watchdog::begin(&recorder.exports[(*slot).index]),
+            dry_run,
+        })
+    });
+    seq
+}
+
+/// Called by the thunk after the original returned
+unsafe extern "system" fn record_exit(seq: u64, ret: u64) {
+    let recorder = match RECORDER.get() {
+        Some(recorder) => recorder,
+        None => return,
+    };
+
+    let call = PENDING
+        .try_with(|pending| pending.borrow_mut().pop())
+        .ok()
+        .flatten();
+    if let Some(call) = &call {
+        watchdog::end(call.watch);
+    }
+    let call = match call {
+        Some(call) if call.seq == seq => call,
+        _ => return,
+    };
+    let duration = call.started.elapsed();
+    recorder.latency[call.export].observe(duration);
+
+    let output = match &recorder.output {
+        Some(output) => output,
+        None => return,
+    };
+
+    let record = CallRecord {
+        seq,
+        thread: GetCurrentThreadId(),
+        export: &recorder.exports[call.export],
+        args: call.args,
+        ret: format!("0x{:x}", ret),
+        duration_us: duration.as_micros(),
+        dry_run: call.dry_run,
+    };
+
+    if let Ok(line) = serde_json::to_string(&record) {
+        let mut output = output.lock().unwrap();
+        let _ = writeln!(output, "{}", line);
+    }
+}
+
+/// Up to `len` bytes at `address`, if it points to readable memory
+unsafe fn readable(address: usize, len: usize) -> Option<Vec<u8>> {
+    const READABLE: u32 = PAGE_READONLY
+        | PAGE_READWRITE
+        | PAGE_WRITECOPY
+        | PAGE_EXECUTE_READ
+        | PAGE_EXECUTE_READWRITE
+        | PAGE_EXECUTE_WRITECOPY;
+
+    if len == 0 || address < 0x10000 {
+        return None;
+    }
+
+    let mut info: MEMORY_BASIC_INFORMATION = std::mem::zeroed();
+    if VirtualQuery(address as LPVOID, &mut info, std::mem::size_of::<MEMORY_BASIC_INFORMATION>()) == 0 {
+        return None;
+    }
+    if info.State != MEM_COMMIT || info.Protect & READABLE == 0 || info.Protect & PAGE_GUARD != 0 {
+        return None;
+    }
+
+    let region_end = info.BaseAddress as usize + info.RegionSize;
+    let len = len.min(region_end - address);
+    Some(patches::read_memory(address, len))
+}
@@ -0,0 +1,270 @@
+/// Redaction of personal data from logs and traces
+///
+/// With `[logging.redact]` enabled, every log event (message, fields and
+/// span fields) and every trace span's arguments pass through `text` /
+/// `fields` before reaching a sink, so logs and traces can be shared:
+/// - `user`: the profile directory (%USERPROFILE%) and the account name
+/// - `computer`: the machine name
+/// - `guids`: anything shaped like a GUID, e.g. hardware and install ids
+/// - `patterns`: more strings, e.g. a real name or a work directory
+///
+/// ```toml
+/// [logging.redact]
+/// enabled = true
+/// mode = "hash"        # "mask": <user>; "hash": <user:3f2a91c0>
+/// patterns = ['D:\Work']
+/// ```
+///
+/// Matching ignores ASCII case, as Windows paths do. Hashes tie the
+/// occurrences of one value together without showing it; short values such
+/// as account names can still be guessed from them.
+
+use crate::proxy_impl::config::RedactConfig;
+use once_cell::sync::OnceCell;
+use serde_json::{Map, Value};
+
+static REDACTOR: OnceCell<Redactor> = OnceCell::new();
+
+/// Strings to hide and how
+#[derive(Debug)]
+pub struct Redactor {
+    /// (value, label), longest value first so a profile path goes before
+    /// the account name inside it
+    secrets: Vec<(String, &'static str)>,
+    guids: bool,
+    hash: bool,
+}
+
+impl Redactor {
+    /// `environment` holds the values of USERPROFILE, USERNAME and
+    /// COMPUTERNAME, when set
+    pub fn new(config: &RedactConfig, environment: &[(&str, Option<String>)]) -> Result<Self, String> {
+        let hash = match config.mode.as_str() {
+            "mask" => false,
+            "hash" => true,
+            other => return Err(format!("unknown mode '{}', expected \"mask\" or \"hash\"", other)),
+        };
+
+        let mut secrets = Vec::new();
+        for (variable, value) in environment {
+            let label = match *variable {
+                "USERPROFILE" if config.user => "profile",
+                "USERNAME" if config.user => "user",
+                "COMPUTERNAME" if config.computer => "computer",
+                _ => continue,
+            };
+            if let Some(value) = value.as_deref().filter(|value| !value.is_empty()) {
+                secrets.push((value.to_string(), label));
+            }
+        }
+        secrets.extend(
+            config.patterns.iter().filter(|pattern| !pattern.is_empty()).map(|pattern| (pattern.clone(), "redacted")),
+        );
+        secrets.sort_by_key(|(value, _)| std::cmp::Reverse(value.len()));
+
+        Ok(Self {
+            secrets,
+            guids: config.guids,
+            hash,
+        })
+    }
+
+    /// `text` with every secret and GUID replaced
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (secret, label) in &self.secrets {
+            text = self.replace(&text, secret, label);
+        }
+        if self.guids {
+            text = self.replace_guids(&text);
+        }
+        text
+    }
+
+    fn replace(&self, text: &str, secret: &str, label: &str) -> String {
+        let lower = text.to_ascii_lowercase();
+        let needle = secret.to_ascii_lowercase();
+        let mut result = String::with_capacity(text.len());
+        let mut rest = 0;
+        while let Some(found) = lower[rest..].find(&needle) {
+            let start = rest + found;
+            result.push_str(&text[rest..start]);
+            result.push_str(&self.placeholder(label, &needle));
+            rest = start + needle.len();
+        }
+        result.push_str(&text[rest..]);
+        result
+    }
+
+    fn replace_guids(&self, text: &str) -> String {
+        let bytes = text.as_bytes();
+        let mut result = String::with_capacity(text.len());
+        let mut rest = 0;
+        let mut i = 0;
+        while i + GUID_LEN <= bytes.len() {
+            let bounded = (i == 0 || !bytes[i - 1].is_ascii_alphanumeric())
+                && bytes.get(i + GUID_LEN).is_none_or(|b| !b.is_ascii_alphanumeric());
+            if bounded && is_guid(&bytes[i..i + GUID_LEN]) {
+                result.push_str(&text[rest..i]);
+                result.push_str(&self.placeholder("guid", &text[i..i + GUID_LEN].to_ascii_lowercase()));
+                i += GUID_LEN;
+                rest = i;
+            } else {
+                i += 1;
+            }
+        }
+        result.push_str(&text[rest..]);
+        result
+    }
+
+    fn placeholder(&self, label: &str, value: &str) -> String {
+        match self.hash {
+            true => format!("<{}:{:08x}>", label, fnv1a(value.as_bytes())),
+            false => format!("<{}>", label),
+        }
+    }
+}
+
+/// "01234567-89ab-cdef-0123-456789abcdef"
+const GUID_LEN: usize = 36;
+
+fn is_guid(bytes: &[u8]) -> bool {
+    bytes.iter().enumerate().all(|(i, b)| match i {
+        8 | 13 | 18 | 23 => *b == b'-',
+        _ => b.is_ascii_hexdigit(),
+    })
+}
+
+/// 32-bit FNV-1a, stable across runs and builds
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0x811c_9dc5, |hash, &b| (hash ^ u32::from(b)).wrapping_mul(0x0100_0193))
+}
+
+/// Start redacting with the current user's and machine's values
+pub fn configure(config: &RedactConfig) -> Result<(), String> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let environment: Vec<(&str, Option<String>)> = ["USERPROFILE", "USERNAME", "COMPUTERNAME"]
+        .into_iter()
+        .map(|variable| (variable, std::env::var(variable).ok()))
+        .collect();
+    let _ = REDACTOR.set(Redactor::new(config, &environment)?);
+    Ok(())
+}
+
+/// Whether `configure` enabled redaction
+pub fn enabled() -> bool {
+    REDACTOR.get().is_some()
+}
+
+/// `text`, redacted if enabled
+pub fn text(text: &str) -> String {
+    match REDACTOR.get() {
+        Some(redactor) => redactor.apply(text),
+        None => text.to_string(),
+    }
+}
+
+/// Redact the string values of `fields` in place
+pub fn fields(fields: &mut Map<String, Value>) {
+    if let Some(redactor) = REDACTOR.get() {
+        for value in fields.values_mut() {
+            if let Value::String(text) = value {
+                *text = redactor.apply(text);
+            }
+        }
+    }
+}
+
+/// Redact every string in `value`, however deeply nested
+pub fn value(value: &mut Value) {
+    if let Some(redactor) = REDACTOR.get() {
+        redact_value(redactor, value);
+    }
+}
+
+fn redact_value(redactor: &Redactor, value: &mut Value) {
+    match value {
+        Value::String(text) => *text = redactor.apply(text),
+        Value::Array(items) => items.iter_mut().for_each(|item| redact_value(redactor, item)),
+        Value::Object(fields) => fields.values_mut().for_each(|field| redact_value(redactor, field)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor(mode: &str, patterns: &[&str]) -> Redactor {
+        let config = RedactConfig {
+            enabled: true,
+            mode: mode.to_string(),
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            ..RedactConfig::default()
+        };
+        let environment = [
+            ("USERPROFILE", Some(r"C:\Users\alice".to_string())),
+            ("USERNAME", Some("alice".to_string())),
+            ("COMPUTERNAME", Some("ALICE-PC".to_string())),
+        ];
+        Redactor::new(&config, &environment).unwrap()
+    }
+
+    #[test]
+    fn user_machine_and_patterns_are_masked() {
+        let redactor = redactor("mask", &[r"D:\Work"]);
+
+        assert_eq!(
+            redactor.apply(r"Opened c:\users\ALICE\AppData\x.cfg on alice-pc as alice"),
+            r"Opened <profile>\AppData\x.cfg on <computer> as <user>"
+        );
+        assert_eq!(redactor.apply(r"d:\work\save.dat"), r"<redacted>\save.dat");
+        assert_eq!(redactor.apply("nothing to hide"), "nothing to hide");
+    }
+
+    #[test]
+    fn guids_are_masked_on_their_own() {
+        let redactor = redactor("mask", &[]);
+
+        assert_eq!(
+            redactor.apply("HwProfileGuid={6F9619FF-8B86-D011-B42D-00C04FC964FF}"),
+            "HwProfileGuid={<guid>}"
+        );
+        // Part of a longer hex string, not a GUID
+        let longer = "a6F9619FF-8B86-D011-B42D-00C04FC964FF";
+        assert_eq!(redactor.apply(longer), longer);
+    }
+
+    #[test]
+    fn hashes_are_stable_and_ignore_case() {
+        let redactor = redactor("hash", &[]);
+
+        let upper = redactor.apply("ALICE");
+        assert!(upper.starts_with("<user:") && upper.len() == "<user:>".len() + 8);
+        assert_eq!(redactor.apply("alice"), upper);
+        assert_ne!(redactor.apply("ALICE-PC"), redactor.apply("ALICE"));
+
+        let config = RedactConfig {
+            mode: "blur".to_string(),
+            ..RedactConfig::default()
+        };
+        assert!(Redactor::new(&config, &[]).is_err());
+    }
+
+    #[test]
+    fn nested_values_are_redacted() {
+        let redactor = redactor("mask", &[]);
+        let mut value = serde_json::json!({
+            "process": {"path": r"C:\Users\alice\game.exe", "pid": 4242},
+            "modules": [{"path": r"C:\Users\alice\mod.dll"}],
+        });
+        redact_value(&redactor, &mut value);
+        assert_eq!(value["process"]["path"], r"<profile>\game.exe");
+        assert_eq!(value["process"]["pid"], 4242);
+        assert_eq!(value["modules"][0]["path"], r"<profile>\mod.dll");
+    }
+}
@@ -0,0 +1,121 @@
+/// Message formats for shipping log events to another machine
+///
+/// With `[logging] remote = "host:port"` events are sent over UDP, either
+/// as RFC 5424 syslog messages (one per datagram, as RFC 5426 expects) or
+/// as JSON Lines packed into datagrams of up to `MAX_DATAGRAM` bytes. The
+/// socket and the batching thread live in logging.rs; this part only builds
+/// the payloads.
+
+/// Datagram size that fits a typical Ethernet MTU unfragmented
+pub const MAX_DATAGRAM: usize = 1400;
+
+/// Facility of every syslog message (1 = user-level)
+const FACILITY: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Syslog,
+    Json,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "syslog" => Ok(Format::Syslog),
+            "json" => Ok(Format::Json),
+            other => Err(format!("unknown remote log format '{}' (expected syslog or json)", other)),
+        }
+    }
+}
+
+/// Syslog severity of a `tracing` level name
+pub fn severity(level: &str) -> u8 {
+    match level {
+        "ERROR" => 3,
+        "WARN" => 4,
+        "INFO" => 6,
+        _ => 7,
+    }
+}
+
+/// An RFC 5424 message: `<PRI>1 TIMESTAMP HOST APP PROCID - - MSG`
+pub fn syslog_message(severity: u8, timestamp: &str, host: &str, pid: u32, message: &str) -> String {
+    format!(
+        "<{}>1 {} {} reflex {} - - {}",
+        FACILITY * 8 + severity,
+        timestamp,
+        if host.is_empty() { "-" } else { host },
+        pid,
+        message
+    )
+}
+
+/// Group queued messages into datagram payloads
+///
+/// Syslog messages go one per datagram, cut to `MAX_DATAGRAM`. JSON lines
+/// are joined with newlines up to `MAX_DATAGRAM`; a longer line is sent
+/// alone rather than cut, which would make it unparseable.
+pub fn datagrams(messages: &[String], format: Format) -> Vec<Vec<u8>> {
+    match format {
+        Format::Syslog => messages
+            .iter()
+            .map(|message| truncate(message, MAX_DATAGRAM).as_bytes().to_vec())
+            .collect(),
+        Format::Json => {
+            let mut datagrams = Vec::new();
+            let mut current: Vec<u8> = Vec::new();
+            for message in messages {
+                if !current.is_empty() && current.len() + message.len() + 1 > MAX_DATAGRAM {
+                    datagrams.push(std::mem::take(&mut current));
+                }
+                current.extend_from_slice(message.as_bytes());
+                current.push(b'\n');
+            }
+            if !current.is_empty() {
+                datagrams.push(current);
+            }
+            datagrams
+        }
+    }
+}
+
+/// Longest prefix of `s` within `max` bytes, on a character boundary
+fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syslog_messages_follow_rfc5424() {
+        assert_eq!(
+            syslog_message(severity("WARN"), "2024-05-01T12:00:00.000Z", "BENCH-01", 4242, "Blocking deletion"),
+            "<12>1 2024-05-01T12:00:00.000Z BENCH-01 reflex 4242 - - Blocking deletion"
+        );
+        assert!(syslog_message(severity("TRACE"), "t", "", 1, "x").starts_with("<15>1 t - reflex"));
+        assert!(Format::parse("gelf").is_err());
+    }
+
+    #[test]
+    fn messages_are_packed_into_datagrams() {
+        let line = "x".repeat(600);
+        let messages = vec![line.clone(), line.clone(), line.clone(), "y".repeat(2000)];
+
+        let json = datagrams(&messages, Format::Json);
+        assert_eq!(json.iter().map(Vec::len).collect::<Vec<_>>(), vec![1202, 601, 2001]);
+
+        let syslog = datagrams(&["é".repeat(800)], Format::Syslog);
+        assert_eq!(syslog.len(), 1);
+        assert_eq!(syslog[0].len(), MAX_DATAGRAM);
+        assert!(std::str::from_utf8(&syslog[0]).is_ok());
+    }
+}
@@ -0,0 +1,101 @@
+/// Fixed-capacity, lock-free ring buffer
+///
+/// Writers never block: each push atomically swaps a new entry into its slot
+/// and frees whatever it displaced. Draining swaps every slot to null, so an
+/// entry is always owned by exactly one side and can't be freed twice.
+/// `snapshot` takes each entry out the same way and puts it back unless a
+/// push has refilled the slot meanwhile.
+
+use std::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+
+struct Entry<T> {
+    seq: u64,
+    value: T,
+}
+
+pub struct RingBuffer<T> {
+    slots: Box<[AtomicPtr<Entry<T>>]>,
+    next: AtomicU64,
+}
+
+unsafe impl<T: Send> Send for RingBuffer<T> {}
+unsafe impl<T: Send> Sync for RingBuffer<T> {}
+
+impl<T> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            slots: (0..capacity)
+                .map(|_| AtomicPtr::new(std::ptr::null_mut()))
+                .collect(),
+            next: AtomicU64::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Append a value, overwriting the oldest one when full
+    pub fn push(&self, value: T) {
+        let seq = self.next.fetch_add(1, Ordering::Relaxed);
+        let index = (seq % self.slots.len() as u64) as usize;
+        let entry = Box::into_raw(Box::new(Entry { seq, value }));
+
+        let old = self.slots[index].swap(entry, Ordering::AcqRel);
+        if !old.is_null() {
+            drop(unsafe { Box::from_raw(old) });
+        }
+    }
+
+    /// Take every buffered value, oldest first, leaving the buffer empty
+    pub fn drain(&self) -> Vec<T> {
+        let mut entries: Vec<Entry<T>> = self
+            .slots
+            .iter()
+            .filter_map(|slot| {
+                let ptr = slot.swap(std::ptr::null_mut(), Ordering::AcqRel);
+                if ptr.is_null() {
+                    None
+                } else {
+                    Some(*unsafe { Box::from_raw(ptr) })
+                }
+            })
+            .collect();
+
+        entries.sort_by_key(|e| e.seq);
+        entries.into_iter().map(|e| e.value).collect()
+    }
+}
+
+impl<T: Clone> RingBuffer<T> {
+    /// Copy of every buffered value, oldest first; the buffer keeps them
+    pub fn snapshot(&self) -> Vec<T> {
+        let mut entries: Vec<(u64, T)> = Vec::new();
+        for slot in self.slots.iter() {
+            let ptr = slot.swap(std::ptr::null_mut(), Ordering::AcqRel);
+            if ptr.is_null() {
+                continue;
+            }
+
+            let entry = unsafe { &*ptr };
+            entries.push((entry.seq, entry.value.clone()));
+            if slot
+                .compare_exchange(std::ptr::null_mut(), ptr, Ordering::AcqRel, Ordering::Acquire)
+                .is_err()
+            {
+                // A newer entry took the slot; this one was about to be overwritten anyway
+                drop(unsafe { Box::from_raw(ptr) });
+            }
+        }
+
+        entries.sort_by_key(|(seq, _)| *seq);
+        entries.into_iter().map(|(_, value)| value).collect()
+    }
+}
+
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        self.drain();
+    }
+}
@@ -0,0 +1,150 @@
+/// MSVC RTTI in a mapped x64 image
+///
+/// Classes with virtual functions compiled by MSVC (without /GR-) leave
+/// three structures behind:
+/// - a type descriptor holding the mangled class name (`.?AVPlayer@game@@`)
+/// - a complete object locator (COL) per vtable, pointing by RVA to the
+///   type descriptor and to itself
+/// - the vtable, preceded by a pointer to its COL
+///
+/// `scan` walks them in that order and returns every class that has a
+/// vtable. Classes with multiple bases get one vtable per base subobject,
+/// told apart by `offset`.
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Longest mangled name accepted
+const MAX_NAME: usize = 512;
+/// `COL.signature` of x64 images, whose COL fields are RVAs
+const COL_SIGNATURE_X64: u32 = 1;
+/// Size of a COL: signature, offset, cdOffset, type descriptor, class
+/// descriptor and self RVAs
+const COL_SIZE: usize = 24;
+
+/// A class and one of its vtables
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Class {
+    /// Demangled name, e.g. "game::Player"
+    pub name: String,
+    /// RVA of the vtable
+    pub vtable: u32,
+    /// Offset of the subobject using this vtable within the full object
+    pub offset: u32,
+}
+
+/// Classes found in `image`, a copy of a module mapped at `base`
+pub fn scan(image: &[u8], base: u64) -> Vec<Class> {
+    let descriptors = type_descriptors(image);
+
+    // COL RVA -> (type descriptor RVA, offset)
+    let mut locators = HashMap::new();
+    for rva in (0..image.len().saturating_sub(COL_SIZE)).step_by(4) {
+        let field = |index: usize| read_u32(image, rva + index * 4);
+        if field(0) == Some(COL_SIGNATURE_X64) && field(5) == Some(rva as u32) {
+            if let (Some(descriptor), Some(offset)) = (field(3), field(1)) {
+                if descriptors.contains_key(&descriptor) {
+                    locators.insert(rva as u32, (descriptor, offset));
+                }
+            }
+        }
+    }
+
+    let mut classes: Vec<Class> = (8..image.len().saturating_sub(7))
+        .step_by(8)
+        .filter_map(|rva| {
+            let locator = read_u64(image, rva - 8)?.checked_sub(base)?;
+            let (descriptor, offset) = locators.get(&u32::try_from(locator).ok()?)?;
+            Some(Class {
+                name: demangle(&descriptors[descriptor]),
+                vtable: rva as u32,
+                offset: *offset,
+            })
+        })
+        .collect();
+    classes.sort_by(|a, b| (&a.name, a.offset).cmp(&(&b.name, b.offset)));
+    classes
+}
+
+/// Type descriptor RVA -> mangled name
+fn type_descriptors(image: &[u8]) -> HashMap<u32, String> {
+    let mut descriptors = HashMap::new();
+    // The name follows the type_info vtable pointer and a spare pointer
+    for name_at in (16..image.len()).step_by(8) {
+        let rest = &image[name_at..];
+        if !(rest.starts_with(b".?AV") || rest.starts_with(b".?AU")) {
+            continue;
+        }
+        let len = match rest.iter().take(MAX_NAME).position(|&b| b == 0) {
+            Some(len) => len,
+            None => continue,
+        };
+        if let Ok(name) = std::str::from_utf8(&rest[..len]) {
+            descriptors.insert((name_at - 16) as u32, name.to_string());
+        }
+    }
+    descriptors
+}
+
+/// `.?AVPlayer@game@@` -> `game::Player`
+///
+/// Names with templates or other special forms are returned mangled.
+pub fn demangle(mangled: &str) -> String {
+    let inner = mangled
+        .strip_prefix(".?AV")
+        .or_else(|| mangled.strip_prefix(".?AU"))
+        .and_then(|name| name.strip_suffix("@@"));
+    match inner {
+        Some(inner) if !inner.is_empty() && !inner.contains(['?', '$']) => {
+            inner.rsplit('@').collect::<Vec<_>>().join("::")
+        }
+        _ => mangled.to_string(),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_demangled_with_their_namespaces() {
+        assert_eq!(demangle(".?AVPlayer@game@@"), "game::Player");
+        assert_eq!(demangle(".?AUState@@"), "State");
+        assert_eq!(demangle(".?AV?$Array@H@core@@"), ".?AV?$Array@H@core@@");
+    }
+
+    #[test]
+    fn vtables_are_found_through_their_locators() {
+        const BASE: u64 = 0x1_8000_0000;
+        let mut image = vec![0u8; 0x400];
+        let mut put = |at: usize, bytes: &[u8]| image[at..at + bytes.len()].copy_from_slice(bytes);
+
+        // Type descriptor at 0x100, name at 0x110
+        put(0x110, b".?AVPlayer@game@@\0");
+        // COL at 0x200: signature, offset 8, cdOffset, descriptor, hierarchy, self
+        for (i, field) in [1u32, 8, 0, 0x100, 0, 0x200].iter().enumerate() {
+            put(0x200 + i * 4, &field.to_le_bytes());
+        }
+        // Vtable at 0x308, its COL pointer just before it
+        put(0x300, &(BASE + 0x200).to_le_bytes());
+        // A pointer to the COL at another base is not a vtable
+        put(0x340, &(0x2_0000_0000u64 + 0x200).to_le_bytes());
+
+        assert_eq!(
+            scan(&image, BASE),
+            vec![Class {
+                name: "game::Player".to_string(),
+                vtable: 0x308,
+                offset: 8,
+            }]
+        );
+    }
+}
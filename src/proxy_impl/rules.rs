@@ -0,0 +1,472 @@
+/// Declarative intercept rules
+///
+/// Rules from the config file decide what the detours do with a call:
+///
+/// ```toml
+/// [[rules]]
+/// api = "DeleteFileW"
+/// path = "*important_file*"   # glob: * and ?, case-insensitive
+/// action = "block"
+///
+/// [[rules]]
+/// api = "RegQueryValueExW"
+/// value_name = "HwProfileGuid"
+/// action = "modify"
+/// value = "{AAAAAAAA-AAAA-AAAA-AAAA-AAAAAAAAAAAA}"
+/// ```
+///
+/// Rules are evaluated in order. The first matching `allow`, `block` or
+/// `modify` rule decides; `log` rules only record the match and evaluation
+/// continues. Without a match the rules pass the call on.
+///
+/// The rules are one handler of a chain the detours consult (`decide`).
+/// Other subsystems register their own with a priority; handlers run by
+/// ascending priority, ties in registration order, and the first to decide
+/// ends the chain; a call no handler decides on is allowed. The rules run
+/// at `RULES_PRIORITY` (0):
+///
+/// ```rust,ignore
+/// // Runs before the rules: saves are never deleted, whatever they say
+/// rules::add_handler("protect-saves", -10, Filter::api("DeleteFileW").path("*.sav"), |_| {
+///     Some(Decision::Block)
+/// });
+/// ```
+///
+/// A handler's `Filter` (API, path glob, registry value name) is checked
+/// by the chain, so the handler is only called for the calls it is about.
+/// The order and filters are listed in the status export (`handlers`).
+///
+/// The chain is copy-on-write (cow.rs): `decide` runs on the current
+/// version without a lock, and registering a handler publishes a new one.
+
+use crate::proxy_impl::cow::CowCell;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Priority the config file's rules are registered at
+pub const RULES_PRIORITY: i32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Call the original
+    Allow,
+    /// Fail the call without calling the original
+    Block,
+    /// Return `value` instead of calling the original
+    Modify,
+    /// Log the call and keep evaluating
+    Log,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rule {
+    /// Hooked API name, e.g. "DeleteFileW"
+    pub api: String,
+    /// Glob matched against the path argument
+    pub path: Option<String>,
+    /// Registry value name (case-insensitive)
+    pub value_name: Option<String>,
+    pub action: Action,
+    /// Replacement value for `modify`
+    pub value: Option<String>,
+}
+
+/// The arguments of an intercepted call, as seen by the rules
+#[derive(Debug, Default)]
+pub struct CallContext<'a> {
+    pub api: &'a str,
+    pub path: Option<&'a str>,
+    pub value_name: Option<&'a str>,
+}
+
+/// Outcome of rule evaluation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    Block,
+    Modify(String),
+}
+
+impl Decision {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Decision::Allow => "allow",
+            Decision::Block => "block",
+            Decision::Modify(_) => "modify",
+        }
+    }
+}
+
+impl Rule {
+    fn matches(&self, call: &CallContext) -> bool {
+        self.api.eq_ignore_ascii_case(call.api)
+            && arguments_match(self.path.as_deref(), self.value_name.as_deref(), call)
+    }
+}
+
+/// Argument predicates a handler is only called for; unset fields match
+/// any call
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Filter {
+    /// Hooked API name (case-insensitive)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api: Option<String>,
+    /// Glob matched against the path argument
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Registry value name (case-insensitive)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value_name: Option<String>,
+}
+
+impl Filter {
+    /// Calls to `api`
+    pub fn api(api: &str) -> Self {
+        Self {
+            api: Some(api.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Only calls whose path matches the glob `pattern`
+    pub fn path(mut self, pattern: &str) -> Self {
+        self.path = Some(pattern.to_string());
+        self
+    }
+
+    /// Only calls for the registry value `name`
+    pub fn value_name(mut self, name: &str) -> Self {
+        self.value_name = Some(name.to_string());
+        self
+    }
+
+    pub fn matches(&self, call: &CallContext) -> bool {
+        self.api.as_ref().is_none_or(|api| api.eq_ignore_ascii_case(call.api))
+            && arguments_match(self.path.as_deref(), self.value_name.as_deref(), call)
+    }
+}
+
+/// A call without a path or value name never matches a pattern for it
+fn arguments_match(path: Option<&str>, value_name: Option<&str>, call: &CallContext) -> bool {
+    if let Some(pattern) = path {
+        match call.path {
+            Some(path) if glob_match(pattern, path) => {}
+            _ => return false,
+        }
+    }
+
+    if let Some(expected) = value_name {
+        match call.value_name {
+            Some(name) if name.eq_ignore_ascii_case(expected) => {}
+            _ => return false,
+        }
+    }
+
+    true
+}
+
+/// Evaluate `rules` against a call: the decision of the first deciding rule
+/// that matches, if any
+pub fn first_match(rules: &[Rule], call: &CallContext) -> Option<Decision> {
+    for (index, rule) in rules.iter().enumerate() {
+        if !rule.matches(call) {
+            continue;
+        }
+
+        match rule.action {
+            Action::Log => {
+                tracing::info!("[rules] Rule #{} matched {:?}", index, call);
+            }
+            Action::Allow => return Some(Decision::Allow),
+            Action::Block => {
+                tracing::info!("[rules] Rule #{} blocked {:?}", index, call);
+                return Some(Decision::Block);
+            }
+            Action::Modify => match &rule.value {
+                Some(value) => {
+                    tracing::info!("[rules] Rule #{} modified {:?}", index, call);
+                    return Some(Decision::Modify(value.clone()));
+                }
+                None => tracing::warn!("[rules] Rule #{} is 'modify' without a value, ignored", index),
+            },
+        }
+    }
+
+    None
+}
+
+/// Decides on a call, or passes it on to the next handler (`None`)
+pub type Handler = Box<dyn Fn(&CallContext) -> Option<Decision> + Send + Sync>;
+
+struct HandlerEntry {
+    name: String,
+    priority: i32,
+    filter: Filter,
+    handler: Handler,
+}
+
+/// Public view of a registered handler
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HandlerInfo {
+    pub name: String,
+    pub priority: i32,
+    pub filter: Filter,
+}
+
+/// Handlers in the order they run
+#[derive(Default, Clone)]
+pub struct HandlerChain {
+    /// Shared with the previous versions of the chain
+    entries: Vec<Arc<HandlerEntry>>,
+}
+
+impl HandlerChain {
+    /// Insert after every handler of the same or a lower priority
+    pub fn add(&mut self, name: &str, priority: i32, filter: Filter, handler: Handler) {
+        let at = self.entries.partition_point(|entry| entry.priority <= priority);
+        self.entries.insert(
+            at,
+            Arc::new(HandlerEntry {
+                name: name.to_string(),
+                priority,
+                filter,
+                handler,
+            }),
+        );
+    }
+
+    /// The decision of the first handler whose filter matches the call and
+    /// that decides; without one the call is allowed
+    pub fn decide(&self, call: &CallContext) -> Decision {
+        self.entries
+            .iter()
+            .filter(|entry| entry.filter.matches(call))
+            .find_map(|entry| (entry.handler)(call))
+            .unwrap_or(Decision::Allow)
+    }
+
+    pub fn list(&self) -> Vec<HandlerInfo> {
+        self.entries
+            .iter()
+            .map(|entry| HandlerInfo {
+                name: entry.name.clone(),
+                priority: entry.priority,
+                filter: entry.filter.clone(),
+            })
+            .collect()
+    }
+}
+
+static HANDLERS: Lazy<CowCell<HandlerChain>> = Lazy::new(CowCell::default);
+
+/// Register a handler deciding on the intercepted calls `filter` matches
+///
+/// Lower priorities run first. Calls already deciding finish on the chain
+/// without the handler.
+pub fn add_handler<F>(name: &str, priority: i32, filter: Filter, handler: F)
+where
+    F: Fn(&CallContext) -> Option<Decision> + Send + Sync + 'static,
+{
+    tracing::info!("[rules] Handler '{}' registered at priority {} for {:?}", name, priority, filter);
+    HANDLERS.update(|chain| chain.add(name, priority, filter, Box::new(handler)));
+}
+
+/// Run the registered handlers on a call
+pub fn decide(call: &CallContext) -> Decision {
+    HANDLERS.load().decide(call)
+}
+
+/// Registered handlers, in the order they run
+pub fn handlers() -> Vec<HandlerInfo> {
+    HANDLERS.load().list()
+}
+
+/// Rules used when the config file does not define any
+///
+/// These reproduce the behavior of the example detours.
+pub fn default_rules() -> Vec<Rule> {
+    vec![
+        Rule {
+            api: "DeleteFileW".to_string(),
+            path: Some("*important_file*".to_string()),
+            value_name: None,
+            action: Action::Block,
+            value: None,
+        },
+        Rule {
+            api: "GetUserNameW".to_string(),
+            path: None,
+            value_name: None,
+            action: Action::Modify,
+            value: Some("CustomUser".to_string()),
+        },
+        Rule {
+            api: "RegQueryValueExW".to_string(),
+            path: None,
+            value_name: Some("HwProfileGuid".to_string()),
+            action: Action::Modify,
+            value: Some("{AAAAAAAA-AAAA-AAAA-AAAA-AAAAAAAAAAAA}".to_string()),
+        },
+    ]
+}
+
+/// Case-insensitive glob match supporting `*` and `?`
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // Let the last * absorb one more character
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(api: &str, path: Option<&str>, action: Action, value: Option<&str>) -> Rule {
+        Rule {
+            api: api.to_string(),
+            path: path.map(str::to_string),
+            value_name: None,
+            action,
+            value: value.map(str::to_string),
+        }
+    }
+
+    fn delete(path: &str) -> CallContext<'_> {
+        CallContext {
+            api: "DeleteFileW",
+            path: Some(path),
+            value_name: None,
+        }
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("*important_file*", r"C:\Saves\IMPORTANT_FILE.dat"));
+        assert!(glob_match("save?.dat", "save1.dat"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("save?.dat", "save10.dat"));
+        assert!(!glob_match("*.sav", "game.save"));
+    }
+
+    #[test]
+    fn first_deciding_rule_wins() {
+        let rules = [
+            rule("DeleteFileW", Some("*keep*"), Action::Allow, None),
+            rule("DeleteFileW", Some("*.sav"), Action::Block, None),
+        ];
+
+        assert_eq!(first_match(&rules, &delete("keep.sav")), Some(Decision::Allow));
+        assert_eq!(first_match(&rules, &delete("slot1.sav")), Some(Decision::Block));
+        assert_eq!(first_match(&rules, &delete("slot1.tmp")), None);
+    }
+
+    #[test]
+    fn log_rules_do_not_decide() {
+        let rules = [
+            rule("deletefilew", None, Action::Log, None),
+            rule("DeleteFileW", None, Action::Block, None),
+        ];
+
+        assert_eq!(first_match(&rules, &delete("anything")), Some(Decision::Block));
+    }
+
+    #[test]
+    fn modify_needs_a_value() {
+        let rules = [
+            rule("GetUserNameW", None, Action::Modify, None),
+            rule("GetUserNameW", None, Action::Modify, Some("Player")),
+        ];
+        let call = CallContext {
+            api: "GetUserNameW",
+            ..Default::default()
+        };
+
+        assert_eq!(first_match(&rules, &call), Some(Decision::Modify("Player".to_string())));
+    }
+
+    #[test]
+    fn handlers_run_by_priority_then_registration() {
+        let mut chain = HandlerChain::default();
+        let any = Filter::default;
+        chain.add("rules", RULES_PRIORITY, any(), Box::new(|_| Some(Decision::Allow)));
+        chain.add("spoof", 10, any(), Box::new(|_| Some(Decision::Modify("x".to_string()))));
+        chain.add("trace", -10, any(), Box::new(|_| None));
+        chain.add("guard", RULES_PRIORITY, any(), Box::new(|_| Some(Decision::Block)));
+
+        let order: Vec<_> = chain.list().into_iter().map(|h| (h.name, h.priority)).collect();
+        assert_eq!(
+            order,
+            [("trace", -10), ("rules", 0), ("guard", 0), ("spoof", 10)].map(|(n, p)| (n.to_string(), p))
+        );
+        assert_eq!(chain.decide(&delete("a")), Decision::Allow);
+        assert_eq!(HandlerChain::default().decide(&delete("a")), Decision::Allow);
+    }
+
+    #[test]
+    fn handlers_are_only_called_for_calls_their_filter_matches() {
+        let mut chain = HandlerChain::default();
+        let filter = Filter::api("deletefilew").path("*.cfg");
+        chain.add("cfg", 0, filter, Box::new(|call| {
+            assert!(call.path.unwrap().ends_with(".cfg"));
+            Some(Decision::Block)
+        }));
+
+        assert_eq!(chain.decide(&delete("game.cfg")), Decision::Block);
+        assert_eq!(chain.decide(&delete("game.sav")), Decision::Allow);
+        let query = CallContext {
+            api: "RegQueryValueExW",
+            value_name: Some("HwProfileGuid"),
+            ..Default::default()
+        };
+        assert_eq!(chain.decide(&query), Decision::Allow);
+        assert!(Filter::api("RegQueryValueExW").value_name("hwprofileguid").matches(&query));
+        assert!(!Filter::default().value_name("Other").matches(&query));
+    }
+
+    #[test]
+    fn path_rules_need_a_path() {
+        let rules = [rule("DeleteFileW", Some("*"), Action::Block, None)];
+        let call = CallContext {
+            api: "DeleteFileW",
+            ..Default::default()
+        };
+
+        assert_eq!(first_match(&rules, &call), None);
+    }
+
+    #[test]
+    fn value_names_match_case_insensitively() {
+        let rules = default_rules();
+        let call = CallContext {
+            api: "RegQueryValueExW",
+            path: None,
+            value_name: Some("hwprofileguid"),
+        };
+
+        assert!(matches!(first_match(&rules, &call), Some(Decision::Modify(_))));
+    }
+}
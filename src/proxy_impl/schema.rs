@@ -0,0 +1,214 @@
+/// Parameter schema for the exports of the original DLL
+///
+/// Recorded arguments (recording.rs) are raw registers. A schema file names
+/// the parameters of known exports and gives their types, so the recording
+/// also carries decoded values - numbers, strings, and the fields of
+/// structures passed by pointer - much like API Monitor definitions:
+///
+/// ```toml
+/// # reflex_schema.toml
+/// [[Init.params]]
+/// name = "flags"
+/// type = "dword"
+///
+/// [[Init.params]]
+/// name = "path"
+/// type = "wstr"
+///
+/// [[Init.params]]
+/// name = "settings"
+/// type = "ptr"
+/// fields = [
+///     { name = "size", type = "dword", offset = 0 },
+///     { name = "mode", type = "byte", offset = 4 },
+///     { name = "name", type = "str", offset = 8 },
+/// ]
+/// ```
+///
+/// Types: `byte`, `word`, `dword`, `int` (signed 32-bit), `qword`, `bool`,
+/// `handle`, `ptr`, `str` (ANSI) and `wstr` (UTF-16). A `ptr` with `fields`
+/// is decoded as a structure; fields may be pointers to structures again,
+/// up to `MAX_DEPTH` levels. Null pointers decode as `null`, unreadable
+/// memory as `"<unreadable>"`.
+
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Characters read for a string argument
+pub const MAX_STRING: usize = 256;
+/// Levels of nested structures decoded
+pub const MAX_DEPTH: usize = 4;
+
+/// Schema of every described export, by export name
+pub type Schema = HashMap<String, ExportSchema>;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ExportSchema {
+    /// Parameters in call order (rcx, rdx, r8, r9, then stack slots)
+    pub params: Vec<Param>,
+}
+
+/// A parameter, or a field of a structure
+#[derive(Debug, Deserialize)]
+pub struct Param {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: Kind,
+    /// Offset of a structure field
+    #[serde(default)]
+    pub offset: usize,
+    /// Fields of the structure a `ptr` points to
+    #[serde(default)]
+    pub fields: Vec<Param>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Byte,
+    Word,
+    Dword,
+    Int,
+    Qword,
+    Bool,
+    Handle,
+    Ptr,
+    Str,
+    Wstr,
+}
+
+impl Kind {
+    /// Bytes the value takes inside a structure
+    pub fn size(self) -> usize {
+        match self {
+            Kind::Byte => 1,
+            Kind::Word => 2,
+            Kind::Dword | Kind::Int | Kind::Bool => 4,
+            Kind::Qword | Kind::Handle | Kind::Ptr | Kind::Str | Kind::Wstr => 8,
+        }
+    }
+}
+
+/// Parse a schema file
+pub fn parse(text: &str) -> Result<Schema, String> {
+    toml::from_str::<Schema>(text).map_err(|e| e.to_string())
+}
+
+/// Decode `value` as `param`; `read(address, len)` returns the readable
+/// bytes at `address`, up to `len`
+pub fn decode(param: &Param, value: u64, read: &dyn Fn(usize, usize) -> Option<Vec<u8>>) -> Value {
+    decode_at(param, value, read, 0)
+}
+
+fn decode_at(param: &Param, value: u64, read: &dyn Fn(usize, usize) -> Option<Vec<u8>>, depth: usize) -> Value {
+    match param.kind {
+        Kind::Byte => json!(value as u8),
+        Kind::Word => json!(value as u16),
+        Kind::Dword => json!(value as u32),
+        Kind::Int => json!(value as u32 as i32),
+        Kind::Qword => json!(value),
+        Kind::Bool => json!(value as u32 != 0),
+        Kind::Handle => json!(format!("0x{:x}", value)),
+        _ if value == 0 => Value::Null,
+        Kind::Ptr if param.fields.is_empty() || depth >= MAX_DEPTH => json!(format!("0x{:x}", value)),
+        Kind::Ptr => {
+            let mut fields = Map::new();
+            for field in &param.fields {
+                let decoded = match read(value as usize + field.offset, field.kind.size()) {
+                    Some(bytes) if bytes.len() == field.kind.size() => {
+                        let mut raw = [0u8; 8];
+                        raw[..bytes.len()].copy_from_slice(&bytes);
+                        decode_at(field, u64::from_le_bytes(raw), read, depth + 1)
+                    }
+                    _ => json!("<unreadable>"),
+                };
+                fields.insert(field.name.clone(), decoded);
+            }
+            Value::Object(fields)
+        }
+        Kind::Str => match read(value as usize, MAX_STRING) {
+            Some(bytes) => {
+                let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                json!(String::from_utf8_lossy(&bytes[..end]))
+            }
+            None => json!("<unreadable>"),
+        },
+        Kind::Wstr => match read(value as usize, MAX_STRING * 2) {
+            Some(bytes) => {
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .take_while(|&u| u != 0)
+                    .collect();
+                json!(String::from_utf16_lossy(&units))
+            }
+            None => json!("<unreadable>"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        [[Init.params]]
+        name = "flags"
+        type = "int"
+
+        [[Init.params]]
+        name = "settings"
+        type = "ptr"
+        fields = [
+            { name = "size", type = "dword", offset = 0 },
+            { name = "name", type = "wstr", offset = 8 },
+            { name = "next", type = "ptr", offset = 16 },
+        ]
+    "#;
+
+    /// Fake address space: a structure at 0x1000, a string at 0x2000
+    fn read(address: usize, len: usize) -> Option<Vec<u8>> {
+        let mut settings = vec![0u8; 24];
+        settings[0..4].copy_from_slice(&24u32.to_le_bytes());
+        settings[8..16].copy_from_slice(&0x2000u64.to_le_bytes());
+        let name: Vec<u8> = "low\0".encode_utf16().flat_map(u16::to_le_bytes).collect();
+
+        let (start, memory) = match address {
+            0x1000..=0x1017 => (0x1000, settings),
+            0x2000..=0x2007 => (0x2000, name),
+            _ => return None,
+        };
+        let bytes = &memory[address - start..];
+        Some(bytes[..len.min(bytes.len())].to_vec())
+    }
+
+    #[test]
+    fn schema_parses() {
+        let schema = parse(SCHEMA).unwrap();
+        let init = &schema["Init"];
+        assert_eq!(init.params.len(), 2);
+        assert_eq!(init.params[1].kind, Kind::Ptr);
+        assert_eq!(init.params[1].fields[1].offset, 8);
+        assert!(parse("[[Init.params]]\nname = \"x\"\ntype = \"float\"").is_err());
+    }
+
+    #[test]
+    fn arguments_decode_by_type() {
+        let schema = parse(SCHEMA).unwrap();
+        let params = &schema["Init"].params;
+
+        assert_eq!(decode(&params[0], 0xFFFF_FFFF, &read), json!(-1));
+        assert_eq!(
+            decode(&params[1], 0x1000, &read),
+            json!({ "size": 24, "name": "low", "next": null })
+        );
+        assert_eq!(decode(&params[1], 0, &read), Value::Null);
+        assert_eq!(decode(&params[1], 0x3000, &read), json!({
+            "size": "<unreadable>",
+            "name": "<unreadable>",
+            "next": "<unreadable>",
+        }));
+    }
+}
@@ -0,0 +1,188 @@
+/// Self-test diagnostic export callable through rundll32
+///
+/// Runs outside of any game to catch setup mistakes early:
+/// 1. Locate reflex_original.dll in the given directory
+/// 2. Load it the same way the proxy does, explaining an architecture
+///    mismatch between it and the proxy
+/// 3. Enumerate its exports (DllMain must be exported for forwarding) and
+///    compare them with the analysed build
+/// 4. Validate every entry of the offset database against the image
+///
+/// Results are written to reflex_selftest.txt in the game directory.
+///
+/// Usage:
+/// `rundll32.exe reflex.dll,ReflexProxySelfTest C:\Games\MyGame`
+
+use crate::proxy_impl::install::{self, ORIGINAL_FILE_NAME};
+use crate::proxy;
+use crate::proxy_impl::{config, exports, offsets, os, pe};
+use std::fs;
+use std::path::Path;
+use winapi::shared::minwindef::{HINSTANCE, HMODULE};
+use winapi::shared::windef::HWND;
+use winapi::um::libloaderapi::FreeLibrary;
+use winapi::um::winnt::LPSTR;
+
+/// Report file written next to the original DLL
+pub const REPORT_FILE_NAME: &str = "reflex_selftest.txt";
+
+/// Collected pass/fail results
+#[derive(Default)]
+pub struct SelfTestReport {
+    lines: Vec<String>,
+    failures: usize,
+}
+
+impl SelfTestReport {
+    fn pass(&mut self, message: String) {
+        tracing::info!("[selftest] PASS {}", message);
+        self.lines.push(format!("[PASS] {}", message));
+    }
+
+    fn fail(&mut self, message: String) {
+        tracing::error!("[selftest] FAIL {}", message);
+        self.lines.push(format!("[FAIL] {}", message));
+        self.failures += 1;
+    }
+
+    fn info(&mut self, message: String) {
+        self.lines.push(format!("       {}", message));
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failures == 0
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = self.lines.join("\r\n");
+        text.push_str(&format!(
+            "\r\n\r\nResult: {} ({} failure(s))\r\n",
+            if self.passed() { "PASS" } else { "FAIL" },
+            self.failures
+        ));
+        text
+    }
+}
+
+/// rundll32 entry point: run the self-test against a game directory
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn ReflexProxySelfTest(
+    hwnd: HWND,
+    _hinst: HINSTANCE,
+    cmd_line: LPSTR,
+    _n_cmd_show: i32,
+) {
+    let args = install::parse_arguments(cmd_line);
+    let game_dir = install::directory_argument(&args);
+
+    let report = run_self_test(&game_dir);
+    let report_path = game_dir.join(REPORT_FILE_NAME);
+
+    let summary = match fs::write(&report_path, report.to_text()) {
+        Ok(()) => format!("Report written to:\n{}", report_path.display()),
+        Err(e) => format!("Failed to write {}: {}", report_path.display(), e),
+    };
+
+    if report.passed() {
+        install::show_result(hwnd, &format!("Self-test PASSED\n\n{}", summary), false);
+    } else {
+        install::show_result(hwnd, &format!("Self-test FAILED\n\n{}", summary), true);
+    }
+}
+
+/// Run all checks against the original DLL in `game_dir`
+pub unsafe fn run_self_test(game_dir: &Path) -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+    let original_path = game_dir.join(ORIGINAL_FILE_NAME);
+
+    if original_path.is_file() {
+        report.pass(format!("Found {}", original_path.display()));
+    } else {
+        report.fail(format!("{} not found", original_path.display()));
+        return report;
+    }
+
+    let flags = os::search_flags(&config::get().loader.search).unwrap_or(0);
+    let module = match os::system().load_library(&original_path.to_string_lossy(), flags) {
+        Ok(module) => module as HMODULE,
+        Err(e) => {
+            match proxy::architecture_mismatch(&original_path) {
+                Some(explanation) => report.fail(format!("{} - {}", e, explanation)),
+                None => report.fail(format!("{} - check its dependencies", e)),
+            }
+            return report;
+        }
+    };
+    report.pass(format!("Loaded {} at {:p}", ORIGINAL_FILE_NAME, module));
+
+    let base = module as *const u8;
+    check_exports(base, &mut report);
+    check_offsets(base, &mut report);
+
+    FreeLibrary(module);
+    report
+}
+
+unsafe fn check_exports(base: *const u8, report: &mut SelfTestReport) {
+    let exports = pe::exports(base);
+    if exports.is_empty() {
+        report.fail("Original DLL has no exports".to_string());
+        return;
+    }
+    report.pass(format!("Enumerated {} export(s)", exports.len()));
+
+    for export in &exports {
+        let name = export.name.as_deref().unwrap_or("<by ordinal>");
+        match &export.forwarder {
+            Some(target) => report.info(format!("#{:<4} {} -> {}", export.ordinal, name, target)),
+            None => report.info(format!("#{:<4} {} @ 0x{:x}", export.ordinal, name, export.rva)),
+        }
+    }
+
+    // initialize_proxy resolves DllMain by name
+    if exports.iter().any(|e| e.name.as_deref() == Some("DllMain")) {
+        report.pass("DllMain is exported".to_string());
+    } else {
+        report.fail("DllMain is not exported - the proxy cannot forward to it".to_string());
+    }
+
+    let loaded: Vec<(Option<&str>, u16)> = exports.iter().map(|e| (e.name.as_deref(), e.ordinal)).collect();
+    let drift = exports::diff(exports::KNOWN_EXPORTS, &loaded);
+    if drift.is_empty() {
+        report.pass("Export table matches the analysed build".to_string());
+        return;
+    }
+    report.info("Export table differs from the analysed build:".to_string());
+    for name in &drift.missing {
+        report.info(format!("  missing {}", name));
+    }
+    for name in &drift.added {
+        report.info(format!("  new     {}", name));
+    }
+    for (name, known, loaded) in &drift.renumbered {
+        report.info(format!("  moved   {} (ordinal {} -> {})", name, known, loaded));
+    }
+}
+
+unsafe fn check_offsets(base: *const u8, report: &mut SelfTestReport) {
+    for entry in offsets::KNOWN_OFFSETS {
+        report.info(format!("{}: {}", entry.name, entry.description));
+        match offsets::validate_offset(base, entry) {
+            Ok(()) => {
+                let offset = offsets::resolve(base, entry).unwrap_or(entry.offset);
+                report.pass(format!(
+                    "Offset {} (0x{:x}) is in executable code",
+                    entry.name, offset
+                ));
+                if offset != entry.offset {
+                    report.info(format!(
+                        "{} moved from 0x{:x} in the analysed build, found through {}",
+                        entry.name, entry.offset, entry.anchor.map_or("", |a| a.export)
+                    ));
+                }
+            }
+            Err(e) => report.fail(format!("Offset {}: {}", entry.name, e)),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+/// Signature scan results kept between launches
+///
+/// Scanning a large module for every `[[patches]]` signature delays
+/// startup. The match of each signature in reflex_original.dll is stored
+/// by RVA together with the SHA-256 of the DLL:
+///
+/// ```json
+/// {"module_sha256": "9f86d0...", "signatures": {"74 05 E8 ?? ?? ?? ??": 4660}}
+/// ```
+///
+/// A cache written for another build of the DLL is discarded as a whole;
+/// sigscan.rs still checks each cached match before using it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SigCache {
+    module_sha256: String,
+    /// Normalized signature -> RVA of its match
+    signatures: BTreeMap<String, u32>,
+}
+
+impl SigCache {
+    /// An empty cache for the module with this hash
+    pub fn new(module_sha256: &str) -> Self {
+        Self {
+            module_sha256: module_sha256.to_string(),
+            signatures: BTreeMap::new(),
+        }
+    }
+
+    /// The cache file's entries if it was written for `module_sha256`, else
+    /// an empty cache
+    pub fn parse(text: &str, module_sha256: &str) -> Self {
+        match serde_json::from_str::<SigCache>(text) {
+            Ok(cache) if cache.module_sha256 == module_sha256 => cache,
+            _ => Self::new(module_sha256),
+        }
+    }
+
+    pub fn get(&self, signature: &str) -> Option<u32> {
+        self.signatures.get(&normalize(signature)).copied()
+    }
+
+    /// Store a match; returns whether the cache changed
+    pub fn insert(&mut self, signature: &str, rva: u32) -> bool {
+        self.signatures.insert(normalize(signature), rva) != Some(rva)
+    }
+
+    pub fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signatures.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+/// One spelling per signature: uppercase bytes, `??` wildcards, single spaces
+fn normalize(signature: &str) -> String {
+    signature
+        .split_whitespace()
+        .map(|token| if token == "?" { "??".to_string() } else { token.to_ascii_uppercase() })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_survive_a_round_trip_for_the_same_module() {
+        let mut cache = SigCache::new("abc");
+        assert!(cache.insert("74 05 e8 ? ?? ?? ??", 0x1234));
+        assert!(!cache.insert("74  05 E8 ?? ?? ?? ??", 0x1234));
+
+        let reloaded = SigCache::parse(&cache.to_json(), "abc");
+        assert_eq!(reloaded.get("74 05 E8 ?? ?? ?? ??"), Some(0x1234));
+        assert_eq!(reloaded.get("74 06"), None);
+    }
+
+    #[test]
+    fn a_cache_of_another_build_is_discarded() {
+        let mut cache = SigCache::new("abc");
+        cache.insert("90 90", 0x10);
+
+        assert!(SigCache::parse(&cache.to_json(), "def").is_empty());
+        assert!(SigCache::parse("not json", "abc").is_empty());
+    }
+}
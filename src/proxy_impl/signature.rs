@@ -0,0 +1,331 @@
+/// Authenticode verification of reflex_original.dll
+///
+/// A sample patched or replaced by someone else behaves differently from
+/// the vendor's build, and nothing in the analysis shows it. With
+/// `[signature] enabled = true` the original's embedded signature is
+/// checked with WinVerifyTrust once it is loaded, from a background thread:
+///
+/// ```toml
+/// [signature]
+/// enabled = true
+/// signer = "NVIDIA Corporation"   # optional: expected signer name
+/// ```
+///
+/// The result and the signer's name, issuer and certificate thumbprint are
+/// logged and written to the environment report (environment.rs). A file
+/// that is unsigned, modified after signing, signed by an untrusted
+/// certificate or by another signer than `signer` is reported as an error
+/// on every sink. Revocation is not checked and nothing is downloaded.
+/// Files signed only through a catalog (most of Windows' own DLLs) count as
+/// unsigned. A chained proxy leaves the check to the next one.
+
+use serde::Serialize;
+
+/// Outcome of verifying a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Signature {
+    pub path: String,
+    /// "valid", "unsigned", "untrusted" or "invalid"
+    pub status: &'static str,
+    /// WinVerifyTrust's result, e.g. "0x800b0100"
+    pub code: String,
+    pub reason: &'static str,
+    pub signer: Option<String>,
+    pub issuer: Option<String>,
+    /// SHA-1 of the signer's certificate, as Windows shows it
+    pub thumbprint: Option<String>,
+    /// Whether `signer` is the configured one; None without one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_signer: Option<bool>,
+}
+
+impl Signature {
+    /// Whether the file is validly signed, by the expected signer if one is
+    /// configured
+    pub fn is_trusted(&self) -> bool {
+        self.status == "valid" && self.expected_signer != Some(false)
+    }
+}
+
+/// Status and explanation of a WinVerifyTrust result
+pub fn classify(code: u32) -> (&'static str, &'static str) {
+    match code {
+        0 => ("valid", "the signature and its certificate chain are valid"),
+        // TRUST_E_NOSIGNATURE, TRUST_E_SUBJECT_FORM_UNKNOWN,
+        // TRUST_E_PROVIDER_UNKNOWN
+        0x800b0100 | 0x800b0003 | 0x800b0001 => ("unsigned", "the file has no embedded signature"),
+        // TRUST_E_BAD_DIGEST
+        0x80096010 => ("invalid", "the file was modified after it was signed"),
+        // CERT_E_EXPIRED
+        0x800b0101 => ("untrusted", "the signing certificate has expired"),
+        // CERT_E_REVOKED
+        0x800b010c => ("untrusted", "the signing certificate was revoked"),
+        // CERT_E_UNTRUSTEDROOT, CERT_E_CHAINING
+        0x800b0109 | 0x800b010a => ("untrusted", "the certificate chain does not end at a trusted root"),
+        // TRUST_E_EXPLICIT_DISTRUST, CRYPT_E_SECURITY_SETTINGS
+        0x800b0111 | 0x80092026 => ("untrusted", "the signer or a certificate is distrusted by policy"),
+        _ => ("invalid", "the signature could not be verified"),
+    }
+}
+
+#[cfg(windows)]
+pub use windows::{result, start, stop};
+
+#[cfg(windows)]
+mod windows {
+    use super::{classify, Signature};
+    use crate::proxy;
+    use crate::proxy_impl::config::SignatureConfig;
+    use crate::proxy_impl::wide::to_wide;
+    use crate::proxy_impl::worker::Worker;
+    use once_cell::sync::OnceCell;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use winapi::ctypes::c_void;
+    use winapi::shared::guiddef::GUID;
+    use winapi::shared::minwindef::HMODULE;
+    use winapi::um::softpub::WINTRUST_ACTION_GENERIC_VERIFY_V2;
+    use winapi::um::wincrypt::{
+        CertCloseStore, CertFindCertificateInStore, CertFreeCertificateContext, CertGetCertificateContextProperty,
+        CertGetNameStringW, CryptMsgClose, CryptMsgGetParam, CryptQueryObject, CERT_FIND_SUBJECT_CERT, CERT_INFO,
+        CERT_NAME_ISSUER_FLAG, CERT_NAME_SIMPLE_DISPLAY_TYPE, CERT_QUERY_CONTENT_FLAG_PKCS7_SIGNED_EMBED,
+        CERT_QUERY_FORMAT_FLAG_BINARY, CERT_QUERY_OBJECT_FILE, CERT_SHA1_HASH_PROP_ID, CMSG_SIGNER_INFO,
+        CMSG_SIGNER_INFO_PARAM, HCERTSTORE, HCRYPTMSG, PCCERT_CONTEXT, PKCS_7_ASN_ENCODING, X509_ASN_ENCODING,
+    };
+    use winapi::um::wintrust::{
+        WinVerifyTrust, WINTRUST_DATA, WINTRUST_FILE_INFO, WTD_CACHE_ONLY_URL_RETRIEVAL, WTD_CHOICE_FILE,
+        WTD_REVOKE_NONE, WTD_STATEACTION_CLOSE, WTD_STATEACTION_VERIFY, WTD_UI_NONE,
+    };
+
+    static STARTED: AtomicBool = AtomicBool::new(false);
+    static RESULT: OnceCell<Signature> = OnceCell::new();
+    static WORKER: Worker = Worker::new();
+
+    /// Verify the loaded original's file from a background thread
+    pub unsafe fn start(config: &SignatureConfig) {
+        let base = proxy::get_original_dll_base();
+        let path = match proxy::get_module_path(base as HMODULE) {
+            Some(path) => path,
+            None => {
+                tracing::warn!("[signature] The original has no file to verify");
+                return;
+            }
+        };
+        STARTED.store(true, Ordering::SeqCst);
+        let expected = config.signer.clone();
+        let spawned = WORKER.spawn("reflex-signature", move || {
+            RESULT.get_or_init(|| unsafe { verify(&path, expected.as_deref()) });
+        });
+        if let Err(e) = spawned {
+            tracing::warn!("[signature] Failed to start the verification thread: {}", e);
+            STARTED.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Wait for a verification still running
+    pub fn stop() {
+        WORKER.stop();
+    }
+
+    /// The verification `start` began, once it finished; None if it was
+    /// not started
+    pub fn result() -> Option<&'static Signature> {
+        STARTED.load(Ordering::SeqCst).then(|| RESULT.wait())
+    }
+
+    unsafe fn verify(path: &Path, expected: Option<&str>) -> Signature {
+        let code = verify_trust(path);
+        let (status, reason) = classify(code);
+        let (signer, issuer, thumbprint) = match signer(path) {
+            Some((signer, issuer, thumbprint)) => (Some(signer), Some(issuer), Some(thumbprint)),
+            None => (None, None, None),
+        };
+        let expected_signer = expected.map(|expected| signer.as_deref().is_some_and(|s| s.eq_ignore_ascii_case(expected)));
+        let signature = Signature {
+            path: path.display().to_string(),
+            status,
+            code: format!("0x{:08x}", code),
+            reason,
+            signer,
+            issuer,
+            thumbprint,
+            expected_signer,
+        };
+
+        if signature.is_trusted() {
+            tracing::info!(
+                "[signature] {} is signed by \"{}\" (issuer \"{}\", thumbprint {})",
+                signature.path,
+                signature.signer.as_deref().unwrap_or("?"),
+                signature.issuer.as_deref().unwrap_or("?"),
+                signature.thumbprint.as_deref().unwrap_or("?")
+            );
+        } else if signature.status != "valid" {
+            tracing::error!(
+                "[signature] !!! {} is {}: {} ({}). The analysis may not be of the vendor's build",
+                signature.path,
+                signature.status.to_uppercase(),
+                signature.reason,
+                signature.code
+            );
+        } else {
+            tracing::error!(
+                "[signature] !!! {} is signed by \"{}\", not \"{}\". The analysis may not be of the vendor's build",
+                signature.path,
+                signature.signer.as_deref().unwrap_or("?"),
+                expected.unwrap_or_default()
+            );
+        }
+        signature
+    }
+
+    /// WinVerifyTrust's result for the embedded signature, offline
+    unsafe fn verify_trust(path: &Path) -> u32 {
+        let path = to_wide(path);
+        let mut file = WINTRUST_FILE_INFO {
+            cbStruct: std::mem::size_of::<WINTRUST_FILE_INFO>() as u32,
+            pcwszFilePath: path.as_ptr(),
+            hFile: std::ptr::null_mut(),
+            pgKnownSubject: std::ptr::null(),
+        };
+        let mut data: WINTRUST_DATA = std::mem::zeroed();
+        data.cbStruct = std::mem::size_of::<WINTRUST_DATA>() as u32;
+        data.dwUIChoice = WTD_UI_NONE;
+        data.fdwRevocationChecks = WTD_REVOKE_NONE;
+        data.dwUnionChoice = WTD_CHOICE_FILE;
+        *data.u.pFile_mut() = &mut file;
+        data.dwStateAction = WTD_STATEACTION_VERIFY;
+        data.dwProvFlags = WTD_CACHE_ONLY_URL_RETRIEVAL;
+
+        let mut action: GUID = WINTRUST_ACTION_GENERIC_VERIFY_V2;
+        let code = WinVerifyTrust(std::ptr::null_mut(), &mut action, &mut data as *mut _ as *mut c_void) as u32;
+        data.dwStateAction = WTD_STATEACTION_CLOSE;
+        WinVerifyTrust(std::ptr::null_mut(), &mut action, &mut data as *mut _ as *mut c_void);
+        code
+    }
+
+    /// Name, issuer and thumbprint of the certificate that signed `path`,
+    /// whether or not it is trusted
+    unsafe fn signer(path: &Path) -> Option<(String, String, String)> {
+        let path = to_wide(path);
+        let (mut encoding, mut content, mut format) = (0, 0, 0);
+        let mut store: HCERTSTORE = std::ptr::null_mut();
+        let mut message: HCRYPTMSG = std::ptr::null_mut();
+        let queried = CryptQueryObject(
+            CERT_QUERY_OBJECT_FILE,
+            path.as_ptr() as *const c_void,
+            CERT_QUERY_CONTENT_FLAG_PKCS7_SIGNED_EMBED,
+            CERT_QUERY_FORMAT_FLAG_BINARY,
+            0,
+            &mut encoding,
+            &mut content,
+            &mut format,
+            &mut store,
+            &mut message,
+            std::ptr::null_mut(),
+        );
+        if queried == 0 {
+            return None;
+        }
+
+        let certificate = signer_certificate(store, message);
+        let details = (!certificate.is_null()).then(|| {
+            let details = (
+                name(certificate, 0),
+                name(certificate, CERT_NAME_ISSUER_FLAG),
+                thumbprint(certificate),
+            );
+            CertFreeCertificateContext(certificate);
+            details
+        });
+        CertCloseStore(store, 0);
+        CryptMsgClose(message);
+        details
+    }
+
+    /// The certificate in `store` matching the message's first signer
+    unsafe fn signer_certificate(store: HCERTSTORE, message: HCRYPTMSG) -> PCCERT_CONTEXT {
+        let mut size = 0;
+        if CryptMsgGetParam(message, CMSG_SIGNER_INFO_PARAM, 0, std::ptr::null_mut(), &mut size) == 0 {
+            return std::ptr::null();
+        }
+        // u64 keeps the buffer aligned for the structure
+        let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+        if CryptMsgGetParam(message, CMSG_SIGNER_INFO_PARAM, 0, buffer.as_mut_ptr() as *mut c_void, &mut size) == 0 {
+            return std::ptr::null();
+        }
+        let info = &*(buffer.as_ptr() as *const CMSG_SIGNER_INFO);
+
+        let mut subject: CERT_INFO = std::mem::zeroed();
+        subject.Issuer = info.Issuer;
+        subject.SerialNumber = info.SerialNumber;
+        CertFindCertificateInStore(
+            store,
+            X509_ASN_ENCODING | PKCS_7_ASN_ENCODING,
+            0,
+            CERT_FIND_SUBJECT_CERT,
+            &subject as *const CERT_INFO as *const c_void,
+            std::ptr::null(),
+        )
+    }
+
+    unsafe fn name(certificate: PCCERT_CONTEXT, flags: u32) -> String {
+        let mut buffer = [0u16; 256];
+        let len = CertGetNameStringW(
+            certificate,
+            CERT_NAME_SIMPLE_DISPLAY_TYPE,
+            flags,
+            std::ptr::null_mut(),
+            buffer.as_mut_ptr(),
+            buffer.len() as u32,
+        ) as usize;
+        // The length includes the terminating NUL
+        String::from_utf16_lossy(&buffer[..len.saturating_sub(1)])
+    }
+
+    unsafe fn thumbprint(certificate: PCCERT_CONTEXT) -> String {
+        let mut hash = [0u8; 20];
+        let mut size = hash.len() as u32;
+        if CertGetCertificateContextProperty(certificate, CERT_SHA1_HASH_PROP_ID, hash.as_mut_ptr() as *mut c_void, &mut size)
+            == 0
+        {
+            return String::new();
+        }
+        hash[..size as usize].iter().map(|b| format!("{:02X}", b)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_are_classified() {
+        assert_eq!(classify(0).0, "valid");
+        assert_eq!(classify(0x800b0100).0, "unsigned");
+        assert_eq!(classify(0x80096010), ("invalid", "the file was modified after it was signed"));
+        assert_eq!(classify(0x800b0101).0, "untrusted");
+        assert_eq!(classify(0x800b0109).0, "untrusted");
+        assert_eq!(classify(0x80004005).0, "invalid");
+    }
+
+    #[test]
+    fn another_signer_is_not_trusted() {
+        let mut signature = Signature {
+            path: "reflex_original.dll".to_string(),
+            status: "valid",
+            code: "0x00000000".to_string(),
+            reason: classify(0).1,
+            signer: Some("NVIDIA Corporation".to_string()),
+            issuer: Some("DigiCert Trusted G4 Code Signing RSA4096 SHA384 2021 CA1".to_string()),
+            thumbprint: None,
+            expected_signer: None,
+        };
+        assert!(signature.is_trusted());
+        signature.expected_signer = Some(false);
+        assert!(!signature.is_trusted());
+        signature.expected_signer = Some(true);
+        signature.status = "unsigned";
+        assert!(!signature.is_trusted());
+    }
+}
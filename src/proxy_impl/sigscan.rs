@@ -0,0 +1,218 @@
+/// Byte signature scanning
+///
+/// Signatures are written as hex bytes with `??` wildcards, the format
+/// IDA and x64dbg copy out: `"48 8B 05 ?? ?? ?? ?? 48 85 C0"`. Scans cover
+/// the executable sections of a mapped image, so code that moves between
+/// builds can still be located.
+///
+/// Matches in reflex_original.dll are cached in `[sigscan] cache_path`
+/// (sigcache.rs), keyed by the DLL's SHA-256, so later launches of the same
+/// build skip the scans. A cached match is used only if the signature still
+/// matches there.
+
+use crate::proxy;
+use crate::proxy_impl::sigcache::SigCache;
+use crate::proxy_impl::{config, hash, pe};
+use once_cell::sync::OnceCell;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use winapi::shared::minwindef::HMODULE;
+
+/// The original's scan cache and whether it changed since it was read;
+/// None when caching is disabled or the DLL cannot be hashed
+static CACHE: OnceCell<Option<Mutex<(SigCache, bool)>>> = OnceCell::new();
+/// Set once the original on disk was replaced (original_watch.rs) or
+/// another build loaded (hot_swap.rs)
+static INVALIDATED: AtomicBool = AtomicBool::new(false);
+
+/// A parsed signature; `None` entries match any byte
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    bytes: Vec<Option<u8>>,
+}
+
+impl Pattern {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let bytes = text
+            .split_whitespace()
+            .map(|token| match token {
+                "?" | "??" => Ok(None),
+                _ => u8::from_str_radix(token, 16)
+                    .map(Some)
+                    .map_err(|_| format!("invalid byte '{}' in signature", token)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let pattern = Self { bytes };
+        if pattern.is_empty() {
+            return Err("empty signature".to_string());
+        }
+        Ok(pattern)
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Does the pattern match at the start of `data`?
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.len() >= self.bytes.len()
+            && self
+                .bytes
+                .iter()
+                .zip(data)
+                .all(|(expected, actual)| expected.is_none_or(|b| b == *actual))
+    }
+
+    /// Offsets of every match in `data`
+    pub fn find_all(&self, data: &[u8]) -> Vec<usize> {
+        if data.len() < self.bytes.len() {
+            return Vec::new();
+        }
+        (0..=data.len() - self.bytes.len())
+            .filter(|&i| self.matches(&data[i..]))
+            .collect()
+    }
+}
+
+/// Parse plain hex bytes ("EB 05"), no wildcards
+pub fn parse_bytes(text: &str) -> Result<Vec<u8>, String> {
+    text.split_whitespace()
+        .map(|token| u8::from_str_radix(token, 16).map_err(|_| format!("invalid byte '{}'", token)))
+        .collect()
+}
+
+/// Addresses of every match in the executable sections of the image at `base`
+pub unsafe fn find_in_module(base: *const u8, pattern: &Pattern) -> Vec<usize> {
+    let mut matches = Vec::new();
+    for section in pe::sections(base).iter().filter(|s| s.is_executable()) {
+        let start = base.add(section.rva as usize);
+        let data = std::slice::from_raw_parts(start, section.size as usize);
+        matches.extend(pattern.find_all(data).into_iter().map(|i| start as usize + i));
+    }
+    matches
+}
+
+/// Address of the single match of `pattern`; ambiguous signatures are an error
+pub unsafe fn find_unique(base: *const u8, pattern: &Pattern) -> Result<usize, String> {
+    match find_in_module(base, pattern).as_slice() {
+        [address] => Ok(*address),
+        [] => Err("signature not found".to_string()),
+        many => Err(format!("signature matches {} locations", many.len())),
+    }
+}
+
+/// `find_unique` in reflex_original.dll (at `base`), through the scan cache
+pub unsafe fn find_unique_in_original(base: *const u8, signature: &str) -> Result<usize, String> {
+    let pattern = Pattern::parse(signature)?;
+    let cache = match original_cache(base) {
+        Some(cache) => cache,
+        None => return find_unique(base, &pattern),
+    };
+
+    let mut cache = cache.lock().unwrap();
+    if let Some(rva) = cache.0.get(signature) {
+        if still_matches(base, rva, &pattern) {
+            return Ok(base as usize + rva as usize);
+        }
+        tracing::debug!("[sigscan] Cached match at 0x{:x} is stale, rescanning", rva);
+    }
+
+    let address = find_unique(base, &pattern)?;
+    if cache.0.insert(signature, (address - base as usize) as u32) {
+        cache.1 = true;
+    }
+    Ok(address)
+}
+
+/// Forget the cached matches and delete the cache file, because the
+/// original on disk no longer is the build they were found in; scans are
+/// not cached for the rest of the session
+pub fn invalidate_cache() {
+    INVALIDATED.store(true, Ordering::SeqCst);
+    let path = &config::get().sigscan.cache_path;
+    if path.is_empty() {
+        return;
+    }
+    match fs::remove_file(path) {
+        Ok(()) => tracing::warn!("[sigscan] Deleted the scan cache {}", path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => tracing::warn!("[sigscan] Failed to delete {}: {}", path, e),
+    }
+}
+
+/// Stop caching scans for the rest of the session, because another build
+/// than the one the cache is for was loaded; what was found is saved first
+pub fn stop_caching() {
+    save_cache();
+    INVALIDATED.store(true, Ordering::SeqCst);
+}
+
+/// Write the scan cache if scans added to it
+pub fn save_cache() {
+    if INVALIDATED.load(Ordering::SeqCst) {
+        return;
+    }
+    let Some(Some(cache)) = CACHE.get() else { return };
+    let mut cache = cache.lock().unwrap();
+    if !cache.1 {
+        return;
+    }
+
+    let path = &config::get().sigscan.cache_path;
+    match fs::write(path, cache.0.to_json()) {
+        Ok(()) => {
+            cache.1 = false;
+            tracing::info!("[sigscan] Cached {} signature match(es) in {}", cache.0.len(), path);
+        }
+        Err(e) => tracing::warn!("[sigscan] Failed to write {}: {}", path, e),
+    }
+}
+
+fn original_cache(base: *const u8) -> Option<&'static Mutex<(SigCache, bool)>> {
+    if INVALIDATED.load(Ordering::SeqCst) {
+        return None;
+    }
+    CACHE
+        .get_or_init(|| {
+            let path = &config::get().sigscan.cache_path;
+            if path.is_empty() {
+                return None;
+            }
+            // A manually mapped or embedded original has no file to hash
+            let module = unsafe { proxy::get_module_path(base as HMODULE) }?;
+            let module_hash = match hash::sha256_file(&module) {
+                Ok(module_hash) => module_hash,
+                Err(e) => {
+                    tracing::warn!("[sigscan] Scan cache disabled: {}", e);
+                    return None;
+                }
+            };
+
+            let cache = match fs::read_to_string(path) {
+                Ok(text) => SigCache::parse(&text, &module_hash),
+                Err(_) => SigCache::new(&module_hash),
+            };
+            if !cache.is_empty() {
+                tracing::debug!("[sigscan] {} cached signature match(es) for this build", cache.len());
+            }
+            Some(Mutex::new((cache, false)))
+        })
+        .as_ref()
+}
+
+/// Does `pattern` still match at `rva`, inside an executable section?
+unsafe fn still_matches(base: *const u8, rva: u32, pattern: &Pattern) -> bool {
+    let end = rva as usize + pattern.len();
+    match pe::section_for_rva(base, rva) {
+        Some(section) if section.is_executable() && end <= (section.rva + section.size) as usize => {
+            pattern.matches(std::slice::from_raw_parts(base.add(rva as usize), pattern.len()))
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,312 @@
+/// Reflex sleep telemetry of a session
+///
+/// The NvAPI hooks (nvapi.rs) report every low-latency call they intercept
+/// here: the sleep mode the game sets (NvAPI_D3D_SetSleepMode), each
+/// NvAPI_D3D_Sleep with the time it blocked, and a count of the other
+/// Reflex entry points. On detach the summary goes to the log and to
+/// `[nvapi] summary_path`:
+/// - calls per entry point
+/// - sleeps: count, total and longest time asleep, share of the session
+/// - intervals between sleeps (one per frame): min, mean, max and a
+///   histogram in milliseconds
+/// - every change of the sleep mode, with its time in the session
+///
+/// The modes recorded are the game's; `[nvapi.sleep_mode]` may replace
+/// parameters before they reach the driver (`SleepMode::overridden`).
+///
+/// Works on durations since the session start and is independent of the
+/// host platform.
+
+use crate::proxy_impl::config::SleepModeConfig;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Upper bounds of the interval histogram, in milliseconds (240 fps to
+/// 10 fps)
+pub const INTERVAL_BUCKETS_MS: [f64; 8] = [4.2, 8.3, 11.1, 16.7, 25.0, 33.3, 50.0, 100.0];
+/// Mode changes kept; later ones are only counted
+pub const MAX_MODE_CHANGES: usize = 256;
+
+static START: Lazy<Instant> = Lazy::new(Instant::now);
+static STATS: Lazy<Mutex<SleepStats>> = Lazy::new(|| Mutex::new(SleepStats::default()));
+
+/// NV_SET_SLEEP_MODE_PARAMS, as the game passed it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SleepMode {
+    pub low_latency: bool,
+    pub boost: bool,
+    pub minimum_interval_us: u32,
+    pub use_markers: bool,
+}
+
+impl SleepMode {
+    /// The mode passed to the driver when the game sets this one
+    pub fn overridden(self, config: &SleepModeConfig) -> Self {
+        let mut interval = config.minimum_interval_us.unwrap_or(self.minimum_interval_us);
+        if let Some([low, high]) = config.clamp_interval_us {
+            interval = interval.max(low).min(high);
+        }
+        Self {
+            low_latency: config.low_latency.unwrap_or(self.low_latency),
+            boost: config.boost.unwrap_or(self.boost),
+            minimum_interval_us: interval,
+            use_markers: config.use_markers.unwrap_or(self.use_markers),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ModeChange {
+    at_s: f64,
+    #[serde(flatten)]
+    mode: SleepMode,
+}
+
+/// Aggregated calls of a session
+#[derive(Debug, Default)]
+pub struct SleepStats {
+    calls: BTreeMap<&'static str, u64>,
+    sleeps: u64,
+    asleep: Duration,
+    longest: Duration,
+    last_sleep: Option<Duration>,
+    intervals: u64,
+    interval_sum: Duration,
+    interval_min: Option<Duration>,
+    interval_max: Duration,
+    /// Intervals per bound of `INTERVAL_BUCKETS_MS`, then above the last
+    interval_buckets: [u64; INTERVAL_BUCKETS_MS.len() + 1],
+    mode: Option<SleepMode>,
+    mode_changes: Vec<ModeChange>,
+    mode_changes_dropped: u64,
+}
+
+impl SleepStats {
+    /// Count a call of `function`
+    pub fn call(&mut self, function: &'static str) {
+        *self.calls.entry(function).or_default() += 1;
+    }
+
+    /// A sleep mode set at `at`; recorded if it differs from the last one,
+    /// and then true
+    pub fn mode(&mut self, at: Duration, mode: SleepMode) -> bool {
+        if self.mode == Some(mode) {
+            return false;
+        }
+        self.mode = Some(mode);
+        if self.mode_changes.len() < MAX_MODE_CHANGES {
+            self.mode_changes.push(ModeChange {
+                at_s: at.as_secs_f64(),
+                mode,
+            });
+        } else {
+            self.mode_changes_dropped += 1;
+        }
+        true
+    }
+
+    /// A sleep starting at `at` that blocked for `took`
+    pub fn sleep(&mut self, at: Duration, took: Duration) {
+        self.sleeps += 1;
+        self.asleep += took;
+        self.longest = self.longest.max(took);
+
+        if let Some(interval) = self.last_sleep.and_then(|last| at.checked_sub(last)) {
+            self.intervals += 1;
+            self.interval_sum += interval;
+            self.interval_min = Some(self.interval_min.map_or(interval, |min| min.min(interval)));
+            self.interval_max = self.interval_max.max(interval);
+            let ms = interval.as_secs_f64() * 1000.0;
+            self.interval_buckets[INTERVAL_BUCKETS_MS.partition_point(|&bound| bound < ms)] += 1;
+        }
+        self.last_sleep = Some(at);
+    }
+
+    /// The summary of a session that has lasted `elapsed`
+    pub fn summary(&self, elapsed: Duration) -> Value {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let mean = |sum: Duration, count: u64| if count == 0 { 0.0 } else { ms(sum) / count as f64 };
+        let share = match elapsed.is_zero() {
+            true => 0.0,
+            false => self.asleep.as_secs_f64() / elapsed.as_secs_f64(),
+        };
+        let buckets: Vec<Value> = self
+            .interval_buckets
+            .iter()
+            .enumerate()
+            .map(|(i, count)| json!({ "le_ms": INTERVAL_BUCKETS_MS.get(i), "count": count }))
+            .collect();
+
+        json!({
+            "duration_s": elapsed.as_secs_f64(),
+            "calls": self.calls,
+            "sleep": {
+                "count": self.sleeps,
+                "total_ms": ms(self.asleep),
+                "mean_ms": mean(self.asleep, self.sleeps),
+                "longest_ms": ms(self.longest),
+                "share": share,
+            },
+            "intervals": {
+                "count": self.intervals,
+                "min_ms": ms(self.interval_min.unwrap_or_default()),
+                "mean_ms": mean(self.interval_sum, self.intervals),
+                "max_ms": ms(self.interval_max),
+                "buckets": buckets,
+            },
+            "mode": self.mode,
+            "mode_changes": self.mode_changes,
+            "mode_changes_dropped": self.mode_changes_dropped,
+        })
+    }
+
+    /// One line for the log
+    pub fn line(&self) -> String {
+        let mean = match self.intervals {
+            0 => 0.0,
+            count => self.interval_sum.as_secs_f64() * 1000.0 / count as f64,
+        };
+        format!(
+            "{} sleep(s), {:.1} ms asleep in total, {:.2} ms between sleeps on average, {} mode change(s)",
+            self.sleeps,
+            self.asleep.as_secs_f64() * 1000.0,
+            mean,
+            self.mode_changes.len() as u64 + self.mode_changes_dropped
+        )
+    }
+}
+
+/// Time since the session started: the first call of `now`, which the
+/// NvAPI hooks make when they are set up
+pub fn now() -> Duration {
+    START.elapsed()
+}
+
+/// Count a call of `function`
+pub fn record_call(function: &'static str) {
+    STATS.lock().unwrap().call(function);
+}
+
+/// A sleep mode the game set; true if it changed
+pub fn record_mode(mode: SleepMode) -> bool {
+    let at = now();
+    STATS.lock().unwrap().mode(at, mode)
+}
+
+/// A sleep that started at `at` (from `now`) and blocked for `took`
+pub fn record_sleep(at: Duration, took: Duration) {
+    STATS.lock().unwrap().sleep(at, took);
+}
+
+/// Log the summary and write it to `path` ("" = log only); returns the
+/// path written
+pub fn write_summary(path: &str) -> Result<Option<String>, String> {
+    let stats = STATS.lock().unwrap();
+    tracing::info!("[sleep_stats] {}", stats.line());
+    if path.is_empty() {
+        return Ok(None);
+    }
+    let json = serde_json::to_string_pretty(&stats.summary(now())).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    Ok(Some(path.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn sleeps_and_intervals_are_aggregated() {
+        let mut stats = SleepStats::default();
+        for (at, took) in [(0, 2), (10, 3), (30, 1), (130, 4)] {
+            stats.call("NvAPI_D3D_Sleep");
+            stats.sleep(ms(at), ms(took));
+        }
+
+        let summary = stats.summary(ms(200));
+        assert_eq!(summary["calls"]["NvAPI_D3D_Sleep"], 4);
+        assert_eq!(summary["sleep"]["count"], 4);
+        assert_eq!(summary["sleep"]["total_ms"], 10.0);
+        assert_eq!(summary["sleep"]["longest_ms"], 4.0);
+        assert!((summary["sleep"]["share"].as_f64().unwrap() - 0.05).abs() < 1e-9);
+
+        let intervals = &summary["intervals"];
+        assert_eq!(intervals["count"], 3);
+        assert_eq!(intervals["min_ms"], 10.0);
+        assert_eq!(intervals["max_ms"], 100.0);
+        let counts: Vec<u64> = intervals["buckets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|bucket| bucket["count"].as_u64().unwrap())
+            .collect();
+        // 10 ms, 20 ms, and 100 ms (on the last bound)
+        assert_eq!(counts, [0, 0, 1, 0, 1, 0, 0, 1, 0]);
+        assert!(intervals["buckets"][8]["le_ms"].is_null());
+    }
+
+    #[test]
+    fn only_mode_changes_are_kept() {
+        let mut stats = SleepStats::default();
+        let on = SleepMode {
+            low_latency: true,
+            minimum_interval_us: 8333,
+            ..SleepMode::default()
+        };
+        assert!(stats.mode(ms(0), on));
+        assert!(!stats.mode(ms(5), on));
+        assert!(stats.mode(ms(1500), SleepMode { boost: true, ..on }));
+
+        let summary = stats.summary(ms(2000));
+        let changes = summary["mode_changes"].as_array().unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[1]["at_s"], 1.5);
+        assert_eq!(changes[1]["boost"], true);
+        assert_eq!(changes[1]["minimum_interval_us"], 8333);
+        assert_eq!(summary["mode"]["boost"], true);
+        assert!(stats.line().ends_with("2 mode change(s)"));
+    }
+
+    #[test]
+    fn overrides_replace_and_clamp() {
+        let game = SleepMode {
+            low_latency: true,
+            boost: false,
+            minimum_interval_us: 0,
+            use_markers: true,
+        };
+        assert_eq!(game.overridden(&SleepModeConfig::default()), game);
+
+        let clamp = SleepModeConfig {
+            boost: Some(true),
+            clamp_interval_us: Some([4167, 16667]),
+            ..SleepModeConfig::default()
+        };
+        let forced = game.overridden(&clamp);
+        assert!(forced.low_latency && forced.boost && forced.use_markers);
+        assert_eq!(forced.minimum_interval_us, 4167);
+        let slow = SleepMode {
+            minimum_interval_us: 50_000,
+            ..game
+        };
+        assert_eq!(slow.overridden(&clamp).minimum_interval_us, 16667);
+
+        let replace = SleepModeConfig {
+            low_latency: Some(false),
+            minimum_interval_us: Some(8333),
+            ..SleepModeConfig::default()
+        };
+        let forced = game.overridden(&replace);
+        assert!(!forced.low_latency);
+        assert_eq!(forced.minimum_interval_us, 8333);
+    }
+}
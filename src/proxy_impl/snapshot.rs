@@ -0,0 +1,148 @@
+/// Snapshots of the original's writable data, and their diff
+///
+/// The `snapshot` pipe command, or the `memory_snapshot` hotkey, copies the
+/// writable sections of reflex_original.dll (`.data` and the like). The next
+/// invocation compares them with the copy, writes every changed range with
+/// hex dumps of both versions to `path` (memdiff.rs) and starts over. Doing
+/// one thing in the game between the two shows where the DLL keeps the
+/// state it touched:
+///
+/// ```text
+/// .data+0x1d0 (reflex_original.dll+0x451d0), 4 byte(s)
+///   before:
+///   00007ffb1c4851d0  00 00 00 00                                      ....
+///   after:
+///   00007ffb1c4851d0  2a 00 00 00                                      *...
+/// ```
+///
+/// ```toml
+/// [memory_diff]
+/// path = "reflex_memdiff.txt"
+/// max_dump = 256               # bytes dumped per range
+///
+/// [hotkeys]
+/// memory_snapshot = "Ctrl+F11"
+/// ```
+///
+/// Sections are read with ReadProcessMemory, so one the original has made
+/// unreadable is skipped rather than crashing the caller.
+
+use crate::proxy;
+use crate::proxy_impl::config::MemoryDiffConfig;
+use crate::proxy_impl::memdiff;
+use crate::proxy_impl::pe::{self, Section};
+use once_cell::sync::Lazy;
+use std::fmt::Write;
+use std::fs;
+use std::sync::Mutex;
+use winapi::um::memoryapi::ReadProcessMemory;
+use winapi::um::processthreadsapi::GetCurrentProcess;
+
+/// Writable sections and their bytes
+type Sections = Vec<(Section, Vec<u8>)>;
+
+/// The sections at the first invocation
+static SNAPSHOT: Lazy<Mutex<Option<Sections>>> = Lazy::new(|| Mutex::new(None));
+
+/// Take a snapshot, or diff against the one taken before
+pub fn toggle(config: &MemoryDiffConfig) -> Result<String, String> {
+    if config.path.is_empty() {
+        return Err("memory diff file is disabled".to_string());
+    }
+    let base = unsafe { proxy::get_original_dll_base() } as usize;
+    if base == 0 {
+        return Err("the original DLL is not loaded".to_string());
+    }
+
+    let mut snapshot = SNAPSHOT.lock().unwrap();
+    let current = unsafe { capture(base) };
+    match snapshot.take() {
+        None => {
+            let bytes: usize = current.iter().map(|(_, data)| data.len()).sum();
+            let message = format!(
+                "snapshot of {} byte(s) in {} section(s) taken, run it again to diff",
+                bytes,
+                current.len()
+            );
+            *snapshot = Some(current);
+            Ok(message)
+        }
+        Some(previous) => {
+            let (report, ranges, bytes) = report(base, &previous, &current, config.max_dump);
+            fs::write(&config.path, report).map_err(|e| format!("Failed to write {}: {}", config.path, e))?;
+            tracing::info!("[snapshot] {} changed range(s), wrote {}", ranges, config.path);
+            Ok(format!("{} changed range(s) in {} byte(s), wrote {}", ranges, bytes, config.path))
+        }
+    }
+}
+
+/// Copy every readable writable section of the image at `base`
+unsafe fn capture(base: usize) -> Sections {
+    pe::sections(base as *const u8)
+        .into_iter()
+        .filter(|section| section.is_writable())
+        .filter_map(|section| {
+            let mut data = vec![0u8; section.size as usize];
+            let mut read = 0;
+            let ok = ReadProcessMemory(
+                GetCurrentProcess(),
+                (base + section.rva as usize) as *const _,
+                data.as_mut_ptr() as *mut _,
+                data.len(),
+                &mut read,
+            );
+            if ok == 0 || read != data.len() {
+                tracing::warn!("[snapshot] Section {} is not readable, skipped", section.name);
+                return None;
+            }
+            Some((section, data))
+        })
+        .collect()
+}
+
+/// The report text, the number of changed ranges and of bytes in them
+fn report(
+    base: usize,
+    previous: &[(Section, Vec<u8>)],
+    current: &[(Section, Vec<u8>)],
+    max_dump: usize,
+) -> (String, usize, usize) {
+    let mut out = String::new();
+    let mut ranges = 0;
+    let mut bytes = 0;
+
+    for (section, before) in previous {
+        let after = match current.iter().find(|(s, _)| s.rva == section.rva) {
+            Some((_, after)) => after,
+            None => continue,
+        };
+
+        for (start, end) in memdiff::changed_ranges(before, after) {
+            ranges += 1;
+            bytes += end - start;
+            let rva = section.rva as usize + start;
+            let shown = (end - start).min(max_dump);
+            let _ = writeln!(
+                out,
+                "{}+0x{:x} (reflex_original.dll+0x{:x}), {} byte(s)",
+                section.name,
+                start,
+                rva,
+                end - start
+            );
+            for (label, data) in [("before", before), ("after", after)] {
+                let _ = writeln!(out, "  {}:", label);
+                for line in memdiff::hex_dump(base + rva, &data[start..start + shown]).lines() {
+                    let _ = writeln!(out, "  {}", line);
+                }
+            }
+            if shown < end - start {
+                let _ = writeln!(out, "  ({} more byte(s))", end - start - shown);
+            }
+            out.push('\n');
+        }
+    }
+
+    let header = format!("reflex_original.dll: {} changed range(s), {} byte(s)\n\n", ranges, bytes);
+    (header + &out, ranges, bytes)
+}
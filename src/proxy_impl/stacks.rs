@@ -0,0 +1,104 @@
+/// Call stacks of selected hooks
+///
+/// When a detour (detours.rs) or syscall hook (syscalls.rs) named in
+/// `[stacks] hooks` fires, the caller's stack is captured with
+/// RtlCaptureStackBackTrace and logged as module+offset frames, innermost
+/// first, so the path leading into an API can be followed in a
+/// disassembler:
+///
+/// ```text
+/// [stacks] DeleteFileW called from:
+///     reflex_original.dll+0x1a2b3c
+///     reflex_original.dll+0x8f10
+///     game.exe+0x4417d
+/// ```
+///
+/// ```toml
+/// [stacks]
+/// hooks = ["DeleteFileW", "NtDeviceIoControlFile"]   # "*" for every hook
+/// depth = 16
+/// ```
+///
+/// With `[symbols]` enabled the frames are named `module!function+offset`
+/// (symbols.rs). Frames inside the proxy itself are left out. Capturing
+/// walks the unwind data of every frame, so select the hooks rather than
+/// using "*" on hot APIs.
+
+use crate::proxy;
+use crate::proxy_impl::config::StackConfig;
+use crate::proxy_impl::symbols;
+use once_cell::sync::{Lazy, OnceCell};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use winapi::shared::minwindef::HMODULE;
+use winapi::um::winnt::RtlCaptureStackBackTrace;
+
+/// RtlCaptureStackBackTrace captures fewer than 63 frames
+const MAX_DEPTH: usize = 62;
+
+struct Selection {
+    all: bool,
+    hooks: Vec<String>,
+    depth: usize,
+}
+
+static SELECTION: OnceCell<Selection> = OnceCell::new();
+/// Module base -> file name
+static MODULES: Lazy<Mutex<HashMap<usize, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Select the hooks whose callers are logged
+pub fn configure(config: &StackConfig) {
+    if config.hooks.is_empty() {
+        return;
+    }
+    let selection = Selection {
+        all: config.hooks.iter().any(|hook| hook == "*"),
+        hooks: config.hooks.clone(),
+        depth: (config.depth as usize).clamp(1, MAX_DEPTH),
+    };
+    tracing::info!("[stacks] Capturing {} frame(s) for {}", selection.depth, config.hooks.join(", "));
+    let _ = SELECTION.set(selection);
+}
+
+/// Log the current call stack if `hook` is selected
+pub unsafe fn capture(hook: &str) {
+    let selection = match SELECTION.get() {
+        Some(selection) if selection.all || selection.hooks.iter().any(|h| h == hook) => selection,
+        _ => return,
+    };
+
+    let own = module_base(capture as *const () as usize);
+    let mut frames = [std::ptr::null_mut(); MAX_DEPTH];
+    let captured = RtlCaptureStackBackTrace(0, MAX_DEPTH as u32, frames.as_mut_ptr(), std::ptr::null_mut()) as usize;
+
+    let stack: String = frames[..captured]
+        .iter()
+        .map(|&frame| frame as usize)
+        .skip_while(|&frame| own.is_some() && module_base(frame) == own)
+        .take(selection.depth)
+        .map(|frame| format!("\n    {}", describe(frame)))
+        .collect();
+    tracing::info!("[stacks] {} called from:{}", hook, stack);
+}
+
+/// `module!function+offset` when dbghelp can name the frame (symbols.rs),
+/// else `module+offset`, or the bare address outside any module
+pub unsafe fn describe(frame: usize) -> String {
+    let base = match module_base(frame) {
+        Some(base) => base,
+        None => return format!("0x{:x}", frame),
+    };
+
+    let mut modules = MODULES.lock().unwrap();
+    let name = modules.entry(base).or_insert_with(|| {
+        proxy::get_module_name(base as HMODULE).unwrap_or_else(|| format!("module_{:x}", base))
+    });
+    match symbols::symbolize(frame) {
+        Some(symbol) => format!("{}!{}", name, symbol),
+        None => format!("{}+0x{:x}", name, frame - base),
+    }
+}
+
+unsafe fn module_base(address: usize) -> Option<usize> {
+    proxy::module_at(address).map(|module| module as usize)
+}
@@ -0,0 +1,216 @@
+/// Runtime status of the proxy, queryable from outside the process
+///
+/// External tools (or another injected module) call the exported
+/// `ReflexProxyGetStatus` to receive a JSON snapshot of:
+/// - the build: version and build id (the sources' git commit, also
+///   exported alone as `ReflexProxyGetBuildId`)
+/// - initialization state
+/// - original DLL path and base address
+/// - DllMain calls forwarded to the original, per reason, with the first
+///   and latest time
+/// - installed hooks, and deferred hooks still waiting for their module
+/// - whether passthrough has every hook suspended
+/// - the role of this copy of the proxy among the copies in the process,
+///   and their status (instance.rs)
+/// - handlers deciding on intercepted calls, in the order they run
+/// - hits and misses of the export resolution cache (export_cache.rs)
+/// - error counters
+
+use crate::proxy::{self, ReasonStats};
+use crate::proxy_impl::deferred::{self, DeferredInfo};
+use crate::proxy_impl::export_cache::ExportCacheStats;
+use crate::proxy_impl::hooks::{self, HookInfo};
+use crate::proxy_impl::instance::{self, Role};
+use crate::proxy_impl::rules::{self, HandlerInfo};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use winapi::shared::minwindef::DWORD;
+
+/// Lifecycle of the proxy inside the host process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InitState {
+    NotStarted = 0,
+    Initializing = 1,
+    Initialized = 2,
+    Failed = 3,
+}
+
+impl InitState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => InitState::Initializing,
+            2 => InitState::Initialized,
+            3 => InitState::Failed,
+            _ => InitState::NotStarted,
+        }
+    }
+}
+
+/// Categories of errors counted for the status report
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorKind {
+    /// Loading or resolving the original DLL failed
+    Load,
+    /// A call could not be forwarded to the original
+    Forward,
+    /// Resolving or installing a detour failed
+    Detour,
+    /// A configured byte patch could not be applied
+    Patch,
+    /// A call into the original did not return in time (watchdog.rs)
+    Hang,
+}
+
+static INIT_STATE: AtomicU8 = AtomicU8::new(InitState::NotStarted as u8);
+static LOAD_ERRORS: AtomicU32 = AtomicU32::new(0);
+static FORWARD_ERRORS: AtomicU32 = AtomicU32::new(0);
+static DETOUR_ERRORS: AtomicU32 = AtomicU32::new(0);
+static PATCH_ERRORS: AtomicU32 = AtomicU32::new(0);
+static HANGS: AtomicU32 = AtomicU32::new(0);
+
+pub fn set_init_state(state: InitState) {
+    INIT_STATE.store(state as u8, Ordering::SeqCst);
+}
+
+pub fn init_state() -> InitState {
+    InitState::from_u8(INIT_STATE.load(Ordering::SeqCst))
+}
+
+pub fn record_error(kind: ErrorKind) {
+    let counter = match kind {
+        ErrorKind::Load => &LOAD_ERRORS,
+        ErrorKind::Forward => &FORWARD_ERRORS,
+        ErrorKind::Detour => &DETOUR_ERRORS,
+        ErrorKind::Patch => &PATCH_ERRORS,
+        ErrorKind::Hang => &HANGS,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+// ============================================================================
+// Status Snapshot
+// ============================================================================
+
+#[derive(Serialize)]
+pub struct StatusReport {
+    pub proxy_version: &'static str,
+    pub build_id: &'static str,
+    pub state: InitState,
+    pub original_dll_path: Option<String>,
+    pub original_dll_base: Option<String>,
+    pub dllmain: Vec<ReasonStats>,
+    pub hooks: Vec<HookInfo>,
+    pub passthrough: bool,
+    pub instance: Role,
+    pub deferred: Vec<DeferredInfo>,
+    pub handlers: Vec<HandlerInfo>,
+    pub export_cache: ExportCacheStats,
+    pub errors: ErrorCounters,
+    /// Status of the other copies of the proxy, without their peers
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub peers: Vec<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+pub struct ErrorCounters {
+    pub load: u32,
+    pub forward: u32,
+    pub detour: u32,
+    pub patch: u32,
+    pub hang: u32,
+}
+
+/// Collect the current status, with the other copies of the proxy
+pub unsafe fn snapshot() -> StatusReport {
+    let mut report = local_snapshot();
+    report.peers = instance::peer_statuses();
+    report
+}
+
+/// The status of this copy alone
+unsafe fn local_snapshot() -> StatusReport {
+    let base = proxy::get_original_dll_base();
+    let (path, base) = if base.is_null() {
+        (None, None)
+    } else {
+        (
+            proxy::get_module_path(base).map(|p| p.display().to_string()),
+            Some(format!("{:p}", base)),
+        )
+    };
+
+    StatusReport {
+        proxy_version: env!("CARGO_PKG_VERSION"),
+        build_id: env!("REFLEX_BUILD_ID"),
+        state: init_state(),
+        original_dll_path: path,
+        original_dll_base: base,
+        dllmain: proxy::dllmain_stats(),
+        hooks: hooks::list_hooks(),
+        passthrough: hooks::passthrough(),
+        instance: instance::role(),
+        deferred: deferred::pending(),
+        handlers: rules::handlers(),
+        export_cache: proxy::export_cache_stats(),
+        errors: ErrorCounters {
+            load: LOAD_ERRORS.load(Ordering::Relaxed),
+            forward: FORWARD_ERRORS.load(Ordering::Relaxed),
+            detour: DETOUR_ERRORS.load(Ordering::Relaxed),
+            patch: PATCH_ERRORS.load(Ordering::Relaxed),
+            hang: HANGS.load(Ordering::Relaxed),
+        },
+        peers: Vec::new(),
+    }
+}
+
+/// Query the proxy status as a NUL-terminated UTF-8 JSON string
+///
+/// Returns the number of bytes required, including the terminator. The
+/// buffer is only written if `len` is at least that large, so callers can
+/// pass a null buffer first to size their allocation.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn ReflexProxyGetStatus(buffer: *mut u8, len: DWORD) -> DWORD {
+    write_json(&snapshot(), buffer, len)
+}
+
+/// `ReflexProxyGetStatus` for the other copies of the proxy
+/// (`instance::PeerStatusFn`); without peers, so copies never query each
+/// other in a loop
+pub unsafe extern "system" fn peer_status(buffer: *mut u8, len: DWORD) -> DWORD {
+    write_json(&local_snapshot(), buffer, len)
+}
+
+unsafe fn write_json(report: &StatusReport, buffer: *mut u8, len: DWORD) -> DWORD {
+    match serde_json::to_string(report) {
+        Ok(json) => write_string(&json, buffer, len),
+        Err(e) => {
+            tracing::error!("[status] Failed to serialize status: {}", e);
+            0
+        }
+    }
+}
+
+/// Query the build id as a NUL-terminated string, like
+/// `ReflexProxyGetStatus`
+///
+/// Two builds with the same id are built from the same sources, and with
+/// the same toolchain are byte-identical.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn ReflexProxyGetBuildId(buffer: *mut u8, len: DWORD) -> DWORD {
+    write_string(env!("REFLEX_BUILD_ID"), buffer, len)
+}
+
+/// Copy `text` and a terminator into `buffer` if it fits; returns the size
+/// needed
+unsafe fn write_string(text: &str, buffer: *mut u8, len: DWORD) -> DWORD {
+    let required = text.len() + 1;
+    if !buffer.is_null() && len as usize >= required {
+        std::ptr::copy_nonoverlapping(text.as_ptr(), buffer, text.len());
+        *buffer.add(text.len()) = 0;
+    }
+
+    required as DWORD
+}
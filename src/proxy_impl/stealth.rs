@@ -0,0 +1,347 @@
+/// Stealth mode: hide the proxy from module enumeration
+///
+/// For studying whether the original DLL or the game enumerates loaded
+/// modules. With `[stealth] enabled = true` the proxy's loader entry is
+/// unlinked from the PEB's load-order and memory-order module lists, which
+/// is what EnumProcessModules, Toolhelp snapshots and PEB walkers read.
+///
+/// The entry stays in the initialization-order list (so DLL_PROCESS_DETACH
+/// is still delivered at exit) and in the loader's hash table (so
+/// GetModuleHandle("reflex.dll") still works). The unlinked links point at
+/// the entry itself, which keeps a later unload harmless, and `unhide`
+/// puts the entry back at the tail of both lists during shutdown.
+///
+/// With `log_enumeration = true` the module-enumeration APIs are hooked
+/// (inline, process-wide) and every call afterwards is logged and audited
+/// with whether reflex_original.dll is on the call stack.
+///
+/// ```toml
+/// [stealth]
+/// enabled = true
+/// log_enumeration = true
+/// ```
+///
+/// x64 only. Must be called with the loader lock held (from DllMain).
+
+use crate::proxy;
+use crate::proxy_impl::audit::{self, Category};
+use crate::proxy_impl::config::StealthConfig;
+use crate::proxy_impl::hooks;
+use crate::proxy_impl::rules::Decision;
+use crate::proxy_impl::status::{self, ErrorKind};
+use crate::proxy_impl::thread_scope;
+use serde_json::json;
+use std::ffi::CString;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use winapi::shared::minwindef::{BOOL, DWORD, HMODULE, LPDWORD};
+use winapi::shared::ntdef::{LIST_ENTRY, NTSTATUS, PVOID, ULONG, UNICODE_STRING};
+use winapi::um::libloaderapi::{GetModuleHandleA, GetProcAddress};
+use winapi::um::winnt::HANDLE;
+
+/// Start of LDR_DATA_TABLE_ENTRY (x64)
+#[repr(C)]
+struct LdrDataTableEntry {
+    in_load_order_links: LIST_ENTRY,
+    in_memory_order_links: LIST_ENTRY,
+    in_initialization_order_links: LIST_ENTRY,
+    dll_base: PVOID,
+    entry_point: PVOID,
+    size_of_image: ULONG,
+    full_dll_name: UNICODE_STRING,
+    base_dll_name: UNICODE_STRING,
+}
+
+/// Start of PEB_LDR_DATA (x64)
+#[repr(C)]
+struct PebLdrData {
+    length: ULONG,
+    initialized: u8,
+    ss_handle: PVOID,
+    in_load_order_module_list: LIST_ENTRY,
+    in_memory_order_module_list: LIST_ENTRY,
+}
+
+/// Offset of PEB.Ldr (x64)
+const PEB_LDR_OFFSET: usize = 0x18;
+
+/// Address of the unlinked LDR entry, 0 while visible
+static HIDDEN_ENTRY: AtomicUsize = AtomicUsize::new(0);
+
+type CreateToolhelp32SnapshotFn = unsafe extern "system" fn(DWORD, DWORD) -> HANDLE;
+type Module32Fn = unsafe extern "system" fn(HANDLE, PVOID) -> BOOL;
+type EnumProcessModulesFn = unsafe extern "system" fn(HANDLE, *mut HMODULE, DWORD, LPDWORD) -> BOOL;
+type EnumProcessModulesExFn = unsafe extern "system" fn(HANDLE, *mut HMODULE, DWORD, LPDWORD, DWORD) -> BOOL;
+type LdrEnumerateLoadedModulesFn = unsafe extern "system" fn(ULONG, PVOID, PVOID) -> NTSTATUS;
+
+/// A hooked enumeration API and the modules that may export it, most
+/// specific first (kernel32 only has short jump stubs for the K32* APIs)
+struct EnumApi {
+    name: &'static str,
+    modules: &'static [&'static [u8]],
+    detour: usize,
+    original: &'static AtomicUsize,
+}
+
+static CREATE_TOOLHELP32_SNAPSHOT: AtomicUsize = AtomicUsize::new(0);
+static MODULE32_FIRST_W: AtomicUsize = AtomicUsize::new(0);
+static MODULE32_NEXT_W: AtomicUsize = AtomicUsize::new(0);
+static ENUM_PROCESS_MODULES: AtomicUsize = AtomicUsize::new(0);
+static ENUM_PROCESS_MODULES_EX: AtomicUsize = AtomicUsize::new(0);
+static LDR_ENUMERATE_LOADED_MODULES: AtomicUsize = AtomicUsize::new(0);
+
+fn enum_apis() -> [EnumApi; 6] {
+    [
+        EnumApi {
+            name: "CreateToolhelp32Snapshot",
+            modules: &[b"kernel32.dll\0"],
+            detour: hooked_create_toolhelp32_snapshot as *const () as usize,
+            original: &CREATE_TOOLHELP32_SNAPSHOT,
+        },
+        EnumApi {
+            name: "Module32FirstW",
+            modules: &[b"kernel32.dll\0"],
+            detour: hooked_module32_first_w as *const () as usize,
+            original: &MODULE32_FIRST_W,
+        },
+        EnumApi {
+            name: "Module32NextW",
+            modules: &[b"kernel32.dll\0"],
+            detour: hooked_module32_next_w as *const () as usize,
+            original: &MODULE32_NEXT_W,
+        },
+        EnumApi {
+            name: "K32EnumProcessModules",
+            modules: &[b"kernelbase.dll\0", b"kernel32.dll\0"],
+            detour: hooked_enum_process_modules as *const () as usize,
+            original: &ENUM_PROCESS_MODULES,
+        },
+        EnumApi {
+            name: "K32EnumProcessModulesEx",
+            modules: &[b"kernelbase.dll\0", b"kernel32.dll\0"],
+            detour: hooked_enum_process_modules_ex as *const () as usize,
+            original: &ENUM_PROCESS_MODULES_EX,
+        },
+        EnumApi {
+            name: "LdrEnumerateLoadedModules",
+            modules: &[b"ntdll.dll\0"],
+            detour: hooked_ldr_enumerate_loaded_modules as *const () as usize,
+            original: &LDR_ENUMERATE_LOADED_MODULES,
+        },
+    ]
+}
+
+/// Hide the module at `base` and, if configured, start logging enumeration
+pub unsafe fn initialize(base: usize, config: &StealthConfig) {
+    match hide(base) {
+        Ok(name) => tracing::info!("[stealth] Unlinked {} from the loader module lists", name),
+        Err(e) => {
+            tracing::error!("[stealth] Failed to hide module: {}", e);
+            return;
+        }
+    }
+
+    if config.log_enumeration {
+        let hooked = hook_enumeration_apis();
+        tracing::info!("[stealth] Logging module enumeration ({} hook(s))", hooked);
+    }
+}
+
+/// Unlink the LDR entry of the module at `base` from the load-order and
+/// memory-order lists; returns the module name
+pub unsafe fn hide(base: usize) -> Result<String, String> {
+    if HIDDEN_ENTRY.load(Ordering::SeqCst) != 0 {
+        return Err("already hidden".to_string());
+    }
+
+    let ldr = loader_data();
+    let entry = find_entry(ldr, base).ok_or_else(|| format!("no loader entry for 0x{:x}", base))?;
+
+    unlink(&mut (*entry).in_load_order_links);
+    unlink(&mut (*entry).in_memory_order_links);
+    HIDDEN_ENTRY.store(entry as usize, Ordering::SeqCst);
+
+    Ok(unicode_string(&(*entry).base_dll_name))
+}
+
+/// Put a hidden entry back at the tail of the lists; no-op when visible
+pub unsafe fn unhide() -> bool {
+    let entry = HIDDEN_ENTRY.swap(0, Ordering::SeqCst) as *mut LdrDataTableEntry;
+    if entry.is_null() {
+        return false;
+    }
+
+    let ldr = loader_data();
+    insert_tail(&mut (*ldr).in_load_order_module_list, &mut (*entry).in_load_order_links);
+    insert_tail(&mut (*ldr).in_memory_order_module_list, &mut (*entry).in_memory_order_links);
+    true
+}
+
+unsafe fn hook_enumeration_apis() -> usize {
+    let mut hooked = 0;
+    for api in enum_apis().iter() {
+        let target = match resolve(api) {
+            Some(target) => target,
+            None => {
+                tracing::debug!("[stealth] {} not found", api.name);
+                continue;
+            }
+        };
+
+        match hooks::install_inline_hook_into(&format!("stealth!{}", api.name), target, api.detour, api.original) {
+            Ok(()) => hooked += 1,
+            Err(e) => {
+                status::record_error(ErrorKind::Detour);
+                tracing::warn!("[stealth] Failed to hook {}: {}", api.name, e);
+            }
+        }
+    }
+    hooked
+}
+
+unsafe fn resolve(api: &EnumApi) -> Option<usize> {
+    let name = CString::new(api.name).unwrap();
+    api.modules.iter().find_map(|module| {
+        let handle = GetModuleHandleA(module.as_ptr() as *const i8);
+        if handle.is_null() {
+            return None;
+        }
+        match GetProcAddress(handle, name.as_ptr()) as usize {
+            0 => None,
+            address => Some(address),
+        }
+    })
+}
+
+// ============================================================================
+// Hook Implementations
+// ============================================================================
+
+unsafe extern "system" fn hooked_create_toolhelp32_snapshot(flags: DWORD, process_id: DWORD) -> HANDLE {
+    let original: CreateToolhelp32SnapshotFn = std::mem::transmute(CREATE_TOOLHELP32_SNAPSHOT.load(Ordering::SeqCst));
+    let snapshot = original(flags, process_id);
+
+    report("CreateToolhelp32Snapshot", snapshot as i64, || {
+        json!({ "flags": format!("0x{:x}", flags), "pid": process_id })
+    });
+    snapshot
+}
+
+unsafe extern "system" fn hooked_module32_first_w(snapshot: HANDLE, entry: PVOID) -> BOOL {
+    let original: Module32Fn = std::mem::transmute(MODULE32_FIRST_W.load(Ordering::SeqCst));
+    let result = original(snapshot, entry);
+
+    report("Module32FirstW", result as i64, || json!({ "snapshot": format!("{:p}", snapshot) }));
+    result
+}
+
+unsafe extern "system" fn hooked_module32_next_w(snapshot: HANDLE, entry: PVOID) -> BOOL {
+    let original: Module32Fn = std::mem::transmute(MODULE32_NEXT_W.load(Ordering::SeqCst));
+    let result = original(snapshot, entry);
+
+    report("Module32NextW", result as i64, || json!({ "snapshot": format!("{:p}", snapshot) }));
+    result
+}
+
+unsafe extern "system" fn hooked_enum_process_modules(
+    process: HANDLE,
+    modules: *mut HMODULE,
+    size: DWORD,
+    needed: LPDWORD,
+) -> BOOL {
+    let original: EnumProcessModulesFn = std::mem::transmute(ENUM_PROCESS_MODULES.load(Ordering::SeqCst));
+    let result = original(process, modules, size, needed);
+
+    report("EnumProcessModules", result as i64, || {
+        json!({ "process": format!("{:p}", process), "size": size })
+    });
+    result
+}
+
+unsafe extern "system" fn hooked_enum_process_modules_ex(
+    process: HANDLE,
+    modules: *mut HMODULE,
+    size: DWORD,
+    needed: LPDWORD,
+    filter: DWORD,
+) -> BOOL {
+    let original: EnumProcessModulesExFn = std::mem::transmute(ENUM_PROCESS_MODULES_EX.load(Ordering::SeqCst));
+    let result = original(process, modules, size, needed, filter);
+
+    report("EnumProcessModulesEx", result as i64, || {
+        json!({ "process": format!("{:p}", process), "size": size, "filter": filter })
+    });
+    result
+}
+
+unsafe extern "system" fn hooked_ldr_enumerate_loaded_modules(flags: ULONG, callback: PVOID, context: PVOID) -> NTSTATUS {
+    let original: LdrEnumerateLoadedModulesFn =
+        std::mem::transmute(LDR_ENUMERATE_LOADED_MODULES.load(Ordering::SeqCst));
+    let status = original(flags, callback, context);
+
+    report("LdrEnumerateLoadedModules", status as i64, || json!({ "flags": flags }));
+    status
+}
+
+// ============================================================================
+// Utility Functions
+// ============================================================================
+
+/// Log and audit a completed call, unless it is out of the thread's scope
+/// or re-entrant
+fn report(api: &str, result: i64, args: impl FnOnce() -> serde_json::Value) {
+    if !thread_scope::in_scope(api) {
+        return;
+    }
+    // Calls made by the reporting itself (walking the caller's stack,
+    // writing the audit log, ...) are not reported again
+    let _reporting = thread_scope::bypass();
+
+    let caller = if unsafe { proxy::called_from_original() } { "original" } else { "other" };
+    let mut args = args();
+    args["caller"] = json!(caller);
+
+    tracing::info!("[stealth] {} by {} {}", api, caller, args);
+    audit::record(Category::Process, api, args, &Decision::Allow, result);
+}
+
+unsafe fn loader_data() -> *mut PebLdrData {
+    let peb: usize;
+    std::arch::asm!("mov {}, gs:[0x60]", out(reg) peb, options(nostack, readonly, preserves_flags));
+    *((peb + PEB_LDR_OFFSET) as *const *mut PebLdrData)
+}
+
+unsafe fn find_entry(ldr: *mut PebLdrData, base: usize) -> Option<*mut LdrDataTableEntry> {
+    let head = &mut (*ldr).in_load_order_module_list as *mut LIST_ENTRY;
+    let mut link = (*head).Flink;
+    while link != head {
+        // in_load_order_links is the first field
+        let entry = link as *mut LdrDataTableEntry;
+        if (*entry).dll_base as usize == base {
+            return Some(entry);
+        }
+        link = (*link).Flink;
+    }
+    None
+}
+
+/// Remove `link` from its list and point it at itself
+unsafe fn unlink(link: *mut LIST_ENTRY) {
+    (*(*link).Blink).Flink = (*link).Flink;
+    (*(*link).Flink).Blink = (*link).Blink;
+    (*link).Flink = link;
+    (*link).Blink = link;
+}
+
+unsafe fn insert_tail(head: *mut LIST_ENTRY, link: *mut LIST_ENTRY) {
+    (*link).Flink = head;
+    (*link).Blink = (*head).Blink;
+    (*(*head).Blink).Flink = link;
+    (*head).Blink = link;
+}
+
+unsafe fn unicode_string(string: &UNICODE_STRING) -> String {
+    if string.Buffer.is_null() {
+        return String::new();
+    }
+    String::from_utf16_lossy(std::slice::from_raw_parts(string.Buffer, string.Length as usize / 2))
+}
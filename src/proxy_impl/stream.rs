@@ -0,0 +1,264 @@
+/// Live trace streaming over WebSocket
+///
+/// Serves closed spans (trace.rs) to WebSocket clients on
+/// `ws://127.0.0.1:<port>/` while the game runs, one JSON text message per
+/// span, so a browser dashboard can show calls and their latency live:
+///
+/// ```json
+/// {"name":"DeleteFileW","cat":"detours","ph":"X","ts":1520.3,"dur":12.1,"pid":4242,"tid":7,"args":{"path":"C:\\tmp\\x.cfg"}}
+/// ```
+///
+/// ```toml
+/// [stream]
+/// enabled = true
+/// port = 9230
+/// ```
+///
+/// ```js
+/// new WebSocket("ws://127.0.0.1:9230").onmessage = (m) => console.log(JSON.parse(m.data));
+/// ```
+///
+/// Only loopback connections are accepted. WebSockets are not covered by
+/// CORS, so an upgrade from a web page (an `Origin` header) is refused with
+/// 403 unless the origin is listed in `origins`, e.g. the dashboard's
+/// `"http://localhost:8000"` or `"null"` for a page opened from disk; a
+/// `Host` other than `127.0.0.1:<port>` or `localhost:<port>` is refused
+/// too. Spans are queued for a sender
+/// thread; when the queue is full (slow clients) new spans are dropped
+/// rather than slowing down the hooked calls. Messages from clients are
+/// not read.
+
+use crate::proxy_impl::config::StreamConfig;
+use crate::proxy_impl::{hash, http};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Appended to the client key for `Sec-WebSocket-Accept` (RFC 6455)
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// A client that cannot take a message within this time is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static PORT: AtomicU16 = AtomicU16::new(0);
+static CLIENTS: Mutex<Vec<TcpStream>> = Mutex::new(Vec::new());
+static QUEUE: Mutex<Option<SyncSender<String>>> = Mutex::new(None);
+/// Length of `CLIENTS`, readable without the lock
+static CLIENT_COUNT: AtomicUsize = AtomicUsize::new(0);
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+/// Web page origins allowed to connect
+static ORIGINS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Start listening; spans are streamed from now on
+pub fn start(config: &StreamConfig) -> Result<(), String> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, config.port)).map_err(|e| {
+        RUNNING.store(false, Ordering::SeqCst);
+        format!("Failed to listen on 127.0.0.1:{}: {}", config.port, e)
+    })?;
+    PORT.store(config.port, Ordering::SeqCst);
+    *ORIGINS.lock().unwrap() = config.origins.clone();
+
+    let (sender, receiver) = mpsc::sync_channel(config.queue.max(1));
+    *QUEUE.lock().unwrap() = Some(sender);
+
+    let spawned = std::thread::Builder::new()
+        .name("reflex-stream-accept".to_string())
+        .spawn(move || accept_loop(listener))
+        .and_then(|_| {
+            std::thread::Builder::new()
+                .name("reflex-stream-send".to_string())
+                .spawn(move || send_loop(receiver))
+        });
+    if let Err(e) = spawned {
+        stop();
+        return Err(format!("Failed to spawn stream threads: {}", e));
+    }
+
+    tracing::info!("[stream] Streaming spans on ws://127.0.0.1:{}/", config.port);
+    Ok(())
+}
+
+/// Close all clients and let the threads exit
+pub fn stop() {
+    if !RUNNING.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    // Closing the queue ends the sender thread; a connection wakes accept()
+    QUEUE.lock().unwrap().take();
+    let _ = TcpStream::connect_timeout(
+        &SocketAddr::from((Ipv4Addr::LOCALHOST, PORT.load(Ordering::SeqCst))),
+        Duration::from_millis(100),
+    );
+    CLIENTS.lock().unwrap().clear();
+    CLIENT_COUNT.store(0, Ordering::Relaxed);
+
+    let dropped = DROPPED.load(Ordering::Relaxed);
+    if dropped > 0 {
+        tracing::warn!("[stream] {} span(s) were dropped because clients were too slow", dropped);
+    }
+}
+
+/// Whether spans are being collected for streaming
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+/// Whether `publish` has anyone to send to
+pub fn has_clients() -> bool {
+    RUNNING.load(Ordering::Relaxed) && CLIENT_COUNT.load(Ordering::Relaxed) > 0
+}
+
+/// Queue one serialized span for the connected clients; never blocks
+pub fn publish(message: String) {
+    if let Some(queue) = QUEUE.lock().unwrap().as_ref() {
+        if let Err(TrySendError::Full(_)) = queue.try_send(message) {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// ============================================================================
+// Server Threads
+// ============================================================================
+
+fn accept_loop(listener: TcpListener) {
+    for connection in listener.incoming() {
+        if !RUNNING.load(Ordering::SeqCst) {
+            break;
+        }
+        let stream = match connection {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::debug!("[stream] accept failed: {}", e);
+                continue;
+            }
+        };
+
+        match handshake(&stream) {
+            Ok(()) => {
+                let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+                let _ = stream.set_nodelay(true);
+                tracing::info!("[stream] Client connected from {:?}", stream.peer_addr().ok());
+                let mut clients = CLIENTS.lock().unwrap();
+                clients.push(stream);
+                CLIENT_COUNT.store(clients.len(), Ordering::Relaxed);
+            }
+            Err(e) => tracing::debug!("[stream] Rejected connection: {}", e),
+        }
+    }
+}
+
+fn send_loop(receiver: Receiver<String>) {
+    while let Ok(message) = receiver.recv() {
+        let frame = text_frame(message.as_bytes());
+        let mut clients = CLIENTS.lock().unwrap();
+        clients.retain_mut(|client| match client.write_all(&frame) {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::info!("[stream] Client disconnected: {}", e);
+                false
+            }
+        });
+        CLIENT_COUNT.store(clients.len(), Ordering::Relaxed);
+    }
+}
+
+/// Answer the HTTP upgrade request of a WebSocket client
+fn handshake(stream: &TcpStream) -> Result<(), String> {
+    stream.set_read_timeout(Some(Duration::from_secs(2))).map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(stream);
+
+    let (mut key, mut host, mut origin) = (None, None, None);
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).map_err(|e| e.to_string())?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let (name, value) = (name.trim(), value.trim().to_string());
+            if name.eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value);
+            } else if name.eq_ignore_ascii_case("host") {
+                host = Some(value);
+            } else if name.eq_ignore_ascii_case("origin") {
+                origin = Some(value);
+            }
+        }
+    }
+
+    let mut stream = stream;
+    let allowed = origin
+        .as_deref()
+        .is_none_or(|origin| ORIGINS.lock().unwrap().iter().any(|o| o.eq_ignore_ascii_case(origin)));
+    if !allowed || !http::is_local_host(host.as_deref(), PORT.load(Ordering::SeqCst)) {
+        let _ = stream.write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n");
+        tracing::warn!("[stream] Refused a client from a web page (Host {:?}, Origin {:?})", host, origin);
+        return Err("request from a web page".to_string());
+    }
+
+    let key = match key {
+        Some(key) => key,
+        None => {
+            let _ = stream.write_all(b"HTTP/1.1 426 Upgrade Required\r\nUpgrade: websocket\r\nContent-Length: 0\r\n\r\n");
+            return Err("not a WebSocket upgrade request".to_string());
+        }
+    };
+
+    let accept = base64(&hash::sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes())?);
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept
+    );
+    stream.write_all(response.as_bytes()).map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Encoding
+// ============================================================================
+
+/// Unmasked, unfragmented server text frame
+fn text_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x81);
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
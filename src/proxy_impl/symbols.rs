@@ -0,0 +1,159 @@
+/// Symbol names for code addresses, via dbghelp
+///
+/// With `[symbols]` enabled, frames in logged call stacks (stacks.rs) and
+/// the faulting address of crash reports (logging.rs) are resolved to
+/// `module!function+0x1c` instead of `module+rva`. dbghelp uses a module's
+/// PDB when it finds one next to the module or on `search_path`, else its
+/// exports; addresses it cannot name keep the `module+rva` form.
+///
+/// ```toml
+/// [symbols]
+/// enabled = true
+/// search_path = "C:\\symbols;srv*C:\\symbols*https://msdl.microsoft.com/download/symbols"
+/// ```
+///
+/// dbghelp.dll is loaded from System32 and initialized on first use. Its
+/// functions are not thread-safe, so every call holds one lock; results are
+/// cached per address.
+
+use crate::proxy_impl::config::SymbolConfig;
+use crate::proxy_impl::os;
+use crate::proxy_impl::wide::to_wide;
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE};
+use winapi::um::processthreadsapi::GetCurrentProcess;
+use winapi::um::winnt::HANDLE;
+
+/// `SYMOPT_UNDNAME | SYMOPT_DEFERRED_LOADS | SYMOPT_FAIL_CRITICAL_ERRORS`
+const SYMBOL_OPTIONS: DWORD = 0x2 | 0x4 | 0x200;
+/// Longest symbol name read, in characters
+const MAX_NAME: usize = 512;
+
+type SymSetOptionsFn = unsafe extern "system" fn(DWORD) -> DWORD;
+type SymInitializeWFn = unsafe extern "system" fn(HANDLE, *const u16, BOOL) -> BOOL;
+type SymRefreshModuleListFn = unsafe extern "system" fn(HANDLE) -> BOOL;
+type SymFromAddrWFn = unsafe extern "system" fn(HANDLE, u64, *mut u64, *mut SymbolInfo) -> BOOL;
+
+/// `SYMBOL_INFOW`
+#[repr(C)]
+struct SymbolInfo {
+    size_of_struct: u32,
+    type_index: u32,
+    reserved: [u64; 2],
+    index: u32,
+    size: u32,
+    mod_base: u64,
+    flags: u32,
+    value: u64,
+    address: u64,
+    register: u32,
+    scope: u32,
+    tag: u32,
+    name_len: u32,
+    max_name_len: u32,
+    name: [u16; 1],
+}
+
+/// `SYMBOL_INFOW` followed by room for the name
+#[repr(C)]
+struct SymbolBuffer {
+    info: SymbolInfo,
+    name: [u16; MAX_NAME],
+}
+
+struct DbgHelp {
+    process: HANDLE,
+    refresh_module_list: SymRefreshModuleListFn,
+    from_addr: SymFromAddrWFn,
+    /// Address -> `function+0x1c`, or None when dbghelp has no name
+    cache: HashMap<usize, Option<String>>,
+}
+
+// The process pseudo-handle and function pointers are usable from any thread
+unsafe impl Send for DbgHelp {}
+
+static CONFIG: OnceCell<SymbolConfig> = OnceCell::new();
+/// None when symbols are disabled or dbghelp failed to initialize
+static DBGHELP: OnceCell<Option<Mutex<DbgHelp>>> = OnceCell::new();
+
+/// Enable symbol lookups; nothing is loaded yet
+pub fn configure(config: &SymbolConfig) {
+    if config.enabled {
+        let _ = CONFIG.set(config.clone());
+    }
+}
+
+/// `function+0x1c` for a code address, if dbghelp can name it
+pub fn symbolize(address: usize) -> Option<String> {
+    let mut dbghelp = dbghelp()?.lock().unwrap();
+    if let Some(cached) = dbghelp.cache.get(&address) {
+        return cached.clone();
+    }
+
+    let name = unsafe { dbghelp.lookup(address) };
+    dbghelp.cache.insert(address, name.clone());
+    name
+}
+
+fn dbghelp() -> Option<&'static Mutex<DbgHelp>> {
+    DBGHELP
+        .get_or_init(|| {
+            let config = CONFIG.get()?;
+            match unsafe { initialize(config) } {
+                Ok(dbghelp) => {
+                    tracing::info!("[symbols] dbghelp initialized");
+                    Some(Mutex::new(dbghelp))
+                }
+                Err(e) => {
+                    tracing::warn!("[symbols] Frames stay unnamed: {}", e);
+                    None
+                }
+            }
+        })
+        .as_ref()
+}
+
+unsafe fn initialize(config: &SymbolConfig) -> Result<DbgHelp, String> {
+    let os = os::system();
+    let module = os.load_library("dbghelp.dll", os::LOAD_LIBRARY_SEARCH_SYSTEM32)?;
+    let function = |name: &str| os.get_proc_address(module, name).ok_or(format!("{} not found", name));
+
+    let set_options: SymSetOptionsFn = std::mem::transmute(function("SymSetOptions")?);
+    let initialize: SymInitializeWFn = std::mem::transmute(function("SymInitializeW")?);
+    let dbghelp = DbgHelp {
+        process: GetCurrentProcess(),
+        refresh_module_list: std::mem::transmute::<usize, SymRefreshModuleListFn>(function("SymRefreshModuleList")?),
+        from_addr: std::mem::transmute::<usize, SymFromAddrWFn>(function("SymFromAddrW")?),
+        cache: HashMap::new(),
+    };
+
+    set_options(SYMBOL_OPTIONS);
+    let search_path = (!config.search_path.is_empty()).then(|| to_wide(&config.search_path));
+    let search_path = search_path.as_ref().map_or(std::ptr::null(), |path| path.as_ptr());
+    if initialize(dbghelp.process, search_path, 1) == FALSE {
+        return Err(format!("SymInitializeW failed with error {}", winapi::um::errhandlingapi::GetLastError()));
+    }
+    Ok(dbghelp)
+}
+
+impl DbgHelp {
+    unsafe fn lookup(&self, address: usize) -> Option<String> {
+        // Pick up modules loaded since the last lookup
+        (self.refresh_module_list)(self.process);
+
+        let mut buffer: Box<SymbolBuffer> = Box::new(std::mem::zeroed());
+        buffer.info.size_of_struct = std::mem::size_of::<SymbolInfo>() as u32;
+        buffer.info.max_name_len = MAX_NAME as u32;
+
+        let mut displacement = 0u64;
+        if (self.from_addr)(self.process, address as u64, &mut displacement, &mut *buffer as *mut _ as *mut SymbolInfo) == FALSE {
+            return None;
+        }
+
+        let len = (buffer.info.name_len as usize).min(MAX_NAME);
+        let name = std::slice::from_raw_parts(std::ptr::addr_of!(buffer.info.name) as *const u16, len);
+        Some(format!("{}+0x{:x}", String::from_utf16_lossy(name), displacement))
+    }
+}
@@ -0,0 +1,293 @@
+/// NT syscall-stub hooks
+///
+/// The API detours (detours.rs) patch the import table of the original DLL,
+/// so they miss calls that go straight to ntdll, e.g. NtDeviceIoControlFile
+/// issued directly or through another system DLL. These hooks patch the
+/// ntdll stubs themselves (inline, see trampoline.rs) and report each call
+/// to the log and the audit log.
+///
+/// The stubs are process-wide; with `only_original = true` (default) only
+/// calls with reflex_original.dll on the call stack are reported. Calls are
+/// always forwarded unchanged.
+///
+/// ```toml
+/// [syscalls]
+/// enabled = true
+/// functions = ["NtCreateFile", "NtDeviceIoControlFile"]   # default: all supported
+/// ```
+
+use crate::proxy;
+use crate::proxy_impl::audit::{self, Category};
+use crate::proxy_impl::config::SyscallConfig;
+use crate::proxy_impl::{hooks, stacks, thread_scope};
+use crate::proxy_impl::rules::Decision;
+use crate::proxy_impl::status::{self, ErrorKind};
+use serde_json::json;
+use std::ffi::CString;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use winapi::shared::ntdef::{HANDLE, NTSTATUS, PLARGE_INTEGER, POBJECT_ATTRIBUTES, PULONG, PVOID, ULONG};
+use winapi::um::libloaderapi::{GetModuleHandleA, GetProcAddress};
+use winapi::um::winnt::ACCESS_MASK;
+
+type NtCreateFileFn = unsafe extern "system" fn(
+    *mut HANDLE,
+    ACCESS_MASK,
+    POBJECT_ATTRIBUTES,
+    PVOID,
+    PLARGE_INTEGER,
+    ULONG,
+    ULONG,
+    ULONG,
+    ULONG,
+    PVOID,
+    ULONG,
+) -> NTSTATUS;
+type NtDeviceIoControlFileFn = unsafe extern "system" fn(
+    HANDLE,
+    HANDLE,
+    PVOID,
+    PVOID,
+    PVOID,
+    ULONG,
+    PVOID,
+    ULONG,
+    PVOID,
+    ULONG,
+) -> NTSTATUS;
+type NtQueryInformationProcessFn = unsafe extern "system" fn(HANDLE, ULONG, PVOID, ULONG, PULONG) -> NTSTATUS;
+type NtQuerySystemInformationFn = unsafe extern "system" fn(ULONG, PVOID, ULONG, PULONG) -> NTSTATUS;
+
+/// A hookable ntdll stub
+struct Stub {
+    name: &'static str,
+    detour: usize,
+    original: &'static AtomicUsize,
+}
+
+static NT_CREATE_FILE: AtomicUsize = AtomicUsize::new(0);
+static NT_DEVICE_IO_CONTROL_FILE: AtomicUsize = AtomicUsize::new(0);
+static NT_QUERY_INFORMATION_PROCESS: AtomicUsize = AtomicUsize::new(0);
+static NT_QUERY_SYSTEM_INFORMATION: AtomicUsize = AtomicUsize::new(0);
+
+static ONLY_ORIGINAL: AtomicBool = AtomicBool::new(true);
+
+fn stubs() -> [Stub; 4] {
+    [
+        Stub {
+            name: "NtCreateFile",
+            detour: hooked_nt_create_file as *const () as usize,
+            original: &NT_CREATE_FILE,
+        },
+        Stub {
+            name: "NtDeviceIoControlFile",
+            detour: hooked_nt_device_io_control_file as *const () as usize,
+            original: &NT_DEVICE_IO_CONTROL_FILE,
+        },
+        Stub {
+            name: "NtQueryInformationProcess",
+            detour: hooked_nt_query_information_process as *const () as usize,
+            original: &NT_QUERY_INFORMATION_PROCESS,
+        },
+        Stub {
+            name: "NtQuerySystemInformation",
+            detour: hooked_nt_query_system_information as *const () as usize,
+            original: &NT_QUERY_SYSTEM_INFORMATION,
+        },
+    ]
+}
+
+/// Hook the configured ntdll stubs
+///
+/// Returns the number of stubs hooked.
+pub unsafe fn initialize(config: &SyscallConfig) -> usize {
+    ONLY_ORIGINAL.store(config.only_original, Ordering::Relaxed);
+
+    let ntdll = GetModuleHandleA(c"ntdll.dll".as_ptr());
+    if ntdll.is_null() {
+        tracing::error!("[syscalls] ntdll.dll is not loaded");
+        return 0;
+    }
+
+    for name in &config.functions {
+        if !stubs().iter().any(|s| s.name.eq_ignore_ascii_case(name)) {
+            tracing::warn!("[syscalls] Unsupported stub {}, ignored", name);
+        }
+    }
+
+    let mut hooked = 0;
+    for stub in stubs().iter() {
+        let wanted = config.functions.is_empty()
+            || config.functions.iter().any(|f| f.eq_ignore_ascii_case(stub.name));
+        if !wanted {
+            continue;
+        }
+
+        let name = CString::new(stub.name).unwrap();
+        let target = GetProcAddress(ntdll, name.as_ptr()) as usize;
+        if target == 0 {
+            tracing::warn!("[syscalls] ntdll!{} not found", stub.name);
+            continue;
+        }
+
+        match hooks::install_inline_hook_into(&format!("ntdll!{}", stub.name), target, stub.detour, stub.original) {
+            Ok(()) => hooked += 1,
+            Err(e) => {
+                status::record_error(ErrorKind::Detour);
+                tracing::warn!("[syscalls] Failed to hook {}: {}", stub.name, e);
+            }
+        }
+    }
+
+    tracing::info!("[syscalls] Hooked {} ntdll stub(s)", hooked);
+    hooked
+}
+
+// ============================================================================
+// Hook Implementations
+// ============================================================================
+
+unsafe extern "system" fn hooked_nt_create_file(
+    file_handle: *mut HANDLE,
+    desired_access: ACCESS_MASK,
+    object_attributes: POBJECT_ATTRIBUTES,
+    io_status_block: PVOID,
+    allocation_size: PLARGE_INTEGER,
+    file_attributes: ULONG,
+    share_access: ULONG,
+    create_disposition: ULONG,
+    create_options: ULONG,
+    ea_buffer: PVOID,
+    ea_length: ULONG,
+) -> NTSTATUS {
+    let original: NtCreateFileFn = std::mem::transmute(NT_CREATE_FILE.load(Ordering::SeqCst));
+    let status = original(
+        file_handle,
+        desired_access,
+        object_attributes,
+        io_status_block,
+        allocation_size,
+        file_attributes,
+        share_access,
+        create_disposition,
+        create_options,
+        ea_buffer,
+        ea_length,
+    );
+
+    report(Category::File, "NtCreateFile", status, || {
+        json!({
+            "path": object_name(object_attributes),
+            "desired_access": format!("0x{:x}", desired_access),
+            "create_disposition": create_disposition,
+        })
+    });
+    status
+}
+
+unsafe extern "system" fn hooked_nt_device_io_control_file(
+    file_handle: HANDLE,
+    event: HANDLE,
+    apc_routine: PVOID,
+    apc_context: PVOID,
+    io_status_block: PVOID,
+    io_control_code: ULONG,
+    input_buffer: PVOID,
+    input_buffer_length: ULONG,
+    output_buffer: PVOID,
+    output_buffer_length: ULONG,
+) -> NTSTATUS {
+    let original: NtDeviceIoControlFileFn =
+        std::mem::transmute(NT_DEVICE_IO_CONTROL_FILE.load(Ordering::SeqCst));
+    let status = original(
+        file_handle,
+        event,
+        apc_routine,
+        apc_context,
+        io_status_block,
+        io_control_code,
+        input_buffer,
+        input_buffer_length,
+        output_buffer,
+        output_buffer_length,
+    );
+
+    report(Category::Device, "NtDeviceIoControlFile", status, || {
+        json!({
+            "handle": format!("{:p}", file_handle),
+            "ioctl": format!("0x{:08x}", io_control_code),
+            "input_length": input_buffer_length,
+            "output_length": output_buffer_length,
+        })
+    });
+    status
+}
+
+unsafe extern "system" fn hooked_nt_query_information_process(
+    process_handle: HANDLE,
+    information_class: ULONG,
+    information: PVOID,
+    length: ULONG,
+    return_length: PULONG,
+) -> NTSTATUS {
+    let original: NtQueryInformationProcessFn =
+        std::mem::transmute(NT_QUERY_INFORMATION_PROCESS.load(Ordering::SeqCst));
+    let status = original(process_handle, information_class, information, length, return_length);
+
+    report(Category::Process, "NtQueryInformationProcess", status, || {
+        json!({
+            "process": format!("{:p}", process_handle),
+            "class": information_class,
+        })
+    });
+    status
+}
+
+unsafe extern "system" fn hooked_nt_query_system_information(
+    information_class: ULONG,
+    information: PVOID,
+    length: ULONG,
+    return_length: PULONG,
+) -> NTSTATUS {
+    let original: NtQuerySystemInformationFn =
+        std::mem::transmute(NT_QUERY_SYSTEM_INFORMATION.load(Ordering::SeqCst));
+    let status = original(information_class, information, length, return_length);
+
+    report(Category::System, "NtQuerySystemInformation", status, || {
+        json!({ "class": information_class })
+    });
+    status
+}
+
+// ============================================================================
+// Utility Functions
+// ============================================================================
+
+/// Log and audit a completed call, unless it is filtered out, out of the
+/// thread's scope or re-entrant
+unsafe fn report(category: Category, api: &str, status: NTSTATUS, args: impl FnOnce() -> serde_json::Value) {
+    if !thread_scope::in_scope(api) {
+        return;
+    }
+    // I/O done by the reporting itself (opening the audit log, ...) is not
+    // reported again
+    let _reporting = thread_scope::bypass();
+
+    if !ONLY_ORIGINAL.load(Ordering::Relaxed) || proxy::called_from_original() {
+        let args = args();
+        tracing::info!("[syscalls] {} {} -> 0x{:08x}", api, args, status);
+        stacks::capture(api);
+        audit::record(category, api, args, &Decision::Allow, status as i64);
+    }
+}
+
+unsafe fn object_name(attributes: POBJECT_ATTRIBUTES) -> Option<String> {
+    if attributes.is_null() || (*attributes).ObjectName.is_null() {
+        return None;
+    }
+    let name = &*(*attributes).ObjectName;
+    if name.Buffer.is_null() {
+        return None;
+    }
+    let chars = std::slice::from_raw_parts(name.Buffer, name.Length as usize / 2);
+    Some(String::from_utf16_lossy(chars))
+}
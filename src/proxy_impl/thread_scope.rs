@@ -0,0 +1,143 @@
+/// Per-thread hook scoping
+///
+/// Calls from some threads are passed straight to the original, without
+/// being decided on, logged or recorded:
+/// - calls from a thread holding a `bypass()` guard, e.g. an overlay render
+///   thread or the proxy's own instrumentation
+/// - calls from threads other than those `[hook_threads]` allows for a
+///   hook, by glob on the thread's name (its description, see
+///   SetThreadDescription, or its Rust name)
+///
+/// ```toml
+/// [hook_threads]
+/// DeleteFileW = ["GameThread", "IOWorker*"]
+/// ```
+///
+/// ```rust,ignore
+/// let _bypass = thread_scope::bypass();
+/// // API calls made here skip the detours
+/// ```
+///
+/// A thread's name is read on its first hooked call; a name given to it
+/// later is not seen. Hooks without an entry run on every thread.
+
+use crate::proxy_impl::{config, rules};
+use std::cell::{Cell, OnceCell};
+use std::marker::PhantomData;
+
+thread_local! {
+    /// Number of live `Bypass` guards on this thread
+    static BYPASS: Cell<u32> = const { Cell::new(0) };
+    static NAME: OnceCell<String> = const { OnceCell::new() };
+}
+
+/// Keeps the current thread out of the detours until dropped
+///
+/// Guards nest. Not `Send`: it belongs to the thread that created it.
+pub struct Bypass {
+    _thread: PhantomData<*const ()>,
+}
+
+impl Drop for Bypass {
+    fn drop(&mut self) {
+        let _ = BYPASS.try_with(|count| count.set(count.get().saturating_sub(1)));
+    }
+}
+
+/// Pass the current thread's calls to the originals while the guard lives
+pub fn bypass() -> Bypass {
+    let _ = BYPASS.try_with(|count| count.set(count.get() + 1));
+    Bypass { _thread: PhantomData }
+}
+
+/// Whether the current thread holds a `Bypass` guard
+///
+/// A thread whose TLS is already gone (it is exiting) counts as bypassed.
+pub fn bypassed() -> bool {
+    BYPASS.try_with(|count| count.get() > 0).unwrap_or(true)
+}
+
+/// Whether the detour of `hook` handles calls from the current thread
+pub fn in_scope(hook: &str) -> bool {
+    if bypassed() {
+        return false;
+    }
+    match config::get().hook_threads.get(hook) {
+        Some(patterns) => NAME
+            .try_with(|name| allowed(patterns, name.get_or_init(thread_name)))
+            .unwrap_or(false),
+        None => true,
+    }
+}
+
+/// Whether a thread named `name` matches one of the globs
+pub fn allowed(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|pattern| rules::glob_match(pattern, name))
+}
+
+/// The current thread's description, else its Rust name, else ""
+fn thread_name() -> String {
+    #[cfg(windows)]
+    if let Some(description) = unsafe { thread_description() } {
+        return description;
+    }
+    std::thread::current().name().unwrap_or_default().to_string()
+}
+
+/// GetThreadDescription (Windows 10 1607+), resolved at runtime
+#[cfg(windows)]
+unsafe fn thread_description() -> Option<String> {
+    use crate::proxy_impl::wide::from_wide_ptr;
+    use winapi::shared::minwindef::FARPROC;
+    use winapi::um::libloaderapi::{GetModuleHandleA, GetProcAddress};
+    use winapi::um::processthreadsapi::GetCurrentThread;
+    use winapi::um::winbase::LocalFree;
+    use winapi::um::winnt::{HANDLE, HRESULT, PWSTR};
+
+    type GetThreadDescriptionFn = unsafe extern "system" fn(HANDLE, *mut PWSTR) -> HRESULT;
+
+    let kernel32 = GetModuleHandleA(c"kernel32.dll".as_ptr());
+    let function = GetProcAddress(kernel32, c"GetThreadDescription".as_ptr());
+    if function.is_null() {
+        return None;
+    }
+    // SAFETY: GetThreadDescription has this signature
+    let get_thread_description = std::mem::transmute::<FARPROC, GetThreadDescriptionFn>(function);
+
+    let mut description: PWSTR = std::ptr::null_mut();
+    if get_thread_description(GetCurrentThread(), &mut description) < 0 || description.is_null() {
+        return None;
+    }
+    let name = from_wide_ptr(description);
+    LocalFree(description as _);
+    name.filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bypass_guards_nest_per_thread() {
+        assert!(!bypassed());
+        let outer = bypass();
+        {
+            let _inner = bypass();
+            assert!(bypassed());
+            assert!(!std::thread::spawn(bypassed).join().unwrap());
+        }
+        assert!(bypassed());
+        drop(outer);
+        assert!(!bypassed());
+    }
+
+    #[test]
+    fn threads_are_allowed_by_name() {
+        let patterns = ["GameThread".to_string(), "IOWorker*".to_string()];
+
+        assert!(allowed(&patterns, "gamethread"));
+        assert!(allowed(&patterns, "IOWorker 3"));
+        assert!(!allowed(&patterns, "RenderThread"));
+        assert!(!allowed(&patterns, ""));
+    }
+}
@@ -0,0 +1,115 @@
+/// TLS callbacks of the original DLL
+///
+/// The loader runs a DLL's TLS callbacks before its DllMain, for every
+/// DLL_PROCESS_ATTACH / THREAD_ATTACH / THREAD_DETACH / PROCESS_DETACH, so
+/// code in them runs before anything the proxy forwards. `watch` queues an
+/// installer (deferred.rs) that runs once reflex_original.dll is mapped but
+/// before it is initialized:
+/// - `log` lists the callbacks of the TLS directory
+/// - `hook` replaces the entries of the callback array with thunks that log
+///   each invocation (reason, thread, duration) and call the original
+///
+/// ```toml
+/// [tls]
+/// log = true
+/// hook = true
+/// ```
+///
+/// At most `MAX_CALLBACKS` callbacks are hooked. The array entries are
+/// restored during DLL_PROCESS_DETACH, before the original is released.
+/// A manually mapped original (manual_map.rs) is never announced by the
+/// loader, so its callbacks are neither listed nor hooked.
+
+use crate::proxy_impl::config::TlsConfig;
+use crate::proxy_impl::patches::PatchSet;
+use crate::proxy::reason_name;
+use crate::proxy_impl::{deferred, pe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::ntdef::PVOID;
+use winapi::um::processthreadsapi::GetCurrentThreadId;
+use winapi::um::winnt::{DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH};
+
+/// Callbacks hooked per module
+const MAX_CALLBACKS: usize = 8;
+
+type TlsCallbackFn = unsafe extern "system" fn(PVOID, DWORD, PVOID);
+
+static ORIGINALS: [AtomicUsize; MAX_CALLBACKS] = [const { AtomicUsize::new(0) }; MAX_CALLBACKS];
+/// Module base, for RVAs in the log
+static BASE: AtomicUsize = AtomicUsize::new(0);
+
+const THUNKS: [TlsCallbackFn; MAX_CALLBACKS] = [
+    callback_thunk::<0>,
+    callback_thunk::<1>,
+    callback_thunk::<2>,
+    callback_thunk::<3>,
+    callback_thunk::<4>,
+    callback_thunk::<5>,
+    callback_thunk::<6>,
+    callback_thunk::<7>,
+];
+
+/// Inspect (and optionally hook) the TLS callbacks of `module` before they
+/// first run
+pub unsafe fn watch(config: &TlsConfig, module: &str) {
+    let hook = config.hook;
+    deferred::when_loaded(module, "tls callbacks", move |base| unsafe { instrument(base, hook) });
+}
+
+unsafe fn instrument(base: usize, hook: bool) -> Result<(), String> {
+    let slots = pe::tls_callback_slots(base as *const u8);
+    if slots.is_empty() {
+        tracing::info!("[tls] The original has no TLS callbacks");
+        return Ok(());
+    }
+
+    BASE.store(base, Ordering::SeqCst);
+    for (index, &slot) in slots.iter().enumerate() {
+        tracing::info!("[tls] Callback #{} at rva 0x{:x}", index, *slot - base);
+    }
+    if !hook {
+        return Ok(());
+    }
+
+    let mut set = PatchSet::new("tls callbacks");
+    for (index, &slot) in slots.iter().enumerate().take(MAX_CALLBACKS) {
+        ORIGINALS[index].store(*slot, Ordering::SeqCst);
+        set.apply(slot as usize, &(THUNKS[index] as usize).to_le_bytes())?;
+    }
+    if slots.len() > MAX_CALLBACKS {
+        tracing::warn!("[tls] Only the first {} of {} callbacks are hooked", MAX_CALLBACKS, slots.len());
+    }
+
+    tracing::info!("[tls] Hooked {} callback(s)", set.len());
+    set.keep_until_detach();
+    Ok(())
+}
+
+unsafe extern "system" fn callback_thunk<const N: usize>(module: PVOID, reason: DWORD, reserved: PVOID) {
+    let original: TlsCallbackFn = std::mem::transmute(ORIGINALS[N].load(Ordering::SeqCst));
+    let started = Instant::now();
+    original(module, reason, reserved);
+    let elapsed = started.elapsed().as_micros();
+
+    let rva = ORIGINALS[N].load(Ordering::SeqCst) - BASE.load(Ordering::SeqCst);
+    match reason {
+        DLL_PROCESS_ATTACH | DLL_PROCESS_DETACH => tracing::info!(
+            "[tls] Callback #{} (rva 0x{:x}) {} on thread {} took {} us",
+            N,
+            rva,
+            reason_name(reason),
+            GetCurrentThreadId(),
+            elapsed
+        ),
+        _ => tracing::debug!(
+            "[tls] Callback #{} (rva 0x{:x}) {} on thread {} took {} us",
+            N,
+            rva,
+            reason_name(reason),
+            GetCurrentThreadId(),
+            elapsed
+        ),
+    }
+}
@@ -0,0 +1,399 @@
+/// Chrome trace-event output
+///
+/// Every `tracing` span (forwarded export calls, hooks) is timed and written
+/// in the Chrome trace JSON format, so a whole session can be opened on a
+/// timeline in Perfetto (ui.perfetto.dev) or chrome://tracing:
+///
+/// ```json
+/// {"traceEvents":[{"name":"DeleteFileW","cat":"detours","ph":"X","ts":1520.3,"dur":12.1,"pid":4242,"tid":7,"args":{"path":"C:\\tmp\\x.cfg"}},...]}
+/// ```
+///
+/// Enabled with `[trace] enabled = true` in reflex_proxy.toml. Events are
+/// kept in memory and written on shutdown or with the `trace` pipe command.
+/// With `format = "binary"` they are instead streamed to `path` as they
+/// close, in the compact format of binary_trace.rs, without `max_events`.
+/// Spans are also collected while stream.rs serves them live, even with
+/// `[trace]` disabled.
+/// Span arguments are redacted like log fields (`[logging.redact]`).
+/// correlation.rs adds instant ("i") events marking the session and frames.
+///
+/// How the game was launched (environment.rs: working directory, Steam or
+/// Epic, the variables named in `environment`) goes into the metadata:
+/// `otherData.launch` in Chrome JSON, a `Launch` event (category
+/// `metadata`) at the start of a binary trace.
+
+use crate::proxy_impl::binary_trace::{self, FieldValue};
+use crate::proxy_impl::config::TraceConfig;
+use crate::proxy_impl::correlation;
+use crate::proxy_impl::environment;
+use crate::proxy_impl::redact;
+#[cfg(feature = "network")]
+use crate::proxy_impl::stream;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::filter::{filter_fn, Filtered};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+use winapi::um::processthreadsapi::{GetCurrentProcessId, GetCurrentThreadId};
+
+#[derive(Serialize)]
+struct TraceEvent {
+    name: &'static str,
+    cat: &'static str,
+    ph: &'static str,
+    /// Microseconds since the proxy was loaded
+    ts: f64,
+    dur: f64,
+    pid: u32,
+    tid: u32,
+    args: Map<String, Value>,
+}
+
+#[derive(Serialize)]
+struct TraceFile<'a> {
+    #[serde(rename = "traceEvents")]
+    trace_events: &'a [TraceEvent],
+    #[serde(rename = "displayTimeUnit")]
+    display_time_unit: &'static str,
+    /// The session id of `[correlation]` and the launch
+    #[serde(rename = "otherData", skip_serializing_if = "Map::is_empty")]
+    other_data: Map<String, Value>,
+}
+
+/// Start time and fields of an open span, stored in its extensions
+struct SpanStart {
+    start: Instant,
+    thread: u32,
+    args: Map<String, Value>,
+}
+
+static START: Lazy<Instant> = Lazy::new(Instant::now);
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SETTINGS: OnceCell<(String, usize)> = OnceCell::new();
+static EVENTS: Lazy<Mutex<Vec<TraceEvent>>> = Lazy::new(|| Mutex::new(Vec::new()));
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+/// Output of the binary format; None for Chrome JSON
+static BINARY: Mutex<Option<binary_trace::Writer<BufWriter<File>>>> = Mutex::new(None);
+static WRITTEN: AtomicUsize = AtomicUsize::new(0);
+/// environment::Launch, redacted
+static LAUNCH: OnceCell<Map<String, Value>> = OnceCell::new();
+
+/// Subscriber layer that records closed spans as complete ("X") events
+pub struct TraceLayer;
+
+/// The trace layer, only interested in spans and only while enabled or
+/// streaming
+pub fn layer<S>() -> Filtered<TraceLayer, impl tracing_subscriber::layer::Filter<S>, S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    TraceLayer.with_filter(filter_fn(|metadata| {
+        metadata.is_span() && (ENABLED.load(Ordering::Relaxed) || streaming())
+    }))
+}
+
+/// Whether stream.rs serves spans live
+fn streaming() -> bool {
+    #[cfg(feature = "network")]
+    return stream::is_running();
+    #[cfg(not(feature = "network"))]
+    false
+}
+
+/// Apply the `[trace]` config section
+pub fn configure(config: &TraceConfig) {
+    if !config.enabled {
+        return;
+    }
+
+    Lazy::force(&START);
+    let launch = LAUNCH.get_or_init(|| launch(&config.environment));
+    match config.format.as_str() {
+        "chrome" => {}
+        "binary" => {
+            let writer = File::create(&config.path)
+                .and_then(|file| binary_trace::Writer::new(BufWriter::new(file), unsafe { GetCurrentProcessId() }))
+                .and_then(|mut writer| {
+                    let fields = flatten(launch);
+                    let fields: Vec<(&str, FieldValue)> =
+                        fields.iter().map(|(key, value)| (key.as_str(), field_value(value))).collect();
+                    writer.event("Launch", "metadata", 0, 0, unsafe { GetCurrentThreadId() }, &fields)?;
+                    Ok(writer)
+                });
+            match writer {
+                Ok(writer) => *BINARY.lock().unwrap() = Some(writer),
+                Err(e) => {
+                    tracing::error!("[trace] Failed to create {}: {}", config.path, e);
+                    return;
+                }
+            }
+        }
+        other => tracing::warn!("[trace] Unknown format '{}', writing Chrome JSON", other),
+    }
+    SETTINGS.get_or_init(|| (config.path.clone(), config.max_events));
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+impl<S> Layer<S> for TraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = ArgsVisitor(Map::new());
+        attrs.record(&mut visitor);
+        redact::fields(&mut visitor.0);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanStart {
+                start: Instant::now(),
+                thread: unsafe { GetCurrentThreadId() },
+                args: visitor.0,
+            });
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut extensions = span.extensions_mut();
+        let started = match extensions.remove::<SpanStart>() {
+            Some(started) => started,
+            None => return,
+        };
+
+        let end = Instant::now();
+        let metadata = span.metadata();
+
+        #[cfg(feature = "network")]
+        if stream::has_clients() {
+            let event = TraceEvent {
+                name: metadata.name(),
+                cat: category(metadata.target()),
+                ph: "X",
+                ts: micros(started.start.saturating_duration_since(*START)),
+                dur: micros(end.saturating_duration_since(started.start)),
+                pid: unsafe { GetCurrentProcessId() },
+                tid: started.thread,
+                args: started.args.clone(),
+            };
+            if let Ok(message) = serde_json::to_string(&event) {
+                stream::publish(message);
+            }
+        }
+        if !ENABLED.load(Ordering::Relaxed) {
+            return;
+        }
+
+        if let Some(writer) = BINARY.lock().unwrap().as_mut() {
+            let fields: Vec<(&str, FieldValue)> = started
+                .args
+                .iter()
+                .map(|(key, value)| (key.as_str(), field_value(value)))
+                .collect();
+            let written = writer.event(
+                metadata.name(),
+                category(metadata.target()),
+                started.start.saturating_duration_since(*START).as_nanos() as u64,
+                end.saturating_duration_since(started.start).as_nanos() as u64,
+                started.thread,
+                &fields,
+            );
+            match written {
+                Ok(()) => WRITTEN.fetch_add(1, Ordering::Relaxed),
+                Err(_) => DROPPED.fetch_add(1, Ordering::Relaxed),
+            };
+            return;
+        }
+
+        let event = TraceEvent {
+            name: metadata.name(),
+            cat: category(metadata.target()),
+            ph: "X",
+            ts: micros(started.start.saturating_duration_since(*START)),
+            dur: micros(end.saturating_duration_since(started.start)),
+            pid: unsafe { GetCurrentProcessId() },
+            tid: started.thread,
+            args: started.args,
+        };
+
+        let max_events = SETTINGS.get().map_or(0, |(_, max)| *max);
+        let mut events = EVENTS.lock().unwrap();
+        if events.len() < max_events {
+            events.push(event);
+        } else {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Record an instant event in category "correlation", e.g. a frame
+/// boundary
+pub fn mark(name: &'static str, args: Map<String, Value>) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let ts = Instant::now().saturating_duration_since(*START);
+    let thread = unsafe { GetCurrentThreadId() };
+
+    if let Some(writer) = BINARY.lock().unwrap().as_mut() {
+        let fields: Vec<(&str, FieldValue)> =
+            args.iter().map(|(key, value)| (key.as_str(), field_value(value))).collect();
+        match writer.event(name, "correlation", ts.as_nanos() as u64, 0, thread, &fields) {
+            Ok(()) => WRITTEN.fetch_add(1, Ordering::Relaxed),
+            Err(_) => DROPPED.fetch_add(1, Ordering::Relaxed),
+        };
+        return;
+    }
+
+    let event = TraceEvent {
+        name,
+        cat: "correlation",
+        ph: "i",
+        ts: micros(ts),
+        dur: 0.0,
+        pid: unsafe { GetCurrentProcessId() },
+        tid: thread,
+        args,
+    };
+    let max_events = SETTINGS.get().map_or(0, |(_, max)| *max);
+    let mut events = EVENTS.lock().unwrap();
+    if events.len() < max_events {
+        events.push(event);
+    } else {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Write all recorded spans to the configured trace file
+///
+/// Returns the file path and the number of events written.
+pub fn write() -> Result<(String, usize), String> {
+    let (path, max_events) = SETTINGS
+        .get()
+        .ok_or_else(|| "tracing is not enabled".to_string())?;
+
+    if let Some(writer) = BINARY.lock().unwrap().as_mut() {
+        writer.flush().map_err(|e| format!("failed to write {}: {}", path, e))?;
+        let dropped = DROPPED.load(Ordering::Relaxed);
+        if dropped > 0 {
+            tracing::warn!("[trace] {} event(s) could not be written", dropped);
+        }
+        return Ok((path.clone(), WRITTEN.load(Ordering::Relaxed)));
+    }
+
+    let mut other_data = Map::new();
+    if let Some(session) = correlation::session() {
+        other_data.insert("session_id".to_string(), json!(correlation::format_guid(&session)));
+    }
+    if let Some(launch) = LAUNCH.get() {
+        other_data.insert("launch".to_string(), Value::Object(launch.clone()));
+    }
+    let events = EVENTS.lock().unwrap();
+    let file = TraceFile {
+        trace_events: &events,
+        display_time_unit: "ms",
+        other_data,
+    };
+    let json = serde_json::to_string(&file).map_err(|e| e.to_string())?;
+    std::fs::write(path, json).map_err(|e| format!("failed to write {}: {}", path, e))?;
+
+    let dropped = DROPPED.load(Ordering::Relaxed);
+    if dropped > 0 {
+        tracing::warn!(
+            "[trace] {} event(s) dropped after reaching max_events = {}",
+            dropped,
+            max_events
+        );
+    }
+
+    Ok((path.clone(), events.len()))
+}
+
+/// How the process was started, with the variables matching `patterns`,
+/// redacted like span arguments
+fn launch(patterns: &[String]) -> Map<String, Value> {
+    let mut launch = match serde_json::to_value(environment::current_launch(patterns)) {
+        Ok(Value::Object(launch)) => launch,
+        _ => Map::new(),
+    };
+    redact::fields(&mut launch);
+    if let Some(Value::Object(variables)) = launch.get_mut("variables") {
+        redact::fields(variables);
+    }
+    launch
+}
+
+/// The launch as flat fields for the binary format: "variables.SteamAppId",
+/// "launcher_arguments" joined by spaces
+fn flatten(launch: &Map<String, Value>) -> Vec<(String, Value)> {
+    let mut fields = Vec::new();
+    for (key, value) in launch {
+        match value {
+            Value::Null => {}
+            Value::Object(inner) => {
+                fields.extend(inner.iter().map(|(name, value)| (format!("{}.{}", key, name), value.clone())));
+            }
+            Value::Array(items) => {
+                let items: Vec<&str> = items.iter().filter_map(Value::as_str).collect();
+                fields.push((key.clone(), Value::from(items.join(" "))));
+            }
+            other => fields.push((key.clone(), other.clone())),
+        }
+    }
+    fields
+}
+
+/// Last module path segment of a span's target, e.g. "detours"
+fn category(target: &'static str) -> &'static str {
+    target.rsplit("::").next().unwrap_or(target)
+}
+
+fn micros(duration: std::time::Duration) -> f64 {
+    duration.as_secs_f64() * 1_000_000.0
+}
+
+/// A span field for the binary format
+fn field_value(value: &Value) -> FieldValue {
+    match value {
+        Value::Number(n) => n
+            .as_u64()
+            .map(FieldValue::U64)
+            .or_else(|| n.as_i64().map(FieldValue::I64))
+            .unwrap_or_else(|| FieldValue::F64(n.as_f64().unwrap_or_default())),
+        Value::String(s) => FieldValue::Str(s.clone()),
+        other => FieldValue::Str(other.to_string()),
+    }
+}
+
+struct ArgsVisitor(Map<String, Value>);
+
+impl Visit for ArgsVisitor {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
@@ -0,0 +1,136 @@
+/// Trampoline construction for inline hooks
+///
+/// The displaced prologue is decoded with iced-x86 so only whole
+/// instructions are copied, and re-encoded at the trampoline address so
+/// RIP-relative operands and relative branches still reach their original
+/// targets. The trampoline is allocated within ±2 GB of the target, which
+/// keeps RIP-relative memory operands encodable after the move.
+///
+/// x64 only.
+
+use iced_x86::{
+    BlockEncoder, BlockEncoderOptions, Decoder, DecoderOptions, FlowControl, Instruction,
+    InstructionBlock,
+};
+use winapi::um::memoryapi::VirtualAlloc;
+use winapi::um::winnt::{MEM_COMMIT, MEM_RESERVE, PAGE_EXECUTE_READWRITE};
+
+/// Size of `jmp qword ptr [rip+0]` followed by the 64-bit destination
+pub const JMP_SIZE: usize = 14;
+/// Bytes allocated per trampoline
+const TRAMPOLINE_SIZE: usize = 128;
+/// Longest possible x86 instruction
+const MAX_INSTRUCTION_SIZE: usize = 15;
+/// Allocation granularity, and the step when searching for free memory
+const ALLOCATION_STEP: usize = 0x10000;
+/// Keep trampolines this close to the target so rel32 operands still fit
+const MAX_DISTANCE: usize = 0x7FF0_0000;
+
+/// A trampoline built for a target function
+pub struct Trampoline {
+    /// Address of the trampoline; callable as the original function
+    pub address: usize,
+    /// Bytes of the target covered by whole instructions (>= JMP_SIZE)
+    pub stolen_len: usize,
+}
+
+/// Build a trampoline that runs the instructions displaced by a `JMP_SIZE`
+/// patch at `target` and then continues in the original function
+pub unsafe fn build(target: usize) -> Result<Trampoline, String> {
+    let instructions = decode_prologue(target)?;
+    let stolen_len = instructions.iter().map(|i| i.len()).sum::<usize>();
+
+    let memory = allocate_near(target, TRAMPOLINE_SIZE)
+        .ok_or_else(|| format!("No free memory for a trampoline near 0x{:x}", target))?;
+
+    let block = InstructionBlock::new(&instructions, memory as u64);
+    let encoded = BlockEncoder::encode(64, block, BlockEncoderOptions::NONE)
+        .map_err(|e| format!("Failed to relocate prologue of 0x{:x}: {}", target, e))?;
+
+    let code = encoded.code_buffer;
+    if code.len() + JMP_SIZE > TRAMPOLINE_SIZE {
+        return Err(format!("Relocated prologue of 0x{:x} is too large", target));
+    }
+
+    let trampoline = memory as *mut u8;
+    std::ptr::copy_nonoverlapping(code.as_ptr(), trampoline, code.len());
+    let back = encode_abs_jump(target + stolen_len);
+    std::ptr::copy_nonoverlapping(back.as_ptr(), trampoline.add(code.len()), JMP_SIZE);
+
+    Ok(Trampoline {
+        address: memory,
+        stolen_len,
+    })
+}
+
+/// Decode whole instructions at `target` until at least `JMP_SIZE` bytes
+/// are covered
+unsafe fn decode_prologue(target: usize) -> Result<Vec<Instruction>, String> {
+    let bytes = std::slice::from_raw_parts(target as *const u8, JMP_SIZE + MAX_INSTRUCTION_SIZE);
+    let mut decoder = Decoder::with_ip(64, bytes, target as u64, DecoderOptions::NONE);
+
+    let mut instructions = Vec::new();
+    let mut covered = 0;
+    while covered < JMP_SIZE {
+        let instruction = decoder.decode();
+        if instruction.is_invalid() {
+            return Err(format!(
+                "Invalid instruction at 0x{:x} in prologue",
+                instruction.ip()
+            ));
+        }
+
+        covered += instruction.len();
+        let flow = instruction.flow_control();
+        instructions.push(instruction);
+
+        // The function ends (or leaves for good) before the patch is complete:
+        // the jump would overwrite whatever follows it
+        if covered < JMP_SIZE
+            && matches!(
+                flow,
+                FlowControl::Return | FlowControl::UnconditionalBranch | FlowControl::Interrupt
+            )
+        {
+            return Err(format!(
+                "Function at 0x{:x} is too short to hook ({} bytes)",
+                target, covered
+            ));
+        }
+    }
+
+    Ok(instructions)
+}
+
+/// Allocate executable memory within `MAX_DISTANCE` of `target`
+unsafe fn allocate_near(target: usize, size: usize) -> Option<usize> {
+    let origin = target & !(ALLOCATION_STEP - 1);
+    let mut distance = ALLOCATION_STEP;
+
+    while distance < MAX_DISTANCE {
+        let candidates = [origin.checked_sub(distance), origin.checked_add(distance)];
+        for address in candidates.iter().flatten() {
+            let memory = VirtualAlloc(
+                *address as _,
+                size,
+                MEM_COMMIT | MEM_RESERVE,
+                PAGE_EXECUTE_READWRITE,
+            );
+            if !memory.is_null() {
+                return Some(memory as usize);
+            }
+        }
+        distance += ALLOCATION_STEP;
+    }
+
+    None
+}
+
+/// `jmp qword ptr [rip+0]` with the destination stored inline
+pub fn encode_abs_jump(destination: usize) -> [u8; JMP_SIZE] {
+    let mut code = [0u8; JMP_SIZE];
+    code[0] = 0xFF;
+    code[1] = 0x25;
+    code[6..].copy_from_slice(&(destination as u64).to_le_bytes());
+    code
+}
@@ -0,0 +1,270 @@
+/// Watchdog for calls into the original DLL that never return
+///
+/// Calls into the original DllMain and into recorded exports (recording.rs)
+/// are registered with `begin`/`end`, so exports are only watched with
+/// `[recording]` enabled: the plain forwarders jump into the original
+/// without registering anything. A background thread checks the calls
+/// every `timeout_ms / 4`; a call still running after `timeout_ms` is
+/// reported once: the stuck thread's stack is logged (frames inside the
+/// original as `reflex_original.dll+rva`), the `hang` error counter is
+/// increased and, with `dump = true`, a minidump is written.
+///
+/// ```toml
+/// [watchdog]
+/// enabled = true
+/// timeout_ms = 5000
+/// dump = true
+/// dump_path = "reflex_hang.dmp"
+/// ```
+///
+/// A hang in the original's DllMain holds the loader lock, and a new thread
+/// cannot start while it is held (DLL_THREAD_ATTACH). The watchdog thread is
+/// therefore created with NtCreateThreadEx and SKIP_THREAD_ATTACH, and its
+/// checks avoid loader APIs. dbghelp.dll is loaded up front for the same
+/// reason; MiniDumpWriteDump itself may still block on the loader lock, so
+/// the stack is logged and flushed before the dump is attempted.
+
+use crate::proxy;
+use crate::proxy_impl::config::WatchdogConfig;
+use crate::proxy_impl::status::{self, ErrorKind};
+use crate::proxy_impl::{logging, os, profiler};
+use once_cell::sync::{Lazy, OnceCell};
+use std::collections::HashMap;
+use std::fs::File;
+use std::os::windows::io::AsRawHandle;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE};
+use winapi::shared::ntdef::{NTSTATUS, PVOID, ULONG};
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::libloaderapi::GetModuleHandleA;
+use winapi::um::processthreadsapi::{GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId};
+use winapi::um::winnt::{ACCESS_MASK, HANDLE, THREAD_ALL_ACCESS};
+
+/// `THREAD_CREATE_FLAGS_SKIP_THREAD_ATTACH`
+const SKIP_THREAD_ATTACH: ULONG = 0x2;
+/// `MiniDumpWithIndirectlyReferencedMemory | MiniDumpWithThreadInfo`
+const DUMP_TYPE: u32 = 0x40 | 0x1000;
+
+type NtCreateThreadExFn = unsafe extern "system" fn(
+    *mut HANDLE,
+    ACCESS_MASK,
+    PVOID,
+    HANDLE,
+    unsafe extern "system" fn(PVOID) -> DWORD,
+    PVOID,
+    ULONG,
+    usize,
+    usize,
+    usize,
+    PVOID,
+) -> NTSTATUS;
+type MiniDumpWriteDumpFn = unsafe extern "system" fn(HANDLE, DWORD, HANDLE, u32, PVOID, PVOID, PVOID) -> BOOL;
+
+/// A call in progress
+struct Watched {
+    name: String,
+    thread: u32,
+    started: Instant,
+    reported: bool,
+}
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static WATCHED: Lazy<Mutex<HashMap<u64, Watched>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static TIMEOUT_MS: AtomicU64 = AtomicU64::new(0);
+static DUMP_PATH: OnceCell<String> = OnceCell::new();
+static MINI_DUMP_WRITE_DUMP: OnceCell<MiniDumpWriteDumpFn> = OnceCell::new();
+
+/// Start the watchdog thread
+pub unsafe fn start(config: &WatchdogConfig) {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    TIMEOUT_MS.store(config.timeout_ms.max(1), Ordering::SeqCst);
+
+    if config.dump {
+        match load_dbghelp() {
+            Ok(write_dump) => {
+                let _ = MINI_DUMP_WRITE_DUMP.set(write_dump);
+                let _ = DUMP_PATH.set(config.dump_path.clone());
+            }
+            Err(e) => tracing::warn!("[watchdog] Hangs will not be dumped: {}", e),
+        }
+    }
+
+    if let Err(e) = spawn() {
+        RUNNING.store(false, Ordering::SeqCst);
+        tracing::error!("[watchdog] Failed to start watchdog thread: {}", e);
+        return;
+    }
+    tracing::info!("[watchdog] Reporting calls that run longer than {} ms", config.timeout_ms);
+}
+
+/// Ask the watchdog thread to exit
+pub fn stop() {
+    RUNNING.store(false, Ordering::SeqCst);
+}
+
+/// Register a call on the current thread; returns its id for `end`
+///
+/// Returns 0, and registers nothing, while the watchdog is not running.
+pub fn begin(name: &str) -> u64 {
+    if !RUNNING.load(Ordering::Relaxed) {
+        return 0;
+    }
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let call = Watched {
+        name: name.to_string(),
+        thread: unsafe { GetCurrentThreadId() },
+        started: Instant::now(),
+        reported: false,
+    };
+    WATCHED.lock().unwrap().insert(id, call);
+    id
+}
+
+/// The call `id` returned
+pub fn end(id: u64) {
+    if id == 0 {
+        return;
+    }
+
+    let call = WATCHED.lock().unwrap().remove(&id);
+    if let Some(call) = call.filter(|c| c.reported) {
+        tracing::warn!(
+            "[watchdog] {} on thread {} returned after {} ms",
+            call.name,
+            call.thread,
+            call.started.elapsed().as_millis()
+        );
+    }
+}
+
+// ============================================================================
+// Watchdog Thread
+// ============================================================================
+
+unsafe fn spawn() -> Result<(), String> {
+    let ntdll = GetModuleHandleA(c"ntdll.dll".as_ptr());
+    let create = os::system()
+        .get_proc_address(ntdll as usize, "NtCreateThreadEx")
+        .ok_or("NtCreateThreadEx not found")?;
+    let create: NtCreateThreadExFn = std::mem::transmute(create);
+
+    let mut thread: HANDLE = std::ptr::null_mut();
+    let result = create(
+        &mut thread,
+        THREAD_ALL_ACCESS,
+        std::ptr::null_mut(),
+        GetCurrentProcess(),
+        watch_loop,
+        std::ptr::null_mut(),
+        SKIP_THREAD_ATTACH,
+        0,
+        0,
+        0,
+        std::ptr::null_mut(),
+    );
+    if result < 0 {
+        return Err(format!("NtCreateThreadEx failed with 0x{:08x}", result));
+    }
+
+    CloseHandle(thread);
+    Ok(())
+}
+
+unsafe extern "system" fn watch_loop(_parameter: PVOID) -> DWORD {
+    let timeout = Duration::from_millis(TIMEOUT_MS.load(Ordering::SeqCst));
+    let interval = (timeout / 4).clamp(Duration::from_millis(10), Duration::from_secs(1));
+
+    while RUNNING.load(Ordering::SeqCst) {
+        std::thread::sleep(interval);
+
+        let hung: Vec<(String, u32, Duration)> = WATCHED
+            .lock()
+            .unwrap()
+            .values_mut()
+            .filter(|call| !call.reported && call.started.elapsed() >= timeout)
+            .map(|call| {
+                call.reported = true;
+                (call.name.clone(), call.thread, call.started.elapsed())
+            })
+            .collect();
+
+        for (name, thread, elapsed) in hung {
+            report(&name, thread, elapsed);
+        }
+    }
+    0
+}
+
+unsafe fn report(name: &str, thread: u32, elapsed: Duration) {
+    status::record_error(ErrorKind::Hang);
+
+    let mut frames = [0usize; profiler::MAX_FRAMES];
+    let stack = match profiler::capture_stack(thread, &mut frames) {
+        Some(depth) => frames[..depth]
+            .iter()
+            .map(|&frame| format!("\n    {}", describe(frame)))
+            .collect::<String>(),
+        None => " (stack unavailable)".to_string(),
+    };
+    tracing::error!(
+        "[watchdog] {} on thread {} has not returned after {} ms, stack:{}",
+        name,
+        thread,
+        elapsed.as_millis(),
+        stack
+    );
+    logging::flush();
+
+    if let (Some(write_dump), Some(path)) = (MINI_DUMP_WRITE_DUMP.get(), DUMP_PATH.get()) {
+        match write_minidump(*write_dump, path) {
+            Ok(()) => tracing::info!("[watchdog] Wrote {}", path),
+            Err(e) => tracing::error!("[watchdog] Failed to write {}: {}", path, e),
+        }
+        logging::flush();
+    }
+}
+
+// ============================================================================
+// Utility Functions
+// ============================================================================
+
+/// `reflex_original.dll+rva` for frames in the original, else the address
+unsafe fn describe(frame: usize) -> String {
+    if proxy::is_in_original(frame) {
+        format!("reflex_original.dll+0x{:x}", frame - proxy::get_original_dll_base() as usize)
+    } else {
+        format!("0x{:x}", frame)
+    }
+}
+
+unsafe fn load_dbghelp() -> Result<MiniDumpWriteDumpFn, String> {
+    let os = os::system();
+    let dbghelp = os.load_library("dbghelp.dll", os::LOAD_LIBRARY_SEARCH_SYSTEM32)?;
+    let address = os
+        .get_proc_address(dbghelp, "MiniDumpWriteDump")
+        .ok_or("MiniDumpWriteDump not found")?;
+    Ok(std::mem::transmute::<usize, MiniDumpWriteDumpFn>(address))
+}
+
+unsafe fn write_minidump(write_dump: MiniDumpWriteDumpFn, path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let written = write_dump(
+        GetCurrentProcess(),
+        GetCurrentProcessId(),
+        file.as_raw_handle() as HANDLE,
+        DUMP_TYPE,
+        std::ptr::null_mut(),
+        std::ptr::null_mut(),
+        std::ptr::null_mut(),
+    );
+    if written == FALSE {
+        return Err(format!("MiniDumpWriteDump failed with error {}", winapi::um::errhandlingapi::GetLastError()));
+    }
+    Ok(())
+}
@@ -0,0 +1,22 @@
+/// UTF-16 strings of the Win32 `W` functions
+///
+/// Arguments are passed as NUL-terminated copies (`to_wide`); strings the
+/// system or a hooked caller hands over are read up to their NUL
+/// (`from_wide_ptr`), unpaired surrogates replaced.
+
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+
+/// NUL-terminated UTF-16 copy of `text`
+pub fn to_wide(text: impl AsRef<OsStr>) -> Vec<u16> {
+    text.as_ref().encode_wide().chain(Some(0)).collect()
+}
+
+/// The NUL-terminated string at `text`; None for a null pointer
+pub unsafe fn from_wide_ptr(text: *const u16) -> Option<String> {
+    if text.is_null() {
+        return None;
+    }
+    let len = (0..).take_while(|&i| *text.add(i) != 0).count();
+    Some(String::from_utf16_lossy(std::slice::from_raw_parts(text, len)))
+}
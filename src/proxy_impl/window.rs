@@ -0,0 +1,151 @@
+/// Main-window gate for UI features
+///
+/// Hotkeys (and an overlay) are pointless, and for an overlay unsafe, before
+/// the game has created its window. With `wait = true` they are started from
+/// a background thread once the process owns a visible top-level window,
+/// or a window of the configured class:
+///
+/// ```toml
+/// [window]
+/// wait = true
+/// class = "UnrealWindow"   # optional; default: any visible top-level window
+/// timeout_ms = 60000       # 0 = wait forever
+/// ```
+///
+/// After the timeout the features start anyway, without a window. With
+/// `wait = false` (the default) they start immediately during attach.
+/// Features start either before `stop` returns or not at all.
+
+use crate::proxy_impl::config::WindowConfig;
+use crate::proxy_impl::worker::Worker;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use winapi::shared::minwindef::{BOOL, FALSE, LPARAM, TRUE};
+use winapi::shared::windef::HWND;
+use winapi::um::processthreadsapi::GetCurrentProcessId;
+use winapi::um::winuser::{EnumWindows, GetClassNameW, GetWindow, GetWindowThreadProcessId, IsWindowVisible, GW_OWNER};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+/// Held by `stop` and while the features start, so they never start after
+/// `stop` returned
+static STARTING: Mutex<()> = Mutex::new(());
+static WORKER: Worker = Worker::new();
+
+/// Run `start` once the main window exists (or right away if not waiting)
+pub fn when_ready<F>(config: &WindowConfig, start: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    if !config.wait {
+        start();
+        return;
+    }
+
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        tracing::warn!("[window] Already waiting for the main window");
+        return;
+    }
+
+    let class = config.class.clone();
+    let timeout = (config.timeout_ms > 0).then(|| Duration::from_millis(config.timeout_ms));
+    let spawned = WORKER.spawn("reflex-window-wait", move || wait(&class, timeout, start));
+
+    if let Err(e) = spawned {
+        RUNNING.store(false, Ordering::SeqCst);
+        tracing::error!("[window] Failed to spawn window wait thread: {}", e);
+    }
+}
+
+/// Stop waiting; features that have not started yet never will
+pub fn stop() {
+    {
+        let _starting = STARTING.lock().unwrap();
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+    WORKER.stop();
+}
+
+/// Start the features unless `stop` was called meanwhile
+fn start_unless_stopped<F: FnOnce()>(start: F) {
+    let _starting = STARTING.lock().unwrap();
+    if RUNNING.load(Ordering::SeqCst) {
+        start();
+    }
+}
+
+fn wait<F: FnOnce()>(class: &str, timeout: Option<Duration>, start: F) {
+    let description = if class.is_empty() { "main window" } else { class };
+    tracing::info!("[window] Waiting for {} before starting UI features", description);
+    let started = Instant::now();
+
+    while RUNNING.load(Ordering::SeqCst) {
+        if let Some(hwnd) = unsafe { find_window(class) } {
+            tracing::info!(
+                "[window] Found {} {:p} after {} ms",
+                description,
+                hwnd,
+                started.elapsed().as_millis()
+            );
+            start_unless_stopped(start);
+            return;
+        }
+
+        if timeout.is_some_and(|t| started.elapsed() >= t) {
+            tracing::warn!(
+                "[window] No {} after {} ms, starting UI features anyway",
+                description,
+                started.elapsed().as_millis()
+            );
+            start_unless_stopped(start);
+            return;
+        }
+        WORKER.sleep(POLL_INTERVAL);
+    }
+}
+
+// ============================================================================
+// Utility Functions
+// ============================================================================
+
+struct Search<'a> {
+    pid: u32,
+    class: &'a str,
+    found: HWND,
+}
+
+/// A visible, unowned top-level window of this process, of class `class`
+/// unless empty
+unsafe fn find_window(class: &str) -> Option<HWND> {
+    let mut search = Search {
+        pid: GetCurrentProcessId(),
+        class,
+        found: std::ptr::null_mut(),
+    };
+    EnumWindows(Some(check_window), &mut search as *mut Search as LPARAM);
+    (!search.found.is_null()).then_some(search.found)
+}
+
+unsafe extern "system" fn check_window(hwnd: HWND, lparam: LPARAM) -> BOOL {
+    let search = &mut *(lparam as *mut Search);
+
+    let mut pid = 0;
+    GetWindowThreadProcessId(hwnd, &mut pid);
+    if pid != search.pid || IsWindowVisible(hwnd) == 0 || !GetWindow(hwnd, GW_OWNER).is_null() {
+        return TRUE;
+    }
+
+    if !search.class.is_empty() {
+        let mut buffer = [0u16; 256];
+        let len = GetClassNameW(hwnd, buffer.as_mut_ptr(), buffer.len() as i32);
+        let name = String::from_utf16_lossy(&buffer[..len.max(0) as usize]);
+        if !name.eq_ignore_ascii_case(search.class) {
+            return TRUE;
+        }
+    }
+
+    search.found = hwnd;
+    FALSE
+}
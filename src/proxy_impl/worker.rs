@@ -0,0 +1,122 @@
+/// Background threads that must not outlive the proxy's code
+///
+/// A thread still running when the proxy is unloaded with FreeLibrary
+/// would go on in unmapped code, so a module keeps the thread it spawns in
+/// a `Worker`, has it sleep with `Worker::sleep` and waits for it when it
+/// stops. Stopping happens on DLL_PROCESS_DETACH, under the loader lock,
+/// which an exiting thread needs for its own detach notifications:
+/// `JoinHandle::join` would deadlock there. `stop` waits for the thread's
+/// body to return instead; what the thread runs after that is its exit in
+/// the system DLLs.
+///
+/// When the process exits, its other threads are already gone and nothing
+/// is waited for (`set_exiting`).
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long `stop` waits before leaving a thread to its fate
+const WAIT_LIMIT: Duration = Duration::from_secs(5);
+
+/// Set on DLL_PROCESS_DETACH at process exit
+static EXITING: AtomicBool = AtomicBool::new(false);
+
+/// The thread of a background task, if one was spawned
+pub struct Worker {
+    thread: Mutex<Option<JoinHandle<()>>>,
+    /// Whether the thread was told to stop, and a wake-up for `sleep`
+    stopping: (Mutex<bool>, Condvar),
+}
+
+impl Worker {
+    pub const fn new() -> Self {
+        Self {
+            thread: Mutex::new(None),
+            stopping: (Mutex::new(false), Condvar::new()),
+        }
+    }
+
+    /// Run `body` on a thread named `name`, after the previous one stopped
+    pub fn spawn<F>(&self, name: &str, body: F) -> std::io::Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.stop();
+        *self.stopping.0.lock().unwrap() = false;
+        let handle = std::thread::Builder::new().name(name.to_string()).spawn(body)?;
+        *self.thread.lock().unwrap() = Some(handle);
+        Ok(())
+    }
+
+    /// Sleep for `duration`, or until `stop`; false once stopping
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let (stopping, wake) = &self.stopping;
+        let (stopping, _) = wake
+            .wait_timeout_while(stopping.lock().unwrap(), duration, |stopping| !*stopping)
+            .unwrap();
+        !*stopping
+    }
+
+    /// Wake the thread from `sleep` and wait for its body to return
+    ///
+    /// Does not wait from the thread itself or at process exit.
+    pub fn stop(&self) {
+        let (stopping, wake) = &self.stopping;
+        *stopping.lock().unwrap() = true;
+        wake.notify_all();
+
+        let Some(handle) = self.thread.lock().unwrap().take() else {
+            return;
+        };
+        if EXITING.load(Ordering::SeqCst) || handle.thread().id() == std::thread::current().id() {
+            return;
+        }
+
+        let started = Instant::now();
+        while !handle.is_finished() {
+            if started.elapsed() >= WAIT_LIMIT {
+                tracing::warn!(
+                    "[worker] {} did not stop within {} ms",
+                    handle.thread().name().unwrap_or("thread"),
+                    WAIT_LIMIT.as_millis()
+                );
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+/// The process is exiting: `stop` returns right away from now on
+pub fn set_exiting() {
+    EXITING.store(true, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    static WORKER: Worker = Worker::new();
+
+    #[test]
+    fn stop_wakes_the_thread_and_waits_for_it() {
+        let (finished, done) = mpsc::channel();
+        WORKER
+            .spawn("reflex-worker-test", move || {
+                while WORKER.sleep(Duration::from_secs(60)) {}
+                let _ = finished.send(());
+            })
+            .unwrap();
+
+        let started = Instant::now();
+        WORKER.stop();
+        assert!(started.elapsed() < WAIT_LIMIT);
+        assert!(done.try_recv().is_ok());
+        assert!(!WORKER.sleep(Duration::from_secs(60)));
+        // Nothing left to wait for
+        WORKER.stop();
+    }
+}
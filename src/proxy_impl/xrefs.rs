@@ -0,0 +1,131 @@
+/// Code references into an image
+///
+/// The scans behind analysis.rs: strings are located in data sections,
+/// then code is decoded linearly (x64) for instructions addressing them
+/// RIP-relative, the way MSVC loads a string's address (`lea rdx,
+/// [rip+0x1234]`). Callers of a function are found the same way, as direct
+/// calls to it or calls through a pointer to it (`call [rip+0x1234]`). Linear decoding can lose sync on data embedded in code,
+/// so a reference may occasionally be missed, but every one reported is a
+/// decoded instruction.
+
+use iced_x86::{Decoder, DecoderOptions, FlowControl, OpKind};
+use std::collections::HashSet;
+
+/// The NUL-terminated strings in `data` containing `text`, as ASCII/UTF-8
+/// or as UTF-16LE, with their start offsets
+pub fn find_strings(data: &[u8], text: &str) -> Vec<(usize, String)> {
+    let mut strings = Vec::new();
+    if text.is_empty() {
+        return strings;
+    }
+
+    for found in find_all(data, text.as_bytes()) {
+        let start = data[..found].iter().rposition(|&b| b == 0).map_or(0, |nul| nul + 1);
+        let end = data[found..].iter().position(|&b| b == 0).map_or(data.len(), |len| found + len);
+        strings.push((start, String::from_utf8_lossy(&data[start..end]).into_owned()));
+    }
+
+    let units: Vec<u16> = data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    let wide: Vec<u16> = text.encode_utf16().collect();
+    for found in find_all(&units, &wide) {
+        let start = units[..found].iter().rposition(|&u| u == 0).map_or(0, |nul| nul + 1);
+        let end = units[found..].iter().position(|&u| u == 0).map_or(units.len(), |len| found + len);
+        strings.push((start * 2, String::from_utf16_lossy(&units[start..end])));
+    }
+
+    strings.sort_by_key(|(start, _)| *start);
+    strings.dedup_by_key(|(start, _)| *start);
+    strings
+}
+
+/// Instructions in `code` (decoded from address `ip`) with a RIP-relative
+/// operand pointing at one of `targets`, as (instruction, target) addresses
+pub fn rip_relative_refs(code: &[u8], ip: u64, targets: &HashSet<u64>) -> Vec<(u64, u64)> {
+    Decoder::with_ip(64, code, ip, DecoderOptions::NONE)
+        .into_iter()
+        .filter(|instruction| instruction.is_ip_rel_memory_operand())
+        .map(|instruction| (instruction.ip(), instruction.ip_rel_memory_address()))
+        .filter(|(_, target)| targets.contains(target))
+        .collect()
+}
+
+/// Call instructions in `code` (decoded from address `ip`) to `target`,
+/// directly or through one of the pointer `slots` holding it
+pub fn calls_to(code: &[u8], ip: u64, target: u64, slots: &HashSet<u64>) -> Vec<u64> {
+    Decoder::with_ip(64, code, ip, DecoderOptions::NONE)
+        .into_iter()
+        .filter(|instruction| matches!(instruction.flow_control(), FlowControl::Call | FlowControl::IndirectCall))
+        .filter(|instruction| match instruction.op0_kind() {
+            OpKind::NearBranch64 => instruction.near_branch_target() == target,
+            OpKind::Memory => {
+                instruction.is_ip_rel_memory_operand() && slots.contains(&instruction.ip_rel_memory_address())
+            }
+            _ => false,
+        })
+        .map(|instruction| instruction.ip())
+        .collect()
+}
+
+fn find_all<T: PartialEq>(data: &[T], needle: &[T]) -> Vec<usize> {
+    data.windows(needle.len())
+        .enumerate()
+        .filter(|(_, window)| *window == needle)
+        .map(|(offset, _)| offset)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_are_found_from_any_part_of_them() {
+        let mut data = b"\0Failed to load config\0config\0\0\0".to_vec();
+        let wide_at = data.len();
+        data.extend("Bad config".encode_utf16().flat_map(u16::to_le_bytes));
+        data.extend([0, 0]);
+
+        assert_eq!(
+            find_strings(&data, "config"),
+            vec![
+                (1, "Failed to load config".to_string()),
+                (23, "config".to_string()),
+                (wide_at, "Bad config".to_string()),
+            ]
+        );
+        assert_eq!(find_strings(&data, "Failed to load").len(), 1);
+        assert!(find_strings(&data, "missing").is_empty());
+    }
+
+    #[test]
+    fn rip_relative_operands_are_matched_against_the_targets() {
+        let code = [
+            0x48, 0x8D, 0x15, 0x10, 0x00, 0x00, 0x00, // lea rdx, [rip+0x10]
+            0x48, 0x8B, 0x05, 0x20, 0x00, 0x00, 0x00, // mov rax, [rip+0x20]
+            0x48, 0x8D, 0x0D, 0x02, 0x00, 0x00, 0x00, // lea rcx, [rip+0x2]
+        ];
+        let ip = 0x1000;
+        let targets = HashSet::from([ip + 0x17, ip + 0x30]);
+
+        assert_eq!(
+            rip_relative_refs(&code, ip, &targets),
+            vec![(ip, ip + 0x17), (ip + 14, ip + 0x17)]
+        );
+    }
+
+    #[test]
+    fn calls_are_found_directly_and_through_pointers() {
+        let code = [
+            0xE8, 0x0B, 0x00, 0x00, 0x00, // call +0xb
+            0xFF, 0x15, 0x20, 0x00, 0x00, 0x00, // call [rip+0x20]
+            0xE9, 0x00, 0x00, 0x00, 0x00, // jmp +0 (not a call)
+            0xE8, 0xEB, 0xFF, 0xFF, 0xFF, // call -0x15
+            0xC3, // ret
+        ];
+        let ip = 0x1000;
+        let slots = HashSet::from([ip + 11 + 0x20]);
+
+        assert_eq!(calls_to(&code, ip, ip + 0x10, &slots), vec![ip, ip + 5]);
+        assert_eq!(calls_to(&code, ip, ip, &HashSet::new()), vec![ip + 16]);
+    }
+}
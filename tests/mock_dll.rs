@@ -0,0 +1,158 @@
+//! Integration tests against a mock reflex_original.dll
+//!
+//! Builds tests/mock_original, puts it next to a copy of the proxy in a
+//! scratch directory and loads the proxy into the test process, the same
+//! way a game would. Windows only.
+//!
+//! Everything runs in one test: the proxy, its config and its log are
+//! process-wide, and the working directory is changed for the duration.
+
+#![cfg(windows)]
+
+use std::ffi::CString;
+use std::fs;
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use winapi::shared::minwindef::{BOOL, DWORD, HMODULE};
+use winapi::shared::ntdef::LPCWSTR;
+use winapi::um::libloaderapi::{FreeLibrary, GetModuleHandleW, GetProcAddress, LoadLibraryW};
+
+const CONFIG: &str = r#"
+detours = true
+
+[logging]
+file = true
+level = "info"
+
+[[rules]]
+api = "DeleteFileW"
+path = "*protected*"
+action = "block"
+"#;
+
+#[test]
+fn proxy_forwards_hooks_and_tears_down() {
+    let dir = prepare_directory();
+    std::env::set_current_dir(&dir).unwrap();
+
+    let proxy = load(&dir.join("reflex.dll"));
+    let original = unsafe { GetModuleHandleW(wide("reflex_original.dll").as_ptr()) };
+    assert!(!original.is_null(), "proxy did not load reflex_original.dll");
+
+    // DllMain forwarding: the loader notifies the mock when the proxy loads
+    // it, then the proxy forwards its own DLL_PROCESS_ATTACH
+    let attach_count: unsafe extern "system" fn() -> u32 = export(original, "MockAttachCount");
+    assert_eq!(
+        unsafe { attach_count() },
+        2,
+        "expected the loader's DLL_PROCESS_ATTACH plus the forwarded one"
+    );
+
+    let add: unsafe extern "system" fn(u32, u32) -> u32 = export(original, "MockAdd");
+    assert_eq!(unsafe { add(2, 3) }, 5);
+
+    // Status export
+    let status = status_json(proxy);
+    assert_eq!(status["state"], "initialized", "status: {}", status);
+    let hooks = status["hooks"].as_array().unwrap();
+    assert!(
+        hooks.iter().any(|h| h["name"] == "DeleteFileW"),
+        "DeleteFileW detour missing: {}",
+        status
+    );
+
+    // The IAT detour sees the original's DeleteFileW calls and applies the rules
+    let delete_file: unsafe extern "system" fn(LPCWSTR) -> BOOL = export(original, "MockDeleteFile");
+    fs::write("protected.txt", "keep").unwrap();
+    fs::write("scratch.txt", "delete").unwrap();
+
+    assert_eq!(unsafe { delete_file(wide("protected.txt").as_ptr()) }, 0);
+    assert!(Path::new("protected.txt").exists(), "blocked deletion went through");
+    assert_ne!(unsafe { delete_file(wide("scratch.txt").as_ptr()) }, 0);
+    assert!(!Path::new("scratch.txt").exists(), "allowed deletion was not forwarded");
+
+    // Teardown: detach is forwarded, then the loader notifies the mock again
+    // when the proxy releases it
+    assert_ne!(unsafe { FreeLibrary(proxy) }, 0);
+    assert_eq!(
+        fs::read_to_string("mock_detached.txt").unwrap(),
+        "2",
+        "expected the forwarded DLL_PROCESS_DETACH plus the loader's one"
+    );
+    assert!(
+        unsafe { GetModuleHandleW(wide("reflex_original.dll").as_ptr()) }.is_null(),
+        "reflex_original.dll is still loaded"
+    );
+
+    let log = fs::read_to_string("reflex.log").unwrap();
+    assert!(log.contains("Shutdown complete"), "log:\n{}", log);
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Scratch directory with reflex.dll, the mock as reflex_original.dll and
+/// a reflex_proxy.toml
+fn prepare_directory() -> PathBuf {
+    let deps = std::env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let target = deps.parent().unwrap().parent().unwrap().to_path_buf();
+
+    let proxy = [deps.join("reflex.dll"), deps.parent().unwrap().join("reflex.dll")]
+        .into_iter()
+        .find(|p| p.exists())
+        .expect("reflex.dll not built");
+    let mock = build_mock(&target);
+
+    let dir = target.join("mock_dll_test");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::copy(proxy, dir.join("reflex.dll")).unwrap();
+    fs::copy(mock, dir.join("reflex_original.dll")).unwrap();
+    fs::write(dir.join("reflex_proxy.toml"), CONFIG).unwrap();
+    dir
+}
+
+/// Build tests/mock_original into its own target directory
+fn build_mock(target: &Path) -> PathBuf {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/mock_original/Cargo.toml");
+    let target_dir = target.join("mock_original");
+
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--quiet", "--manifest-path"])
+        .arg(&manifest)
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "building the mock DLL failed");
+
+    target_dir.join("debug").join("reflex_original.dll")
+}
+
+fn load(path: &Path) -> HMODULE {
+    let module = unsafe { LoadLibraryW(wide(path).as_ptr()) };
+    assert!(!module.is_null(), "failed to load {}", path.display());
+    module
+}
+
+fn export<F>(module: HMODULE, name: &str) -> F {
+    let name = CString::new(name).unwrap();
+    let address = unsafe { GetProcAddress(module, name.as_ptr()) };
+    assert!(!address.is_null(), "missing export {:?}", name);
+    unsafe { std::mem::transmute_copy(&address) }
+}
+
+fn status_json(proxy: HMODULE) -> serde_json::Value {
+    let get_status: unsafe extern "system" fn(*mut u8, DWORD) -> DWORD = export(proxy, "ReflexProxyGetStatus");
+    let required = unsafe { get_status(std::ptr::null_mut(), 0) };
+    let mut buffer = vec![0u8; required as usize];
+    unsafe { get_status(buffer.as_mut_ptr(), required) };
+    buffer.pop(); // NUL terminator
+    serde_json::from_slice(&buffer).unwrap()
+}
+
+fn wide(text: impl AsRef<std::ffi::OsStr>) -> Vec<u16> {
+    text.as_ref().encode_wide().chain(Some(0)).collect()
+}
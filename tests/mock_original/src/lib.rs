@@ -0,0 +1,61 @@
+/// Mock reflex_original.dll for the proxy integration tests
+///
+/// Exports DllMain plus a few functions with known behavior:
+/// - `MockAdd(a, b)` returns `a + b`
+/// - `MockAttachCount()` / `MockDetachCount()` count DllMain notifications
+/// - `MockDeleteFile(path)` calls the imported DeleteFileW, so the proxy's
+///   IAT detour on this DLL can be observed
+///
+/// On DLL_PROCESS_DETACH it writes `mock_detached.txt` to the working
+/// directory, which outlives the DLL for teardown checks.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use winapi::shared::minwindef::{BOOL, DWORD, HINSTANCE, LPVOID, TRUE};
+use winapi::shared::ntdef::LPCWSTR;
+use winapi::um::fileapi::DeleteFileW;
+use winapi::um::winnt::{DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH};
+
+pub const DETACH_MARKER: &str = "mock_detached.txt";
+
+static ATTACH_COUNT: AtomicU32 = AtomicU32::new(0);
+static DETACH_COUNT: AtomicU32 = AtomicU32::new(0);
+
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "system" fn DllMain(_hinst_dll: HINSTANCE, fdw_reason: DWORD, _lpv_reserved: LPVOID) -> BOOL {
+    match fdw_reason {
+        DLL_PROCESS_ATTACH => {
+            ATTACH_COUNT.fetch_add(1, Ordering::SeqCst);
+        }
+        DLL_PROCESS_DETACH => {
+            let detaches = DETACH_COUNT.fetch_add(1, Ordering::SeqCst) + 1;
+            let _ = std::fs::write(DETACH_MARKER, detaches.to_string());
+        }
+        _ => {}
+    }
+    TRUE
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "system" fn MockAdd(a: u32, b: u32) -> u32 {
+    a.wrapping_add(b)
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "system" fn MockAttachCount() -> u32 {
+    ATTACH_COUNT.load(Ordering::SeqCst)
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "system" fn MockDetachCount() -> u32 {
+    DETACH_COUNT.load(Ordering::SeqCst)
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn MockDeleteFile(path: LPCWSTR) -> BOOL {
+    DeleteFileW(path)
+}